@@ -3,16 +3,24 @@
 //! Usage: cargo run --bin fs_cache_bench -- <directory_path>
 //!
 //! This tool will recursively walk the given directory, cache all entries using
-//! RedbFilesystemCache, and report timing and throughput statistics.
+//! RedbFilesystemCache, and report timing and throughput statistics. It then runs a batch of
+//! synthetic remove/create pairs through the move detector twice, once with the cache behind
+//! `dyn FilesystemCacheStorage` and once with the concrete type, to compare dispatch cost.
+//! Finally it stores 10k synthetic nodes into fresh databases as one batch with stats counters
+//! adjusted once per batch, as one batch with counters adjusted per node, and one by one, to
+//! show what per-node stats updates cost. Last, it lists one wide directory among many
+//! registered watches, once by scanning every watch and once a page at a time through the
+//! path-to-watches index.
 
 use redb::Database;
 use rust_watcher::database::storage::filesystem_cache::trait_def::FilesystemCacheStorage;
 use rust_watcher::database::storage::filesystem_cache::RedbFilesystemCache;
 use rust_watcher::database::types::{FilesystemNode, WatchMetadata};
+use rust_watcher::{EventType, FileSystemEvent, MoveDetector, MoveDetectorConfig};
 use std::env;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 use walkdir::WalkDir;
 
@@ -41,6 +49,7 @@ fn main() {
 		node_count: 0,
 		is_active: true,
 		config_hash: 0,
+		event_retention: None,
 		permissions: None,
 	};
 	pollster::block_on(cache.store_watch_metadata(&metadata))
@@ -87,5 +96,174 @@ fn main() {
 		symlinks = stats.symlinks,
 		size = stats.cache_size_bytes
 	);
+
+	// Same batch, same database; only the detector's cache type differs. Rounds alternate and
+	// the best of each is reported, since later rounds run against a warmer page cache.
+	const MOVE_PAIRS: usize = 5_000;
+	const ROUNDS: usize = 3;
+	let (mut best_dyn, mut best_generic) = (Duration::MAX, Duration::MAX);
+	for _ in 0..ROUNDS {
+		let mut detector: MoveDetector<'_, dyn FilesystemCacheStorage> =
+			MoveDetector::new(MoveDetectorConfig::default(), &mut cache);
+		best_dyn = best_dyn.min(pollster::block_on(run_move_batch(
+			&mut detector,
+			MOVE_PAIRS,
+		)));
+
+		let mut detector: MoveDetector<'_, RedbFilesystemCache> =
+			MoveDetector::new(MoveDetectorConfig::default(), &mut cache);
+		best_generic = best_generic.min(pollster::block_on(run_move_batch(
+			&mut detector,
+			MOVE_PAIRS,
+		)));
+	}
+	println!(
+		"Move detection over {MOVE_PAIRS} remove/create pairs (best of {ROUNDS}): dyn cache {best_dyn:?}, generic cache {best_generic:?}"
+	);
+	let template = std::fs::metadata(&dir).expect("Failed to stat directory");
+	let (batched, per_node, individual) = store_stats_bench(&template, STATS_NODES);
+	println!(
+		"Storing {STATS_NODES} nodes: batch with batched stats {batched:?}, batch with per-node stats {per_node:?}, individually {individual:?}"
+	);
+	let (scanned, paged) = unified_listing_bench(&template, LISTING_WATCHES, LISTING_WIDTH);
+	println!(
+		"Listing {LISTING_WIDTH} children among {LISTING_WATCHES} watches: scanning all watches {scanned:?}, paged through the index {paged:?}"
+	);
 	println!("Database file: {db_path:?}");
 }
+
+const LISTING_WATCHES: usize = 500;
+const LISTING_WIDTH: usize = 5_000;
+const LISTING_PAGE: usize = 500;
+
+/// Time a unified listing of one directory of `width` children, cached by two of `watches`
+/// registered watches (the rest cache one node elsewhere each), with the scan over every watch
+/// and paged through the index
+fn unified_listing_bench(
+	template: &std::fs::Metadata, watches: usize, width: usize,
+) -> (Duration, Duration) {
+	let path = std::env::temp_dir().join(format!("fs_cache_bench-{}.redb", Uuid::new_v4()));
+	let db = Arc::new(Database::create(&path).expect("Failed to create database"));
+	let mut cache = RedbFilesystemCache::new(db);
+	let wide = PathBuf::from("/nonexistent-bench/wide");
+	let children: Vec<_> = (0..width)
+		.map(|i| FilesystemNode::new(wide.join(format!("node_{i}.dat")), template))
+		.collect();
+	for i in 0..watches {
+		let watch_id = Uuid::new_v4();
+		let root_path = match i {
+			0 | 1 => wide.clone(),
+			_ => PathBuf::from(format!("/nonexistent-bench/other_{i}")),
+		};
+		let metadata = WatchMetadata {
+			watch_id,
+			root_path: root_path.clone(),
+			created_at: chrono::Utc::now(),
+			last_scan: None,
+			node_count: 0,
+			is_active: true,
+			config_hash: 0,
+			event_retention: None,
+			permissions: None,
+		};
+		pollster::block_on(cache.store_watch_metadata(&metadata))
+			.expect("Failed to store watch metadata");
+		let nodes = match i {
+			0 | 1 => children.clone(),
+			_ => vec![FilesystemNode::new(root_path.join("only.dat"), template)],
+		};
+		pollster::block_on(cache.batch_store_filesystem_nodes(&watch_id, &nodes, "bench"))
+			.expect("Batch cache insert failed");
+	}
+
+	let start = Instant::now();
+	let listed = pollster::block_on(cache.list_directory_unified(&wide)).expect("Listing failed");
+	let scanned = start.elapsed();
+	assert_eq!(listed.len(), width);
+
+	let start = Instant::now();
+	let (mut listed, mut cursor) = (0, None);
+	loop {
+		let page = pollster::block_on(cache.list_directory_unified_page(
+			&wide,
+			cursor.as_ref(),
+			LISTING_PAGE,
+		))
+		.expect("Paged listing failed");
+		listed += page.nodes.len();
+		cursor = page.next_cursor;
+		if cursor.is_none() {
+			break;
+		}
+	}
+	let paged = start.elapsed();
+	assert_eq!(listed, width);
+	let _ = std::fs::remove_file(path);
+	(scanned, paged)
+}
+
+const STATS_NODES: usize = 10_000;
+
+/// Time storing `count` synthetic nodes into a fresh database per mode: one batch with stats
+/// batched, one batch with per-node stats, and one store per node
+fn store_stats_bench(template: &std::fs::Metadata, count: usize) -> (Duration, Duration, Duration) {
+	let watch_id = Uuid::new_v4();
+	let nodes: Vec<_> = (0..count)
+		.map(|i| {
+			let path = PathBuf::from("/nonexistent-bench/stats").join(format!("node_{i}.dat"));
+			FilesystemNode::new(path, template)
+		})
+		.collect();
+	let fresh_cache = || {
+		let path = std::env::temp_dir().join(format!("fs_cache_bench-{}.redb", Uuid::new_v4()));
+		let db = Arc::new(Database::create(&path).expect("Failed to create database"));
+		(RedbFilesystemCache::new(db), path)
+	};
+
+	let mut timings = Vec::new();
+	for batched in [true, false] {
+		let (cache, path) = fresh_cache();
+		let mut cache = cache.with_batched_stats(batched);
+		let start = Instant::now();
+		pollster::block_on(cache.batch_store_filesystem_nodes(&watch_id, &nodes, "bench"))
+			.expect("Batch cache insert failed");
+		timings.push(start.elapsed());
+		let _ = std::fs::remove_file(path);
+	}
+
+	let (mut cache, path) = fresh_cache();
+	let start = Instant::now();
+	for node in &nodes {
+		pollster::block_on(cache.store_filesystem_node(&watch_id, node, "bench"))
+			.expect("Cache insert failed");
+	}
+	let individual = start.elapsed();
+	let _ = std::fs::remove_file(path);
+	(timings[0], timings[1], individual)
+}
+
+/// Feed remove/create pairs for paths that do not exist, so no file IO is involved and the
+/// timing is dominated by matching and cache lookups.
+async fn run_move_batch<C: FilesystemCacheStorage + ?Sized>(
+	detector: &mut MoveDetector<'_, C>, pairs: usize,
+) -> Duration {
+	let start = Instant::now();
+	for i in 0..pairs {
+		let name = format!("bench_{i}.dat");
+		let size = Some(1024 + i as u64);
+		let source = PathBuf::from("/nonexistent-bench/src").join(&name);
+		let destination = PathBuf::from("/nonexistent-bench/dst").join(&name);
+		detector
+			.process_event(FileSystemEvent::new(EventType::Remove, source, false, size))
+			.await;
+		detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				destination,
+				false,
+				size,
+			))
+			.await;
+	}
+	start.elapsed()
+}
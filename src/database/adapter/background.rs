@@ -4,31 +4,34 @@
 //! such as compaction, health checks, stats refresh, and time index repair.
 
 use crate::database::background_tasks::{
-	BackgroundTaskManager, CompactionTask, HealthCheckTask, StatsRefreshTask, TimeIndexRepairTask,
+	BackgroundTaskManager, CompactionTask, HealthCheckTask, SharedStorage, StatsRefreshTask,
+	TimeIndexRepairTask,
 };
+use crate::database::config::DatabaseConfig;
 use crate::database::storage::RedbStorage;
 use std::sync::Arc;
 
 pub fn setup_background_manager(
-	storage: &dyn crate::database::storage::DatabaseStorage,
+	storage: &Arc<SharedStorage>, config: &DatabaseConfig,
 ) -> Option<Arc<BackgroundTaskManager>> {
-	if let Some(db) = storage
-		.as_any()
-		.downcast_ref::<RedbStorage>()
-		.map(|redb_storage| redb_storage.get_database())
-	{
-		let db = db.clone();
-		let repair = Arc::new(TimeIndexRepairTask { db: db.clone() });
-		let compact = Arc::new(CompactionTask { db: db.clone() });
-		let health = Arc::new(HealthCheckTask { db: db.clone() });
-		let stats = Arc::new(StatsRefreshTask { db });
-		let mut manager = BackgroundTaskManager::new();
-		manager.register_task(repair);
-		manager.register_task(compact);
-		manager.register_task(health);
-		manager.register_task(stats);
-		Some(Arc::new(manager))
-	} else {
-		None
+	// Freshly created and not shared yet, so the lock is free
+	let is_redb = storage.try_read().is_ok_and(|s| s.as_any().is::<RedbStorage>());
+	if !is_redb {
+		return None;
 	}
+	let storage = Arc::downgrade(storage);
+	let repair = Arc::new(TimeIndexRepairTask { storage: storage.clone() });
+	let compact = Arc::new(CompactionTask {
+		storage: storage.clone(),
+		fragmentation_threshold: config.compaction_fragmentation_threshold,
+		min_reclaimable_bytes: config.compaction_min_reclaimable_bytes,
+	});
+	let health = Arc::new(HealthCheckTask { storage: storage.clone() });
+	let stats = Arc::new(StatsRefreshTask { storage });
+	let mut manager = BackgroundTaskManager::new();
+	manager.register_task(repair);
+	manager.register_task(compact);
+	manager.register_task(health);
+	manager.register_task(stats);
+	Some(Arc::new(manager))
 }
@@ -4,31 +4,52 @@
 //! including functions for connecting to the database, executing queries,
 //! and managing transactions.
 
+use crate::database::circuit_breaker::CircuitBreaker;
+use crate::database::retry::retry_write;
+use crate::database::storage::filesystem_cache::watch_mapping::WatchMappingHelpers;
 use crate::database::storage::filesystem_cache::RedbFilesystemCache;
 use crate::database::types::FilesystemNode;
 use crate::database::{
 	config::DatabaseConfig,
-	error::DatabaseResult,
-	storage::{DatabaseStorage, RedbStorage},
-	types::{DatabaseStats, EventRecord, MetadataRecord, StorageKey},
+	error::{DatabaseError, DatabaseResult},
+	query::EventQuery,
+	storage::{DatabaseStorage, ImportReport, RedbStorage},
+	types::{DatabaseStats, EventPage, EventRecord, MetadataRecord, StorageKey},
 };
 use crate::events::FileSystemEvent;
 use chrono::{DateTime, Utc};
+use std::future::Future;
 use std::path::Path;
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
 use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 use super::background::setup_background_manager;
 use super::maintenance::BackgroundMaintenanceMetrics;
 
+/// Async facade over the configured storage backend.
+///
+/// Durability contract: every write method commits its own redb transaction before returning `Ok`,
+/// with `DatabaseConfig::durability` (default `Durability::Immediate`, i.e. fsync on commit; the
+/// relaxed levels can lose acknowledged writes on a crash, see `Durability`). The one exception is
+/// `store_event` with `DatabaseConfig::batch_event_writes`, which buffers events in the adapter
+/// until [`DatabaseAdapter::flush`], `close`, a full batch or `flush_interval`; dropping the last
+/// handle without `close` commits that buffer from a spawned task, which a process exiting right
+/// after may not wait for. What `Drop` cannot guarantee is *when* the file lock is released: redb
+/// closes the file once the last `Arc<Database>` goes away, and clones of this adapter, caches from
+/// `get_filesystem_cache` and handles from `get_raw_database` all keep it alive. Call
+/// [`DatabaseAdapter::close`] before reopening the same path in-process.
 #[derive(Clone)]
 pub struct DatabaseAdapter {
 	storage: Arc<RwLock<Box<dyn DatabaseStorage>>>,
 	config: DatabaseConfig,
 	enabled: bool,
 	maintenance_metrics: Arc<RwLock<BackgroundMaintenanceMetrics>>,
+	/// Events not committed yet under `DatabaseConfig::batch_event_writes`, shared by clones
+	write_buffer: Arc<tokio::sync::Mutex<WriteBuffer>>,
+	/// Present with `DatabaseConfig::circuit_breaker`, shared by clones
+	breaker: Option<Arc<Mutex<CircuitBreaker>>>,
 	#[allow(dead_code)]
 	background_manager: Option<Arc<crate::database::background_tasks::BackgroundTaskManager>>,
 }
@@ -36,16 +57,92 @@ pub struct DatabaseAdapter {
 impl DatabaseAdapter {
 	/// Create a new database adapter with the given configuration
 	pub async fn new(config: DatabaseConfig) -> DatabaseResult<Self> {
-		let storage: Box<dyn DatabaseStorage> = Box::new(RedbStorage::new(config.clone()).await?);
-		let enabled = true;
-		let background_manager = setup_background_manager(storage.as_ref());
-		Ok(Self {
-			storage: Arc::new(RwLock::new(storage)),
+		let storage = RedbStorage::new(config.clone()).await?;
+		Ok(Self::with_storage(Box::new(storage), config))
+	}
+
+	/// Adapter over an already initialized storage backend
+	pub(crate) fn with_storage(storage: Box<dyn DatabaseStorage>, config: DatabaseConfig) -> Self {
+		let storage = Arc::new(RwLock::new(storage));
+		let background_manager = setup_background_manager(&storage, &config);
+		let breaker = config
+			.circuit_breaker
+			.clone()
+			.map(|breaker| Arc::new(Mutex::new(CircuitBreaker::new(breaker))));
+		let adapter = Self {
+			storage,
 			config,
-			enabled,
+			enabled: true,
 			maintenance_metrics: Arc::new(RwLock::new(BackgroundMaintenanceMetrics::new())),
+			write_buffer: Arc::default(),
+			breaker,
 			background_manager,
-		})
+		};
+		if adapter.config.batch_event_writes {
+			adapter.spawn_flush_timer();
+		}
+		adapter
+	}
+
+	/// Handle over `storage` and `write_buffer` used to commit from a spawned task; it runs no
+	/// background tasks and does not flush again when dropped
+	fn committer(
+		storage: Arc<RwLock<Box<dyn DatabaseStorage>>>,
+		write_buffer: Arc<tokio::sync::Mutex<WriteBuffer>>, config: DatabaseConfig,
+		breaker: Option<Arc<Mutex<CircuitBreaker>>>,
+	) -> Self {
+		Self {
+			storage,
+			config: DatabaseConfig { batch_event_writes: false, ..config },
+			enabled: true,
+			maintenance_metrics: Arc::new(RwLock::new(BackgroundMaintenanceMetrics::new())),
+			write_buffer,
+			breaker,
+			background_manager: None,
+		}
+	}
+
+	/// Commit the write buffer once its oldest event has waited `flush_interval`, so the end of
+	/// a burst does not wait for the next `store_event`. Holds the adapter weakly and ends with
+	/// the last handle.
+	fn spawn_flush_timer(&self) {
+		let storage = Arc::downgrade(&self.storage);
+		let write_buffer = Arc::downgrade(&self.write_buffer);
+		let config = self.config.clone();
+		let breaker = self.breaker.clone();
+		let interval = config.flush_interval;
+		crate::runtime::spawn(async move {
+			let mut wait = interval;
+			loop {
+				crate::runtime::sleep(wait).await;
+				let Some(write_buffer) = write_buffer.upgrade() else {
+					return;
+				};
+				let mut buffer = write_buffer.lock().await;
+				let age = buffer.oldest_age();
+				if buffer.records.is_empty() || age < interval {
+					wait = interval - age;
+					continue;
+				}
+				wait = interval;
+				// Upgraded only while the buffer is locked, which `close` relies on
+				let Some(storage) = storage.upgrade() else {
+					return;
+				};
+				let committer = Self::committer(
+					storage,
+					write_buffer.clone(),
+					config.clone(),
+					breaker.clone(),
+				);
+				if let Err(e) = committer.commit_buffered(&mut buffer).await {
+					warn!(
+						"Failed to commit buffered events after flush_interval: {}",
+						e
+					);
+				}
+			}
+		});
 	}
 
 	/// Create a disabled adapter (no-op implementation for when database is not needed)
@@ -55,6 +152,8 @@ impl DatabaseAdapter {
 			config: DatabaseConfig::default(),
 			enabled: false,
 			maintenance_metrics: Arc::new(RwLock::new(BackgroundMaintenanceMetrics::new())),
+			write_buffer: Arc::default(),
+			breaker: None,
 			background_manager: None,
 		}
 	}
@@ -76,16 +175,129 @@ impl DatabaseAdapter {
 			return Ok(());
 		}
 		// TODO: This is a workaround for missing EventRecord::from_event_with_retention. Use EventRecord::new instead.
+		let retention = self
+			.config
+			.event_type_retention
+			.get(&event.event_type)
+			.unwrap_or(&self.config.event_retention);
 		let record = EventRecord::new(
 			format!("{:?}", event.event_type),
 			event.path.clone(),
 			event.is_directory,
-			chrono::Duration::from_std(self.config.event_retention)
+			chrono::Duration::from_std(*retention)
 				.unwrap_or_else(|_| chrono::Duration::seconds(86400)),
 			0, // sequence_number placeholder
 		);
-		let mut storage = self.storage.write().await;
-		storage.store_event(&record).await
+		let record = EventRecord {
+			confidence: event.move_data.as_ref().map(|move_data| move_data.confidence),
+			detection_method: event
+				.move_data
+				.as_ref()
+				.map(|move_data| format!("{:?}", move_data.detection_method)),
+			source_path: event.move_data.as_ref().map(|move_data| move_data.source_path.clone()),
+			..record
+		};
+		if self.config.batch_event_writes {
+			let mut buffer = self.write_buffer.lock().await;
+			buffer.push(record);
+			if buffer.records.len() >= self.config.write_batch_size
+				|| buffer.oldest_age() >= self.config.flush_interval
+			{
+				self.commit_buffered(&mut buffer).await?;
+			}
+			return Ok(());
+		}
+		self.guarded(retry_write(
+			&self.config.write_retry,
+			"store_event",
+			|| async { self.storage.write().await.store_event(&record).await },
+		))
+		.await
+	}
+
+	/// Whether `DatabaseConfig::circuit_breaker` is open, i.e. event and metadata writes are
+	/// being skipped (or probed) until the database recovers; always false without a breaker
+	pub fn persistence_suspended(&self) -> bool {
+		self.breaker.as_ref().is_some_and(|breaker| lock(breaker).is_open())
+	}
+
+	/// Run `write` unless the circuit breaker is open, and count its outcome
+	async fn guarded<T>(
+		&self, write: impl Future<Output = DatabaseResult<T>>,
+	) -> DatabaseResult<T> {
+		let Some(breaker) = &self.breaker else {
+			return write.await;
+		};
+		if !lock(breaker).admit(Instant::now()) {
+			return Err(DatabaseError::CircuitOpen);
+		}
+		let started = Instant::now();
+		let result = write.await;
+		let now = Instant::now();
+		let mut breaker = lock(breaker);
+		let was_open = breaker.is_open();
+		breaker.record(result.is_ok(), now - started, now);
+		match (was_open, breaker.is_open()) {
+			(false, true) => warn!(
+				"Database writes keep failing or stalling; skipping them for {:?}",
+				self.config.circuit_breaker.as_ref().map(|config| config.open_duration)
+			),
+			(true, false) => info!("Database write succeeded again; persistence resumed"),
+			_ => {}
+		}
+		result
+	}
+
+	/// Events buffered by `store_event` under `DatabaseConfig::batch_event_writes` and not
+	/// committed yet; always 0 without it
+	pub async fn pending_write_count(&self) -> usize {
+		self.write_buffer.lock().await.records.len()
+	}
+
+	/// Commit the events buffered under `DatabaseConfig::batch_event_writes` in one transaction,
+	/// returning how many were committed.
+	///
+	/// Once this returns `Ok` they are durable and visible to queries. Unrelated to
+	/// [`DatabaseAdapter::compact`], which only reclaims space. On error the events stay
+	/// buffered for the next attempt.
+	pub async fn flush(&self) -> DatabaseResult<usize> {
+		if !self.enabled {
+			return Ok(0);
+		}
+		let mut buffer = self.write_buffer.lock().await;
+		self.commit_buffered(&mut buffer).await
+	}
+
+	/// Commit `buffer` while its lock is held, so batches reach the log in the order they were
+	/// buffered
+	async fn commit_buffered(&self, buffer: &mut WriteBuffer) -> DatabaseResult<usize> {
+		if buffer.records.is_empty() {
+			return Ok(0);
+		}
+		let records = &buffer.records;
+		let result = self
+			.guarded(retry_write(
+				&self.config.write_retry,
+				"store_events",
+				|| async { self.storage.write().await.store_events(records).await },
+			))
+			.await;
+		if let Err(e) = result {
+			let excess = buffer.records.len().saturating_sub(self.config.memory_buffer_size);
+			if excess > 0 {
+				warn!(
+					"Event batch commit failed and the buffer is full; dropping the {} oldest events",
+					excess
+				);
+				buffer.records.drain(..excess);
+			}
+			return Err(e);
+		}
+		let committed = buffer.records.len();
+		buffer.records.clear();
+		buffer.oldest = None;
+		debug!("Committed {} buffered events", committed);
+		Ok(committed)
 	}
 
 	pub async fn store_metadata(
@@ -96,8 +308,12 @@ impl DatabaseAdapter {
 		}
 		// TODO: This is a workaround for missing MetadataRecord::from_metadata. Use MetadataRecord::new instead.
 		let record = MetadataRecord::new(path.to_path_buf(), metadata.is_dir());
-		let mut storage = self.storage.write().await;
-		storage.store_metadata(&record).await
+		self.guarded(retry_write(
+			&self.config.write_retry,
+			"store_metadata",
+			|| async { self.storage.write().await.store_metadata(&record).await },
+		))
+		.await
 	}
 
 	pub async fn get_events_for_path(&self, path: &Path) -> DatabaseResult<Vec<EventRecord>> {
@@ -109,6 +325,20 @@ impl DatabaseAdapter {
 		storage.get_events(&key).await
 	}
 
+	/// Events for `path` a page at a time: up to `limit` events with a `sequence_number` above
+	/// `after`, oldest first. Pass `None` for the first page and the returned `next_cursor` for
+	/// each following one; events appended meanwhile show up on later pages.
+	pub async fn get_events_for_path_paged(
+		&self, path: &Path, after: Option<u64>, limit: usize,
+	) -> DatabaseResult<EventPage> {
+		if !self.enabled {
+			return Ok(EventPage::default());
+		}
+		let key = StorageKey::path_hash(path);
+		let mut storage = self.storage.write().await;
+		storage.get_events_page(&key, after, limit).await
+	}
+
 	pub async fn get_metadata(&self, path: &Path) -> DatabaseResult<Option<MetadataRecord>> {
 		if !self.enabled {
 			return Ok(None);
@@ -117,6 +347,30 @@ impl DatabaseAdapter {
 		storage.get_metadata(path).await
 	}
 
+	/// The last event stored for `path`, from the compacted table kept with
+	/// `DatabaseConfig::track_current_state`; `None` without it or if nothing was stored
+	///
+	/// One lookup however long the log is, and unaffected by retention pruning the log. Events
+	/// still buffered under `DatabaseConfig::batch_event_writes` are not reflected until
+	/// flushed. A removed path reports its Remove, and so does the source of a move, with the
+	/// move's sequence number, confidence and detection method.
+	pub async fn current_state(&self, path: &Path) -> DatabaseResult<Option<EventRecord>> {
+		if !self.enabled {
+			return Ok(None);
+		}
+		let mut storage = self.storage.write().await;
+		storage.get_current_state(path).await
+	}
+
+	/// Stored events matching `query`, in append order; see [`EventQuery`]
+	pub async fn query(&self, query: EventQuery) -> DatabaseResult<Vec<EventRecord>> {
+		if !self.enabled {
+			return Ok(Vec::new());
+		}
+		let mut storage = self.storage.write().await;
+		storage.query_events(&query).await
+	}
+
 	pub async fn find_events_by_time_range(
 		&self, start: DateTime<Utc>, end: DateTime<Utc>,
 	) -> DatabaseResult<Vec<EventRecord>> {
@@ -127,13 +381,21 @@ impl DatabaseAdapter {
 		storage.find_events_by_time_range(start, end).await
 	}
 
+	/// Remove events past `DatabaseConfig::event_retention`, or past the
+	/// `WatchMetadata::event_retention` of a watch whose root contains them.
+	///
+	/// Events do not record their watch, so they are attributed by path; with overlapping
+	/// watches the longest per-watch retention wins, and watches without one are not
+	/// considered. Events buffered under `batch_event_writes` are not touched.
+	///
+	/// Ages are measured with [`crate::database::clock::now`], so a backward jump of the system
+	/// clock does not make events stored during it look older than they are.
 	pub async fn cleanup_old_events(&self) -> DatabaseResult<usize> {
 		if !self.enabled {
 			return Ok(0);
 		}
-		let cutoff = SystemTime::now()
-			.checked_sub(self.config.event_retention)
-			.unwrap_or_else(SystemTime::now);
+		let now = crate::database::clock::now_system();
+		let cutoff = now.checked_sub(self.config.event_retention).unwrap_or(now);
 		let mut storage = self.storage.write().await;
 		let count = storage.cleanup_expired_events(cutoff).await?;
 		if count > 0 {
@@ -160,6 +422,14 @@ impl DatabaseAdapter {
 		storage.get_stats().await
 	}
 
+	/// Compact the database file unconditionally.
+	///
+	/// Fails while a filesystem cache or raw database handle obtained from this adapter is
+	/// still alive, including the ones a running watcher holds; see
+	/// `storage::maintenance::compact_database`. The background compaction task only does this
+	/// once [`DatabaseAdapter::fragmentation_ratio`] exceeds
+	/// `DatabaseConfig::compaction_fragmentation_threshold` and the free space
+	/// `compaction_min_reclaimable_bytes`, and skips it while other handles are open.
 	pub async fn compact(&self) -> DatabaseResult<()> {
 		if !self.enabled {
 			return Ok(());
@@ -168,6 +438,19 @@ impl DatabaseAdapter {
 		storage.compact().await
 	}
 
+	/// Share of the database file not holding live data, from 0.0 to 1.0.
+	///
+	/// Rises as events are deleted (the freed pages stay in the file) and drops after
+	/// [`DatabaseAdapter::compact`]. Small databases read high regardless, since redb
+	/// preallocates space. Always 0.0 for a disabled adapter.
+	pub async fn fragmentation_ratio(&self) -> DatabaseResult<f64> {
+		if !self.enabled {
+			return Ok(0.0);
+		}
+		let storage = self.storage.read().await;
+		storage.fragmentation_ratio().await
+	}
+
 	pub async fn health_check(&self) -> DatabaseResult<bool> {
 		if !self.enabled {
 			return Ok(true);
@@ -201,7 +484,24 @@ impl DatabaseAdapter {
 		storage
 			.as_any()
 			.downcast_ref::<crate::database::storage::core::RedbStorage>()
-			.map(|redb_storage| RedbFilesystemCache::new(redb_storage.database().clone()))
+			.map(|redb_storage| redb_storage.cache())
+	}
+
+	/// Ids of the watches whose filesystem cache holds a node for `path`, i.e. the watches
+	/// monitoring it, in no particular order.
+	///
+	/// Read from the path-to-watches index the cache maintains, keyed like the cache by the
+	/// canonical path (or `path` as given when it cannot be canonicalized). A path is only known
+	/// once a watch has cached it, through its initial scan or an event, and entries are not
+	/// removed when the node is, so a path deleted since may still list its former watches.
+	/// Empty for a disabled adapter.
+	pub async fn watches_covering(&self, path: &Path) -> DatabaseResult<Vec<uuid::Uuid>> {
+		let Some(database) = self.get_raw_database().await.filter(|_| self.enabled) else {
+			return Ok(Vec::new());
+		};
+		let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+		let path_hash = crate::database::types::calculate_path_hash(&canonical);
+		WatchMappingHelpers::get_watches_for_path(&database, path_hash)
 	}
 
 	pub async fn get_raw_database(&self) -> Option<Arc<redb::Database>> {
@@ -212,6 +512,59 @@ impl DatabaseAdapter {
 			.map(|redb_storage| redb_storage.get_database())
 	}
 
+	/// Merge events, metadata and filesystem cache nodes from another database file into this one.
+	///
+	/// Watch ids are kept as they are; a watch present in both files ends up with the union of
+	/// its nodes and is listed in `ImportReport::shared_watch_ids`. Imported events get fresh
+	/// sequence numbers after the existing ones, so the merged log still has a single strictly
+	/// increasing order. Where both files hold a different value for the same key, this
+	/// database's value is kept and the key is reported as a conflict. Importing the same file
+	/// twice does not duplicate events. See `storage::import` for what is not merged.
+	///
+	/// The source must not be open elsewhere (close its adapter first). A disabled adapter
+	/// imports nothing.
+	pub async fn import_from(&self, other_db_path: &Path) -> DatabaseResult<ImportReport> {
+		if !self.enabled {
+			return Ok(ImportReport::default());
+		}
+		if let (Ok(source), Ok(own)) = (
+			other_db_path.canonicalize(),
+			self.config.database_path.canonicalize(),
+		) {
+			if source == own {
+				return Err(DatabaseError::InvalidConfiguration(format!(
+					"cannot import {} into itself",
+					source.display()
+				)));
+			}
+		}
+		// Holding the write lock keeps store_* calls from interleaving with the import
+		let storage = self.storage.write().await;
+		let database = storage
+			.as_any()
+			.downcast_ref::<RedbStorage>()
+			.map(|redb_storage| redb_storage.get_database())
+			.ok_or_else(|| {
+				DatabaseError::StorageError("import requires the redb storage backend".to_string())
+			})?;
+		let path = other_db_path.to_path_buf();
+		let report = crate::runtime::spawn_blocking(move || {
+			crate::database::storage::import::import_database(&database, &path)
+		})
+		.join()
+		.await
+		.ok_or_else(|| DatabaseError::Other("database import task panicked".to_string()))??;
+		drop(storage);
+		info!(
+			"Imported {} records from {} ({} events resequenced, {} conflicts)",
+			report.records_imported(),
+			other_db_path.display(),
+			report.events_resequenced,
+			report.conflicts.len()
+		);
+		Ok(report)
+	}
+
 	pub async fn get_maintenance_metrics(&self) -> BackgroundMaintenanceMetrics {
 		self.maintenance_metrics.read().await.clone()
 	}
@@ -222,11 +575,114 @@ impl DatabaseAdapter {
 		}
 		if let Some(manager) = &self.background_manager {
 			let manager = manager.clone();
-			tokio::spawn(async move {
+			crate::runtime::spawn(async move {
 				manager.start().await;
 			});
 		}
 	}
+
+	/// Hold the storage the way a stuck store call would, until the guard is dropped
+	#[cfg(test)]
+	pub(crate) async fn lock_storage(
+		&self,
+	) -> tokio::sync::OwnedRwLockWriteGuard<Box<dyn DatabaseStorage>> {
+		self.storage.clone().write_owned().await
+	}
+
+	/// Stop background maintenance and release this handle to the database.
+	///
+	/// Waits for in-flight writes on other clones and for any running maintenance task, so when
+	/// this returns and no other clone/cache handle exists the redb file is closed and can be
+	/// reopened. If other clones are still alive they keep the database (and its background
+	/// tasks) running; that case is logged rather than treated as an error.
+	pub async fn close(self) -> DatabaseResult<()> {
+		if !self.enabled {
+			return Ok(());
+		}
+		// Held to the end so the flush timer cannot hold the storage during the count below
+		let mut buffer = self.write_buffer.lock().await;
+		let flushed = self.commit_buffered(&mut buffer).await;
+		// Taking the write lock drains any store_* call that is still holding the storage.
+		drop(self.storage.write().await);
+		if Arc::strong_count(&self.storage) > 1 {
+			warn!(
+				"Closing one of {} database adapter handles; {} stays open until the rest are dropped",
+				Arc::strong_count(&self.storage),
+				self.config.database_path.display()
+			);
+			return flushed.map(|_| ());
+		}
+		if let Some(manager) = &self.background_manager {
+			manager.shutdown().await;
+		}
+		if !buffer.records.is_empty() {
+			// Retrying from `Drop` would keep the file open after `close` returns
+			warn!(
+				"Dropping {} buffered events that could not be committed on close",
+				buffer.records.len()
+			);
+			buffer.records.clear();
+		}
+		drop(buffer);
+		debug!(
+			"Database adapter closed: {}",
+			self.config.database_path.display()
+		);
+		flushed.map(|_| ())
+	}
+}
+
+impl Drop for DatabaseAdapter {
+	fn drop(&mut self) {
+		// Only the last handle flushes, and only if events are still buffered
+		if !self.config.batch_event_writes || Arc::strong_count(&self.write_buffer) > 1 {
+			return;
+		}
+		if self.write_buffer.try_lock().map_or(true, |buffer| buffer.records.is_empty()) {
+			return;
+		}
+		if !crate::runtime::is_available() {
+			warn!("Database adapter dropped outside a runtime; buffered events are lost");
+			return;
+		}
+		let committer = Self::committer(
+			self.storage.clone(),
+			self.write_buffer.clone(),
+			self.config.clone(),
+			self.breaker.clone(),
+		);
+		crate::runtime::spawn(async move {
+			if let Err(e) = committer.flush().await {
+				warn!(
+					"Failed to commit buffered events of a dropped database adapter: {}",
+					e
+				);
+			}
+		});
+	}
+}
+
+fn lock(breaker: &Mutex<CircuitBreaker>) -> std::sync::MutexGuard<'_, CircuitBreaker> {
+	breaker.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Events buffered by `DatabaseAdapter::store_event`, oldest first
+#[derive(Default)]
+struct WriteBuffer {
+	records: Vec<EventRecord>,
+	/// When the oldest buffered event was stored
+	oldest: Option<std::time::Instant>,
+}
+
+impl WriteBuffer {
+	fn push(&mut self, record: EventRecord) {
+		self.oldest.get_or_insert_with(std::time::Instant::now);
+		self.records.push(record);
+	}
+
+	fn oldest_age(&self) -> std::time::Duration {
+		self.oldest.map(|oldest| oldest.elapsed()).unwrap_or_default()
+	}
 }
 
 struct NoOpStorage;
@@ -326,4 +782,287 @@ impl DatabaseStorage for NoOpStorage {
 	async fn compact(&mut self) -> DatabaseResult<()> {
 		Ok(())
 	}
+	async fn fragmentation_ratio(&self) -> DatabaseResult<f64> {
+		Ok(0.0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::types::WatchMetadata;
+	use crate::events::EventType;
+	use std::sync::Mutex;
+
+	type Stored = Arc<Mutex<Vec<EventRecord>>>;
+
+	/// Storage whose event writes fail with `failure` for the first `failures` attempts
+	struct FlakyStorage {
+		failures: usize,
+		failure: fn() -> DatabaseError,
+		attempts: Arc<Mutex<usize>>,
+		stored: Stored,
+	}
+
+	#[async_trait::async_trait]
+	impl DatabaseStorage for FlakyStorage {
+		fn as_any(&self) -> &dyn std::any::Any {
+			self
+		}
+		async fn initialize(&mut self) -> DatabaseResult<()> {
+			Ok(())
+		}
+		async fn store_event(&mut self, record: &EventRecord) -> DatabaseResult<()> {
+			let mut attempts = self.attempts.lock().unwrap();
+			*attempts += 1;
+			if *attempts <= self.failures {
+				return Err((self.failure)());
+			}
+			self.stored.lock().unwrap().push(record.clone());
+			Ok(())
+		}
+		async fn store_metadata(&mut self, _metadata: &MetadataRecord) -> DatabaseResult<()> {
+			Ok(())
+		}
+		async fn get_events(&mut self, _key: &StorageKey) -> DatabaseResult<Vec<EventRecord>> {
+			Ok(Vec::new())
+		}
+		async fn get_metadata(&mut self, _path: &Path) -> DatabaseResult<Option<MetadataRecord>> {
+			Ok(None)
+		}
+		async fn find_events_by_time_range(
+			&mut self, _start: DateTime<Utc>, _end: DateTime<Utc>,
+		) -> DatabaseResult<Vec<EventRecord>> {
+			Ok(Vec::new())
+		}
+		async fn close(self) -> DatabaseResult<()> {
+			Ok(())
+		}
+		async fn store_filesystem_node(
+			&mut self, _watch_id: &uuid::Uuid, _node: &FilesystemNode, _event_type: &str,
+		) -> DatabaseResult<()> {
+			Ok(())
+		}
+		async fn get_filesystem_node(
+			&mut self, _watch_id: &uuid::Uuid, _path: &Path,
+		) -> DatabaseResult<Option<FilesystemNode>> {
+			Ok(None)
+		}
+		async fn get_node(
+			&mut self, _watch_id: &uuid::Uuid, _path: &Path,
+		) -> DatabaseResult<Option<FilesystemNode>> {
+			Ok(None)
+		}
+		async fn list_directory_for_watch(
+			&mut self, _watch_id: &uuid::Uuid, _parent_path: &Path,
+		) -> DatabaseResult<Vec<FilesystemNode>> {
+			Ok(Vec::new())
+		}
+		async fn batch_store_filesystem_nodes(
+			&mut self, _watch_id: &uuid::Uuid, _nodes: &[FilesystemNode], _event_type: &str,
+		) -> DatabaseResult<()> {
+			Ok(())
+		}
+		async fn store_watch_metadata(&mut self, _metadata: &WatchMetadata) -> DatabaseResult<()> {
+			Ok(())
+		}
+		async fn get_watch_metadata(
+			&mut self, _watch_id: &uuid::Uuid,
+		) -> DatabaseResult<Option<WatchMetadata>> {
+			Ok(None)
+		}
+		async fn delete_events_older_than(&mut self, _cutoff: SystemTime) -> DatabaseResult<usize> {
+			Ok(0)
+		}
+		async fn count_events(&self) -> DatabaseResult<usize> {
+			Ok(0)
+		}
+		async fn delete_oldest_events(&mut self, _n: usize) -> DatabaseResult<usize> {
+			Ok(0)
+		}
+		async fn search_nodes(&mut self, _pattern: &str) -> DatabaseResult<Vec<FilesystemNode>> {
+			Ok(Vec::new())
+		}
+		async fn cleanup_expired_events(&mut self, _cutoff: SystemTime) -> DatabaseResult<usize> {
+			Ok(0)
+		}
+		async fn cleanup_events_with_policy(
+			&mut self, _config: &crate::database::storage::event_retention::EventRetentionConfig,
+		) -> DatabaseResult<usize> {
+			Ok(0)
+		}
+		async fn get_stats(&self) -> DatabaseResult<DatabaseStats> {
+			Ok(DatabaseStats::default())
+		}
+		async fn compact(&mut self) -> DatabaseResult<()> {
+			Ok(())
+		}
+		async fn fragmentation_ratio(&self) -> DatabaseResult<f64> {
+			Ok(0.0)
+		}
+	}
+
+	/// Adapter over a `FlakyStorage`, with its attempt counter and stored records
+	fn flaky_adapter(
+		failures: usize, failure: fn() -> DatabaseError,
+	) -> (DatabaseAdapter, Arc<Mutex<usize>>, Stored) {
+		let attempts = Arc::new(Mutex::new(0));
+		let stored = Arc::new(Mutex::new(Vec::new()));
+		let storage =
+			FlakyStorage { failures, failure, attempts: attempts.clone(), stored: stored.clone() };
+		let adapter = DatabaseAdapter::with_storage(Box::new(storage), DatabaseConfig::default());
+		(adapter, attempts, stored)
+	}
+
+	#[tokio::test]
+	async fn test_circuit_breaker_trips_and_recovers() {
+		use crate::database::config::CircuitBreakerConfig;
+		use std::time::Duration;
+
+		let breaker_adapter = |failures, breaker| {
+			let attempts = Arc::new(Mutex::new(0));
+			let stored = Arc::new(Mutex::new(Vec::new()));
+			let storage = FlakyStorage {
+				failures,
+				failure: || DatabaseError::Timeout,
+				attempts: attempts.clone(),
+				stored: stored.clone(),
+			};
+			let mut config =
+				DatabaseConfig { circuit_breaker: Some(breaker), ..Default::default() };
+			config.write_retry.max_retries = 0;
+			(
+				DatabaseAdapter::with_storage(Box::new(storage), config),
+				attempts,
+				stored,
+			)
+		};
+		// Reached the storage and failed there
+		let failed = |result: DatabaseResult<()>| matches!(result, Err(e) if !matches!(e, DatabaseError::CircuitOpen));
+		let event = FileSystemEvent::new(EventType::Create, "/w/a.txt".into(), false, Some(1));
+		let open_duration = Duration::from_millis(50);
+		let breaker = CircuitBreakerConfig {
+			failure_threshold: 2,
+			slow_write_threshold: None,
+			open_duration,
+		};
+
+		// Three failing writes, then a healthy database
+		let (adapter, attempts, stored) = breaker_adapter(3, breaker.clone());
+		assert!(failed(adapter.store_event(&event).await));
+		assert!(!adapter.persistence_suspended());
+		assert!(failed(adapter.store_event(&event).await));
+		assert!(adapter.persistence_suspended());
+		// Open: writes are skipped without reaching the storage
+		assert!(matches!(
+			adapter.store_event(&event).await,
+			Err(DatabaseError::CircuitOpen)
+		));
+		assert!(matches!(
+			adapter.store_metadata(Path::new("/w"), &std::fs::metadata(".").unwrap()).await,
+			Err(DatabaseError::CircuitOpen)
+		));
+		assert_eq!(*attempts.lock().unwrap(), 2);
+
+		// The probe after `open_duration` fails and reopens the breaker
+		tokio::time::sleep(open_duration).await;
+		assert!(failed(adapter.store_event(&event).await));
+		assert!(matches!(
+			adapter.store_event(&event).await,
+			Err(DatabaseError::CircuitOpen)
+		));
+		assert_eq!(*attempts.lock().unwrap(), 3);
+
+		// The next probe succeeds and closes it
+		tokio::time::sleep(open_duration).await;
+		adapter.store_event(&event).await.unwrap();
+		assert!(!adapter.persistence_suspended());
+		adapter.store_event(&event).await.unwrap();
+		assert_eq!(stored.lock().unwrap().len(), 2);
+
+		// Writes slower than `slow_write_threshold` trip it even though they succeed
+		let slow = CircuitBreakerConfig {
+			failure_threshold: 1,
+			slow_write_threshold: Some(Duration::ZERO),
+			..breaker
+		};
+		let (adapter, _, stored) = breaker_adapter(0, slow);
+		adapter.store_event(&event).await.unwrap();
+		assert!(adapter.persistence_suspended());
+		assert!(matches!(
+			adapter.store_event(&event).await,
+			Err(DatabaseError::CircuitOpen)
+		));
+		assert_eq!(stored.lock().unwrap().len(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_buffered_events_are_committed_without_another_store() {
+		use std::time::Duration;
+
+		let batching = || {
+			let stored = Arc::new(Mutex::new(Vec::new()));
+			let storage = FlakyStorage {
+				failures: 0,
+				failure: || DatabaseError::Timeout,
+				attempts: Arc::default(),
+				stored: stored.clone(),
+			};
+			let config = DatabaseConfig {
+				batch_event_writes: true,
+				flush_interval: Duration::from_millis(50),
+				..Default::default()
+			};
+			(
+				DatabaseAdapter::with_storage(Box::new(storage), config),
+				stored,
+			)
+		};
+		let event = FileSystemEvent::new(EventType::Create, "/w/a.txt".into(), false, Some(1));
+
+		// The timer commits the tail of a burst once `flush_interval` has passed
+		let (adapter, stored) = batching();
+		adapter.store_event(&event).await.unwrap();
+		assert_eq!(adapter.pending_write_count().await, 1);
+		tokio::time::sleep(Duration::from_millis(200)).await;
+		assert_eq!(adapter.pending_write_count().await, 0);
+		assert_eq!(stored.lock().unwrap().len(), 1);
+
+		// Dropping the last handle commits what is still buffered
+		let (adapter, stored) = batching();
+		adapter.store_event(&event).await.unwrap();
+		drop(adapter);
+		tokio::time::sleep(Duration::from_millis(20)).await;
+		assert_eq!(stored.lock().unwrap().len(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_transient_write_failures_are_retried() {
+		let (adapter, attempts, stored) = flaky_adapter(1, || DatabaseError::Timeout);
+		let event = FileSystemEvent::new(EventType::Create, "/w/a.txt".into(), false, Some(1));
+
+		adapter.store_event(&event).await.unwrap();
+		assert_eq!(*attempts.lock().unwrap(), 2);
+		assert_eq!(stored.lock().unwrap().len(), 1);
+	}
+
+	#[tokio::test]
+	async fn test_permanent_write_failures_are_not_retried() {
+		let (adapter, attempts, stored) = flaky_adapter(1, || DatabaseError::SizeLimitExceeded);
+		let event = FileSystemEvent::new(EventType::Create, "/w/a.txt".into(), false, Some(1));
+
+		let error = adapter.store_event(&event).await.unwrap_err();
+		assert!(
+			matches!(error, DatabaseError::SizeLimitExceeded),
+			"{error:?}"
+		);
+		assert_eq!(*attempts.lock().unwrap(), 1);
+		assert!(stored.lock().unwrap().is_empty());
+
+		// A write that keeps timing out gives up after `max_retries` retries
+		let (adapter, attempts, _) = flaky_adapter(usize::MAX, || DatabaseError::Timeout);
+		assert!(adapter.store_event(&event).await.is_err());
+		let expected = DatabaseConfig::default().write_retry.max_retries as usize + 1;
+		assert_eq!(*attempts.lock().unwrap(), expected);
+	}
 }
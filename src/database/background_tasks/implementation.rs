@@ -4,16 +4,35 @@
 //! database compaction, and other core maintenance operations.
 
 use crate::database::background_tasks::BackgroundTask;
-use crate::database::storage::maintenance;
+use crate::database::storage::{maintenance, DatabaseStorage, RedbStorage};
 use anyhow::Error;
 use redb::Database;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+/// The adapter's storage, as the background tasks see it
+pub type SharedStorage = RwLock<Box<dyn DatabaseStorage>>;
+
+/// Borrow the redb database for one run; `None` once the storage is gone or not redb.
+///
+/// Tasks hold a `Weak` reference to the storage rather than to the database itself, so a
+/// scheduled task never keeps the redb file open (and locked) after the owning storage has
+/// been dropped, and between runs nothing stands in the way of compaction, which needs the
+/// only reference to the database.
+async fn database_of(storage: &Weak<SharedStorage>) -> Option<Arc<Database>> {
+	let storage = storage.upgrade()?;
+	let storage = storage.read().await;
+	storage.as_any().downcast_ref::<RedbStorage>().map(RedbStorage::get_database)
+}
 
 /// Background task for repairing the time index.
+///
+/// A run after the owning storage has been dropped is a no-op, as for every task here.
 pub struct TimeIndexRepairTask {
-	pub db: Arc<Database>,
+	pub storage: Weak<SharedStorage>,
 }
 
 impl BackgroundTask for TimeIndexRepairTask {
@@ -27,14 +46,27 @@ impl BackgroundTask for TimeIndexRepairTask {
 		Duration::from_secs(3600) // 1 hour
 	}
 	fn run(&self) -> Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send>> {
-		let db = self.db.clone();
-		Box::pin(async move { maintenance::repair_time_index(&db).await.map_err(Error::from) })
+		let storage = self.storage.clone();
+		Box::pin(async move {
+			let Some(db) = database_of(&storage).await else {
+				return Ok(());
+			};
+			maintenance::repair_time_index(&db).await.map_err(Error::from)
+		})
 	}
 }
 
-/// Background task for compacting the database.
+/// Background task for compacting the database once it is fragmented enough.
+///
+/// Each run only measures the file (in a read transaction) and compacts when both its
+/// fragmentation ratio and its free bytes exceed their thresholds, since compacting a large
+/// file is slow and blocks writers. While anything else holds the database, e.g. a running
+/// watcher's caches, compaction cannot get exclusive access (see
+/// `maintenance::compact_database`) and the run is skipped without error.
 pub struct CompactionTask {
-	pub db: Arc<Database>,
+	pub storage: Weak<SharedStorage>,
+	pub fragmentation_threshold: f64,
+	pub min_reclaimable_bytes: u64,
 }
 
 impl BackgroundTask for CompactionTask {
@@ -48,14 +80,51 @@ impl BackgroundTask for CompactionTask {
 		Duration::from_secs(7200) // 2 hours
 	}
 	fn run(&self) -> Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send>> {
-		let db = self.db.clone();
-		Box::pin(async move { maintenance::compact_database(&db).await.map_err(Error::from) })
+		let storage = self.storage.clone();
+		let threshold = self.fragmentation_threshold;
+		let min_reclaimable = self.min_reclaimable_bytes;
+		Box::pin(async move {
+			let Some(storage) = storage.upgrade() else {
+				return Ok(());
+			};
+			let (ratio, reclaimable) = {
+				let storage = storage.read().await;
+				let Some(redb) = storage.as_any().downcast_ref::<RedbStorage>() else {
+					return Ok(());
+				};
+				(
+					storage.fragmentation_ratio().await?,
+					redb.reclaimable_bytes()?,
+				)
+			};
+			if ratio < threshold || reclaimable < min_reclaimable {
+				debug!(
+					"Fragmentation {:.2} ({} bytes free) below threshold {:.2} ({} bytes), not compacting",
+					ratio, reclaimable, threshold, min_reclaimable
+				);
+				return Ok(());
+			}
+			let mut storage = storage.write().await;
+			let exclusive = storage
+				.as_any()
+				.downcast_ref::<RedbStorage>()
+				.is_some_and(RedbStorage::has_exclusive_access);
+			if !exclusive {
+				debug!("Database is held elsewhere, skipping compaction");
+				return Ok(());
+			}
+			debug!(
+				"Fragmentation {:.2} ({} bytes free) reached threshold {:.2}, compacting",
+				ratio, reclaimable, threshold
+			);
+			storage.compact().await.map_err(Error::from)
+		})
 	}
 }
 
 /// Background task for health checking the database.
 pub struct HealthCheckTask {
-	pub db: Arc<Database>,
+	pub storage: Weak<SharedStorage>,
 }
 
 impl BackgroundTask for HealthCheckTask {
@@ -69,8 +138,11 @@ impl BackgroundTask for HealthCheckTask {
 		Duration::from_secs(1800) // 30 minutes
 	}
 	fn run(&self) -> Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send>> {
-		let db = self.db.clone();
+		let storage = self.storage.clone();
 		Box::pin(async move {
+			let Some(db) = database_of(&storage).await else {
+				return Ok(());
+			};
 			let _ok = maintenance::health_check(&db).await?;
 			Ok(())
 		})
@@ -79,7 +151,7 @@ impl BackgroundTask for HealthCheckTask {
 
 /// Background task for refreshing database stats.
 pub struct StatsRefreshTask {
-	pub db: Arc<Database>,
+	pub storage: Weak<SharedStorage>,
 }
 
 impl BackgroundTask for StatsRefreshTask {
@@ -93,8 +165,11 @@ impl BackgroundTask for StatsRefreshTask {
 		Duration::from_secs(3600) // 1 hour
 	}
 	fn run(&self) -> Pin<Box<dyn std::future::Future<Output = Result<(), Error>> + Send>> {
-		let db = self.db.clone();
+		let storage = self.storage.clone();
 		Box::pin(async move {
+			let Some(db) = database_of(&storage).await else {
+				return Ok(());
+			};
 			let _stats = maintenance::get_database_stats(&db).await?;
 			Ok(())
 		})
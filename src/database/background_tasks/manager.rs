@@ -4,11 +4,12 @@
 //! maintenance tasks (e.g., index repair, compaction, health checks) with isolation,
 //! adaptive scheduling, and observability.
 
+use crate::runtime::{self, JoinHandle};
 use rand::{rng, Rng};
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, RwLock};
 
@@ -41,6 +42,8 @@ pub struct BackgroundTaskManager {
 	tasks: Vec<Arc<dyn BackgroundTask>>,
 	metrics: Arc<RwLock<HashMap<String, TaskMetrics>>>,
 	trigger_senders: HashMap<String, mpsc::Sender<()>>,
+	/// Task loops never exit on their own, so they are aborted on shutdown/drop.
+	handles: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl BackgroundTaskManager {
@@ -49,6 +52,7 @@ impl BackgroundTaskManager {
 			tasks: Vec::new(),
 			metrics: Arc::new(RwLock::new(HashMap::new())),
 			trigger_senders: HashMap::new(),
+			handles: Mutex::new(Vec::new()),
 		}
 	}
 
@@ -58,7 +62,7 @@ impl BackgroundTaskManager {
 		self.trigger_senders.insert(name.clone(), tx);
 		self.tasks.push(task.clone());
 		let metrics = self.metrics.clone();
-		tokio::spawn(async move {
+		let handle = runtime::spawn(async move {
 			let mut backoff = 0u32;
 			loop {
 				let start = Instant::now();
@@ -101,11 +105,12 @@ impl BackgroundTaskManager {
 				let jitter = rng().random_range(0..base.as_millis().max(1) as u64 / 10);
 				let sleep_dur = base + Duration::from_millis(jitter);
 				tokio::select! {
-					_ = tokio::time::sleep(sleep_dur) => {},
+					_ = runtime::sleep(sleep_dur) => {},
 					_ = rx.recv() => {}, // On-demand trigger
 				}
 			}
 		});
+		self.handles.lock().unwrap_or_else(|e| e.into_inner()).push(handle);
 	}
 
 	pub async fn start(self: Arc<Self>) {
@@ -121,6 +126,33 @@ impl BackgroundTaskManager {
 	pub async fn get_metrics(&self) -> HashMap<String, TaskMetrics> {
 		self.metrics.read().await.clone()
 	}
+
+	/// Abort all task loops and wait until they have actually been torn down.
+	///
+	/// Unlike `Drop`, this guarantees that no task is still mid-run (and holding the database)
+	/// when it returns. A task aborted mid-run loses that run's work; none of the current tasks
+	/// write anything that a later run would not redo.
+	pub async fn shutdown(&self) {
+		let handles: Vec<_> =
+			self.handles.lock().unwrap_or_else(|e| e.into_inner()).drain(..).collect();
+		for handle in &handles {
+			handle.abort();
+		}
+		for handle in handles {
+			// None here is the expected cancellation, nothing to report
+			let _ = handle.join().await;
+		}
+	}
+}
+
+impl Drop for BackgroundTaskManager {
+	fn drop(&mut self) {
+		// Best effort: cancellation is processed by the runtime later, so a task that is
+		// currently running may finish its run after this returns. Use `shutdown` if that matters.
+		for handle in self.handles.lock().unwrap_or_else(|e| e.into_inner()).drain(..) {
+			handle.abort();
+		}
+	}
 }
 
 impl Default for BackgroundTaskManager {
@@ -0,0 +1,68 @@
+//! Suspending persistence while the database keeps failing
+//!
+//! Backs `DatabaseConfig::circuit_breaker`. The adapter asks [`CircuitBreaker::admit`] before
+//! each event or metadata write and reports how it went with [`CircuitBreaker::record`]; the
+//! breaker is shared by clones of the adapter, so the watcher's writes and an application's
+//! writes through the same adapter count together.
+
+use crate::database::config::CircuitBreakerConfig;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+	/// Writes go through; counting consecutive failures
+	Closed { failures: u32 },
+	/// Writes are skipped until `until`
+	Open { until: Instant },
+	/// One probe write is in flight; others are skipped until it is recorded
+	HalfOpen,
+}
+
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+	config: CircuitBreakerConfig,
+	state: State,
+}
+
+impl CircuitBreaker {
+	pub(crate) fn new(config: CircuitBreakerConfig) -> Self {
+		Self { config, state: State::Closed { failures: 0 } }
+	}
+
+	/// Whether a write may go to the storage at `now`; the first one after `open_duration`
+	/// becomes the probe
+	pub(crate) fn admit(&mut self, now: Instant) -> bool {
+		match self.state {
+			State::Closed { .. } => true,
+			State::Open { until } if now >= until => {
+				self.state = State::HalfOpen;
+				true
+			}
+			State::Open { .. } | State::HalfOpen => false,
+		}
+	}
+
+	/// Account for an admitted write that finished at `now` after `elapsed`
+	pub(crate) fn record(&mut self, succeeded: bool, elapsed: Duration, now: Instant) {
+		let slow = self.config.slow_write_threshold.is_some_and(|limit| elapsed > limit);
+		if succeeded && !slow {
+			self.state = State::Closed { failures: 0 };
+			return;
+		}
+		let failures = match self.state {
+			State::Closed { failures } => failures + 1,
+			// A failed probe reopens right away
+			State::Open { .. } | State::HalfOpen => self.config.failure_threshold,
+		};
+		self.state = if failures >= self.config.failure_threshold {
+			State::Open { until: now + self.config.open_duration }
+		} else {
+			State::Closed { failures }
+		};
+	}
+
+	/// Open or probing: writes are being skipped, or were until the probe in flight
+	pub(crate) fn is_open(&self) -> bool {
+		!matches!(self.state, State::Closed { .. })
+	}
+}
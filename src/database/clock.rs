@@ -0,0 +1,142 @@
+//! Wall-clock time for persisted timestamps
+//!
+//! Event timestamps, `expires_at` and retention cutoffs are wall-clock times, since they are
+//! stored and compared across restarts. The system clock can jump backward, typically when NTP
+//! corrects a clock that ran ahead. Events stamped with the raw clock during such a jump would
+//! sort before events stored earlier, and once the clock is corrected again they would look as
+//! old as the jump was long and could be pruned moments after being stored.
+//!
+//! [`now`] never goes backward within a process: after a backward jump it keeps advancing from
+//! the last reading at the rate of the monotonic clock, until the system clock catches up.
+//! Forward jumps are taken as they come. Across restarts the system clock is used as is, so a
+//! clock that is set back while the process is down still produces earlier timestamps, and
+//! time-range queries take whatever bounds they are given.
+
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+static CLOCK: MonotonicClock = MonotonicClock::new();
+
+/// Current wall-clock time, never earlier than a previous reading in this process
+pub fn now() -> DateTime<Utc> {
+	CLOCK.observe(Utc::now(), Instant::now())
+}
+
+/// [`now`] as a `SystemTime`
+pub fn now_system() -> SystemTime {
+	now().into()
+}
+
+/// Wall-clock readings clamped to never go backward
+#[derive(Debug, Default)]
+pub struct MonotonicClock {
+	last: Mutex<Option<(DateTime<Utc>, Instant)>>,
+}
+
+impl MonotonicClock {
+	pub const fn new() -> Self {
+		Self { last: Mutex::new(None) }
+	}
+
+	/// The time to use for a system clock reading of `wall` taken at `instant`: `wall`, or the
+	/// previous result advanced by the monotonic time since, whichever is later
+	pub fn observe(&self, wall: DateTime<Utc>, instant: Instant) -> DateTime<Utc> {
+		let mut last = self.last.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		let time = match *last {
+			Some((last_time, last_instant)) => {
+				let elapsed =
+					chrono::Duration::from_std(instant.saturating_duration_since(last_instant))
+						.unwrap_or(chrono::Duration::MAX);
+				let continued = last_time.checked_add_signed(elapsed).unwrap_or(last_time);
+				wall.max(continued)
+			}
+			None => wall,
+		};
+		*last = Some((time, instant));
+		time
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::{
+		storage::{DatabaseStorage, RedbStorage},
+		types::EventRecord,
+		DatabaseConfig,
+	};
+	use chrono::Duration;
+	use std::path::PathBuf;
+
+	#[test]
+	fn test_backward_jumps_are_clamped_and_forward_jumps_kept() {
+		let clock = MonotonicClock::new();
+		let start = Instant::now();
+		let t = Utc::now();
+		assert_eq!(clock.observe(t, start), t);
+		// Clock set back two hours a minute later
+		let minute = std::time::Duration::from_secs(60);
+		assert_eq!(
+			clock.observe(t - Duration::hours(2), start + minute),
+			t + Duration::minutes(1)
+		);
+		assert_eq!(
+			clock.observe(
+				t - Duration::hours(2) + Duration::minutes(1),
+				start + 2 * minute
+			),
+			t + Duration::minutes(2)
+		);
+		// Corrected forward again
+		let corrected = t + Duration::minutes(3);
+		assert_eq!(clock.observe(corrected, start + 3 * minute), corrected);
+		let ahead = t + Duration::days(1);
+		assert_eq!(clock.observe(ahead, start + 4 * minute), ahead);
+	}
+
+	#[tokio::test]
+	async fn test_events_stored_during_a_backward_jump_are_not_pruned_after_correction() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let config = DatabaseConfig {
+			database_path: temp_dir.path().join("clock.redb"),
+			..DatabaseConfig::for_small_directories()
+		};
+		let mut storage = RedbStorage::new(config).await.unwrap();
+		let retention = Duration::hours(1);
+		let record = |path: &str, stamped: DateTime<Utc>| {
+			let mut record = EventRecord::new(
+				"Create".to_string(),
+				PathBuf::from(path),
+				false,
+				retention,
+				0,
+			);
+			record.timestamp = stamped;
+			record.expires_at = stamped + retention;
+			record
+		};
+
+		let clock = MonotonicClock::new();
+		let start = Instant::now();
+		let t = Utc::now();
+		clock.observe(t, start);
+		// A minute later the system clock reads two hours earlier; one event is stamped with
+		// the raw reading, one through the clock
+		let minute = std::time::Duration::from_secs(60);
+		let raw = t - Duration::hours(2) + Duration::minutes(1);
+		let clamped = clock.observe(raw, start + minute);
+		storage.store_event(&record("/watched/raw.txt", raw)).await.unwrap();
+		storage.store_event(&record("/watched/clamped.txt", clamped)).await.unwrap();
+
+		// Cleanup right after the clock is corrected, with the cutoff the adapter would use
+		let now = clock.observe(t + Duration::minutes(2), start + 2 * minute);
+		let before: SystemTime = (now - retention).into();
+		let removed = storage.cleanup_expired_events(before).await.unwrap();
+		assert_eq!(removed, 1, "only the raw-stamped event looks expired");
+		let remaining =
+			storage.find_events_by_time_range(t - Duration::days(1), now).await.unwrap();
+		let paths: Vec<_> = remaining.iter().map(|event| event.path.clone()).collect();
+		assert_eq!(paths, vec![PathBuf::from("/watched/clamped.txt")]);
+	}
+}
@@ -1,5 +1,9 @@
 //! Database configuration for different scale scenarios
 
+use super::storage::SerializationFormat;
+use crate::error::ErrorRecoveryConfig;
+use crate::events::EventType;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -21,14 +25,118 @@ pub struct DatabaseConfig {
 	/// How long to keep events in the database before cleanup
 	pub event_retention: Duration,
 
+	/// Per-type replacements for `event_retention` when `DatabaseAdapter::store_event` sets
+	/// `EventRecord::expires_at`, e.g. to keep moves and removes as an audit trail long after
+	/// writes are pruned. Types not listed use `event_retention`. Honored by every cleanup
+	/// that prunes by `expires_at`: `DatabaseAdapter::cleanup_old_events` and background
+	/// maintenance.
+	pub event_type_retention: HashMap<EventType, Duration>,
+
 	/// Batch size for database operations
 	pub write_batch_size: usize,
 
+	/// Buffer events in `DatabaseAdapter::store_event` and commit them in batches
+	///
+	/// A batch is committed once `write_batch_size` events are buffered, once the oldest one
+	/// has waited `flush_interval` (a timer task checks, so no further store is needed), on
+	/// `DatabaseAdapter::flush` and `close`, and when the last adapter handle is dropped.
+	/// Buffered events are not visible to queries and are lost if the process dies first.
+	/// Off by default: every event is committed before `store_event` returns.
+	pub batch_event_writes: bool,
+
 	/// Cache size for frequently accessed metadata
 	pub read_cache_size: usize,
 
 	/// Enable database compression (if supported)
 	pub enable_compression: bool,
+
+	/// Fragmentation ratio (see `DatabaseAdapter::fragmentation_ratio`) above which the
+	/// background compaction task compacts the file; below it the task only measures
+	pub compaction_fragmentation_threshold: f64,
+
+	/// Bytes the file must have free as well before the background task compacts it, so small
+	/// files, which read as fragmented because redb preallocates, are left alone
+	pub compaction_min_reclaimable_bytes: u64,
+
+	/// Record encoding for a newly created database. An existing file keeps the format it was
+	/// created with; see `storage::codec`.
+	pub serialization_format: SerializationFormat,
+
+	/// Retries of event, metadata and filesystem cache writes that fail transiently
+	///
+	/// Only errors `DatabaseError::is_retryable` accepts are retried; see `database::retry`.
+	/// A retried write stalls the event loop for the backoff, so keep the delays short;
+	/// `max_retries: 0` disables retrying.
+	pub write_retry: ErrorRecoveryConfig,
+
+	/// Adjust filesystem cache stats counters once per batch store instead of once per node
+	///
+	/// Counters come out the same either way; per-node updates only cost more during initial
+	/// scans and other bulk stores. See `RedbFilesystemCache::with_batched_stats`.
+	pub batch_cache_stats: bool,
+
+	/// How far each event, metadata and filesystem cache commit is persisted before it returns
+	///
+	/// See [`Durability`] for what each level can lose on a crash. Table setup, retention and
+	/// compaction always commit with [`Durability::Immediate`].
+	pub durability: Durability,
+
+	/// Also keep the latest event of every path in a compacted table, read through
+	/// `DatabaseAdapter::current_state`
+	///
+	/// Updated in the same transaction as each appended event, so it answers "what happened
+	/// to this path last" in one lookup. Retention and `delete_oldest_events` only prune the
+	/// full log; a path's entry stays until a newer event replaces it, and a Remove stays as
+	/// the path's last state. A move leaves a Remove as the state of its source. Costs one
+	/// extra write per event.
+	pub track_current_state: bool,
+
+	/// Stop writing events and metadata for a while once the database keeps failing or
+	/// stalling, so the event stream is not held up by it
+	///
+	/// See [`CircuitBreakerConfig`]. While the breaker is open, `DatabaseAdapter::store_event`
+	/// and `store_metadata` return `DatabaseError::CircuitOpen` without touching the storage
+	/// and the watcher delivers events unpersisted. `None` (the default) attempts every write.
+	pub circuit_breaker: Option<CircuitBreakerConfig>,
+}
+
+/// When [`DatabaseConfig::circuit_breaker`] stops and resumes persistence
+///
+/// `failure_threshold` consecutive failed writes open the breaker: writes are skipped for
+/// `open_duration`, then the next one goes through as a probe. A probe that succeeds closes the
+/// breaker again; one that fails keeps it open for another `open_duration`. A write counts
+/// once, after its `DatabaseConfig::write_retry` retries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitBreakerConfig {
+	/// Consecutive failed writes that open the breaker
+	pub failure_threshold: u32,
+	/// A write that takes longer than this counts as failed even when it succeeds, e.g. on a
+	/// disk that has become very slow; `None` judges writes by their result only
+	pub slow_write_threshold: Option<Duration>,
+	/// How long writes are skipped once the breaker opens, before the next one probes the
+	/// database
+	pub open_duration: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+	fn default() -> Self {
+		Self {
+			failure_threshold: 5,
+			slow_write_threshold: Some(Duration::from_secs(2)),
+			open_duration: Duration::from_secs(30),
+		}
+	}
+}
+
+/// Default `DatabaseConfig::write_retry`: up to three retries, 10ms apart and doubling
+pub fn default_write_retry() -> ErrorRecoveryConfig {
+	ErrorRecoveryConfig {
+		max_retries: 3,
+		initial_retry_delay: Duration::from_millis(10),
+		max_retry_delay: Duration::from_millis(200),
+		backoff_multiplier: 2.0,
+		exponential_backoff: true,
+	}
 }
 
 impl DatabaseConfig {
@@ -41,9 +149,19 @@ impl DatabaseConfig {
 			max_database_size: 100 * 1024 * 1024, // 100MB limit
 			flush_interval: Duration::from_secs(30),
 			event_retention: Duration::from_secs(300), // 5 minutes
+			event_type_retention: HashMap::new(),
 			write_batch_size: 100,
+			batch_event_writes: false,
 			read_cache_size: 1024,
 			enable_compression: false,
+			compaction_fragmentation_threshold: 0.5,
+			compaction_min_reclaimable_bytes: 64 * 1024 * 1024,
+			serialization_format: SerializationFormat::Bincode,
+			write_retry: default_write_retry(),
+			batch_cache_stats: true,
+			durability: Durability::Immediate,
+			track_current_state: false,
+			circuit_breaker: None,
 		}
 	}
 
@@ -55,9 +173,19 @@ impl DatabaseConfig {
 			max_database_size: 1024 * 1024 * 1024, // 1GB limit
 			flush_interval: Duration::from_secs(60),
 			event_retention: Duration::from_secs(600), // 10 minutes
+			event_type_retention: HashMap::new(),
 			write_batch_size: 1000,
+			batch_event_writes: false,
 			read_cache_size: 10_000,
 			enable_compression: true,
+			compaction_fragmentation_threshold: 0.5,
+			compaction_min_reclaimable_bytes: 64 * 1024 * 1024,
+			serialization_format: SerializationFormat::Bincode,
+			write_retry: default_write_retry(),
+			batch_cache_stats: true,
+			durability: Durability::Immediate,
+			track_current_state: false,
+			circuit_breaker: None,
 		}
 	}
 
@@ -69,9 +197,19 @@ impl DatabaseConfig {
 			max_database_size: 10 * 1024 * 1024 * 1024, // 10GB limit
 			flush_interval: Duration::from_secs(120),
 			event_retention: Duration::from_secs(1800), // 30 minutes
+			event_type_retention: HashMap::new(),
 			write_batch_size: 5000,
+			batch_event_writes: false,
 			read_cache_size: 50_000,
 			enable_compression: true,
+			compaction_fragmentation_threshold: 0.5,
+			compaction_min_reclaimable_bytes: 64 * 1024 * 1024,
+			serialization_format: SerializationFormat::Bincode,
+			write_retry: default_write_retry(),
+			batch_cache_stats: true,
+			durability: Durability::Immediate,
+			track_current_state: false,
+			circuit_breaker: None,
 		}
 	}
 
@@ -84,9 +222,76 @@ impl DatabaseConfig {
 			max_database_size: 0,                       // Unlimited
 			flush_interval: Duration::from_secs(300),   // 5 minutes
 			event_retention: Duration::from_secs(3600), // 1 hour
+			event_type_retention: HashMap::new(),
 			write_batch_size: 10_000,
+			batch_event_writes: false,
 			read_cache_size: 100_000,
 			enable_compression: true,
+			compaction_fragmentation_threshold: 0.5,
+			compaction_min_reclaimable_bytes: 64 * 1024 * 1024,
+			serialization_format: SerializationFormat::Bincode,
+			write_retry: default_write_retry(),
+			batch_cache_stats: true,
+			durability: Durability::Immediate,
+			track_current_state: false,
+			circuit_breaker: None,
+		}
+	}
+
+	/// Configuration derived from [`CustomParams`] instead of a fixed preset.
+	///
+	/// Formulas, with `nodes = expected_nodes` and `budget = memory_budget_bytes`:
+	/// - `memory_buffer_size = min(clamp(nodes / 10, 1_000, 100_000), budget / 2 / 512)`, at
+	///   least 100: half the budget goes to buffered events, at roughly 512 bytes each.
+	/// - `read_cache_size = min(max(nodes, 1_024), budget / 2 / 256)`, at least 100: the other
+	///   half caches metadata at roughly 256 bytes each; caching more entries than nodes is waste.
+	/// - `write_batch_size = memory_buffer_size / d`, at least 1, with `d` = 100 for
+	///   [`DurabilityPreference::Durable`], 10 for `Balanced` and 5 for `Throughput`.
+	/// - `flush_interval = base * (1 + floor(log10(max(nodes / 10_000, 1))))`, with `base` =
+	///   1s, 30s or 120s for the same three preferences.
+	/// - `event_retention = flush_interval * 10`.
+	///
+	/// Size estimates are rough averages, not measurements; treat the budget as a target.
+	/// `max_database_size` is left unlimited and compression is enabled from 10K nodes up, as in
+	/// the presets. The result always passes [`DatabaseConfig::validate`].
+	pub fn for_custom(params: CustomParams) -> Self {
+		const EVENT_BYTES: u64 = 512;
+		const METADATA_BYTES: u64 = 256;
+		let nodes = params.expected_nodes;
+		let half_budget = params.memory_budget_bytes / 2;
+
+		let memory_buffer_size =
+			(nodes / 10).clamp(1_000, 100_000).min(half_budget / EVENT_BYTES).max(100) as usize;
+		let read_cache_size = nodes.max(1_024).min(half_budget / METADATA_BYTES).max(100) as usize;
+
+		let (batch_divisor, base_flush) = match params.durability {
+			DurabilityPreference::Durable => (100, Duration::from_secs(1)),
+			DurabilityPreference::Balanced => (10, Duration::from_secs(30)),
+			DurabilityPreference::Throughput => (5, Duration::from_secs(120)),
+		};
+		let write_batch_size = (memory_buffer_size / batch_divisor).max(1);
+		let scale = (nodes / 10_000).max(1).ilog10() + 1;
+		let flush_interval = base_flush * scale;
+
+		Self {
+			database_path: params.database_path,
+			memory_buffer_size,
+			max_database_size: 0,
+			flush_interval,
+			event_retention: flush_interval * 10,
+			event_type_retention: HashMap::new(),
+			write_batch_size,
+			batch_event_writes: false,
+			read_cache_size,
+			enable_compression: nodes >= 10_000,
+			compaction_fragmentation_threshold: 0.5,
+			compaction_min_reclaimable_bytes: 64 * 1024 * 1024,
+			serialization_format: SerializationFormat::Bincode,
+			write_retry: default_write_retry(),
+			batch_cache_stats: true,
+			durability: Durability::Immediate,
+			track_current_state: false,
+			circuit_breaker: None,
 		}
 	}
 
@@ -119,10 +324,78 @@ impl DatabaseConfig {
 			return Err("Event retention must be greater than 0".to_string());
 		}
 
+		if self
+			.circuit_breaker
+			.as_ref()
+			.is_some_and(|breaker| breaker.failure_threshold == 0)
+		{
+			return Err("Circuit breaker failure threshold must be greater than 0".to_string());
+		}
+
+		if !(self.compaction_fragmentation_threshold > 0.0
+			&& self.compaction_fragmentation_threshold <= 1.0)
+		{
+			return Err("Compaction fragmentation threshold must be in (0, 1]".to_string());
+		}
+
 		Ok(())
 	}
 }
 
+/// redb commit durability used for [`DatabaseConfig::durability`]
+///
+/// redb never exposes a half-applied transaction: after a crash the file opens at the last
+/// commit that reached disk. The levels differ in which commit that is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+	/// Every commit is fsynced before it returns; nothing acknowledged is lost on a crash
+	#[default]
+	Immediate,
+	/// Commits are written and a write barrier queued but not awaited, so a crash or power
+	/// loss can drop the most recent commits (never reorder them). Only some platforms have a
+	/// cheaper barrier than fsync (macOS does); elsewhere this costs the same as `Immediate`.
+	Eventual,
+	/// Commits are not synced at all and become persistent only with the next `Immediate` or
+	/// `Eventual` commit; redb makes one when the last handle on the database is dropped. A crash
+	/// before then loses all of them. Pages freed by these commits are only reclaimed on a durable
+	/// one, so the file grows faster in between.
+	None,
+}
+
+impl Durability {
+	pub(crate) fn to_redb(self) -> redb::Durability {
+		match self {
+			Durability::Immediate => redb::Durability::Immediate,
+			Durability::Eventual => redb::Durability::Eventual,
+			Durability::None => redb::Durability::None,
+		}
+	}
+}
+
+/// How [`DatabaseConfig::for_custom`] trades write throughput against losing buffered events
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurabilityPreference {
+	/// Small batches and a short flush interval; little is lost on a crash
+	Durable,
+	/// Roughly what the presets do
+	#[default]
+	Balanced,
+	/// Large batches and a long flush interval
+	Throughput,
+}
+
+/// Inputs for [`DatabaseConfig::for_custom`]
+#[derive(Debug, Clone)]
+pub struct CustomParams {
+	/// Path where the database file will be stored
+	pub database_path: PathBuf,
+	/// Expected number of files and directories under watch
+	pub expected_nodes: u64,
+	/// Memory the event buffer and metadata cache may use together, in bytes
+	pub memory_budget_bytes: u64,
+	pub durability: DurabilityPreference,
+}
+
 impl Default for DatabaseConfig {
 	fn default() -> Self {
 		Self::for_moderate_directories()
@@ -168,6 +441,79 @@ mod tests {
 		assert!(config.validate().is_err());
 		config.write_batch_size = 2000; // Larger than memory buffer
 		assert!(config.validate().is_err());
+		config.write_batch_size = 100;
+
+		// Fragmentation threshold outside (0, 1]
+		for threshold in [0.0, -0.5, 1.5, f64::NAN] {
+			config.compaction_fragmentation_threshold = threshold;
+			assert!(config.validate().is_err(), "{threshold} accepted");
+		}
+	}
+
+	fn custom(nodes: u64, budget: u64, durability: DurabilityPreference) -> DatabaseConfig {
+		DatabaseConfig::for_custom(CustomParams {
+			database_path: PathBuf::from("/custom/database/path"),
+			expected_nodes: nodes,
+			memory_budget_bytes: budget,
+			durability,
+		})
+	}
+
+	#[test]
+	fn test_for_custom_scales_monotonically_and_validates() {
+		use DurabilityPreference::*;
+		let node_counts = [0, 1_000, 10_000, 100_000, 1_000_000, 100_000_000];
+		let budgets = [0, 1 << 20, 16 << 20, 256 << 20, 4 << 30];
+		let durabilities = [Durable, Balanced, Throughput];
+
+		for &durability in &durabilities {
+			for &budget in &budgets {
+				for &nodes in &node_counts {
+					let config = custom(nodes, budget, durability);
+					assert!(
+						config.validate().is_ok(),
+						"{nodes} nodes, {budget} bytes: {config:?}"
+					);
+				}
+				for pair in node_counts.windows(2) {
+					let (smaller, larger) = (
+						custom(pair[0], budget, durability),
+						custom(pair[1], budget, durability),
+					);
+					assert!(smaller.memory_buffer_size <= larger.memory_buffer_size);
+					assert!(smaller.read_cache_size <= larger.read_cache_size);
+					assert!(smaller.write_batch_size <= larger.write_batch_size);
+					assert!(smaller.flush_interval <= larger.flush_interval);
+					assert!(smaller.event_retention <= larger.event_retention);
+				}
+			}
+			for pair in budgets.windows(2) {
+				let (smaller, larger) = (
+					custom(1_000_000, pair[0], durability),
+					custom(1_000_000, pair[1], durability),
+				);
+				assert!(smaller.memory_buffer_size <= larger.memory_buffer_size);
+				assert!(smaller.read_cache_size <= larger.read_cache_size);
+				assert!(smaller.write_batch_size <= larger.write_batch_size);
+			}
+		}
+
+		// Trading durability for throughput never shrinks batches or shortens flushes
+		for pair in durabilities.windows(2) {
+			let (safer, faster) = (
+				custom(100_000, 256 << 20, pair[0]),
+				custom(100_000, 256 << 20, pair[1]),
+			);
+			assert!(safer.write_batch_size <= faster.write_batch_size);
+			assert!(safer.flush_interval <= faster.flush_interval);
+		}
+
+		// A generous budget reproduces the moderate preset's buffer for its node range
+		let moderate = custom(100_000, 1 << 30, Balanced);
+		assert_eq!(moderate.memory_buffer_size, 10_000);
+		assert_eq!(moderate.write_batch_size, 1_000);
+		assert_eq!(moderate.flush_interval, Duration::from_secs(60));
+		assert_eq!(moderate.event_retention, Duration::from_secs(600));
 	}
 
 	#[test]
@@ -60,6 +60,10 @@ pub enum DatabaseError {
 
 	#[error("Other error: {0}")]
 	Other(String),
+
+	/// The write was skipped because `DatabaseConfig::circuit_breaker` is open
+	#[error("Persistence suspended by the circuit breaker")]
+	CircuitOpen,
 }
 
 impl DatabaseError {
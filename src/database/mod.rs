@@ -6,14 +6,23 @@
 
 pub mod adapter;
 pub mod background_tasks;
+pub(crate) mod circuit_breaker;
+pub mod clock;
 pub mod config;
 pub mod error;
 pub mod path_utils;
+pub mod query;
+pub(crate) mod retry;
 pub mod storage;
 pub mod types;
 
 pub use adapter::DatabaseAdapter;
-pub use config::DatabaseConfig;
+pub use config::{
+	CircuitBreakerConfig, CustomParams, DatabaseConfig, Durability, DurabilityPreference,
+};
 pub use error::{DatabaseError, DatabaseResult};
-pub use storage::{DatabaseStorage, RedbStorage};
-pub use types::{EventRecord, MetadataRecord, StorageKey};
+pub use query::EventQuery;
+pub use storage::{
+	DatabaseStorage, ImportConflict, ImportReport, RedbStorage, SerializationFormat,
+};
+pub use types::{EventPage, EventRecord, ListingOrder, ListingSort, MetadataRecord, StorageKey};
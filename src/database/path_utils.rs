@@ -98,3 +98,40 @@ pub fn normalize_path(path: &Path) -> PathBuf {
 pub fn paths_equal(a: &Path, b: &Path) -> bool {
 	normalize_path(a) == normalize_path(b)
 }
+
+/// Normalize the separators of a path without touching the filesystem.
+///
+/// - On Windows, `/` becomes `\`.
+/// - Repeated separators collapse into one, except the leading pair of a UNC path on Windows.
+/// - Trailing separators are removed, unless they are the root (`/`, `C:\`).
+///
+/// Case, `.` and `..` are left alone, and so are verbatim (`\\?\`) paths, where `/` is an
+/// ordinary character, and paths that are not valid Unicode. Event paths and path hashes
+/// go through this, so `C:\a\b` and `C:/a/b/` are one path.
+pub fn normalize_separators(path: &Path) -> PathBuf {
+	let Some(text) = path.to_str() else {
+		return path.to_path_buf();
+	};
+	#[cfg(windows)]
+	let (text, separator) = match text.starts_with(r"\\?\") {
+		true => (text.to_string(), '\\'),
+		false => (text.replace('/', "\\"), '\\'),
+	};
+	#[cfg(not(windows))]
+	let (text, separator) = (text.to_string(), '/');
+
+	// A UNC path starts with a pair of separators
+	let lead = if cfg!(windows) && text.starts_with(r"\\") { 2 } else { 0 };
+	let mut normalized = String::with_capacity(text.len());
+	normalized.push_str(&text[..lead]);
+	for c in text[lead..].chars() {
+		if c == separator && normalized.len() > lead && normalized.ends_with(separator) {
+			continue;
+		}
+		normalized.push(c);
+	}
+	while normalized.ends_with(separator) && Path::new(&normalized).parent().is_some() {
+		normalized.pop();
+	}
+	PathBuf::from(normalized)
+}
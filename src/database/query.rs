@@ -0,0 +1,308 @@
+//! Typed queries over stored events
+//!
+//! An [`EventQuery`] combines predicates on path, time, event type and move confidence, and
+//! `DatabaseAdapter::query` runs it. The storage answers it from the most selective index the
+//! predicates allow, in this order: the exact path's event log, the path-prefix index, the
+//! hourly time buckets, and only when none applies a scan of every event. Every predicate is
+//! still checked against every candidate, so the index chosen affects cost, never results.
+//!
+//! Results are in append order (ascending `sequence_number`); `limit` keeps the oldest.
+
+use crate::database::types::{EventRecord, StorageKey};
+use crate::events::EventType;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+
+/// Width of a time-index bucket, in seconds; must match what `event_storage` writes
+pub const TIME_BUCKET_SECONDS: i64 = 3600;
+
+/// Components of an event's path that key its entry in the path-prefix index.
+///
+/// Prefixes with fewer components cannot be looked up and fall back to another plan.
+pub const PREFIX_INDEX_DEPTH: usize = 4;
+
+/// Most time buckets a range is looked up bucket by bucket; wider ranges are scanned
+pub const MAX_TIME_BUCKETS: i64 = 24 * 366;
+
+/// Predicates on stored events, built up with chained calls
+///
+/// ```ignore
+/// let query = EventQuery::new()
+///     .under_prefix("/srv/projects/site")
+///     .event_type(EventType::Move)
+///     .min_confidence(0.8)
+///     .limit(100);
+/// let events = adapter.query(query).await?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EventQuery {
+	path: Option<PathBuf>,
+	prefix: Option<PathBuf>,
+	time_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+	event_type: Option<String>,
+	min_confidence: Option<f32>,
+	limit: Option<usize>,
+}
+
+impl EventQuery {
+	/// A query matching every event
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Only events for exactly `path`, as it was stored
+	pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+		self.path = Some(path.into());
+		self
+	}
+
+	/// Only events for `prefix` or paths below it, compared component by component
+	pub fn under_prefix(mut self, prefix: impl Into<PathBuf>) -> Self {
+		self.prefix = Some(prefix.into());
+		self
+	}
+
+	/// Only events stamped between `start` and `end`, both inclusive
+	pub fn time_range(mut self, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+		self.time_range = Some((start, end));
+		self
+	}
+
+	/// Only events of `event_type`
+	pub fn event_type(mut self, event_type: EventType) -> Self {
+		self.event_type = Some(format!("{event_type:?}"));
+		self
+	}
+
+	/// Only events with a recorded move confidence of at least `confidence`; events without
+	/// one do not match
+	pub fn min_confidence(mut self, confidence: f32) -> Self {
+		self.min_confidence = Some(confidence);
+		self
+	}
+
+	/// At most `limit` events, the oldest first
+	pub fn limit(mut self, limit: usize) -> Self {
+		self.limit = Some(limit);
+		self
+	}
+
+	/// Whether `record` satisfies every predicate
+	pub fn matches(&self, record: &EventRecord) -> bool {
+		self.path.as_ref().is_none_or(|path| record.path == *path)
+			&& self.prefix.as_ref().is_none_or(|prefix| record.path.starts_with(prefix))
+			&& self
+				.time_range
+				.is_none_or(|(start, end)| record.timestamp >= start && record.timestamp <= end)
+			&& self
+				.event_type
+				.as_ref()
+				.is_none_or(|event_type| record.event_type == *event_type)
+			&& self
+				.min_confidence
+				.is_none_or(|min| record.confidence.is_some_and(|c| c >= min))
+	}
+
+	/// Keep the matching `candidates`, in append order and up to `limit`
+	pub(crate) fn finish(
+		&self, candidates: impl IntoIterator<Item = EventRecord>,
+	) -> Vec<EventRecord> {
+		let mut events: Vec<_> =
+			candidates.into_iter().filter(|record| self.matches(record)).collect();
+		events.sort_by_key(|record| record.sequence_number);
+		if let Some(limit) = self.limit {
+			events.truncate(limit);
+		}
+		events
+	}
+
+	/// The time range to fetch candidates for, everything when the query has none
+	pub(crate) fn time_bounds(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+		self.time_range.unwrap_or((DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC))
+	}
+
+	/// Which index answers this query; `prefix_indexed` is whether every stored event is in the
+	/// path-prefix index
+	pub(crate) fn plan(&self, prefix_indexed: bool) -> QueryPlan {
+		if let Some(path) = &self.path {
+			return QueryPlan::PathHash(StorageKey::path_hash(path));
+		}
+		if let Some(key) = self.prefix.as_deref().and_then(prefix_index_key) {
+			if prefix_indexed {
+				return QueryPlan::PathPrefix(key);
+			}
+		}
+		if let Some((start, end)) = self.time_range {
+			let first = StorageKey::time_bucket(start, TIME_BUCKET_SECONDS);
+			let last = StorageKey::time_bucket(end, TIME_BUCKET_SECONDS);
+			if let (StorageKey::TimeBucket(first), StorageKey::TimeBucket(last)) = (first, last) {
+				if first > last {
+					return QueryPlan::Empty;
+				}
+				if (last - first) / TIME_BUCKET_SECONDS < MAX_TIME_BUCKETS {
+					let buckets = (first..=last)
+						.step_by(TIME_BUCKET_SECONDS as usize)
+						.map(StorageKey::TimeBucket)
+						.collect();
+					return QueryPlan::TimeBuckets(buckets);
+				}
+			}
+		}
+		QueryPlan::FullScan
+	}
+}
+
+/// Path-prefix index key of `path`: its first [`PREFIX_INDEX_DEPTH`] components, `None` when
+/// it has fewer
+pub(crate) fn prefix_index_key(path: &Path) -> Option<StorageKey> {
+	(path.components().count() >= PREFIX_INDEX_DEPTH)
+		.then(|| StorageKey::path_prefix(path, PREFIX_INDEX_DEPTH))
+}
+
+/// How a query's candidates are fetched
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum QueryPlan {
+	/// The events log entries of one path
+	PathHash(StorageKey),
+	/// One bucket of the path-prefix index
+	PathPrefix(StorageKey),
+	/// The time-index buckets covering the range, oldest first
+	TimeBuckets(Vec<StorageKey>),
+	/// Nothing can match
+	Empty,
+	/// Every event
+	FullScan,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::{DatabaseAdapter, DatabaseConfig};
+	use crate::events::{FileSystemEvent, MoveDetectionMethod, MoveEvent};
+
+	#[test]
+	fn test_each_predicate_picks_the_most_selective_index() {
+		let path = PathBuf::from("/srv/projects/site/index.html");
+		let prefix = PathBuf::from("/srv/projects/site");
+		let now = Utc::now();
+		let hour_ago = now - chrono::Duration::hours(1);
+
+		let by_path = EventQuery::new().path(&path).under_prefix(&prefix).time_range(hour_ago, now);
+		assert_eq!(
+			by_path.plan(true),
+			QueryPlan::PathHash(StorageKey::path_hash(&path))
+		);
+
+		let by_prefix = EventQuery::new().under_prefix(&prefix).time_range(hour_ago, now);
+		assert!(matches!(by_prefix.plan(true), QueryPlan::PathPrefix(_)));
+		// Not every event is in the prefix index yet, or the prefix is too short for it
+		assert!(matches!(by_prefix.plan(false), QueryPlan::TimeBuckets(_)));
+		let shallow = EventQuery::new().under_prefix("/srv").time_range(hour_ago, now);
+		assert!(matches!(shallow.plan(true), QueryPlan::TimeBuckets(_)));
+
+		match EventQuery::new().time_range(hour_ago, now).plan(true) {
+			QueryPlan::TimeBuckets(buckets) => assert!((1..=2).contains(&buckets.len())),
+			plan => panic!("expected time buckets, got {plan:?}"),
+		}
+		assert_eq!(
+			EventQuery::new().time_range(now, hour_ago).plan(true),
+			QueryPlan::Empty
+		);
+		let decade = EventQuery::new().time_range(now - chrono::Duration::days(3650), now);
+		assert_eq!(decade.plan(true), QueryPlan::FullScan);
+
+		let unindexed = EventQuery::new().event_type(EventType::Move).min_confidence(0.5).limit(3);
+		assert_eq!(unindexed.plan(true), QueryPlan::FullScan);
+	}
+
+	#[tokio::test]
+	async fn test_query_predicates_and_combined_query() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let adapter = DatabaseAdapter::new(DatabaseConfig {
+			database_path: temp_dir.path().join("query.redb"),
+			..DatabaseConfig::for_small_directories()
+		})
+		.await
+		.unwrap();
+		let site = PathBuf::from("/srv/projects/site");
+		let other = PathBuf::from("/srv/projects/other");
+		let event = |event_type: EventType, path: PathBuf| {
+			FileSystemEvent::new(event_type, path, false, Some(10))
+		};
+		let moved = |confidence: f32, destination: PathBuf| {
+			event(EventType::Create, destination.clone()).with_move_data(MoveEvent::new(
+				other.join("old.txt"),
+				destination,
+				confidence,
+				MoveDetectionMethod::Inode,
+			))
+		};
+
+		adapter
+			.store_event(&event(EventType::Create, site.join("a.txt")))
+			.await
+			.unwrap();
+		adapter.store_event(&event(EventType::Write, site.join("a.txt"))).await.unwrap();
+		adapter
+			.store_event(&event(EventType::Create, other.join("b.txt")))
+			.await
+			.unwrap();
+		adapter.store_event(&moved(0.95, site.join("c.txt"))).await.unwrap();
+		adapter.store_event(&moved(0.5, site.join("d.txt"))).await.unwrap();
+		adapter
+			.store_event(&event(EventType::Remove, site.join("sub/e.txt")))
+			.await
+			.unwrap();
+
+		let paths = |events: Vec<EventRecord>| -> Vec<PathBuf> {
+			events.into_iter().map(|event| event.path).collect()
+		};
+		let query = |query: EventQuery| adapter.query(query);
+
+		let by_path = query(EventQuery::new().path(site.join("a.txt"))).await.unwrap();
+		assert_eq!(by_path.len(), 2);
+		assert!(by_path[0].sequence_number < by_path[1].sequence_number);
+
+		let database = adapter.get_raw_database().await.unwrap();
+		let plan = |query: &EventQuery| {
+			crate::database::storage::event_storage::plan(&database, query).unwrap()
+		};
+		assert!(matches!(
+			plan(&EventQuery::new().under_prefix(&site)),
+			QueryPlan::PathPrefix(_)
+		));
+		let under_site = paths(query(EventQuery::new().under_prefix(&site)).await.unwrap());
+		assert_eq!(under_site.len(), 5);
+		assert!(!under_site.contains(&other.join("b.txt")));
+		assert!(under_site.contains(&site.join("sub/e.txt")));
+
+		let now = Utc::now();
+		let recent = query(EventQuery::new().time_range(now - chrono::Duration::minutes(5), now));
+		assert_eq!(recent.await.unwrap().len(), 6);
+		let future = now + chrono::Duration::hours(2);
+		let later =
+			query(EventQuery::new().time_range(future, future + chrono::Duration::hours(1)));
+		assert!(later.await.unwrap().is_empty());
+
+		let removes = query(EventQuery::new().event_type(EventType::Remove)).await.unwrap();
+		assert_eq!(paths(removes), vec![site.join("sub/e.txt")]);
+
+		let confident = query(EventQuery::new().min_confidence(0.9)).await.unwrap();
+		assert_eq!(paths(confident), vec![site.join("c.txt")]);
+
+		let first_two = paths(query(EventQuery::new().limit(2)).await.unwrap());
+		assert_eq!(first_two, vec![site.join("a.txt"), site.join("a.txt")]);
+
+		let combined = EventQuery::new()
+			.under_prefix(&site)
+			.time_range(now - chrono::Duration::minutes(5), now)
+			.event_type(EventType::Move)
+			.min_confidence(0.4)
+			.limit(1);
+		assert!(matches!(plan(&combined), QueryPlan::PathPrefix(_)));
+		assert_eq!(
+			paths(query(combined).await.unwrap()),
+			vec![site.join("c.txt")]
+		);
+	}
+}
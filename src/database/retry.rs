@@ -0,0 +1,35 @@
+//! Retrying storage writes that fail transiently
+//!
+//! A write that fails with an error `DatabaseError::is_retryable` accepts (a timeout, a failed
+//! transaction, a lost connection) is retried with the backoff of `DatabaseConfig::write_retry`;
+//! anything else, such as corruption or a full database, is returned right away. redb itself
+//! serializes writers and blocks instead of failing, so with the built-in storage this mostly
+//! matters for custom `DatabaseStorage` backends.
+
+use crate::database::error::{DatabaseError, DatabaseResult};
+use crate::error::{ErrorRecoveryConfig, WatcherError};
+use crate::retry::RetryManager;
+use std::future::Future;
+
+/// Run `write`, retrying it per `config` while it fails with a retryable error.
+///
+/// When the retries run out the error names the operation and the attempts, with the last
+/// failure as a `DatabaseError::StorageError`.
+pub(crate) async fn retry_write<T, F, Fut>(
+	config: &ErrorRecoveryConfig, operation: &str, mut write: F,
+) -> DatabaseResult<T>
+where
+	F: FnMut() -> Fut + Send,
+	Fut: Future<Output = DatabaseResult<T>> + Send,
+{
+	RetryManager::new(config.clone())
+		.execute_simple(operation, || {
+			let attempt = write();
+			async move { attempt.await.map_err(WatcherError::from) }
+		})
+		.await
+		.map_err(|error| match error {
+			WatcherError::Database(error) => *error,
+			other => DatabaseError::StorageError(other.to_string()),
+		})
+}
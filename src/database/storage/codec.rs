@@ -0,0 +1,372 @@
+//! Encoding of event and metadata records
+//!
+//! bincode is compact and fast but not self-describing: a record decodes only into exactly the
+//! struct layout that wrote it, so adding a field to `EventRecord` or `MetadataRecord` makes
+//! every existing database unreadable. [`SerializationFormat::Cbor`] stores field names with
+//! each record; added fields marked `#[serde(default)]` decode from old records and unknown
+//! fields are ignored, at the price of larger records and slower encoding.
+//!
+//! The format is a property of the database file, not of the process: it is written to the stats
+//! table next to the schema version when the database is first initialized, and every read and
+//! write looks it up there. A file that predates this key holds bincode records.
+//!
+//! Limitations:
+//! - Only the event log, the time index and the metadata table use the recorded format. The
+//!   filesystem cache and multi-watch tables stay on bincode; their keys are serialized structs
+//!   compared byte for byte, which needs a canonical encoding.
+//! - The format cannot be changed for an existing file. Opening it with a different
+//!   `DatabaseConfig::serialization_format` logs a warning and keeps the recorded one.
+
+use super::tables::{
+	upgrade_cache_nodes, EVENTS_LOG_TABLE, METADATA_TABLE, SCHEMA_VERSION, SCHEMA_VERSION_KEY,
+	SERIALIZATION_FORMAT_KEY, STATS_TABLE,
+};
+use crate::database::error::{DatabaseError, DatabaseResult};
+use redb::{Database, ReadTransaction, ReadableTable, ReadableTableMetadata, TableError};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{info, warn};
+
+/// Encoder/decoder for stored records
+pub trait RecordCodec {
+	fn encode<T: Serialize>(&self, value: &T) -> DatabaseResult<Vec<u8>>;
+	fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> DatabaseResult<T>;
+}
+
+/// bincode 1.x with its default options
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl RecordCodec for BincodeCodec {
+	fn encode<T: Serialize>(&self, value: &T) -> DatabaseResult<Vec<u8>> {
+		bincode::serialize(value).map_err(|e| DatabaseError::Serialization(e.to_string()))
+	}
+
+	fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> DatabaseResult<T> {
+		bincode::deserialize(bytes).map_err(|e| DatabaseError::Deserialization(e.to_string()))
+	}
+}
+
+/// CBOR (RFC 8949) through `ciborium`; structs are encoded as maps keyed by field name
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CborCodec;
+
+impl RecordCodec for CborCodec {
+	fn encode<T: Serialize>(&self, value: &T) -> DatabaseResult<Vec<u8>> {
+		let mut bytes = Vec::new();
+		ciborium::into_writer(value, &mut bytes)
+			.map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+		Ok(bytes)
+	}
+
+	fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> DatabaseResult<T> {
+		ciborium::from_reader(bytes).map_err(|e| DatabaseError::Deserialization(e.to_string()))
+	}
+}
+
+/// Which codec a database stores its records with, see the module docs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SerializationFormat {
+	/// Compact, but any change to a record struct breaks existing files
+	#[default]
+	Bincode,
+	/// Self-describing; tolerates added optional fields and removed fields
+	Cbor,
+}
+
+impl SerializationFormat {
+	/// Identifier stored in the database; never reuse a value
+	fn id(self) -> u8 {
+		match self {
+			Self::Bincode => 0,
+			Self::Cbor => 1,
+		}
+	}
+
+	fn from_id(id: u8) -> Option<Self> {
+		match id {
+			0 => Some(Self::Bincode),
+			1 => Some(Self::Cbor),
+			_ => None,
+		}
+	}
+
+	fn from_stored(bytes: Option<&[u8]>) -> DatabaseResult<Self> {
+		match bytes {
+			None => Ok(Self::Bincode),
+			Some([id]) => Self::from_id(*id).ok_or_else(|| {
+				DatabaseError::CorruptionError(format!("unknown serialization format id {id}"))
+			}),
+			Some(other) => Err(DatabaseError::CorruptionError(format!(
+				"malformed serialization format entry ({} bytes)",
+				other.len()
+			))),
+		}
+	}
+}
+
+impl RecordCodec for SerializationFormat {
+	fn encode<T: Serialize>(&self, value: &T) -> DatabaseResult<Vec<u8>> {
+		match self {
+			Self::Bincode => BincodeCodec.encode(value),
+			Self::Cbor => CborCodec.encode(value),
+		}
+	}
+
+	fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> DatabaseResult<T> {
+		match self {
+			Self::Bincode => BincodeCodec.decode(bytes),
+			Self::Cbor => CborCodec.decode(bytes),
+		}
+	}
+}
+
+/// Format recorded in an open stats table
+pub(crate) fn stored_format(
+	stats_table: &impl ReadableTable<&'static [u8], &'static [u8]>,
+) -> DatabaseResult<SerializationFormat> {
+	let stored = stats_table.get(SERIALIZATION_FORMAT_KEY)?;
+	SerializationFormat::from_stored(stored.as_ref().map(|v| v.value()))
+}
+
+/// Format of the database a read transaction belongs to
+pub(crate) fn read_format(read_txn: &ReadTransaction) -> DatabaseResult<SerializationFormat> {
+	match read_txn.open_table(STATS_TABLE) {
+		Ok(stats_table) => stored_format(&stats_table),
+		Err(TableError::TableDoesNotExist(_)) => Ok(SerializationFormat::Bincode),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Format of the database a write transaction belongs to
+pub(crate) fn write_format(
+	write_txn: &redb::WriteTransaction,
+) -> DatabaseResult<SerializationFormat> {
+	stored_format(&write_txn.open_table(STATS_TABLE)?)
+}
+
+/// Record the schema version and, for a database without records yet, the `requested` format.
+///
+/// Files recording an older version (or none) have their cache nodes upgraded first; a newer
+/// version is refused rather than stamped over. Returns the format the database actually uses.
+/// A database that already holds records but has no format entry was written before the entry
+/// existed and is recorded as bincode.
+pub(crate) fn record_format(
+	database: &Database, requested: SerializationFormat,
+) -> DatabaseResult<SerializationFormat> {
+	let write_txn = database.begin_write()?;
+	let format = {
+		let is_empty = write_txn.open_multimap_table(EVENTS_LOG_TABLE)?.is_empty()?
+			&& write_txn.open_table(METADATA_TABLE)?.is_empty()?;
+		let mut stats_table = write_txn.open_table(STATS_TABLE)?;
+		let stored = stats_table.get(SERIALIZATION_FORMAT_KEY)?.map(|v| v.value().to_vec());
+		let format = match stored {
+			Some(bytes) => SerializationFormat::from_stored(Some(&bytes))?,
+			None if is_empty => requested,
+			None => SerializationFormat::Bincode,
+		};
+		if format != requested {
+			warn!(
+				"Database records are stored as {:?}; ignoring configured {:?}",
+				format, requested
+			);
+		}
+		let version = stats_table
+			.get(SCHEMA_VERSION_KEY)?
+			.and_then(|v| <[u8; 4]>::try_from(v.value()).ok())
+			.map(u32::from_le_bytes);
+		if version.is_some_and(|version| version > SCHEMA_VERSION) {
+			return Err(DatabaseError::InitializationFailed(format!(
+				"database schema version {} is newer than the supported {}",
+				version.unwrap_or_default(),
+				SCHEMA_VERSION
+			)));
+		}
+		if version.is_none_or(|version| version < SCHEMA_VERSION) {
+			let upgraded = upgrade_cache_nodes(&write_txn)?;
+			if upgraded > 0 {
+				info!(
+					"Upgraded {} filesystem cache nodes to schema version {}",
+					upgraded, SCHEMA_VERSION
+				);
+			}
+		}
+		stats_table.insert(SERIALIZATION_FORMAT_KEY, &[format.id()][..])?;
+		stats_table.insert(SCHEMA_VERSION_KEY, &SCHEMA_VERSION.to_le_bytes()[..])?;
+		format
+	};
+	write_txn.commit()?;
+	Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::types::{EventRecord, MetadataRecord};
+	use serde::Deserialize;
+	use std::path::PathBuf;
+
+	const FORMATS: [SerializationFormat; 2] =
+		[SerializationFormat::Bincode, SerializationFormat::Cbor];
+
+	#[test]
+	fn test_records_round_trip_in_every_format() {
+		let mut event = EventRecord::new(
+			"Create".to_string(),
+			PathBuf::from("/watched/file.txt"),
+			false,
+			chrono::Duration::minutes(5),
+			7,
+		);
+		event.size = Some(42);
+		event.confidence = Some(0.75);
+		let mut metadata = MetadataRecord::new(PathBuf::from("/watched/dir"), true);
+		metadata.inode = Some(1234);
+
+		for format in FORMATS {
+			let decoded: EventRecord = format.decode(&format.encode(&event).unwrap()).unwrap();
+			assert_eq!(decoded.event_id, event.event_id, "{format:?}");
+			assert_eq!(decoded.sequence_number, 7);
+			assert_eq!(decoded.path, event.path);
+			assert_eq!(decoded.timestamp, event.timestamp);
+			assert_eq!(decoded.size, Some(42));
+			assert_eq!(decoded.confidence, Some(0.75));
+			assert_eq!(decoded.expires_at, event.expires_at);
+
+			let decoded: MetadataRecord =
+				format.decode(&format.encode(&metadata).unwrap()).unwrap();
+			assert_eq!(decoded.path, metadata.path, "{format:?}");
+			assert_eq!(decoded.inode, Some(1234));
+			assert_eq!(decoded.cached_at, metadata.cached_at);
+			assert!(decoded.is_directory);
+		}
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct RecordV1 {
+		path: PathBuf,
+		size: Option<u64>,
+	}
+
+	#[derive(Debug, Serialize, Deserialize, PartialEq)]
+	struct RecordV2 {
+		path: PathBuf,
+		size: Option<u64>,
+		#[serde(default)]
+		owner: Option<u32>,
+	}
+
+	#[test]
+	fn test_cbor_tolerates_added_optional_field() {
+		let v1 = RecordV1 { path: PathBuf::from("/a"), size: Some(3) };
+		let bytes = CborCodec.encode(&v1).unwrap();
+		let upgraded: RecordV2 = CborCodec.decode(&bytes).unwrap();
+		assert_eq!(
+			upgraded,
+			RecordV2 { path: PathBuf::from("/a"), size: Some(3), owner: None }
+		);
+
+		// Older readers skip the field they do not know
+		let v2 = RecordV2 { path: PathBuf::from("/b"), size: None, owner: Some(1000) };
+		let downgraded: RecordV1 = CborCodec.decode(&CborCodec.encode(&v2).unwrap()).unwrap();
+		assert_eq!(
+			downgraded,
+			RecordV1 { path: PathBuf::from("/b"), size: None }
+		);
+
+		// The reason the option exists
+		let bytes = BincodeCodec.encode(&v1).unwrap();
+		assert!(BincodeCodec.decode::<RecordV2>(&bytes).is_err());
+	}
+
+	#[tokio::test]
+	async fn test_format_is_recorded_once_and_kept() {
+		let dir = tempfile::TempDir::new().unwrap();
+		let database =
+			std::sync::Arc::new(Database::create(dir.path().join("codec.redb")).unwrap());
+		super::super::tables::initialize_tables(&database).await.unwrap();
+
+		let format = record_format(&database, SerializationFormat::Cbor).unwrap();
+		assert_eq!(format, SerializationFormat::Cbor);
+		// Reopening with a different configuration keeps what the file was written with
+		let format = record_format(&database, SerializationFormat::Bincode).unwrap();
+		assert_eq!(format, SerializationFormat::Cbor);
+		assert_eq!(
+			read_format(&database.begin_read().unwrap()).unwrap(),
+			SerializationFormat::Cbor
+		);
+	}
+
+	#[tokio::test]
+	async fn test_version_1_cache_nodes_are_upgraded_on_open() {
+		use crate::database::storage::tables::MULTI_WATCH_FS_CACHE;
+		use crate::database::types::{CacheInfo, ComputedProperties, FilesystemNode, NodeType};
+		use std::time::SystemTime;
+
+		#[derive(Serialize)]
+		struct NodeMetadataV1 {
+			modified_time: SystemTime,
+			created_time: Option<SystemTime>,
+			accessed_time: Option<SystemTime>,
+			permissions: u32,
+			inode: Option<u64>,
+			windows_id: Option<u64>,
+		}
+
+		#[derive(Serialize)]
+		struct FilesystemNodeV1 {
+			path: PathBuf,
+			node_type: NodeType,
+			metadata: NodeMetadataV1,
+			cache_info: CacheInfo,
+			computed: ComputedProperties,
+			last_event_type: Option<String>,
+		}
+
+		let dir = tempfile::TempDir::new().unwrap();
+		let database =
+			std::sync::Arc::new(Database::create(dir.path().join("codec.redb")).unwrap());
+		super::super::tables::initialize_tables(&database).await.unwrap();
+		let v1 = FilesystemNodeV1 {
+			path: PathBuf::from("/watched/old.txt"),
+			node_type: NodeType::File { size: 5, content_hash: None, mime_type: None },
+			metadata: NodeMetadataV1 {
+				modified_time: SystemTime::UNIX_EPOCH,
+				created_time: None,
+				accessed_time: None,
+				permissions: 0o644,
+				inode: Some(42),
+				windows_id: None,
+			},
+			cache_info: CacheInfo::default(),
+			computed: ComputedProperties::default(),
+			last_event_type: Some("Create".to_string()),
+		};
+		let write_txn = database.begin_write().unwrap();
+		write_txn
+			.open_table(MULTI_WATCH_FS_CACHE)
+			.unwrap()
+			.insert(&b"node"[..], &bincode::serialize(&v1).unwrap()[..])
+			.unwrap();
+		write_txn.commit().unwrap();
+
+		record_format(&database, SerializationFormat::Bincode).unwrap();
+		let read_txn = database.begin_read().unwrap();
+		let table = read_txn.open_table(MULTI_WATCH_FS_CACHE).unwrap();
+		let bytes = table.get(&b"node"[..]).unwrap().unwrap();
+		let node: FilesystemNode = bincode::deserialize(bytes.value()).unwrap();
+		assert_eq!(node.path, PathBuf::from("/watched/old.txt"));
+		assert_eq!(node.metadata.inode, Some(42));
+		assert_eq!(node.metadata.uid, None);
+		drop(read_txn);
+
+		// A file from a newer version is not stamped over
+		let write_txn = database.begin_write().unwrap();
+		write_txn
+			.open_table(STATS_TABLE)
+			.unwrap()
+			.insert(SCHEMA_VERSION_KEY, &(SCHEMA_VERSION + 1).to_le_bytes()[..])
+			.unwrap();
+		write_txn.commit().unwrap();
+		assert!(record_format(&database, SerializationFormat::Bincode).is_err());
+	}
+}
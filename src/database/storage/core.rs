@@ -3,13 +3,15 @@
 //! This module defines the main storage traits and provides the primary
 //! RedbStorage implementation that coordinates all storage operations.
 
+use super::codec::RecordCodec;
 use super::filesystem_cache::trait_def::FilesystemCacheStorage;
 use super::filesystem_cache::RedbFilesystemCache;
 use crate::database::types::FilesystemNode;
 use crate::database::{
 	config::DatabaseConfig,
 	error::DatabaseResult,
-	types::{DatabaseStats, EventRecord, MetadataRecord, StorageKey},
+	query::{EventQuery, QueryPlan},
+	types::{DatabaseStats, EventPage, EventRecord, MetadataRecord, StorageKey},
 };
 use chrono::{DateTime, Utc};
 use redb::{Database, ReadableMultimapTable, ReadableTable};
@@ -29,15 +31,48 @@ pub trait DatabaseStorage: Send + Sync {
 	/// Store an event record
 	async fn store_event(&mut self, record: &EventRecord) -> DatabaseResult<()>;
 
+	/// Store several event records, in order; all or none when the backend has transactions
+	async fn store_events(&mut self, records: &[EventRecord]) -> DatabaseResult<()> {
+		for record in records {
+			self.store_event(record).await?;
+		}
+		Ok(())
+	}
+
 	/// Retrieve events by key
 	async fn get_events(&mut self, key: &StorageKey) -> DatabaseResult<Vec<EventRecord>>;
 
+	/// Up to `limit` events for `key` with a `sequence_number` above `after` (all when `None`),
+	/// in ascending order
+	async fn get_events_page(
+		&mut self, key: &StorageKey, after: Option<u64>, limit: usize,
+	) -> DatabaseResult<EventPage> {
+		let events = self.get_events(key).await?;
+		Ok(EventPage::from_sorted(events.into_iter(), after, limit))
+	}
+
 	/// Store metadata record
 	async fn store_metadata(&mut self, record: &MetadataRecord) -> DatabaseResult<()>;
 
 	/// Retrieve metadata by path
 	async fn get_metadata(&mut self, path: &Path) -> DatabaseResult<Option<MetadataRecord>>;
 
+	/// Events matching `query`, in append order.
+	///
+	/// The default fetches candidates through `get_events` or `find_events_by_time_range` and
+	/// filters them; backends with better indexes override it.
+	async fn query_events(&mut self, query: &EventQuery) -> DatabaseResult<Vec<EventRecord>> {
+		let candidates = match query.plan(false) {
+			QueryPlan::PathHash(key) => self.get_events(&key).await?,
+			QueryPlan::Empty => Vec::new(),
+			_ => {
+				let (start, end) = query.time_bounds();
+				self.find_events_by_time_range(start, end).await?
+			}
+		};
+		Ok(query.finish(candidates))
+	}
+
 	/// Find events by time range
 	async fn find_events_by_time_range(
 		&mut self, start: DateTime<Utc>, end: DateTime<Utc>,
@@ -57,6 +92,15 @@ pub trait DatabaseStorage: Send + Sync {
 	/// Compact database
 	async fn compact(&mut self) -> DatabaseResult<()>;
 
+	/// Share of the storage file not holding live data, 0.0 to 1.0; 0.0 if not applicable
+	async fn fragmentation_ratio(&self) -> DatabaseResult<f64>;
+
+	/// The last event stored for `path`, kept with `DatabaseConfig::track_current_state`;
+	/// `None` from backends without such a table
+	async fn get_current_state(&mut self, _path: &Path) -> DatabaseResult<Option<EventRecord>> {
+		Ok(None)
+	}
+
 	/// Close the database
 	async fn close(self) -> DatabaseResult<()>;
 
@@ -92,6 +136,18 @@ pub trait DatabaseStorage: Send + Sync {
 		&mut self, cutoff: std::time::SystemTime,
 	) -> DatabaseResult<usize>;
 
+	/// Like [`Self::delete_events_older_than`], with the cutoff of an event whose
+	/// `EventRecord::event_type` is in `type_cutoffs` taken from there instead
+	///
+	/// The default ignores `type_cutoffs` and deletes by `cutoff` alone, for backends that
+	/// cannot tell event types apart cheaply.
+	async fn delete_events_older_than_by_type(
+		&mut self, cutoff: std::time::SystemTime,
+		_type_cutoffs: &std::collections::HashMap<String, std::time::SystemTime>,
+	) -> DatabaseResult<usize> {
+		self.delete_events_older_than(cutoff).await
+	}
+
 	/// Count total number of events in the log
 	async fn count_events(&self) -> DatabaseResult<usize>;
 
@@ -138,13 +194,27 @@ impl RedbStorage {
 		&self.config
 	}
 
+	/// Bytes compaction could return to the OS; see `maintenance::reclaimable_bytes`
+	pub fn reclaimable_bytes(&self) -> DatabaseResult<u64> {
+		super::maintenance::reclaimable_bytes(&self.database, &self.config.database_path)
+	}
+
+	/// Whether nothing else holds the database, which compaction needs; see
+	/// `maintenance::compact_database`
+	pub(crate) fn has_exclusive_access(&self) -> bool {
+		Arc::strong_count(&self.database) == 1
+	}
+
 	/// Get a clone of the underlying Arc<Database> (for test/maintenance only)
 	pub fn get_database(&self) -> Arc<Database> {
 		self.database.clone()
 	}
 
-	fn cache(&self) -> RedbFilesystemCache {
+	/// Filesystem cache over this database, configured from `DatabaseConfig`
+	pub(crate) fn cache(&self) -> RedbFilesystemCache {
 		RedbFilesystemCache::new(self.database.clone())
+			.with_batched_stats(self.config.batch_cache_stats)
+			.with_durability(self.config.durability)
 	}
 }
 
@@ -157,31 +227,63 @@ impl DatabaseStorage for RedbStorage {
 	async fn initialize(&mut self) -> DatabaseResult<()> {
 		// Initialize all required tables through specialized modules
 		super::tables::initialize_tables(&self.database).await?;
+		super::codec::record_format(&self.database, self.config.serialization_format)?;
 		Ok(())
 	}
 
 	async fn store_event(&mut self, record: &EventRecord) -> DatabaseResult<()> {
-		super::event_storage::store_event(&self.database, record).await
+		super::event_storage::store_event(
+			&self.database,
+			record,
+			self.config.durability,
+			self.config.track_current_state,
+		)
+		.await
+	}
+
+	async fn store_events(&mut self, records: &[EventRecord]) -> DatabaseResult<()> {
+		super::event_storage::store_events(
+			&self.database,
+			records,
+			self.config.durability,
+			self.config.track_current_state,
+		)
+		.await
 	}
 
 	async fn get_events(&mut self, key: &StorageKey) -> DatabaseResult<Vec<EventRecord>> {
 		super::event_storage::get_events(&self.database, key).await
 	}
 
+	async fn get_events_page(
+		&mut self, key: &StorageKey, after: Option<u64>, limit: usize,
+	) -> DatabaseResult<EventPage> {
+		super::event_storage::get_events_page(&self.database, key, after, limit).await
+	}
+
 	async fn store_metadata(&mut self, record: &MetadataRecord) -> DatabaseResult<()> {
-		super::metadata_storage::store_metadata(&self.database, record).await
+		super::metadata_storage::store_metadata(&self.database, record, self.config.durability)
+			.await
 	}
 
 	async fn get_metadata(&mut self, path: &Path) -> DatabaseResult<Option<MetadataRecord>> {
 		super::metadata_storage::get_metadata(&self.database, path).await
 	}
 
+	async fn get_current_state(&mut self, path: &Path) -> DatabaseResult<Option<EventRecord>> {
+		super::event_storage::get_current_state(&self.database, path).await
+	}
+
 	async fn find_events_by_time_range(
 		&mut self, start: DateTime<Utc>, end: DateTime<Utc>,
 	) -> DatabaseResult<Vec<EventRecord>> {
 		super::indexing::find_events_by_time_range(&self.database, start, end).await
 	}
 
+	async fn query_events(&mut self, query: &EventQuery) -> DatabaseResult<Vec<EventRecord>> {
+		super::event_storage::query_events(&self.database, query).await
+	}
+
 	async fn cleanup_expired_events(&mut self, before: SystemTime) -> DatabaseResult<usize> {
 		super::maintenance::cleanup_expired_events(&self.database, before).await
 	}
@@ -198,7 +300,11 @@ impl DatabaseStorage for RedbStorage {
 	}
 
 	async fn compact(&mut self) -> DatabaseResult<()> {
-		super::maintenance::compact_database(&self.database).await
+		super::maintenance::compact_database(&mut self.database).await
+	}
+
+	async fn fragmentation_ratio(&self) -> DatabaseResult<f64> {
+		super::maintenance::fragmentation_ratio(&self.database, &self.config.database_path)
 	}
 
 	async fn close(self) -> DatabaseResult<()> {
@@ -252,6 +358,14 @@ impl DatabaseStorage for RedbStorage {
 
 	async fn delete_events_older_than(
 		&mut self, cutoff: std::time::SystemTime,
+	) -> DatabaseResult<usize> {
+		self.delete_events_older_than_by_type(cutoff, &std::collections::HashMap::new())
+			.await
+	}
+
+	async fn delete_events_older_than_by_type(
+		&mut self, cutoff: std::time::SystemTime,
+		type_cutoffs: &std::collections::HashMap<String, std::time::SystemTime>,
 	) -> DatabaseResult<usize> {
 		// WARNING: This implementation iterates all events. Performance will degrade with large logs.
 		// For production, use an indexed timestamp or batch delete if supported by backend.
@@ -261,6 +375,7 @@ impl DatabaseStorage for RedbStorage {
 			write_txn.open_multimap_table(crate::database::storage::tables::EVENTS_LOG_TABLE)?;
 		let mut stats_table =
 			write_txn.open_table(crate::database::storage::tables::STATS_TABLE)?;
+		let format = super::codec::stored_format(&stats_table)?;
 		let mut removed = 0;
 		let mut to_remove = Vec::new();
 		for entry in events_log.iter()? {
@@ -272,12 +387,13 @@ impl DatabaseStorage for RedbStorage {
 					Err(_) => continue, // Skip corrupt
 				};
 				let value_bytes = value_guard.value();
-				let record: EventRecord = match bincode::deserialize(value_bytes) {
+				let record: EventRecord = match format.decode(value_bytes) {
 					Ok(r) => r,
 					Err(_) => continue, // Skip corrupt
 				};
+				let cutoff = type_cutoffs.get(&record.event_type).copied().unwrap_or(cutoff);
 				if record.timestamp < chrono::DateTime::<chrono::Utc>::from(cutoff) {
-					to_remove.push((key_bytes.to_vec(), value_bytes.to_vec()));
+					to_remove.push((record, key_bytes.to_vec(), value_bytes.to_vec()));
 				}
 			}
 		}
@@ -286,11 +402,25 @@ impl DatabaseStorage for RedbStorage {
 		let mut count = count_bytes
 			.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
 			.unwrap_or(0);
-		for (key, value) in to_remove {
+		let mut time_index =
+			write_txn.open_multimap_table(crate::database::storage::tables::TIME_INDEX_TABLE)?;
+		let mut prefix_index =
+			write_txn.open_multimap_table(crate::database::storage::tables::INDEXES_TABLE)?;
+		let mut sequence_index =
+			write_txn.open_table(crate::database::storage::tables::EVENT_SEQUENCE_INDEX)?;
+		for (record, key, value) in to_remove {
 			events_log.remove(key.as_slice(), value.as_slice())?;
+			super::event_storage::unindex_record(
+				&mut time_index,
+				&mut prefix_index,
+				&mut sequence_index,
+				&record,
+				&value,
+			)?;
 			removed += 1;
 			count = count.saturating_sub(1);
 		}
+		drop((time_index, prefix_index, sequence_index));
 		stats_table.insert(
 			crate::database::storage::tables::EVENT_COUNT_KEY,
 			&count.to_le_bytes()[..],
@@ -326,6 +456,7 @@ impl DatabaseStorage for RedbStorage {
 			write_txn.open_multimap_table(crate::database::storage::tables::EVENTS_LOG_TABLE)?;
 		let mut stats_table =
 			write_txn.open_table(crate::database::storage::tables::STATS_TABLE)?;
+		let format = super::codec::stored_format(&stats_table)?;
 		let mut all_events = Vec::new();
 		for entry in events_log.iter()? {
 			let (key_guard, multimap_value) = entry?;
@@ -336,25 +467,39 @@ impl DatabaseStorage for RedbStorage {
 					Err(_) => continue,
 				};
 				let value_bytes = value_guard.value();
-				let record: EventRecord = match bincode::deserialize(value_bytes) {
+				let record: EventRecord = match format.decode(value_bytes) {
 					Ok(r) => r,
 					Err(_) => continue,
 				};
-				all_events.push((record.timestamp, key_bytes.to_vec(), value_bytes.to_vec()));
+				all_events.push((record, key_bytes.to_vec(), value_bytes.to_vec()));
 			}
 		}
-		all_events.sort_by_key(|(ts, _, _)| *ts);
+		all_events.sort_by_key(|(record, _, _)| record.timestamp);
 		let mut removed = 0;
 		// Decrement persistent event counter for each event removed
 		let count_bytes = stats_table.get(crate::database::storage::tables::EVENT_COUNT_KEY)?;
 		let mut count = count_bytes
 			.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
 			.unwrap_or(0);
-		for (_ts, key, value) in all_events.into_iter().take(n) {
+		let mut time_index =
+			write_txn.open_multimap_table(crate::database::storage::tables::TIME_INDEX_TABLE)?;
+		let mut prefix_index =
+			write_txn.open_multimap_table(crate::database::storage::tables::INDEXES_TABLE)?;
+		let mut sequence_index =
+			write_txn.open_table(crate::database::storage::tables::EVENT_SEQUENCE_INDEX)?;
+		for (record, key, value) in all_events.into_iter().take(n) {
 			events_log.remove(key.as_slice(), value.as_slice())?;
+			super::event_storage::unindex_record(
+				&mut time_index,
+				&mut prefix_index,
+				&mut sequence_index,
+				&record,
+				&value,
+			)?;
 			removed += 1;
 			count = count.saturating_sub(1);
 		}
+		drop((time_index, prefix_index, sequence_index));
 		stats_table.insert(
 			crate::database::storage::tables::EVENT_COUNT_KEY,
 			&count.to_le_bytes()[..],
@@ -417,6 +562,7 @@ pub struct CoreTest;
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::database::config::Durability;
 	use std::path::PathBuf;
 	use tempfile::tempdir;
 
@@ -453,4 +599,80 @@ mod tests {
 		// total_events is u64, always >= 0; this check is redundant.
 		// assert!(stats.total_events >= 0);
 	}
+
+	/// In-memory redb backend counting the syncs commits ask for, as (immediate, eventual)
+	#[derive(Debug, Default)]
+	struct SyncCountingBackend {
+		inner: redb::backends::InMemoryBackend,
+		syncs: Arc<std::sync::Mutex<(usize, usize)>>,
+	}
+
+	impl redb::StorageBackend for SyncCountingBackend {
+		fn len(&self) -> Result<u64, std::io::Error> {
+			self.inner.len()
+		}
+
+		fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, std::io::Error> {
+			self.inner.read(offset, len)
+		}
+
+		fn set_len(&self, len: u64) -> Result<(), std::io::Error> {
+			self.inner.set_len(len)
+		}
+
+		fn sync_data(&self, eventual: bool) -> Result<(), std::io::Error> {
+			let mut syncs = self.syncs.lock().unwrap();
+			if eventual {
+				syncs.1 += 1;
+			} else {
+				syncs.0 += 1;
+			}
+			self.inner.sync_data(eventual)
+		}
+
+		fn write(&self, offset: u64, data: &[u8]) -> Result<(), std::io::Error> {
+			self.inner.write(offset, data)
+		}
+	}
+
+	#[tokio::test]
+	async fn test_configured_durability_applies_to_writes() {
+		for (durability, expected) in [
+			(Durability::Immediate, (true, false)),
+			(Durability::Eventual, (false, true)),
+			(Durability::None, (false, false)),
+		] {
+			let backend = SyncCountingBackend::default();
+			let syncs = backend.syncs.clone();
+			let database = redb::Builder::new().create_with_backend(backend).unwrap();
+			let config = DatabaseConfig { durability, ..DatabaseConfig::for_small_directories() };
+			let mut storage = RedbStorage { database: Arc::new(database), config };
+			storage.initialize().await.unwrap();
+
+			*syncs.lock().unwrap() = (0, 0);
+			let record = EventRecord::new(
+				"created".to_string(),
+				PathBuf::from("/test/durable.txt"),
+				false,
+				chrono::Duration::hours(24),
+				0,
+			);
+			storage.store_event(&record).await.unwrap();
+			let metadata = MetadataRecord::new(PathBuf::from("/test/durable.txt"), false);
+			storage.store_metadata(&metadata).await.unwrap();
+			let (immediate, eventual) = *syncs.lock().unwrap();
+			assert_eq!((immediate > 0, eventual > 0), expected, "{durability:?}");
+
+			let key = StorageKey::path_hash(&record.path);
+			let stored = storage.get_events(&key).await.unwrap();
+			assert_eq!(stored.len(), 1, "{durability:?}");
+			assert_eq!(stored[0].path, record.path);
+			let stored = storage.get_metadata(&metadata.path).await.unwrap();
+			assert_eq!(
+				stored.map(|m| m.path),
+				Some(metadata.path),
+				"{durability:?}"
+			);
+		}
+	}
 }
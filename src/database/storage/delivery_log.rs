@@ -0,0 +1,181 @@
+//! Delivered events and their acknowledgements, for `WatcherConfig::delivery_log`
+//
+// DELIVERY_LOG is keyed by the watch id followed by the big-endian sequence number, so one
+// watch's events form a single range in sequence order. Values are the event as JSON, which
+// keeps non-UTF-8 paths (see `events::path_serde`); bincode would reject them. DELIVERY_ACKS
+// holds each watch's acknowledged high-water mark, and acknowledged events are deleted from the
+// log as the mark advances, so the log only holds what may still need replaying.
+//
+// Limitations:
+// - Sequences are per watch id; a watch that should resume after a restart needs a fixed
+//   `WatcherConfig::watch_id`.
+// - Only the high-water mark is stored. Acknowledgements above a gap live in the handle and
+//   are lost on restart, so those events are replayed once more.
+
+use crate::database::error::{DatabaseError, DatabaseResult};
+use crate::database::storage::tables::{DELIVERY_ACKS, DELIVERY_LOG};
+use crate::events::FileSystemEvent;
+use redb::{Database, ReadableTable, TableError};
+use uuid::Uuid;
+
+pub struct DeliveryLog;
+
+impl DeliveryLog {
+	/// Record `event`, delivered as `sequence` of `watch_id`
+	pub fn append(
+		database: &Database, watch_id: &Uuid, sequence: u64, event: &FileSystemEvent,
+	) -> DatabaseResult<()> {
+		let value =
+			serde_json::to_vec(event).map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+		let write_txn = database.begin_write()?;
+		{
+			let mut log = write_txn.open_table(DELIVERY_LOG)?;
+			log.insert(key(watch_id, sequence).as_slice(), value.as_slice())?;
+		}
+		write_txn.commit()?;
+		Ok(())
+	}
+
+	/// Sequence for the next event of `watch_id`: one past the last logged or acknowledged
+	pub fn next_sequence(database: &Database, watch_id: &Uuid) -> DatabaseResult<u64> {
+		let read_txn = database.begin_read()?;
+		let last_logged = match read_txn.open_table(DELIVERY_LOG) {
+			Ok(log) => log
+				.range(key(watch_id, 0).as_slice()..=key(watch_id, u64::MAX).as_slice())?
+				.next_back()
+				.transpose()?
+				.map(|(key, _)| sequence_of(key.value())),
+			Err(TableError::TableDoesNotExist(_)) => None,
+			Err(e) => return Err(e.into()),
+		};
+		drop(read_txn);
+		let last = last_logged.max(Self::acknowledged(database, watch_id)?);
+		Ok(last.map_or(0, |last| last.saturating_add(1)))
+	}
+
+	/// Highest sequence of `watch_id` acknowledged together with all before it
+	pub fn acknowledged(database: &Database, watch_id: &Uuid) -> DatabaseResult<Option<u64>> {
+		let read_txn = database.begin_read()?;
+		let acks = match read_txn.open_table(DELIVERY_ACKS) {
+			Ok(table) => table,
+			Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+		let acknowledged = acks
+			.get(watch_id.as_bytes().as_slice())?
+			.and_then(|v| v.value().try_into().ok().map(u64::from_le_bytes));
+		Ok(acknowledged)
+	}
+
+	/// Acknowledge every event of `watch_id` up to and including `through`, dropping them from
+	/// the log; returns how many were dropped. Never moves the mark backwards.
+	pub fn acknowledge(
+		database: &Database, watch_id: &Uuid, through: u64,
+	) -> DatabaseResult<usize> {
+		let write_txn = database.begin_write()?;
+		let removed = {
+			let mut acks = write_txn.open_table(DELIVERY_ACKS)?;
+			let current = acks
+				.get(watch_id.as_bytes().as_slice())?
+				.and_then(|v| v.value().try_into().ok().map(u64::from_le_bytes));
+			if current.is_some_and(|current| current >= through) {
+				return Ok(0);
+			}
+			acks.insert(
+				watch_id.as_bytes().as_slice(),
+				through.to_le_bytes().as_slice(),
+			)?;
+			let mut log = write_txn.open_table(DELIVERY_LOG)?;
+			let mut acknowledged = Vec::new();
+			for entry in
+				log.range(key(watch_id, 0).as_slice()..=key(watch_id, through).as_slice())?
+			{
+				acknowledged.push(entry?.0.value().to_vec());
+			}
+			for key in &acknowledged {
+				log.remove(key.as_slice())?;
+			}
+			acknowledged.len()
+		};
+		write_txn.commit()?;
+		Ok(removed)
+	}
+
+	/// Events of `watch_id` not acknowledged yet, in sequence order
+	pub fn unacknowledged(
+		database: &Database, watch_id: &Uuid,
+	) -> DatabaseResult<Vec<FileSystemEvent>> {
+		let from = Self::acknowledged(database, watch_id)?.map_or(0, |a| a.saturating_add(1));
+		let read_txn = database.begin_read()?;
+		let log = match read_txn.open_table(DELIVERY_LOG) {
+			Ok(table) => table,
+			Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+			Err(e) => return Err(e.into()),
+		};
+		let mut events = Vec::new();
+		for entry in
+			log.range(key(watch_id, from).as_slice()..=key(watch_id, u64::MAX).as_slice())?
+		{
+			let (_, value) = entry?;
+			let event = serde_json::from_slice(value.value())
+				.map_err(|e| DatabaseError::Deserialization(e.to_string()))?;
+			events.push(event);
+		}
+		Ok(events)
+	}
+}
+
+fn key(watch_id: &Uuid, sequence: u64) -> [u8; 24] {
+	let mut key = [0u8; 24];
+	key[..16].copy_from_slice(watch_id.as_bytes());
+	key[16..].copy_from_slice(&sequence.to_be_bytes());
+	key
+}
+
+fn sequence_of(key: &[u8]) -> u64 {
+	key.get(16..24)
+		.and_then(|bytes| bytes.try_into().ok())
+		.map_or(0, u64::from_be_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::events::EventType;
+	use std::path::PathBuf;
+
+	#[test]
+	fn test_acknowledge_drops_the_prefix_of_one_watch_only() {
+		let dir = tempfile::tempdir().unwrap();
+		let database = Database::create(dir.path().join("delivery.redb")).unwrap();
+		let (watch, other) = (Uuid::new_v4(), Uuid::new_v4());
+		assert_eq!(DeliveryLog::next_sequence(&database, &watch).unwrap(), 0);
+		for sequence in 0..4 {
+			let path = PathBuf::from(format!("/w/{sequence}.txt"));
+			let event = FileSystemEvent::new(EventType::Create, path, false, None);
+			DeliveryLog::append(&database, &watch, sequence, &event).unwrap();
+			DeliveryLog::append(&database, &other, sequence, &event).unwrap();
+		}
+
+		assert_eq!(DeliveryLog::acknowledge(&database, &watch, 1).unwrap(), 2);
+		assert_eq!(DeliveryLog::acknowledge(&database, &watch, 0).unwrap(), 0);
+		assert_eq!(
+			DeliveryLog::acknowledged(&database, &watch).unwrap(),
+			Some(1)
+		);
+		let left = DeliveryLog::unacknowledged(&database, &watch).unwrap();
+		let paths: Vec<_> = left.iter().map(|e| e.path.clone()).collect();
+		assert_eq!(
+			paths,
+			[PathBuf::from("/w/2.txt"), PathBuf::from("/w/3.txt")]
+		);
+		assert_eq!(
+			DeliveryLog::unacknowledged(&database, &other).unwrap().len(),
+			4
+		);
+
+		// Acknowledging everything still continues the numbering
+		DeliveryLog::acknowledge(&database, &watch, 3).unwrap();
+		assert_eq!(DeliveryLog::next_sequence(&database, &watch).unwrap(), 4);
+	}
+}
@@ -9,18 +9,25 @@
 //
 // Edge Cases:
 // - Duplicate events: The event log is append-only; duplicate events for the same path/key are not deduplicated. Consumers must handle this at query time if needed.
-// - Ordering: Events are ordered by insertion timestamp only. There is no guarantee of strict monotonicity if system clocks are skewed or if events are inserted out of order.
+// - Ordering: Events are ordered by insertion timestamp only. Timestamps come from `database::clock`, which does not go backward within a process, but a clock set back between runs or events inserted out of order still break strict monotonicity.
 // - Retention: Cleanup is non-atomic. If a crash or concurrent modification occurs during cleanup, some expired events may persist until the next run. Count-based retention may temporarily exceed the limit under heavy concurrent insertions.
 //
 // Exposes both explicit cleanup API and optional background task integration.
 
 use crate::database::storage::core::DatabaseStorage;
+use crate::events::EventType;
+use std::collections::HashMap;
 use std::time::{Duration, SystemTime}; // Use the correct trait
 
 /// Retention policy configuration for event cleanup.
 pub struct EventRetentionConfig {
 	/// Retain events newer than this duration (relative to now).
 	pub max_event_age: Duration,
+	/// Per-type replacements for `max_event_age`, e.g. to keep moves and removes for an audit
+	/// trail long after writes are pruned. Types not listed use `max_event_age`. The cleanups
+	/// that prune by `EventRecord::expires_at` take theirs from
+	/// `DatabaseConfig::event_type_retention` instead.
+	pub type_retention: HashMap<EventType, Duration>,
 	/// Maximum number of events to retain (optional, None = unlimited).
 	pub max_events: Option<usize>,
 	/// If true, cleanup runs periodically in the background.
@@ -33,6 +40,7 @@ impl Default for EventRetentionConfig {
 	fn default() -> Self {
 		Self {
 			max_event_age: Duration::from_secs(60 * 60 * 24 * 30), // 30 days
+			type_retention: HashMap::new(),
 			max_events: None,
 			background: false,
 			background_interval: None,
@@ -44,11 +52,21 @@ impl Default for EventRetentionConfig {
 pub async fn cleanup_old_events<S: DatabaseStorage>(
 	storage: &mut S, config: &EventRetentionConfig,
 ) -> crate::database::error::DatabaseResult<usize> {
-	// Remove events older than max_event_age.
-	let cutoff = SystemTime::now()
-		.checked_sub(config.max_event_age)
-		.unwrap_or(SystemTime::UNIX_EPOCH);
-	let mut removed = storage.delete_events_older_than(cutoff).await?;
+	// Remove events older than max_event_age, or than the retention of their type.
+	let now = crate::database::clock::now_system();
+	let cutoff_for = |age| now.checked_sub(age).unwrap_or(SystemTime::UNIX_EPOCH);
+	let cutoff = cutoff_for(config.max_event_age);
+	let mut removed = if config.type_retention.is_empty() {
+		storage.delete_events_older_than(cutoff).await?
+	} else {
+		// Keyed as `EventRecord::event_type` names the type
+		let type_cutoffs = config
+			.type_retention
+			.iter()
+			.map(|(event_type, age)| (format!("{event_type:?}"), cutoff_for(*age)))
+			.collect();
+		storage.delete_events_older_than_by_type(cutoff, &type_cutoffs).await?
+	};
 	// Optionally enforce max_events limit (remove oldest if over limit).
 	if let Some(max) = config.max_events {
 		let total = storage.count_events().await?;
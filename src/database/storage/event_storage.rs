@@ -3,86 +3,335 @@
 //! This module handles storage and retrieval of filesystem events.
 //! Focused on basic CRUD operations for EventRecord instances.
 
+use super::codec::RecordCodec;
+use super::transactions::begin_write;
 use crate::database::{
+	config::Durability,
 	error::DatabaseResult,
-	types::{EventRecord, StorageKey},
+	query::{prefix_index_key, EventQuery, QueryPlan, TIME_BUCKET_SECONDS},
+	types::{EventPage, EventRecord, StorageKey},
 };
-use redb::{Database, ReadableTable};
+use redb::{Database, ReadableMultimapTable, ReadableTable, WriteTransaction};
+use std::collections::BinaryHeap;
 use std::sync::Arc;
 
-/// Store an event record using the provided database
-pub async fn store_event(database: &Arc<Database>, record: &EventRecord) -> DatabaseResult<()> {
-	let write_txn = database.begin_write()?;
-	{
-		let mut events_log = write_txn.open_multimap_table(super::tables::EVENTS_LOG_TABLE)?;
-		let mut stats_table = write_txn.open_table(super::tables::STATS_TABLE)?;
-		let mut time_index = write_txn.open_multimap_table(super::tables::TIME_INDEX_TABLE)?;
-		let key = StorageKey::path_hash(&record.path);
-		let key_bytes = key.to_bytes();
-
-		// Assign sequence number
-		let seq_bytes = stats_table.get(super::tables::EVENT_SEQUENCE_KEY)?;
-		let mut sequence_number = seq_bytes
-			.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
-			.unwrap_or(0);
-
-		let mut record = record.clone();
-		record.sequence_number = sequence_number;
-		sequence_number = sequence_number.saturating_add(1);
-		stats_table.insert(
-			super::tables::EVENT_SEQUENCE_KEY,
-			&sequence_number.to_le_bytes()[..],
+/// Store an event record using the provided database, and with `track_current_state` make it
+/// its path's current state
+pub async fn store_event(
+	database: &Arc<Database>, record: &EventRecord, durability: Durability,
+	track_current_state: bool,
+) -> DatabaseResult<()> {
+	let write_txn = begin_write(database, durability)?;
+	let sequence_number = append_record(&write_txn, record)?;
+	if track_current_state {
+		set_current_state(
+			&write_txn,
+			&EventRecord { sequence_number, ..record.clone() },
 		)?;
+	}
+	write_txn.commit()?;
+	Ok(())
+}
 
-		let record_bytes = bincode::serialize(&record)
-			.map_err(|e| crate::database::error::DatabaseError::Serialization(e.to_string()))?;
-
-		events_log.insert(key_bytes.as_slice(), record_bytes.as_slice())?;
-
-		// Increment persistent event counter
-		let count_bytes = stats_table.get(super::tables::EVENT_COUNT_KEY)?;
-		let mut count = count_bytes
-			.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
-			.unwrap_or(0);
-		count = count.saturating_add(1);
-		stats_table.insert(super::tables::EVENT_COUNT_KEY, &count.to_le_bytes()[..])?;
-
-		// Increment per-event-type counter
-		let type_key = crate::database::types::event_type_stat_key(&record.event_type);
-		let type_count_bytes = stats_table.get(type_key.as_slice())?;
-		let mut type_count = type_count_bytes
-			.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
-			.unwrap_or(0);
-		type_count = type_count.saturating_add(1);
-		stats_table.insert(type_key.as_slice(), &type_count.to_le_bytes()[..])?;
-
-		// Use hourly buckets for time index (customize as needed)
-		let bucket_size_seconds = 3600;
-		let time_bucket =
-			crate::database::types::StorageKey::time_bucket(record.timestamp, bucket_size_seconds);
-		let time_bucket_bytes = time_bucket.to_bytes();
-		time_index.insert(time_bucket_bytes.as_slice(), record_bytes.as_slice())?;
+/// Store several event records in one transaction, in order
+pub async fn store_events(
+	database: &Arc<Database>, records: &[EventRecord], durability: Durability,
+	track_current_state: bool,
+) -> DatabaseResult<()> {
+	let write_txn = begin_write(database, durability)?;
+	for record in records {
+		let sequence_number = append_record(&write_txn, record)?;
+		if track_current_state {
+			set_current_state(
+				&write_txn,
+				&EventRecord { sequence_number, ..record.clone() },
+			)?;
+		}
 	}
 	write_txn.commit()?;
 	Ok(())
 }
 
+/// Replace the current state of `record`'s path with `record`, and that of a move's source
+/// with a Remove
+fn set_current_state(write_txn: &WriteTransaction, record: &EventRecord) -> DatabaseResult<()> {
+	let format = super::codec::write_format(write_txn)?;
+	let mut current_state = write_txn.open_table(super::tables::CURRENT_STATE_TABLE)?;
+	if let Some(source_path) = &record.source_path {
+		let moved_away = EventRecord {
+			event_type: "Remove".to_string(),
+			path: source_path.clone(),
+			size: None,
+			inode: None,
+			windows_id: None,
+			content_hash: None,
+			source_path: None,
+			..record.clone()
+		};
+		let key = crate::database::types::calculate_path_hash(source_path).to_le_bytes();
+		current_state.insert(key.as_slice(), format.encode(&moved_away)?.as_slice())?;
+	}
+	let key = crate::database::types::calculate_path_hash(&record.path).to_le_bytes();
+	current_state.insert(key.as_slice(), format.encode(record)?.as_slice())?;
+	Ok(())
+}
+
+/// The last event stored for `path` with `DatabaseConfig::track_current_state`
+pub async fn get_current_state(
+	database: &Arc<Database>, path: &std::path::Path,
+) -> DatabaseResult<Option<EventRecord>> {
+	let read_txn = database.begin_read()?;
+	let current_state = match read_txn.open_table(super::tables::CURRENT_STATE_TABLE) {
+		Ok(table) => table,
+		Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+		Err(e) => return Err(e.into()),
+	};
+	let key = crate::database::types::calculate_path_hash(path).to_le_bytes();
+	let Some(bytes) = current_state.get(key.as_slice())? else {
+		return Ok(None);
+	};
+	let record = super::codec::read_format(&read_txn)?.decode::<EventRecord>(bytes.value())?;
+	// Keyed by path hash alone; another path's state is not this one's
+	Ok(crate::database::types::same_hashed_path(&record.path, path).then_some(record))
+}
+
+/// Append one record inside an open write transaction, returning the sequence number it got.
+///
+/// The record's own `sequence_number` is ignored; the next value of the persistent sequence
+/// counter is assigned, so callers can batch several appends into one commit.
+pub(crate) fn append_record(
+	write_txn: &WriteTransaction, record: &EventRecord,
+) -> DatabaseResult<u64> {
+	let mut events_log = write_txn.open_multimap_table(super::tables::EVENTS_LOG_TABLE)?;
+	let mut stats_table = write_txn.open_table(super::tables::STATS_TABLE)?;
+	let mut time_index = write_txn.open_multimap_table(super::tables::TIME_INDEX_TABLE)?;
+	let mut prefix_index = write_txn.open_multimap_table(super::tables::INDEXES_TABLE)?;
+	let mut sequence_index = write_txn.open_table(super::tables::EVENT_SEQUENCE_INDEX)?;
+	let key = StorageKey::path_hash(&record.path);
+	let key_bytes = key.to_bytes();
+
+	// Assign sequence number
+	let seq_bytes = stats_table.get(super::tables::EVENT_SEQUENCE_KEY)?;
+	let mut sequence_number = seq_bytes
+		.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
+		.unwrap_or(0);
+
+	let mut record = record.clone();
+	record.sequence_number = sequence_number;
+	sequence_number = sequence_number.saturating_add(1);
+	stats_table.insert(
+		super::tables::EVENT_SEQUENCE_KEY,
+		&sequence_number.to_le_bytes()[..],
+	)?;
+
+	let record_bytes = super::codec::stored_format(&stats_table)?.encode(&record)?;
+
+	if events_log.iter()?.next().is_none() {
+		stats_table.insert(super::tables::EVENT_PREFIX_INDEXED_KEY, &[][..])?;
+		stats_table.insert(super::tables::EVENT_SEQUENCE_INDEXED_KEY, &[][..])?;
+	}
+	events_log.insert(key_bytes.as_slice(), record_bytes.as_slice())?;
+	sequence_index.insert(
+		sequence_index_key(&key_bytes, record.sequence_number).as_slice(),
+		record_bytes.as_slice(),
+	)?;
+
+	// Increment persistent event counter
+	let count_bytes = stats_table.get(super::tables::EVENT_COUNT_KEY)?;
+	let mut count = count_bytes
+		.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
+		.unwrap_or(0);
+	count = count.saturating_add(1);
+	stats_table.insert(super::tables::EVENT_COUNT_KEY, &count.to_le_bytes()[..])?;
+
+	// Increment per-event-type counter
+	let type_key = crate::database::types::event_type_stat_key(&record.event_type);
+	let type_count_bytes = stats_table.get(type_key.as_slice())?;
+	let mut type_count = type_count_bytes
+		.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])))
+		.unwrap_or(0);
+	type_count = type_count.saturating_add(1);
+	stats_table.insert(type_key.as_slice(), &type_count.to_le_bytes()[..])?;
+
+	// Use hourly buckets for time index (customize as needed)
+	let time_bucket =
+		crate::database::types::StorageKey::time_bucket(record.timestamp, TIME_BUCKET_SECONDS);
+	let time_bucket_bytes = time_bucket.to_bytes();
+	time_index.insert(time_bucket_bytes.as_slice(), record_bytes.as_slice())?;
+	if let Some(prefix) = prefix_index_key(&record.path) {
+		prefix_index.insert(prefix.to_bytes().as_slice(), record_bytes.as_slice())?;
+	}
+	Ok(record.sequence_number)
+}
+
+/// Key of the event with `sequence_number` under the events log key `key_bytes` in
+/// `EVENT_SEQUENCE_INDEX`; big-endian so a path's events sort by sequence
+pub(crate) fn sequence_index_key(key_bytes: &[u8], sequence_number: u64) -> Vec<u8> {
+	[key_bytes, &sequence_number.to_be_bytes()[..]].concat()
+}
+
+/// Drop `record`, stored as `record_bytes`, from the time, path-prefix and sequence indexes;
+/// the events log entry is removed by the caller
+pub(crate) fn unindex_record(
+	time_index: &mut redb::MultimapTable<&[u8], &[u8]>,
+	prefix_index: &mut redb::MultimapTable<&[u8], &[u8]>,
+	sequence_index: &mut redb::Table<&[u8], &[u8]>, record: &EventRecord, record_bytes: &[u8],
+) -> DatabaseResult<()> {
+	let time_bucket = StorageKey::time_bucket(record.timestamp, TIME_BUCKET_SECONDS);
+	time_index.remove(time_bucket.to_bytes().as_slice(), record_bytes)?;
+	if let Some(prefix) = prefix_index_key(&record.path) {
+		prefix_index.remove(prefix.to_bytes().as_slice(), record_bytes)?;
+	}
+	let key_bytes = StorageKey::path_hash(&record.path).to_bytes();
+	sequence_index.remove(sequence_index_key(&key_bytes, record.sequence_number).as_slice())?;
+	Ok(())
+}
+
+/// The plan `query_events` would run `query` with
+#[cfg(test)]
+pub(crate) fn plan(database: &Arc<Database>, query: &EventQuery) -> DatabaseResult<QueryPlan> {
+	plan_in(&database.begin_read()?, query)
+}
+
+fn plan_in(read_txn: &redb::ReadTransaction, query: &EventQuery) -> DatabaseResult<QueryPlan> {
+	let prefix_indexed = match read_txn.open_table(super::tables::STATS_TABLE) {
+		Ok(stats) => stats.get(super::tables::EVENT_PREFIX_INDEXED_KEY)?.is_some(),
+		Err(redb::TableError::TableDoesNotExist(_)) => false,
+		Err(e) => return Err(e.into()),
+	};
+	Ok(query.plan(prefix_indexed))
+}
+
+/// Run `query` from the index its plan picks, see `database::query`
+pub async fn query_events(
+	database: &Arc<Database>, query: &EventQuery,
+) -> DatabaseResult<Vec<EventRecord>> {
+	let read_txn = database.begin_read()?;
+	let format = super::codec::read_format(&read_txn)?;
+	let (table, keys) = match plan_in(&read_txn, query)? {
+		QueryPlan::Empty => return Ok(Vec::new()),
+		QueryPlan::FullScan => {
+			let events_log = read_txn.open_multimap_table(super::tables::EVENTS_LOG_TABLE)?;
+			let mut candidates = Vec::new();
+			for entry in events_log.iter()? {
+				for value in entry?.1 {
+					candidates.push(format.decode::<EventRecord>(value?.value())?);
+				}
+			}
+			return Ok(query.finish(candidates));
+		}
+		QueryPlan::PathHash(key) => (super::tables::EVENTS_LOG_TABLE, vec![key]),
+		QueryPlan::PathPrefix(key) => (super::tables::INDEXES_TABLE, vec![key]),
+		QueryPlan::TimeBuckets(keys) => (super::tables::TIME_INDEX_TABLE, keys),
+	};
+	let index = read_txn.open_multimap_table(table)?;
+	let mut candidates = Vec::new();
+	for key in keys {
+		for value in index.get(key.to_bytes().as_slice())? {
+			candidates.push(format.decode::<EventRecord>(value?.value())?);
+		}
+	}
+	Ok(query.finish(candidates))
+}
+
 /// Retrieve events by storage key using the provided database
 pub async fn get_events(
 	database: &Arc<Database>, key: &StorageKey,
 ) -> DatabaseResult<Vec<EventRecord>> {
 	let read_txn = database.begin_read()?;
 	let events_log = read_txn.open_multimap_table(super::tables::EVENTS_LOG_TABLE)?;
+	let format = super::codec::read_format(&read_txn)?;
 	let key_bytes = key.to_bytes();
 	let multimap = events_log.get(key_bytes.as_slice())?;
 	let mut events = Vec::new();
 	for item in multimap {
 		let value = item?;
-		let record = bincode::deserialize::<EventRecord>(value.value())
-			.map_err(|e| crate::database::error::DatabaseError::Deserialization(e.to_string()))?;
+		let record = format.decode::<EventRecord>(value.value())?;
 		events.push(record);
 	}
 	// Enforce append order: sort by sequence_number (ascending)
 	events.sort_by_key(|e| e.sequence_number);
 	Ok(events)
 }
+
+/// One page of the events stored under `key`, see `DatabaseStorage::get_events_page`.
+///
+/// Seeks to the first event after `after` in `EVENT_SEQUENCE_INDEX` and decodes only the page
+/// (plus one event, to tell whether another page follows).
+pub async fn get_events_page(
+	database: &Arc<Database>, key: &StorageKey, after: Option<u64>, limit: usize,
+) -> DatabaseResult<EventPage> {
+	let keep = limit.max(1) + 1;
+	let read_txn = database.begin_read()?;
+	let format = super::codec::read_format(&read_txn)?;
+	let key_bytes = key.to_bytes();
+	let sequence_indexed = match read_txn.open_table(super::tables::STATS_TABLE) {
+		Ok(stats) => stats.get(super::tables::EVENT_SEQUENCE_INDEXED_KEY)?.is_some(),
+		Err(redb::TableError::TableDoesNotExist(_)) => false,
+		Err(e) => return Err(e.into()),
+	};
+	if !sequence_indexed {
+		return scan_events_page(&read_txn, format, &key_bytes, after, keep, limit);
+	}
+	let sequence_index = read_txn.open_table(super::tables::EVENT_SEQUENCE_INDEX)?;
+	let first = match after {
+		Some(after) => after.checked_add(1),
+		None => Some(0),
+	};
+	let Some(first) = first else {
+		return Ok(EventPage::default());
+	};
+	let start = sequence_index_key(&key_bytes, first);
+	let end = sequence_index_key(&key_bytes, u64::MAX);
+	let mut events = Vec::with_capacity(keep);
+	for entry in sequence_index.range(start.as_slice()..=end.as_slice())?.take(keep) {
+		events.push(format.decode::<EventRecord>(entry?.1.value())?);
+	}
+	Ok(EventPage::from_sorted(events.into_iter(), after, limit))
+}
+
+/// `get_events_page` for files with events from before `EVENT_SEQUENCE_INDEX` existed. The
+/// events log orders a key's values by their encoded bytes rather than by sequence, so every
+/// event under the key is decoded; only the lowest `keep` above `after` are kept.
+fn scan_events_page(
+	read_txn: &redb::ReadTransaction, format: super::codec::SerializationFormat, key_bytes: &[u8],
+	after: Option<u64>, keep: usize, limit: usize,
+) -> DatabaseResult<EventPage> {
+	let events_log = read_txn.open_multimap_table(super::tables::EVENTS_LOG_TABLE)?;
+	// Max-heap on sequence number, so the highest of the kept events is evicted first
+	let mut lowest = BinaryHeap::with_capacity(keep + 1);
+	for item in events_log.get(key_bytes)? {
+		let record = format.decode::<EventRecord>(item?.value())?;
+		if after.is_some_and(|after| record.sequence_number <= after) {
+			continue;
+		}
+		lowest.push(BySequence(record));
+		if lowest.len() > keep {
+			lowest.pop();
+		}
+	}
+	let events = lowest.into_sorted_vec().into_iter().map(|BySequence(record)| record);
+	Ok(EventPage::from_sorted(events, after, limit))
+}
+
+struct BySequence(EventRecord);
+
+impl PartialEq for BySequence {
+	fn eq(&self, other: &Self) -> bool {
+		self.0.sequence_number == other.0.sequence_number
+	}
+}
+
+impl Eq for BySequence {}
+
+impl PartialOrd for BySequence {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for BySequence {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		self.0.sequence_number.cmp(&other.0.sequence_number)
+	}
+}
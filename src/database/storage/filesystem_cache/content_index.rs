@@ -0,0 +1,140 @@
+//! Content-hash index: which path most recently held a given content
+//
+// Backs `MoveDetectorConfig::content_hash_index`. Two tables kept one-to-one:
+// CONTENT_HASH_INDEX (hash -> path) answers "where was this content last seen", and
+// CONTENT_HASH_PATHS (path -> hash) lets a path's old entry be dropped when it is rehashed and
+// lets a rename carry entries (including everything below a renamed directory) along.
+//
+// Limitations:
+// - Paths are keyed by their lossy UTF-8 form, like PATH_PREFIX_TABLE; a non-UTF-8 path is
+//   recorded but never found to be missing, so it never pairs.
+// - Only the latest path per hash is kept. Of several identical files, only the one recorded
+//   last can be a move source.
+
+use crate::database::error::DatabaseResult;
+use crate::database::storage::tables::{CONTENT_HASH_INDEX, CONTENT_HASH_PATHS};
+use redb::{Database, ReadableTable, TableError};
+use std::path::{Path, PathBuf};
+
+pub struct ContentIndexHelpers;
+
+impl ContentIndexHelpers {
+	/// Record `path` as the latest location of `hash`, replacing the previous entries of both
+	pub fn record(database: &Database, hash: &str, path: &Path) -> DatabaseResult<()> {
+		let path_key = path.to_string_lossy();
+		let write_txn = database.begin_write()?;
+		{
+			let mut by_hash = write_txn.open_table(CONTENT_HASH_INDEX)?;
+			let mut by_path = write_txn.open_table(CONTENT_HASH_PATHS)?;
+			let previous_path = by_hash.get(hash.as_bytes())?.map(|v| v.value().to_vec());
+			if let Some(previous_path) = previous_path {
+				if previous_path != path_key.as_bytes() {
+					Self::remove_if(&mut by_path, &previous_path, hash.as_bytes())?;
+				}
+			}
+			let previous_hash = by_path.get(path_key.as_bytes())?.map(|v| v.value().to_vec());
+			if let Some(previous_hash) = previous_hash {
+				if previous_hash != hash.as_bytes() {
+					Self::remove_if(&mut by_hash, &previous_hash, path_key.as_bytes())?;
+				}
+			}
+			by_hash.insert(hash.as_bytes(), path_key.as_bytes())?;
+			by_path.insert(path_key.as_bytes(), hash.as_bytes())?;
+		}
+		write_txn.commit()?;
+		Ok(())
+	}
+
+	/// Latest path recorded for `hash`
+	pub fn lookup(database: &Database, hash: &str) -> DatabaseResult<Option<PathBuf>> {
+		let read_txn = database.begin_read()?;
+		let by_hash = match read_txn.open_table(CONTENT_HASH_INDEX) {
+			Ok(table) => table,
+			Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+		let path = by_hash
+			.get(hash.as_bytes())?
+			.map(|v| PathBuf::from(String::from_utf8_lossy(v.value()).into_owned()));
+		Ok(path)
+	}
+
+	/// Point the entries of `old`, and of every path below it, at the same place under `new`
+	pub fn relocate(database: &Database, old: &Path, new: &Path) -> DatabaseResult<usize> {
+		let old_key = old.to_string_lossy().into_owned();
+		let new_key = new.to_string_lossy().into_owned();
+		let write_txn = database.begin_write()?;
+		let moved = {
+			let mut by_hash = write_txn.open_table(CONTENT_HASH_INDEX)?;
+			let mut by_path = write_txn.open_table(CONTENT_HASH_PATHS)?;
+			// Keys are ordered, so the subtree is one contiguous range starting at `old`
+			let mut entries = Vec::new();
+			for entry in by_path.range(old_key.as_bytes()..)? {
+				let (key, value) = entry?;
+				let Some(suffix) = key.value().strip_prefix(old_key.as_bytes()) else {
+					break;
+				};
+				if suffix.is_empty() || matches!(suffix[0], b'/' | b'\\') {
+					entries.push((
+						key.value().to_vec(),
+						suffix.to_vec(),
+						value.value().to_vec(),
+					));
+				}
+			}
+			for (old_path, suffix, hash) in &entries {
+				let mut new_path = new_key.as_bytes().to_vec();
+				new_path.extend_from_slice(suffix);
+				by_path.remove(old_path.as_slice())?;
+				by_path.insert(new_path.as_slice(), hash.as_slice())?;
+				let current = by_hash.get(hash.as_slice())?.map(|v| v.value().to_vec());
+				if current.as_ref() == Some(old_path) {
+					by_hash.insert(hash.as_slice(), new_path.as_slice())?;
+				}
+			}
+			entries.len()
+		};
+		write_txn.commit()?;
+		Ok(moved)
+	}
+
+	/// Remove `key` only while it still maps to `expected`
+	fn remove_if(
+		table: &mut redb::Table<&'static [u8], &'static [u8]>, key: &[u8], expected: &[u8],
+	) -> DatabaseResult<()> {
+		let matches = table.get(key)?.is_some_and(|v| v.value() == expected);
+		if matches {
+			table.remove(key)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_relocate_carries_a_directory_subtree_and_nothing_else() {
+		let dir = tempfile::tempdir().unwrap();
+		let database = Database::create(dir.path().join("index.redb")).unwrap();
+		ContentIndexHelpers::record(&database, "h1", Path::new("/w/docs/a.txt")).unwrap();
+		ContentIndexHelpers::record(&database, "h2", Path::new("/w/docs/sub/b.txt")).unwrap();
+		ContentIndexHelpers::record(&database, "h3", Path::new("/w/docs-old/c.txt")).unwrap();
+
+		let moved =
+			ContentIndexHelpers::relocate(&database, Path::new("/w/docs"), Path::new("/w/archive"))
+				.unwrap();
+		assert_eq!(moved, 2);
+		let lookup = |hash| ContentIndexHelpers::lookup(&database, hash).unwrap();
+		assert_eq!(lookup("h1"), Some(PathBuf::from("/w/archive/a.txt")));
+		assert_eq!(lookup("h2"), Some(PathBuf::from("/w/archive/sub/b.txt")));
+		// Shares the name prefix, not the directory
+		assert_eq!(lookup("h3"), Some(PathBuf::from("/w/docs-old/c.txt")));
+
+		// Rehashing a path drops its old entry
+		ContentIndexHelpers::record(&database, "h4", Path::new("/w/archive/a.txt")).unwrap();
+		assert_eq!(lookup("h1"), None);
+		assert_eq!(lookup("h4"), Some(PathBuf::from("/w/archive/a.txt")));
+	}
+}
@@ -0,0 +1,71 @@
+//! Decision log: the pairings the move detector weighed, for offline tuning
+//
+// Backs `MoveDetectorConfig::record_decisions`. MOVE_DECISIONS is keyed by a big-endian
+// sequence, so iteration is oldest first and the oldest records are the first range to drop
+// once the log is over DECISION_LOG_CAPACITY.
+//
+// Limitations:
+// - One log per database, shared by every detector writing to it; records do not name a watch.
+// - Retention is by count only; the log is not pruned by age or by event retention.
+
+use super::utils::{deserialize, serialize};
+use crate::database::config::Durability;
+use crate::database::error::DatabaseResult;
+use crate::database::storage::tables::MOVE_DECISIONS;
+use crate::database::storage::transactions::begin_write;
+use crate::database::types::MoveDecisionRecord;
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableError};
+
+/// Records kept in the decision log; the oldest are dropped past this
+pub const DECISION_LOG_CAPACITY: u64 = 100_000;
+
+pub struct DecisionLogHelpers;
+
+impl DecisionLogHelpers {
+	/// Append `records` after the newest one, then trim the log to its capacity
+	pub fn append(
+		database: &Database, durability: Durability, records: &[MoveDecisionRecord],
+	) -> DatabaseResult<()> {
+		if records.is_empty() {
+			return Ok(());
+		}
+		let write_txn = begin_write(database, durability)?;
+		{
+			let mut table = write_txn.open_table(MOVE_DECISIONS)?;
+			let first = match table.last()? {
+				Some((key, _)) => Self::sequence(key.value()) + 1,
+				None => 0,
+			};
+			for (sequence, record) in (first..).zip(records) {
+				table.insert(
+					sequence.to_be_bytes().as_slice(),
+					serialize(record)?.as_slice(),
+				)?;
+			}
+			for _ in DECISION_LOG_CAPACITY..table.len()? {
+				table.pop_first()?;
+			}
+		}
+		write_txn.commit()?;
+		Ok(())
+	}
+
+	/// Every retained record, oldest first
+	pub fn all(database: &Database) -> DatabaseResult<Vec<MoveDecisionRecord>> {
+		let read_txn = database.begin_read()?;
+		let table = match read_txn.open_table(MOVE_DECISIONS) {
+			Ok(table) => table,
+			Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+			Err(e) => return Err(e.into()),
+		};
+		let mut records = Vec::new();
+		for entry in table.iter()? {
+			records.push(deserialize(entry?.1.value())?);
+		}
+		Ok(records)
+	}
+
+	fn sequence(key: &[u8]) -> u64 {
+		key.try_into().map(u64::from_be_bytes).unwrap_or_default()
+	}
+}
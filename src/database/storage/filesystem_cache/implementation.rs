@@ -9,32 +9,73 @@
 //!
 //! TODO: Refactor search to use indexed or batched queries for production use.
 
-use super::utils::{deserialize, key_to_bytes, serialize};
+use super::slots;
+use super::utils::{deserialize, serialize};
+use crate::database::config::Durability;
 use crate::database::error::DatabaseResult;
 use crate::database::storage::filesystem_cache::utils;
 use crate::database::storage::tables::{
-	MULTI_WATCH_FS_CACHE, MULTI_WATCH_HIERARCHY, PATH_PREFIX_TABLE, PATH_STATS, SHARED_NODES,
-	STATS_TABLE, WATCH_REGISTRY, WATCH_STATS,
+	MULTI_WATCH_FS_CACHE, MULTI_WATCH_HIERARCHY, PATH_PREFIX_TABLE, PATH_STATS, PATH_TO_WATCHES,
+	SHARED_NODES, STATS_TABLE, WATCH_REGISTRY, WATCH_STATS,
 };
+use crate::database::storage::transactions::begin_write;
 use crate::database::types::{
-	calculate_path_hash, FilesystemNode, SharedNodeInfo, WatchMetadata, WatchScopedKey,
+	calculate_path_hash, same_hashed_path, DirectoryCursor, DirectoryPage, FilesystemNode,
+	MoveDecisionRecord, SharedNodeInfo, WatchMetadata, WatchScopedKey,
 };
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use uuid::Uuid;
 
 use super::trait_def::{CacheStats, FilesystemCacheStorage};
+use crate::database::storage::filesystem_cache::content_index::ContentIndexHelpers;
+use crate::database::storage::filesystem_cache::decision_log::DecisionLogHelpers;
+use crate::database::storage::filesystem_cache::stats::StatsBatch;
 use crate::database::storage::filesystem_cache::watch_mapping::WatchMappingHelpers;
 use redb::{ReadableMultimapTable, ReadableTable};
-use tracing::{debug, info};
+use tracing::{debug, warn};
 
+#[derive(Clone)]
 pub struct RedbFilesystemCache {
 	pub(crate) database: Arc<redb::Database>,
+	/// Home slot of a path in the node table; see `slots`
+	path_hash: fn(&Path) -> u64,
+	/// Whether batch stores adjust stats counters once per batch; see `with_batched_stats`
+	batched_stats: bool,
+	/// Durability of node and watch writes; see `with_durability`
+	durability: Durability,
 }
 
 impl RedbFilesystemCache {
 	pub fn new(database: Arc<redb::Database>) -> Self {
-		Self { database }
+		Self {
+			database,
+			path_hash: calculate_path_hash,
+			batched_stats: true,
+			durability: Durability::Immediate,
+		}
+	}
+
+	/// Whether `batch_store_filesystem_nodes` accumulates the stats increments of all its nodes
+	/// and writes each counter once (the default), or updates the counters node by node like
+	/// separate stores would. The counters come out the same either way.
+	pub fn with_batched_stats(mut self, batched_stats: bool) -> Self {
+		self.batched_stats = batched_stats;
+		self
+	}
+
+	/// Commit node and watch writes with `durability` instead of `Durability::Immediate`.
+	/// Stale-entry cleanup and stats repair always commit immediately.
+	pub fn with_durability(mut self, durability: Durability) -> Self {
+		self.durability = durability;
+		self
+	}
+
+	/// Place nodes by `path_hash` instead of `calculate_path_hash`, to force collisions
+	#[cfg(test)]
+	pub(crate) fn with_path_hash(mut self, path_hash: fn(&Path) -> u64) -> Self {
+		self.path_hash = path_hash;
+		self
 	}
 
 	/// Initialize the filesystem cache tables
@@ -44,6 +85,11 @@ impl RedbFilesystemCache {
 		Ok(())
 	}
 
+	/// `path` canonicalized, as nodes are keyed, or as given when that fails
+	fn canonical(path: &Path) -> PathBuf {
+		path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+	}
+
 	/// Create a scoped key for a watch-specific node
 	pub fn create_scoped_key(watch_id: &Uuid, path_hash: u64) -> WatchScopedKey {
 		WatchScopedKey { watch_id: *watch_id, path_hash }
@@ -55,21 +101,19 @@ impl RedbFilesystemCache {
 		path_hash
 	}
 
-	/// Helper: insert all path prefixes for a node into PATH_PREFIX_TABLE
+	/// Helper: insert all path prefixes for a node stored under `key_bytes` into
+	/// PATH_PREFIX_TABLE
 	fn index_path_prefixes(
-		write_txn: &redb::WriteTransaction, node: &FilesystemNode, watch_id: &Uuid,
+		write_txn: &redb::WriteTransaction, node: &FilesystemNode, key_bytes: &[u8],
 	) -> DatabaseResult<()> {
 		let mut prefix_table = write_txn.open_multimap_table(PATH_PREFIX_TABLE)?;
 		let path = &node.path;
-		let path_hash = calculate_path_hash(path);
-		let scoped_key = Self::create_scoped_key(watch_id, path_hash);
-		let key_bytes = key_to_bytes(&scoped_key);
 		// Insert all parent prefixes (e.g., /a, /a/b, /a/b/c)
 		let mut prefix = Path::new("").to_path_buf();
 		for component in path.components() {
 			prefix.push(component);
 			let prefix_str = prefix.to_string_lossy();
-			prefix_table.insert(prefix_str.as_bytes(), key_bytes.as_slice())?;
+			prefix_table.insert(prefix_str.as_bytes(), key_bytes)?;
 		}
 		Ok(())
 	}
@@ -85,20 +129,20 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 			Ok(p) => p,
 			Err(_) => node.path.clone(),
 		};
-		let path_hash = calculate_path_hash(&canonical);
-		let scoped_key = Self::create_scoped_key(watch_id, path_hash);
-		let key_bytes = serialize(&scoped_key)?;
+		let path_hash = (self.path_hash)(&canonical);
 
 		// Always update last_event_type before storing
 		let mut node = node.clone();
 		node.last_event_type = Some(event_type.to_string());
 		let node_bytes = serialize(&node)?;
 
-		let mut write_txn = self.database.begin_write()?;
+		let write_txn = begin_write(&self.database, self.durability)?;
 		{
 			// Store the node
 			{
 				let mut fs_cache_table = write_txn.open_table(MULTI_WATCH_FS_CACHE)?;
+				let key_bytes =
+					slots::place(&write_txn, &fs_cache_table, watch_id, path_hash, &node.path)?;
 				fs_cache_table.insert(key_bytes.as_slice(), node_bytes.as_slice())?;
 
 				// Update hierarchy relationships
@@ -114,7 +158,7 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 				WatchMappingHelpers::insert_watch_mapping(&write_txn, path_hash, watch_id)?;
 
 				// Update path prefix index
-				Self::index_path_prefixes(&write_txn, &node, watch_id)?;
+				Self::index_path_prefixes(&write_txn, &node, &key_bytes)?;
 
 				// Update unified node index for O(1) cross-watch lookup
 				{
@@ -136,28 +180,13 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 			} // <-- All table borrows dropped here
 
 			// --- Incremental stats update: per-watch and per-path ---
-			let all_watches = WatchMappingHelpers::get_watches_for_path(&self.database, path_hash)?;
-			for wid in all_watches.iter() {
-				crate::database::storage::filesystem_cache::stats::increment_stats(
-					&mut write_txn,
-					wid,
-					path_hash,
-					event_type,
-				)?;
+			let mut stats = StatsBatch::default();
+			for wid in WatchMappingHelpers::get_watches_for_path_in(&write_txn, path_hash)? {
+				stats.increment(&wid, path_hash, event_type);
 			}
+			stats.apply(&write_txn)?;
 		}
 		write_txn.commit()?;
-		if cfg!(debug_assertions) {
-			let stats = self.get_cache_stats(watch_id).await.unwrap_or_default();
-			info!(
-				"[DEBUG] After insert: nodes={}, dirs={}, files={}, symlinks={}, size={} bytes",
-				stats.total_nodes,
-				stats.directories,
-				stats.files,
-				stats.symlinks,
-				stats.cache_size_bytes
-			);
-		}
 		Ok(())
 	}
 
@@ -171,20 +200,22 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 		};
 		let read_txn = self.database.begin_read()?;
 		let fs_cache_table = read_txn.open_table(MULTI_WATCH_FS_CACHE)?;
-		let path_hash = calculate_path_hash(&canonical);
-		let scoped_key = Self::create_scoped_key(watch_id, path_hash);
-		let key_bytes = serialize(&scoped_key)?;
-		let result = match fs_cache_table.get(key_bytes.as_slice())? {
-			Some(bytes) => Some(deserialize(bytes.value())?),
-			None => None,
-		};
-		Ok(result)
+		let path_hash = (self.path_hash)(&canonical);
+		let collided = slots::collided(&read_txn, watch_id, path_hash)?;
+		let slot = slots::find(
+			&fs_cache_table,
+			watch_id,
+			path_hash,
+			collided,
+			&[path, &canonical],
+		)?;
+		Ok(slot.map(|slot| slot.node))
 	}
 
 	async fn list_directory_for_watch(
 		&mut self, watch_id: &Uuid, parent_path: &Path,
 	) -> DatabaseResult<Vec<FilesystemNode>> {
-		let parent_hash = calculate_path_hash(parent_path);
+		let parent_hash = (self.path_hash)(parent_path);
 		let parent_key = Self::create_scoped_key(watch_id, parent_hash);
 		let parent_key_bytes = serialize(&parent_key)?;
 
@@ -200,7 +231,10 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 			let child_key = child_key_result?;
 			if let Some(node_bytes) = fs_cache_table.get(child_key.value())? {
 				let node: FilesystemNode = deserialize(node_bytes.value())?;
-				nodes.push(node);
+				// A directory whose path hash collides shares the parent key
+				if node.path.parent().is_some_and(|parent| same_hashed_path(parent, parent_path)) {
+					nodes.push(node);
+				}
 			}
 		}
 
@@ -208,12 +242,15 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 	}
 
 	async fn store_watch_metadata(&mut self, metadata: &WatchMetadata) -> DatabaseResult<()> {
-		let write_txn = self.database.begin_write()?;
+		let write_txn = begin_write(&self.database, self.durability)?;
 		{
 			let mut watch_registry = write_txn.open_table(WATCH_REGISTRY)?;
 			let key = metadata.watch_id.as_bytes();
 			watch_registry.insert(key.as_slice(), serialize(metadata)?.as_slice())?;
 		}
+		// The root is never cached as a node; map it so listings of it find the watch
+		let root_hash = (self.path_hash)(&Self::canonical(&metadata.root_path));
+		WatchMappingHelpers::insert_watch_mapping(&write_txn, root_hash, &metadata.watch_id)?;
 		write_txn.commit()?;
 		Ok(())
 	}
@@ -225,25 +262,34 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 		let watch_registry = read_txn.open_table(WATCH_REGISTRY)?;
 		let key = watch_id.as_bytes();
 		let result = match watch_registry.get(key.as_slice())? {
-			Some(bytes) => Some(deserialize(bytes.value())?),
+			Some(bytes) => Some(WatchMetadata::decode(bytes.value()).map_err(|e| {
+				crate::database::error::DatabaseError::Deserialization(e.to_string())
+			})?),
 			None => None,
 		};
 		Ok(result)
 	}
 
 	async fn remove_watch(&mut self, watch_id: &Uuid) -> DatabaseResult<()> {
-		let write_txn = self.database.begin_write()?;
+		let write_txn = begin_write(&self.database, self.durability)?;
 		{
 			let mut watch_registry = write_txn.open_table(WATCH_REGISTRY)?;
 			let key = watch_id.as_bytes();
-			watch_registry.remove(key.as_slice())?;
+			let removed = watch_registry.remove(key.as_slice())?;
+			if let Some(metadata) =
+				removed.and_then(|bytes| WatchMetadata::decode(bytes.value()).ok())
+			{
+				let root_hash = (self.path_hash)(&Self::canonical(&metadata.root_path));
+				let mut path_watches = write_txn.open_multimap_table(PATH_TO_WATCHES)?;
+				path_watches.remove(root_hash.to_le_bytes().as_slice(), key.as_slice())?;
+			}
 		}
 		write_txn.commit()?;
 		Ok(())
 	}
 
 	async fn store_shared_node(&mut self, shared_info: &SharedNodeInfo) -> DatabaseResult<()> {
-		let write_txn = self.database.begin_write()?;
+		let write_txn = begin_write(&self.database, self.durability)?;
 		{
 			let mut shared_table = write_txn.open_table(SHARED_NODES)?;
 			let key = shared_info.node.computed.path_hash.to_le_bytes();
@@ -265,20 +311,35 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 	}
 
 	async fn batch_store_filesystem_nodes(
-		&mut self, watch_id: &Uuid, nodes: &[FilesystemNode], _event_type: &str,
+		&mut self, watch_id: &Uuid, nodes: &[FilesystemNode], event_type: &str,
 	) -> DatabaseResult<()> {
-		let write_txn = self.database.begin_write()?;
+		let write_txn = begin_write(&self.database, self.durability)?;
+		let mut stats = StatsBatch::default();
 		{
 			let mut fs_cache_table = write_txn.open_table(MULTI_WATCH_FS_CACHE)?;
+			let mut hierarchy_table = write_txn.open_multimap_table(MULTI_WATCH_HIERARCHY)?;
 			for node in nodes {
-				let path_hash = calculate_path_hash(&node.path);
-				let scoped_key = Self::create_scoped_key(watch_id, path_hash);
-				let key_bytes = serialize(&scoped_key)?;
-				fs_cache_table.insert(key_bytes.as_slice(), serialize(node)?.as_slice())?;
-				Self::index_path_prefixes(&write_txn, node, watch_id)?;
-				// Optionally update stats here if needed, using _event_type
+				let path_hash = (self.path_hash)(&node.path);
+				let key_bytes =
+					slots::place(&write_txn, &fs_cache_table, watch_id, path_hash, &node.path)?;
+				let mut node = node.clone();
+				node.last_event_type = Some(event_type.to_string());
+				fs_cache_table.insert(key_bytes.as_slice(), serialize(&node)?.as_slice())?;
+				if let Some(parent_hash) = node.computed.parent_hash {
+					let parent_key = serialize(&Self::create_scoped_key(watch_id, parent_hash))?;
+					hierarchy_table.insert(parent_key.as_slice(), key_bytes.as_slice())?;
+				}
+				Self::index_path_prefixes(&write_txn, &node, &key_bytes)?;
+				WatchMappingHelpers::insert_watch_mapping(&write_txn, path_hash, watch_id)?;
+				for wid in WatchMappingHelpers::get_watches_for_path_in(&write_txn, path_hash)? {
+					stats.increment(&wid, path_hash, event_type);
+				}
+				if !self.batched_stats {
+					std::mem::take(&mut stats).apply(&write_txn)?;
+				}
 			}
-		} // fs_cache_table dropped here
+		} // fs_cache_table and hierarchy_table dropped here
+		stats.apply(&write_txn)?;
 		write_txn.commit()?;
 		Ok(())
 	}
@@ -428,11 +489,97 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 		Ok(children)
 	}
 
+	async fn list_directory_unified_page(
+		&mut self, parent_path: &Path, after: Option<&DirectoryCursor>, limit: usize,
+	) -> DatabaseResult<DirectoryPage> {
+		let limit = limit.max(1);
+		let read_txn = self.database.begin_read()?;
+		let hierarchy_table = read_txn.open_multimap_table(MULTI_WATCH_HIERARCHY)?;
+		let fs_cache_table = read_txn.open_table(MULTI_WATCH_FS_CACHE)?;
+		let path_watches = read_txn.open_multimap_table(PATH_TO_WATCHES)?;
+
+		// Watches that cached the parent or have it as their root, in a fixed order to page by
+		let canonical_parent = Self::canonical(parent_path);
+		let parent_key = (self.path_hash)(&canonical_parent).to_le_bytes();
+		let mut watches = Vec::new();
+		for entry in path_watches.get(parent_key.as_slice())? {
+			if let Ok(watch_id) = Uuid::from_slice(entry?.value()) {
+				watches.push(watch_id);
+			}
+		}
+		watches.sort();
+		watches.dedup();
+
+		let parent_hash = calculate_path_hash(parent_path);
+		let mut page = DirectoryPage::default();
+		for (index, watch_id) in watches.iter().enumerate() {
+			let resume_after = match after {
+				Some(cursor) if *watch_id < cursor.watch_id => continue,
+				Some(cursor) if *watch_id == cursor.watch_id => Some(cursor.child_key.as_slice()),
+				_ => None,
+			};
+			let scoped_parent = serialize(&Self::create_scoped_key(watch_id, parent_hash))?;
+			for child_key in hierarchy_table.get(scoped_parent.as_slice())? {
+				let child_key = child_key?;
+				let child_key = child_key.value();
+				if resume_after.is_some_and(|resume_after| child_key <= resume_after) {
+					continue;
+				}
+				let Some(node_bytes) = fs_cache_table.get(child_key)? else {
+					continue;
+				};
+				let node: FilesystemNode = deserialize(node_bytes.value())?;
+				// A directory whose path hash collides shares the parent key
+				if !node.path.parent().is_some_and(|parent| same_hashed_path(parent, parent_path)) {
+					continue;
+				}
+				// Listed with the first watch that has it; the parent is canonical already, so
+				// the child's key path is its name joined to it
+				let home = match node.path.file_name() {
+					Some(name) => (self.path_hash)(&canonical_parent.join(name)),
+					None => (self.path_hash)(&Self::canonical(&node.path)),
+				};
+				let mut listed = false;
+				for earlier in &watches[..index] {
+					let collided = slots::collided(&read_txn, earlier, home)?;
+					if slots::find(&fs_cache_table, earlier, home, collided, &[&node.path])?
+						.is_some()
+					{
+						listed = true;
+						break;
+					}
+				}
+				if listed {
+					continue;
+				}
+				page.nodes.push(node);
+				if page.nodes.len() == limit {
+					page.next_cursor = Some(DirectoryCursor {
+						watch_id: *watch_id,
+						child_key: child_key.to_vec(),
+					});
+					return Ok(page);
+				}
+			}
+		}
+		Ok(page)
+	}
+
 	async fn get_unified_node(&mut self, path: &Path) -> DatabaseResult<Option<FilesystemNode>> {
 		// Prefer shared node if present, else use unified node index for O(1) lookup
 		let path_hash = calculate_path_hash(path);
+		let is_path = |node: &FilesystemNode| {
+			let matches = same_hashed_path(&node.path, path);
+			if !matches {
+				warn!(
+					"Path hash of {:?} collides with cached {:?}; not returning it",
+					path, node.path
+				);
+			}
+			matches
+		};
 		if let Some(shared) = self.get_shared_node(path_hash).await? {
-			return Ok(Some(shared.node));
+			return Ok(Some(shared.node).filter(is_path));
 		}
 		// Use unified node index for O(1) lookup
 		let read_txn = self.database.begin_read()?;
@@ -440,7 +587,7 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 			read_txn.open_table(crate::database::storage::tables::UNIFIED_NODE_INDEX)?;
 		if let Some(node_bytes) = unified_index.get(path_hash.to_le_bytes().as_slice())? {
 			let node: FilesystemNode = utils::deserialize(node_bytes.value())?;
-			return Ok(Some(node));
+			return Ok(Some(node).filter(is_path));
 		}
 		Ok(None)
 	}
@@ -473,12 +620,25 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 			Ok(p) => p,
 			Err(_) => path.to_path_buf(),
 		};
-		let path_hash = calculate_path_hash(&canonical);
-		let scoped_key = Self::create_scoped_key(watch_id, path_hash);
-		let key_bytes = serialize(&scoped_key)?;
-		let mut write_txn = self.database.begin_write()?;
+		let path_hash = (self.path_hash)(&canonical);
+		let collided = {
+			let read_txn = self.database.begin_read()?;
+			slots::collided(&read_txn, watch_id, path_hash)?
+		};
+		let mut write_txn = begin_write(&self.database, self.durability)?;
 		{
 			let mut fs_cache_table = write_txn.open_table(MULTI_WATCH_FS_CACHE)?;
+			let slot = slots::find(
+				&fs_cache_table,
+				watch_id,
+				path_hash,
+				collided,
+				&[path, &canonical],
+			)?;
+			let Some(slots::Slot { key: key_bytes, .. }) = slot else {
+				// Not cached, or only a colliding path is
+				return Ok(());
+			};
 			fs_cache_table.remove(key_bytes.as_slice())?;
 			// Remove from hierarchy table (as child)
 			let mut hierarchy_table = write_txn.open_multimap_table(MULTI_WATCH_HIERARCHY)?;
@@ -524,6 +684,38 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 		Ok(())
 	}
 
+	async fn invalidate_unified_node(&mut self, path: &Path) -> DatabaseResult<()> {
+		let key_hash = (self.path_hash)(&Self::canonical(path));
+		for watch_id in WatchMappingHelpers::get_watches_for_path(&self.database, key_hash)? {
+			self.remove_filesystem_node(&watch_id, path, "Remove").await?;
+		}
+		// The cross-watch entries outlive per-watch removes; only `path`'s own are dropped,
+		// not those of a path its hash collides with
+		let key = calculate_path_hash(path).to_le_bytes();
+		let write_txn = begin_write(&self.database, self.durability)?;
+		{
+			let mut unified_index =
+				write_txn.open_table(crate::database::storage::tables::UNIFIED_NODE_INDEX)?;
+			let cached = match unified_index.get(key.as_slice())? {
+				Some(bytes) => Some(deserialize::<FilesystemNode>(bytes.value())?),
+				None => None,
+			};
+			if cached.is_some_and(|node| same_hashed_path(&node.path, path)) {
+				unified_index.remove(key.as_slice())?;
+			}
+			let mut shared_table = write_txn.open_table(SHARED_NODES)?;
+			let shared = match shared_table.get(key.as_slice())? {
+				Some(bytes) => Some(deserialize::<SharedNodeInfo>(bytes.value())?),
+				None => None,
+			};
+			if shared.is_some_and(|shared| same_hashed_path(&shared.node.path, path)) {
+				shared_table.remove(key.as_slice())?;
+			}
+		}
+		write_txn.commit()?;
+		Ok(())
+	}
+
 	async fn rename_filesystem_node(
 		&mut self, watch_id: &Uuid, old_path: &Path, new_path: &Path, event_type: &str,
 	) -> DatabaseResult<()> {
@@ -536,29 +728,25 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 			Ok(p) => p,
 			Err(_) => new_path.to_path_buf(),
 		};
-		let old_hash = calculate_path_hash(&old_canonical);
-		let new_hash = calculate_path_hash(&new_canonical);
-		let old_scoped_key = Self::create_scoped_key(watch_id, old_hash);
-		let new_scoped_key = Self::create_scoped_key(watch_id, new_hash);
-		let old_key_bytes = serialize(&old_scoped_key)?;
-		let new_key_bytes = serialize(&new_scoped_key)?;
-		let mut write_txn = self.database.begin_write()?;
+		let old_hash = (self.path_hash)(&old_canonical);
+		let new_hash = (self.path_hash)(&new_canonical);
+		let collided = {
+			let read_txn = self.database.begin_read()?;
+			slots::collided(&read_txn, watch_id, old_hash)?
+		};
+		let mut write_txn = begin_write(&self.database, self.durability)?;
 		{
 			let mut fs_cache_table = write_txn.open_table(MULTI_WATCH_FS_CACHE)?;
-			// Scope the immutable borrow to avoid borrow checker issues
-			let node_opt = {
-				if let Some(node_bytes) = fs_cache_table.get(old_key_bytes.as_slice())? {
-					let mut node: FilesystemNode = deserialize(node_bytes.value())?;
-					node.path = new_canonical.clone();
-					node.computed.path_hash = new_hash;
-					Some(node)
-				} else {
-					None
-				}
-			};
-			if let Some(node) = node_opt {
-				fs_cache_table.insert(new_key_bytes.as_slice(), serialize(&node)?.as_slice())?;
+			let old_paths = [old_path, old_canonical.as_path()];
+			let slot = slots::find(&fs_cache_table, watch_id, old_hash, collided, &old_paths)?;
+			if let Some(slots::Slot { key: old_key_bytes, mut node }) = slot {
+				node.path = new_canonical.clone();
+				node.computed.path_hash = new_hash;
+				// Removed first, so the node can keep its slot if both paths share a hash
 				fs_cache_table.remove(old_key_bytes.as_slice())?;
+				let new_key_bytes =
+					slots::place(&write_txn, &fs_cache_table, watch_id, new_hash, &node.path)?;
+				fs_cache_table.insert(new_key_bytes.as_slice(), serialize(&node)?.as_slice())?;
 				// Update hierarchy: remove old parent->child, add new parent->child
 				let mut hierarchy_table = write_txn.open_multimap_table(MULTI_WATCH_HIERARCHY)?;
 				let mut to_remove = Vec::new();
@@ -702,6 +890,35 @@ impl FilesystemCacheStorage for RedbFilesystemCache {
 	) -> DatabaseResult<Vec<FilesystemNode>> {
 		self.list_descendants_modular(path).await
 	}
+
+	async fn record_content_hash(&mut self, hash: &str, path: &Path) -> DatabaseResult<()> {
+		ContentIndexHelpers::record(&self.database, hash, path)
+	}
+
+	async fn path_for_content_hash(&mut self, hash: &str) -> DatabaseResult<Option<PathBuf>> {
+		ContentIndexHelpers::lookup(&self.database, hash)
+	}
+
+	async fn relocate_content_hashes(
+		&mut self, old_path: &Path, new_path: &Path,
+	) -> DatabaseResult<()> {
+		let moved = ContentIndexHelpers::relocate(&self.database, old_path, new_path)?;
+		debug!(
+			"Relocated {} content hash entries {:?} -> {:?}",
+			moved, old_path, new_path
+		);
+		Ok(())
+	}
+
+	async fn record_move_decisions(
+		&mut self, records: &[MoveDecisionRecord],
+	) -> DatabaseResult<()> {
+		DecisionLogHelpers::append(&self.database, self.durability, records)
+	}
+
+	async fn move_decisions(&mut self) -> DatabaseResult<Vec<MoveDecisionRecord>> {
+		DecisionLogHelpers::all(&self.database)
+	}
 }
 
 // End of FilesystemCacheStorage trait impl
@@ -1,11 +1,14 @@
 mod implementation;
 pub use implementation::RedbFilesystemCache;
 
+pub(crate) mod slots;
 pub mod stats;
 pub mod synchronizer;
 pub mod trait_def;
 mod utils;
 
+pub mod content_index;
+pub mod decision_log;
 pub mod hierarchy;
 pub mod indexing;
 pub mod shared;
@@ -0,0 +1,210 @@
+//! Where a path's node lives in `MULTI_WATCH_FS_CACHE`
+//!
+//! Nodes are keyed by watch and a 64-bit hash of the path. Two paths sharing a hash would share
+//! a key, so storing one would silently replace the other and looking one up would return the
+//! other. Every node read through these helpers is therefore checked against the path asked
+//! for, and a path whose home slot (its own hash) holds another path is stored in the first
+//! free of up to [`MAX_PROBES`] alternative slots. The home slot is then recorded in
+//! `COLLIDED_PATH_HASHES`, so only lookups of hashes that have collided probe further; every
+//! other lookup stays a single read. Records are never removed, which only costs the extra
+//! probes once the colliding paths are gone.
+//!
+//! Only the node table is chained. Per-path stats, the path-to-watches mapping and the
+//! unified node index stay keyed by the home hash and are shared by colliding paths; reads of
+//! the unified index are verified and come back empty on a mismatch.
+
+use super::utils::{deserialize, serialize};
+use crate::database::error::{DatabaseError, DatabaseResult};
+use crate::database::storage::tables::COLLIDED_PATH_HASHES;
+use crate::database::types::{same_hashed_path, FilesystemNode, WatchScopedKey};
+use redb::{ReadTransaction, ReadableTable, Table, WriteTransaction};
+use std::path::Path;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Slots tried for one home hash, the home slot included
+pub const MAX_PROBES: u64 = 8;
+
+/// A node found in the table, with the key it is stored under
+pub(crate) struct Slot {
+	pub key: Vec<u8>,
+	pub node: FilesystemNode,
+}
+
+/// Key of probe `probe` for `home`; probe 0 is the home slot
+fn probe_key(watch_id: &Uuid, home: u64, probe: u64) -> DatabaseResult<Vec<u8>> {
+	let path_hash = home ^ probe.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+	serialize(&WatchScopedKey { watch_id: *watch_id, path_hash })
+}
+
+/// Whether `home` has ever collided, as seen by a read transaction
+pub(crate) fn collided(
+	read_txn: &ReadTransaction, watch_id: &Uuid, home: u64,
+) -> DatabaseResult<bool> {
+	let table = match read_txn.open_table(COLLIDED_PATH_HASHES) {
+		Ok(table) => table,
+		Err(redb::TableError::TableDoesNotExist(_)) => return Ok(false),
+		Err(e) => return Err(e.into()),
+	};
+	Ok(table.get(probe_key(watch_id, home, 0)?.as_slice())?.is_some())
+}
+
+/// Whether `home` has ever collided, as seen by a write transaction
+fn collided_in_write(
+	write_txn: &WriteTransaction, watch_id: &Uuid, home: u64,
+) -> DatabaseResult<bool> {
+	let table = write_txn.open_table(COLLIDED_PATH_HASHES)?;
+	let collided = table.get(probe_key(watch_id, home, 0)?.as_slice())?.is_some();
+	Ok(collided)
+}
+
+/// The node stored for a path hashing to `home`; `paths` are the forms of that path a stored
+/// node may carry (e.g. as given and canonicalized)
+pub(crate) fn find(
+	table: &impl ReadableTable<&'static [u8], &'static [u8]>, watch_id: &Uuid, home: u64,
+	collided: bool, paths: &[&Path],
+) -> DatabaseResult<Option<Slot>> {
+	let probes = if collided { MAX_PROBES } else { 1 };
+	for probe in 0..probes {
+		let key = probe_key(watch_id, home, probe)?;
+		let Some(bytes) = table.get(key.as_slice())? else {
+			continue;
+		};
+		let node: FilesystemNode = deserialize(bytes.value())?;
+		if paths.iter().any(|path| same_hashed_path(&node.path, path)) {
+			return Ok(Some(Slot { key, node }));
+		}
+	}
+	Ok(None)
+}
+
+/// Every node stored for paths hashing to `home`, without checking which paths they are
+pub(crate) fn all_at(
+	table: &impl ReadableTable<&'static [u8], &'static [u8]>, watch_id: &Uuid, home: u64,
+	collided: bool,
+) -> DatabaseResult<Vec<FilesystemNode>> {
+	let probes = if collided { MAX_PROBES } else { 1 };
+	let mut nodes = Vec::new();
+	for probe in 0..probes {
+		if let Some(bytes) = table.get(probe_key(watch_id, home, probe)?.as_slice())? {
+			nodes.push(deserialize(bytes.value())?);
+		}
+	}
+	Ok(nodes)
+}
+
+/// Key to store `path`'s node under: where it already is, else its home slot, else the first
+/// free alternative, recording the collision
+pub(crate) fn place(
+	write_txn: &WriteTransaction, table: &Table<&'static [u8], &'static [u8]>, watch_id: &Uuid,
+	home: u64, path: &Path,
+) -> DatabaseResult<Vec<u8>> {
+	let collided = collided_in_write(write_txn, watch_id, home)?;
+	if let Some(slot) = find(table, watch_id, home, collided, &[path])? {
+		return Ok(slot.key);
+	}
+	for probe in 0..MAX_PROBES {
+		let key = probe_key(watch_id, home, probe)?;
+		if table.get(key.as_slice())?.is_some() {
+			continue;
+		}
+		if probe > 0 && !collided {
+			warn!("Path hash {:#x} collides for {:?}; chaining it", home, path);
+			let mut collisions = write_txn.open_table(COLLIDED_PATH_HASHES)?;
+			collisions.insert(probe_key(watch_id, home, 0)?.as_slice(), b"".as_slice())?;
+		}
+		return Ok(key);
+	}
+	Err(DatabaseError::StorageError(format!(
+		"{MAX_PROBES} paths already share the hash {home:#x} of {path:?}"
+	)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::storage::filesystem_cache::trait_def::FilesystemCacheStorage;
+	use crate::database::storage::filesystem_cache::RedbFilesystemCache;
+	use crate::database::types::NodeType;
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn test_colliding_paths_do_not_clobber_each_other() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let database =
+			Arc::new(redb::Database::create(temp_dir.path().join("slots.redb")).unwrap());
+		crate::database::storage::tables::initialize_tables(&database).await.unwrap();
+		// Every path hashes alike
+		let mut cache = RedbFilesystemCache::new(database).with_path_hash(|_| 42);
+		let watch_id = Uuid::new_v4();
+		let root = temp_dir.path().canonicalize().unwrap();
+		let (one, two, three) = (
+			root.join("one.txt"),
+			root.join("two.txt"),
+			root.join("three.txt"),
+		);
+		let node = |path: &Path, contents: &str| {
+			std::fs::write(path, contents).unwrap();
+			FilesystemNode::new(path.to_path_buf(), &std::fs::metadata(path).unwrap())
+		};
+		let size = |node: Option<FilesystemNode>| match node.map(|node| node.node_type) {
+			Some(NodeType::File { size, .. }) => Some(size),
+			_ => None,
+		};
+
+		cache
+			.store_filesystem_node(&watch_id, &node(&one, "1"), "create")
+			.await
+			.unwrap();
+		cache
+			.store_filesystem_node(&watch_id, &node(&two, "22"), "create")
+			.await
+			.unwrap();
+		assert_eq!(
+			size(cache.get_filesystem_node(&watch_id, &one).await.unwrap()),
+			Some(1)
+		);
+		assert_eq!(
+			size(cache.get_filesystem_node(&watch_id, &two).await.unwrap()),
+			Some(2)
+		);
+		assert!(cache.get_filesystem_node(&watch_id, &three).await.unwrap().is_none());
+
+		// Updating one keeps its slot; removing it leaves the other in place
+		cache
+			.store_filesystem_node(&watch_id, &node(&one, "1111"), "modify")
+			.await
+			.unwrap();
+		assert_eq!(
+			size(cache.get_filesystem_node(&watch_id, &one).await.unwrap()),
+			Some(4)
+		);
+		cache.remove_filesystem_node(&watch_id, &one, "remove").await.unwrap();
+		assert!(cache.get_filesystem_node(&watch_id, &one).await.unwrap().is_none());
+		assert_eq!(
+			size(cache.get_filesystem_node(&watch_id, &two).await.unwrap()),
+			Some(2)
+		);
+
+		// The home slot is free again; the second path is still found past it, and renamed
+		// into the home slot
+		cache.rename_filesystem_node(&watch_id, &two, &three, "rename").await.unwrap();
+		assert!(cache.get_filesystem_node(&watch_id, &two).await.unwrap().is_none());
+		assert_eq!(
+			size(cache.get_filesystem_node(&watch_id, &three).await.unwrap()),
+			Some(2)
+		);
+		cache
+			.store_filesystem_node(&watch_id, &node(&one, "1"), "create")
+			.await
+			.unwrap();
+		assert_eq!(
+			size(cache.get_filesystem_node(&watch_id, &one).await.unwrap()),
+			Some(1)
+		);
+		assert_eq!(
+			size(cache.get_filesystem_node(&watch_id, &three).await.unwrap()),
+			Some(2)
+		);
+	}
+}
@@ -1,6 +1,8 @@
 //! Stats and indexing logic for filesystem cache
 //!
 //! Contains per-watch and per-path stats update helpers for insert/remove operations.
+//! Stores of many nodes accumulate their increments in a [`StatsBatch`] and write each counter
+//! once per transaction instead of once per node.
 
 use super::utils::{deserialize, serialize};
 use crate::database::error::DatabaseResult;
@@ -26,38 +28,89 @@ pub struct WatchStats {
 pub fn increment_stats(
 	write_txn: &mut WriteTransaction, watch_id: &Uuid, path_hash: u64, event_type: &str,
 ) -> DatabaseResult<()> {
-	let mut watch_stats_table = write_txn.open_table(WATCH_STATS)?;
-	let mut path_stats_table = write_txn.open_table(PATH_STATS)?;
-	let mut stats_table = write_txn.open_table(STATS_TABLE)?;
+	let mut batch = StatsBatch::default();
+	batch.increment(watch_id, path_hash, event_type);
+	batch.apply(write_txn)
+}
 
-	let watch_key = &watch_id.as_bytes()[..];
-	let mut watch_stats = match watch_stats_table.get(watch_key)? {
-		Some(bytes) => deserialize::<WatchStats>(bytes.value()).unwrap_or_default(),
-		None => WatchStats::default(),
-	};
-	watch_stats.event_count += 1;
-	*watch_stats.per_type_counts.entry(event_type.to_string()).or_insert(0) += 1;
-	watch_stats_table.insert(watch_key, serialize(&watch_stats)?.as_slice())?;
+/// Stats increments accumulated in memory and applied in one go
+///
+/// Applying reads and writes each touched watch, path and event-type counter once, however
+/// many increments it received; the result is the same as calling [`increment_stats`] for
+/// each of them.
+#[derive(Debug, Default)]
+pub struct StatsBatch {
+	watches: HashMap<Uuid, HashMap<String, u64>>,
+	paths: HashMap<u64, HashMap<String, u64>>,
+}
 
-	let path_key_arr = path_hash.to_le_bytes();
-	let path_key = &path_key_arr[..];
-	let mut path_stats = match path_stats_table.get(path_key)? {
-		Some(bytes) => deserialize::<PathStats>(bytes.value()).unwrap_or_default(),
-		None => PathStats::default(),
-	};
-	path_stats.event_count += 1;
-	*path_stats.per_type_counts.entry(event_type.to_string()).or_insert(0) += 1;
-	path_stats_table.insert(path_key, serialize(&path_stats)?.as_slice())?;
+impl StatsBatch {
+	/// Count one `event_type` event for `watch_id` and `path_hash`
+	pub fn increment(&mut self, watch_id: &Uuid, path_hash: u64, event_type: &str) {
+		for counts in [
+			self.watches.entry(*watch_id).or_default(),
+			self.paths.entry(path_hash).or_default(),
+		] {
+			*counts.entry(event_type.to_string()).or_insert(0) += 1;
+		}
+	}
 
-	// Update global stats table for this event type
-	let stat_key = crate::database::types::event_type_stat_key(event_type);
-	let mut count = match stats_table.get(stat_key.as_slice())? {
-		Some(bytes) => u64::from_le_bytes(bytes.value().try_into().unwrap_or([0u8; 8])),
-		None => 0,
-	};
-	count += 1;
-	stats_table.insert(stat_key.as_slice(), count.to_le_bytes().as_slice())?;
-	Ok(())
+	pub fn is_empty(&self) -> bool {
+		self.watches.is_empty()
+	}
+
+	/// Add the accumulated increments to the stored counters
+	pub fn apply(self, write_txn: &WriteTransaction) -> DatabaseResult<()> {
+		if self.is_empty() {
+			return Ok(());
+		}
+		let mut watch_stats_table = write_txn.open_table(WATCH_STATS)?;
+		let mut path_stats_table = write_txn.open_table(PATH_STATS)?;
+		let mut stats_table = write_txn.open_table(STATS_TABLE)?;
+
+		let mut global: HashMap<String, u64> = HashMap::new();
+		for (watch_id, counts) in self.watches {
+			let watch_key = &watch_id.as_bytes()[..];
+			let mut watch_stats = match watch_stats_table.get(watch_key)? {
+				Some(bytes) => deserialize::<WatchStats>(bytes.value()).unwrap_or_default(),
+				None => WatchStats::default(),
+			};
+			for (event_type, count) in counts {
+				watch_stats.event_count += count;
+				*global.entry(event_type.clone()).or_insert(0) += count;
+				*watch_stats.per_type_counts.entry(event_type).or_insert(0) += count;
+			}
+			watch_stats_table.insert(watch_key, serialize(&watch_stats)?.as_slice())?;
+		}
+
+		for (path_hash, counts) in self.paths {
+			let path_key_arr = path_hash.to_le_bytes();
+			let path_key = &path_key_arr[..];
+			let mut path_stats = match path_stats_table.get(path_key)? {
+				Some(bytes) => deserialize::<PathStats>(bytes.value()).unwrap_or_default(),
+				None => PathStats::default(),
+			};
+			for (event_type, count) in counts {
+				path_stats.event_count += count;
+				*path_stats.per_type_counts.entry(event_type).or_insert(0) += count;
+			}
+			path_stats_table.insert(path_key, serialize(&path_stats)?.as_slice())?;
+		}
+
+		// Update global stats table for each event type
+		for (event_type, added) in global {
+			let stat_key = crate::database::types::event_type_stat_key(&event_type);
+			let count = match stats_table.get(stat_key.as_slice())? {
+				Some(bytes) => u64::from_le_bytes(bytes.value().try_into().unwrap_or([0u8; 8])),
+				None => 0,
+			};
+			stats_table.insert(
+				stat_key.as_slice(),
+				(count + added).to_le_bytes().as_slice(),
+			)?;
+		}
+		Ok(())
+	}
 }
 
 /// Decrement event count for watch, path, and global stats (per-type)
@@ -121,3 +174,74 @@ pub fn decrement_stats(
 	stats_table.insert(stat_key.as_slice(), count.to_le_bytes().as_slice())?;
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::storage::filesystem_cache::trait_def::FilesystemCacheStorage;
+	use crate::database::storage::filesystem_cache::RedbFilesystemCache;
+	use crate::database::types::{calculate_path_hash, event_type_stat_key, FilesystemNode};
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn test_batched_stats_match_per_node_stats() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let nodes: Vec<_> = (0..3)
+			.map(|i| {
+				let path = temp_dir.path().join(format!("file_{i}.txt"));
+				std::fs::write(&path, "x").unwrap();
+				FilesystemNode::new(path.clone(), &std::fs::metadata(&path).unwrap())
+			})
+			.collect();
+		let watch_id = Uuid::new_v4();
+
+		let mut counters = Vec::new();
+		for batched in [true, false] {
+			let database = Arc::new(
+				redb::Database::create(temp_dir.path().join(format!("stats-{batched}.redb")))
+					.unwrap(),
+			);
+			crate::database::storage::tables::initialize_tables(&database).await.unwrap();
+			let mut cache = RedbFilesystemCache::new(database.clone()).with_batched_stats(batched);
+			cache.batch_store_filesystem_nodes(&watch_id, &nodes, "scan").await.unwrap();
+			// A single store of a path no watch was mapped to yet counts too
+			let extra = temp_dir.path().join("extra.txt");
+			std::fs::write(&extra, "y").unwrap();
+			let node = FilesystemNode::new(extra.clone(), &std::fs::metadata(&extra).unwrap());
+			cache.store_filesystem_node(&watch_id, &node, "create").await.unwrap();
+
+			let read_txn = database.begin_read().unwrap();
+			let watch_stats: WatchStats = deserialize(
+				read_txn
+					.open_table(WATCH_STATS)
+					.unwrap()
+					.get(&watch_id.as_bytes()[..])
+					.unwrap()
+					.unwrap()
+					.value(),
+			)
+			.unwrap();
+			let path_stats: PathStats = deserialize(
+				read_txn
+					.open_table(PATH_STATS)
+					.unwrap()
+					.get(&calculate_path_hash(&nodes[0].path).to_le_bytes()[..])
+					.unwrap()
+					.unwrap()
+					.value(),
+			)
+			.unwrap();
+			let stats_table = read_txn.open_table(STATS_TABLE).unwrap();
+			let global = stats_table.get(event_type_stat_key("scan").as_slice()).unwrap();
+			let global = u64::from_le_bytes(global.unwrap().value().try_into().unwrap());
+
+			assert_eq!(watch_stats.event_count, 4);
+			assert_eq!(watch_stats.per_type_counts.get("scan"), Some(&3));
+			assert_eq!(watch_stats.per_type_counts.get("create"), Some(&1));
+			assert_eq!(path_stats.event_count, 1);
+			counters.push((watch_stats.per_type_counts, global));
+		}
+		assert_eq!(counters[0], counters[1]);
+		assert_eq!(counters[0].1, 3);
+	}
+}
@@ -7,8 +7,13 @@
 //! - No batching; each event is processed individually.
 //! - No cross-process coordination.
 
+use crate::database::error::DatabaseResult;
+use crate::database::retry::retry_write;
 use crate::database::storage::FilesystemCacheStorage;
+use crate::diagnostics::{DiagnosticsSender, WatcherDiagnostic};
+use crate::error::ErrorRecoveryConfig;
 use crate::events::{EventType, FileSystemEvent};
+use std::future::Future;
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -21,6 +26,26 @@ pub trait FilesystemCacheSynchronizer: Send + Sync {
 /// Default implementation for a synchronizer that updates the cache incrementally.
 pub struct DefaultFilesystemCacheSynchronizer<T: FilesystemCacheStorage> {
 	pub cache: Arc<tokio::sync::Mutex<T>>,
+	/// Retries of cache writes that fail transiently, see `DatabaseConfig::write_retry`;
+	/// `None` tries each write once
+	pub write_retry: Option<ErrorRecoveryConfig>,
+	/// Where what a Write changed in a cached node is reported, as
+	/// `WatcherDiagnostic::NodeChanged`
+	pub diagnostics: Option<DiagnosticsSender>,
+}
+
+impl<T: FilesystemCacheStorage> DefaultFilesystemCacheSynchronizer<T> {
+	/// Run a cache write under `write_retry`; `write` locks the cache for each attempt
+	async fn write<F, Fut>(&self, operation: &str, mut write: F) -> DatabaseResult<()>
+	where
+		F: FnMut() -> Fut + Send,
+		Fut: Future<Output = DatabaseResult<()>> + Send,
+	{
+		match &self.write_retry {
+			Some(retry) => retry_write(retry, operation, write).await,
+			None => write().await,
+		}
+	}
 }
 
 #[async_trait::async_trait]
@@ -30,15 +55,46 @@ impl<T: FilesystemCacheStorage> FilesystemCacheSynchronizer
 	async fn handle_event(&mut self, watch_id: &Uuid, event: &FileSystemEvent) {
 		// This is a pragmatic, non-transactional implementation.
 		// TODO: Add error handling/reporting, and consider transactional semantics.
-		let mut cache = self.cache.lock().await;
+		let cache = &self.cache;
 		let event_type_str = format!("{:?}", event.event_type);
+		let event_type = event_type_str.as_str();
 		match event.event_type {
-			EventType::Create | EventType::Write | EventType::Chmod | EventType::Other(_) => {
+			EventType::Create
+			| EventType::Copy
+			| EventType::Write
+			| EventType::Chmod
+			| EventType::Other(_) => {
 				// Attempt to store or update the node in the cache.
 				if let Some(ref node) = event_to_node(event) {
-					if let Err(e) =
-						cache.store_filesystem_node(watch_id, node, &event_type_str).await
-					{
+					// The previous version is only fetched for writes: it costs an extra read
+					// per event and creates rarely have a meaningful predecessor.
+					if event.event_type == EventType::Write {
+						let previous =
+							cache.lock().await.get_filesystem_node(watch_id, &event.path).await;
+						if let Ok(Some(previous)) = previous {
+							let diff = previous.diff(node);
+							if !diff.is_empty() {
+								tracing::debug!("WRITE {:?}: {}", event.path, diff.summary());
+								crate::diagnostics::emit(
+									self.diagnostics.as_ref(),
+									WatcherDiagnostic::NodeChanged {
+										path: event.path.clone(),
+										diff,
+									},
+								);
+							}
+						}
+					}
+					let stored = self
+						.write("store_filesystem_node", move || async move {
+							cache
+								.lock()
+								.await
+								.store_filesystem_node(watch_id, node, event_type)
+								.await
+						})
+						.await;
+					if let Err(e) = stored {
 						// Log error, but do not panic. In production, consider error metrics.
 						tracing::warn!("Cache update failed: {}", e);
 					}
@@ -47,11 +103,16 @@ impl<T: FilesystemCacheStorage> FilesystemCacheSynchronizer
 					tracing::debug!("No node info for event, skipping cache update: {:?}", event);
 				}
 			}
-			EventType::Remove => {
-				// Remove the node from the cache if possible.
+			EventType::Remove | EventType::MovedToIgnored => {
+				// Remove the node from the cache if possible; an ignored destination is not
+				// tracked.
 				let path = &event.path;
-				if let Err(e) = cache.remove_filesystem_node(watch_id, path, &event_type_str).await
-				{
+				let removed = self
+					.write("remove_filesystem_node", move || async move {
+						cache.lock().await.remove_filesystem_node(watch_id, path, event_type).await
+					})
+					.await;
+				if let Err(e) = removed {
 					tracing::warn!("Cache node removal failed: {}", e);
 				}
 			}
@@ -60,12 +121,37 @@ impl<T: FilesystemCacheStorage> FilesystemCacheSynchronizer
 				if let Some(ref move_data) = event.move_data {
 					let old_path = &move_data.source_path;
 					let new_path = &move_data.destination_path;
-					if let Err(e) = cache
-						.rename_filesystem_node(watch_id, old_path, new_path, &event_type_str)
-						.await
-					{
+					let renamed = self
+						.write("rename_filesystem_node", move || async move {
+							let mut cache = cache.lock().await;
+							cache
+								.rename_filesystem_node(watch_id, old_path, new_path, event_type)
+								.await
+						})
+						.await;
+					if let Err(e) = renamed {
 						tracing::warn!("Cache node rename failed: {}", e);
 					}
+					// The renamed node carries whatever the cache had, which may have drifted;
+					// the destination exists, so refresh it from disk
+					let refreshed = std::fs::symlink_metadata(new_path)
+						.is_ok()
+						.then(|| event_to_node(event))
+						.flatten();
+					if let Some(node) = &refreshed {
+						let stored = self
+							.write("store_filesystem_node", move || async move {
+								cache
+									.lock()
+									.await
+									.store_filesystem_node(watch_id, node, event_type)
+									.await
+							})
+							.await;
+						if let Err(e) = stored {
+							tracing::warn!("Cache node refresh after rename failed: {}", e);
+						}
+					}
 				} else {
 					tracing::debug!(
 						"Missing move_data for rename event, skipping cache rename: {:?}",
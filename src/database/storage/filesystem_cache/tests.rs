@@ -23,7 +23,11 @@ mod tests {
 		let db = redb::Database::create(&db_path).unwrap();
 		let mut cache = super::super::implementation::RedbFilesystemCache::new(Arc::new(db));
 		let cache = Arc::new(tokio::sync::Mutex::new(cache));
-		let mut synchronizer = DefaultFilesystemCacheSynchronizer { cache: cache.clone() };
+		let mut synchronizer = DefaultFilesystemCacheSynchronizer {
+			cache: cache.clone(),
+			write_retry: None,
+			diagnostics: None,
+		};
 		let watch_id = Uuid::new_v4();
 		// Simulate a create event
 		let test_path = temp_dir.path().join("file.txt");
@@ -36,6 +40,13 @@ mod tests {
 			is_directory: false,
 			size: Some(4),
 			move_data: None,
+			timestamp_source: crate::events::TimestampSource::ProcessingTime,
+			ownership: None,
+			snapshot: false,
+			sequence: None,
+			identity_paths: Vec::new(),
+			startup: false,
+			resolved_path: None,
 		};
 		synchronizer.handle_event(&watch_id, &event).await;
 		// Node should exist in cache
@@ -58,7 +69,11 @@ mod tests {
 		let db = redb::Database::create(&db_path).unwrap();
 		let mut cache = super::super::implementation::RedbFilesystemCache::new(Arc::new(db));
 		let cache = Arc::new(tokio::sync::Mutex::new(cache));
-		let mut synchronizer = DefaultFilesystemCacheSynchronizer { cache: cache.clone() };
+		let mut synchronizer = DefaultFilesystemCacheSynchronizer {
+			cache: cache.clone(),
+			write_retry: None,
+			diagnostics: None,
+		};
 		let watch_id = Uuid::new_v4();
 		// Create a file and add to cache
 		let test_path = temp_dir.path().join("file2.txt");
@@ -71,6 +86,13 @@ mod tests {
 			is_directory: false,
 			size: Some(5),
 			move_data: None,
+			timestamp_source: crate::events::TimestampSource::ProcessingTime,
+			ownership: None,
+			snapshot: false,
+			sequence: None,
+			identity_paths: Vec::new(),
+			startup: false,
+			resolved_path: None,
 		};
 		synchronizer.handle_event(&watch_id, &event).await;
 		let node = cache.lock().await.get_filesystem_node(&watch_id, &test_path).await.unwrap();
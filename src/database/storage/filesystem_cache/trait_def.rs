@@ -1,8 +1,11 @@
 //! Trait definitions for filesystem cache storage operations
 
 use crate::database::error::DatabaseResult;
-use crate::database::types::{FilesystemNode, SharedNodeInfo, WatchMetadata};
-use std::path::Path;
+use crate::database::types::{
+	DirectoryCursor, DirectoryPage, FilesystemNode, ListingOrder, MoveDecisionRecord,
+	SharedNodeInfo, WatchMetadata,
+};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 #[async_trait::async_trait]
@@ -22,6 +25,15 @@ pub trait FilesystemCacheStorage: Send + Sync {
 		&mut self, watch_id: &Uuid, parent_path: &Path,
 	) -> DatabaseResult<Vec<FilesystemNode>>;
 
+	/// [`FilesystemCacheStorage::list_directory_for_watch`] in `order`
+	async fn list_directory_for_watch_sorted(
+		&mut self, watch_id: &Uuid, parent_path: &Path, order: ListingOrder,
+	) -> DatabaseResult<Vec<FilesystemNode>> {
+		let mut nodes = self.list_directory_for_watch(watch_id, parent_path).await?;
+		order.sort(&mut nodes);
+		Ok(nodes)
+	}
+
 	/// Store watch metadata
 	async fn store_watch_metadata(&mut self, metadata: &WatchMetadata) -> DatabaseResult<()>;
 
@@ -60,10 +72,36 @@ pub trait FilesystemCacheStorage: Send + Sync {
 	// === Phase 3: Unified and Hierarchical Queries ===
 
 	/// List directory contents across all watches (unified view).
+	///
+	/// Consults every registered watch and collects all children at once; for wide
+	/// directories or many watches see [`FilesystemCacheStorage::list_directory_unified_page`].
 	async fn list_directory_unified(
 		&mut self, parent_path: &std::path::Path,
 	) -> DatabaseResult<Vec<FilesystemNode>>;
 
+	/// [`FilesystemCacheStorage::list_directory_unified`] up to `limit` children at a time
+	///
+	/// Pass `None` for the first page and the returned `next_cursor` for each following one.
+	/// Only watches the path-to-watches index maps to `parent_path` are consulted, rather than
+	/// every registered watch; a child cached by several of them is listed once. Backends
+	/// without such an index return the whole listing as one page. A `limit` of 0 is treated
+	/// as 1.
+	async fn list_directory_unified_page(
+		&mut self, parent_path: &Path, _after: Option<&DirectoryCursor>, _limit: usize,
+	) -> DatabaseResult<DirectoryPage> {
+		let nodes = self.list_directory_unified(parent_path).await?;
+		Ok(DirectoryPage { nodes, next_cursor: None })
+	}
+
+	/// [`FilesystemCacheStorage::list_directory_unified`] in `order`
+	async fn list_directory_unified_sorted(
+		&mut self, parent_path: &Path, order: ListingOrder,
+	) -> DatabaseResult<Vec<FilesystemNode>> {
+		let mut nodes = self.list_directory_unified(parent_path).await?;
+		order.sort(&mut nodes);
+		Ok(nodes)
+	}
+
 	/// Get a unified node view for a given path (across all watches).
 	async fn get_unified_node(
 		&mut self, path: &std::path::Path,
@@ -96,6 +134,45 @@ pub trait FilesystemCacheStorage: Send + Sync {
 	async fn rename_filesystem_node(
 		&mut self, watch_id: &Uuid, old_path: &Path, new_path: &Path, event_type: &str,
 	) -> DatabaseResult<()>;
+
+	/// Drop the node cached for `path` by every watch, along with the view
+	/// [`Self::get_unified_node`] returns, e.g. once it is known to be stale
+	async fn invalidate_unified_node(&mut self, path: &Path) -> DatabaseResult<()>;
+
+	// === Content-hash index (see `MoveDetectorConfig::content_hash_index`) ===
+	// Shared by every watch using the same storage. The defaults keep no index, which turns
+	// the feature off for backends that do not implement it.
+
+	/// Record `path` as the latest location of content hashing to `hash`
+	async fn record_content_hash(&mut self, _hash: &str, _path: &Path) -> DatabaseResult<()> {
+		Ok(())
+	}
+
+	/// Latest path recorded for `hash`; the file may since have been removed or changed
+	async fn path_for_content_hash(&mut self, _hash: &str) -> DatabaseResult<Option<PathBuf>> {
+		Ok(None)
+	}
+
+	/// Follow a rename: entries for `old_path` and every path below it move under `new_path`
+	async fn relocate_content_hashes(
+		&mut self, _old_path: &Path, _new_path: &Path,
+	) -> DatabaseResult<()> {
+		Ok(())
+	}
+
+	// === Move detector decisions (see `MoveDetectorConfig::record_decisions`) ===
+
+	/// Append decision records, dropping the oldest beyond the log's capacity
+	async fn record_move_decisions(
+		&mut self, _records: &[MoveDecisionRecord],
+	) -> DatabaseResult<()> {
+		Ok(())
+	}
+
+	/// Every retained decision record, oldest first
+	async fn move_decisions(&mut self) -> DatabaseResult<Vec<MoveDecisionRecord>> {
+		Ok(Vec::new())
+	}
 }
 
 /// Cache statistics for monitoring
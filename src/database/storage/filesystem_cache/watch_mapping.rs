@@ -8,7 +8,7 @@
 
 use crate::database::error::DatabaseResult;
 use redb::Database;
-use redb::WriteTransaction;
+use redb::{ReadableMultimapTable, WriteTransaction};
 use uuid::Uuid;
 
 pub struct WatchMappingHelpers;
@@ -45,5 +45,21 @@ impl WatchMappingHelpers {
 		}
 		Ok(watches)
 	}
+
+	/// Enumerate all watches for a given path hash as `write_txn` sees them, including mappings
+	/// it inserted itself
+	pub fn get_watches_for_path_in(
+		write_txn: &WriteTransaction, path_hash: u64,
+	) -> DatabaseResult<Vec<Uuid>> {
+		let path_watches_table =
+			write_txn.open_multimap_table(crate::database::storage::tables::PATH_TO_WATCHES)?;
+		let mut watches = Vec::new();
+		for entry in path_watches_table.get(path_hash.to_le_bytes().as_slice())? {
+			if let Ok(uuid) = Uuid::from_slice(entry?.value()) {
+				watches.push(uuid);
+			}
+		}
+		Ok(watches)
+	}
 	// TODO: Add more helpers as needed (removal, lookup, etc.)
 }
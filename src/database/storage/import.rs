@@ -0,0 +1,273 @@
+//! Merging one database file into another
+//!
+//! Used when consolidating watchers, e.g. moving from one database per project to a shared one.
+//! Events are appended to the target log and re-sequenced; metadata and filesystem cache tables
+//! are merged key by key with the target winning on conflicts. Everything is written in a single
+//! transaction, so a failed import leaves the target untouched.
+//!
+//! Limitations:
+//! - The source file must not be open anywhere else; redb refuses to open it twice.
+//! - Per-watch and per-path stats (`watch_stats`, `path_stats`) are not merged. Rebuild them with
+//!   `RedbFilesystemCache::repair_stats_counters` if they matter to you.
+//! - `WatchMetadata` of a watch id present in both files keeps the target's values (including
+//!   `node_count`), even though the nodes themselves are merged.
+//! - Pending watch transactions are not imported; they only make sense for the process that
+//!   opened them. Neither are the delivery log and its acknowledgements
+//!   (`WatcherConfig::delivery_log`), whose sequences are only meaningful in their own file.
+//! - The latest-state table (`DatabaseConfig::track_current_state`) is not merged; the target's
+//!   entries only change with events stored after the import.
+//! - Both files must store records in the same `SerializationFormat`; metadata records are copied
+//!   as raw bytes, so a mismatch is refused up front.
+
+use super::codec::{RecordCodec, SerializationFormat};
+use super::tables::{
+	DEPTH_INDEX_TABLE, EVENTS_LOG_TABLE, EXTENSION_INDEX, FS_CACHE_TABLE, HIERARCHY_TABLE,
+	METADATA_COUNT_KEY, METADATA_TABLE, MULTI_WATCH_FS_CACHE, MULTI_WATCH_HIERARCHY,
+	PATH_PREFIX_TABLE, PATH_TO_WATCHES, SHARED_NODES, STATS_TABLE, UNIFIED_NODE_INDEX,
+	WATCH_REGISTRY,
+};
+use crate::database::error::{DatabaseError, DatabaseResult};
+use crate::database::types::{EventRecord, StorageKey};
+use redb::{
+	Database, MultimapTableDefinition, ReadTransaction, ReadableMultimapTable, ReadableTable,
+	TableDefinition, TableError, TableHandle, WriteTransaction,
+};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Outcome of [`import_database`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+	/// Events appended to the target log
+	pub events_imported: usize,
+	/// Imported events whose sequence number was already taken in the target and had to change.
+	/// Relative order among imported events is preserved; they all sort after existing events.
+	pub events_resequenced: usize,
+	/// Events skipped because an event with the same id is already in the target, e.g. when the
+	/// same file is imported twice
+	pub events_skipped: usize,
+	/// Metadata records added to the target
+	pub metadata_imported: usize,
+	/// Filesystem cache and index entries added to the target
+	pub cache_entries_imported: usize,
+	/// Watch ids registered in both databases; their cache nodes were merged
+	pub shared_watch_ids: Vec<Uuid>,
+	/// Keys present in both databases with different values; the target's value was kept
+	pub conflicts: Vec<ImportConflict>,
+}
+
+impl ImportReport {
+	/// Total number of records written to the target
+	pub fn records_imported(&self) -> usize {
+		self.events_imported + self.metadata_imported + self.cache_entries_imported
+	}
+}
+
+/// A source record that was not imported because the target already had a different value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportConflict {
+	/// Name of the redb table
+	pub table: String,
+	/// Raw table key; usually a path hash or a watch-scoped key
+	pub key: Vec<u8>,
+}
+
+/// Merge every record of the database at `source_path` into `target`.
+pub fn import_database(target: &Arc<Database>, source_path: &Path) -> DatabaseResult<ImportReport> {
+	let source = Database::open(source_path)?;
+	let source_txn = source.begin_read()?;
+	let mut report = ImportReport::default();
+
+	let write_txn = target.begin_write()?;
+	let format = super::codec::read_format(&source_txn)?;
+	let target_format = super::codec::write_format(&write_txn)?;
+	if format != target_format {
+		return Err(DatabaseError::InvalidConfiguration(format!(
+			"cannot import {format:?} records into a {target_format:?} database"
+		)));
+	}
+	import_events(&source_txn, &write_txn, format, &mut report)?;
+
+	report.metadata_imported = merge_table(
+		&source_txn,
+		&write_txn,
+		METADATA_TABLE,
+		&mut report.conflicts,
+	)?;
+	if report.metadata_imported > 0 {
+		add_to_metadata_count(&write_txn, report.metadata_imported as u64)?;
+	}
+
+	report.shared_watch_ids = shared_watch_ids(&source_txn, &write_txn)?;
+	// Registry entries differ for every shared watch (scan times, node counts), so they are
+	// reported through `shared_watch_ids` rather than as conflicts
+	let mut registry_conflicts = Vec::new();
+	report.cache_entries_imported += merge_table(
+		&source_txn,
+		&write_txn,
+		WATCH_REGISTRY,
+		&mut registry_conflicts,
+	)?;
+
+	for table in [FS_CACHE_TABLE, MULTI_WATCH_FS_CACHE, SHARED_NODES, UNIFIED_NODE_INDEX] {
+		report.cache_entries_imported +=
+			merge_table(&source_txn, &write_txn, table, &mut report.conflicts)?;
+	}
+	// The event prefix index is not copied: `import_events` indexes every record it appends
+	// under the sequence number the target assigned
+	for table in [
+		HIERARCHY_TABLE,
+		PATH_PREFIX_TABLE,
+		DEPTH_INDEX_TABLE,
+		MULTI_WATCH_HIERARCHY,
+		PATH_TO_WATCHES,
+		EXTENSION_INDEX,
+	] {
+		report.cache_entries_imported += merge_multimap(&source_txn, &write_txn, table)?;
+	}
+
+	write_txn.commit()?;
+	Ok(report)
+}
+
+/// Append the source's events in their original order, skipping ids the target already has
+fn import_events(
+	source: &ReadTransaction, target: &WriteTransaction, format: SerializationFormat,
+	report: &mut ImportReport,
+) -> DatabaseResult<()> {
+	let mut records = Vec::new();
+	match source.open_multimap_table(EVENTS_LOG_TABLE) {
+		Ok(events_log) => {
+			for entry in events_log.iter()? {
+				let (_key, values) = entry?;
+				for value in values {
+					records.push(format.decode::<EventRecord>(value?.value())?);
+				}
+			}
+		}
+		Err(TableError::TableDoesNotExist(_)) => return Ok(()),
+		Err(e) => return Err(e.into()),
+	}
+	records.sort_by_key(|record| record.sequence_number);
+
+	// An event with the same id has the same path, so only the target events under each
+	// imported path are read, once per path
+	let mut existing_ids: HashMap<Vec<u8>, HashSet<Uuid>> = HashMap::new();
+	for record in records {
+		let key = StorageKey::path_hash(&record.path).to_bytes();
+		let ids = match existing_ids.entry(key) {
+			Entry::Occupied(entry) => entry.into_mut(),
+			Entry::Vacant(entry) => {
+				let events_log = target.open_multimap_table(EVENTS_LOG_TABLE)?;
+				let mut ids = HashSet::new();
+				for value in events_log.get(entry.key().as_slice())? {
+					ids.insert(format.decode::<EventRecord>(value?.value())?.event_id);
+				}
+				entry.insert(ids)
+			}
+		};
+		if !ids.insert(record.event_id) {
+			report.events_skipped += 1;
+			continue;
+		}
+		let assigned = super::event_storage::append_record(target, &record)?;
+		if assigned != record.sequence_number {
+			report.events_resequenced += 1;
+		}
+		report.events_imported += 1;
+	}
+	Ok(())
+}
+
+/// Copy keys missing from the target; returns how many were copied
+fn merge_table(
+	source: &ReadTransaction, target: &WriteTransaction, definition: TableDefinition<&[u8], &[u8]>,
+	conflicts: &mut Vec<ImportConflict>,
+) -> DatabaseResult<usize> {
+	let source_table = match source.open_table(definition) {
+		Ok(table) => table,
+		Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+		Err(e) => return Err(e.into()),
+	};
+	let mut target_table = target.open_table(definition)?;
+	let mut imported = 0;
+	for entry in source_table.iter()? {
+		let (key, value) = entry?;
+		let existing = target_table.get(key.value())?.map(|v| v.value() == value.value());
+		match existing {
+			None => {
+				target_table.insert(key.value(), value.value())?;
+				imported += 1;
+			}
+			Some(true) => {}
+			Some(false) => conflicts.push(ImportConflict {
+				table: definition.name().to_string(),
+				key: key.value().to_vec(),
+			}),
+		}
+	}
+	Ok(imported)
+}
+
+/// Union of the source and target values per key; returns how many pairs were new
+fn merge_multimap(
+	source: &ReadTransaction, target: &WriteTransaction,
+	definition: MultimapTableDefinition<&[u8], &[u8]>,
+) -> DatabaseResult<usize> {
+	let source_table = match source.open_multimap_table(definition) {
+		Ok(table) => table,
+		Err(TableError::TableDoesNotExist(_)) => return Ok(0),
+		Err(e) => return Err(e.into()),
+	};
+	let mut target_table = target.open_multimap_table(definition)?;
+	let mut imported = 0;
+	for entry in source_table.iter()? {
+		let (key, values) = entry?;
+		for value in values {
+			if !target_table.insert(key.value(), value?.value())? {
+				imported += 1;
+			}
+		}
+	}
+	Ok(imported)
+}
+
+fn shared_watch_ids(
+	source: &ReadTransaction, target: &WriteTransaction,
+) -> DatabaseResult<Vec<Uuid>> {
+	let source_registry = match source.open_table(WATCH_REGISTRY) {
+		Ok(table) => table,
+		Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+		Err(e) => return Err(e.into()),
+	};
+	let target_registry = target.open_table(WATCH_REGISTRY)?;
+	let mut shared = Vec::new();
+	for entry in source_registry.iter()? {
+		let (key, _value) = entry?;
+		if target_registry.get(key.value())?.is_some() {
+			if let Ok(id) = Uuid::from_slice(key.value()) {
+				shared.push(id);
+			}
+		}
+	}
+	Ok(shared)
+}
+
+/// Bump the persistent metadata counter, if there is one. A missing counter is rebuilt by a
+/// full scan on the next stats query, which then already includes the imported records.
+fn add_to_metadata_count(target: &WriteTransaction, added: u64) -> DatabaseResult<()> {
+	let mut stats_table = target.open_table(STATS_TABLE)?;
+	let current = stats_table
+		.get(METADATA_COUNT_KEY)?
+		.map(|v| u64::from_le_bytes(v.value().try_into().unwrap_or([0u8; 8])));
+	if let Some(current) = current {
+		stats_table.insert(
+			METADATA_COUNT_KEY,
+			&current.saturating_add(added).to_le_bytes()[..],
+		)?;
+	}
+	Ok(())
+}
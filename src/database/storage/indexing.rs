@@ -6,6 +6,7 @@
 //! **DEPRECATED**: The canonical implementation is now in `indexing.rs`. This file
 //! exists only for backward compatibility and will be removed in a future release.
 
+use super::codec::RecordCodec;
 use crate::database::{error::DatabaseResult, types::EventRecord};
 use chrono::{DateTime, Utc};
 use redb::Database;
@@ -78,6 +79,7 @@ pub async fn find_events_by_time_range(
 	use redb::ReadableMultimapTable;
 	let read_txn = database.begin_read()?;
 	let events_log = read_txn.open_multimap_table(EVENTS_LOG_TABLE)?;
+	let format = super::codec::read_format(&read_txn)?;
 	let mut result = Vec::new();
 	for entry in events_log.iter()? {
 		let (_key_guard, multimap_value) = entry?;
@@ -87,7 +89,7 @@ pub async fn find_events_by_time_range(
 				Err(_) => continue, // Skip corrupt
 			};
 			let value_bytes = value_guard.value();
-			let record: EventRecord = match bincode::deserialize(value_bytes) {
+			let record: EventRecord = match format.decode(value_bytes) {
 				Ok(r) => r,
 				Err(_) => continue, // Skip corrupt
 			};
@@ -3,8 +3,14 @@
 //! This module handles database maintenance, statistics collection,
 //! and health monitoring operations.
 
-use crate::database::{config::DatabaseConfig, error::DatabaseResult, types::DatabaseStats};
+use super::codec::RecordCodec;
+use crate::database::{
+	config::DatabaseConfig,
+	error::{DatabaseError, DatabaseResult},
+	types::DatabaseStats,
+};
 use redb::{Database, ReadableMultimapTable, ReadableTable};
+use std::path::Path;
 use std::sync::Arc;
 
 /// Trait for maintenance and statistics operations
@@ -81,11 +87,11 @@ impl MaintenanceStorage for MaintenanceImpl {
 	}
 
 	async fn perform_maintenance(&mut self) -> DatabaseResult<()> {
-		use std::time::{Duration, SystemTime};
+		use std::time::Duration;
 		use tracing::info;
 		// Cleanup expired records (default retention: 30 days)
 		let retention = Duration::from_secs(30 * 24 * 60 * 60);
-		let before = SystemTime::now() - retention;
+		let before = crate::database::clock::now_system() - retention;
 		let cleaned = cleanup_expired_events(&self.database, before).await?;
 		info!("Maintenance: cleaned up {} expired events", cleaned);
 		// Update statistics (repair counters if needed)
@@ -108,6 +114,10 @@ impl MaintenanceStorage for MaintenanceImpl {
 }
 
 /// Clean up expired events using the provided database
+///
+/// Events under the `root_path` of a watch with `WatchMetadata::event_retention` are removed
+/// once older than that retention instead; under several such watches, the longest applies.
+/// Everything else is removed when its `expires_at` is before `before`.
 pub async fn cleanup_expired_events(
 	database: &Arc<Database>, before: std::time::SystemTime,
 ) -> DatabaseResult<usize> {
@@ -116,42 +126,73 @@ pub async fn cleanup_expired_events(
 
 	let mut removed = 0usize;
 	let write_txn = database.begin_write()?;
+	let format = super::codec::write_format(&write_txn)?;
 	{
+		let watch_cutoffs = watch_retention_cutoffs(&write_txn, crate::database::clock::now())?;
 		let mut events_log =
 			write_txn.open_multimap_table(crate::database::storage::tables::EVENTS_LOG_TABLE)?;
 		let mut time_index =
 			write_txn.open_multimap_table(crate::database::storage::tables::TIME_INDEX_TABLE)?;
 		let bucket_size_seconds = 3600; // Must match store_event
 		let before_dt: DateTime<Utc> = before.into();
-		let before_bucket = crate::database::types::StorageKey::TimeBucket(
-			before_dt.timestamp() / bucket_size_seconds * bucket_size_seconds,
-		);
+		// A watch with a shorter retention needs newer buckets scanned
+		let scan_until = watch_cutoffs.iter().map(|(_, cutoff)| *cutoff).fold(before_dt, Ord::max);
+		let last_bucket = scan_until.timestamp() / bucket_size_seconds * bucket_size_seconds;
 		let mut to_remove = Vec::new();
-		// Iterate all time buckets up to and including before_bucket
+		// Iterate all time buckets up to and including last_bucket
 		for bucket in time_index.iter()? {
 			let (bucket_guard, multimap_value) = bucket?;
 			let bucket_bytes = bucket_guard.value();
 			let bucket_key = bucket_bytes;
-			// Only process buckets <= before_bucket
-			if bucket_bytes <= &before_bucket.to_bytes()[..] {
+			// Only process buckets up to last_bucket; the keys are little-endian, so compare the
+			// decoded start rather than the bytes
+			let bucket_start = match crate::database::types::StorageKey::from_bytes(bucket_bytes) {
+				Ok(crate::database::types::StorageKey::TimeBucket(start)) => start,
+				_ => continue,
+			};
+			if bucket_start <= last_bucket {
 				for value_guard in multimap_value.flatten() {
 					let value = value_guard.value();
-					if let Ok(event) = bincode::deserialize::<EventRecord>(value) {
-						if event.expires_at < before_dt {
+					if let Ok(event) = format.decode::<EventRecord>(value) {
+						let expired = watch_cutoffs
+							.iter()
+							.filter(|(root, _)| event.path.starts_with(root))
+							.map(|(_, cutoff)| *cutoff)
+							.min()
+							.map_or(event.expires_at < before_dt, |cutoff| {
+								event.timestamp < cutoff
+							});
+						if expired {
 							// Remove from both time index and event log
 							let path_hash_key =
 								crate::database::types::StorageKey::path_hash(&event.path)
 									.to_bytes();
-							to_remove.push((bucket_key.to_vec(), path_hash_key, value.to_vec()));
+							to_remove.push((
+								bucket_key.to_vec(),
+								path_hash_key,
+								value.to_vec(),
+								event.path,
+								event.sequence_number,
+							));
 						}
 					}
 				}
 			}
 		}
-		for (bucket_key, path_hash_key, value) in to_remove {
+		let mut prefix_index =
+			write_txn.open_multimap_table(crate::database::storage::tables::INDEXES_TABLE)?;
+		let mut sequence_index =
+			write_txn.open_table(crate::database::storage::tables::EVENT_SEQUENCE_INDEX)?;
+		for (bucket_key, path_hash_key, value, path, sequence_number) in to_remove {
 			if time_index.remove(bucket_key.as_slice(), value.as_slice())? {
-				// Remove from event log as well
+				// Remove from event log, prefix and sequence indexes as well
 				let _ = events_log.remove(path_hash_key.as_slice(), value.as_slice());
+				if let Some(prefix) = crate::database::query::prefix_index_key(&path) {
+					prefix_index.remove(prefix.to_bytes().as_slice(), value.as_slice())?;
+				}
+				let sequence_key =
+					super::event_storage::sequence_index_key(&path_hash_key, sequence_number);
+				sequence_index.remove(sequence_key.as_slice())?;
 				removed += 1;
 			}
 		}
@@ -160,6 +201,30 @@ pub async fn cleanup_expired_events(
 	Ok(removed)
 }
 
+/// Root path and oldest timestamp to keep of every watch with its own event retention
+fn watch_retention_cutoffs(
+	write_txn: &redb::WriteTransaction, now: chrono::DateTime<chrono::Utc>,
+) -> DatabaseResult<Vec<(std::path::PathBuf, chrono::DateTime<chrono::Utc>)>> {
+	use crate::database::types::WatchMetadata;
+	let registry = write_txn.open_table(crate::database::storage::tables::WATCH_REGISTRY)?;
+	let mut cutoffs = Vec::new();
+	for entry in registry.iter()? {
+		let (_, value) = entry?;
+		let Ok(watch) = WatchMetadata::decode(value.value()) else {
+			continue;
+		};
+		if let Some(retention) = watch.event_retention {
+			// Too long to represent is as good as forever
+			let cutoff = chrono::Duration::from_std(retention)
+				.ok()
+				.and_then(|retention| now.checked_sub_signed(retention))
+				.unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+			cutoffs.push((watch.root_path, cutoff));
+		}
+	}
+	Ok(cutoffs)
+}
+
 /// Get database statistics using the provided database
 ///
 /// LIMITATIONS & TODOs (read before using in production):
@@ -287,18 +352,82 @@ pub async fn get_database_stats(database: &Arc<Database>) -> DatabaseResult<Data
 	})
 }
 
-/// Compact database using the provided database
-pub async fn compact_database(_database: &Arc<Database>) -> DatabaseResult<()> {
-	// TODO: Implement database compaction
-	// For now, return success - this would be implemented properly in Phase 1.2
+/// Share of the database file not holding live data, from 0.0 (tight) to 1.0.
+///
+/// Computed as `1 - (stored + metadata bytes) / file size` from redb's own accounting. Deleted
+/// records leave free pages behind that redb reuses but never returns to the OS, so this rises
+/// after large deletes and drops after [`compact_database`]. redb preallocates its regions, so
+/// a fresh or nearly empty file reads high as well; there is little to reclaim from it, which
+/// [`reclaimable_bytes`] tells apart.
+pub fn fragmentation_ratio(database: &Database, file_path: &Path) -> DatabaseResult<f64> {
+	let (file_size, live) = space_usage(database, file_path)?;
+	if file_size == 0 {
+		return Ok(0.0);
+	}
+	Ok((1.0 - live as f64 / file_size as f64).clamp(0.0, 1.0))
+}
+
+/// Bytes of the database file not holding live data, i.e. roughly what compaction would
+/// return to the OS at most
+pub fn reclaimable_bytes(database: &Database, file_path: &Path) -> DatabaseResult<u64> {
+	let (file_size, live) = space_usage(database, file_path)?;
+	Ok(file_size.saturating_sub(live))
+}
+
+/// File size and live (stored + metadata) bytes of every table, read in a read transaction
+/// so measuring never waits for or blocks writers
+fn space_usage(database: &Database, file_path: &Path) -> DatabaseResult<(u64, u64)> {
+	use redb::ReadableTableMetadata;
+
+	let file_size = std::fs::metadata(file_path)?.len();
+	let read_txn = database.begin_read()?;
+	let mut live = 0;
+	for handle in read_txn.list_tables()? {
+		let stats = read_txn.open_untyped_table(handle)?.stats()?;
+		live += stats.stored_bytes() + stats.metadata_bytes();
+	}
+	for handle in read_txn.list_multimap_tables()? {
+		let stats = read_txn.open_untyped_multimap_table(handle)?.stats()?;
+		live += stats.stored_bytes() + stats.metadata_bytes();
+	}
+	Ok((file_size, live))
+}
+
+/// Compact the database file, returning free pages to the OS.
+///
+/// redb needs exclusive access for this, so it fails while anything else holds the database:
+/// filesystem caches from `DatabaseAdapter::get_filesystem_cache` (a running watcher keeps
+/// two), handles from `get_raw_database`, or an open read transaction. Slow on large files;
+/// writers are blocked for the duration.
+pub async fn compact_database(database: &mut Arc<Database>) -> DatabaseResult<()> {
+	let database = Arc::get_mut(database).ok_or_else(|| {
+		DatabaseError::StorageError(
+			"cannot compact while other handles to the database are open".to_string(),
+		)
+	})?;
+	database
+		.compact()
+		.map_err(|e| DatabaseError::StorageError(format!("compaction failed: {e}")))?;
 	Ok(())
 }
 
 /// Repair the time index by scanning the event log and rebuilding all time buckets
+///
+/// The path-prefix and sequence indexes are rebuilt along with it, which also lets prefix
+/// queries and paging use them on files with events stored before they existed.
 pub async fn repair_time_index(database: &Arc<Database>) -> DatabaseResult<()> {
+	use crate::database::storage::tables::{
+		EVENT_PREFIX_INDEXED_KEY, EVENT_SEQUENCE_INDEX, EVENT_SEQUENCE_INDEXED_KEY, INDEXES_TABLE,
+		STATS_TABLE,
+	};
 	use crate::database::types::EventRecord;
 	let write_txn = database.begin_write()?;
+	let format = super::codec::write_format(&write_txn)?;
 	{
+		write_txn.delete_multimap_table(INDEXES_TABLE)?;
+		let mut prefix_index = write_txn.open_multimap_table(INDEXES_TABLE)?;
+		write_txn.delete_table(EVENT_SEQUENCE_INDEX)?;
+		let mut sequence_index = write_txn.open_table(EVENT_SEQUENCE_INDEX)?;
 		let mut time_index =
 			write_txn.open_multimap_table(crate::database::storage::tables::TIME_INDEX_TABLE)?;
 		// Remove all entries from the time index manually
@@ -318,18 +447,29 @@ pub async fn repair_time_index(database: &Arc<Database>) -> DatabaseResult<()> {
 			write_txn.open_multimap_table(crate::database::storage::tables::EVENTS_LOG_TABLE)?;
 		let bucket_size_seconds = 3600;
 		for entry in events_log.iter()? {
-			let (_key_guard, multimap_value) = entry?;
+			let (key_guard, multimap_value) = entry?;
 			for value_guard in multimap_value.flatten() {
 				let value = value_guard.value();
-				if let Ok(event) = bincode::deserialize::<EventRecord>(value) {
+				if let Ok(event) = format.decode::<EventRecord>(value) {
+					let sequence_key = super::event_storage::sequence_index_key(
+						key_guard.value(),
+						event.sequence_number,
+					);
+					sequence_index.insert(sequence_key.as_slice(), value)?;
 					let time_bucket = crate::database::types::StorageKey::time_bucket(
 						event.timestamp,
 						bucket_size_seconds,
 					);
 					time_index.insert(time_bucket.to_bytes().as_slice(), value)?;
+					if let Some(prefix) = crate::database::query::prefix_index_key(&event.path) {
+						prefix_index.insert(prefix.to_bytes().as_slice(), value)?;
+					}
 				}
 			}
 		}
+		let mut stats_table = write_txn.open_table(STATS_TABLE)?;
+		stats_table.insert(EVENT_PREFIX_INDEXED_KEY, &[][..])?;
+		stats_table.insert(EVENT_SEQUENCE_INDEXED_KEY, &[][..])?;
 	}
 	write_txn.commit()?;
 	Ok(())
@@ -3,7 +3,8 @@
 //! This module handles storage and retrieval of file metadata records.
 //! Provides efficient path-based lookups and prefix-based queries.
 
-use crate::database::{error::DatabaseResult, types::MetadataRecord};
+use super::codec::{RecordCodec, SerializationFormat};
+use crate::database::{config::Durability, error::DatabaseResult, types::MetadataRecord};
 use redb::{Database, ReadableTable};
 use std::{path::Path, sync::Arc};
 
@@ -50,15 +51,17 @@ impl MetadataStorageImpl {
 	}
 
 	/// Serialize record to bytes
-	fn serialize_record(record: &MetadataRecord) -> DatabaseResult<Vec<u8>> {
-		bincode::serialize(record)
-			.map_err(|e| crate::database::error::DatabaseError::Serialization(e.to_string()))
+	fn serialize_record(
+		format: SerializationFormat, record: &MetadataRecord,
+	) -> DatabaseResult<Vec<u8>> {
+		format.encode(record)
 	}
 
 	/// Deserialize bytes to record
-	fn deserialize_record(bytes: &[u8]) -> DatabaseResult<MetadataRecord> {
-		bincode::deserialize(bytes)
-			.map_err(|e| crate::database::error::DatabaseError::Deserialization(e.to_string()))
+	fn deserialize_record(
+		format: SerializationFormat, bytes: &[u8],
+	) -> DatabaseResult<MetadataRecord> {
+		format.decode(bytes)
 	}
 }
 
@@ -71,7 +74,8 @@ impl MetadataStorage for MetadataStorageImpl {
 			let mut stats_table = write_txn.open_table(super::tables::STATS_TABLE)?;
 			let path_hash = Self::path_hash(&record.path);
 			let key_bytes = path_hash.to_le_bytes();
-			let record_bytes = Self::serialize_record(record)?;
+			let format = super::codec::stored_format(&stats_table)?;
+			let record_bytes = Self::serialize_record(format, record)?;
 
 			let existed = metadata_table.get(key_bytes.as_slice())?.is_some();
 			metadata_table.insert(key_bytes.as_slice(), record_bytes.as_slice())?;
@@ -92,12 +96,13 @@ impl MetadataStorage for MetadataStorageImpl {
 	async fn get_metadata(&mut self, path: &Path) -> DatabaseResult<Option<MetadataRecord>> {
 		let read_txn = self.database.begin_read()?;
 		let metadata_table = read_txn.open_table(super::tables::METADATA_TABLE)?;
+		let format = super::codec::read_format(&read_txn)?;
 
 		let path_hash = Self::path_hash(path);
 		let key_bytes = path_hash.to_le_bytes();
 
 		if let Some(record_bytes) = metadata_table.get(key_bytes.as_slice())? {
-			let record = Self::deserialize_record(record_bytes.value())?;
+			let record = Self::deserialize_record(format, record_bytes.value())?;
 			Ok(Some(record))
 		} else {
 			Ok(None)
@@ -131,13 +136,14 @@ impl MetadataStorage for MetadataStorageImpl {
 	async fn list_metadata(&mut self, prefix: Option<&str>) -> DatabaseResult<Vec<MetadataRecord>> {
 		let read_txn = self.database.begin_read()?;
 		let metadata_table = read_txn.open_table(super::tables::METADATA_TABLE)?;
+		let format = super::codec::read_format(&read_txn)?;
 
 		let mut metadata_records = Vec::new();
 		let iter = metadata_table.iter()?;
 
 		for item in iter {
 			let (_, value) = item?;
-			let record = Self::deserialize_record(value.value())?;
+			let record = Self::deserialize_record(format, value.value())?;
 
 			// Apply prefix filter if specified
 			if let Some(prefix_str) = prefix {
@@ -155,15 +161,15 @@ impl MetadataStorage for MetadataStorageImpl {
 
 /// Store metadata record using the provided database
 pub async fn store_metadata(
-	database: &Arc<Database>, record: &MetadataRecord,
+	database: &Arc<Database>, record: &MetadataRecord, durability: Durability,
 ) -> DatabaseResult<()> {
-	let write_txn = database.begin_write()?;
+	let write_txn = super::transactions::begin_write(database, durability)?;
 	{
+		let format = super::codec::write_format(&write_txn)?;
 		let mut metadata_table = write_txn.open_table(super::tables::METADATA_TABLE)?;
 		let path_hash = crate::database::types::calculate_path_hash(&record.path);
 		let key_bytes = path_hash.to_le_bytes();
-		let record_bytes = bincode::serialize(record)
-			.map_err(|e| crate::database::error::DatabaseError::Serialization(e.to_string()))?;
+		let record_bytes = format.encode(record)?;
 		metadata_table.insert(key_bytes.as_slice(), record_bytes.as_slice())?;
 	}
 	write_txn.commit()?;
@@ -176,11 +182,20 @@ pub async fn get_metadata(
 ) -> DatabaseResult<Option<MetadataRecord>> {
 	let read_txn = database.begin_read()?;
 	let metadata_table = read_txn.open_table(super::tables::METADATA_TABLE)?;
+	let format = super::codec::read_format(&read_txn)?;
 	let path_hash = crate::database::types::calculate_path_hash(path);
 	let key_bytes = path_hash.to_le_bytes();
 	if let Some(record_bytes) = metadata_table.get(key_bytes.as_slice())? {
-		let record = bincode::deserialize::<MetadataRecord>(record_bytes.value())
-			.map_err(|e| crate::database::error::DatabaseError::Deserialization(e.to_string()))?;
+		let record = format.decode::<MetadataRecord>(record_bytes.value())?;
+		// Records are keyed by path hash alone; another path's record is not this one's
+		if !crate::database::types::same_hashed_path(&record.path, path) {
+			tracing::warn!(
+				"Path hash of {:?} collides with stored metadata for {:?}",
+				path,
+				record.path
+			);
+			return Ok(None);
+		}
 		Ok(Some(record))
 	} else {
 		Ok(None)
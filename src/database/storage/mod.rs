@@ -1,9 +1,12 @@
 //! Storage module for database operations
 
+pub mod codec;
 pub mod core;
+pub mod delivery_log;
 pub mod event_retention;
 pub mod event_storage;
 pub mod filesystem_cache;
+pub mod import;
 pub mod indexing;
 pub mod maintenance;
 pub mod metadata_storage;
@@ -12,7 +15,9 @@ pub mod tables;
 pub mod transactions;
 
 // Re-export the main traits and implementation
+pub use codec::{RecordCodec, SerializationFormat};
 pub use core::{CoreTest, DatabaseStorage, RedbStorage};
+pub use import::{ImportConflict, ImportReport};
 pub use tables::*;
 
 // Re-export specific trait capabilities for focused usage
@@ -56,7 +56,7 @@ impl MultiWatchDatabase {
 		let mut result = Vec::new();
 		for entry in table.range::<&[u8]>(..)? {
 			let (_key, value) = entry?;
-			if let Ok(meta) = bincode::deserialize::<WatchMetadata>(value.value()) {
+			if let Ok(meta) = WatchMetadata::decode(value.value()) {
 				result.push(meta);
 			}
 		}
@@ -69,7 +69,7 @@ impl MultiWatchDatabase {
 		let read_txn = self.database.begin_read()?;
 		let table = read_txn.open_table(crate::database::storage::tables::WATCH_REGISTRY)?;
 		if let Some(value) = table.get(&watch_id.as_bytes()[..])? {
-			let meta = bincode::deserialize::<WatchMetadata>(value.value()).ok();
+			let meta = WatchMetadata::decode(value.value()).ok();
 			Ok(meta)
 		} else {
 			Ok(None)
@@ -160,6 +160,7 @@ impl MultiWatchDatabase {
 	pub async fn merge_nodes_to_shared(
 		&self, path: &std::path::Path, watch_ids: &[uuid::Uuid],
 	) -> Result<(), String> {
+		use crate::database::storage::multi_watch::optimization::{add_scopes, stored_scopes};
 		use crate::database::types::{FilesystemNode, SharedNodeInfo, UnifiedNode};
 		use chrono::Utc;
 		let path_hash = crate::database::types::calculate_path_hash(path);
@@ -177,6 +178,8 @@ impl MultiWatchDatabase {
 				permissions: 0,
 				inode: None,
 				windows_id: None,
+				uid: None,
+				gid: None,
 			},
 			cache_info: crate::database::types::CacheInfo {
 				cached_at: Utc::now(),
@@ -192,15 +195,7 @@ impl MultiWatchDatabase {
 			},
 			last_event_type: None, // Added for compatibility with new FilesystemNode
 		};
-		let shared_info = SharedNodeInfo {
-			node,
-			watching_scopes: watch_ids.to_vec(),
-			reference_count: watch_ids.len() as u32,
-			last_shared_update: Utc::now(),
-		};
 		let key = path_hash.to_le_bytes();
-		let value =
-			bincode::serialize(&UnifiedNode::Shared { shared_info }).map_err(|e| e.to_string())?;
 
 		// Input validation: Ensure all watch-specific nodes exist and are consistent
 		for watch_id in watch_ids {
@@ -220,6 +215,31 @@ impl MultiWatchDatabase {
 			let mut table = write_txn
 				.open_table(crate::database::storage::tables::SHARED_NODES)
 				.map_err(|e| e.to_string())?;
+			let existing = table
+				.get(key.as_slice())
+				.map_err(|e| e.to_string())?
+				.map(|value| value.value().to_vec());
+			// A real node shared earlier keeps its place; only its scopes grow
+			let value = match existing.as_deref().map(SharedNodeInfo::decode) {
+				Some(Ok(mut shared_info)) => {
+					add_scopes(&mut shared_info.watching_scopes, watch_ids);
+					shared_info.reference_count = shared_info.watching_scopes.len() as u32;
+					bincode::serialize(&shared_info)
+				}
+				_ => {
+					let mut watching_scopes =
+						existing.as_deref().map(stored_scopes).unwrap_or_default();
+					add_scopes(&mut watching_scopes, watch_ids);
+					let shared_info = SharedNodeInfo {
+						node,
+						reference_count: watching_scopes.len() as u32,
+						watching_scopes,
+						last_shared_update: Utc::now(),
+					};
+					bincode::serialize(&UnifiedNode::Shared { shared_info })
+				}
+			}
+			.map_err(|e| e.to_string())?;
 			table.insert(key.as_slice(), value.as_slice()).map_err(|e| e.to_string())?;
 		}
 		write_txn.commit().map_err(|e| e.to_string())?;
@@ -249,10 +269,10 @@ impl MultiWatchDatabase {
 		mut shutdown: tokio::sync::watch::Receiver<bool>,
 	) {
 		let db = self.clone();
-		tokio::spawn(async move {
+		crate::runtime::spawn(async move {
 			loop {
 				tokio::select! {
-					_ = tokio::time::sleep(interval) => {
+					_ = crate::runtime::sleep(interval) => {
 						db.optimize_shared_cache().await;
 					}
 					_ = shutdown.changed() => {
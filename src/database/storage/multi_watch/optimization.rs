@@ -1,11 +1,38 @@
 //! Overlap detection and shared cache optimization routines
 //!
 //! Moved from multi_watch.rs. This module handles overlap detection, statistics, and shared cache optimization.
-use crate::database::error::DatabaseResult;
+use crate::database::error::{DatabaseError, DatabaseResult};
+use crate::database::storage::filesystem_cache::slots;
 use crate::database::storage::multi_watch::implementation::MultiWatchDatabase;
 use crate::database::storage::multi_watch::types::{WatchMetadata, WatchOverlap};
-use redb::ReadableTable;
+use crate::database::storage::tables::{MULTI_WATCH_FS_CACHE, PATH_TO_WATCHES, SHARED_NODES};
+use crate::database::types::{same_hashed_path, FilesystemNode, SharedNodeInfo, UnifiedNode};
+use redb::{ReadableMultimapTable, ReadableTable};
 use std::path::PathBuf;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Watches recorded for a stored shared node, in either the plain `SharedNodeInfo` form or
+/// the `UnifiedNode::Shared` form root merges write
+pub(crate) fn stored_scopes(bytes: &[u8]) -> Vec<Uuid> {
+	SharedNodeInfo::decode(bytes)
+		.ok()
+		.or_else(|| match bincode::deserialize(bytes) {
+			Ok(UnifiedNode::Shared { shared_info }) => Some(shared_info),
+			_ => None,
+		})
+		.map(|existing| existing.watching_scopes)
+		.unwrap_or_default()
+}
+
+/// Append the `watch_ids` not already in `scopes`
+pub(crate) fn add_scopes(scopes: &mut Vec<Uuid>, watch_ids: &[Uuid]) {
+	for watch_id in watch_ids {
+		if !scopes.contains(watch_id) {
+			scopes.push(*watch_id);
+		}
+	}
+}
 
 /// Detect overlap between two watches by root path
 pub fn detect_overlap(watch_a: &WatchMetadata, watch_b: &WatchMetadata) -> WatchOverlap {
@@ -63,29 +90,128 @@ impl MultiWatchDatabase {
 		Ok(overlaps)
 	}
 
-	/// Optimize the shared cache by analyzing overlap statistics and node reference counts.
+	/// Paths cached by both watches, in path order
+	///
+	/// Candidates come from `PATH_TO_WATCHES`; each is confirmed against the nodes the two
+	/// watches actually hold, since mappings outlive the nodes they were made for.
+	pub async fn shared_nodes_between(
+		&self, watch_a: &Uuid, watch_b: &Uuid,
+	) -> DatabaseResult<Vec<PathBuf>> {
+		Ok(self
+			.shared_cached_nodes(watch_a, watch_b)?
+			.into_iter()
+			.map(|node| node.path)
+			.collect())
+	}
+
+	/// `watch_a`'s nodes for the paths `watch_b` caches too
+	fn shared_cached_nodes(
+		&self, watch_a: &Uuid, watch_b: &Uuid,
+	) -> DatabaseResult<Vec<FilesystemNode>> {
+		let read_txn = self.database.begin_read()?;
+		let path_watches = match read_txn.open_multimap_table(PATH_TO_WATCHES) {
+			Ok(table) => table,
+			Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+			Err(e) => return Err(e.into()),
+		};
+		let fs_cache = read_txn.open_table(MULTI_WATCH_FS_CACHE)?;
+		let mut shared = Vec::new();
+		for entry in path_watches.iter()? {
+			let (key, watches) = entry?;
+			let (mut in_a, mut in_b) = (false, false);
+			for watch in watches {
+				let watch = watch?;
+				in_a |= watch.value() == watch_a.as_bytes();
+				in_b |= watch.value() == watch_b.as_bytes();
+			}
+			let Ok(home) = <[u8; 8]>::try_from(key.value()).map(u64::from_le_bytes) else {
+				continue;
+			};
+			if !(in_a && in_b) {
+				continue;
+			}
+			let nodes_b = slots::all_at(
+				&fs_cache,
+				watch_b,
+				home,
+				slots::collided(&read_txn, watch_b, home)?,
+			)?;
+			for node in slots::all_at(
+				&fs_cache,
+				watch_a,
+				home,
+				slots::collided(&read_txn, watch_a, home)?,
+			)? {
+				if nodes_b.iter().any(|other| same_hashed_path(&other.path, &node.path)) {
+					shared.push(node);
+				}
+			}
+		}
+		shared.sort_by(|a, b| a.path.cmp(&b.path));
+		Ok(shared)
+	}
+
+	/// Record each of `nodes` as shared by `watch_ids`, in one transaction, in the plain
+	/// `SharedNodeInfo` form `get_shared_node` and `remove_watch` read
+	///
+	/// Scopes already recorded for a node are kept, so a path shared by several pairs ends up
+	/// with every watch that caches it.
+	fn store_shared_nodes(
+		&self, nodes: Vec<FilesystemNode>, watch_ids: &[Uuid],
+	) -> DatabaseResult<()> {
+		let write_txn = self.database.begin_write()?;
+		{
+			let mut table = write_txn.open_table(SHARED_NODES)?;
+			for node in nodes {
+				let key = node.computed.path_hash.to_le_bytes();
+				let mut watching_scopes = match table.get(key.as_slice())? {
+					Some(value) => stored_scopes(value.value()),
+					None => Vec::new(),
+				};
+				add_scopes(&mut watching_scopes, watch_ids);
+				let shared_info = SharedNodeInfo {
+					node,
+					reference_count: watching_scopes.len() as u32,
+					watching_scopes,
+					last_shared_update: chrono::Utc::now(),
+				};
+				let value = bincode::serialize(&shared_info)
+					.map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+				table.insert(key.as_slice(), value.as_slice())?;
+			}
+		}
+		write_txn.commit()?;
+		Ok(())
+	}
+
+	/// Share the nodes that overlapping watches both cache
 	///
-	/// This implementation is minimal and only logs detected overlaps. In production, this should
-	/// trigger cache merges/splits and update shared node state as needed.
+	/// For each pair of watches whose roots overlap, the common root is merged into a shared
+	/// node, and so is every path [`Self::shared_nodes_between`] finds in both caches. The
+	/// per-watch copies are then removed by [`Self::cleanup_redundant_and_orphaned_nodes`].
+	/// Identical roots are not merged yet.
 	pub async fn optimize_shared_cache(&self) {
 		let overlaps = match self.compute_overlap_statistics().await {
 			Ok(o) => o,
 			Err(e) => {
-				eprintln!("[MultiWatchDatabase] Failed to compute overlap statistics: {e}");
+				warn!("Failed to compute overlap statistics: {e}");
 				return;
 			}
 		};
 		for overlap in overlaps {
+			let pair = match overlap {
+				WatchOverlap::Partial { watch_a, watch_b, .. } => Some((watch_a, watch_b)),
+				WatchOverlap::Ancestor { ancestor, descendant } => Some((ancestor, descendant)),
+				_ => None,
+			};
 			match overlap {
 				WatchOverlap::Partial { watch_a, watch_b, ref common_prefix } => {
 					if let Err(e) =
 						self.merge_nodes_to_shared(common_prefix, &[watch_a, watch_b]).await
 					{
-						eprintln!(
-							"[MultiWatchDatabase] Failed to merge nodes at {common_prefix:?}: {e}"
-						);
+						warn!("Failed to merge nodes at {common_prefix:?}: {e}");
 					} else {
-						println!("[MultiWatchDatabase] Merged nodes at {common_prefix:?} into shared node for watches {watch_a:?}, {watch_b:?}");
+						debug!("Merged nodes at {common_prefix:?} into shared node for watches {watch_a:?}, {watch_b:?}");
 					}
 				}
 				WatchOverlap::Ancestor { ancestor: watch_a, descendant: watch_b } => {
@@ -93,19 +219,26 @@ impl MultiWatchDatabase {
 						let path = &watch.root_path;
 						if let Err(e) = self.merge_nodes_to_shared(path, &[watch_a, watch_b]).await
 						{
-							eprintln!(
-								"[MultiWatchDatabase] Failed to merge nodes at {path:?}: {e}"
-							);
+							warn!("Failed to merge nodes at {path:?}: {e}");
 						} else {
-							println!("[MultiWatchDatabase] Merged nodes at {path:?} into shared node for watches {watch_a:?}, {watch_b:?}");
+							debug!("Merged nodes at {path:?} into shared node for watches {watch_a:?}, {watch_b:?}");
 						}
 					}
 				}
 				_ => {}
 			}
+			// After the root merges, so a cached root keeps its real node
+			if let Some((watch_a, watch_b)) = pair {
+				let shared = self
+					.shared_cached_nodes(&watch_a, &watch_b)
+					.and_then(|nodes| self.store_shared_nodes(nodes, &[watch_a, watch_b]));
+				if let Err(e) = shared {
+					warn!("Failed to share nodes of {watch_a:?}, {watch_b:?}: {e}");
+				}
+			}
 		}
 		if let Err(e) = self.cleanup_redundant_and_orphaned_nodes().await {
-			eprintln!("[MultiWatchDatabase] Cleanup after optimization failed: {e}");
+			warn!("Cleanup after optimization failed: {e}");
 		}
 	}
 
@@ -3,7 +3,7 @@
 //! This module contains all table definitions used across the storage implementation.
 //! Centralizing table definitions here ensures consistency and makes schema evolution easier.
 
-use crate::database::error::DatabaseResult;
+use crate::database::error::{DatabaseError, DatabaseResult};
 use redb::{Database, MultimapTableDefinition, TableDefinition};
 use std::sync::Arc;
 
@@ -15,7 +15,13 @@ pub const EVENTS_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("ev
 /// Metadata table for storing file metadata
 pub const METADATA_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("metadata");
 
-/// General-purpose indexes for events (size, time buckets, etc.)
+/// Latest event per path, with `DatabaseConfig::track_current_state`
+/// (path hash -> serialized EventRecord); never pruned by retention
+pub const CURRENT_STATE_TABLE: TableDefinition<&[u8], &[u8]> =
+	TableDefinition::new("current_state");
+
+/// General-purpose indexes for events; holds the path-prefix index
+/// (`StorageKey::PathPrefix` -> serialized EventRecord, see `database::query`)
 pub const INDEXES_TABLE: MultimapTableDefinition<&[u8], &[u8]> =
 	MultimapTableDefinition::new("indexes");
 
@@ -31,6 +37,12 @@ pub const EXTENSION_INDEX: redb::MultimapTableDefinition<&[u8], &[u8]> =
 pub const TIME_INDEX_TABLE: MultimapTableDefinition<&[u8], &[u8]> =
 	MultimapTableDefinition::new("time_index");
 
+/// Event log in per-path sequence order: the path-hash key of `EVENTS_LOG_TABLE` followed by
+/// the big-endian sequence number, to the encoded record. Lets a page of a path's events be
+/// read without decoding the others.
+pub const EVENT_SEQUENCE_INDEX: TableDefinition<&[u8], &[u8]> =
+	TableDefinition::new("event_sequence_index");
+
 // ===== Filesystem Cache Tables =====
 
 /// Primary filesystem cache table (path_hash -> FilesystemNode)
@@ -48,6 +60,25 @@ pub const PATH_PREFIX_TABLE: MultimapTableDefinition<&[u8], &[u8]> =
 pub const DEPTH_INDEX_TABLE: MultimapTableDefinition<&[u8], &[u8]> =
 	MultimapTableDefinition::new("depth_index");
 
+/// Latest path seen with a given content (content_hash -> path), for cross-restart moves
+pub const CONTENT_HASH_INDEX: TableDefinition<&[u8], &[u8]> =
+	TableDefinition::new("content_hash_index");
+
+/// Reverse of CONTENT_HASH_INDEX (path -> content_hash), kept one-to-one with it
+pub const CONTENT_HASH_PATHS: TableDefinition<&[u8], &[u8]> =
+	TableDefinition::new("content_hash_paths");
+
+/// Move detector decisions kept with `MoveDetectorConfig::record_decisions`
+/// (big-endian sequence -> bincode MoveDecisionRecord)
+pub const MOVE_DECISIONS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("move_decisions");
+
+/// Events delivered by watchers with `WatcherConfig::delivery_log`
+/// (watch_id ++ big-endian sequence -> event as JSON)
+pub const DELIVERY_LOG: TableDefinition<&[u8], &[u8]> = TableDefinition::new("delivery_log");
+
+/// Acknowledged high-water mark per watch (watch_id -> u64, little-endian bytes)
+pub const DELIVERY_ACKS: TableDefinition<&[u8], &[u8]> = TableDefinition::new("delivery_acks");
+
 // ===== Multi-Watch Tables =====
 
 /// Multi-watch filesystem cache with watch scoping (watch_scoped_key -> FilesystemNode)
@@ -58,6 +89,11 @@ pub const MULTI_WATCH_FS_CACHE: TableDefinition<&[u8], &[u8]> =
 pub const MULTI_WATCH_HIERARCHY: MultimapTableDefinition<&[u8], &[u8]> =
 	MultimapTableDefinition::new("multi_hierarchy");
 
+/// Home slots of MULTI_WATCH_FS_CACHE that more than one path hashed to
+/// (watch_scoped_key -> empty); see `filesystem_cache::slots`
+pub const COLLIDED_PATH_HASHES: TableDefinition<&[u8], &[u8]> =
+	TableDefinition::new("collided_path_hashes");
+
 /// Shared nodes table (path_hash -> SharedNodeInfo)
 pub const SHARED_NODES: TableDefinition<&[u8], &[u8]> = TableDefinition::new("shared_nodes");
 
@@ -101,9 +137,16 @@ pub const UNIFIED_NODE_INDEX: TableDefinition<&[u8], &[u8]> =
 	TableDefinition::new("unified_node_index");
 
 /// Table groups for easier management
-pub const BASIC_TABLES: &[&str] = &["events", "metadata", "indexes"];
-pub const FILESYSTEM_CACHE_TABLES: &[&str] =
-	&["fs_cache", "hierarchy", "path_prefix", "depth_index"];
+pub const BASIC_TABLES: &[&str] =
+	&["events", "metadata", "indexes", "delivery_log", "delivery_acks"];
+pub const FILESYSTEM_CACHE_TABLES: &[&str] = &[
+	"fs_cache",
+	"hierarchy",
+	"path_prefix",
+	"depth_index",
+	"content_hash_index",
+	"content_hash_paths",
+];
 pub const MULTI_WATCH_TABLES: &[&str] = &[
 	"multi_fs_cache",
 	"multi_hierarchy",
@@ -119,10 +162,14 @@ pub const ALL_TABLES: &[&str] = &[
 	"events",
 	"metadata",
 	"indexes",
+	"delivery_log",
+	"delivery_acks",
 	"fs_cache",
 	"hierarchy",
 	"path_prefix",
 	"depth_index",
+	"content_hash_index",
+	"content_hash_paths",
 	"multi_fs_cache",
 	"multi_hierarchy",
 	"shared_nodes",
@@ -136,7 +183,17 @@ pub const ALL_TABLES: &[&str] = &[
 ];
 
 /// Schema version for migration tracking
-pub const SCHEMA_VERSION: u32 = 1;
+///
+/// 2: `NodeMetadata` gained `uid`/`gid`. Cache values are bincode, so version 1 nodes are
+/// rewritten by [`upgrade_cache_nodes`] when the file is opened.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Key for the schema version in STATS_TABLE (u32, little-endian bytes)
+pub const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+/// Key for the record codec in STATS_TABLE (one byte, see `codec::SerializationFormat`).
+/// Absent in files created before it existed, which hold bincode records.
+pub const SERIALIZATION_FORMAT_KEY: &[u8] = b"serialization_format";
 
 /// Key for event count in STATS_TABLE (u64, little-endian bytes)
 pub const EVENT_COUNT_KEY: &[u8] = b"event_count";
@@ -153,6 +210,54 @@ pub const EVENT_SEQUENCE_KEY: &[u8] = b"event_sequence";
 // This key is used to store the next event sequence number for strict append order.
 // It must be incremented transactionally on every event insert.
 
+/// Key marking that every event in the log is in the path-prefix index (empty value). Set when
+/// an event is appended to an empty log or the indexes are repaired; files with events from
+/// before the index existed lack it, and prefix queries scan them instead.
+pub const EVENT_PREFIX_INDEXED_KEY: &[u8] = b"event_prefix_indexed";
+
+/// Key marking that every event in the log is in `EVENT_SEQUENCE_INDEX` (empty value), set
+/// like `EVENT_PREFIX_INDEXED_KEY`; paging falls back to decoding a path's events without it
+pub const EVENT_SEQUENCE_INDEXED_KEY: &[u8] = b"event_sequence_indexed";
+
+/// Rewrite the cache nodes a version 1 file holds in the current layout, returning how many.
+///
+/// Runs before the version is stamped, for files recording an older version or none (the key
+/// is newer than version 2, so a file without it may hold either layout). Values that already
+/// decode are left alone.
+pub(crate) fn upgrade_cache_nodes(write_txn: &redb::WriteTransaction) -> DatabaseResult<usize> {
+	use crate::database::types::{FilesystemNode, SharedNodeInfo};
+	use redb::ReadableTable;
+
+	fn rewrite<T: serde::Serialize + serde::de::DeserializeOwned>(
+		write_txn: &redb::WriteTransaction, definition: TableDefinition<&[u8], &[u8]>,
+		decode: fn(&[u8]) -> bincode::Result<T>,
+	) -> DatabaseResult<usize> {
+		let mut table = write_txn.open_table(definition)?;
+		let mut upgraded = Vec::new();
+		for entry in table.iter()? {
+			let (key, value) = entry?;
+			if bincode::deserialize::<T>(value.value()).is_ok() {
+				continue;
+			}
+			// Undecodable either way: left for the reader to report, as before
+			if let Ok(node) = decode(value.value()) {
+				let bytes = bincode::serialize(&node)
+					.map_err(|e| DatabaseError::Serialization(e.to_string()))?;
+				upgraded.push((key.value().to_vec(), bytes));
+			}
+		}
+		for (key, bytes) in &upgraded {
+			table.insert(key.as_slice(), bytes.as_slice())?;
+		}
+		Ok(upgraded.len())
+	}
+
+	Ok(rewrite(write_txn, FS_CACHE_TABLE, FilesystemNode::decode)?
+		+ rewrite(write_txn, MULTI_WATCH_FS_CACHE, FilesystemNode::decode)?
+		+ rewrite(write_txn, UNIFIED_NODE_INDEX, FilesystemNode::decode)?
+		+ rewrite(write_txn, SHARED_NODES, SharedNodeInfo::decode)?)
+}
+
 /// Initialize all database tables
 pub async fn initialize_tables(database: &Arc<Database>) -> DatabaseResult<()> {
 	let write_txn = database.begin_write()?;
@@ -160,19 +265,27 @@ pub async fn initialize_tables(database: &Arc<Database>) -> DatabaseResult<()> {
 		// Initialize basic tables
 		let _events_table = write_txn.open_table(EVENTS_TABLE)?;
 		let _metadata_table = write_txn.open_table(METADATA_TABLE)?;
+		let _current_state_table = write_txn.open_table(CURRENT_STATE_TABLE)?;
 		let _indexes_table = write_txn.open_multimap_table(INDEXES_TABLE)?;
+		let _delivery_log_table = write_txn.open_table(DELIVERY_LOG)?;
+		let _delivery_acks_table = write_txn.open_table(DELIVERY_ACKS)?;
 		// Initialize append-only event log table (multimap)
 		let _events_log_table = write_txn.open_multimap_table(EVENTS_LOG_TABLE)?;
+		let _event_sequence_index = write_txn.open_table(EVENT_SEQUENCE_INDEX)?;
 
 		// Initialize filesystem cache tables
 		let _fs_cache_table = write_txn.open_table(FS_CACHE_TABLE)?;
 		let _hierarchy_table = write_txn.open_multimap_table(HIERARCHY_TABLE)?;
 		let _path_prefix_table = write_txn.open_multimap_table(PATH_PREFIX_TABLE)?;
 		let _depth_index_table = write_txn.open_multimap_table(DEPTH_INDEX_TABLE)?;
+		let _content_hash_index = write_txn.open_table(CONTENT_HASH_INDEX)?;
+		let _content_hash_paths = write_txn.open_table(CONTENT_HASH_PATHS)?;
+		let _move_decisions = write_txn.open_table(MOVE_DECISIONS)?;
 
 		// Initialize multi-watch tables
 		let _multi_fs_cache_table = write_txn.open_table(MULTI_WATCH_FS_CACHE)?;
 		let _multi_hierarchy_table = write_txn.open_multimap_table(MULTI_WATCH_HIERARCHY)?;
+		let _collided_path_hashes = write_txn.open_table(COLLIDED_PATH_HASHES)?;
 		let _shared_nodes_table = write_txn.open_table(SHARED_NODES)?;
 		let _watch_registry_table = write_txn.open_table(WATCH_REGISTRY)?;
 		let _path_to_watches_table = write_txn.open_multimap_table(PATH_TO_WATCHES)?;
@@ -3,10 +3,20 @@
 //! This module provides common transaction patterns and utilities
 //! for consistent error handling and resource management across all storage operations.
 
+use crate::database::config::Durability;
 use crate::database::error::{DatabaseError, DatabaseResult};
 use redb::{Database, ReadTransaction, WriteTransaction};
 use std::sync::Arc;
 
+/// Begin a write transaction that commits with `durability`
+pub(crate) fn begin_write(
+	database: &Database, durability: Durability,
+) -> DatabaseResult<WriteTransaction> {
+	let mut write_txn = database.begin_write()?;
+	write_txn.set_durability(durability.to_redb());
+	Ok(write_txn)
+}
+
 /// Transaction helper utilities
 pub struct TransactionUtils;
 
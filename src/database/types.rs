@@ -45,17 +45,25 @@ pub struct EventRecord {
 	/// Detection method used (for move events)
 	pub detection_method: Option<String>,
 
+	/// Where a move event came from; not kept in the log, only used to mark the source path
+	/// as moved away under `DatabaseConfig::track_current_state`
+	#[serde(skip)]
+	pub source_path: Option<PathBuf>,
+
 	/// Time-to-live for automatic cleanup
 	pub expires_at: DateTime<Utc>,
 }
 
 impl EventRecord {
 	/// Create a new event record with automatic expiration
+	///
+	/// Stamped with [`crate::database::clock::now`], which does not follow the system clock
+	/// backward.
 	pub fn new(
 		event_type: String, path: PathBuf, is_directory: bool,
 		retention_duration: chrono::Duration, sequence_number: u64,
 	) -> Self {
-		let now = Utc::now();
+		let now = crate::database::clock::now();
 		Self {
 			event_id: Uuid::new_v4(),
 			sequence_number,
@@ -69,13 +77,14 @@ impl EventRecord {
 			content_hash: None,
 			confidence: None,
 			detection_method: None,
+			source_path: None,
 			expires_at: now + retention_duration,
 		}
 	}
 
 	/// Check if this record has expired
 	pub fn is_expired(&self) -> bool {
-		Utc::now() > self.expires_at
+		crate::database::clock::now() > self.expires_at
 	}
 
 	/// Update the expiration time
@@ -183,6 +192,9 @@ pub struct NodeMetadata {
 	pub permissions: u32,
 	pub inode: Option<u64>,
 	pub windows_id: Option<u64>,
+	/// Unix owner and group; `None` elsewhere
+	pub uid: Option<u32>,
+	pub gid: Option<u32>,
 }
 
 /// Cache-specific metadata
@@ -282,6 +294,34 @@ impl StorageKey {
 	}
 }
 
+/// One page of a path's events, see `DatabaseAdapter::get_events_for_path_paged`
+#[derive(Debug, Clone, Default)]
+pub struct EventPage {
+	/// Events in ascending `sequence_number` order
+	pub events: Vec<EventRecord>,
+
+	/// `sequence_number` to pass as `after` for the next page; `None` on the last page
+	pub next_cursor: Option<u64>,
+}
+
+impl EventPage {
+	/// The page after `after` of `events`, which must be in ascending `sequence_number` order.
+	/// A `limit` of 0 is treated as 1 so that paging always advances.
+	pub(crate) fn from_sorted(
+		events: impl Iterator<Item = EventRecord>, after: Option<u64>, limit: usize,
+	) -> Self {
+		let limit = limit.max(1);
+		let mut events: Vec<_> = events
+			.filter(|event| after.is_none_or(|after| event.sequence_number > after))
+			.take(limit + 1)
+			.collect();
+		let more = events.len() > limit;
+		events.truncate(limit);
+		let next_cursor = more.then(|| events.last().map(|event| event.sequence_number)).flatten();
+		Self { events, next_cursor }
+	}
+}
+
 /// Statistics about database usage
 ///
 /// NOTE: For scalable stats, total_events should be loaded from a persistent counter in STATS_TABLE (see EVENT_COUNT_KEY).
@@ -334,17 +374,149 @@ impl DatabaseStats {
 	}
 }
 
+/// What [`ListingOrder`] sorts directory entries by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListingSort {
+	/// File name, case-insensitively, with the exact name breaking ties
+	#[default]
+	Name,
+	/// File size; directories and symlinks count as 0
+	Size,
+	/// Last modification time
+	Modified,
+}
+
+/// Order for directory listings, which the cache otherwise returns in index order
+///
+/// Entries that compare equal fall back to their name, then to their full path, so the order
+/// is the same on every call. The default is ascending by name with no grouping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ListingOrder {
+	pub by: ListingSort,
+	/// Reverse the `by` comparison (largest or newest first); the name fallback stays ascending
+	pub descending: bool,
+	/// List directories before everything else, each group sorted on its own
+	pub directories_first: bool,
+}
+
+impl ListingOrder {
+	/// Ascending by `by`
+	pub fn by(by: ListingSort) -> Self {
+		Self { by, ..Default::default() }
+	}
+
+	pub fn descending(mut self) -> Self {
+		self.descending = true;
+		self
+	}
+
+	pub fn directories_first(mut self) -> Self {
+		self.directories_first = true;
+		self
+	}
+
+	/// Sort `nodes` in this order
+	pub fn sort(&self, nodes: &mut [FilesystemNode]) {
+		let name = |node: &FilesystemNode| {
+			let name = node.path.file_name().unwrap_or(node.path.as_os_str()).to_string_lossy();
+			(name.to_lowercase(), name.into_owned())
+		};
+		let group = |node: &FilesystemNode| {
+			!(self.directories_first && matches!(node.node_type, NodeType::Directory { .. }))
+		};
+		nodes.sort_by(|a, b| {
+			let by_name = || name(a).cmp(&name(b)).then_with(|| a.path.cmp(&b.path));
+			let key = match self.by {
+				ListingSort::Name => by_name(),
+				ListingSort::Size => a.listing_size().cmp(&b.listing_size()),
+				ListingSort::Modified => a.metadata.modified_time.cmp(&b.metadata.modified_time),
+			};
+			let key = if self.descending { key.reverse() } else { key };
+			group(a).cmp(&group(b)).then(key).then_with(by_name)
+		});
+	}
+}
+
+/// One page of a unified directory listing, see
+/// `FilesystemCacheStorage::list_directory_unified_page`
+#[derive(Debug, Clone, Default)]
+pub struct DirectoryPage {
+	/// Children in index order: by watch, then by the order the cache keeps them in
+	pub nodes: Vec<FilesystemNode>,
+
+	/// Where the next page starts; `None` once the listing is exhausted. A full page can be
+	/// followed by an empty one.
+	pub next_cursor: Option<DirectoryCursor>,
+}
+
+/// Position in a unified directory listing, only meaningful to the cache that returned it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryCursor {
+	pub(crate) watch_id: Uuid,
+	pub(crate) child_key: Vec<u8>,
+}
+
+/// One pairing the move detector weighed, see `MoveDetectorConfig::record_decisions`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MoveDecisionRecord {
+	pub recorded_at: DateTime<Utc>,
+	/// Path of the remove half
+	pub source: PathBuf,
+	/// Path of the create half
+	pub destination: PathBuf,
+	/// Each signal's match, 0.0 to 1.0, before weighting; enough to re-score under new weights
+	pub signals: DecisionSignals,
+	/// Each signal as it entered the default weighted sum (0.0 for timing it was left out of)
+	pub contributions: DecisionSignals,
+	/// `weight_parent_correlation` when the pair earned it, else 0.0
+	pub parent_correlation_bonus: f32,
+	/// Score the detector compared against `threshold`, from the configured scorer
+	pub confidence: f32,
+	pub threshold: f32,
+	/// Whether this candidate became the move
+	pub accepted: bool,
+}
+
+/// Per-signal values of a [`MoveDecisionRecord`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DecisionSignals {
+	pub size: f32,
+	pub time: f32,
+	/// Inode on Unix, file ID on Windows
+	pub identity: f32,
+	pub content_hash: f32,
+	pub name_similarity: f32,
+}
+
 /// Calculate a consistent hash for a path
 pub fn calculate_path_hash(path: &Path) -> u64 {
 	use std::collections::hash_map::DefaultHasher;
 	use std::hash::{Hash, Hasher};
 
+	let path = crate::database::path_utils::normalize_separators(path);
 	let mut hasher = DefaultHasher::new();
-	// Normalize path for consistent hashing across platforms
-	path.to_string_lossy().to_lowercase().hash(&mut hasher);
+	match path.to_str() {
+		// Normalize path for consistent hashing across platforms
+		Some(text) => text.to_lowercase().hash(&mut hasher),
+		// Non-Unicode paths hash their raw encoding: lossy conversion would map every invalid
+		// sequence to U+FFFD and make distinct paths collide. No case folding is attempted.
+		None => path.as_os_str().hash(&mut hasher),
+	}
 	hasher.finish()
 }
 
+/// Whether `a` and `b` are the same path as far as `calculate_path_hash` is concerned, i.e.
+/// whether a node stored for one may be returned for the other
+pub fn same_hashed_path(a: &Path, b: &Path) -> bool {
+	use crate::database::path_utils::normalize_separators;
+	let (a, b) = (normalize_separators(a), normalize_separators(b));
+	match (a.to_str(), b.to_str()) {
+		(Some(a), Some(b)) => a == b || a.to_lowercase() == b.to_lowercase(),
+		(None, None) => a.as_os_str() == b.as_os_str(),
+		_ => false,
+	}
+}
+
 /// Update the existing StorageKey enum with filesystem cache variants
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ExtendedStorageKey {
@@ -418,6 +590,124 @@ pub struct WatchMetadata {
 	pub is_active: bool,
 	pub config_hash: u64,
 	pub permissions: Option<WatchPermissions>, // Optional for backward compatibility
+	/// Replaces `DatabaseConfig::event_retention` for events under `root_path`; see
+	/// `DatabaseAdapter::cleanup_old_events`
+	pub event_retention: Option<std::time::Duration>,
+}
+
+/// `WatchMetadata` as stored before `event_retention`; bincode has no optional trailing fields
+#[derive(Deserialize)]
+struct LegacyWatchMetadata {
+	watch_id: Uuid,
+	root_path: PathBuf,
+	created_at: DateTime<Utc>,
+	last_scan: Option<DateTime<Utc>>,
+	node_count: u64,
+	is_active: bool,
+	config_hash: u64,
+	permissions: Option<WatchPermissions>,
+}
+
+impl WatchMetadata {
+	/// Decode a watch registry value, including ones written before `event_retention` existed
+	pub(crate) fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+		bincode::deserialize(bytes).or_else(|e| {
+			let legacy: LegacyWatchMetadata = bincode::deserialize(bytes).map_err(|_| e)?;
+			Ok(Self {
+				watch_id: legacy.watch_id,
+				root_path: legacy.root_path,
+				created_at: legacy.created_at,
+				last_scan: legacy.last_scan,
+				node_count: legacy.node_count,
+				is_active: legacy.is_active,
+				config_hash: legacy.config_hash,
+				permissions: legacy.permissions,
+				event_retention: None,
+			})
+		})
+	}
+}
+
+/// `NodeMetadata` as stored before `uid`/`gid` (schema version 1)
+#[derive(Deserialize)]
+struct LegacyNodeMetadata {
+	modified_time: SystemTime,
+	created_time: Option<SystemTime>,
+	accessed_time: Option<SystemTime>,
+	permissions: u32,
+	inode: Option<u64>,
+	windows_id: Option<u64>,
+}
+
+/// `FilesystemNode` as stored with a [`LegacyNodeMetadata`]
+#[derive(Deserialize)]
+struct LegacyFilesystemNode {
+	path: PathBuf,
+	node_type: NodeType,
+	metadata: LegacyNodeMetadata,
+	cache_info: CacheInfo,
+	computed: ComputedProperties,
+	last_event_type: Option<String>,
+}
+
+impl From<LegacyFilesystemNode> for FilesystemNode {
+	fn from(legacy: LegacyFilesystemNode) -> Self {
+		let metadata = legacy.metadata;
+		Self {
+			path: legacy.path,
+			node_type: legacy.node_type,
+			metadata: NodeMetadata {
+				modified_time: metadata.modified_time,
+				created_time: metadata.created_time,
+				accessed_time: metadata.accessed_time,
+				permissions: metadata.permissions,
+				inode: metadata.inode,
+				windows_id: metadata.windows_id,
+				uid: None,
+				gid: None,
+			},
+			cache_info: legacy.cache_info,
+			computed: legacy.computed,
+			last_event_type: legacy.last_event_type,
+		}
+	}
+}
+
+/// `SharedNodeInfo` holding a [`LegacyFilesystemNode`]
+#[derive(Deserialize)]
+struct LegacySharedNodeInfo {
+	node: LegacyFilesystemNode,
+	watching_scopes: Vec<Uuid>,
+	reference_count: u32,
+	last_shared_update: DateTime<Utc>,
+}
+
+impl FilesystemNode {
+	/// Decode a cache value, including ones written by schema version 1 (without owner ids).
+	///
+	/// The current layout is tried first: bincode ignores trailing bytes, so a current record
+	/// would also decode as the shorter legacy one.
+	pub(crate) fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+		bincode::deserialize(bytes).or_else(|e| {
+			let legacy: LegacyFilesystemNode = bincode::deserialize(bytes).map_err(|_| e)?;
+			Ok(legacy.into())
+		})
+	}
+}
+
+impl SharedNodeInfo {
+	/// Decode a shared node value, including ones written by schema version 1
+	pub(crate) fn decode(bytes: &[u8]) -> bincode::Result<Self> {
+		bincode::deserialize(bytes).or_else(|e| {
+			let legacy: LegacySharedNodeInfo = bincode::deserialize(bytes).map_err(|_| e)?;
+			Ok(Self {
+				node: legacy.node.into(),
+				watching_scopes: legacy.watching_scopes,
+				reference_count: legacy.reference_count,
+				last_shared_update: legacy.last_shared_update,
+			})
+		})
+	}
 }
 
 /// Unified node that can represent shared or watch-specific data
@@ -433,6 +723,14 @@ pub enum UnifiedNode {
 }
 
 impl FilesystemNode {
+	/// Size for [`ListingSort::Size`]
+	fn listing_size(&self) -> u64 {
+		match self.node_type {
+			NodeType::File { size, .. } => size,
+			_ => 0,
+		}
+	}
+
 	/// Create a new filesystem node from standard metadata
 	pub fn new_with_event_type(
 		path: PathBuf, metadata: &std::fs::Metadata, event_type: Option<String>,
@@ -501,6 +799,146 @@ impl FilesystemNode {
 		self.cache_info.last_verified = Utc::now();
 		self.cache_info.needs_refresh = false;
 	}
+
+	/// Compare this (previous) version of a node against a newer one for the same path.
+	///
+	/// Only filesystem-visible fields are compared; cache bookkeeping (`cache_info`,
+	/// `computed`, `last_event_type`) is ignored. A content hash change is only reported when
+	/// both sides have a hash, since hashes are computed on demand and `None` means unknown.
+	/// Nodes synthesized from events without on-disk metadata carry `UNIX_EPOCH` mtimes and
+	/// zero permissions, so a diff against them will report those as changed.
+	pub fn diff(&self, other: &FilesystemNode) -> NodeDiff {
+		let mut changes = Vec::new();
+
+		match (&self.node_type, &other.node_type) {
+			(
+				NodeType::File { size: old_size, content_hash: old_hash, .. },
+				NodeType::File { size: new_size, content_hash: new_hash, .. },
+			) => {
+				if old_size != new_size {
+					changes.push(NodeChange::Size { old: *old_size, new: *new_size });
+				}
+				if let (Some(old_hash), Some(new_hash)) = (old_hash, new_hash) {
+					if old_hash != new_hash {
+						changes.push(NodeChange::ContentHash {
+							old: old_hash.clone(),
+							new: new_hash.clone(),
+						});
+					}
+				}
+			}
+			(
+				NodeType::Directory { total_size: old_size, .. },
+				NodeType::Directory { total_size: new_size, .. },
+			) => {
+				if old_size != new_size {
+					changes.push(NodeChange::Size { old: *old_size, new: *new_size });
+				}
+			}
+			(
+				NodeType::Symlink { target: old_target, .. },
+				NodeType::Symlink { target: new_target, .. },
+			) => {
+				if old_target != new_target {
+					changes.push(NodeChange::SymlinkTarget {
+						old: old_target.clone(),
+						new: new_target.clone(),
+					});
+				}
+			}
+			(old, new) => {
+				changes.push(NodeChange::Type { old: old.kind_name(), new: new.kind_name() });
+			}
+		}
+
+		if self.metadata.modified_time != other.metadata.modified_time {
+			changes.push(NodeChange::ModifiedTime {
+				old: self.metadata.modified_time,
+				new: other.metadata.modified_time,
+			});
+		}
+		if self.metadata.permissions != other.metadata.permissions {
+			changes.push(NodeChange::Permissions {
+				old: self.metadata.permissions,
+				new: other.metadata.permissions,
+			});
+		}
+
+		NodeDiff { changes }
+	}
+}
+
+impl NodeType {
+	/// Short lowercase name of the variant, for diffs and log output
+	pub fn kind_name(&self) -> &'static str {
+		match self {
+			NodeType::File { .. } => "file",
+			NodeType::Directory { .. } => "directory",
+			NodeType::Symlink { .. } => "symlink",
+		}
+	}
+}
+
+/// A single field that differs between two versions of a `FilesystemNode`
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeChange {
+	/// The node changed between file, directory and symlink; other type-specific fields are
+	/// not compared in that case
+	Type {
+		old: &'static str,
+		new: &'static str,
+	},
+	/// File size, or aggregated `total_size` for directories
+	Size {
+		old: u64,
+		new: u64,
+	},
+	ContentHash {
+		old: String,
+		new: String,
+	},
+	ModifiedTime {
+		old: SystemTime,
+		new: SystemTime,
+	},
+	Permissions {
+		old: u32,
+		new: u32,
+	},
+	SymlinkTarget {
+		old: PathBuf,
+		new: PathBuf,
+	},
+}
+
+/// Structured result of `FilesystemNode::diff`; empty when nothing observable changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeDiff {
+	pub changes: Vec<NodeChange>,
+}
+
+impl NodeDiff {
+	pub fn is_empty(&self) -> bool {
+		self.changes.is_empty()
+	}
+
+	/// Human-readable one-line summary, e.g. "size 100->250, content changed"
+	pub fn summary(&self) -> String {
+		self.changes
+			.iter()
+			.map(|change| match change {
+				NodeChange::Type { old, new } => format!("type {old}->{new}"),
+				NodeChange::Size { old, new } => format!("size {old}->{new}"),
+				NodeChange::ContentHash { .. } => "content changed".to_string(),
+				NodeChange::ModifiedTime { .. } => "mtime changed".to_string(),
+				NodeChange::Permissions { old, new } => format!("permissions {old:o}->{new:o}"),
+				NodeChange::SymlinkTarget { old, new } => {
+					format!("target {}->{}", old.display(), new.display())
+				}
+			})
+			.collect::<Vec<_>>()
+			.join(", ")
+	}
 }
 
 impl NodeMetadata {
@@ -510,13 +948,27 @@ impl NodeMetadata {
 			modified_time: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
 			created_time: metadata.created().ok(),
 			accessed_time: metadata.accessed().ok(),
-			permissions: 0, // Platform-specific implementation needed
-			inode: None,    // Platform-specific implementation needed
+			permissions: permission_bits(metadata),
+			inode: None, // Platform-specific implementation needed
 			windows_id: None,
+			uid: crate::events::FileOwnership::from_metadata(metadata).map(|owner| owner.uid),
+			gid: crate::events::FileOwnership::from_metadata(metadata).map(|owner| owner.gid),
 		}
 	}
 }
 
+/// Unix mode bits; elsewhere only the read-only flag is available (1 = read-only).
+#[cfg(unix)]
+fn permission_bits(metadata: &std::fs::Metadata) -> u32 {
+	use std::os::unix::fs::PermissionsExt;
+	metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+fn permission_bits(metadata: &std::fs::Metadata) -> u32 {
+	metadata.permissions().readonly() as u32
+}
+
 impl Default for NodeMetadata {
 	fn default() -> Self {
 		Self {
@@ -526,6 +978,8 @@ impl Default for NodeMetadata {
 			permissions: 0,
 			inode: None,
 			windows_id: None,
+			uid: None,
+			gid: None,
 		}
 	}
 }
@@ -610,6 +1064,24 @@ mod tests {
 		assert_eq!(calculate_path_hash(&path1), calculate_path_hash(&path2));
 	}
 
+	#[test]
+	fn test_path_hash_ignores_repeated_and_trailing_separators() {
+		let path = Path::new("/test/dir");
+		for variant in ["/test/dir/", "/test//dir", "//test/dir//"] {
+			assert_eq!(
+				calculate_path_hash(Path::new(variant)),
+				calculate_path_hash(path),
+				"{variant}"
+			);
+			assert!(same_hashed_path(Path::new(variant), path), "{variant}");
+		}
+		// The root keeps its separator
+		assert_ne!(
+			calculate_path_hash(Path::new("/")),
+			calculate_path_hash(Path::new(""))
+		);
+	}
+
 	#[test]
 	fn test_node_refresh_logic() {
 		use std::fs;
@@ -634,6 +1106,104 @@ mod tests {
 		node.mark_verified();
 		assert!(!node.needs_refresh(Duration::from_secs(3600)));
 	}
+
+	fn file_node(size: u64, content_hash: Option<&str>) -> FilesystemNode {
+		FilesystemNode {
+			path: PathBuf::from("/test/diff.txt"),
+			node_type: NodeType::File {
+				size,
+				content_hash: content_hash.map(str::to_string),
+				mime_type: None,
+			},
+			metadata: NodeMetadata::default(),
+			cache_info: CacheInfo::default(),
+			computed: ComputedProperties::default(),
+			last_event_type: None,
+		}
+	}
+
+	#[test]
+	fn test_node_diff_no_change() {
+		let old = file_node(100, Some("abc"));
+		let mut new = old.clone();
+		// Cache bookkeeping must not show up as a change
+		new.mark_stale();
+		new.last_event_type = Some("Write".to_string());
+		let diff = old.diff(&new);
+		assert!(diff.is_empty(), "unexpected changes: {:?}", diff.changes);
+		assert_eq!(diff.summary(), "");
+	}
+
+	#[test]
+	fn test_node_diff_size() {
+		let diff = file_node(100, None).diff(&file_node(250, None));
+		assert_eq!(diff.changes, vec![NodeChange::Size { old: 100, new: 250 }]);
+		assert_eq!(diff.summary(), "size 100->250");
+	}
+
+	#[test]
+	fn test_node_diff_content_hash() {
+		let diff = file_node(100, Some("abc")).diff(&file_node(100, Some("def")));
+		assert_eq!(
+			diff.changes,
+			vec![NodeChange::ContentHash { old: "abc".to_string(), new: "def".to_string() }]
+		);
+
+		// Unknown hash on either side is not a change
+		assert!(file_node(100, None).diff(&file_node(100, Some("def"))).is_empty());
+	}
+
+	#[test]
+	fn test_node_diff_modified_time() {
+		let old = file_node(100, None);
+		let mut new = old.clone();
+		new.metadata.modified_time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(60);
+		let diff = old.diff(&new);
+		assert_eq!(diff.changes.len(), 1);
+		assert!(matches!(diff.changes[0], NodeChange::ModifiedTime { .. }));
+	}
+
+	#[test]
+	fn test_node_diff_permissions() {
+		let mut old = file_node(100, None);
+		old.metadata.permissions = 0o644;
+		let mut new = old.clone();
+		new.metadata.permissions = 0o600;
+		let diff = old.diff(&new);
+		assert_eq!(
+			diff.changes,
+			vec![NodeChange::Permissions { old: 0o644, new: 0o600 }]
+		);
+		assert_eq!(diff.summary(), "permissions 644->600");
+	}
+
+	#[test]
+	fn test_node_diff_type() {
+		let old = file_node(100, None);
+		let mut new = old.clone();
+		new.node_type = NodeType::Directory { child_count: 0, total_size: 0, max_depth: 0 };
+		let diff = old.diff(&new);
+		assert_eq!(
+			diff.changes,
+			vec![NodeChange::Type { old: "file", new: "directory" }]
+		);
+	}
+
+	#[test]
+	fn test_node_diff_symlink_target() {
+		let mut old = file_node(0, None);
+		old.node_type = NodeType::Symlink { target: PathBuf::from("/test/a"), resolved: None };
+		let mut new = old.clone();
+		new.node_type = NodeType::Symlink { target: PathBuf::from("/test/b"), resolved: None };
+		let diff = old.diff(&new);
+		assert_eq!(
+			diff.changes,
+			vec![NodeChange::SymlinkTarget {
+				old: PathBuf::from("/test/a"),
+				new: PathBuf::from("/test/b")
+			}]
+		);
+	}
 }
 
 /// Key type for scoping cache entries to a specific watch
@@ -0,0 +1,165 @@
+//! Typed operational signals, delivered separately from filesystem events.
+//!
+//! Everything sent here is also logged through `tracing` as before; the channel exists so an
+//! application can react (alert, rescan, back off) without scraping logs. Obtain it with
+//! [`crate::start_with_diagnostics`].
+//!
+//! Delivery is best effort: the channel is bounded and a diagnostic that does not fit is
+//! dropped (with a debug log) rather than stalling the event loop or the backend thread. A
+//! consumer that stops reading therefore loses diagnostics, never events.
+
+use crate::database::types::NodeDiff;
+use crate::events::EventType;
+use crate::metrics::SummaryEvent;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+/// Capacity of the diagnostics channel
+pub const DIAGNOSTICS_CHANNEL_CAPACITY: usize = 100;
+
+/// An operational problem the watcher ran into
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum WatcherDiagnostic {
+	/// An event was left out of move detection because `max_pending_events` was reached.
+	///
+	/// The event itself is still delivered; it just cannot be paired into a move, so a move
+	/// involving it shows up as a separate Remove and Create.
+	DroppedEvent {
+		path: PathBuf,
+		event_type: EventType,
+		reason: String,
+	},
+	/// The backend lost events (e.g. the inotify queue overflowed). Anything under `path`, or
+	/// the whole tree when `None`, may have changed unreported; rescan to resynchronize.
+	BackendOverflow { path: Option<PathBuf> },
+	/// A scan (initial or catch-up of a new directory) could not read an entry and skipped it
+	ScanSkip {
+		path: Option<PathBuf>,
+		reason: String,
+	},
+	/// Writes to the database failed, or it could not be opened and the watcher runs without
+	/// persistence. Events are still delivered.
+	///
+	/// When `DatabaseConfig::circuit_breaker` opens, the reason says persistence is suspended;
+	/// writes skipped while it stays open are not reported one by one.
+	PersistenceDegraded { reason: String },
+	/// A write went through again after `DatabaseConfig::circuit_breaker` had suspended
+	/// persistence; events from here on are stored. Those delivered in between are not.
+	PersistenceRestored,
+	/// The filesystem cache recorded `path` as a directory when the filesystem says it is a
+	/// file, or the other way round. The cache has drifted, and the stale node is dropped from
+	/// it; see `MoveDetectorConfig::cache_mismatch` for which side move detection used.
+	CacheMismatch {
+		path: PathBuf,
+		cached_is_directory: bool,
+		disk_is_directory: bool,
+	},
+	/// `path` could not be read because it is too long for the OS, e.g. past `MAX_PATH` on
+	/// Windows without long-path support. Its metadata and content hash are missing, so a move
+	/// involving it may not be detected. See `MoveDetectorConfig::long_path_prefix`.
+	PathTooLong { path: PathBuf },
+	/// A remove or create found nothing to pair with although counterparts were pending; see
+	/// `MoveDetectorConfig::near_miss_diagnostics`. Sizes are the detector's size buckets.
+	MoveNearMiss {
+		path: PathBuf,
+		event_type: EventType,
+		/// Bucket the event was filed under; `None` is the bucket of events without a size
+		bucket: Option<u64>,
+		/// Pending counterparts in that bucket; all were below `confidence_threshold` or out
+		/// of `move_scope`
+		bucket_candidates: usize,
+		/// Closest other size bucket holding counterparts, with their count, e.g. a size one
+		/// byte off because of a concurrent write
+		nearest_bucket: Option<(u64, usize)>,
+		/// Pending counterparts across all buckets
+		total_candidates: usize,
+	},
+	/// A sink registered with `WatcherConfig::with_event_sink` failed to write the event for
+	/// `path`, after any retries. The event was still delivered to the receiver.
+	SinkWriteFailed {
+		sink: String,
+		path: PathBuf,
+		reason: String,
+	},
+	/// The backend needs a descriptor per watched entry (kqueue) and the tree under `path` needs
+	/// about `needed` of them, more than the file descriptor limit of `limit` allows even after
+	/// trying to raise it. The watcher does not start.
+	DescriptorBudgetExceeded {
+		path: PathBuf,
+		needed: u64,
+		limit: u64,
+	},
+	/// The watch root was removed and a directory reappeared at `path` within
+	/// `WatcherConfig::root_gone_grace`. It is watched again and its entries were reported as
+	/// Creates; changes made while the root was missing are otherwise lost.
+	WatchRootReplaced { path: PathBuf },
+	/// The watch root was removed and did not reappear within `WatcherConfig::root_gone_grace`.
+	/// The watcher has stopped.
+	WatchRootGone { path: PathBuf },
+	/// A file was moved `moves` times within `window`, more than
+	/// `MoveDetectorConfig::flapping_threshold`, between `paths` (in the order it first reached
+	/// them). Reported once until it settles; see `MoveDetectorConfig::suppress_flapping_moves`.
+	FlappingDetected {
+		paths: Vec<PathBuf>,
+		moves: usize,
+		window: Duration,
+	},
+	/// Counts of delivered events since the previous summary, sent every
+	/// `WatcherConfig::summary_interval`
+	Summary(SummaryEvent),
+	/// An OS limit was hit, e.g. the inotify watch limit; `path` is what could not be watched
+	ResourceLimit {
+		resource: String,
+		path: Option<PathBuf>,
+	},
+	/// The watch limit was hit while registering the tree at start, and
+	/// `WatcherConfig::degrade_on_watch_limit` kept the watcher running without the subtrees
+	/// under `unwatched` (each the top of one). Changes in them go unreported.
+	WatchesDegraded { unwatched: Vec<PathBuf> },
+	/// `candidates` pending counterparts were more than
+	/// `MoveDetectorConfig::name_similarity_candidate_cap`, so the event at `path` was only
+	/// paired by inode, Windows ID or content hash, if at all
+	NameSimilarityCapped {
+		path: PathBuf,
+		event_type: EventType,
+		candidates: usize,
+	},
+	/// The event channel was `fill` full (0.0 to 1.0) and `WatcherConfig::adaptive_coalescing`
+	/// started coalescing writes (`coalescing: true`) or, with the consumer caught up, stopped
+	Backpressure { coalescing: bool, fill: f32 },
+	/// A Write changed what the filesystem cache had for `path`, e.g. "size 100->250, content
+	/// changed" (see `NodeDiff::summary`). Needs a database, whose cache holds the previous
+	/// version; writes that change nothing the cache records are not reported.
+	NodeChanged { path: PathBuf, diff: NodeDiff },
+}
+
+/// Sending half of the diagnostics channel; cheap to clone, never blocks
+#[derive(Debug, Clone)]
+pub struct DiagnosticsSender {
+	tx: mpsc::Sender<WatcherDiagnostic>,
+}
+
+impl DiagnosticsSender {
+	/// Create a bounded diagnostics channel
+	pub fn channel() -> (Self, mpsc::Receiver<WatcherDiagnostic>) {
+		let (tx, rx) = mpsc::channel(DIAGNOSTICS_CHANNEL_CAPACITY);
+		(Self { tx }, rx)
+	}
+
+	/// Send without waiting; dropped if the channel is full or closed
+	pub fn emit(&self, diagnostic: WatcherDiagnostic) {
+		if let Err(e) = self.tx.try_send(diagnostic) {
+			debug!("Diagnostic not delivered: {}", e);
+		}
+	}
+}
+
+/// Emit through an optional sender, for call sites where diagnostics may be disabled
+pub(crate) fn emit(sender: Option<&DiagnosticsSender>, diagnostic: WatcherDiagnostic) {
+	if let Some(sender) = sender {
+		sender.emit(diagnostic);
+	}
+}
@@ -27,6 +27,9 @@ pub enum WatcherError {
 	#[error("Failed to send stop signal to watcher")]
 	StopSignal,
 
+	#[error("Watcher did not stop within {timeout:?}, task was aborted")]
+	ShutdownTimeout { timeout: Duration },
+
 	#[error("Watcher not initialized")]
 	NotInitialized,
 
@@ -216,6 +219,7 @@ impl WatcherError {
 			WatcherError::ConfigurationError { .. } => false,
 			WatcherError::NotInitialized => false,
 			WatcherError::StopSignal => false,
+			WatcherError::ShutdownTimeout { .. } => false,
 			WatcherError::RecoveryFailed { .. } => false,
 			WatcherError::Json(_) => false,
 			WatcherError::ValidationError { .. } => false,
@@ -262,6 +266,7 @@ impl WatcherError {
 			WatcherError::ChannelSend => "channel",
 			WatcherError::InvalidPath { .. } => "configuration",
 			WatcherError::StopSignal => "shutdown",
+			WatcherError::ShutdownTimeout { .. } => "shutdown",
 			WatcherError::NotInitialized => "initialization",
 			WatcherError::PermissionDenied { .. } => "permission",
 			WatcherError::ResourceExhausted { .. } => "resource",
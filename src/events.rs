@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum EventType {
 	Create,
 	Write,
@@ -12,7 +12,21 @@ pub enum EventType {
 	RenameTo,   // New name in rename operation
 	Rename,     // Generic rename (when direction unclear)
 	Move,
+	/// A path moved into one matched by `WatcherConfig::ignore_patterns`; see
+	/// `WatcherConfig::moved_to_ignored`. `path` is the watched source, and `move_data` carries
+	/// the ignored destination.
+	MovedToIgnored,
+	/// A create whose content matches a file that still exists elsewhere; see
+	/// `MoveDetectorConfig::detect_copies`. `move_data` carries the existing file as source and
+	/// `path` as destination.
+	Copy,
 	Chmod,
+	/// A notify kind without a dedicated variant, named by [`EventType::notify_kind_name`]
+	///
+	/// From `notify` this is every `Access` kind (e.g. `"access_open"`, `"access_close_write"`),
+	/// `EventKind::Any` (`"any"`) and `EventKind::Other` (`"other"`). Create, modify and remove
+	/// kinds always map to the dedicated variants, whatever their sub-kind. `Other` events are
+	/// never considered for move pairing.
 	Other(String),
 }
 
@@ -29,33 +43,201 @@ impl From<notify::EventKind> for EventType {
 				_ => EventType::Write,
 			},
 			notify::EventKind::Remove(_) => EventType::Remove,
-			notify::EventKind::Access(_) => EventType::Other("Access".to_string()),
-			notify::EventKind::Other => EventType::Other("Unknown".to_string()),
-			_ => EventType::Other(format!("{kind:?}")),
+			notify::EventKind::Access(_) | notify::EventKind::Any | notify::EventKind::Other => {
+				EventType::Other(EventType::notify_kind_name(&kind).to_string())
+			}
+		}
+	}
+}
+
+impl EventType {
+	/// Stable snake_case name of a notify event kind
+	///
+	/// The name is the kind followed by as much sub-kind detail as is useful to consumers, e.g.
+	/// `"create_folder"`, `"modify_name_from"`, `"access_close_write"`. Unlike `Debug` output
+	/// these strings are part of the API and do not change with `notify` versions; a kind
+	/// without a more specific name falls back to its parent (`"access_open"` for every open
+	/// mode). This is the string carried by [`EventType::Other`].
+	pub fn notify_kind_name(kind: &notify::EventKind) -> &'static str {
+		use notify::event::{
+			AccessKind, AccessMode, CreateKind, ModifyKind, RemoveKind, RenameMode,
+		};
+		use notify::EventKind;
+		match kind {
+			EventKind::Any => "any",
+			EventKind::Other => "other",
+			EventKind::Access(access) => match access {
+				AccessKind::Read => "access_read",
+				AccessKind::Open(_) => "access_open",
+				AccessKind::Close(AccessMode::Write) => "access_close_write",
+				AccessKind::Close(_) => "access_close",
+				AccessKind::Any | AccessKind::Other => "access",
+			},
+			EventKind::Create(create) => match create {
+				CreateKind::File => "create_file",
+				CreateKind::Folder => "create_folder",
+				CreateKind::Any | CreateKind::Other => "create",
+			},
+			EventKind::Modify(modify) => match modify {
+				ModifyKind::Data(_) => "modify_data",
+				ModifyKind::Metadata(_) => "modify_metadata",
+				ModifyKind::Name(RenameMode::From) => "modify_name_from",
+				ModifyKind::Name(RenameMode::To) => "modify_name_to",
+				ModifyKind::Name(RenameMode::Both) => "modify_name_both",
+				ModifyKind::Name(_) => "modify_name",
+				ModifyKind::Any | ModifyKind::Other => "modify",
+			},
+			EventKind::Remove(remove) => match remove {
+				RemoveKind::File => "remove_file",
+				RemoveKind::Folder => "remove_folder",
+				RemoveKind::Any | RemoveKind::Other => "remove",
+			},
+		}
+	}
+
+	/// Stable name of this event type: the variant in snake_case, or the carried string for
+	/// [`EventType::Other`]
+	pub fn as_str(&self) -> &str {
+		match self {
+			EventType::Create => "create",
+			EventType::Write => "write",
+			EventType::Remove => "remove",
+			EventType::RenameFrom => "rename_from",
+			EventType::RenameTo => "rename_to",
+			EventType::Rename => "rename",
+			EventType::Move => "move",
+			EventType::MovedToIgnored => "moved_to_ignored",
+			EventType::Copy => "copy",
+			EventType::Chmod => "chmod",
+			EventType::Other(name) => name,
 		}
 	}
 }
 
+/// Serialized form of a `FileSystemEvent`.
+///
+/// Path policy: paths that are valid Unicode serialize as plain strings, exactly as before.
+/// Paths that are not (arbitrary bytes on Unix, unpaired surrogates on Windows) serialize as an
+/// array of the raw OS units - bytes on Unix, UTF-16 code units on Windows - so nothing is lost.
+/// Those arrays are only meaningful on the platform family that produced them. Binary formats
+/// (bincode) keep serde's default `PathBuf` encoding, which rejects non-Unicode paths.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileSystemEvent {
 	pub id: Uuid,
 	pub event_type: EventType,
+	#[serde(with = "path_serde")]
 	pub path: PathBuf,
 	pub timestamp: DateTime<Utc>,
 	pub is_directory: bool,
 	pub size: Option<u64>,
 	pub move_data: Option<MoveEvent>,
+	/// Where `timestamp` came from
+	#[serde(default)]
+	pub timestamp_source: TimestampSource,
+	/// Owner of the file, only captured when `WatcherConfig::capture_ownership` is set
+	#[serde(default)]
+	pub ownership: Option<FileOwnership>,
+	/// A synthetic Create for an entry that already existed at start, reported by the
+	/// `WatcherConfig::emit_existing_on_start` scan rather than observed as a change
+	#[serde(default)]
+	pub snapshot: bool,
+	/// Position in the watch's delivery log, to pass to `WatcherHandle::ack`; only set with
+	/// `WatcherConfig::delivery_log`, and only once the event was logged
+	#[serde(default)]
+	pub sequence: Option<u64>,
+	/// Every path this event's file was seen under, `path` first; only set with
+	/// `WatcherConfig::identity_dedup`, for events collapsed across hard links or mounts
+	#[serde(default, with = "path_list_serde")]
+	pub identity_paths: Vec<PathBuf>,
+	/// Observed during `WatcherConfig::startup_quiet_period`, likely churn that was already
+	/// under way when the watch started
+	#[serde(default)]
+	pub startup: bool,
+	/// Real path behind `path` when it is a symlink or lies below one; only set with
+	/// `WatcherConfig::resolve_targets`, and `None` for a dangling link
+	#[serde(default, with = "path_option_serde")]
+	pub resolved_path: Option<PathBuf>,
+}
+
+/// Numeric Unix owner and group of a file.
+///
+/// Names are not resolved: `getpwuid`/`getgrgid` go through NSS and can block on LDAP or NIS
+/// for each lookup, which is not something to do per event. Consumers that want names can
+/// resolve and cache them on their side.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileOwnership {
+	pub uid: u32,
+	pub gid: u32,
+}
+
+impl FileOwnership {
+	/// Owner from already-read metadata; always `None` off Unix
+	pub fn from_metadata(metadata: &std::fs::Metadata) -> Option<Self> {
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::MetadataExt;
+			Some(Self { uid: metadata.uid(), gid: metadata.gid() })
+		}
+		#[cfg(not(unix))]
+		{
+			let _ = metadata;
+			None
+		}
+	}
+}
+
+/// Origin of `FileSystemEvent::timestamp`.
+///
+/// notify 6.x events carry no time on any backend (inotify, FSEvents, kqueue and
+/// ReadDirectoryChangesW alike), so the live watcher stamps events when it converts them
+/// (`ProcessingTime`) unless `WatcherConfig::timestamp_source` asks for `OsEvent`, which it
+/// then reads from the file's status-change time where it can. Events built from another
+/// source that records the operation time can use `FileSystemEvent::with_os_timestamp`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TimestampSource {
+	/// Time of the filesystem operation as reported by the OS
+	OsEvent,
+	/// Time the event was processed; lags the operation by queueing and processing delay
+	#[default]
+	ProcessingTime,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct MoveEvent {
+	#[serde(with = "path_serde")]
 	pub source_path: PathBuf,
+	#[serde(with = "path_serde")]
 	pub destination_path: PathBuf,
 	pub confidence: f32, // 0.0 to 1.0, how confident we are this is a move
 	pub detection_method: MoveDetectionMethod,
+	/// Paired after the source's Remove had already expired and been delivered; this Move is
+	/// a correction of that Remove (see `MoveDetectorConfig::late_pairing_window`)
+	#[serde(default)]
+	pub late: bool,
+	/// Source and destination share a parent directory, i.e. only the name changed
+	#[serde(default)]
+	pub same_directory: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+impl MoveEvent {
+	/// A non-late move; `same_directory` is derived from the two paths
+	pub fn new(
+		source_path: PathBuf, destination_path: PathBuf, confidence: f32,
+		detection_method: MoveDetectionMethod,
+	) -> Self {
+		let same_directory = source_path.parent() == destination_path.parent();
+		Self {
+			source_path,
+			destination_path,
+			confidence,
+			detection_method,
+			late: false,
+			same_directory,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum MoveDetectionMethod {
 	/// Detected by filesystem events (most reliable)
 	FileSystemEvent,
@@ -75,8 +257,20 @@ pub enum MoveDetectionMethod {
 	MetadataMatching,
 	/// Detected by rename events
 	Rename,
+	/// One half of an atomic exchange (e.g. `renameat2` with `RENAME_EXCHANGE`).
+	///
+	/// Both halves (A->B and B->A) are delivered back to back and describe a single swap:
+	/// applying them one after the other as independent moves would lose one of the files.
+	Exchange,
 	/// Detected by heuristics when other methods uncertain
 	Heuristics,
+	/// Paired by file-name stem along a recently confirmed directory mapping, with nothing
+	/// tying the two files together (e.g. copy + delete across filesystems, or a file
+	/// compressed on the way); see `MoveDetectorConfig::cross_device_stem_matching`
+	CrossDevice,
+	/// A file renamed out of the watched tree that came back, paired with where it left; see
+	/// `MoveDetectorConfig::round_trip_window`
+	RoundTrip,
 }
 
 impl FileSystemEvent {
@@ -91,17 +285,48 @@ impl FileSystemEvent {
 			is_directory,
 			size,
 			move_data: None,
+			timestamp_source: TimestampSource::ProcessingTime,
+			ownership: None,
+			snapshot: false,
+			sequence: None,
+			identity_paths: Vec::new(),
+			startup: false,
+			resolved_path: None,
 		}
 	}
 
+	/// Use a timestamp reported by the OS for this event instead of the processing time.
+	///
+	/// Move detection measures remove->create latency from this time, so a create arriving
+	/// long after its remove was *reported* is not paired just because both were processed
+	/// close together.
+	pub fn with_os_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+		self.timestamp = timestamp;
+		self.timestamp_source = TimestampSource::OsEvent;
+		self
+	}
+
 	pub fn with_move_data(mut self, move_data: MoveEvent) -> Self {
 		self.move_data = Some(move_data);
 		self.event_type = EventType::Move;
 		self
 	}
 
+	/// Report this create as an [`EventType::Copy`] of `source`, whose content it matches
+	pub fn into_copy_of(mut self, source: PathBuf) -> Self {
+		self.move_data = Some(MoveEvent::new(
+			source,
+			self.path.clone(),
+			1.0,
+			MoveDetectionMethod::ContentHash,
+		));
+		self.event_type = EventType::Copy;
+		self
+	}
+
 	pub fn is_move(&self) -> bool {
-		self.move_data.is_some() || self.event_type == EventType::Move
+		self.event_type != EventType::Copy
+			&& (self.move_data.is_some() || self.event_type == EventType::Move)
 	}
 
 	pub fn to_json(&self) -> serde_json::Result<String> {
@@ -109,6 +334,117 @@ impl FileSystemEvent {
 	}
 }
 
+/// Lossless path (de)serialization for human-readable formats; see `FileSystemEvent`.
+mod path_serde {
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+	use std::ffi::OsString;
+	use std::path::{Path, PathBuf};
+
+	#[cfg(unix)]
+	type OsUnit = u8;
+	#[cfg(windows)]
+	type OsUnit = u16;
+
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum HumanReadablePath {
+		Text(String),
+		#[cfg(any(unix, windows))]
+		Raw(Vec<OsUnit>),
+	}
+
+	#[cfg(unix)]
+	fn to_units(path: &Path) -> Vec<OsUnit> {
+		use std::os::unix::ffi::OsStrExt;
+		path.as_os_str().as_bytes().to_vec()
+	}
+
+	#[cfg(windows)]
+	fn to_units(path: &Path) -> Vec<OsUnit> {
+		use std::os::windows::ffi::OsStrExt;
+		path.as_os_str().encode_wide().collect()
+	}
+
+	#[cfg(unix)]
+	fn from_units(units: Vec<OsUnit>) -> PathBuf {
+		use std::os::unix::ffi::OsStringExt;
+		PathBuf::from(OsString::from_vec(units))
+	}
+
+	#[cfg(windows)]
+	fn from_units(units: Vec<OsUnit>) -> PathBuf {
+		use std::os::windows::ffi::OsStringExt;
+		PathBuf::from(OsString::from_wide(&units))
+	}
+
+	pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+		if !serializer.is_human_readable() {
+			return path.serialize(serializer);
+		}
+		match path.to_str() {
+			Some(text) => serializer.serialize_str(text),
+			#[cfg(any(unix, windows))]
+			None => to_units(path).serialize(serializer),
+			#[cfg(not(any(unix, windows)))]
+			None => path.serialize(serializer),
+		}
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+		if !deserializer.is_human_readable() {
+			return PathBuf::deserialize(deserializer);
+		}
+		Ok(match HumanReadablePath::deserialize(deserializer)? {
+			HumanReadablePath::Text(text) => PathBuf::from(text),
+			#[cfg(any(unix, windows))]
+			HumanReadablePath::Raw(units) => from_units(units),
+		})
+	}
+}
+
+/// [`path_serde`] for each path of a list
+mod path_list_serde {
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+	use std::path::PathBuf;
+
+	#[derive(Serialize, Deserialize)]
+	struct Item(#[serde(with = "super::path_serde")] PathBuf);
+
+	pub fn serialize<S: Serializer>(paths: &[PathBuf], serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_seq(paths.iter().map(|path| Item(path.clone())))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Vec<PathBuf>, D::Error> {
+		Ok(Vec::<Item>::deserialize(deserializer)?
+			.into_iter()
+			.map(|Item(path)| path)
+			.collect())
+	}
+}
+
+/// [`path_serde`] for an optional path
+mod path_option_serde {
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+	use std::path::PathBuf;
+
+	#[derive(Serialize, Deserialize)]
+	struct Item(#[serde(with = "super::path_serde")] PathBuf);
+
+	pub fn serialize<S: Serializer>(
+		path: &Option<PathBuf>, serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		path.clone().map(Item).serialize(serializer)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Option<PathBuf>, D::Error> {
+		Ok(Option::<Item>::deserialize(deserializer)?.map(|Item(path)| path))
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -132,6 +468,8 @@ mod tests {
 			MoveDetectionMethod::WindowsId,
 			MoveDetectionMethod::ContentHash,
 			MoveDetectionMethod::NameAndTiming,
+			MoveDetectionMethod::CrossDevice,
+			MoveDetectionMethod::RoundTrip,
 		];
 
 		for method in methods {
@@ -151,6 +489,13 @@ mod tests {
 			is_directory: false,
 			size: Some(100),
 			move_data: None,
+			timestamp_source: TimestampSource::ProcessingTime,
+			ownership: None,
+			snapshot: false,
+			sequence: None,
+			identity_paths: Vec::new(),
+			startup: false,
+			resolved_path: None,
 		};
 
 		assert_eq!(event.event_type, EventType::Create);
@@ -162,12 +507,22 @@ mod tests {
 
 	#[test]
 	fn test_filesystem_event_with_move_data() {
-		let move_event = MoveEvent {
-			source_path: PathBuf::from("/source.txt"),
-			destination_path: PathBuf::from("/dest.txt"),
-			confidence: 0.95,
-			detection_method: MoveDetectionMethod::FileSystemEvent,
-		};
+		let move_event = MoveEvent::new(
+			PathBuf::from("/source.txt"),
+			PathBuf::from("/dest.txt"),
+			0.95,
+			MoveDetectionMethod::FileSystemEvent,
+		);
+		assert!(move_event.same_directory);
+		assert!(
+			!MoveEvent::new(
+				PathBuf::from("/a/source.txt"),
+				PathBuf::from("/b/source.txt"),
+				0.95,
+				MoveDetectionMethod::FileSystemEvent,
+			)
+			.same_directory
+		);
 
 		let mut event = FileSystemEvent {
 			id: uuid::Uuid::new_v4(),
@@ -177,6 +532,13 @@ mod tests {
 			is_directory: false,
 			size: Some(100),
 			move_data: None,
+			timestamp_source: TimestampSource::ProcessingTime,
+			ownership: None,
+			snapshot: false,
+			sequence: None,
+			identity_paths: Vec::new(),
+			startup: false,
+			resolved_path: None,
 		};
 
 		event = event.with_move_data(move_event);
@@ -191,6 +553,64 @@ mod tests {
 		assert_eq!(move_data.confidence, 0.95);
 	}
 
+	#[test]
+	fn test_timestamp_source() {
+		let event = FileSystemEvent::new(EventType::Create, PathBuf::from("/a.txt"), false, None);
+		assert_eq!(event.timestamp_source, TimestampSource::ProcessingTime);
+
+		let os_time = chrono::Utc::now() - chrono::Duration::seconds(5);
+		let event = event.with_os_timestamp(os_time);
+		assert_eq!(event.timestamp_source, TimestampSource::OsEvent);
+		assert_eq!(event.timestamp, os_time);
+
+		// Events serialized before the field existed were all stamped at processing time
+		let mut json: serde_json::Value = serde_json::to_value(&event).unwrap();
+		json.as_object_mut().unwrap().remove("timestamp_source");
+		let old: FileSystemEvent = serde_json::from_value(json).unwrap();
+		assert_eq!(old.timestamp_source, TimestampSource::ProcessingTime);
+	}
+
+	#[test]
+	fn test_notify_kind_names_are_stable() {
+		use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RenameMode};
+		use notify::EventKind;
+
+		let cases = [
+			(
+				EventKind::Access(AccessKind::Close(AccessMode::Write)),
+				"access_close_write",
+			),
+			(
+				EventKind::Access(AccessKind::Open(AccessMode::Read)),
+				"access_open",
+			),
+			(EventKind::Create(CreateKind::Folder), "create_folder"),
+			(
+				EventKind::Modify(ModifyKind::Name(RenameMode::Both)),
+				"modify_name_both",
+			),
+			(EventKind::Any, "any"),
+			(EventKind::Other, "other"),
+		];
+		for (kind, name) in cases {
+			assert_eq!(EventType::notify_kind_name(&kind), name);
+		}
+
+		let close_write = EventType::from(EventKind::Access(AccessKind::Close(AccessMode::Write)));
+		assert_eq!(
+			close_write,
+			EventType::Other("access_close_write".to_string())
+		);
+		assert_eq!(close_write.as_str(), "access_close_write");
+		assert_eq!(EventType::from(EventKind::Other).as_str(), "other");
+		// Kinds with a dedicated variant never end up in Other
+		assert_eq!(
+			EventType::from(EventKind::Create(CreateKind::Folder)),
+			EventType::Create
+		);
+		assert_eq!(EventType::RenameFrom.as_str(), "rename_from");
+	}
+
 	#[test]
 	fn test_event_serialization() {
 		let event = FileSystemEvent {
@@ -201,6 +621,13 @@ mod tests {
 			is_directory: false,
 			size: Some(50),
 			move_data: None,
+			timestamp_source: TimestampSource::ProcessingTime,
+			ownership: None,
+			snapshot: false,
+			sequence: None,
+			identity_paths: Vec::new(),
+			startup: false,
+			resolved_path: None,
 		};
 
 		let json = event.to_json().unwrap();
@@ -208,4 +635,31 @@ mod tests {
 		assert!(json.contains("test.txt"));
 		assert!(json.contains("50"));
 	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_non_utf8_path_json_round_trip() {
+		use std::ffi::OsStr;
+		use std::os::unix::ffi::OsStrExt;
+
+		let path = PathBuf::from(OsStr::from_bytes(b"/test/caf\xe9.txt"));
+		let event = FileSystemEvent::new(EventType::Create, path.clone(), false, Some(1));
+		let json = event.to_json().unwrap();
+		assert!(
+			json.contains("233"),
+			"raw byte should appear in the array: {json}"
+		);
+
+		let restored: FileSystemEvent = serde_json::from_str(&json).unwrap();
+		assert_eq!(restored.path, path);
+
+		// Unicode paths keep the plain string form
+		let plain = FileSystemEvent::new(
+			EventType::Create,
+			PathBuf::from("/test/ok.txt"),
+			false,
+			None,
+		);
+		assert!(plain.to_json().unwrap().contains("\"/test/ok.txt\""));
+	}
 }
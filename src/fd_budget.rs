@@ -0,0 +1,171 @@
+//! File descriptor budget for backends that hold one descriptor per watched entry
+//!
+//! kqueue (the BSDs, and macOS when notify is built with `macos_kqueue`) opens every file and
+//! directory it watches, so a recursive watch of a large tree needs as many descriptors as the
+//! tree has entries. Left alone, registration fails part way through the tree with `EMFILE`
+//! and the watcher reports nothing useful about why.
+//!
+//! Before registering, [`ensure`] counts the entries the registrations cover and compares them
+//! with `RLIMIT_NOFILE`. If the soft limit is too low it is raised up to the hard limit; if even
+//! the hard limit is too low the watcher does not start, with an error naming both counts.
+//! Backends that do not open watched entries (inotify, FSEvents, Windows, polling) are not
+//! checked. The count is a snapshot: entries created afterwards still take descriptors, and
+//! descriptors the process holds elsewhere are only covered by [`FD_HEADROOM`].
+
+use crate::error::{Result, WatcherError};
+use notify::{RecursiveMode, WatcherKind};
+use std::path::PathBuf;
+
+/// Descriptors kept free for the database, sockets and whatever else the process has open
+pub const FD_HEADROOM: u64 = 64;
+
+/// Descriptors `kind` holds for each watched file or directory
+pub fn descriptors_per_entry(kind: WatcherKind) -> u64 {
+	match kind {
+		WatcherKind::Kqueue => 1,
+		_ => 0,
+	}
+}
+
+/// Entries the backend will watch for `registrations`, counting each registered path itself
+pub fn estimate(registrations: &[(PathBuf, RecursiveMode)]) -> u64 {
+	registrations
+		.iter()
+		.map(|(path, mode)| {
+			let mut walk = walkdir::WalkDir::new(path).follow_links(false);
+			if *mode == RecursiveMode::NonRecursive {
+				walk = walk.max_depth(1);
+			}
+			walk.into_iter().filter_map(|entry| entry.ok()).count() as u64
+		})
+		.sum()
+}
+
+/// What to do about a need of `needed` descriptors given the current limits: nothing
+/// (`Ok(None)`), raise the soft limit to the returned value, or give up
+pub fn plan(needed: u64, soft: u64, hard: u64) -> Result<Option<u64>> {
+	let needed = needed.saturating_add(FD_HEADROOM);
+	if needed <= soft {
+		Ok(None)
+	} else if needed <= hard {
+		Ok(Some(needed))
+	} else {
+		Err(WatcherError::ResourceExhausted {
+			resource: "file descriptors".to_string(),
+			details: format!(
+				"watching needs about {needed} descriptors (including {FD_HEADROOM} spare) but \
+				 the hard limit is {hard}; raise it (ulimit -Hn) or watch a smaller tree"
+			),
+			current_usage: needed.to_string(),
+			limit: hard.to_string(),
+		})
+	}
+}
+
+/// A descriptor need the limit could not be raised to meet
+#[derive(Debug)]
+pub struct Shortfall {
+	pub needed: u64,
+	pub limit: u64,
+	pub error: WatcherError,
+}
+
+/// Make sure the descriptor limit covers `registrations` on a `kind` backend, raising the soft
+/// limit if needed
+pub fn ensure(
+	registrations: &[(PathBuf, RecursiveMode)], kind: WatcherKind,
+) -> std::result::Result<(), Shortfall> {
+	let per_entry = descriptors_per_entry(kind);
+	if per_entry == 0 {
+		return Ok(());
+	}
+	let needed = estimate(registrations).saturating_mul(per_entry);
+	ensure_limit(needed)
+}
+
+#[cfg(unix)]
+fn ensure_limit(needed: u64) -> std::result::Result<(), Shortfall> {
+	use nix::sys::resource::{getrlimit, setrlimit, Resource};
+	use tracing::{info, warn};
+
+	let (soft, hard) = match getrlimit(Resource::RLIMIT_NOFILE) {
+		Ok(limits) => limits,
+		Err(e) => {
+			warn!("Could not read the file descriptor limit: {}", e);
+			return Ok(());
+		}
+	};
+	// macOS rejects soft limits above OPEN_MAX even when the hard limit is unlimited
+	let hard = if cfg!(target_os = "macos") { hard.min(10240) } else { hard };
+	match plan(needed, soft, hard) {
+		Ok(None) => Ok(()),
+		Ok(Some(raised)) => match setrlimit(Resource::RLIMIT_NOFILE, raised, hard) {
+			Ok(()) => {
+				info!(
+					"Raised the file descriptor limit from {} to {} for {} watched entries",
+					soft, raised, needed
+				);
+				Ok(())
+			}
+			Err(e) => {
+				let error = WatcherError::ResourceExhausted {
+					resource: "file descriptors".to_string(),
+					details: format!(
+						"watching needs about {raised} descriptors but the limit is {soft} and \
+						 raising it failed: {e}"
+					),
+					current_usage: raised.to_string(),
+					limit: soft.to_string(),
+				};
+				Err(Shortfall { needed: raised, limit: soft, error })
+			}
+		},
+		Err(error) => {
+			Err(Shortfall { needed: needed.saturating_add(FD_HEADROOM), limit: hard, error })
+		}
+	}
+}
+
+#[cfg(not(unix))]
+fn ensure_limit(_needed: u64) -> std::result::Result<(), Shortfall> {
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_budget_is_kept_raised_or_reported() {
+		assert_eq!(plan(100, 256, 1024).unwrap(), None);
+		assert_eq!(plan(500, 256, 1024).unwrap(), Some(500 + FD_HEADROOM));
+
+		let error = plan(100_000, 256, 10240).unwrap_err();
+		let message = error.to_string();
+		assert!(message.contains("file descriptors"), "{message}");
+		assert!(
+			message.contains(&(100_000 + FD_HEADROOM).to_string()),
+			"{message}"
+		);
+		assert!(message.contains("10240"), "{message}");
+		assert!(error.is_resource_limit());
+	}
+
+	#[test]
+	fn test_estimate_counts_watched_entries() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let root = temp_dir.path();
+		std::fs::create_dir_all(root.join("a/b")).unwrap();
+		std::fs::write(root.join("top.txt"), "x").unwrap();
+		std::fs::write(root.join("a/b/deep.txt"), "x").unwrap();
+
+		let recursive = [(root.to_path_buf(), RecursiveMode::Recursive)];
+		assert_eq!(estimate(&recursive), 5);
+		let flat = [(root.to_path_buf(), RecursiveMode::NonRecursive)];
+		assert_eq!(estimate(&flat), 3);
+
+		assert_eq!(descriptors_per_entry(WatcherKind::Kqueue), 1);
+		assert_eq!(descriptors_per_entry(WatcherKind::Inotify), 0);
+		assert!(ensure(&recursive, WatcherKind::Inotify).is_ok());
+	}
+}
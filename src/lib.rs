@@ -1,17 +1,40 @@
 pub mod database;
+pub mod diagnostics;
 mod error;
 mod events;
+pub(crate) mod fd_budget;
 pub mod filesystem_poc;
+pub(crate) mod long_paths;
+pub mod metrics;
+#[cfg(feature = "mirror")]
+pub mod mirror;
 pub mod move_detection;
 mod retry;
+pub mod runtime;
+pub mod sink;
 mod watcher;
 
-pub use database::{DatabaseAdapter, DatabaseConfig, DatabaseStorage, RedbStorage};
+pub use database::{
+	CircuitBreakerConfig, CustomParams, DatabaseAdapter, DatabaseConfig, DatabaseStorage,
+	Durability, DurabilityPreference, RedbStorage, SerializationFormat,
+};
+pub use diagnostics::WatcherDiagnostic;
 pub use error::{ErrorRecoveryConfig, Result, WatcherError};
-pub use events::{EventType, FileSystemEvent, MoveDetectionMethod, MoveEvent};
-pub use move_detection::{MoveDetector, MoveDetectorConfig};
+pub use events::{
+	EventType, FileOwnership, FileSystemEvent, MoveDetectionMethod, MoveEvent, TimestampSource,
+};
+pub use metrics::{SummaryEvent, WatcherStats};
+pub use move_detection::{
+	diff_trees, diff_trees_with_config, BlendedConfidenceScorer, BoundedHasher,
+	CacheMismatchPolicy, ConfidenceScorer, ContentHasher, MoveDetector, MoveDetectorConfig,
+	MoveScope, ThresholdOverride, TimeoutOverride, XxHashContentHasher,
+};
 pub use retry::{RetryConfigBuilder, RetryManager, RetryableOperation};
-pub use watcher::{start, WatcherConfig, WatcherHandle};
+pub use sink::EventSink;
+pub use watcher::{
+	start, start_with_diagnostics, AdaptiveCoalescing, KnownOperation, MovedToIgnoredPolicy,
+	WatchTargets, WatcherConfig, WatcherHandle, DEFAULT_SIDECAR_PATTERNS,
+};
 
 #[cfg(test)]
 pub use crate::move_detection::test_helpers::DummyCache;
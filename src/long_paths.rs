@@ -0,0 +1,108 @@
+//! Paths longer than the Windows `MAX_PATH` limit
+//!
+//! Without long-path support enabled in the OS and the application manifest, Win32 calls fail
+//! for paths of 260 characters or more, usually as "path not found". The verbatim `\\?\`
+//! prefix lifts the limit for one call. Elsewhere paths are passed through unchanged, and the
+//! only length failure is the OS's own (`ENAMETOOLONG`).
+//!
+//! Rust's standard library already adds the prefix to absolute paths in its own filesystem
+//! calls. Applying it here as well covers the paths handed to other code, such as a custom
+//! `ContentHasher`, and makes failures that remain (e.g. on paths with `..` components)
+//! recognizable as length failures.
+
+use std::borrow::Cow;
+use std::io;
+use std::path::Path;
+
+/// Length from which Win32 calls without long-path support fail
+pub const MAX_PATH: usize = 260;
+
+/// `path` in the form to hand to filesystem calls.
+///
+/// On Windows with `prefix` set, absolute drive and UNC paths of `MAX_PATH` or more are turned
+/// into their verbatim form. Verbatim paths are not normalized by the OS, so paths with `..`
+/// components, relative paths and paths that already carry a prefix are left alone.
+pub fn for_fs(path: &Path, prefix: bool) -> Cow<'_, Path> {
+	#[cfg(windows)]
+	{
+		if prefix && path.as_os_str().len() >= MAX_PATH {
+			if let Some(verbatim) = verbatim(path) {
+				return Cow::Owned(verbatim);
+			}
+		}
+	}
+	let _ = prefix;
+	Cow::Borrowed(path)
+}
+
+/// Whether `error`, from a filesystem call on `path`, is caused by the path's length
+pub fn is_too_long(error: &io::Error, path: &Path) -> bool {
+	const ERROR_PATH_NOT_FOUND: i32 = 3;
+	const ERROR_INVALID_NAME: i32 = 123;
+	match error.kind() {
+		// ENAMETOOLONG, or ERROR_FILENAME_EXCED_RANGE on Windows
+		io::ErrorKind::InvalidFilename if error.raw_os_error() != Some(ERROR_INVALID_NAME) => true,
+		// What Win32 reports for most over-long paths, though not only for them
+		_ => {
+			cfg!(windows)
+				&& matches!(
+					error.raw_os_error(),
+					Some(ERROR_PATH_NOT_FOUND | ERROR_INVALID_NAME)
+				) && path.as_os_str().len() >= MAX_PATH
+		}
+	}
+}
+
+#[cfg(windows)]
+fn verbatim(path: &Path) -> Option<std::path::PathBuf> {
+	use std::ffi::OsString;
+	use std::path::{Component, Prefix};
+
+	let mut components = path.components();
+	let Some(Component::Prefix(prefix)) = components.next() else {
+		return None;
+	};
+	let mut verbatim = OsString::from(r"\\?\");
+	match prefix.kind() {
+		Prefix::Disk(_) => verbatim.push(prefix.as_os_str()),
+		Prefix::UNC(server, share) => {
+			verbatim.push(r"UNC\");
+			verbatim.push(server);
+			verbatim.push(r"\");
+			verbatim.push(share);
+		}
+		_ => return None,
+	}
+	for component in components {
+		match component {
+			Component::RootDir | Component::CurDir => {}
+			Component::Normal(name) => {
+				verbatim.push(r"\");
+				verbatim.push(name);
+			}
+			Component::ParentDir | Component::Prefix(_) => return None,
+		}
+	}
+	Some(verbatim.into())
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_long_paths_get_the_verbatim_prefix() {
+		let long = format!(r"C:\{}\file.txt", "d".repeat(MAX_PATH));
+		let prefixed = for_fs(Path::new(&long), true);
+		assert_eq!(prefixed.as_os_str(), format!(r"\\?\{long}").as_str());
+		assert_eq!(for_fs(Path::new(&long), false).as_os_str(), long.as_str());
+		assert_eq!(
+			for_fs(Path::new(r"C:\short.txt"), true).as_os_str(),
+			r"C:\short.txt"
+		);
+
+		let unc = format!(r"\\server\share\{}", "d".repeat(MAX_PATH));
+		let expected = format!(r"\\?\UNC\server\share\{}", "d".repeat(MAX_PATH));
+		assert_eq!(for_fs(Path::new(&unc), true).as_os_str(), expected.as_str());
+	}
+}
@@ -47,6 +47,7 @@ async fn main() -> anyhow::Result<()> {
 			database_path: PathBuf::from("./watcher.redb"),
 			..Default::default()
 		}),
+		..Default::default()
 	};
 
 	// Start watching and get the event receiver
@@ -0,0 +1,143 @@
+//! Minimal HTTP endpoint for health checks and scraping (`http-metrics` feature).
+//!
+//! Routes, all `GET`:
+//! - `/healthz`: `200 ok` while [`crate::WatcherHandle::healthy`], `503 unavailable` otherwise
+//! - `/metrics`: [`WatcherStats::to_prometheus`], Prometheus text format
+//! - `/stats`: [`WatcherStats`] as JSON
+//!
+//! Anything else gets a 404, other methods a 405. HTTP/1.1 only, no TLS and no
+//! authentication: bind it to loopback or a private interface. Each connection is served on
+//! its own task; requests never touch the event loop.
+
+use super::{is_healthy, WatcherMetrics, WatcherStats};
+use crate::error::Result;
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Wait after a failed `accept`, doubled on each further failure up to
+/// [`MAX_ACCEPT_BACKOFF`]; errors such as running out of file descriptors persist until
+/// connections close, and retrying at once would spin
+const MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A running metrics server; stops accepting connections when dropped
+#[derive(Debug)]
+pub struct MetricsServer {
+	local_addr: SocketAddr,
+	task: tokio::task::JoinHandle<()>,
+}
+
+impl MetricsServer {
+	/// Address the server is bound to, with the actual port when 0 was requested
+	pub fn local_addr(&self) -> SocketAddr {
+		self.local_addr
+	}
+}
+
+impl Drop for MetricsServer {
+	fn drop(&mut self) {
+		// Connections already accepted run on their own tasks and finish their request
+		self.task.abort();
+	}
+}
+
+#[derive(Clone)]
+struct Source {
+	ready: watch::Receiver<bool>,
+	metrics: Arc<WatcherMetrics>,
+}
+
+impl Source {
+	fn stats(&self) -> WatcherStats {
+		self.metrics.snapshot(is_healthy(&self.ready))
+	}
+}
+
+pub(crate) async fn serve(
+	addr: SocketAddr, ready: watch::Receiver<bool>, metrics: Arc<WatcherMetrics>,
+) -> Result<MetricsServer> {
+	let listener = TcpListener::bind(addr).await?;
+	let local_addr = listener.local_addr()?;
+	info!("Serving watcher metrics on http://{}", local_addr);
+	let source = Source { ready, metrics };
+	let task = tokio::spawn(async move {
+		let mut backoff = MIN_ACCEPT_BACKOFF;
+		loop {
+			let stream = match listener.accept().await {
+				Ok((stream, _)) => {
+					backoff = MIN_ACCEPT_BACKOFF;
+					stream
+				}
+				Err(e) => {
+					warn!(
+						"Metrics server failed to accept a connection, retrying in {:?}: {}",
+						backoff, e
+					);
+					tokio::time::sleep(backoff).await;
+					backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+					continue;
+				}
+			};
+			let source = source.clone();
+			tokio::spawn(async move {
+				let service = service_fn(move |request| {
+					let response = respond(&source, &request);
+					async move { Ok::<_, Infallible>(response) }
+				});
+				if let Err(e) =
+					http1::Builder::new().serve_connection(TokioIo::new(stream), service).await
+				{
+					debug!("Metrics connection ended with an error: {}", e);
+				}
+			});
+		}
+	});
+	Ok(MetricsServer { local_addr, task })
+}
+
+fn respond(source: &Source, request: &Request<Incoming>) -> Response<Full<Bytes>> {
+	if request.method() != Method::GET {
+		return text(StatusCode::METHOD_NOT_ALLOWED, "method not allowed\n");
+	}
+	match request.uri().path() {
+		"/healthz" if is_healthy(&source.ready) => text(StatusCode::OK, "ok\n"),
+		"/healthz" => text(StatusCode::SERVICE_UNAVAILABLE, "unavailable\n"),
+		"/metrics" => response(
+			StatusCode::OK,
+			PROMETHEUS_CONTENT_TYPE,
+			source.stats().to_prometheus(),
+		),
+		"/stats" => match serde_json::to_string(&source.stats()) {
+			Ok(json) => response(StatusCode::OK, "application/json", json),
+			Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e}\n")),
+		},
+		_ => text(StatusCode::NOT_FOUND, "not found\n"),
+	}
+}
+
+fn text(status: StatusCode, body: &str) -> Response<Full<Bytes>> {
+	response(status, "text/plain; charset=utf-8", body.to_string())
+}
+
+fn response(status: StatusCode, content_type: &str, body: String) -> Response<Full<Bytes>> {
+	let mut response = Response::new(Full::new(Bytes::from(body)));
+	*response.status_mut() = status;
+	if let Ok(value) = content_type.parse() {
+		response.headers_mut().insert(CONTENT_TYPE, value);
+	}
+	response
+}
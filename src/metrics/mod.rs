@@ -0,0 +1,281 @@
+//! Counters a running watcher keeps, for [`crate::WatcherHandle::stats`].
+//!
+//! Counters are plain relaxed atomics bumped on the event loop, so reading them never waits on
+//! the loop; a snapshot taken while events are in flight may be off by the events in flight.
+//! They start at zero with each `start()` and are not persisted.
+//!
+//! With the `http-metrics` feature the same snapshot is served over HTTP, see [`http`]; with the
+//! `prometheus` feature it can be registered in an existing Prometheus registry instead, see
+//! [`prometheus`](self::prometheus).
+
+#[cfg(feature = "http-metrics")]
+pub mod http;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+
+use crate::events::{EventType, FileSystemEvent};
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Ready flag published by the watcher task, still held by that task
+pub(crate) fn is_healthy(ready: &watch::Receiver<bool>) -> bool {
+	// The sender is dropped when the watcher task ends, for whatever reason
+	*ready.borrow() && ready.has_changed().is_ok()
+}
+
+/// Live counters shared between the event loop and the handle
+#[derive(Debug, Default)]
+pub(crate) struct WatcherMetrics {
+	events_received: AtomicU64,
+	events_delivered: AtomicU64,
+	moves_detected: AtomicU64,
+	creates_delivered: AtomicU64,
+	writes_delivered: AtomicU64,
+	removes_delivered: AtomicU64,
+	bytes_changed: AtomicU64,
+	persistence_errors: AtomicU64,
+}
+
+impl WatcherMetrics {
+	pub(crate) fn record_received(&self) {
+		self.events_received.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub(crate) fn record_delivered(&self, event: &FileSystemEvent) {
+		self.events_delivered.fetch_add(1, Ordering::Relaxed);
+		if event.is_move() {
+			self.moves_detected.fetch_add(1, Ordering::Relaxed);
+		}
+		let counter = match event.event_type {
+			EventType::Create => &self.creates_delivered,
+			EventType::Write => &self.writes_delivered,
+			EventType::Remove => &self.removes_delivered,
+			_ => return,
+		};
+		counter.fetch_add(1, Ordering::Relaxed);
+		if event.event_type != EventType::Remove && !event.is_directory {
+			let size = event.size.unwrap_or(0);
+			self.bytes_changed.fetch_add(size, Ordering::Relaxed);
+		}
+	}
+
+	pub(crate) fn record_persistence_error(&self) {
+		self.persistence_errors.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub(crate) fn snapshot(&self, healthy: bool) -> WatcherStats {
+		WatcherStats {
+			healthy,
+			events_received: self.events_received.load(Ordering::Relaxed),
+			events_delivered: self.events_delivered.load(Ordering::Relaxed),
+			moves_detected: self.moves_detected.load(Ordering::Relaxed),
+			creates_delivered: self.creates_delivered.load(Ordering::Relaxed),
+			writes_delivered: self.writes_delivered.load(Ordering::Relaxed),
+			removes_delivered: self.removes_delivered.load(Ordering::Relaxed),
+			bytes_changed: self.bytes_changed.load(Ordering::Relaxed),
+			persistence_errors: self.persistence_errors.load(Ordering::Relaxed),
+		}
+	}
+}
+
+/// Point-in-time copy of a watcher's counters
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WatcherStats {
+	/// See [`crate::WatcherHandle::healthy`]
+	pub healthy: bool,
+	/// Paths reported by the backend that passed the callback filters (event types, own
+	/// database, targets, ignore patterns). One backend event can carry several paths.
+	pub events_received: u64,
+	/// Events sent to the receiver, after move detection, `event_types` and stabilization
+	pub events_delivered: u64,
+	/// Delivered events that carry move data
+	pub moves_detected: u64,
+	/// Delivered Create events
+	pub creates_delivered: u64,
+	/// Delivered Write events
+	pub writes_delivered: u64,
+	/// Delivered Remove events
+	pub removes_delivered: u64,
+	/// Sum of the sizes of delivered file Creates and Writes, i.e. the bytes that may have
+	/// changed rather than an exact diff
+	pub bytes_changed: u64,
+	/// Failed database writes of events or metadata
+	pub persistence_errors: u64,
+}
+
+impl WatcherStats {
+	/// Prometheus text exposition format (version 0.0.4)
+	pub fn to_prometheus(&self) -> String {
+		let mut out = String::new();
+		for series in self.series() {
+			let (name, kind) = (series.name, if series.gauge { "gauge" } else { "counter" });
+			let _ = writeln!(out, "# HELP rust_watcher_{name} {}", series.help);
+			let _ = writeln!(out, "# TYPE rust_watcher_{name} {kind}");
+			let _ = writeln!(out, "rust_watcher_{name} {}", series.value);
+		}
+		out
+	}
+
+	/// Every exported metric, in the same order whatever the values
+	pub(crate) fn series(&self) -> Vec<MetricSeries> {
+		let counter = |name: &str, help: &str, value: u64| MetricSeries {
+			name: name.to_string(),
+			gauge: false,
+			help: help.to_string(),
+			value,
+		};
+		let mut series = vec![
+			MetricSeries {
+				gauge: true,
+				..counter(
+					"up",
+					"1 while the watcher is running and capturing changes",
+					self.healthy as u64,
+				)
+			},
+			counter(
+				"events_received_total",
+				"Paths reported by the backend after filtering",
+				self.events_received,
+			),
+			counter(
+				"events_delivered_total",
+				"Events sent to the receiver",
+				self.events_delivered,
+			),
+			counter(
+				"moves_detected_total",
+				"Delivered events carrying move data",
+				self.moves_detected,
+			),
+		];
+		for (kind, value) in [
+			("create", self.creates_delivered),
+			("write", self.writes_delivered),
+			("remove", self.removes_delivered),
+		] {
+			series.push(counter(
+				&format!("{kind}_events_delivered_total"),
+				&format!("Delivered {kind} events"),
+				value,
+			));
+		}
+		series.push(counter(
+			"bytes_changed_total",
+			"Sum of the sizes of delivered file creates and writes",
+			self.bytes_changed,
+		));
+		series.push(counter(
+			"persistence_errors_total",
+			"Failed database writes",
+			self.persistence_errors,
+		));
+		series
+	}
+
+	/// Counts added since `earlier`, a snapshot of the same watcher taken `period` ago
+	pub(crate) fn summary_since(&self, earlier: &WatcherStats, period: Duration) -> SummaryEvent {
+		SummaryEvent {
+			period,
+			creates: self.creates_delivered - earlier.creates_delivered,
+			writes: self.writes_delivered - earlier.writes_delivered,
+			removes: self.removes_delivered - earlier.removes_delivered,
+			moves: self.moves_detected - earlier.moves_detected,
+			bytes_changed: self.bytes_changed - earlier.bytes_changed,
+		}
+	}
+}
+
+/// One metric of [`WatcherStats::to_prometheus`], named without the `rust_watcher_` prefix
+pub(crate) struct MetricSeries {
+	pub(crate) name: String,
+	/// A gauge rather than a counter
+	pub(crate) gauge: bool,
+	pub(crate) help: String,
+	pub(crate) value: u64,
+}
+
+/// Delivered events over one `WatcherConfig::summary_interval`, pushed on the diagnostics
+/// channel as [`crate::WatcherDiagnostic::Summary`]
+///
+/// Built from the same counters as [`WatcherStats`], so the counts follow the same rules:
+/// events are counted as delivered, after `event_types` filtering and write stabilization.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SummaryEvent {
+	/// Time covered, from the previous summary (or the start of the watcher) to this one
+	pub period: Duration,
+	pub creates: u64,
+	pub writes: u64,
+	pub removes: u64,
+	/// Events carrying move data, whatever their type
+	pub moves: u64,
+	/// See [`WatcherStats::bytes_changed`]
+	pub bytes_changed: u64,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
+
+	#[test]
+	fn test_prometheus_text_lists_every_counter() {
+		let metrics = WatcherMetrics::default();
+		metrics.record_received();
+		metrics.record_delivered(&moved());
+		metrics.record_delivered(&FileSystemEvent::new(
+			EventType::Create,
+			PathBuf::from("/b"),
+			false,
+			Some(5),
+		));
+		let text = metrics.snapshot(true).to_prometheus();
+
+		assert!(text.contains("rust_watcher_up 1\n"), "{text}");
+		assert!(text.contains("rust_watcher_events_received_total 1\n"));
+		assert!(text.contains("rust_watcher_events_delivered_total 2\n"));
+		assert!(text.contains("rust_watcher_moves_detected_total 1\n"));
+		assert!(text.contains("rust_watcher_create_events_delivered_total 1\n"));
+		assert!(text.contains("rust_watcher_bytes_changed_total 5\n"));
+		assert!(text.contains("# TYPE rust_watcher_persistence_errors_total counter\n"));
+	}
+
+	#[test]
+	fn test_summary_counts_only_what_was_delivered_in_between() {
+		let metrics = WatcherMetrics::default();
+		let write = FileSystemEvent::new(EventType::Write, PathBuf::from("/a"), false, Some(7));
+		metrics.record_delivered(&write);
+		let earlier = metrics.snapshot(true);
+		metrics.record_delivered(&write);
+		metrics.record_delivered(&moved());
+		let remove = FileSystemEvent::new(EventType::Remove, PathBuf::from("/c"), false, Some(9));
+		metrics.record_delivered(&remove);
+
+		let summary = metrics.snapshot(true).summary_since(&earlier, Duration::from_secs(1));
+		assert_eq!(
+			summary,
+			SummaryEvent {
+				period: Duration::from_secs(1),
+				creates: 0,
+				writes: 1,
+				removes: 1,
+				moves: 1,
+				bytes_changed: 7,
+			}
+		);
+	}
+
+	fn moved() -> FileSystemEvent {
+		let mut event = FileSystemEvent::new(EventType::Move, PathBuf::from("/a"), false, None);
+		event.move_data = Some(crate::events::MoveEvent::new(
+			PathBuf::from("/z"),
+			PathBuf::from("/a"),
+			1.0,
+			crate::events::MoveDetectionMethod::Rename,
+		));
+		event
+	}
+}
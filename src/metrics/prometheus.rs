@@ -0,0 +1,100 @@
+//! A watcher's counters in a caller's [`::prometheus::Registry`] (`prometheus` feature).
+//!
+//! For applications that already expose a registry through their own server, where
+//! [`http`](super::http) would be a second one. The values are read from the watcher's counters
+//! whenever the registry is gathered, so a scrape sees what [`WatcherStats`] would show at that
+//! moment; nothing is copied in between.
+//!
+//! The metrics are those of [`WatcherStats::to_prometheus`], each with a `watch_id` label
+//! holding `WatcherConfig::watch_id` so several watchers can share one registry:
+//! - `rust_watcher_up` (gauge): 1 while [`crate::WatcherHandle::healthy`]
+//! - `rust_watcher_events_received_total`: [`WatcherStats::events_received`]
+//! - `rust_watcher_events_delivered_total`: [`WatcherStats::events_delivered`]
+//! - `rust_watcher_moves_detected_total`: [`WatcherStats::moves_detected`]
+//! - `rust_watcher_create_events_delivered_total`, `rust_watcher_write_events_delivered_total`
+//!   and `rust_watcher_remove_events_delivered_total`: the per-type delivered counts
+//! - `rust_watcher_bytes_changed_total`: [`WatcherStats::bytes_changed`]
+//! - `rust_watcher_persistence_errors_total`: [`WatcherStats::persistence_errors`]
+//!
+//! Counters start at zero with each `start()`; a restarted watcher with the same `watch_id`
+//! looks like a counter reset to Prometheus, which its `rate()` handles.
+
+use super::{is_healthy, WatcherMetrics, WatcherStats};
+use ::prometheus::core::{Collector, Desc};
+use ::prometheus::proto::MetricFamily;
+use ::prometheus::{IntCounter, IntGauge, Opts, Registry};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+enum Metric {
+	Gauge(IntGauge),
+	Counter(IntCounter),
+}
+
+impl Metric {
+	fn collector(&self) -> &dyn Collector {
+		match self {
+			Metric::Gauge(gauge) => gauge,
+			Metric::Counter(counter) => counter,
+		}
+	}
+
+	fn set(&self, value: u64) {
+		match self {
+			Metric::Gauge(gauge) => gauge.set(value as i64),
+			Metric::Counter(counter) => {
+				counter.reset();
+				counter.inc_by(value);
+			}
+		}
+	}
+}
+
+struct WatcherCollector {
+	ready: watch::Receiver<bool>,
+	source: Arc<WatcherMetrics>,
+	descs: Vec<Desc>,
+	/// In `WatcherStats::series` order; locked so concurrent gathers do not interleave updates
+	metrics: Mutex<Vec<Metric>>,
+}
+
+impl Collector for WatcherCollector {
+	fn desc(&self) -> Vec<&Desc> {
+		self.descs.iter().collect()
+	}
+
+	fn collect(&self) -> Vec<MetricFamily> {
+		let stats = self.source.snapshot(is_healthy(&self.ready));
+		let metrics = self.metrics.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+		metrics
+			.iter()
+			.zip(stats.series())
+			.flat_map(|(metric, series)| {
+				metric.set(series.value);
+				metric.collector().collect()
+			})
+			.collect()
+	}
+}
+
+pub(crate) fn register(
+	registry: &Registry, watch_id: uuid::Uuid, ready: watch::Receiver<bool>,
+	source: Arc<WatcherMetrics>,
+) -> ::prometheus::Result<()> {
+	let mut metrics = Vec::new();
+	for series in WatcherStats::default().series() {
+		let opts = Opts::new(format!("rust_watcher_{}", series.name), series.help)
+			.const_label("watch_id", watch_id.to_string());
+		metrics.push(match series.gauge {
+			true => Metric::Gauge(IntGauge::with_opts(opts)?),
+			false => Metric::Counter(IntCounter::with_opts(opts)?),
+		});
+	}
+	let descs = metrics.iter().flat_map(|metric| metric.collector().desc()).cloned().collect();
+	registry.register(Box::new(WatcherCollector {
+		ready,
+		source,
+		descs,
+		metrics: Mutex::new(metrics),
+	}))
+}
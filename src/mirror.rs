@@ -0,0 +1,269 @@
+//! Rebuild the structure of a watched tree from its event stream
+//!
+//! [`DirectoryMirror`] is a reference consumer: it applies delivered events to an in-memory
+//! map of paths (with file sizes, not contents) and can write that structure out as a
+//! directory of placeholder files. Sync tools can start from it; tests use it to check that the
+//! events of a run are enough to follow the tree, moves of whole directories included.
+//!
+//! Set `MoveDetectorConfig::round_trip_window` on the watcher: without it a rename the detector
+//! cannot pair (out of the tree, or into a directory created a moment earlier and not watched
+//! yet) is dropped rather than reported as a Remove, and the mirror keeps the old name.
+//!
+//! Limitations:
+//! - Contents are not mirrored; `materialize` creates files of the recorded size, zero-filled.
+//! - A directory renamed in from outside the tree arrives as a single create; its contents
+//!   show up only as far as the watcher's catch-up scan reports them.
+//! - Changes below a directory right after it was renamed can be reported under its old path
+//!   (the backend's watch still carries it); those changes are lost to the mirror.
+//! - Symlinks are recorded as whatever `is_directory` said, never as links.
+
+use crate::events::{EventType, FileSystemEvent};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One path in a [`DirectoryMirror`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MirrorEntry {
+	pub is_directory: bool,
+	/// Last size reported for a file; `None` for directories and files never reported with one
+	pub size: Option<u64>,
+}
+
+/// The paths below `root` as the applied events describe them
+#[derive(Debug, Clone)]
+pub struct DirectoryMirror {
+	root: PathBuf,
+	entries: BTreeMap<PathBuf, MirrorEntry>,
+}
+
+impl DirectoryMirror {
+	/// An empty mirror of `root`; events for paths outside it are ignored
+	pub fn new(root: impl Into<PathBuf>) -> Self {
+		Self { root: root.into(), entries: BTreeMap::new() }
+	}
+
+	/// A mirror of what is below `root` on disk right now, to apply events on top of
+	///
+	/// Start the watcher (and wait for `WatcherHandle::ready`) before scanning, so nothing that
+	/// changes in between is missed; events for entries the scan already saw are harmless.
+	pub fn scan(root: impl Into<PathBuf>) -> io::Result<Self> {
+		let mut mirror = Self::new(root);
+		for entry in walkdir::WalkDir::new(&mirror.root).min_depth(1) {
+			let entry = entry.map_err(io::Error::other)?;
+			let metadata = entry.metadata().map_err(io::Error::other)?;
+			let is_directory = metadata.is_dir();
+			let size = (!is_directory).then_some(metadata.len());
+			mirror.entries.insert(entry.into_path(), MirrorEntry { is_directory, size });
+		}
+		Ok(mirror)
+	}
+
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+
+	/// Every mirrored path, absolute, in path order
+	pub fn entries(&self) -> &BTreeMap<PathBuf, MirrorEntry> {
+		&self.entries
+	}
+
+	/// Mirrored paths relative to `root`, for comparing against another tree
+	pub fn relative_paths(&self) -> BTreeSet<PathBuf> {
+		self.entries
+			.keys()
+			.filter_map(|path| path.strip_prefix(&self.root).ok())
+			.map(Path::to_path_buf)
+			.collect()
+	}
+
+	/// Update the mirror for one delivered event
+	///
+	/// Creates, copies and writes record the path (and the directories above it); removes drop
+	/// it with everything below it. Moves carry the source's subtree to the destination, or
+	/// record the destination when the source is unknown, as after a late pairing whose remove
+	/// was already applied. A `Rename` without `move_data` has no direction, so it is resolved
+	/// by checking whether the path still exists. Permission and access events change nothing.
+	pub fn apply(&mut self, event: &FileSystemEvent) {
+		match &event.event_type {
+			EventType::Create | EventType::Copy | EventType::Write | EventType::RenameTo => {
+				self.upsert(&event.path, event.is_directory, event.size);
+			}
+			EventType::Remove | EventType::RenameFrom | EventType::MovedToIgnored => {
+				self.remove(&event.path);
+			}
+			EventType::Move | EventType::Rename => match &event.move_data {
+				Some(move_data) => {
+					self.relocate(&move_data.source_path, &move_data.destination_path);
+					self.upsert(&move_data.destination_path, event.is_directory, event.size);
+				}
+				None if std::fs::symlink_metadata(&event.path).is_ok() => {
+					self.upsert(&event.path, event.is_directory, event.size);
+				}
+				None => self.remove(&event.path),
+			},
+			EventType::Chmod | EventType::Other(_) => {}
+		}
+	}
+
+	/// Create the mirrored structure below `target`: directories, and files of the recorded
+	/// size (zero-filled, usually sparse). Existing entries are left alone or resized; nothing
+	/// is deleted.
+	pub fn materialize(&self, target: &Path) -> io::Result<()> {
+		std::fs::create_dir_all(target)?;
+		for (path, entry) in &self.entries {
+			let Ok(relative) = path.strip_prefix(&self.root) else {
+				continue;
+			};
+			let destination = target.join(relative);
+			if entry.is_directory {
+				std::fs::create_dir_all(&destination)?;
+			} else {
+				if let Some(parent) = destination.parent() {
+					std::fs::create_dir_all(parent)?;
+				}
+				let file = std::fs::OpenOptions::new()
+					.create(true)
+					.truncate(false)
+					.write(true)
+					.open(&destination)?;
+				file.set_len(entry.size.unwrap_or(0))?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Record `path` and any directories between it and `root` that are not known yet
+	fn upsert(&mut self, path: &Path, is_directory: bool, size: Option<u64>) {
+		if path == self.root || !path.starts_with(&self.root) {
+			return;
+		}
+		for ancestor in path.ancestors().skip(1) {
+			if ancestor == self.root {
+				break;
+			}
+			self.entries
+				.entry(ancestor.to_path_buf())
+				.or_insert(MirrorEntry { is_directory: true, size: None });
+		}
+		let entry = self
+			.entries
+			.entry(path.to_path_buf())
+			.or_insert(MirrorEntry { is_directory, size: None });
+		entry.is_directory = is_directory;
+		if is_directory {
+			entry.size = None;
+		} else if size.is_some() {
+			entry.size = size;
+		}
+	}
+
+	/// Drop `path` and everything below it
+	fn remove(&mut self, path: &Path) {
+		for removed in self.subtree(path) {
+			self.entries.remove(&removed);
+		}
+	}
+
+	/// Move `source` and everything below it to `destination`, replacing what was there
+	fn relocate(&mut self, source: &Path, destination: &Path) {
+		let moved: Vec<(PathBuf, MirrorEntry)> = self
+			.subtree(source)
+			.into_iter()
+			.filter_map(|path| self.entries.remove(&path).map(|entry| (path, entry)))
+			.collect();
+		if moved.is_empty() {
+			return;
+		}
+		self.remove(destination);
+		if !destination.starts_with(&self.root) {
+			return;
+		}
+		for (path, entry) in moved {
+			let Ok(suffix) = path.strip_prefix(source) else {
+				continue;
+			};
+			let target = if suffix.as_os_str().is_empty() {
+				destination.to_path_buf()
+			} else {
+				destination.join(suffix)
+			};
+			self.upsert(&target, entry.is_directory, entry.size);
+		}
+	}
+
+	/// `path` and every mirrored path below it. Paths order component by component, so the
+	/// subtree is one contiguous range starting at `path`.
+	fn subtree(&self, path: &Path) -> Vec<PathBuf> {
+		self.entries
+			.range(path.to_path_buf()..)
+			.map(|(key, _)| key)
+			.take_while(|key| key.starts_with(path))
+			.cloned()
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::events::{MoveDetectionMethod, MoveEvent};
+
+	fn event(event_type: EventType, path: &str, is_directory: bool) -> FileSystemEvent {
+		FileSystemEvent::new(event_type, PathBuf::from(path), is_directory, None)
+	}
+
+	fn moved(from: &str, to: &str, is_directory: bool) -> FileSystemEvent {
+		event(EventType::Create, to, is_directory).with_move_data(MoveEvent::new(
+			PathBuf::from(from),
+			PathBuf::from(to),
+			1.0,
+			MoveDetectionMethod::Inode,
+		))
+	}
+
+	fn paths(mirror: &DirectoryMirror) -> Vec<&str> {
+		mirror.entries().keys().map(|p| p.to_str().unwrap()).collect()
+	}
+
+	#[test]
+	fn test_directory_move_carries_its_subtree() {
+		let mut mirror = DirectoryMirror::new("/w");
+		let mut write = event(EventType::Write, "/w/docs/sub/a.txt", false);
+		write.size = Some(7);
+		mirror.apply(&write);
+		mirror.apply(&event(EventType::Create, "/w/docs.txt", false));
+		assert_eq!(
+			paths(&mirror),
+			["/w/docs", "/w/docs/sub", "/w/docs/sub/a.txt", "/w/docs.txt"]
+		);
+
+		mirror.apply(&moved("/w/docs", "/w/archive/docs", true));
+		assert_eq!(
+			paths(&mirror),
+			[
+				"/w/archive",
+				"/w/archive/docs",
+				"/w/archive/docs/sub",
+				"/w/archive/docs/sub/a.txt",
+				"/w/docs.txt"
+			]
+		);
+		let file = mirror.entries()[Path::new("/w/archive/docs/sub/a.txt")];
+		assert_eq!(file, MirrorEntry { is_directory: false, size: Some(7) });
+
+		mirror.apply(&event(EventType::Remove, "/w/archive", true));
+		assert_eq!(paths(&mirror), ["/w/docs.txt"]);
+	}
+
+	#[test]
+	fn test_move_with_unknown_source_records_the_destination() {
+		let mut mirror = DirectoryMirror::new("/w");
+		mirror.apply(&moved("/w/gone.txt", "/w/new.txt", false));
+		assert_eq!(paths(&mirror), ["/w/new.txt"]);
+		// Moves out of the root drop the source; events outside the root are ignored
+		mirror.apply(&moved("/w/new.txt", "/elsewhere/new.txt", false));
+		mirror.apply(&event(EventType::Create, "/elsewhere/other.txt", false));
+		assert!(paths(&mirror).is_empty());
+	}
+}
@@ -1,12 +1,91 @@
+use crate::move_detection::matching::{BlendedConfidenceScorer, ConfidenceScorer};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Which remove/create and rename pairings may be reported as moves
+///
+/// Pairs outside the scope are reported as a Remove and a Create instead. Exchanges
+/// (`renameat2(RENAME_EXCHANGE)`) are not affected: both paths still exist afterwards, so
+/// there is no Remove to report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MoveScope {
+	#[default]
+	AllMoves,
+	/// Only moves into a different parent directory; plain renames become Remove + Create
+	CrossDirectoryOnly,
+	/// Only renames within one parent directory
+	SameDirectoryOnly,
+}
+
+impl MoveScope {
+	/// Whether a move from `source` to `destination` may be reported as such
+	pub fn allows_pair(&self, source: &Path, destination: &Path) -> bool {
+		let same_directory = source.parent() == destination.parent();
+		match self {
+			MoveScope::AllMoves => true,
+			MoveScope::CrossDirectoryOnly => !same_directory,
+			MoveScope::SameDirectoryOnly => same_directory,
+		}
+	}
+}
+
+/// What a remove uses when the filesystem cache and the filesystem disagree on its kind
+///
+/// Only the persistent cache is consulted this way: it is read for removed paths the detector
+/// did not see while they existed, and it can have drifted (changes made while nothing was
+/// watching, an older database). The filesystem side is a stat of the path if it still exists,
+/// otherwise the event's `is_directory`, which the watcher takes from the kind the backend
+/// reported (inotify does, most others do not) before falling back to heuristics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CacheMismatchPolicy {
+	/// Ignore the cached node's metadata (size, Windows ID) for this remove
+	#[default]
+	PreferFilesystem,
+	/// Use the cached metadata anyway, as before mismatches were checked
+	PreferCache,
+}
+
+/// A `confidence_threshold` for pairs involving paths under `prefix`
+///
+/// Prefixes are compared component by component with event paths as reported, which are
+/// under the canonicalized watch root. Of several prefixes containing a path, the one with
+/// the most components applies; paths under none use `MoveDetectorConfig::confidence_threshold`.
+/// A pair takes the higher threshold of its source and destination, so a move is only as
+/// lenient as both of its ends allow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThresholdOverride {
+	pub prefix: PathBuf,
+	pub confidence_threshold: f32,
+}
+
+/// A `timeout` for pending events under `prefix`
+///
+/// Prefixes match like [`ThresholdOverride`]'s, the most specific one applying. A pending
+/// remove or create is held for the timeout of its own path, and a pair may span the longer
+/// timeout of its two paths, so a slow staging area can wait for the other half of a move
+/// from anywhere.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeoutOverride {
+	pub prefix: PathBuf,
+	pub timeout: Duration,
+}
+
 /// Configuration for the move detector
 #[derive(Debug, Clone)]
 pub struct MoveDetectorConfig {
 	/// Timeout for matching remove/create events
 	pub timeout: Duration,
+	/// Timeouts that replace `timeout` under particular directories, e.g. where large files
+	/// are staged and the create routinely comes long after the remove; see
+	/// [`TimeoutOverride`]
+	pub timeout_overrides: Vec<TimeoutOverride>,
 	/// Confidence threshold for considering a match valid (0.0 to 1.0)
 	pub confidence_threshold: f32,
+	/// Thresholds that replace `confidence_threshold` under particular directories, e.g.
+	/// strict in a `node_modules` that churns and lenient in a `Downloads` where files are
+	/// moved all the time; see [`ThresholdOverride`]
+	pub threshold_overrides: Vec<ThresholdOverride>,
 	/// Weight for size matching in confidence calculation
 	pub weight_size_match: f32,
 	/// Weight for time factor in confidence calculation
@@ -21,13 +100,236 @@ pub struct MoveDetectorConfig {
 	pub max_pending_events: usize,
 	/// Maximum file size for content hashing (bytes)
 	pub content_hash_max_file_size: u64,
+	/// Minimum name similarity required to pair zero-byte files (0.0 to 1.0)
+	///
+	/// Every empty file lands in the same size bucket, so size carries no signal for them.
+	/// Inode/Windows ID matches are not subject to this filter. 1.0 requires identical names
+	/// and finds them by name; below that, every pending empty file is compared by name.
+	pub zero_byte_min_name_similarity: f32,
+	/// Largest candidate list still scored in full; `None` (the default) scores every one
+	///
+	/// Scoring a pair computes the edit distance between the two file names, so a size bucket
+	/// holding many pending files of the same size (4 KiB files, say) costs a distance per
+	/// candidate for each event, quadratic over a burst. Past the cap, only candidates sharing
+	/// the event's inode, Windows ID or content hash are scored; the event stays unpaired if
+	/// there are none. Each time this happens, `WatcherDiagnostic::NameSimilarityCapped` is
+	/// sent.
+	pub name_similarity_candidate_cap: Option<usize>,
+	/// Also pair files whose sizes differ by up to this many bytes, as when a last write lands
+	/// while the file is being moved
+	///
+	/// 0, the default, pairs only equal sizes. Otherwise the size buckets within the tolerance
+	/// are searched after the exact one, and the size signal drops in proportion to the
+	/// difference: `d` bytes score `1 - d / (size_tolerance + 1)` instead of 1. Those buckets
+	/// are looked up size by size or found in one pass over the pending sizes, whichever is
+	/// less work, so a large tolerance costs no more than a scan of the buckets. Pairs that
+	/// differ in size are reported with `MoveDetectionMethod::Heuristics`.
+	pub size_tolerance: u64,
+	/// Keep removes for this long after `timeout` expires and pair late creates with them
+	///
+	/// `None` disables late pairing. A late pair requires the same file name, the same
+	/// file/directory kind and, for files, the same known size; the resulting Move has
+	/// `late: true`. The Remove was already delivered by then, so consumers must be able to
+	/// retract it. At most `detector::LATE_PAIRING_CAPACITY` expired removes are retained.
+	pub late_pairing_window: Option<Duration>,
+	/// Remember files renamed out of the watched tree for this long and report one coming
+	/// back as a move
+	///
+	/// `None` disables it. A rename whose new name is outside the watch (a RenameFrom or
+	/// generic rename that times out unpaired) is then delivered as a Remove, and a later
+	/// create with the same file name, kind and, for files, known size is reported as a Move
+	/// from where it left with `late: true` and `MoveDetectionMethod::RoundTrip`; source and
+	/// destination are the same path when it returned to where it was. Plain removes are not
+	/// remembered, since a delete looks no different; that includes backends that report a
+	/// rename out as a Remove (Windows, FSEvents). At most `detector::LATE_PAIRING_CAPACITY`
+	/// departures are retained.
+	pub round_trip_window: Option<Duration>,
+	/// Report a file moved more than this many times within `flapping_window` as
+	/// `WatcherDiagnostic::FlappingDetected`, once per episode
+	///
+	/// `None` disables it. A file is followed from move to move, so A -> B -> A counts two
+	/// moves of the same file; the halves of an exchange are not counted. Needs a diagnostics
+	/// channel to report to, unless `suppress_flapping_moves` is all that is wanted.
+	pub flapping_threshold: Option<usize>,
+	/// Window `flapping_threshold` counts moves in
+	pub flapping_window: Duration,
+	/// Hold back the moves of a flapping file and deliver a single Move from where it was
+	/// when it started flapping to where it is once it has stayed put for `flapping_window`
+	///
+	/// Nothing is delivered if it ended up where it started. Only applies with
+	/// `flapping_threshold` set; the moves up to the threshold are delivered as usual.
+	pub suppress_flapping_moves: bool,
+	/// Restrict which pairings are reported as moves; see [`MoveScope`]
+	pub move_scope: MoveScope,
+	/// Only let timing add confidence to pairs that already share a strong signal (inode,
+	/// Windows ID or content hash), and otherwise use it only to break ties between candidates
+	///
+	/// Without this, any two events inside `timeout` get a timing boost, which in a quiet
+	/// directory can push an unrelated same-size remove/create pair over the threshold. With it,
+	/// pairs that rely on size and name alone need a lower `confidence_threshold` to match.
+	pub timing_as_tiebreaker_only: bool,
+	/// Hold back unmatched Removes until `timeout` expires, so one operation is reported as
+	/// either a Move or a Remove, never a Remove followed by a Move
+	///
+	/// Every Remove is then delivered `timeout` late (plus up to `detector::EXCHANGE_WINDOW` of
+	/// polling delay in the watcher). Removes beyond `max_pending_events` cannot be held and are
+	/// delivered immediately, and removes still held when the watcher stops are delivered then.
+	/// Creates are not deferred, so a create that arrives before its remove is still reported
+	/// twice.
+	pub defer_removes: bool,
+	/// Report a file removed and created again at the same path within this window as a
+	/// single Write, for apps that save by deleting and recreating the file in place
+	///
+	/// `None` disables it. With it set, every file Remove not paired as a move right away is
+	/// held back for the window (directories are not), so consumers see it that much later.
+	/// The recreated file must be at least `detector::REWRITE_MIN_SIZE_RATIO` of the removed
+	/// size, when both are known; a much smaller file at the old path is reported as a Remove
+	/// and a Create. A held remove can still pair with a create elsewhere as a move. Must not
+	/// exceed `timeout`.
+	pub rewrite_window: Option<Duration>,
+	/// Reuse a file's content hash while its size and mtime are unchanged
+	///
+	/// Saves re-reading files that are created, written and moved in quick succession. A
+	/// rewrite that keeps both size and mtime (possible on filesystems with coarse timestamps,
+	/// e.g. FAT's 2 seconds, or after a tool restores the mtime) is served the stale hash.
+	/// Hashes are kept as long as the other per-path metadata, about twice `timeout`.
+	pub cache_content_hashes: bool,
+	/// Confidence added to a heuristic pair whose source and destination directories match a
+	/// move confirmed within the last `2 * timeout` (0.0 disables)
+	///
+	/// During a reorganization (many files moving from `/old/` to `/new/`) each confirmed move
+	/// makes its siblings' moves more likely. The bonus is added on top of the weighted sum, so
+	/// it is not counted in the weights that must add up to 1.0. Moves within one directory
+	/// never earn it: a directory mapped onto itself says nothing about a reorganization.
+	pub weight_parent_correlation: f32,
+	/// Also pair creates with files removed before the watcher started, or in another watch,
+	/// through a content-hash index kept in the filesystem cache database
+	///
+	/// Every hashed create (see `content_hash_max_file_size`; empty files are skipped) records
+	/// its path under its content hash, and renames carry those entries along. A create that
+	/// finds no pending remove looks its hash up; if the recorded path is a different one that
+	/// no longer exists, the create becomes a Move from there with
+	/// `MoveDetectionMethod::ContentHash` and `late: true`, since the Remove was delivered
+	/// earlier or never observed by this process. A recorded file that still exists makes the
+	/// create a copy, not a move. The index shares the database of the filesystem cache, so it
+	/// spans restarts and watches only when `WatcherConfig::database_config` is set (and the
+	/// watches use the same database file). Content changed by later writes is not rehashed,
+	/// so a file edited after its create is paired by its original content.
+	pub content_hash_index: bool,
+	/// Report a create as `EventType::Copy` when the content-hash index has its content
+	/// recorded at another path that still exists with the same content
+	///
+	/// Requires `content_hash_index`. The Copy replaces the Create, with the existing file as
+	/// `move_data.source_path` and `MoveDetectionMethod::ContentHash`; when several files share
+	/// the content, the source is the one the index saw last. Otherwise the copy is treated
+	/// like a create, so a remove of its source shortly after still pairs with it as a move
+	/// (copy + delete is how moves across filesystems happen). Costs one extra hash of the
+	/// source per matched create.
+	pub detect_copies: bool,
+	/// See [`CacheMismatchPolicy`]; a mismatch is always logged and reported as
+	/// `WatcherDiagnostic::CacheMismatch`
+	pub cache_mismatch: CacheMismatchPolicy,
+	/// Most files read for content hashing at the same time (at least 1)
+	///
+	/// A burst of creates, e.g. an unpacked archive, would otherwise read as many files as
+	/// there are creates. The limit is process-wide: every detector with the same value
+	/// shares it, across watchers, unless given its own `BoundedHasher` through
+	/// `MoveDetector::with_bounded_hasher`. Directory scans hash their files this many at a
+	/// time through `MoveDetector::prehash`. Hashes served from the `cache_content_hashes`
+	/// cache take no permit.
+	pub max_concurrent_hashes: usize,
+	/// Break exact confidence ties between candidates by path edit distance to the other half
+	///
+	/// Ties are common when several same-size files are removed and one is created. The
+	/// candidate whose full path is closest wins; remaining ties go to the oldest candidate,
+	/// then to the smallest path, so the result never depends on bucket iteration order.
+	/// With this off, ties go straight to the oldest candidate. Under
+	/// `timing_as_tiebreaker_only`, the closer-in-time candidate is preferred first.
+	pub tie_break_by_path_proximity: bool,
+	/// Pair a remove and a create that share a file-name stem when their directories follow a
+	/// move confirmed within the last `2 * timeout`, reported as `MoveDetectionMethod::CrossDevice`
+	///
+	/// For moves that keep neither inode nor content: copy + delete across filesystems, or
+	/// move-then-compress. The stem is the name up to its first `.`, so `report.txt` pairs with
+	/// `report.txt.gz` or `report.zip`. A moved directory maps itself as well as its parent,
+	/// so the files below it can follow it. Only tried when no pair clears
+	/// `confidence_threshold` otherwise; among several candidates the oldest wins.
+	pub cross_device_stem_matching: bool,
+	/// Report every remove or create that could have paired but did not as
+	/// `WatcherDiagnostic::MoveNearMiss`, with the size buckets involved
+	///
+	/// Diagnostic only; matching is unchanged. Costs a pass over the pending size buckets per
+	/// unpaired event, and does nothing without a diagnostics channel.
+	pub near_miss_diagnostics: bool,
+	/// On Windows, read files at paths of `MAX_PATH` (260) characters or more through the
+	/// verbatim `\\?\` prefix, so deep trees work without long-path support enabled in the OS
+	///
+	/// Paths that still fail because of their length are reported as
+	/// `WatcherDiagnostic::PathTooLong`. Has no effect on other platforms.
+	pub long_path_prefix: bool,
+	/// Pair rename events that do not say which side they are (`EventType::Rename`) by whether
+	/// their path still exists; on by default on macOS only
+	///
+	/// FSEvents flags both paths of a rename as renamed, in separate events and without a
+	/// cookie. A flagged path that is gone is held as the old name, and the next flagged path
+	/// that exists becomes a definitive Move from it (`MoveDetectionMethod::Rename`). An old
+	/// name without a counterpart within `timeout` (renamed out of the watch) is reported as a
+	/// Remove, and a new name without one (renamed in) as a Create. Backends that also report
+	/// the halves as RenameFrom/RenameTo, such as inotify's, would produce each move twice, so
+	/// elsewhere generic renames are passed through unchanged.
+	pub pair_generic_renames: bool,
+	/// Report a backend rename event that carries both paths (`Modify(Name(Both))`) as a
+	/// definitive Move right away (confidence 1.0, `MoveDetectionMethod::Rename`)
+	///
+	/// inotify reports every rename this way in addition to its RenameFrom/RenameTo halves; a
+	/// both-paths event for a rename the halves already reported is dropped, so each rename
+	/// is still reported once. Exchanges and `move_scope` apply as for paired halves. Off, the
+	/// event is split into two generic renames (`EventType::Rename`), one per path.
+	pub rename_pairs_as_moves: bool,
+	/// Report every event's way through the detector (pending, candidate, matched, timed out,
+	/// dropped) as a [`crate::move_detection::lifecycle::LifecycleEvent`]
+	///
+	/// For debugging and visualizing pairing decisions; matching is unchanged. The watcher
+	/// hands the stream out through `WatcherHandle::lifecycle_events`. Costs a confidence
+	/// computation per pending counterpart in the incoming event's buckets and a pass over the
+	/// pending events on each cleanup, so leave it off in production.
+	pub lifecycle_trace: bool,
+	/// Store every pairing the detector weighs in the filesystem cache database, for tuning
+	/// offline
+	///
+	/// Each candidate a remove or create is scored against becomes a
+	/// `database::types::MoveDecisionRecord`: both paths, the raw and weighted signals, the
+	/// confidence, the threshold and whether it became the move. The raw signals let recorded
+	/// decisions be re-scored under other weights, e.g. next to `tests/move_accuracy.rs`.
+	/// Candidates are the pending events in the incoming event's inode and size buckets, as
+	/// with `lifecycle_trace`; late, round-trip, content-index and stem pairings are not
+	/// recorded. Read them back with `FilesystemCacheStorage::move_decisions`; the log keeps the
+	/// newest `decision_log::DECISION_LOG_CAPACITY` records. Costs a database write per event
+	/// that had candidates, so leave it off in production.
+	pub record_decisions: bool,
+	/// Before reporting a move found by inode or Windows ID whose source path is still
+	/// readable, check that source and destination have the same content
+	///
+	/// A source that still exists is a hard link, or a file put in the old one's place since
+	/// its remove was seen; an identity match then says little on its own. When the contents
+	/// differ the pair is not reported: the create goes on as unpaired and the remove stays
+	/// pending, so both are reported separately unless something else pairs them. Both files
+	/// are hashed, so this only applies to files within `content_hash_max_file_size`; sources
+	/// that are gone, directories and moves found otherwise are reported as before.
+	pub verify_moves: bool,
+	/// Scores candidate remove/create pairs; defaults to [`BlendedConfidenceScorer`], the
+	/// weighted sum of the `weight_*` fields above
+	///
+	/// Replace it to make pairing follow another policy; the weights are then only used if the
+	/// scorer reads them, though `validate` still checks them. See [`ConfidenceScorer`].
+	pub confidence_scorer: Arc<dyn ConfidenceScorer>,
 }
 
 impl Default for MoveDetectorConfig {
 	fn default() -> Self {
 		// Adjust weights and threshold based on platform capabilities
 		// All weights must sum to 1.0
-		#[cfg(unix)]
+		#[cfg(all(unix, not(target_os = "macos")))]
 		let (
 			confidence_threshold,
 			weight_inode,
@@ -47,6 +349,21 @@ impl Default for MoveDetectorConfig {
 			weight_content,
 		) = (0.5, 0.25, 0.25, 0.2, 0.2, 0.1); // Lower threshold, higher name/time weights
 
+		// FSEvents may report a rename as a delete and a create some time apart, and the
+		// removed file's inode is no longer available by then: rely on size and content
+		#[cfg(target_os = "macos")]
+		let (
+			confidence_threshold,
+			weight_inode,
+			weight_size,
+			weight_name,
+			weight_time,
+			weight_content,
+		) = (0.6, 0.15, 0.25, 0.15, 0.15, 0.3);
+
+		// Long enough for the gap FSEvents can leave between the two halves
+		let timeout = Duration::from_millis(if cfg!(target_os = "macos") { 2000 } else { 1000 });
+
 		#[cfg(not(any(unix, windows)))]
 		let (
 			confidence_threshold,
@@ -58,7 +375,7 @@ impl Default for MoveDetectorConfig {
 		) = (0.5, 0.25, 0.25, 0.2, 0.2, 0.1);
 
 		Self {
-			timeout: Duration::from_millis(1000),
+			timeout,
 			confidence_threshold,
 			weight_size_match: weight_size,
 			weight_time_factor: weight_time,
@@ -67,6 +384,36 @@ impl Default for MoveDetectorConfig {
 			weight_name_similarity: weight_name,
 			max_pending_events: 1000,
 			content_hash_max_file_size: 1024 * 1024, // 1MB
+			zero_byte_min_name_similarity: 1.0,
+			name_similarity_candidate_cap: None,
+			size_tolerance: 0,
+			late_pairing_window: None,
+			round_trip_window: None,
+			flapping_threshold: None,
+			flapping_window: Duration::from_secs(10),
+			suppress_flapping_moves: false,
+			move_scope: MoveScope::AllMoves,
+			threshold_overrides: Vec::new(),
+			timeout_overrides: Vec::new(),
+			timing_as_tiebreaker_only: false,
+			defer_removes: false,
+			rewrite_window: None,
+			cache_content_hashes: true,
+			weight_parent_correlation: 0.1,
+			content_hash_index: false,
+			detect_copies: false,
+			cache_mismatch: CacheMismatchPolicy::PreferFilesystem,
+			max_concurrent_hashes: 4,
+			tie_break_by_path_proximity: true,
+			cross_device_stem_matching: false,
+			near_miss_diagnostics: false,
+			long_path_prefix: true,
+			pair_generic_renames: cfg!(target_os = "macos"),
+			rename_pairs_as_moves: true,
+			lifecycle_trace: false,
+			record_decisions: false,
+			verify_moves: false,
+			confidence_scorer: Arc::new(BlendedConfidenceScorer),
 		}
 	}
 }
@@ -77,16 +424,104 @@ impl MoveDetectorConfig {
 		Self { timeout: Duration::from_millis(timeout_ms), ..Default::default() }
 	}
 
+	/// Confidence threshold for `path`: that of the most specific override containing it, else
+	/// `confidence_threshold`
+	pub fn threshold_for(&self, path: &Path) -> f32 {
+		self.threshold_overrides
+			.iter()
+			.filter(|o| path.starts_with(&o.prefix))
+			.max_by_key(|o| o.prefix.components().count())
+			.map_or(self.confidence_threshold, |o| o.confidence_threshold)
+	}
+
+	/// Confidence a move from `source` to `destination` needs: the higher of the thresholds
+	/// of its two paths
+	pub fn pair_threshold(&self, source: &Path, destination: &Path) -> f32 {
+		if self.threshold_overrides.is_empty() {
+			return self.confidence_threshold;
+		}
+		self.threshold_for(source).max(self.threshold_for(destination))
+	}
+
+	/// Matching timeout for `path`: that of the most specific override containing it, else
+	/// `timeout`
+	pub fn timeout_for(&self, path: &Path) -> Duration {
+		self.timeout_overrides
+			.iter()
+			.filter(|o| path.starts_with(&o.prefix))
+			.max_by_key(|o| o.prefix.components().count())
+			.map_or(self.timeout, |o| o.timeout)
+	}
+
+	/// Longest a move from `source` to `destination` may take: the longer of the timeouts of
+	/// its two paths
+	pub fn pair_timeout(&self, source: &Path, destination: &Path) -> Duration {
+		if self.timeout_overrides.is_empty() {
+			return self.timeout;
+		}
+		self.timeout_for(source).max(self.timeout_for(destination))
+	}
+
+	/// The longest timeout any path gets
+	pub fn longest_timeout(&self) -> Duration {
+		self.timeout_overrides
+			.iter()
+			.map(|o| o.timeout)
+			.fold(self.timeout, Duration::max)
+	}
+
 	/// Validate the configuration and return errors if invalid
 	pub fn validate(&self) -> Result<(), String> {
 		if self.confidence_threshold < 0.0 || self.confidence_threshold > 1.0 {
 			return Err("confidence_threshold must be between 0.0 and 1.0".to_string());
 		}
 
+		if let Some(invalid) = self
+			.threshold_overrides
+			.iter()
+			.find(|o| !(0.0..=1.0).contains(&o.confidence_threshold))
+		{
+			return Err(format!(
+				"confidence_threshold for {:?} must be between 0.0 and 1.0",
+				invalid.prefix
+			));
+		}
+
+		if let Some(invalid) = self.timeout_overrides.iter().find(|o| o.timeout.is_zero()) {
+			return Err(format!(
+				"timeout for {:?} must be greater than 0",
+				invalid.prefix
+			));
+		}
+
 		if self.max_pending_events == 0 {
 			return Err("max_pending_events must be greater than 0".to_string());
 		}
 
+		if !(0.0..=1.0).contains(&self.zero_byte_min_name_similarity) {
+			return Err("zero_byte_min_name_similarity must be between 0.0 and 1.0".to_string());
+		}
+
+		if self.max_concurrent_hashes == 0 {
+			return Err("max_concurrent_hashes must be greater than 0".to_string());
+		}
+
+		if !(0.0..=1.0).contains(&self.weight_parent_correlation) {
+			return Err("weight_parent_correlation must be between 0.0 and 1.0".to_string());
+		}
+
+		if self.flapping_threshold == Some(0) {
+			return Err("flapping_threshold must be greater than 0".to_string());
+		}
+
+		if self.rewrite_window.is_some_and(|window| window > self.timeout) {
+			return Err("rewrite_window must not exceed timeout".to_string());
+		}
+
+		if self.detect_copies && !self.content_hash_index {
+			return Err("detect_copies requires content_hash_index".to_string());
+		}
+
 		// Check that weights sum to approximately 1.0 (allow some tolerance)
 		let total_weight = self.weight_size_match
 			+ self.weight_time_factor
@@ -173,6 +608,30 @@ mod tests {
 		assert!(config.validate().is_ok());
 	}
 	#[test]
+	fn test_config_validation_zero_byte_similarity() {
+		let config =
+			MoveDetectorConfig { zero_byte_min_name_similarity: 1.5, ..Default::default() };
+		assert!(config.validate().is_err());
+
+		let config =
+			MoveDetectorConfig { zero_byte_min_name_similarity: 0.0, ..Default::default() };
+		assert!(config.validate().is_ok());
+	}
+	#[test]
+	fn test_config_validation_rewrite_window() {
+		let config = MoveDetectorConfig {
+			rewrite_window: Some(Duration::from_secs(2)),
+			..MoveDetectorConfig::with_timeout(1000)
+		};
+		assert!(config.validate().is_err());
+
+		let config = MoveDetectorConfig {
+			rewrite_window: Some(Duration::from_millis(500)),
+			..MoveDetectorConfig::with_timeout(1000)
+		};
+		assert!(config.validate().is_ok());
+	}
+	#[test]
 	fn test_config_validation_weights() {
 		// Modify weights to sum to something far from 1.0
 		let config = MoveDetectorConfig {
@@ -1,16 +1,36 @@
 use crate::database::storage::filesystem_cache::trait_def::FilesystemCacheStorage;
-use crate::events::{EventType, FileSystemEvent, MoveEvent};
-use crate::move_detection::config::MoveDetectorConfig;
+use crate::database::types::MoveDecisionRecord;
+use crate::diagnostics::{DiagnosticsSender, WatcherDiagnostic};
+use crate::events::{EventType, FileSystemEvent, MoveDetectionMethod, MoveEvent};
+use crate::long_paths;
+use crate::move_detection::config::{CacheMismatchPolicy, MoveDetectorConfig};
 use crate::move_detection::events::{PendingEvent, PendingEventsStorage};
+use crate::move_detection::flapping::FlappingTracker;
 use crate::move_detection::heuristics::PathTypeInference;
-use crate::move_detection::matching::{MetadataExtractor, MoveMatching};
+use crate::move_detection::lifecycle::{LifecycleEvent, LifecycleSender, LifecycleStage};
+use crate::move_detection::matching::{
+	nearby_sized, BoundedHasher, ContentHasher, MetadataExtractor, MoveMatching, ParentCorrelations,
+};
 use crate::move_detection::metadata::{FileMetadata, MetadataCache};
-use crate::move_detection::monitoring::{PendingEventsSummary, ResourceStats};
-use std::path::Path;
-use tokio::time::Instant;
+use crate::move_detection::monitoring::{PendingEventAges, PendingEventsSummary, ResourceStats};
+use crate::runtime::Instant;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{debug, warn};
 
-pub struct MoveDetector<'a> {
+/// Pairs remove/create (and rename) events into moves.
+///
+/// Generic over the persistent cache. The default, `dyn FilesystemCacheStorage`, keeps the
+/// detector usable with any backend chosen at runtime; naming a concrete cache type (as the
+/// watcher does with `RedbFilesystemCache`) monomorphizes the detector so cache calls are
+/// dispatched statically. Both share this one implementation. The cache trait is an
+/// `async_trait`, so each call still boxes its future either way; only the vtable hop goes away.
+pub struct MoveDetector<
+	'a,
+	C: FilesystemCacheStorage + ?Sized + 'a = dyn FilesystemCacheStorage + 'a,
+> {
 	/// Event storage organized for efficient lookups
 	pending_events: PendingEventsStorage,
 
@@ -18,28 +38,392 @@ pub struct MoveDetector<'a> {
 	metadata_cache: MetadataCache,
 
 	/// Reference to persistent filesystem cache
-	cache: &'a mut dyn FilesystemCacheStorage,
+	cache: &'a mut C,
 
 	/// Configuration for move detection
 	config: MoveDetectorConfig,
 
 	/// Resource usage statistics
 	stats: ResourceStats,
+
+	/// Removes that expired unmatched, oldest first; only filled when late pairing is enabled
+	expired_removes: VecDeque<PendingEvent>,
+
+	/// Files renamed out of the watch, as Removes, oldest first; only filled when
+	/// `MoveDetectorConfig::round_trip_window` is set
+	departures: VecDeque<PendingEvent>,
+
+	/// File removes held back for `MoveDetectorConfig::rewrite_window`, oldest first; they
+	/// stay in `pending_events` too, so they can still pair as moves
+	rewrite_holds: VecDeque<(FileSystemEvent, Instant)>,
+
+	/// Rename whose source still existed when it was paired, held as a possible exchange half
+	held_rename: Option<(FileSystemEvent, Instant)>,
+
+	/// Source and destination of the last rename paired from its RenameFrom/RenameTo halves,
+	/// until the next event; a both-paths event for the same rename repeats it
+	last_paired_rename: Option<(PathBuf, PathBuf)>,
+
+	/// Computes content hashes for created files, `max_concurrent_hashes` at a time
+	content_hasher: BoundedHasher,
+
+	/// Where events left out of move detection are reported, if anywhere
+	diagnostics: Option<DiagnosticsSender>,
+
+	/// Where `MoveDetectorConfig::lifecycle_trace` transitions are reported, if anywhere
+	lifecycle: Option<LifecycleSender>,
+
+	/// Directory mappings of recent moves, for `MoveDetectorConfig::weight_parent_correlation`
+	parent_correlations: ParentCorrelations,
+	/// Recent moves per file, for `MoveDetectorConfig::flapping_threshold`
+	flapping: FlappingTracker,
 }
 
-impl<'a> MoveDetector<'a> {
-	pub fn new(config: MoveDetectorConfig, cache: &'a mut dyn FilesystemCacheStorage) -> Self {
+/// Upper bound on expired removes retained for late pairing
+pub const LATE_PAIRING_CAPACITY: usize = 256;
+
+/// How long a possible first half of an exchange waits for its counterpart.
+///
+/// inotify has no exchange flag: `renameat2(RENAME_EXCHANGE)` is reported as two ordinary
+/// rename pairs (A->B, then B->A) with separate cookies, back to back. Only renames whose
+/// source still exists after the fact are held, so plain renames are not delayed.
+pub const EXCHANGE_WINDOW: Duration = Duration::from_millis(100);
+
+/// Confidence of a move found through `MoveDetectorConfig::content_hash_index`.
+///
+/// Identical content with a vanished source is strong evidence, but nothing ties the two
+/// files together in time, so it stays below a definitive rename.
+pub const CONTENT_HASH_INDEX_CONFIDENCE: f32 = 0.9;
+
+/// Smallest size, relative to the removed file, a file recreated at its path may have to be
+/// reported as a rewrite under `MoveDetectorConfig::rewrite_window`.
+///
+/// Rewrites usually keep or grow the content; a much smaller file is more likely a new one.
+pub const REWRITE_MIN_SIZE_RATIO: f64 = 0.9;
+
+/// Confidence of a move found through `MoveDetectorConfig::cross_device_stem_matching`.
+///
+/// Only the name stem and a directory mapping connect the two files, so it stays below the
+/// content-hash index.
+pub const CROSS_DEVICE_CONFIDENCE: f32 = 0.75;
+
+impl<'a, C: FilesystemCacheStorage + ?Sized> MoveDetector<'a, C> {
+	pub fn new(config: MoveDetectorConfig, cache: &'a mut C) -> Self {
+		let parent_correlations = ParentCorrelations::new(config.longest_timeout() * 2);
+		let content_hasher = BoundedHasher::shared(config.max_concurrent_hashes);
 		Self {
 			pending_events: PendingEventsStorage::new(),
 			metadata_cache: MetadataCache::new(),
 			cache,
 			config,
 			stats: ResourceStats::new(),
+			expired_removes: VecDeque::new(),
+			departures: VecDeque::new(),
+			rewrite_holds: VecDeque::new(),
+			held_rename: None,
+			last_paired_rename: None,
+			content_hasher,
+			diagnostics: None,
+			lifecycle: None,
+			parent_correlations,
+			flapping: FlappingTracker::new(),
+		}
+	}
+
+	/// Report events that cannot be held for pairing (`max_pending_events` reached) as
+	/// [`WatcherDiagnostic::DroppedEvent`]
+	pub fn with_diagnostics(mut self, diagnostics: DiagnosticsSender) -> Self {
+		self.diagnostics = Some(diagnostics);
+		self
+	}
+
+	/// Report each event's way through the detector when `MoveDetectorConfig::lifecycle_trace`
+	/// is on
+	pub fn with_lifecycle(mut self, lifecycle: LifecycleSender) -> Self {
+		self.lifecycle = Some(lifecycle);
+		self
+	}
+
+	fn tracing_lifecycle(&self) -> bool {
+		self.config.lifecycle_trace && self.lifecycle.is_some()
+	}
+
+	/// Report `stage` of `event`; see `MoveDetectorConfig::lifecycle_trace`
+	fn trace(&self, event: &FileSystemEvent, stage: LifecycleStage) {
+		if !self.config.lifecycle_trace {
+			return;
+		}
+		if let Some(lifecycle) = &self.lifecycle {
+			lifecycle.emit(LifecycleEvent {
+				correlation_id: event.id,
+				path: event.path.clone(),
+				event_type: event.event_type.clone(),
+				stage,
+			});
+		}
+	}
+
+	/// Report both halves of a move as matched with each other
+	fn trace_move(
+		&self, source: &FileSystemEvent, destination: &FileSystemEvent, confidence: f32,
+		method: MoveDetectionMethod,
+	) {
+		let matched = |counterpart: &FileSystemEvent| LifecycleStage::Matched {
+			counterpart: Some(counterpart.id),
+			confidence,
+			method,
+		};
+		self.trace(source, matched(destination));
+		self.trace(destination, matched(source));
+	}
+
+	/// Report the pending counterparts in the inode and size buckets of `incoming` (a Remove
+	/// or a Create, per `kind`) as candidates for it
+	fn trace_candidates(&self, incoming: &PendingEvent, kind: EventType) {
+		if !self.tracing_lifecycle() {
+			return;
+		}
+		for candidate in self.bucket_candidates(incoming, &kind) {
+			let (remove, create) = match kind {
+				EventType::Remove => (incoming, candidate),
+				_ => (candidate, incoming),
+			};
+			let confidence =
+				MoveMatching::confidence(remove, create, &self.config, &self.parent_correlations);
+			self.trace(
+				&candidate.event,
+				LifecycleStage::Candidate { against: incoming.event.id, confidence },
+			);
+		}
+	}
+
+	/// The pending counterparts in the inode and size buckets of `incoming`, each once
+	fn bucket_candidates(&self, incoming: &PendingEvent, kind: &EventType) -> Vec<&PendingEvent> {
+		let storage = &self.pending_events;
+		let (by_inode, by_size, no_size) = match kind {
+			EventType::Remove => (
+				&storage.creates_by_inode,
+				&storage.creates_by_size,
+				&storage.creates_no_size,
+			),
+			_ => (
+				&storage.removes_by_inode,
+				&storage.removes_by_size,
+				&storage.removes_no_size,
+			),
+		};
+		let mut seen = HashSet::new();
+		incoming
+			.inode
+			.and_then(|inode| by_inode.get(&inode))
+			.into_iter()
+			.chain(incoming.event.size.and_then(|size| by_size.get(&size)).into_iter().flatten())
+			.chain(
+				incoming
+					.event
+					.size
+					.map(|size| nearby_sized(by_size, size, &self.config))
+					.unwrap_or_default(),
+			)
+			.chain(no_size.iter())
+			.filter(|candidate| seen.insert(candidate.event.id))
+			.collect()
+	}
+
+	/// Send `WatcherDiagnostic::NameSimilarityCapped` if a candidate list of `incoming` is
+	/// over `name_similarity_candidate_cap`; the lists are those matching tries
+	fn report_candidate_cap(&self, incoming: &PendingEvent, kind: &EventType) {
+		let Some(cap) = self.config.name_similarity_candidate_cap else {
+			return;
+		};
+		let storage = &self.pending_events;
+		let (by_size, no_size) = match kind {
+			EventType::Remove => (&storage.creates_by_size, &storage.creates_no_size),
+			_ => (&storage.removes_by_size, &storage.removes_no_size),
+		};
+		let candidates = match incoming.event.size {
+			Some(size) => by_size
+				.get(&size)
+				.map_or(0, Vec::len)
+				.max(nearby_sized(by_size, size, &self.config).len()),
+			None => no_size.len().max(by_size.values().map(Vec::len).sum()),
+		};
+		if candidates <= cap {
+			return;
+		}
+		debug!(
+			"{} candidates for {:?}, over the cap of {}; matching by identity only",
+			candidates, incoming.event.path, cap
+		);
+		crate::diagnostics::emit(
+			self.diagnostics.as_ref(),
+			WatcherDiagnostic::NameSimilarityCapped {
+				path: incoming.event.path.clone(),
+				event_type: kind.clone(),
+				candidates,
+			},
+		);
+	}
+
+	/// Decision records for the candidates of `incoming`, keyed by candidate id and not yet
+	/// marked accepted; empty unless `record_decisions` is on
+	fn weigh_candidates(
+		&self, incoming: &PendingEvent, kind: EventType,
+	) -> Vec<(uuid::Uuid, MoveDecisionRecord)> {
+		if !self.config.record_decisions {
+			return Vec::new();
+		}
+		let recorded_at = chrono::Utc::now();
+		self.bucket_candidates(incoming, &kind)
+			.into_iter()
+			.map(|candidate| {
+				let (remove, create) = match kind {
+					EventType::Remove => (incoming, candidate),
+					_ => (candidate, incoming),
+				};
+				let (source, destination) = (&remove.event.path, &create.event.path);
+				let correlated = self.parent_correlations.contains(source, destination);
+				let record = MoveDecisionRecord {
+					recorded_at,
+					source: source.clone(),
+					destination: destination.clone(),
+					signals: MoveMatching::signals(remove, create, &self.config),
+					contributions: MoveMatching::weighted_signals(remove, create, &self.config),
+					parent_correlation_bonus: if correlated {
+						self.config.weight_parent_correlation
+					} else {
+						0.0
+					},
+					confidence: MoveMatching::confidence(
+						remove,
+						create,
+						&self.config,
+						&self.parent_correlations,
+					),
+					threshold: self.config.pair_threshold(source, destination),
+					accepted: false,
+				};
+				(candidate.event.id, record)
+			})
+			.collect()
+	}
+
+	/// Store weighed decisions, the one for `accepted` marked as the move
+	async fn record_decisions(
+		&mut self, weighed: Vec<(uuid::Uuid, MoveDecisionRecord)>, accepted: Option<uuid::Uuid>,
+	) {
+		if weighed.is_empty() {
+			return;
+		}
+		let records: Vec<_> = weighed
+			.into_iter()
+			.map(|(id, record)| MoveDecisionRecord { accepted: Some(id) == accepted, ..record })
+			.collect();
+		if let Err(e) = self.cache.record_move_decisions(&records).await {
+			warn!("Failed to record {} move decisions: {}", records.len(), e);
+		}
+	}
+
+	/// Pending removes and creates matching `filter`, each once
+	fn pending_matching(&self, filter: impl Fn(&PendingEvent) -> bool) -> Vec<&FileSystemEvent> {
+		let storage = &self.pending_events;
+		let mut seen = HashSet::new();
+		storage
+			.removes_by_size
+			.values()
+			.flatten()
+			.chain(&storage.removes_no_size)
+			.chain(storage.removes_by_inode.values())
+			.chain(storage.removes_by_windows_id.values())
+			.chain(storage.creates_by_size.values().flatten())
+			.chain(&storage.creates_no_size)
+			.chain(storage.creates_by_inode.values())
+			.chain(storage.creates_by_windows_id.values())
+			.filter(|pending| filter(pending) && seen.insert(pending.event.id))
+			.map(|pending| &pending.event)
+			.collect()
+	}
+
+	fn report_pending_limit(&self, event: &FileSystemEvent, kind: &str) {
+		let reason = format!(
+			"max_pending_events ({}) reached for pending {kind}",
+			self.config.max_pending_events
+		);
+		self.trace(event, LifecycleStage::Dropped { reason: reason.clone() });
+		crate::diagnostics::emit(
+			self.diagnostics.as_ref(),
+			WatcherDiagnostic::DroppedEvent {
+				path: event.path.clone(),
+				event_type: event.event_type.clone(),
+				reason,
+			},
+		);
+	}
+
+	/// Explain an event left unpaired while counterparts were pending; see
+	/// `MoveDetectorConfig::near_miss_diagnostics`
+	fn report_near_miss(&self, event: &FileSystemEvent) {
+		if !self.config.near_miss_diagnostics || self.diagnostics.is_none() {
+			return;
+		}
+		let storage = &self.pending_events;
+		let (by_size, no_size) = match event.event_type {
+			EventType::Remove => (&storage.creates_by_size, &storage.creates_no_size),
+			_ => (&storage.removes_by_size, &storage.removes_no_size),
+		};
+		let total_candidates = by_size.values().map(Vec::len).sum::<usize>() + no_size.len();
+		if total_candidates == 0 {
+			return;
 		}
+		let bucket_candidates = match event.size {
+			Some(size) => by_size.get(&size).map_or(0, Vec::len),
+			None => no_size.len(),
+		};
+		let nearest_bucket = event.size.and_then(|size| {
+			by_size
+				.iter()
+				.filter(|(bucket, _)| **bucket != size)
+				.min_by_key(|(bucket, _)| (bucket.abs_diff(size), **bucket))
+				.map(|(bucket, candidates)| (*bucket, candidates.len()))
+		});
+		debug!(
+			"Near miss for {:?}: bucket {:?} had {} of {} candidates, nearest {:?}",
+			event.path, event.size, bucket_candidates, total_candidates, nearest_bucket
+		);
+		crate::diagnostics::emit(
+			self.diagnostics.as_ref(),
+			WatcherDiagnostic::MoveNearMiss {
+				path: event.path.clone(),
+				event_type: event.event_type.clone(),
+				bucket: event.size,
+				bucket_candidates,
+				nearest_bucket,
+				total_candidates,
+			},
+		);
+	}
+
+	/// Replace the default XxHash64 content hasher, e.g. with a cryptographic hash or an
+	/// instrumented one in tests
+	pub fn with_content_hasher(mut self, hasher: Arc<dyn ContentHasher>) -> Self {
+		self.content_hasher = self.content_hasher.with_hasher(hasher);
+		self
+	}
+
+	/// Hash through `hasher`, sharing its concurrency limit with every detector given a clone
+	/// of it; replaces both the hasher and `max_concurrent_hashes`
+	pub fn with_bounded_hasher(mut self, hasher: BoundedHasher) -> Self {
+		self.content_hasher = hasher;
+		self
+	}
+
+	/// The hasher in use, to share its concurrency limit with other detectors
+	pub fn bounded_hasher(&self) -> &BoundedHasher {
+		&self.content_hasher
 	}
 
 	/// Create a new MoveDetector with default configuration and custom timeout
-	pub fn with_timeout(timeout_ms: u64, cache: &'a mut dyn FilesystemCacheStorage) -> Self {
+	pub fn with_timeout(timeout_ms: u64, cache: &'a mut C) -> Self {
 		let config = MoveDetectorConfig::with_timeout(timeout_ms);
 		Self::new(config, cache)
 	}
@@ -51,9 +435,20 @@ impl<'a> MoveDetector<'a> {
 		);
 
 		self.stats.record_event_processed();
+		self.trace(&event, LifecycleStage::Received);
+
+		// Access and unclassified kinds say nothing about identity; keep them away from the
+		// pending state and from any rename being held for exchange pairing
+		if matches!(event.event_type, EventType::Other(_)) {
+			debug!("Passing through unclassified event: {:?}", event.path);
+			return vec![event];
+		}
 
 		// Cache metadata for files we can still access (not for remove events)
-		if !matches!(event.event_type, EventType::Remove | EventType::RenameFrom) {
+		if !matches!(
+			event.event_type,
+			EventType::Remove | EventType::RenameFrom | EventType::MovedToIgnored
+		) {
 			self.cache_file_metadata(&event.path).await;
 		}
 
@@ -73,13 +468,28 @@ impl<'a> MoveDetector<'a> {
 			summary.has_pending_rename_from
 		);
 
-		self.cleanup_expired_events().await;
+		let mut result = self.cleanup_expired_events().await;
+
+		// Anything but the rest of a rename sequence ends the wait for an exchange counterpart
+		match event.event_type {
+			EventType::RenameFrom | EventType::RenameTo | EventType::Rename => {}
+			_ => {
+				self.last_paired_rename = None;
+				result.extend(self.take_held_rename());
+			}
+		}
 
-		let result = match event.event_type {
+		result.extend(match event.event_type {
 			EventType::Remove => {
 				debug!("Handling Remove event for: {:?}", event.path);
+				self.forget_rename_from(&event.path);
 				self.handle_remove_event(event).await
 			}
+			EventType::MovedToIgnored => {
+				// The RenameTo half was ignored, so nothing will complete the pending RenameFrom
+				self.forget_rename_from(&event.path);
+				vec![event]
+			}
 			EventType::Create => {
 				debug!("Handling Create event for: {:?}", event.path);
 				self.handle_create_event(event).await
@@ -92,6 +502,14 @@ impl<'a> MoveDetector<'a> {
 				debug!("Handling RenameTo event for: {:?}", event.path);
 				self.handle_rename_to_event(event).await
 			}
+			EventType::Rename if event.move_data.is_some() => {
+				debug!("Handling both-paths rename event for: {:?}", event.path);
+				self.handle_rename_pair(event).await
+			}
+			EventType::Rename if self.config.pair_generic_renames => {
+				debug!("Handling generic rename event for: {:?}", event.path);
+				self.handle_generic_rename(event).await
+			}
 			EventType::Rename => {
 				// Generic rename event - treat as both remove and create
 				debug!("Processing generic rename event for: {:?}", event.path);
@@ -104,8 +522,9 @@ impl<'a> MoveDetector<'a> {
 				);
 				vec![event] // Pass through other events
 			}
-		};
+		});
 
+		let result = self.watch_flapping(result);
 		if result.len() > 1 {
 			debug!("Returning {} events from processing", result.len());
 		}
@@ -113,6 +532,31 @@ impl<'a> MoveDetector<'a> {
 		result
 	}
 
+	/// Count delivered moves toward `MoveDetectorConfig::flapping_threshold`, reporting files
+	/// that exceed it and holding their moves back under `suppress_flapping_moves`
+	fn watch_flapping(&mut self, events: Vec<FileSystemEvent>) -> Vec<FileSystemEvent> {
+		let Some(threshold) = self.config.flapping_threshold else {
+			return events;
+		};
+		let now = Instant::now();
+		let mut delivered = Vec::with_capacity(events.len());
+		for event in events {
+			let (released, diagnostic) = self.flapping.observe(
+				event,
+				now,
+				threshold,
+				self.config.flapping_window,
+				self.config.suppress_flapping_moves,
+			);
+			delivered.extend(released);
+			if let Some(diagnostic) = diagnostic {
+				warn!("File is flapping between names: {:?}", diagnostic);
+				crate::diagnostics::emit(self.diagnostics.as_ref(), diagnostic);
+			}
+		}
+		delivered
+	}
+
 	/// Infer whether a removed path was likely a directory based on available context
 	pub fn infer_path_type(&self, path: &Path) -> Option<bool> {
 		PathTypeInference::infer_path_type(path, &self.metadata_cache, &self.pending_events)
@@ -129,40 +573,418 @@ impl<'a> MoveDetector<'a> {
 		)
 	}
 
+	/// Whether a rename is being held as a possible exchange half
+	pub fn has_held_events(&self) -> bool {
+		self.held_rename.is_some() || self.flapping.has_held()
+	}
+
+	/// Release a held rename whose exchange counterpart did not arrive within `EXCHANGE_WINDOW`,
+	/// and the net move of files that stopped flapping (`suppress_flapping_moves`).
+	///
+	/// Events are otherwise only released when the next event is processed, so a caller that
+	/// can go idle must poll this while `has_held_events` is true.
+	pub fn take_expired_held(&mut self) -> Vec<FileSystemEvent> {
+		let renamed = match &self.held_rename {
+			Some((_, held_at)) if held_at.elapsed() >= EXCHANGE_WINDOW => self.take_held_rename(),
+			_ => Vec::new(),
+		};
+		let mut released = self.watch_flapping(renamed);
+		released.extend(self.flapping.take_settled(Instant::now(), self.config.flapping_window));
+		released
+	}
+
+	/// Whether unmatched removes are being held back under `MoveDetectorConfig::defer_removes`
+	/// or `MoveDetectorConfig::rewrite_window`, or the old name of a rename waits for its
+	/// counterpart and is released as a Remove if none arrives (generic renames, see
+	/// `MoveDetectorConfig::pair_generic_renames`, and any rename under
+	/// `MoveDetectorConfig::round_trip_window`)
+	pub fn has_deferred_removes(&self) -> bool {
+		self.config.defer_removes && self.pending_events.count_removes() > 0
+			|| !self.rewrite_holds.is_empty()
+			|| self.pending_generic_rename()
+			|| self.config.round_trip_window.is_some()
+				&& self.pending_events.pending_rename_from.is_some()
+	}
+
+	/// Release deferred removes whose move window has expired, oldest first.
+	///
+	/// Like `take_expired_held`, this has to be polled by callers that can go idle while
+	/// `has_deferred_removes` is true; otherwise removes are released with the next event.
+	pub async fn take_expired_removes(&mut self) -> Vec<FileSystemEvent> {
+		self.cleanup_expired_events().await
+	}
+
+	/// Drop all pairing state: pending removes and creates, a pending RenameFrom, the metadata
+	/// and content-hash caches, and removes kept for late pairing.
+	///
+	/// For a controlled resync, e.g. when the caller knows the event stream was interrupted and
+	/// nothing pending can be paired correctly any more. Returns what must still be delivered:
+	/// a rename held as a possible exchange half (already a complete move), the net move of a
+	/// file whose flapping moves were held back, and with
+	/// `emit_removes` the removes that were never delivered, oldest first, as plain Removes.
+	/// Those are the removes held by `defer_removes` or `rewrite_window` and a pending
+	/// RenameFrom; other pending removes were already delivered and are not repeated. Statistics
+	/// and the persistent filesystem cache are kept.
+	pub fn reset(&mut self, emit_removes: bool) -> Vec<FileSystemEvent> {
+		if self.tracing_lifecycle() {
+			let rename_from = self.pending_events.pending_rename_from.as_ref();
+			for event in self
+				.pending_matching(|_| true)
+				.into_iter()
+				.chain(rename_from.map(|(from, _)| from))
+			{
+				self.trace(
+					event,
+					LifecycleStage::Dropped { reason: "reset".to_string() },
+				);
+			}
+		}
+		let held = self.take_held_rename();
+		let mut released = self.watch_flapping(held);
+		released.extend(self.flapping.clear());
+		if emit_removes {
+			if self.config.defer_removes {
+				let deferred = self.pending_removes(|_| true);
+				released.extend(deferred.into_iter().map(|pending| pending.event));
+			}
+			released.extend(self.rewrite_holds.drain(..).map(|(event, _)| event));
+			if let Some((from, _)) = self.pending_events.pending_rename_from.take() {
+				released.push(FileSystemEvent { event_type: EventType::Remove, ..from });
+			}
+		}
+		self.pending_events.clear();
+		self.rewrite_holds.clear();
+		self.metadata_cache.clear();
+		self.expired_removes.clear();
+		self.departures.clear();
+		debug!("Move detection reset, releasing {} events", released.len());
+		released
+	}
+
+	fn take_held_rename(&mut self) -> Vec<FileSystemEvent> {
+		match self.held_rename.take() {
+			Some((event, _)) => self.apply_move_scope(event),
+			None => Vec::new(),
+		}
+	}
+
+	/// Split a rename outside `config.move_scope` into a Remove of the source and a Create of
+	/// the destination. Heuristic pairings are filtered earlier, in `MoveMatching`.
+	fn apply_move_scope(&self, event: FileSystemEvent) -> Vec<FileSystemEvent> {
+		let Some(move_data) = &event.move_data else {
+			return vec![event];
+		};
+		if self
+			.config
+			.move_scope
+			.allows_pair(&move_data.source_path, &move_data.destination_path)
+		{
+			return vec![event];
+		}
+		debug!(
+			"Rename {:?} -> {:?} outside move scope, reporting as remove + create",
+			move_data.source_path, move_data.destination_path
+		);
+		let remove = FileSystemEvent {
+			id: uuid::Uuid::new_v4(),
+			event_type: EventType::Remove,
+			path: move_data.source_path.clone(),
+			move_data: None,
+			..event.clone()
+		};
+		let create = FileSystemEvent { event_type: EventType::Create, move_data: None, ..event };
+		vec![remove, create]
+	}
+
 	/// Get resource usage statistics
 	pub fn get_resource_stats(&mut self) -> ResourceStats {
 		self.stats.update(&self.pending_events, &self.metadata_cache);
 		self.stats.clone()
 	}
 
+	/// The configuration this detector was created with
+	pub fn config(&self) -> &MoveDetectorConfig {
+		&self.config
+	}
+
+	/// Ages of pending removes and creates; also included in `get_resource_stats`
+	pub fn pending_event_ages(&self) -> PendingEventAges {
+		PendingEventAges::from_storage(&self.pending_events)
+	}
+
 	/// Get summary of pending events for debugging
 	pub fn get_pending_events_summary(&self) -> PendingEventsSummary {
 		PendingEventsSummary::from_storage(&self.pending_events)
 	}
 
+	/// Metadata of `path`, read through `long_path_prefix`; a failure caused by the path's
+	/// length is reported, any other failure just yields `None`
+	fn read_metadata(&self, path: &Path) -> Option<std::fs::Metadata> {
+		let fs_path = long_paths::for_fs(path, self.config.long_path_prefix);
+		match std::fs::metadata(&fs_path) {
+			Ok(metadata) => Some(metadata),
+			Err(e) => {
+				if long_paths::is_too_long(&e, &fs_path) {
+					warn!("Path too long to read metadata: {:?}", path);
+					crate::diagnostics::emit(
+						self.diagnostics.as_ref(),
+						WatcherDiagnostic::PathTooLong { path: path.to_path_buf() },
+					);
+				}
+				None
+			}
+		}
+	}
+
 	/// Cache metadata for a file path
-	async fn cache_file_metadata(&mut self, path: &Path) {
-		if let Ok(metadata) = std::fs::metadata(path) {
-			let size = if metadata.is_file() { Some(metadata.len()) } else { None };
-			let windows_id = MetadataExtractor::get_windows_id(path).await;
+	async fn cache_file_metadata(&mut self, path: &Path) -> Option<FileMetadata> {
+		let metadata = self.read_metadata(path)?;
+		let size = if metadata.is_file() { Some(metadata.len()) } else { None };
+		let fs_path = long_paths::for_fs(path, self.config.long_path_prefix);
+		let windows_id = MetadataExtractor::get_windows_id(&fs_path).await;
+		let inode = MetadataExtractor::get_inode(&fs_path).await;
+
+		let file_metadata = FileMetadata::new(size, windows_id).with_inode(inode);
+		self.metadata_cache.insert(path.to_path_buf(), file_metadata.clone());
+		Some(file_metadata)
+	}
+
+	/// Record the size, inode and Windows ID of `paths` as they are now, so a remove of one of
+	/// them carries its identity even though the file is gone by the time it is reported.
+	///
+	/// For files that existed before the watcher started (or were not created while it ran)
+	/// and are about to be moved in a way the backend reports as a remove and a create. The
+	/// metadata is kept for [`PREWARM_TTL`](crate::move_detection::metadata::PREWARM_TTL) or
+	/// until the path's remove consumes it. Returns how many paths could be read.
+	pub async fn prewarm(&mut self, paths: &[PathBuf]) -> usize {
+		let mut cached = 0;
+		for path in paths {
+			if let Some(mut metadata) = self.cache_file_metadata(path).await {
+				metadata.prewarmed = true;
+				self.metadata_cache.insert(path.clone(), metadata);
+				cached += 1;
+			}
+		}
+		debug!("Prewarmed metadata for {} of {} paths", cached, paths.len());
+		cached
+	}
+
+	/// Hash the files among `paths` ahead of their events, up to `max_concurrent_hashes` at a
+	/// time, so a burst of creates is not read one file after the other.
+	///
+	/// The hashes go to the `cache_content_hashes` cache, where the events' own lookups find
+	/// them while size and mtime are unchanged; with that cache disabled this does nothing.
+	/// Returns how many files were hashed.
+	pub async fn prehash(&mut self, paths: &[PathBuf]) -> usize {
+		if !self.config.cache_content_hashes {
+			return 0;
+		}
+		let mut wanted = Vec::new();
+		for path in paths {
+			let Some(metadata) = self.read_metadata(path) else {
+				continue;
+			};
+			if !metadata.is_file() || metadata.len() > self.config.content_hash_max_file_size {
+				continue;
+			}
+			let Ok(modified) = metadata.modified() else {
+				continue;
+			};
+			if self.metadata_cache.content_hash(path, metadata.len(), modified).is_none() {
+				wanted.push((path.clone(), metadata.len(), modified));
+			}
+		}
+		let fs_paths: Vec<PathBuf> = wanted
+			.iter()
+			.map(|(path, ..)| long_paths::for_fs(path, self.config.long_path_prefix).into_owned())
+			.collect();
+		let hashes = self.content_hasher.hash_files(&fs_paths).await;
+		let mut hashed = 0;
+		for ((path, size, modified), hash) in wanted.into_iter().zip(hashes) {
+			if let Some(hash) = hash {
+				self.metadata_cache.insert_content_hash(path, size, modified, hash);
+				hashed += 1;
+			}
+		}
+		hashed
+	}
+
+	async fn record_move(
+		&mut self, source: &Path, destination: &Path, confidence: f32, method: MoveDetectionMethod,
+	) {
+		self.stats.record_move_detected(method, confidence);
+		if self.config.cross_device_stem_matching && destination.is_dir() {
+			self.parent_correlations.record_directory(source, destination);
+		} else {
+			self.parent_correlations.record(source, destination);
+		}
+		if self.config.content_hash_index {
+			if let Err(e) = self.cache.relocate_content_hashes(source, destination).await {
+				warn!(
+					"Failed to update content hash index for {:?}: {}",
+					source, e
+				);
+			}
+		}
+	}
+
+	/// Oldest pending counterpart of `pending` (a Remove or a Create, per `kind`) under
+	/// `cross_device_stem_matching`
+	fn stem_counterpart(&self, pending: &PendingEvent, kind: EventType) -> Option<PendingEvent> {
+		if !self.config.cross_device_stem_matching {
+			return None;
+		}
+		let (config, correlations) = (&self.config, &self.parent_correlations);
+		let matches = |candidate: &&PendingEvent| {
+			let (remove, create) = match kind {
+				EventType::Remove => (pending, *candidate),
+				_ => (*candidate, pending),
+			};
+			CROSS_DEVICE_CONFIDENCE >= config.pair_threshold(&remove.event.path, &create.event.path)
+				&& MoveMatching::is_stem_match(remove, create, config, correlations)
+		};
+		let candidates: Box<dyn Iterator<Item = &PendingEvent>> = match kind {
+			EventType::Remove => Box::new(self.pending_events.iter_creates()),
+			_ => Box::new(self.pending_events.iter_removes()),
+		};
+		candidates
+			.filter(matches)
+			.min_by(|a, b| {
+				a.timestamp.cmp(&b.timestamp).then_with(|| a.event.path.cmp(&b.event.path))
+			})
+			.cloned()
+	}
+
+	/// The Move for a `cross_device_stem_matching` pair; the pending create is consumed
+	async fn cross_device_move(
+		&mut self, remove: &PendingEvent, create: &PendingEvent,
+	) -> FileSystemEvent {
+		self.pending_events.remove_create_by_id(create.event.id);
+		let (source, destination) = (&remove.event.path, &create.event.path);
+		debug!(
+			"Cross-device move by name stem: {:?} -> {:?}",
+			source, destination
+		);
+		let move_event = MoveEvent::new(
+			source.clone(),
+			destination.clone(),
+			CROSS_DEVICE_CONFIDENCE,
+			MoveDetectionMethod::CrossDevice,
+		);
+		self.record_move(
+			source,
+			destination,
+			CROSS_DEVICE_CONFIDENCE,
+			MoveDetectionMethod::CrossDevice,
+		)
+		.await;
+		self.trace_move(
+			&remove.event,
+			&create.event,
+			CROSS_DEVICE_CONFIDENCE,
+			MoveDetectionMethod::CrossDevice,
+		);
+		create.event.clone().with_move_data(move_event)
+	}
+
+	/// Path the content-hash index last saw `hash` at, if that file is gone and may be paired
+	/// with a create at `destination`
+	async fn indexed_source(&mut self, destination: &Path, hash: Option<&str>) -> Option<PathBuf> {
+		let source = match self.cache.path_for_content_hash(hash?).await {
+			Ok(source) => source?,
+			Err(e) => {
+				warn!("Content hash index lookup failed: {}", e);
+				return None;
+			}
+		};
+		// Recreated in place, or a copy of a file that is still there
+		if source == destination || source.symlink_metadata().is_ok() {
+			return None;
+		}
+		if CONTENT_HASH_INDEX_CONFIDENCE < self.config.pair_threshold(&source, destination) {
+			return None;
+		}
+		self.config.move_scope.allows_pair(&source, destination).then_some(source)
+	}
+
+	/// Existing file the content-hash index last saw `hash` at, if it still has that content
+	async fn copy_source(&mut self, destination: &Path, hash: &str) -> Option<PathBuf> {
+		let source = match self.cache.path_for_content_hash(hash).await {
+			Ok(source) => source?,
+			Err(e) => {
+				warn!("Content hash index lookup failed: {}", e);
+				return None;
+			}
+		};
+		if source == destination {
+			return None;
+		}
+		// Written since it was indexed
+		(self.content_hash(&source).await.as_deref() == Some(hash)).then_some(source)
+	}
+
+	/// Whether an identity-matched pair whose source is still readable has the same content at
+	/// both ends; true whenever there is nothing to verify. See `verify_moves`.
+	async fn contents_agree(&mut self, remove: &PendingEvent, create: &PendingEvent) -> bool {
+		if !self.config.verify_moves || create.event.is_directory {
+			return true;
+		}
+		let method = MoveMatching::determine_detection_method(remove, create);
+		if !matches!(
+			method,
+			MoveDetectionMethod::Inode | MoveDetectionMethod::WindowsId
+		) {
+			return true;
+		}
+		let Some(source_hash) = self.content_hash(&remove.event.path).await else {
+			return true;
+		};
+		let destination_hash = match &create.content_hash {
+			Some(hash) => Some(hash.clone()),
+			None => self.content_hash(&create.event.path).await,
+		};
+		let agree = destination_hash.is_none_or(|hash| hash == source_hash);
+		if !agree {
+			debug!(
+				"Not a move: {:?} still exists with other content than {:?}",
+				remove.event.path, create.event.path
+			);
+		}
+		agree
+	}
 
-			let file_metadata = FileMetadata::new(size, windows_id);
-			self.metadata_cache.insert(path.to_path_buf(), file_metadata);
+	/// Content hash for move matching, reusing the cached one while size and mtime match
+	async fn content_hash(&mut self, path: &Path) -> Option<String> {
+		let metadata = self.read_metadata(path)?;
+		if !metadata.is_file() || metadata.len() > self.config.content_hash_max_file_size {
+			return None;
+		}
+		let modified = metadata.modified().ok().filter(|_| self.config.cache_content_hashes);
+		if let Some(modified) = modified {
+			if let Some(hash) = self.metadata_cache.content_hash(path, metadata.len(), modified) {
+				return Some(hash.to_string());
+			}
 		}
+		let fs_path = long_paths::for_fs(path, self.config.long_path_prefix);
+		let hash = self.content_hasher.hash_file(&fs_path).await?;
+		if let Some(modified) = modified {
+			self.metadata_cache.insert_content_hash(
+				path.to_path_buf(),
+				metadata.len(),
+				modified,
+				hash.clone(),
+			);
+		}
+		Some(hash)
 	}
+
 	async fn handle_remove_event(&mut self, mut event: FileSystemEvent) -> Vec<FileSystemEvent> {
 		// Try to get cached metadata for this file (since it's being removed)
 		let mut cached_metadata = self.metadata_cache.remove(&event.path);
 		if cached_metadata.is_none() {
 			// Fallback: query persistent cache for metadata
 			if let Ok(Some(node)) = self.cache.get_unified_node(&event.path).await {
-				let (size, windows_id) = match &node.node_type {
-					crate::database::types::NodeType::File { size, .. } => {
-						(Some(*size), node.metadata.windows_id)
-					}
-					_ => (None, node.metadata.windows_id),
-				};
-				cached_metadata = Some(FileMetadata::new(size, windows_id));
+				cached_metadata = self.reconcile_cached_node(&event, &node).await;
 			}
 		}
 		debug!(
@@ -178,7 +1000,10 @@ impl<'a> MoveDetector<'a> {
 			}
 		}
 
-		let inode = MetadataExtractor::get_inode(&event.path).await;
+		let inode = match MetadataExtractor::get_inode(&event.path).await {
+			Some(inode) => Some(inode),
+			None => cached_metadata.as_ref().and_then(|m| m.inode),
+		};
 		let windows_id = cached_metadata.as_ref().and_then(|m| m.windows_id);
 
 		debug!(
@@ -190,17 +1015,36 @@ impl<'a> MoveDetector<'a> {
 			PendingEvent::new(event.clone()).with_inode(inode).with_windows_id(windows_id);
 
 		// Check if this removal matches a recent create (reverse move detection)
+		self.trace_candidates(&pending, EventType::Remove);
+		let weighed = self.weigh_candidates(&pending, EventType::Remove);
+		self.report_candidate_cap(&pending, &EventType::Remove);
 		debug!("Searching for matching create event...");
-		if let Some(matching_create) =
-			MoveMatching::find_matching_create(&pending, &self.pending_events, &self.config).await
-		{
+		let mut matching_create = MoveMatching::find_matching_create(
+			&pending,
+			&self.pending_events,
+			&self.config,
+			&self.parent_correlations,
+		)
+		.await;
+		if let Some(create) = &matching_create {
+			if !self.contents_agree(&pending, create).await {
+				matching_create = None;
+			}
+		}
+		self.record_decisions(weighed, matching_create.as_ref().map(|c| c.event.id))
+			.await;
+		if let Some(matching_create) = matching_create {
 			debug!(
 				"Found matching create event: {:?}",
 				matching_create.event.path
 			);
 
-			let confidence =
-				MoveMatching::calculate_confidence(&pending, &matching_create, &self.config);
+			let confidence = MoveMatching::confidence(
+				&pending,
+				&matching_create,
+				&self.config,
+				&self.parent_correlations,
+			);
 			let detection_method =
 				MoveMatching::determine_detection_method(&pending, &matching_create);
 
@@ -209,14 +1053,22 @@ impl<'a> MoveDetector<'a> {
 				confidence, detection_method
 			);
 
-			let move_event = MoveEvent {
-				source_path: matching_create.event.path.clone(),
-				destination_path: event.path.clone(),
+			// The create came first, but the removed path is still the source
+			let move_event = MoveEvent::new(
+				event.path.clone(),
+				matching_create.event.path.clone(),
 				confidence,
 				detection_method,
-			};
+			);
 
-			self.stats.record_move_detected(confidence);
+			self.record_move(
+				&event.path,
+				&matching_create.event.path,
+				confidence,
+				detection_method,
+			)
+			.await;
+			self.trace_move(&event, &matching_create.event, confidence, detection_method);
 
 			let mut move_event_fs = matching_create.event.clone();
 			move_event_fs = move_event_fs.with_move_data(move_event);
@@ -226,34 +1078,87 @@ impl<'a> MoveDetector<'a> {
 
 			debug!(
 				"Detected move: {:?} -> {:?} (confidence: {:.2})",
-				matching_create.event.path, event.path, confidence
+				event.path, matching_create.event.path, confidence
 			);
 			return vec![move_event_fs];
 		} else {
 			debug!("No matching create event found");
-		} // Store this removal as pending
+		}
+		if let Some(create) = self.stem_counterpart(&pending, EventType::Remove) {
+			return vec![self.cross_device_move(&pending, &create).await];
+		}
+		self.report_near_miss(&event);
+		// Store this removal as pending
 		if self.pending_events.count_removes() < self.config.max_pending_events {
+			self.trace(&event, LifecycleStage::Pending);
 			self.pending_events.add_remove(pending);
 			debug!(
 				"Added remove event to pending storage (total removes: {})",
 				self.pending_events.count_removes()
 			);
+			if self.config.defer_removes {
+				return Vec::new();
+			}
+			if self.config.rewrite_window.is_some() && !event.is_directory {
+				self.rewrite_holds.push_back((event, Instant::now()));
+				return Vec::new();
+			}
 		} else {
 			warn!(
 				"Too many pending remove events, dropping event for: {:?}",
 				event.path
 			);
+			self.report_pending_limit(&event, "removes");
 		}
 
 		vec![event]
 	}
+
+	/// Metadata of a removed path from its persistent cache node, checked against the
+	/// filesystem first; see [`CacheMismatchPolicy`]. A node of the wrong kind is dropped from
+	/// the cache, so later lookups do not run into it again.
+	async fn reconcile_cached_node(
+		&mut self, event: &FileSystemEvent, node: &crate::database::types::FilesystemNode,
+	) -> Option<FileMetadata> {
+		use crate::database::types::NodeType;
+		let cached_is_directory = matches!(node.node_type, NodeType::Directory { .. });
+		let disk_is_directory =
+			event.path.symlink_metadata().map(|m| m.is_dir()).unwrap_or(event.is_directory);
+		if cached_is_directory != disk_is_directory {
+			warn!(
+				"Filesystem cache has {:?} as a {}, the filesystem as a {}",
+				event.path,
+				if cached_is_directory { "directory" } else { "file" },
+				if disk_is_directory { "directory" } else { "file" }
+			);
+			crate::diagnostics::emit(
+				self.diagnostics.as_ref(),
+				WatcherDiagnostic::CacheMismatch {
+					path: event.path.clone(),
+					cached_is_directory,
+					disk_is_directory,
+				},
+			);
+			if let Err(e) = self.cache.invalidate_unified_node(&event.path).await {
+				warn!(
+					"Failed to drop stale cache node for {:?}: {}",
+					event.path, e
+				);
+			}
+			if self.config.cache_mismatch == CacheMismatchPolicy::PreferFilesystem {
+				return None;
+			}
+		}
+		let size = match &node.node_type {
+			NodeType::File { size, .. } => Some(*size),
+			_ => None,
+		};
+		Some(FileMetadata::new(size, node.metadata.windows_id))
+	}
+
 	async fn handle_create_event(&mut self, event: FileSystemEvent) -> Vec<FileSystemEvent> {
 		let inode = MetadataExtractor::get_inode(&event.path).await;
-		let content_hash = MetadataExtractor::get_content_hash(
-			&event.path,
-			self.config.content_hash_max_file_size,
-		)
-		.await;
+		let content_hash = self.content_hash(&event.path).await;
 		let windows_id = MetadataExtractor::get_windows_id(&event.path).await;
 		debug!(
 			"Create event metadata: inode={:?}, content_hash={:?}, windows_id={:?}",
@@ -262,23 +1167,77 @@ impl<'a> MoveDetector<'a> {
 			windows_id
 		);
 
+		let indexed_hash = content_hash.clone().filter(|_| {
+			// Every empty file shares one hash
+			self.config.content_hash_index
+				&& std::fs::metadata(&event.path).is_ok_and(|metadata| metadata.len() > 0)
+		});
 		let pending = PendingEvent::new(event.clone())
 			.with_inode(inode)
 			.with_content_hash(content_hash)
 			.with_windows_id(windows_id);
 
+		let path = event.path.clone();
+		let result = match self.take_rewritten_remove(&event) {
+			Some(Ok(remove)) => {
+				debug!("Rewritten in place: {:?}", path);
+				self.trace(
+					&remove,
+					LifecycleStage::Dropped { reason: "recreated at the same path".to_string() },
+				);
+				vec![FileSystemEvent { event_type: EventType::Write, ..event }]
+			}
+			// Delivered first, so the Remove does not follow the new file's Create
+			Some(Err(remove)) => {
+				let mut result = vec![remove];
+				result.extend(self.pair_create(event, pending, indexed_hash.as_deref()).await);
+				result
+			}
+			None => self.pair_create(event, pending, indexed_hash.as_deref()).await,
+		};
+		// After pairing, so the lookup above still saw where the content was before
+		if let Some(hash) = indexed_hash {
+			if let Err(e) = self.cache.record_content_hash(&hash, &path).await {
+				warn!("Failed to record content hash of {:?}: {}", path, e);
+			}
+		}
+		result
+	}
+
+	async fn pair_create(
+		&mut self, event: FileSystemEvent, pending: PendingEvent, indexed_hash: Option<&str>,
+	) -> Vec<FileSystemEvent> {
 		// Check if this creation matches a recent removal
+		self.trace_candidates(&pending, EventType::Create);
+		let weighed = self.weigh_candidates(&pending, EventType::Create);
+		self.report_candidate_cap(&pending, &EventType::Create);
 		debug!("Searching for matching remove event...");
-		if let Some(matching_remove) =
-			MoveMatching::find_matching_remove(&pending, &self.pending_events, &self.config).await
-		{
+		let mut matching_remove = MoveMatching::find_matching_remove(
+			&pending,
+			&self.pending_events,
+			&self.config,
+			&self.parent_correlations,
+		)
+		.await;
+		if let Some(remove) = &matching_remove {
+			if !self.contents_agree(remove, &pending).await {
+				matching_remove = None;
+			}
+		}
+		self.record_decisions(weighed, matching_remove.as_ref().map(|r| r.event.id))
+			.await;
+		if let Some(matching_remove) = matching_remove {
 			debug!(
 				"Found matching remove event: {:?}",
 				matching_remove.event.path
 			);
 
-			let confidence =
-				MoveMatching::calculate_confidence(&matching_remove, &pending, &self.config);
+			let confidence = MoveMatching::confidence(
+				&matching_remove,
+				&pending,
+				&self.config,
+				&self.parent_correlations,
+			);
 			let detection_method =
 				MoveMatching::determine_detection_method(&matching_remove, &pending);
 
@@ -289,16 +1248,25 @@ impl<'a> MoveDetector<'a> {
 
 			let event_path = event.path.clone(); // Clone path before moving event
 
-			let move_event = MoveEvent {
-				source_path: matching_remove.event.path.clone(),
-				destination_path: event_path.clone(),
+			let move_event = MoveEvent::new(
+				matching_remove.event.path.clone(),
+				event_path.clone(),
 				confidence,
 				detection_method,
-			};
+			);
 
-			self.stats.record_move_detected(confidence);
+			self.record_move(
+				&matching_remove.event.path,
+				&event_path,
+				confidence,
+				detection_method,
+			)
+			.await;
+			self.trace_move(&matching_remove.event, &event, confidence, detection_method);
 
 			let move_event_fs = event.with_move_data(move_event);
+			// Consumed: it must neither pair again nor be released later as a deferred Remove
+			self.consume_remove(matching_remove.event.id);
 			debug!(
 				"Detected move: {:?} -> {:?} (confidence: {:.2})",
 				matching_remove.event.path, event_path, confidence
@@ -308,32 +1276,346 @@ impl<'a> MoveDetector<'a> {
 			debug!("No matching remove event found");
 		}
 
-		// Store this creation as pending
-		if self.pending_events.count_creates() < self.config.max_pending_events {
-			self.pending_events.add_create(pending);
-			debug!(
-				"Added create event to pending storage (total creates: {})",
-				self.pending_events.count_creates()
-			);
-		} else {
-			warn!(
-				"Too many pending create events, dropping event for: {:?}",
-				event.path
-			);
+		if let Some(remove) = self.stem_counterpart(&pending, EventType::Create) {
+			// Consumed like any other paired remove, see above
+			self.consume_remove(remove.event.id);
+			return vec![self.cross_device_move(&remove, &pending).await];
 		}
 
-		vec![event]
+		if let Some(late_remove) = self.take_late_remove(&pending) {
+			let confidence =
+				self.config.confidence_scorer.score(&late_remove, &pending, &self.config);
+			debug!(
+				"Late move: {:?} -> {:?} (confidence: {:.2})",
+				late_remove.event.path, event.path, confidence
+			);
+			let move_event = MoveEvent {
+				late: true,
+				..MoveEvent::new(
+					late_remove.event.path.clone(),
+					event.path.clone(),
+					confidence,
+					MoveDetectionMethod::NameAndTiming,
+				)
+			};
+			self.record_move(
+				&late_remove.event.path,
+				&event.path,
+				confidence,
+				MoveDetectionMethod::NameAndTiming,
+			)
+			.await;
+			self.trace_move(
+				&late_remove.event,
+				&event,
+				confidence,
+				MoveDetectionMethod::NameAndTiming,
+			);
+			return vec![event.with_move_data(move_event)];
+		}
+
+		if let Some(departed) = self.take_departure(&pending) {
+			let confidence = self.config.confidence_scorer.score(&departed, &pending, &self.config);
+			debug!(
+				"Round trip: {:?} -> {:?} (confidence: {:.2})",
+				departed.event.path, event.path, confidence
+			);
+			let method = MoveDetectionMethod::RoundTrip;
+			let move_event = MoveEvent {
+				late: true,
+				..MoveEvent::new(
+					departed.event.path.clone(),
+					event.path.clone(),
+					confidence,
+					method,
+				)
+			};
+			self.record_move(&departed.event.path, &event.path, confidence, method).await;
+			self.trace_move(&departed.event, &event, confidence, method);
+			return vec![event.with_move_data(move_event)];
+		}
+
+		if let Some(source) = self.indexed_source(&event.path, indexed_hash).await {
+			// A remove of the source still pending here has either been delivered already or
+			// is held by `defer_removes` or `rewrite_window`; in the latter case this move
+			// replaces it
+			let pending_remove = self
+				.pending_events
+				.iter_removes()
+				.find(|remove| remove.event.path == source)
+				.map(|remove| remove.event.clone());
+			let held = pending_remove.as_ref().is_some_and(|remove| self.consume_remove(remove.id));
+			self.expired_removes.retain(|remove| remove.event.path != source);
+			debug!("Content hash index move: {:?} -> {:?}", source, event.path);
+			let move_event = MoveEvent {
+				late: !held,
+				..MoveEvent::new(
+					source.clone(),
+					event.path.clone(),
+					CONTENT_HASH_INDEX_CONFIDENCE,
+					MoveDetectionMethod::ContentHash,
+				)
+			};
+			self.record_move(
+				&source,
+				&event.path,
+				CONTENT_HASH_INDEX_CONFIDENCE,
+				MoveDetectionMethod::ContentHash,
+			)
+			.await;
+			match &pending_remove {
+				Some(remove) => self.trace_move(
+					remove,
+					&event,
+					CONTENT_HASH_INDEX_CONFIDENCE,
+					MoveDetectionMethod::ContentHash,
+				),
+				None => self.trace(
+					&event,
+					LifecycleStage::Matched {
+						counterpart: None,
+						confidence: CONTENT_HASH_INDEX_CONFIDENCE,
+						method: MoveDetectionMethod::ContentHash,
+					},
+				),
+			}
+			return vec![event.with_move_data(move_event)];
+		}
+
+		let copy_source = match indexed_hash {
+			Some(hash) if self.config.detect_copies => self.copy_source(&event.path, hash).await,
+			_ => None,
+		};
+
+		self.report_near_miss(&event);
+		// Store this creation as pending
+		if self.pending_events.count_creates() < self.config.max_pending_events {
+			self.trace(&event, LifecycleStage::Pending);
+			self.pending_events.add_create(pending);
+			debug!(
+				"Added create event to pending storage (total creates: {})",
+				self.pending_events.count_creates()
+			);
+		} else {
+			warn!(
+				"Too many pending create events, dropping event for: {:?}",
+				event.path
+			);
+			self.report_pending_limit(&event, "creates");
+		}
+
+		match copy_source {
+			Some(source) => {
+				debug!("Copy: {:?} -> {:?}", source, event.path);
+				vec![event.into_copy_of(source)]
+			}
+			None => vec![event],
+		}
+	}
+	/// Take the remove of `create`'s path held back for `MoveDetectorConfig::rewrite_window`
+	///
+	/// `Ok` when the create rewrites it: a file removed within the window and not much larger
+	/// than the new one (`REWRITE_MIN_SIZE_RATIO`). `Err` when the new file is too small; the
+	/// remove is then due for delivery, but stays pending so it can still pair as a move.
+	fn take_rewritten_remove(
+		&mut self, create: &FileSystemEvent,
+	) -> Option<Result<FileSystemEvent, FileSystemEvent>> {
+		let window = self.config.rewrite_window?;
+		if create.is_directory {
+			return None;
+		}
+		let now = Instant::now();
+		let remove = if self.config.defer_removes {
+			self.pending_removes(|pending| {
+				pending.event.path == create.path
+					&& !pending.event.is_directory
+					&& now.duration_since(pending.timestamp) <= window
+			})
+			.pop()
+			.map(|pending| pending.event)
+		} else {
+			self.rewrite_holds
+				.iter()
+				.rev()
+				.find(|(remove, held_at)| {
+					remove.path == create.path && now.duration_since(*held_at) <= window
+				})
+				.map(|(remove, _)| remove.clone())
+		}?;
+		let new_size = create.size.or_else(|| self.read_metadata(&create.path).map(|m| m.len()));
+		if let (Some(old_size), Some(new_size)) = (remove.size, new_size) {
+			if (new_size as f64) < old_size as f64 * REWRITE_MIN_SIZE_RATIO {
+				let held_len = self.rewrite_holds.len();
+				self.rewrite_holds.retain(|(held, _)| held.id != remove.id);
+				// Under `defer_removes` it is released with the other deferred removes
+				return (self.rewrite_holds.len() < held_len).then_some(Err(remove));
+			}
+		}
+		self.consume_remove(remove.id);
+		Some(Ok(remove))
 	}
-	async fn handle_rename_from_event(&mut self, event: FileSystemEvent) -> Vec<FileSystemEvent> {
+
+	/// Take a remove that was paired out of the pending removes and the rewrite holds; returns
+	/// whether it had not been delivered yet (held by `defer_removes` or `rewrite_window`)
+	fn consume_remove(&mut self, id: uuid::Uuid) -> bool {
+		let pending = self.pending_events.remove_remove_by_id(id);
+		let held_len = self.rewrite_holds.len();
+		self.rewrite_holds.retain(|(remove, _)| remove.id != id);
+		pending && self.config.defer_removes || self.rewrite_holds.len() < held_len
+	}
+
+	/// Rewrite holds older than `MoveDetectorConfig::rewrite_window`, oldest first
+	fn expired_rewrite_holds(&mut self, now: Instant) -> Vec<FileSystemEvent> {
+		let Some(window) = self.config.rewrite_window else {
+			return Vec::new();
+		};
+		let mut expired = Vec::new();
+		while self
+			.rewrite_holds
+			.front()
+			.is_some_and(|(_, held_at)| now.duration_since(*held_at) > window)
+		{
+			if let Some((remove, _)) = self.rewrite_holds.pop_front() {
+				expired.push(remove);
+			}
+		}
+		expired
+	}
+
+	async fn handle_rename_from_event(
+		&mut self, mut event: FileSystemEvent,
+	) -> Vec<FileSystemEvent> {
 		debug!(
 			"Storing RenameFrom event for later pairing: {:?}",
 			event.path
 		);
+		// The halves of a rename arrive back to back, so one still waiting went out of the watch
+		let released = self.depart_pending_rename_from().into_iter().collect();
+		if self.config.round_trip_window.is_some() && event.size.is_none() {
+			event.size = self.cached_size(&event).await;
+		}
 		// Store the rename "from" event temporarily
-		self.pending_events.pending_rename_from = Some((event.clone(), Instant::now()));
+		self.trace(&event, LifecycleStage::Pending);
+		self.pending_events.pending_rename_from = Some((event, Instant::now()));
+
+		// Don't emit anything else yet - wait for the "to" event
+		released
+	}
+
+	/// Pair an [`EventType::Rename`] by whether its path still exists; see
+	/// `MoveDetectorConfig::pair_generic_renames`
+	async fn handle_generic_rename(&mut self, mut event: FileSystemEvent) -> Vec<FileSystemEvent> {
+		if event.path.symlink_metadata().is_err() {
+			// The old name. One still waiting was renamed out of the watch
+			let released = self.depart_pending_rename_from().into_iter().collect();
+			if self.config.round_trip_window.is_some() && event.size.is_none() {
+				event.size = self.cached_size(&event).await;
+			}
+			self.trace(&event, LifecycleStage::Pending);
+			self.pending_events.pending_rename_from = Some((event, Instant::now()));
+			return released;
+		}
+		if self.pending_events.pending_rename_from.is_some() {
+			return self.handle_rename_to_event(event).await;
+		}
+		// Renamed in from outside the watch
+		self.handle_create_event(FileSystemEvent { event_type: EventType::Create, ..event })
+			.await
+	}
+
+	/// Report a rename whose event carried both paths (the source in `move_data`) as a Move,
+	/// unless its RenameFrom/RenameTo halves were just paired into one
+	async fn handle_rename_pair(&mut self, mut event: FileSystemEvent) -> Vec<FileSystemEvent> {
+		let Some(source) = event.move_data.take().map(|move_data| move_data.source_path) else {
+			return vec![event];
+		};
+		if self
+			.last_paired_rename
+			.take()
+			.is_some_and(|(from, to)| from == source && to == event.path)
+		{
+			debug!(
+				"Dropping rename already paired from its halves: {:?}",
+				event.path
+			);
+			return Vec::new();
+		}
+		// Pair through the usual path, so exchanges and move scope apply; a RenameFrom of
+		// another rename that is still waiting is kept waiting
+		self.forget_rename_from(&source);
+		let from = FileSystemEvent::new(EventType::RenameFrom, source, event.is_directory, None);
+		let waiting = self.pending_events.pending_rename_from.replace((from, Instant::now()));
+		let paired = self
+			.handle_rename_to_event(FileSystemEvent { event_type: EventType::RenameTo, ..event })
+			.await;
+		self.pending_events.pending_rename_from = waiting;
+		self.last_paired_rename = None;
+		paired
+	}
+
+	/// Whether the pending RenameFrom is the old name of a generic rename
+	fn pending_generic_rename(&self) -> bool {
+		let pending = self.pending_events.pending_rename_from.as_ref();
+		pending.is_some_and(|(from, _)| from.event_type == EventType::Rename)
+	}
+
+	/// Give up on the old name of a rename still waiting for its counterpart: it was renamed
+	/// out of the watch. Returns it as the Remove it turned out to be if it is a generic rename
+	/// or `MoveDetectorConfig::round_trip_window` is set, which also remembers it as departed;
+	/// otherwise it is dropped.
+	fn depart_pending_rename_from(&mut self) -> Option<FileSystemEvent> {
+		let generic = self.pending_generic_rename();
+		let (from, _) = self.pending_events.pending_rename_from.take()?;
+		if !generic && self.config.round_trip_window.is_none() {
+			return None;
+		}
+		let remove = FileSystemEvent { event_type: EventType::Remove, ..from };
+		if self.config.round_trip_window.is_some() {
+			self.departures.push_back(PendingEvent::new(remove.clone()));
+			while self.departures.len() > LATE_PAIRING_CAPACITY {
+				self.departures.pop_front();
+			}
+		}
+		Some(remove)
+	}
+
+	/// Size of `event`'s path as last seen, from the metadata cache or the persistent cache
+	async fn cached_size(&mut self, event: &FileSystemEvent) -> Option<u64> {
+		if let Some(metadata) = self.metadata_cache.get(&event.path) {
+			return metadata.size;
+		}
+		match self.cache.get_unified_node(&event.path).await {
+			Ok(Some(node)) => self.reconcile_cached_node(event, &node).await?.size,
+			_ => None,
+		}
+	}
+
+	/// Find and remove the most recent departure that a create brings back.
+	///
+	/// Like late pairing, only exact name plus kind and size count; the path may be the same.
+	fn take_departure(&mut self, create: &PendingEvent) -> Option<PendingEvent> {
+		self.config.round_trip_window?;
+		let create_event = &create.event;
+		let index = self.departures.iter().rposition(|departed| {
+			let departed_event = &departed.event;
+			departed_event.path.file_name() == create_event.path.file_name()
+				&& departed_event.is_directory == create_event.is_directory
+				&& MoveMatching::in_scope(departed, create, &self.config)
+				&& (create_event.is_directory
+					|| (departed_event.size.is_some() && departed_event.size == create_event.size))
+		})?;
+		self.departures.remove(index)
+	}
 
-		// Don't emit anything yet - wait for the "to" event
-		vec![]
+	/// Drop a pending RenameFrom of `path`, so a later unrelated RenameTo is not paired with it
+	fn forget_rename_from(&mut self, path: &Path) {
+		let pending = self.pending_events.pending_rename_from.as_ref();
+		if pending.is_some_and(|(from, _)| from.path == path) {
+			if let Some((from, _)) = self.pending_events.pending_rename_from.take() {
+				let reason = "superseded by a later event for the same path".to_string();
+				self.trace(&from, LifecycleStage::Dropped { reason });
+			}
+		}
 	}
 
 	async fn handle_rename_to_event(&mut self, event: FileSystemEvent) -> Vec<FileSystemEvent> {
@@ -347,20 +1629,39 @@ impl<'a> MoveDetector<'a> {
 			let event_path = event.path.clone(); // Clone path before moving event
 
 			// Create a move event from the rename pair
-			let move_event = MoveEvent {
-				source_path: from_event.path.clone(),
-				destination_path: event_path.clone(),
-				confidence: 1.0, // Rename events are definitive
-				detection_method: crate::events::MoveDetectionMethod::Rename,
-			};
+			let move_event = MoveEvent::new(
+				from_event.path.clone(),
+				event_path.clone(),
+				1.0, // Rename events are definitive
+				crate::events::MoveDetectionMethod::Rename,
+			);
 
-			self.stats.record_move_detected(1.0);
+			self.record_move(
+				&from_event.path,
+				&event_path,
+				1.0,
+				MoveDetectionMethod::Rename,
+			)
+			.await;
+			self.trace_move(&from_event, &event, 1.0, MoveDetectionMethod::Rename);
+			self.last_paired_rename = Some((from_event.path.clone(), event_path.clone()));
 			let move_event_fs = event.with_move_data(move_event);
 			debug!(
 				"Detected rename: {:?} -> {:?} (confidence: 1.0)",
 				from_event.path, event_path
 			);
-			vec![move_event_fs]
+
+			if let Some(exchange) = self.pair_exchange(&move_event_fs) {
+				return exchange;
+			}
+			let mut output = self.take_held_rename();
+			// A plain rename leaves nothing at the source; an exchange leaves the other file there
+			if from_event.path.symlink_metadata().is_ok() {
+				self.held_rename = Some((move_event_fs, Instant::now()));
+			} else {
+				output.extend(self.apply_move_scope(move_event_fs));
+			}
+			output
 		} else {
 			debug!("No matching RenameFrom event found, treating as create");
 			// No matching "from" event - treat as regular create
@@ -371,58 +1672,113 @@ impl<'a> MoveDetector<'a> {
 			return self.handle_create_event(event).await;
 		}
 	}
+	/// If `rename` reverses the held rename, return both marked as one exchange
+	fn pair_exchange(&mut self, rename: &FileSystemEvent) -> Option<Vec<FileSystemEvent>> {
+		let (held, _) = self.held_rename.as_ref()?;
+		let (held_move, current_move) = (held.move_data.as_ref()?, rename.move_data.as_ref()?);
+		if held_move.source_path != current_move.destination_path
+			|| held_move.destination_path != current_move.source_path
+		{
+			return None;
+		}
+
+		let mut pair: Vec<_> =
+			self.held_rename.take().map(|(event, _)| event).into_iter().collect();
+		pair.push(rename.clone());
+		for half in &mut pair {
+			if let Some(move_data) = half.move_data.as_mut() {
+				move_data.detection_method = MoveDetectionMethod::Exchange;
+			}
+		}
+		debug!(
+			"Detected exchange: {:?} <-> {:?}",
+			current_move.source_path, current_move.destination_path
+		);
+		Some(pair)
+	}
+
 	/// Clean up expired pending events and old metadata
-	async fn cleanup_expired_events(&mut self) {
+	///
+	/// Returns the expired removes that `defer_removes` or `rewrite_window` was holding back,
+	/// oldest first, and the old name of a rename whose counterpart did not arrive, if
+	/// `depart_pending_rename_from` releases it.
+	async fn cleanup_expired_events(&mut self) -> Vec<FileSystemEvent> {
 		let now = Instant::now();
-		let timeout = self.config.timeout;
 
 		// Count events before cleanup for logging
 		let initial_removes = self.pending_events.count_removes();
 		let initial_creates = self.pending_events.count_creates();
+		if self.tracing_lifecycle() {
+			let expired = self.pending_matching(|pending| expired(&self.config, pending, now));
+			for event in expired {
+				self.trace(event, LifecycleStage::TimedOut);
+			}
+		}
+
+		let expiring = if self.config.late_pairing_window.is_some() || self.config.defer_removes {
+			self.expiring_removes(now)
+		} else {
+			Vec::new()
+		};
+		let mut released = if self.config.defer_removes {
+			expiring.iter().map(|pending| pending.event.clone()).collect()
+		} else {
+			self.expired_rewrite_holds(now)
+		};
+		if let Some(window) = self.config.late_pairing_window {
+			self.retain_expired_removes(expiring, now, window);
+		}
 
 		// Clean up expired remove events
 		self.pending_events.removes_by_size.retain(|_, events| {
-			events.retain(|event| now.duration_since(event.timestamp) <= timeout);
+			events.retain(|event| !expired(&self.config, event, now));
 			!events.is_empty()
 		});
 
 		self.pending_events
 			.removes_no_size
-			.retain(|event| now.duration_since(event.timestamp) <= timeout);
+			.retain(|event| !expired(&self.config, event, now));
 
 		self.pending_events
 			.removes_by_inode
-			.retain(|_, event| now.duration_since(event.timestamp) <= timeout);
+			.retain(|_, event| !expired(&self.config, event, now));
 
 		self.pending_events
 			.removes_by_windows_id
-			.retain(|_, event| now.duration_since(event.timestamp) <= timeout);
+			.retain(|_, event| !expired(&self.config, event, now));
 
 		// Clean up expired create events
 		self.pending_events.creates_by_size.retain(|_, events| {
-			events.retain(|event| now.duration_since(event.timestamp) <= timeout);
+			events.retain(|event| !expired(&self.config, event, now));
 			!events.is_empty()
 		});
 
 		self.pending_events
 			.creates_no_size
-			.retain(|event| now.duration_since(event.timestamp) <= timeout);
+			.retain(|event| !expired(&self.config, event, now));
 
 		self.pending_events
 			.creates_by_inode
-			.retain(|_, event| now.duration_since(event.timestamp) <= timeout);
+			.retain(|_, event| !expired(&self.config, event, now));
 
 		self.pending_events
 			.creates_by_windows_id
-			.retain(|_, event| now.duration_since(event.timestamp) <= timeout);
+			.retain(|_, event| !expired(&self.config, event, now));
 		// Clean up old rename from event
 		let had_rename_from = self.pending_events.pending_rename_from.is_some();
-		if let Some((_, timestamp)) = &self.pending_events.pending_rename_from {
-			if now.duration_since(*timestamp) > timeout {
+		if let Some((from, timestamp)) = &self.pending_events.pending_rename_from {
+			if now.duration_since(*timestamp) > self.config.timeout_for(&from.path) {
 				debug!("Cleaning up expired RenameFrom event");
-				self.pending_events.pending_rename_from = None;
+				if let Some((from, _)) = &self.pending_events.pending_rename_from {
+					self.trace(from, LifecycleStage::TimedOut);
+				}
+				released.extend(self.depart_pending_rename_from());
 			}
 		}
+		if let Some(window) = self.config.round_trip_window {
+			self.departures
+				.retain(|departed| now.duration_since(departed.timestamp) <= window);
+		}
 
 		// Count events after cleanup and log if any were removed
 		let final_removes = self.pending_events.count_removes();
@@ -439,16 +1795,87 @@ impl<'a> MoveDetector<'a> {
 		}
 
 		// Clean up old metadata cache entries
-		self.metadata_cache.cleanup_old_entries(timeout * 2); // Keep metadata longer than events
+		// Keep metadata longer than events
+		self.metadata_cache.cleanup_old_entries(self.config.longest_timeout() * 2);
+		released
+	}
+
+	/// Pending removes past their timeout, oldest first
+	fn expiring_removes(&self, now: Instant) -> Vec<PendingEvent> {
+		self.pending_removes(|pending| expired(&self.config, pending, now))
+	}
+
+	/// Pending removes matching `filter`, oldest first
+	fn pending_removes(&self, filter: impl Fn(&PendingEvent) -> bool) -> Vec<PendingEvent> {
+		// A remove is indexed in up to three maps, so dedupe by event id
+		let mut seen = HashSet::new();
+		let mut removes = self
+			.pending_events
+			.removes_by_size
+			.values()
+			.flatten()
+			.chain(self.pending_events.removes_no_size.iter())
+			.chain(self.pending_events.removes_by_inode.values())
+			.chain(self.pending_events.removes_by_windows_id.values())
+			.filter(|pending| filter(pending))
+			.filter(|pending| seen.insert(pending.event.id))
+			.cloned()
+			.collect::<Vec<_>>();
+		removes.sort_by_key(|pending| pending.timestamp);
+		removes
 	}
+
+	/// Move removes that are about to expire into the late-pairing buffer and drop entries
+	/// that have outlived `timeout + window`.
+	fn retain_expired_removes(
+		&mut self, expiring: Vec<PendingEvent>, now: Instant, window: Duration,
+	) {
+		self.expired_removes.extend(expiring);
+
+		let config = &self.config;
+		self.expired_removes.retain(|pending| {
+			now.duration_since(pending.timestamp)
+				<= config.timeout_for(&pending.event.path) + window
+		});
+		while self.expired_removes.len() > LATE_PAIRING_CAPACITY {
+			self.expired_removes.pop_front();
+		}
+	}
+
+	/// Find and remove the most recent expired remove that strongly matches a create.
+	///
+	/// Timing no longer carries signal this late, so only exact name plus kind and size count.
+	fn take_late_remove(&mut self, create: &PendingEvent) -> Option<PendingEvent> {
+		self.config.late_pairing_window?;
+		let create_event = &create.event;
+		let index = self.expired_removes.iter().rposition(|remove| {
+			let remove_event = &remove.event;
+			remove_event.path != create_event.path
+				&& remove_event.path.file_name() == create_event.path.file_name()
+				&& remove_event.is_directory == create_event.is_directory
+				&& MoveMatching::in_scope(remove, create, &self.config)
+				&& (create_event.is_directory
+					|| (remove_event.size.is_some() && remove_event.size == create_event.size))
+		})?;
+		self.expired_removes.remove(index)
+	}
+}
+
+/// Whether `pending` has outlived the timeout for its path
+fn expired(config: &MoveDetectorConfig, pending: &PendingEvent, now: Instant) -> bool {
+	now.duration_since(pending.timestamp) > config.timeout_for(&pending.event.path)
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use crate::move_detection::test_helpers::DummyCache;
+	use crate::database::types::FilesystemNode;
+	use crate::move_detection::config::{ThresholdOverride, TimeoutOverride};
+	use crate::move_detection::matching::XxHashContentHasher;
+	use crate::move_detection::test_helpers::{DummyCache, MockCache};
 	use std::path::PathBuf;
 	use std::time::Duration;
+	use uuid::Uuid;
 
 	#[test]
 	fn test_move_detector_creation() {
@@ -513,4 +1940,1254 @@ mod tests {
 		let stats = detector.get_resource_stats();
 		assert_eq!(stats.total_events_processed, 0);
 	}
+
+	#[tokio::test]
+	async fn test_both_paths_rename_is_a_single_move() {
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(MoveDetectorConfig::default(), &mut cache);
+		let both = |from: &str, to: &str| FileSystemEvent {
+			move_data: Some(MoveEvent::new(
+				PathBuf::from(from),
+				PathBuf::from(to),
+				1.0,
+				MoveDetectionMethod::Rename,
+			)),
+			..FileSystemEvent::new(EventType::Rename, PathBuf::from(to), false, None)
+		};
+
+		let output = detector.process_event(both("/w/old.txt", "/w/new.txt")).await;
+		assert_eq!(output.len(), 1, "{output:?}");
+		assert_eq!(output[0].event_type, EventType::Move);
+		let move_data = output[0].move_data.as_ref().unwrap();
+		assert_eq!(move_data.source_path, PathBuf::from("/w/old.txt"));
+		assert_eq!(move_data.destination_path, PathBuf::from("/w/new.txt"));
+		assert_eq!(move_data.confidence, 1.0);
+		assert_eq!(move_data.detection_method, MoveDetectionMethod::Rename);
+
+		// inotify reports the halves first; the both-paths event then repeats the same rename
+		let half = |event_type, path: &str| {
+			FileSystemEvent::new(event_type, PathBuf::from(path), false, None)
+		};
+		let mut output = detector.process_event(half(EventType::RenameFrom, "/w/a.txt")).await;
+		output.extend(detector.process_event(half(EventType::RenameTo, "/w/b.txt")).await);
+		output.extend(detector.process_event(both("/w/a.txt", "/w/b.txt")).await);
+		let moves: Vec<_> = output
+			.iter()
+			.filter_map(|e| e.move_data.as_ref())
+			.map(|m| (m.source_path.clone(), m.destination_path.clone()))
+			.collect();
+		assert_eq!(
+			moves,
+			[(PathBuf::from("/w/a.txt"), PathBuf::from("/w/b.txt"))]
+		);
+		assert_eq!(output.len(), 1, "{output:?}");
+		assert_eq!(
+			detector.get_resource_stats().moves_by(MoveDetectionMethod::Rename),
+			2
+		);
+	}
+
+	#[tokio::test]
+	async fn test_resource_stats_count_moves_by_method() {
+		let config = MoveDetectorConfig {
+			confidence_threshold: 0.4,
+			pair_generic_renames: false,
+			..Default::default()
+		};
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let event = |event_type, path: &str, size| FileSystemEvent {
+			size,
+			..FileSystemEvent::new(event_type, PathBuf::from(path), false, None)
+		};
+		let mut moves = Vec::new();
+		for (from, to) in [("/w/a.bin", "/x/a.bin"), ("/w/b.bin", "/x/b.bin")] {
+			detector.process_event(event(EventType::Remove, from, Some(42))).await;
+			moves.extend(detector.process_event(event(EventType::Create, to, Some(42))).await);
+		}
+		detector.process_event(event(EventType::RenameFrom, "/w/old.txt", None)).await;
+		moves.extend(detector.process_event(event(EventType::RenameTo, "/w/new.txt", None)).await);
+		let methods: Vec<_> = moves
+			.iter()
+			.filter_map(|e| e.move_data.as_ref())
+			.map(|m| m.detection_method)
+			.collect();
+		assert_eq!(
+			methods,
+			[
+				MoveDetectionMethod::SizeAndTime,
+				MoveDetectionMethod::SizeAndTime,
+				MoveDetectionMethod::Rename
+			]
+		);
+
+		let stats = detector.get_resource_stats();
+		assert_eq!(stats.moves_detected, 3);
+		assert_eq!(stats.moves_by(MoveDetectionMethod::SizeAndTime), 2);
+		assert_eq!(stats.moves_by(MoveDetectionMethod::Rename), 1);
+		assert_eq!(stats.moves_by(MoveDetectionMethod::ContentHash), 0);
+		let rename = &stats.moves_by_method[&MoveDetectionMethod::Rename];
+		assert_eq!(rename.average_confidence(), 1.0);
+		let by_size = &stats.moves_by_method[&MoveDetectionMethod::SizeAndTime];
+		let expected: f32 =
+			moves[..2].iter().map(|e| e.move_data.as_ref().unwrap().confidence).sum();
+		assert!((by_size.average_confidence() - expected / 2.0).abs() < 1e-6);
+	}
+
+	#[tokio::test]
+	async fn test_lifecycle_ties_the_halves_of_a_move_together() {
+		use crate::move_detection::lifecycle::LifecycleSender;
+		let config = MoveDetectorConfig {
+			lifecycle_trace: true,
+			confidence_threshold: 0.4,
+			pair_generic_renames: false,
+			..Default::default()
+		};
+		let mut cache = DummyCache;
+		let (lifecycle, mut receiver) = LifecycleSender::channel();
+		let mut detector = MoveDetector::new(config, &mut cache).with_lifecycle(lifecycle);
+		let event = |event_type, path: &str| FileSystemEvent {
+			size: Some(42),
+			..FileSystemEvent::new(event_type, PathBuf::from(path), false, None)
+		};
+		let remove = event(EventType::Remove, "/w/report.txt");
+		let create = event(EventType::Create, "/x/report.txt");
+		let (remove_id, create_id) = (remove.id, create.id);
+		detector.process_event(remove).await;
+		let moved = detector.process_event(create).await;
+		assert_eq!(moved.len(), 1);
+		assert_eq!(moved[0].id, create_id);
+
+		let mut transitions = Vec::new();
+		while let Ok(transition) = receiver.try_recv() {
+			transitions.push(transition);
+		}
+		let stages = |id| {
+			transitions
+				.iter()
+				.filter(|t| t.correlation_id == id)
+				.map(|t| t.stage.clone())
+				.collect::<Vec<_>>()
+		};
+		let confidence = moved[0].move_data.as_ref().unwrap().confidence;
+		let method = moved[0].move_data.as_ref().unwrap().detection_method;
+		assert_eq!(
+			stages(remove_id),
+			[
+				LifecycleStage::Received,
+				LifecycleStage::Pending,
+				LifecycleStage::Candidate { against: create_id, confidence },
+				LifecycleStage::Matched { counterpart: Some(create_id), confidence, method },
+			]
+		);
+		assert_eq!(
+			stages(create_id),
+			[
+				LifecycleStage::Received,
+				LifecycleStage::Matched { counterpart: Some(remove_id), confidence, method },
+			]
+		);
+
+		// Off by default: an attached channel stays silent
+		let mut cache = DummyCache;
+		let (lifecycle, mut receiver) = LifecycleSender::channel();
+		let mut detector =
+			MoveDetector::new(MoveDetectorConfig::default(), &mut cache).with_lifecycle(lifecycle);
+		detector.process_event(event(EventType::Remove, "/w/report.txt")).await;
+		assert!(receiver.try_recv().is_err());
+	}
+
+	#[tokio::test]
+	async fn test_dyn_and_generic_detectors_agree() {
+		let source = PathBuf::from("/nonexistent/src/same.bin");
+		let destination = PathBuf::from("/nonexistent/dst/same.bin");
+		let events = || {
+			[
+				FileSystemEvent::new(EventType::Remove, source.clone(), false, Some(42)),
+				FileSystemEvent::new(EventType::Create, destination.clone(), false, Some(42)),
+			]
+		};
+
+		let mut dyn_cache = DummyCache;
+		let mut dyn_detector: MoveDetector<'_, dyn FilesystemCacheStorage> =
+			MoveDetector::new(MoveDetectorConfig::default(), &mut dyn_cache);
+		let mut generic_cache = DummyCache;
+		let mut generic_detector: MoveDetector<'_, DummyCache> =
+			MoveDetector::new(MoveDetectorConfig::default(), &mut generic_cache);
+
+		for (dyn_event, generic_event) in events().into_iter().zip(events()) {
+			let from_dyn = dyn_detector.process_event(dyn_event).await;
+			let from_generic = generic_detector.process_event(generic_event).await;
+			let summarize = |events: &[FileSystemEvent]| {
+				events
+					.iter()
+					.map(|e| (e.event_type.clone(), e.path.clone(), e.move_data.clone()))
+					.collect::<Vec<_>>()
+			};
+			assert_eq!(summarize(&from_dyn), summarize(&from_generic));
+		}
+	}
+
+	#[tokio::test]
+	async fn test_other_events_do_not_participate_in_move_detection() {
+		let config = MoveDetectorConfig { confidence_threshold: 0.4, ..Default::default() };
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let source = PathBuf::from("/nonexistent/src/data.bin");
+		let destination = PathBuf::from("/nonexistent/dst/data.bin");
+
+		detector
+			.process_event(FileSystemEvent::new(
+				EventType::Remove,
+				source.clone(),
+				false,
+				Some(42),
+			))
+			.await;
+		let access = FileSystemEvent::new(
+			EventType::Other("access_close_write".to_string()),
+			destination.clone(),
+			false,
+			Some(42),
+		);
+		let output = detector.process_event(access.clone()).await;
+		assert_eq!(output.len(), 1);
+		assert_eq!(output[0].id, access.id);
+		assert!(output[0].move_data.is_none());
+
+		// The pending remove was left for the real create
+		let output = detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				destination,
+				false,
+				Some(42),
+			))
+			.await;
+		assert_eq!(
+			output[0].move_data.as_ref().map(|m| &m.source_path),
+			Some(&source)
+		);
+	}
+
+	/// Pairs a create only with a remove of a file named `b.txt`, whatever else matches
+	#[derive(Debug)]
+	struct OnlyFromB;
+
+	impl crate::move_detection::matching::ConfidenceScorer for OnlyFromB {
+		fn score(
+			&self, remove_event: &PendingEvent, _create_event: &PendingEvent,
+			_config: &MoveDetectorConfig,
+		) -> f32 {
+			let from_b = remove_event.event.path.file_name() == Some("b.txt".as_ref());
+			if from_b {
+				1.0
+			} else {
+				0.0
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn test_custom_confidence_scorer_decides_the_pairing() {
+		let config = MoveDetectorConfig {
+			confidence_scorer: std::sync::Arc::new(OnlyFromB),
+			..Default::default()
+		};
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		for name in ["a.txt", "b.txt"] {
+			let path = PathBuf::from("/w/old").join(name);
+			detector
+				.process_event(FileSystemEvent::new(
+					EventType::Remove,
+					path,
+					false,
+					Some(42),
+				))
+				.await;
+		}
+
+		// Same name and size as a.txt, which the default scorer would prefer
+		let destination = PathBuf::from("/w/new/a.txt");
+		let output = detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				destination,
+				false,
+				Some(42),
+			))
+			.await;
+		assert_eq!(output.len(), 1, "{output:?}");
+		let move_data = output[0].move_data.as_ref().expect("create should be paired");
+		assert_eq!(move_data.source_path, PathBuf::from("/w/old/b.txt"));
+		assert_eq!(move_data.confidence, 1.0);
+	}
+
+	#[tokio::test]
+	async fn test_threshold_overrides_apply_per_directory() {
+		let threshold = |prefix: &str, confidence_threshold| ThresholdOverride {
+			prefix: PathBuf::from(prefix),
+			confidence_threshold,
+		};
+		let config = MoveDetectorConfig {
+			threshold_overrides: vec![
+				threshold("/nonexistent/downloads", 0.4),
+				threshold("/nonexistent/project/node_modules", 0.95),
+				threshold("/nonexistent/project/node_modules/vendored", 0.4),
+			],
+			..Default::default()
+		};
+		assert_eq!(
+			config.threshold_for(Path::new("/nonexistent/project/node_modules/vendored/a")),
+			0.4
+		);
+		assert_eq!(
+			config.threshold_for(Path::new("/nonexistent/project/src/a")),
+			config.confidence_threshold
+		);
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+
+		// The same size-only pair, borderline under the default threshold, in each directory
+		let mut moved = Vec::new();
+		for dir in ["/nonexistent/downloads", "/nonexistent/project/node_modules"] {
+			let dir = PathBuf::from(dir);
+			let remove =
+				FileSystemEvent::new(EventType::Remove, dir.join("pkg.tgz"), false, Some(4096));
+			detector.process_event(remove).await;
+			let create = FileSystemEvent::new(
+				EventType::Create,
+				dir.join("sub/pkg.tgz"),
+				false,
+				Some(4096),
+			);
+			let output = detector.process_event(create).await;
+			moved.push(output.iter().any(|event| event.move_data.is_some()));
+			detector.reset(false);
+		}
+		assert_eq!(moved, vec![true, false]);
+	}
+
+	#[tokio::test]
+	async fn test_timeout_overrides_hold_events_longer_under_a_slow_prefix() {
+		let config = MoveDetectorConfig {
+			timeout: Duration::from_millis(50),
+			timeout_overrides: vec![TimeoutOverride {
+				prefix: PathBuf::from("/nonexistent/staging"),
+				timeout: Duration::from_secs(10),
+			}],
+			confidence_threshold: 0.4,
+			..Default::default()
+		};
+		assert_eq!(
+			config.pair_timeout(
+				Path::new("/nonexistent/a"),
+				Path::new("/nonexistent/staging/a")
+			),
+			Duration::from_secs(10)
+		);
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+
+		// The create comes well past the global timeout in both directories
+		let mut moved = Vec::new();
+		for dir in ["/nonexistent/fast", "/nonexistent/staging"] {
+			let dir = PathBuf::from(dir);
+			let remove =
+				FileSystemEvent::new(EventType::Remove, dir.join("big.iso"), false, Some(4096));
+			detector.process_event(remove).await;
+			tokio::time::sleep(Duration::from_millis(150)).await;
+			let create = FileSystemEvent::new(
+				EventType::Create,
+				dir.join("done/big.iso"),
+				false,
+				Some(4096),
+			);
+			let output = detector.process_event(create).await;
+			moved.push(output.iter().any(|event| event.event_type == EventType::Move));
+			detector.reset(false);
+		}
+		assert_eq!(moved, vec![false, true]);
+	}
+
+	#[tokio::test]
+	async fn test_remove_takes_its_size_from_the_persistent_cache() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, destination) = (
+			dir.path().join("report.txt"),
+			dir.path().join("b/report.txt"),
+		);
+		std::fs::write(&source, "eleven byte").unwrap();
+		let node = FilesystemNode::new(source.clone(), &std::fs::metadata(&source).unwrap());
+		std::fs::remove_file(&source).unwrap();
+		let config = MoveDetectorConfig { confidence_threshold: 0.4, ..Default::default() };
+		let remove = FileSystemEvent::new(EventType::Remove, source.clone(), false, None);
+		let create = FileSystemEvent::new(EventType::Create, destination, false, Some(11));
+
+		// The remove has no size of its own; the seeded node supplies it and the pair matches
+		let mut cache = MockCache::new().with_node(Uuid::new_v4(), node);
+		let mut detector = MoveDetector::new(config.clone(), &mut cache);
+		let removed = detector.process_event(remove.clone()).await;
+		assert_eq!(removed[0].size, Some(11));
+		let output = detector.process_event(create).await;
+		assert_eq!(
+			output[0].move_data.as_ref().map(|data| &data.source_path),
+			Some(&source)
+		);
+
+		// A failing cache is no worse than an empty one: the remove just has no size
+		let mut cache = MockCache::new().failing("disk gone");
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let removed = detector.process_event(remove).await;
+		assert_eq!(removed.len(), 1);
+		assert_eq!(removed[0].size, None);
+	}
+
+	#[tokio::test]
+	async fn test_create_before_remove_pairs_like_remove_before_create() {
+		let config = MoveDetectorConfig {
+			confidence_threshold: 0.4,
+			timeout: Duration::from_secs(60),
+			..Default::default()
+		};
+		let (source, destination) = (
+			PathBuf::from("/nonexistent/src/photo.jpg"),
+			PathBuf::from("/nonexistent/dst/photo.jpg"),
+		);
+		let remove = FileSystemEvent::new(EventType::Remove, source.clone(), false, Some(2048));
+		let create =
+			FileSystemEvent::new(EventType::Create, destination.clone(), false, Some(2048));
+
+		let mut moves = Vec::new();
+		for order in [[&remove, &create], [&create, &remove]] {
+			let mut cache = DummyCache;
+			let mut detector = MoveDetector::new(config.clone(), &mut cache);
+			let first = detector.process_event(order[0].clone()).await;
+			assert!(first.iter().all(|event| event.move_data.is_none()));
+			let output = detector.process_event(order[1].clone()).await;
+			assert_eq!(output.len(), 1, "{output:?}");
+			assert_eq!(output[0].event_type, EventType::Move);
+			assert_eq!(output[0].path, destination);
+			moves.push(output[0].move_data.clone().unwrap());
+		}
+		for move_data in &moves {
+			assert_eq!(move_data.source_path, source);
+			assert_eq!(move_data.destination_path, destination);
+			assert_eq!(move_data.detection_method, MoveDetectionMethod::SizeAndTime);
+		}
+		// Microseconds apart either way, so the time factor is all but 1.0 for both orders
+		assert!(
+			(moves[0].confidence - moves[1].confidence).abs() < 0.01,
+			"{moves:?}"
+		);
+	}
+
+	async fn reorganization_confidences(weight_parent_correlation: f32) -> Vec<f32> {
+		let config = MoveDetectorConfig {
+			confidence_threshold: 0.4,
+			weight_parent_correlation,
+			..Default::default()
+		};
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let mut confidences = Vec::new();
+		for i in 0..5u64 {
+			let name = format!("file_{i}.bin");
+			let source = PathBuf::from("/nonexistent/old").join(&name);
+			let destination = PathBuf::from("/nonexistent/new").join(&name);
+			let size = Some(100 + i);
+			detector
+				.process_event(FileSystemEvent::new(
+					EventType::Remove,
+					source.clone(),
+					false,
+					size,
+				))
+				.await;
+			let output = detector
+				.process_event(FileSystemEvent::new(
+					EventType::Create,
+					destination,
+					false,
+					size,
+				))
+				.await;
+			let move_data = output[0].move_data.as_ref().expect("create not paired");
+			assert_eq!(move_data.source_path, source);
+			confidences.push(move_data.confidence);
+		}
+		confidences
+	}
+
+	#[tokio::test]
+	async fn test_later_moves_of_a_reorganization_get_the_parent_correlation_bonus() {
+		let boosted = reorganization_confidences(0.1).await;
+		let plain = reorganization_confidences(0.0).await;
+
+		// The first move has nothing to correlate with
+		assert!(
+			(boosted[0] - plain[0]).abs() < 0.01,
+			"{boosted:?} vs {plain:?}"
+		);
+		for (later, baseline) in boosted[1..].iter().zip(&plain[1..]) {
+			assert!(
+				(later - baseline - 0.1).abs() < 0.01,
+				"{boosted:?} vs {plain:?}"
+			);
+			assert!(*later > boosted[0]);
+		}
+	}
+
+	#[test]
+	fn test_parent_correlations_ignore_same_directory_renames() {
+		let mut correlations = ParentCorrelations::new(Duration::from_secs(2));
+		correlations.record(Path::new("/a/x.txt"), Path::new("/a/y.txt"));
+		assert!(!correlations.contains(Path::new("/a/z.txt"), Path::new("/a/w.txt")));
+
+		correlations.record(Path::new("/a/x.txt"), Path::new("/b/x.txt"));
+		assert!(correlations.contains(Path::new("/a/other.txt"), Path::new("/b/other.txt")));
+		assert!(!correlations.contains(Path::new("/b/other.txt"), Path::new("/a/other.txt")));
+	}
+
+	struct CountingHasher(std::sync::atomic::AtomicUsize);
+
+	impl ContentHasher for CountingHasher {
+		fn hash_file(&self, path: &Path) -> Option<String> {
+			self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+			XxHashContentHasher.hash_file(path)
+		}
+	}
+
+	#[tokio::test]
+	async fn test_content_hash_reused_while_size_and_mtime_unchanged() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("data.bin");
+		std::fs::write(&path, b"first").unwrap();
+
+		let hasher = Arc::new(CountingHasher(Default::default()));
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(MoveDetectorConfig::default(), &mut cache)
+			.with_content_hasher(hasher.clone());
+		let calls = || hasher.0.load(std::sync::atomic::Ordering::SeqCst);
+
+		let first = detector.content_hash(&path).await;
+		let second = detector.content_hash(&path).await;
+		assert!(first.is_some());
+		assert_eq!(first, second);
+		assert_eq!(calls(), 1);
+
+		// A different size invalidates the cached hash
+		std::fs::write(&path, b"second write").unwrap();
+		let third = detector.content_hash(&path).await;
+		assert_ne!(third, first);
+		assert_eq!(calls(), 2);
+
+		// Same size, different mtime: rehashed as well
+		let file = std::fs::File::options().write(true).open(&path).unwrap();
+		file.set_modified(std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1))
+			.unwrap();
+		detector.content_hash(&path).await;
+		assert_eq!(calls(), 3);
+	}
+
+	#[tokio::test]
+	async fn test_generic_renames_are_paired_by_which_path_exists() {
+		// The sequence FSEvents reports for `mv draft.txt final.txt` and for a rename out of
+		// the watch: each path flagged as renamed, no cookie, no inode for the old name
+		let dir = tempfile::tempdir().unwrap();
+		let (draft, renamed, moved_out) = (
+			dir.path().join("draft.txt"),
+			dir.path().join("final.txt"),
+			dir.path().join("gone.txt"),
+		);
+		std::fs::write(&renamed, "text").unwrap();
+		let config = MoveDetectorConfig {
+			pair_generic_renames: true,
+			timeout: Duration::from_millis(50),
+			..Default::default()
+		};
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let rename =
+			|path: &PathBuf| FileSystemEvent::new(EventType::Rename, path.clone(), false, None);
+
+		assert!(detector.process_event(rename(&draft)).await.is_empty());
+		let events = detector.process_event(rename(&renamed)).await;
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].event_type, EventType::Move);
+		let move_data = events[0].move_data.as_ref().unwrap();
+		assert_eq!(
+			(&move_data.source_path, &move_data.destination_path),
+			(&draft, &renamed)
+		);
+		assert_eq!(move_data.detection_method, MoveDetectionMethod::Rename);
+		assert_eq!(move_data.confidence, 1.0);
+
+		assert!(detector.process_event(rename(&moved_out)).await.is_empty());
+		assert!(detector.has_deferred_removes());
+		tokio::time::sleep(Duration::from_millis(80)).await;
+		let released = detector.take_expired_removes().await;
+		assert_eq!(released.len(), 1);
+		assert_eq!(released[0].event_type, EventType::Remove);
+		assert_eq!(released[0].path, moved_out);
+		assert!(!detector.has_deferred_removes());
+	}
+
+	#[tokio::test]
+	async fn test_reset_clears_pending_state_and_releases_deferred_removes() {
+		let config = MoveDetectorConfig { defer_removes: true, ..Default::default() };
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let event = |event_type, path: &str, size| FileSystemEvent {
+			size,
+			..FileSystemEvent::new(event_type, PathBuf::from(path), false, None)
+		};
+		for (path, size) in [("/w/a.txt", Some(10)), ("/w/b.txt", None)] {
+			assert!(detector.process_event(event(EventType::Remove, path, size)).await.is_empty());
+		}
+		detector.process_event(event(EventType::Create, "/w/c.bin", Some(99))).await;
+		detector.process_event(event(EventType::RenameFrom, "/w/d.txt", None)).await;
+		detector
+			.metadata_cache
+			.insert(PathBuf::from("/w/e.txt"), FileMetadata::new(None, None));
+		let summary = detector.get_pending_events_summary();
+		assert_eq!(summary.total_removes(), 2);
+		assert!(summary.has_pending_rename_from);
+
+		let released = detector.reset(true);
+		let released: Vec<_> =
+			released.iter().map(|e| (e.event_type.clone(), e.path.clone())).collect();
+		assert_eq!(
+			released,
+			[
+				(EventType::Remove, PathBuf::from("/w/a.txt")),
+				(EventType::Remove, PathBuf::from("/w/b.txt")),
+				(EventType::Remove, PathBuf::from("/w/d.txt")),
+			]
+		);
+		let summary = detector.get_pending_events_summary();
+		assert_eq!((summary.total_removes(), summary.total_creates()), (0, 0));
+		assert!(!summary.has_pending_rename_from);
+		assert!(detector.metadata_cache.is_empty());
+		assert!(!detector.has_deferred_removes());
+	}
+
+	#[tokio::test]
+	async fn test_remove_and_recreate_at_the_same_path_is_a_single_write() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("data.bin");
+		let config = MoveDetectorConfig {
+			rewrite_window: Some(Duration::from_millis(200)),
+			..Default::default()
+		};
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let event = |event_type| FileSystemEvent::new(event_type, path.clone(), false, None);
+		std::fs::write(&path, vec![1u8; 1000]).unwrap();
+		detector.process_event(event(EventType::Create)).await;
+
+		// Rewritten with more content: one Write, nothing left to release
+		std::fs::remove_file(&path).unwrap();
+		assert!(detector.process_event(event(EventType::Remove)).await.is_empty());
+		assert!(detector.has_deferred_removes());
+		std::fs::write(&path, vec![2u8; 1200]).unwrap();
+		let events = detector.process_event(event(EventType::Create)).await;
+		assert_eq!(events.len(), 1, "{events:?}");
+		assert_eq!(events[0].event_type, EventType::Write);
+		assert_eq!(events[0].path, path);
+		assert!(!detector.has_deferred_removes());
+
+		// A much smaller file at the same path is a new one
+		std::fs::remove_file(&path).unwrap();
+		assert!(detector.process_event(event(EventType::Remove)).await.is_empty());
+		std::fs::write(&path, b"tiny").unwrap();
+		let events = detector.process_event(event(EventType::Create)).await;
+		let kinds: Vec<_> = events.iter().map(|e| e.event_type.clone()).collect();
+		assert_eq!(kinds, [EventType::Remove, EventType::Create]);
+
+		// Not recreated within the window: the Remove is released once it passes
+		std::fs::remove_file(&path).unwrap();
+		assert!(detector.process_event(event(EventType::Remove)).await.is_empty());
+		tokio::time::sleep(Duration::from_millis(300)).await;
+		let released = detector.take_expired_removes().await;
+		assert_eq!(released.len(), 1);
+		assert_eq!(released[0].event_type, EventType::Remove);
+		assert!(!detector.has_deferred_removes());
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_prewarmed_file_pairs_by_inode_after_it_is_gone() {
+		let dir = tempfile::tempdir().unwrap();
+		std::fs::create_dir(dir.path().join("archive")).unwrap();
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(MoveDetectorConfig::default(), &mut cache);
+		let move_away = |name: &str| {
+			let (source, destination) = (
+				dir.path().join(name),
+				dir.path().join("archive").join("renamed.dat"),
+			);
+			std::fs::write(&source, "existed before the watcher").unwrap();
+			(source, destination)
+		};
+
+		// Without prewarming, the remove of a file that is already gone has no inode
+		let (source, destination) = move_away("cold.txt");
+		std::fs::rename(&source, &destination).unwrap();
+		let remove = FileSystemEvent::new(EventType::Remove, source, false, None);
+		detector.process_event(remove).await;
+		let size = Some(std::fs::metadata(&destination).unwrap().len());
+		let create = FileSystemEvent::new(EventType::Create, destination.clone(), false, size);
+		let events = detector.process_event(create).await;
+		assert!(events.iter().all(|event| event
+			.move_data
+			.as_ref()
+			.is_none_or(|data| data.detection_method != MoveDetectionMethod::Inode)));
+		std::fs::remove_file(&destination).unwrap();
+		detector.reset(false);
+
+		let (source, destination) = move_away("warm.txt");
+		assert_eq!(
+			detector.prewarm(&[source.clone(), dir.path().join("missing")]).await,
+			1
+		);
+		std::fs::rename(&source, &destination).unwrap();
+		let remove = FileSystemEvent::new(EventType::Remove, source.clone(), false, None);
+		detector.process_event(remove).await;
+		let create = FileSystemEvent::new(EventType::Create, destination.clone(), false, size);
+		let events = detector.process_event(create).await;
+		assert_eq!(events.len(), 1);
+		let move_data = events[0].move_data.as_ref().expect("a move");
+		assert_eq!(move_data.source_path, source);
+		assert_eq!(move_data.destination_path, destination);
+		assert_eq!(move_data.detection_method, MoveDetectionMethod::Inode);
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_verify_moves_rejects_an_inode_match_with_other_content() {
+		let dir = tempfile::tempdir().unwrap();
+		let (source, destination) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+		let run = |verify_moves| {
+			let (source, destination) = (source.clone(), destination.clone());
+			async move {
+				let config = MoveDetectorConfig {
+					verify_moves,
+					confidence_threshold: 0.5,
+					..Default::default()
+				};
+				let mut cache = DummyCache;
+				let mut detector = MoveDetector::new(config, &mut cache);
+				// The remove is seen while a.txt is still a link to b.txt's inode; by the time
+				// b.txt's create arrives, a.txt has been replaced by an unrelated file
+				std::fs::write(&source, "original").unwrap();
+				std::fs::hard_link(&source, &destination).unwrap();
+				let remove = FileSystemEvent::new(EventType::Remove, source.clone(), false, None);
+				detector.process_event(remove).await;
+				std::fs::remove_file(&source).unwrap();
+				std::fs::write(&source, "replacement").unwrap();
+				let create =
+					FileSystemEvent::new(EventType::Create, destination.clone(), false, Some(8));
+				let events = detector.process_event(create).await;
+				std::fs::remove_file(&source).unwrap();
+				std::fs::remove_file(&destination).unwrap();
+				events
+			}
+		};
+
+		let events = run(false).await;
+		assert_eq!(events.len(), 1);
+		let move_data = events[0].move_data.as_ref().expect("a move");
+		assert_eq!(move_data.detection_method, MoveDetectionMethod::Inode);
+
+		let events = run(true).await;
+		assert!(events.iter().all(|event| event.move_data.is_none()));
+		assert!(events
+			.iter()
+			.all(|event| event.event_type != EventType::Move && event.path == destination));
+	}
+
+	#[tokio::test]
+	async fn test_size_tolerance_pairs_a_file_that_grew_during_the_move() {
+		let moved = |size_tolerance, created_size| async move {
+			// No inode or content to go on: size, time and name alone have to clear it
+			let config = MoveDetectorConfig {
+				size_tolerance,
+				confidence_threshold: 0.5,
+				..Default::default()
+			};
+			let mut cache = DummyCache;
+			let mut detector = MoveDetector::new(config, &mut cache);
+			let remove = FileSystemEvent::new(
+				EventType::Remove,
+				PathBuf::from("/w/inbox/report.txt"),
+				false,
+				Some(1000),
+			);
+			detector.process_event(remove).await;
+			let create = FileSystemEvent::new(
+				EventType::Create,
+				PathBuf::from("/w/done/report.txt"),
+				false,
+				Some(created_size),
+			);
+			let events = detector.process_event(create).await;
+			events.into_iter().find_map(|event| event.move_data)
+		};
+
+		let exact = moved(0, 1000).await.expect("an exact-size move");
+		assert!(moved(0, 1003).await.is_none());
+		let near = moved(16, 1003).await.expect("a move within the tolerance");
+		assert_eq!(near.source_path, PathBuf::from("/w/inbox/report.txt"));
+		assert_eq!(near.detection_method, MoveDetectionMethod::Heuristics);
+		assert!(near.confidence < exact.confidence);
+		// The penalty grows with the difference
+		let nearer = moved(16, 1001).await.expect("a move within the tolerance");
+		assert!(near.confidence < nearer.confidence && nearer.confidence < exact.confidence);
+		assert!(moved(16, 1017).await.is_none());
+	}
+
+	#[tokio::test]
+	async fn test_near_miss_reports_the_neighbouring_size_bucket() {
+		let config = MoveDetectorConfig { near_miss_diagnostics: true, ..Default::default() };
+		let (diagnostics, mut diagnostics_rx) = DiagnosticsSender::channel();
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache).with_diagnostics(diagnostics);
+		let event = |event_type, path: &str, size| {
+			FileSystemEvent::new(event_type, PathBuf::from(path), false, Some(size))
+		};
+
+		detector.process_event(event(EventType::Remove, "/w/data.log", 100)).await;
+		// Nothing was pending for the remove, so it is no near miss
+		assert!(diagnostics_rx.try_recv().is_err());
+		// Grew by one byte between the remove and the create
+		let result =
+			detector.process_event(event(EventType::Create, "/w/moved/data.log", 101)).await;
+		assert_eq!(result[0].event_type, EventType::Create);
+
+		match diagnostics_rx.try_recv().unwrap() {
+			WatcherDiagnostic::MoveNearMiss {
+				path,
+				event_type,
+				bucket,
+				bucket_candidates,
+				nearest_bucket,
+				total_candidates,
+			} => {
+				assert_eq!(path, PathBuf::from("/w/moved/data.log"));
+				assert_eq!(event_type, EventType::Create);
+				assert_eq!(bucket, Some(101));
+				assert_eq!(bucket_candidates, 0);
+				assert_eq!(nearest_bucket, Some((100, 1)));
+				assert_eq!(total_candidates, 1);
+			}
+			other => panic!("unexpected diagnostic: {other:?}"),
+		}
+	}
+
+	#[tokio::test]
+	async fn test_name_similarity_cap_leaves_only_identity_matches() {
+		// Low enough for size, time and name to pair on their own
+		let uncapped = MoveDetectorConfig { confidence_threshold: 0.5, ..Default::default() };
+		let config =
+			MoveDetectorConfig { name_similarity_candidate_cap: Some(4), ..uncapped.clone() };
+		let mut storage = PendingEventsStorage::new();
+		for i in 0..10 {
+			let path = PathBuf::from(format!("/w/dst/file{i}.bin"));
+			let create = FileSystemEvent::new(EventType::Create, path, false, Some(4096));
+			storage.add_create(
+				PendingEvent::new(create)
+					.with_inode(Some(100 + i))
+					.with_windows_id(Some(100 + i)),
+			);
+		}
+		let remove = |name: &str, id: Option<u64>| {
+			let path = PathBuf::from(format!("/w/src/{name}"));
+			PendingEvent::new(FileSystemEvent::new(
+				EventType::Remove,
+				path,
+				false,
+				Some(4096),
+			))
+			.with_inode(id)
+			.with_windows_id(id)
+		};
+		let correlations = ParentCorrelations::new(config.timeout);
+		let find = |remove: &PendingEvent, config: &MoveDetectorConfig| {
+			pollster::block_on(MoveMatching::find_matching_create(
+				remove,
+				&storage,
+				config,
+				&correlations,
+			))
+			.map(|create| create.event.path)
+		};
+
+		// The file's identity still pairs past the cap
+		assert_eq!(
+			find(&remove("renamed.bin", Some(103)), &config),
+			Some(PathBuf::from("/w/dst/file3.bin"))
+		);
+		// Size, time and name alone do without the cap, but the bucket is too full to score
+		let by_name = remove("file5.bin", None);
+		assert_eq!(
+			find(&by_name, &uncapped),
+			Some(PathBuf::from("/w/dst/file5.bin"))
+		);
+		assert_eq!(find(&by_name, &config), None);
+
+		let (diagnostics, mut diagnostics_rx) = DiagnosticsSender::channel();
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache).with_diagnostics(diagnostics);
+		for i in 0..5 {
+			let path = PathBuf::from(format!("/w/dst/file{i}.bin"));
+			detector
+				.process_event(FileSystemEvent::new(
+					EventType::Create,
+					path,
+					false,
+					Some(4096),
+				))
+				.await;
+		}
+		assert!(diagnostics_rx.try_recv().is_err());
+		let path = PathBuf::from("/w/src/file9.bin");
+		detector
+			.process_event(FileSystemEvent::new(
+				EventType::Remove,
+				path.clone(),
+				false,
+				Some(4096),
+			))
+			.await;
+		assert_eq!(
+			diagnostics_rx.try_recv().unwrap(),
+			WatcherDiagnostic::NameSimilarityCapped {
+				path,
+				event_type: EventType::Remove,
+				candidates: 5,
+			}
+		);
+	}
+
+	#[derive(Default)]
+	struct ConcurrencyProbe {
+		current: std::sync::atomic::AtomicUsize,
+		max: std::sync::atomic::AtomicUsize,
+	}
+
+	impl ContentHasher for ConcurrencyProbe {
+		fn hash_file(&self, path: &Path) -> Option<String> {
+			use std::sync::atomic::Ordering::SeqCst;
+			let now = self.current.fetch_add(1, SeqCst) + 1;
+			self.max.fetch_max(now, SeqCst);
+			std::thread::sleep(Duration::from_millis(20));
+			self.current.fetch_sub(1, SeqCst);
+			XxHashContentHasher.hash_file(path)
+		}
+	}
+
+	#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+	async fn test_bounded_hasher_never_exceeds_its_limit() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("data.bin");
+		std::fs::write(&path, b"contents").unwrap();
+
+		let probe = Arc::new(ConcurrencyProbe::default());
+		let hasher = BoundedHasher::new(probe.clone(), 3);
+		let tasks: Vec<_> = (0..32)
+			.map(|_| {
+				let hasher = hasher.clone();
+				let path = path.clone();
+				tokio::spawn(async move { hasher.hash_file(&path).await })
+			})
+			.collect();
+		for task in tasks {
+			assert!(task.await.unwrap().is_some());
+		}
+		let max = probe.max.load(std::sync::atomic::Ordering::SeqCst);
+		assert!((1..=3).contains(&max), "peak concurrency {max}");
+	}
+
+	#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+	async fn test_detectors_share_the_hash_limit() {
+		// A limit no other test configures, so only this test takes its permits
+		const LIMIT: usize = 7;
+		let dir = tempfile::tempdir().unwrap();
+		let paths: Vec<PathBuf> = (0..24)
+			.map(|i| {
+				let path = dir.path().join(format!("{i}.bin"));
+				std::fs::write(&path, format!("contents {i}")).unwrap();
+				path
+			})
+			.collect();
+
+		let probe = Arc::new(ConcurrencyProbe::default());
+		let config = MoveDetectorConfig { max_concurrent_hashes: LIMIT, ..Default::default() };
+		let (mut first_cache, mut second_cache) = (DummyCache, DummyCache);
+		let mut first =
+			MoveDetector::new(config.clone(), &mut first_cache).with_content_hasher(probe.clone());
+		let mut second =
+			MoveDetector::new(config, &mut second_cache).with_content_hasher(probe.clone());
+		let (first_half, second_half) = paths.split_at(12);
+		let (a, b) = tokio::join!(first.prehash(first_half), second.prehash(second_half));
+		assert_eq!(a + b, 24);
+		let max = probe.max.load(std::sync::atomic::Ordering::SeqCst);
+		assert!((2..=LIMIT).contains(&max), "peak concurrency {max}");
+	}
+
+	#[tokio::test]
+	async fn test_content_hash_cache_can_be_disabled() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("data.bin");
+		std::fs::write(&path, b"contents").unwrap();
+
+		let hasher = Arc::new(CountingHasher(Default::default()));
+		let config = MoveDetectorConfig { cache_content_hashes: false, ..Default::default() };
+		let mut cache = DummyCache;
+		let mut detector =
+			MoveDetector::new(config, &mut cache).with_content_hasher(hasher.clone());
+		detector.content_hash(&path).await;
+		detector.content_hash(&path).await;
+		assert_eq!(hasher.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+	}
+
+	async fn create_with_index(
+		database: &Arc<redb::Database>, path: &Path,
+	) -> Vec<FileSystemEvent> {
+		let config = MoveDetectorConfig { content_hash_index: true, ..Default::default() };
+		create_indexed(database, path, config).await
+	}
+
+	async fn create_indexed(
+		database: &Arc<redb::Database>, path: &Path, config: MoveDetectorConfig,
+	) -> Vec<FileSystemEvent> {
+		// A fresh detector and cache handle each time, as after a restart
+		let mut cache =
+			crate::database::storage::filesystem_cache::RedbFilesystemCache::new(database.clone());
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let size = std::fs::metadata(path).ok().map(|metadata| metadata.len());
+		detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				path.to_path_buf(),
+				false,
+				size,
+			))
+			.await
+	}
+
+	#[tokio::test]
+	async fn test_content_hash_index_pairs_a_move_made_before_restart() {
+		let dir = tempfile::tempdir().unwrap();
+		let database = Arc::new(redb::Database::create(dir.path().join("index.redb")).unwrap());
+		let original = dir.path().join("report.txt");
+		std::fs::write(&original, "quarterly numbers").unwrap();
+		create_with_index(&database, &original).await;
+
+		// Moved while nothing was watching: no Remove is ever seen
+		let archived = dir.path().join("archive").join("report-2024.txt");
+		std::fs::create_dir(archived.parent().unwrap()).unwrap();
+		std::fs::rename(&original, &archived).unwrap();
+
+		let events = create_with_index(&database, &archived).await;
+		assert_eq!(events.len(), 1);
+		let move_data = events[0].move_data.as_ref().expect("move through the index");
+		assert_eq!(move_data.source_path, original);
+		assert_eq!(move_data.destination_path, archived);
+		assert_eq!(move_data.detection_method, MoveDetectionMethod::ContentHash);
+		assert!(move_data.late);
+
+		// The entry followed the file, so the next restart pairs from the new location
+		let restored = dir.path().join("report.txt");
+		std::fs::rename(&archived, &restored).unwrap();
+		let events = create_with_index(&database, &restored).await;
+		assert_eq!(
+			events[0].move_data.as_ref().map(|m| m.source_path.clone()),
+			Some(archived)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_content_hash_index_does_not_pair_copies_of_existing_files() {
+		let dir = tempfile::tempdir().unwrap();
+		let database = Arc::new(redb::Database::create(dir.path().join("index.redb")).unwrap());
+		let original = dir.path().join("template.txt");
+		std::fs::write(&original, "shared boilerplate").unwrap();
+		create_with_index(&database, &original).await;
+
+		let copy = dir.path().join("copy.txt");
+		std::fs::copy(&original, &copy).unwrap();
+		let events = create_with_index(&database, &copy).await;
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].event_type, EventType::Create);
+		assert!(events[0].move_data.is_none());
+	}
+
+	#[tokio::test]
+	async fn test_record_decisions_stores_every_weighed_pairing() {
+		let dir = tempfile::tempdir().unwrap();
+		let database = Arc::new(redb::Database::create(dir.path().join("decisions.redb")).unwrap());
+		let mut cache =
+			crate::database::storage::filesystem_cache::RedbFilesystemCache::new(database.clone());
+		// Without inode or content to go on, size, timing and name decide
+		let config = MoveDetectorConfig {
+			record_decisions: true,
+			confidence_threshold: 0.5,
+			..Default::default()
+		};
+		let threshold = config.confidence_threshold;
+		let weight_size = config.weight_size_match;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		for name in ["a.txt", "b.txt"] {
+			let path = PathBuf::from("/w/old").join(name);
+			detector
+				.process_event(FileSystemEvent::new(
+					EventType::Remove,
+					path,
+					false,
+					Some(42),
+				))
+				.await;
+		}
+		let destination = PathBuf::from("/w/new/a.txt");
+		let output = detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				destination.clone(),
+				false,
+				Some(42),
+			))
+			.await;
+		assert!(output[0].is_move());
+
+		let mut records = cache.move_decisions().await.unwrap();
+		records.sort_by(|a, b| a.source.cmp(&b.source));
+		assert_eq!(records.len(), 2, "{records:?}");
+		let (accepted, rejected) = (&records[0], &records[1]);
+		assert_eq!(accepted.source, PathBuf::from("/w/old/a.txt"));
+		assert_eq!(rejected.source, PathBuf::from("/w/old/b.txt"));
+		for record in &records {
+			assert_eq!(record.destination, destination);
+			assert_eq!(record.threshold, threshold);
+			assert_eq!(record.signals.size, 1.0);
+			assert_eq!(record.contributions.size, weight_size);
+		}
+		assert!(accepted.accepted && !rejected.accepted);
+		assert!(accepted.confidence >= threshold);
+		assert!(accepted.signals.name_similarity > rejected.signals.name_similarity);
+	}
+
+	#[tokio::test]
+	async fn test_detect_copies_reports_the_existing_source() {
+		let dir = tempfile::tempdir().unwrap();
+		let database = Arc::new(redb::Database::create(dir.path().join("index.redb")).unwrap());
+		let config = MoveDetectorConfig {
+			content_hash_index: true,
+			detect_copies: true,
+			..Default::default()
+		};
+		let original = dir.path().join("photo.jpg");
+		std::fs::write(&original, "pixels").unwrap();
+		let events = create_indexed(&database, &original, config.clone()).await;
+		assert_eq!(events[0].event_type, EventType::Create);
+
+		let copy = dir.path().join("backup").join("photo.jpg");
+		std::fs::create_dir(copy.parent().unwrap()).unwrap();
+		std::fs::copy(&original, &copy).unwrap();
+		let events = create_indexed(&database, &copy, config.clone()).await;
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].event_type, EventType::Copy);
+		assert_eq!(events[0].path, copy);
+		assert!(!events[0].is_move());
+		let copy_data = events[0].move_data.as_ref().unwrap();
+		assert_eq!(copy_data.source_path, original);
+		assert_eq!(copy_data.destination_path, copy);
+		assert_eq!(copy_data.detection_method, MoveDetectionMethod::ContentHash);
+
+		// A source edited since it was indexed is no longer a match
+		let other = dir.path().join("other.jpg");
+		std::fs::copy(&copy, &other).unwrap();
+		std::fs::write(&copy, "retouched").unwrap();
+		let events = create_indexed(&database, &other, config).await;
+		assert_eq!(events[0].event_type, EventType::Create);
+	}
+
+	#[tokio::test]
+	async fn test_cache_node_type_that_disagrees_with_disk_is_not_used() {
+		use crate::database::storage::filesystem_cache::RedbFilesystemCache;
+		use crate::database::types::FilesystemNode;
+
+		let dir = tempfile::tempdir().unwrap();
+		let database = Arc::new(redb::Database::create(dir.path().join("cache.redb")).unwrap());
+		crate::database::storage::tables::initialize_tables(&database).await.unwrap();
+		let mut cache = RedbFilesystemCache::new(database);
+		let source = dir.path().join("assets");
+		let destination = dir.path().join("moved").join("assets");
+		let watch_id = uuid::Uuid::new_v4();
+
+		// Cached while `assets` was a 999-byte file; replaced by a directory while unwatched
+		std::fs::write(&source, vec![0u8; 999]).unwrap();
+		let node = FilesystemNode::new_with_event_type(
+			source.clone(),
+			&std::fs::metadata(&source).unwrap(),
+			None,
+		);
+		cache.store_filesystem_node(&watch_id, &node, "Create").await.unwrap();
+		std::fs::remove_file(&source).unwrap();
+		std::fs::create_dir(&source).unwrap();
+		std::fs::create_dir(destination.parent().unwrap()).unwrap();
+		std::fs::rename(&source, &destination).unwrap();
+
+		// Explicit weights keep the scores the same on every platform. The stale size would
+		// score 0.47 here, below the threshold; the corrected directory scores 0.51.
+		let config = MoveDetectorConfig {
+			timeout: Duration::from_secs(60),
+			confidence_threshold: 0.5,
+			weight_size_match: 0.2,
+			weight_time_factor: 0.15,
+			weight_inode_match: 0.35,
+			weight_content_hash: 0.2,
+			weight_name_similarity: 0.1,
+			..Default::default()
+		};
+		let (diagnostics, mut diagnostics_rx) = DiagnosticsSender::channel();
+		let mut detector = MoveDetector::new(config, &mut cache).with_diagnostics(diagnostics);
+		// As the watcher reports it: the backend said the removed path was a directory
+		detector
+			.process_event(FileSystemEvent::new(
+				EventType::Remove,
+				source.clone(),
+				true,
+				None,
+			))
+			.await;
+		let events = detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				destination.clone(),
+				true,
+				None,
+			))
+			.await;
+
+		let move_data = events[0].move_data.as_ref().expect("directory move detected");
+		assert_eq!(move_data.source_path, source);
+		assert!(events[0].is_directory);
+		assert_eq!(
+			diagnostics_rx.try_recv().unwrap(),
+			WatcherDiagnostic::CacheMismatch {
+				path: source.clone(),
+				cached_is_directory: false,
+				disk_is_directory: true,
+			}
+		);
+		drop(detector);
+		assert!(
+			cache.get_unified_node(&source).await.unwrap().is_none(),
+			"stale node kept"
+		);
+		assert!(cache.get_filesystem_node(&watch_id, &source).await.unwrap().is_none());
+	}
 }
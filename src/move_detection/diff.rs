@@ -0,0 +1,242 @@
+//! Synthetic events from two snapshots of a tree
+//!
+//! [`diff_trees`] compares two listings of the same tree, say from two cache scans taken
+//! while nothing was watching, and reports what changed between them as the watcher would
+//! have: removes and creates that the move matcher pairs become Moves, and files still at
+//! their path with a different size, modification time or content hash become Writes.
+//!
+//! Pairing goes through [`MoveMatching`] with the nodes' recorded inodes, Windows IDs and
+//! content hashes, so the diff never touches the filesystem. Every pair is scored as if both
+//! halves happened at once; the time signal cannot tell snapshot pairs apart. A directory
+//! that moved is one Move, like a directory rename is live: its children that moved along
+//! with it are not reported separately.
+
+use super::config::MoveDetectorConfig;
+use super::events::{PendingEvent, PendingEventsStorage};
+use super::matching::{MoveMatching, ParentCorrelations};
+use crate::database::types::{FilesystemNode, NodeType};
+use crate::events::{EventType, FileSystemEvent, MoveEvent};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The events that turn `old` into `new`, scored with the default [`MoveDetectorConfig`]
+///
+/// See [`diff_trees_with_config`].
+pub fn diff_trees(old: &[FilesystemNode], new: &[FilesystemNode]) -> Vec<FileSystemEvent> {
+	diff_trees_with_config(old, new, &MoveDetectorConfig::default())
+}
+
+/// The events that turn `old` into `new`, in path order
+///
+/// A path in both snapshots whose node changed kind (a file replaced by a directory) is a
+/// Remove and a Create. Directories at the same path are never reported as written.
+pub fn diff_trees_with_config(
+	old: &[FilesystemNode], new: &[FilesystemNode], config: &MoveDetectorConfig,
+) -> Vec<FileSystemEvent> {
+	let old_by_path: HashMap<&Path, &FilesystemNode> =
+		old.iter().map(|node| (node.path.as_path(), node)).collect();
+	let new_by_path: HashMap<&Path, &FilesystemNode> =
+		new.iter().map(|node| (node.path.as_path(), node)).collect();
+
+	let mut events = Vec::new();
+	let mut removes = Vec::new();
+	let mut pending = PendingEventsStorage::new();
+	for node in old {
+		match new_by_path.get(node.path.as_path()) {
+			Some(current) if same_kind(node, current) => {
+				if changed(node, current) {
+					events.push(synthetic(EventType::Write, current));
+				}
+			}
+			_ => removes.push(pending_event(EventType::Remove, node)),
+		}
+	}
+	for node in new {
+		match old_by_path.get(node.path.as_path()) {
+			Some(previous) if same_kind(previous, node) => {}
+			_ => pending.add_create(pending_event(EventType::Create, node)),
+		}
+	}
+
+	removes.sort_by(|a, b| a.event.path.cmp(&b.event.path));
+	let correlations = ParentCorrelations::new(config.timeout);
+	let mut moves = Vec::new();
+	for remove in removes {
+		// Matching never waits on anything, so the future is ready on its first poll
+		let matched = pollster::block_on(MoveMatching::find_matching_create(
+			&remove,
+			&pending,
+			config,
+			&correlations,
+		));
+		match matched {
+			Some(create) => {
+				pending.remove_create_by_id(create.event.id);
+				let move_data = MoveEvent::new(
+					remove.event.path.clone(),
+					create.event.path.clone(),
+					MoveMatching::confidence(&remove, &create, config, &correlations),
+					MoveMatching::determine_detection_method(&remove, &create),
+				);
+				moves.push(create.event.with_move_data(move_data));
+			}
+			None => events.push(remove.event),
+		}
+	}
+	events.extend(pending.iter_creates().map(|create| create.event.clone()));
+
+	let moved_directories: Vec<(PathBuf, PathBuf)> = moves
+		.iter()
+		.filter(|event| event.is_directory)
+		.filter_map(|event| event.move_data.as_ref())
+		.map(|data| (data.source_path.clone(), data.destination_path.clone()))
+		.collect();
+	events.extend(moves.into_iter().filter(|event| {
+		let data = event.move_data.as_ref().expect("moves carry move data");
+		!moved_directories.iter().any(|(source, destination)| {
+			data.source_path != *source
+				&& data
+					.source_path
+					.strip_prefix(source)
+					.is_ok_and(|relative| data.destination_path == destination.join(relative))
+		})
+	}));
+
+	events.sort_by(|a, b| a.path.cmp(&b.path));
+	events
+}
+
+fn same_kind(a: &FilesystemNode, b: &FilesystemNode) -> bool {
+	std::mem::discriminant(&a.node_type) == std::mem::discriminant(&b.node_type)
+}
+
+fn changed(old: &FilesystemNode, new: &FilesystemNode) -> bool {
+	match (&old.node_type, &new.node_type) {
+		(
+			NodeType::File { size: old_size, content_hash: old_hash, .. },
+			NodeType::File { size: new_size, content_hash: new_hash, .. },
+		) => {
+			old_size != new_size
+				|| old_hash != new_hash
+				|| old.metadata.modified_time != new.metadata.modified_time
+		}
+		(NodeType::Symlink { target: old_target, .. }, NodeType::Symlink { target, .. }) => {
+			old_target != target
+		}
+		_ => false,
+	}
+}
+
+fn synthetic(event_type: EventType, node: &FilesystemNode) -> FileSystemEvent {
+	let (is_directory, size) = match node.node_type {
+		NodeType::File { size, .. } => (false, Some(size)),
+		NodeType::Directory { .. } => (true, None),
+		NodeType::Symlink { .. } => (false, None),
+	};
+	FileSystemEvent::new(event_type, node.path.clone(), is_directory, size)
+}
+
+fn pending_event(event_type: EventType, node: &FilesystemNode) -> PendingEvent {
+	let content_hash = match &node.node_type {
+		NodeType::File { content_hash, .. } => content_hash.clone(),
+		_ => None,
+	};
+	PendingEvent::new(synthetic(event_type, node))
+		.with_inode(node.metadata.inode)
+		.with_windows_id(node.metadata.windows_id)
+		.with_content_hash(content_hash)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::database::types::{CacheInfo, ComputedProperties, NodeMetadata};
+	use std::time::SystemTime;
+
+	fn node(path: &str, node_type: NodeType, inode: u64) -> FilesystemNode {
+		FilesystemNode {
+			path: PathBuf::from(path),
+			node_type,
+			metadata: NodeMetadata {
+				modified_time: SystemTime::UNIX_EPOCH,
+				created_time: None,
+				accessed_time: None,
+				permissions: 0o644,
+				inode: Some(inode),
+				windows_id: Some(inode),
+				uid: None,
+				gid: None,
+			},
+			cache_info: CacheInfo::default(),
+			computed: ComputedProperties::default(),
+			last_event_type: None,
+		}
+	}
+
+	fn file(path: &str, size: u64, hash: &str, inode: u64) -> FilesystemNode {
+		let content_hash = Some(hash.to_string());
+		node(
+			path,
+			NodeType::File { size, content_hash, mime_type: None },
+			inode,
+		)
+	}
+
+	fn dir(path: &str, inode: u64) -> FilesystemNode {
+		node(
+			path,
+			NodeType::Directory { child_count: 0, total_size: 0, max_depth: 0 },
+			inode,
+		)
+	}
+
+	#[test]
+	fn test_diff_trees_reports_a_moved_file_as_a_move() {
+		let old = vec![
+			dir("/w/docs", 1),
+			file("/w/docs/report.txt", 4096, "aaaa", 2),
+			file("/w/notes.txt", 10, "bbbb", 3),
+			file("/w/gone.txt", 20, "cccc", 4),
+		];
+		let new = vec![
+			dir("/w/docs", 1),
+			dir("/w/archive", 5),
+			file("/w/archive/report.txt", 4096, "aaaa", 2),
+			file("/w/notes.txt", 12, "dddd", 3),
+			file("/w/fresh.txt", 30, "eeee", 6),
+		];
+
+		let events = diff_trees(&old, &new);
+		let summary: Vec<(EventType, &Path)> = events
+			.iter()
+			.map(|event| (event.event_type.clone(), event.path.as_path()))
+			.collect();
+		assert_eq!(
+			summary,
+			vec![
+				(EventType::Create, Path::new("/w/archive")),
+				(EventType::Move, Path::new("/w/archive/report.txt")),
+				(EventType::Create, Path::new("/w/fresh.txt")),
+				(EventType::Remove, Path::new("/w/gone.txt")),
+				(EventType::Write, Path::new("/w/notes.txt")),
+			]
+		);
+		let move_data = events[1].move_data.as_ref().unwrap();
+		assert_eq!(move_data.source_path, PathBuf::from("/w/docs/report.txt"));
+		assert_eq!(
+			move_data.destination_path,
+			PathBuf::from("/w/archive/report.txt")
+		);
+	}
+
+	#[test]
+	fn test_diff_trees_reports_a_moved_directory_once() {
+		let old = vec![dir("/w/a", 1), file("/w/a/x.txt", 64, "xxxx", 2)];
+		let new = vec![dir("/w/b", 1), file("/w/b/x.txt", 64, "xxxx", 2)];
+
+		let events = diff_trees(&old, &new);
+		assert_eq!(events.len(), 1, "{events:?}");
+		assert_eq!(events[0].event_type, EventType::Move);
+		assert_eq!(events[0].path, PathBuf::from("/w/b"));
+	}
+}
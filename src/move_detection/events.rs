@@ -1,6 +1,7 @@
-use crate::events::FileSystemEvent;
+use crate::events::{FileSystemEvent, TimestampSource};
+use crate::runtime::Instant;
 use std::collections::HashMap;
-use tokio::time::Instant;
+use std::ffi::OsStr;
 
 /// A filesystem event that is pending matching for move detection
 #[derive(Debug, Clone)]
@@ -14,14 +15,15 @@ pub struct PendingEvent {
 }
 
 impl PendingEvent {
+	/// Matching clock starts now, or at the OS time for events with an OS timestamp
 	pub fn new(event: FileSystemEvent) -> Self {
-		Self {
-			event,
-			timestamp: Instant::now(),
-			inode: None,
-			content_hash: None,
-			windows_id: None,
+		let mut timestamp = Instant::now();
+		if event.timestamp_source == TimestampSource::OsEvent {
+			// A timestamp from the future (clock skew) counts as now
+			let age = (chrono::Utc::now() - event.timestamp).to_std().unwrap_or_default();
+			timestamp = timestamp.checked_sub(age).unwrap_or(timestamp);
 		}
+		Self { event, timestamp, inode: None, content_hash: None, windows_id: None }
 	}
 
 	pub fn with_inode(mut self, inode: Option<u64>) -> Self {
@@ -78,7 +80,7 @@ impl PendingEventsStorage {
 
 		// Store by size for quick size-based matching
 		if let Some(size) = event.event.size {
-			self.removes_by_size.entry(size).or_default().push(event);
+			push_sized(self.removes_by_size.entry(size).or_default(), size, event);
 		} else {
 			self.removes_no_size.push(event);
 		}
@@ -98,12 +100,22 @@ impl PendingEventsStorage {
 
 		// Store by size for quick size-based matching
 		if let Some(size) = event.event.size {
-			self.creates_by_size.entry(size).or_default().push(event);
+			push_sized(self.creates_by_size.entry(size).or_default(), size, event);
 		} else {
 			self.creates_no_size.push(event);
 		}
 	}
 
+	/// Iterate pending removes, each once (the inode/Windows ID maps only index these)
+	pub fn iter_removes(&self) -> impl Iterator<Item = &PendingEvent> {
+		self.removes_by_size.values().flatten().chain(self.removes_no_size.iter())
+	}
+
+	/// Iterate pending creates, each once
+	pub fn iter_creates(&self) -> impl Iterator<Item = &PendingEvent> {
+		self.creates_by_size.values().flatten().chain(self.creates_no_size.iter())
+	}
+
 	/// Count total pending remove events
 	pub fn count_removes(&self) -> usize {
 		self.removes_by_size.values().map(|v| v.len()).sum::<usize>() + self.removes_no_size.len()
@@ -113,45 +125,36 @@ impl PendingEventsStorage {
 	pub fn count_creates(&self) -> usize {
 		self.creates_by_size.values().map(|v| v.len()).sum::<usize>() + self.creates_no_size.len()
 	}
-	/// Remove a pending create event by its ID
+	/// Remove a pending create event by its ID from every index it is stored in
 	pub fn remove_create_by_id(&mut self, event_id: uuid::Uuid) -> bool {
-		// Check inode-based storage
-		if let Some(position) = self
-			.creates_by_inode
-			.iter()
-			.find(|(_, event)| event.event.id == event_id)
-			.map(|(inode, _)| *inode)
-		{
-			self.creates_by_inode.remove(&position);
-			return true;
-		}
-
-		// Check Windows ID storage
-		if let Some(position) = self
-			.creates_by_windows_id
-			.iter()
-			.find(|(_, event)| event.event.id == event_id)
-			.map(|(id, _)| *id)
-		{
-			self.creates_by_windows_id.remove(&position);
-			return true;
-		}
-
-		// Check size-based storage
-		for events in self.creates_by_size.values_mut() {
-			if let Some(pos) = events.iter().position(|e| e.event.id == event_id) {
-				events.remove(pos);
-				return true;
-			}
-		}
-
-		// Check no-size storage
-		if let Some(pos) = self.creates_no_size.iter().position(|e| e.event.id == event_id) {
-			self.creates_no_size.remove(pos);
-			return true;
-		}
+		// The inode/Windows ID maps are counted too: their entries can outlive the buckets
+		let stored = |storage: &Self| {
+			storage.count_creates()
+				+ storage.creates_by_inode.len()
+				+ storage.creates_by_windows_id.len()
+		};
+		let before = stored(self);
+		self.creates_by_inode.retain(|_, event| event.event.id != event_id);
+		self.creates_by_windows_id.retain(|_, event| event.event.id != event_id);
+		self.creates_by_size.retain(|_, events| {
+			events.retain(|event| event.event.id != event_id);
+			!events.is_empty()
+		});
+		self.creates_no_size.retain(|event| event.event.id != event_id);
+		stored(self) != before
+	}
 
-		false
+	/// Remove a pending remove event by its ID from every index it is stored in
+	pub fn remove_remove_by_id(&mut self, event_id: uuid::Uuid) -> bool {
+		let before = self.count_removes();
+		self.removes_by_inode.retain(|_, event| event.event.id != event_id);
+		self.removes_by_windows_id.retain(|_, event| event.event.id != event_id);
+		self.removes_by_size.retain(|_, events| {
+			events.retain(|event| event.event.id != event_id);
+			!events.is_empty()
+		});
+		self.removes_no_size.retain(|event| event.event.id != event_id);
+		self.count_removes() != before
 	}
 
 	/// Clear all pending events
@@ -167,3 +170,24 @@ impl PendingEventsStorage {
 		self.pending_rename_from = None;
 	}
 }
+
+/// Add `event` to its size bucket. The size-0 bucket is kept ordered by file name, so
+/// [`zero_byte_named`] finds the empty files of one name without scanning all of them.
+fn push_sized(bucket: &mut Vec<PendingEvent>, size: u64, event: PendingEvent) {
+	if size == 0 {
+		let name = event.event.path.file_name();
+		let at = bucket.partition_point(|pending| pending.event.path.file_name() <= name);
+		bucket.insert(at, event);
+	} else {
+		bucket.push(event);
+	}
+}
+
+/// The events of a size-0 bucket whose file is named `name`, in the order they were added
+pub(crate) fn zero_byte_named<'b>(
+	bucket: &'b [PendingEvent], name: Option<&OsStr>,
+) -> &'b [PendingEvent] {
+	let start = bucket.partition_point(|pending| pending.event.path.file_name() < name);
+	let end = bucket.partition_point(|pending| pending.event.path.file_name() <= name);
+	&bucket[start..end]
+}
@@ -0,0 +1,231 @@
+//! Files moved back and forth in quick succession
+//!
+//! Some tools rename a file over and over (swap files, sync clients fighting each other,
+//! A/B deployments flipping a symlink target), which floods consumers with moves that cancel
+//! out. [`FlappingTracker`] follows each file through the moves the detector delivers, keyed
+//! by the path it was last moved to, and counts its moves within
+//! `MoveDetectorConfig::flapping_window`. Past `flapping_threshold` it reports one
+//! [`WatcherDiagnostic::FlappingDetected`] and, with `suppress_flapping_moves`, holds the
+//! moves back until the file has stayed put for a full window, then releases one net Move.
+//!
+//! Identity is the chain of move endpoints: a file renamed A -> B -> A is one file whose
+//! track moved twice. A file reaching a path by other means (a plain create) starts no track.
+
+use crate::diagnostics::WatcherDiagnostic;
+use crate::events::{EventType, FileSystemEvent, MoveDetectionMethod, MoveEvent};
+use crate::runtime::Instant;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Most distinct paths remembered for one file's diagnostic
+pub const MAX_FLAPPING_PATHS: usize = 16;
+
+/// Recent moves of one file
+#[derive(Debug)]
+struct Track {
+	moves: VecDeque<Instant>,
+	paths: Vec<PathBuf>,
+	reported: bool,
+	/// Where the file was when suppression began, and its latest suppressed move
+	held: Option<(PathBuf, FileSystemEvent)>,
+}
+
+impl Track {
+	fn last_move(&self) -> Option<Instant> {
+		self.moves.back().copied()
+	}
+
+	fn remember(&mut self, path: &PathBuf) {
+		if self.paths.len() < MAX_FLAPPING_PATHS && !self.paths.contains(path) {
+			self.paths.push(path.clone());
+		}
+	}
+
+	/// The net move of the suppressed moves, `None` when the file is back where it started
+	fn release(&mut self) -> Option<FileSystemEvent> {
+		let (origin, mut event) = self.held.take()?;
+		let latest = event.move_data.take()?;
+		if origin == latest.destination_path {
+			return None;
+		}
+		event.move_data = Some(MoveEvent::new(
+			origin,
+			latest.destination_path,
+			latest.confidence,
+			latest.detection_method,
+		));
+		Some(event)
+	}
+}
+
+/// Flapping state of every file moved within the window
+#[derive(Debug, Default)]
+pub struct FlappingTracker {
+	tracks: HashMap<PathBuf, Track>,
+}
+
+impl FlappingTracker {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Count `event` if it is a move, returning what to deliver in its place and the
+	/// diagnostic to report, if any.
+	///
+	/// The events returned are `event` itself, or nothing while it is suppressed, preceded by
+	/// the net move of an earlier episode of the same file that settled without being polled.
+	pub fn observe(
+		&mut self, event: FileSystemEvent, now: Instant, threshold: usize, window: Duration,
+		suppress: bool,
+	) -> (Vec<FileSystemEvent>, Option<WatcherDiagnostic>) {
+		let (source, destination) = match &event.move_data {
+			Some(data)
+				if event.event_type == EventType::Move
+					&& data.detection_method != MoveDetectionMethod::Exchange =>
+			{
+				(data.source_path.clone(), data.destination_path.clone())
+			}
+			_ => return (vec![event], None),
+		};
+		self.prune(now, window);
+
+		let mut released = Vec::new();
+		let mut track = self.tracks.remove(&source).unwrap_or(Track {
+			moves: VecDeque::new(),
+			paths: Vec::new(),
+			reported: false,
+			held: None,
+		});
+		if track.last_move().is_some_and(|last| now.duration_since(last) >= window) {
+			released.extend(track.release());
+		}
+		while track.moves.front().is_some_and(|at| now.duration_since(*at) >= window) {
+			track.moves.pop_front();
+		}
+		track.moves.push_back(now);
+		track.remember(&source);
+		track.remember(&destination);
+
+		let flapping = track.moves.len() > threshold;
+		let diagnostic = if flapping && !track.reported {
+			track.reported = true;
+			Some(WatcherDiagnostic::FlappingDetected {
+				paths: track.paths.clone(),
+				moves: track.moves.len(),
+				window,
+			})
+		} else {
+			None
+		};
+		if !flapping {
+			track.reported = false;
+		}
+
+		if suppress && (flapping || track.held.is_some()) {
+			let origin = match track.held.take() {
+				Some((origin, _)) => origin,
+				None => source,
+			};
+			track.held = Some((origin, event));
+		} else {
+			released.push(event);
+		}
+		self.tracks.insert(destination, track);
+		(released, diagnostic)
+	}
+
+	/// Whether moves are held back for a file still flapping
+	pub fn has_held(&self) -> bool {
+		self.tracks.values().any(|track| track.held.is_some())
+	}
+
+	/// Net moves of suppressed files that have not moved for `window`
+	pub fn take_settled(&mut self, now: Instant, window: Duration) -> Vec<FileSystemEvent> {
+		let settled = self.tracks.values_mut().filter(|track| {
+			track.last_move().is_some_and(|last| now.duration_since(last) >= window)
+		});
+		let released = settled.filter_map(Track::release).collect();
+		self.prune(now, window);
+		released
+	}
+
+	/// Forget everything, returning the net moves still held back
+	pub fn clear(&mut self) -> Vec<FileSystemEvent> {
+		let released = self.tracks.values_mut().filter_map(Track::release).collect();
+		self.tracks.clear();
+		released
+	}
+
+	/// Drop tracks with no move in the window and nothing held
+	fn prune(&mut self, now: Instant, window: Duration) {
+		self.tracks.retain(|_, track| {
+			track.held.is_some()
+				|| track.last_move().is_some_and(|last| now.duration_since(last) < window)
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn moved(from: &str, to: &str) -> FileSystemEvent {
+		FileSystemEvent::new(EventType::Move, PathBuf::from(to), false, Some(3)).with_move_data(
+			MoveEvent::new(
+				PathBuf::from(from),
+				PathBuf::from(to),
+				1.0,
+				MoveDetectionMethod::FileSystemEvent,
+			),
+		)
+	}
+
+	#[test]
+	fn test_flapping_is_reported_once_and_suppressed_to_the_net_move() {
+		let window = Duration::from_secs(10);
+		let start = Instant::now();
+		let mut tracker = FlappingTracker::new();
+		let mut delivered = 0;
+		let mut diagnostics = Vec::new();
+		let hops = [("/w/a", "/w/b"), ("/w/b", "/w/a"), ("/w/a", "/w/b"), ("/w/b", "/w/a")];
+		for (i, (from, to)) in
+			hops.iter().chain(hops.iter()).chain([("/w/a", "/w/c")].iter()).enumerate()
+		{
+			let now = start + Duration::from_millis(i as u64 * 100);
+			let (released, diagnostic) = tracker.observe(moved(from, to), now, 3, window, true);
+			delivered += released.len();
+			diagnostics.extend(diagnostic);
+		}
+		// The first three moves went through, the rest are held
+		assert_eq!(delivered, 3);
+		assert_eq!(
+			diagnostics,
+			vec![WatcherDiagnostic::FlappingDetected {
+				paths: vec![PathBuf::from("/w/a"), PathBuf::from("/w/b")],
+				moves: 4,
+				window,
+			}]
+		);
+		assert!(tracker.has_held());
+		assert!(tracker.take_settled(start + Duration::from_secs(5), window).is_empty());
+
+		// Held from /w/b, where the last delivered move left it
+		let settled = tracker.take_settled(start + Duration::from_secs(11), window);
+		assert_eq!(settled.len(), 1);
+		let data = settled[0].move_data.as_ref().unwrap();
+		assert_eq!(data.source_path, PathBuf::from("/w/b"));
+		assert_eq!(data.destination_path, PathBuf::from("/w/c"));
+		assert!(!tracker.has_held());
+
+		// A file that flaps back to where it started is not reported as moved at all
+		let mut tracker = FlappingTracker::new();
+		for (i, (from, to)) in hops.iter().chain([("/w/a", "/w/b")].iter()).enumerate() {
+			let now = start + Duration::from_millis(i as u64 * 100);
+			tracker.observe(moved(from, to), now, 1, window, true);
+		}
+		assert!(tracker.has_held());
+		assert!(tracker.take_settled(start + Duration::from_secs(11), window).is_empty());
+		assert!(!tracker.has_held());
+	}
+}
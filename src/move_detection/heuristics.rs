@@ -1,6 +1,7 @@
 use crate::database::path_utils::paths_equal;
 use crate::move_detection::events::PendingEventsStorage;
 use crate::move_detection::metadata::MetadataCache;
+use std::ffi::OsStr;
 use std::path::Path;
 
 /// Heuristics for determining if a removed path was a file or directory
@@ -170,8 +171,15 @@ impl PathTypeInference {
 	}
 }
 
-/// Simple Levenshtein distance implementation for name similarity
+/// Simple Levenshtein distance implementation for name similarity (counted in chars)
 pub fn levenshtein_distance(s1: &str, s2: &str) -> usize {
+	let s1_chars: Vec<char> = s1.chars().collect();
+	let s2_chars: Vec<char> = s2.chars().collect();
+	levenshtein_units(&s1_chars, &s2_chars)
+}
+
+/// Levenshtein distance over arbitrary units (chars, raw bytes, UTF-16 code units)
+fn levenshtein_units<T: PartialEq>(s1: &[T], s2: &[T]) -> usize {
 	let len1 = s1.len();
 	let len2 = s2.len();
 
@@ -193,12 +201,9 @@ pub fn levenshtein_distance(s1: &str, s2: &str) -> usize {
 		matrix[0][j] = j;
 	}
 
-	let s1_chars: Vec<char> = s1.chars().collect();
-	let s2_chars: Vec<char> = s2.chars().collect();
-
 	for i in 1..=len1 {
 		for j in 1..=len2 {
-			let cost = if s1_chars[i - 1] == s2_chars[j - 1] { 0 } else { 1 };
+			let cost = if s1[i - 1] == s2[j - 1] { 0 } else { 1 };
 			matrix[i][j] = std::cmp::min(
 				std::cmp::min(
 					matrix[i - 1][j] + 1, // deletion
@@ -212,16 +217,51 @@ pub fn levenshtein_distance(s1: &str, s2: &str) -> usize {
 	matrix[len1][len2]
 }
 
+/// Levenshtein distance between two full paths, over the same units as name similarity
+pub fn path_distance(path1: &Path, path2: &Path) -> usize {
+	levenshtein_units(
+		&name_units(path1.as_os_str()),
+		&name_units(path2.as_os_str()),
+	)
+}
+
+/// Comparable units of a file name without going through lossy UTF-8 conversion.
+///
+/// Valid Unicode names compare by char. Anything else falls back to the raw OS encoding (bytes
+/// on Unix, UTF-16 units on Windows), so two distinct non-UTF-8 names never collapse into the
+/// same replacement-character string. Comparing a Unicode name against a non-Unicode one mixes
+/// chars and bytes, which overstates the distance for non-ASCII characters; that only lowers
+/// similarity, it never produces a false match.
+fn name_units(name: &OsStr) -> Vec<u32> {
+	if let Some(text) = name.to_str() {
+		return text.chars().map(u32::from).collect();
+	}
+	#[cfg(unix)]
+	{
+		use std::os::unix::ffi::OsStrExt;
+		name.as_bytes().iter().map(|&b| u32::from(b)).collect()
+	}
+	#[cfg(windows)]
+	{
+		use std::os::windows::ffi::OsStrExt;
+		name.encode_wide().map(u32::from).collect()
+	}
+	#[cfg(not(any(unix, windows)))]
+	{
+		name.to_string_lossy().chars().map(u32::from).collect()
+	}
+}
+
 /// Calculate name similarity between two paths
 pub fn calculate_name_similarity(path1: &Path, path2: &Path) -> f32 {
-	let name1 = path1.file_name().and_then(|n| n.to_str()).unwrap_or("");
-	let name2 = path2.file_name().and_then(|n| n.to_str()).unwrap_or("");
+	let name1 = path1.file_name().map(name_units).unwrap_or_default();
+	let name2 = path2.file_name().map(name_units).unwrap_or_default();
 
 	if name1.is_empty() || name2.is_empty() {
 		return 0.0;
 	}
 
-	let distance = levenshtein_distance(name1, name2);
+	let distance = levenshtein_units(&name1, &name2);
 	let max_len = std::cmp::max(name1.len(), name2.len());
 
 	if max_len == 0 {
@@ -326,4 +366,25 @@ mod tests {
 		let similarity = calculate_name_similarity(&path1, &path2);
 		assert!(similarity < 0.5); // Should be low similarity
 	}
+
+	#[test]
+	fn test_name_similarity_non_ascii_does_not_panic() {
+		// Byte length differs from char length; indexing chars by byte length used to panic
+		let similarity = calculate_name_similarity(
+			&PathBuf::from("/a/caf\u{e9}.txt"),
+			&PathBuf::from("/b/cafe.txt"),
+		);
+		assert!(similarity > 0.8 && similarity < 1.0);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_name_similarity_non_utf8() {
+		use std::os::unix::ffi::OsStrExt;
+		let a = PathBuf::from(OsStr::from_bytes(b"/a/report\xff1.txt"));
+		let b = PathBuf::from(OsStr::from_bytes(b"/b/report\xff1.txt"));
+		let c = PathBuf::from(OsStr::from_bytes(b"/b/\xfe\xfd"));
+		assert_eq!(calculate_name_similarity(&a, &b), 1.0);
+		assert!(calculate_name_similarity(&a, &c) < 0.3);
+	}
 }
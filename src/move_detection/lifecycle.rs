@@ -0,0 +1,78 @@
+//! Trace of each event's path through move detection
+//!
+//! With `MoveDetectorConfig::lifecycle_trace` on and a [`LifecycleSender`] attached, the
+//! detector reports every state change of every event it is given: received, held as pending,
+//! weighed as a candidate for an incoming counterpart, matched, timed out or dropped. Each
+//! transition carries the event's id as its correlation id, and matches and candidates name the
+//! counterpart's id, so the two halves of a move can be tied together. The id of a Move is that
+//! of its create half.
+//!
+//! This is a debugging aid for visualizing the detector's decisions, not an audit log:
+//! delivery is best effort like diagnostics, and a full channel drops transitions instead of
+//! stalling the detector.
+
+use crate::events::{EventType, MoveDetectionMethod};
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+/// Capacity of the lifecycle channel; every event produces at least one transition
+pub const LIFECYCLE_CHANNEL_CAPACITY: usize = 1024;
+
+/// One step of an event through move detection
+#[derive(Debug, Clone, PartialEq)]
+pub enum LifecycleStage {
+	/// Handed to the detector
+	Received,
+	/// Held for pairing: a remove, create or the old name of a rename
+	Pending,
+	/// Weighed against the incoming event `against`; pairing needs `confidence` to reach
+	/// `confidence_threshold`. Only the counterparts in the incoming event's inode and size
+	/// buckets are reported, which is where regular matching looks.
+	Candidate {
+		against: uuid::Uuid,
+		confidence: f32,
+	},
+	/// Paired into a move with `counterpart`, `None` when the other side never went through
+	/// the detector (e.g. a source found through the content-hash index)
+	Matched {
+		counterpart: Option<uuid::Uuid>,
+		confidence: f32,
+		method: MoveDetectionMethod,
+	},
+	/// Nothing paired with it within `timeout`. A remove may still be paired later under
+	/// `late_pairing_window`, which then reports it as matched.
+	TimedOut,
+	/// Left out of or removed from pairing before it could time out
+	Dropped { reason: String },
+}
+
+/// A state change of the event `correlation_id`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifecycleEvent {
+	pub correlation_id: uuid::Uuid,
+	pub path: PathBuf,
+	pub event_type: EventType,
+	pub stage: LifecycleStage,
+}
+
+/// Sending half of the lifecycle channel; cheap to clone, never blocks
+#[derive(Debug, Clone)]
+pub struct LifecycleSender {
+	tx: mpsc::Sender<LifecycleEvent>,
+}
+
+impl LifecycleSender {
+	/// Create a bounded lifecycle channel
+	pub fn channel() -> (Self, mpsc::Receiver<LifecycleEvent>) {
+		let (tx, rx) = mpsc::channel(LIFECYCLE_CHANNEL_CAPACITY);
+		(Self { tx }, rx)
+	}
+
+	/// Send without waiting; dropped if the channel is full or closed
+	pub fn emit(&self, event: LifecycleEvent) {
+		if let Err(e) = self.tx.try_send(event) {
+			debug!("Lifecycle transition not delivered: {}", e);
+		}
+	}
+}
@@ -1,11 +1,40 @@
+use crate::database::types::DecisionSignals;
 use crate::events::MoveDetectionMethod;
 use crate::move_detection::config::MoveDetectorConfig;
-use crate::move_detection::events::{PendingEvent, PendingEventsStorage};
-use crate::move_detection::heuristics::calculate_name_similarity;
+use crate::move_detection::events::{zero_byte_named, PendingEvent, PendingEventsStorage};
+use crate::move_detection::heuristics::{calculate_name_similarity, path_distance};
+use crate::runtime::Instant;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 use twox_hash::XxHash64;
 
+/// The pending events in the buckets of `by_size` within `config.size_tolerance` of `size`,
+/// leaving out the bucket of `size` itself
+pub(crate) fn nearby_sized<'s>(
+	by_size: &'s HashMap<u64, Vec<PendingEvent>>, size: u64, config: &MoveDetectorConfig,
+) -> Vec<&'s PendingEvent> {
+	let tolerance = config.size_tolerance;
+	if tolerance == 0 {
+		return Vec::new();
+	}
+	if tolerance.saturating_mul(2) < by_size.len() as u64 {
+		(size.saturating_sub(tolerance)..=size.saturating_add(tolerance))
+			.filter(|bucket| *bucket != size)
+			.filter_map(|bucket| by_size.get(&bucket))
+			.flatten()
+			.collect()
+	} else {
+		by_size
+			.iter()
+			.filter(|(bucket, _)| **bucket != size && bucket.abs_diff(size) <= tolerance)
+			.flat_map(|(_, candidates)| candidates)
+			.collect()
+	}
+}
+
 /// Move matching algorithms and confidence calculations
 pub struct MoveMatching;
 
@@ -13,15 +42,21 @@ impl MoveMatching {
 	/// Find a matching create event for a given remove event
 	pub async fn find_matching_create(
 		remove_event: &PendingEvent, storage: &PendingEventsStorage, config: &MoveDetectorConfig,
+		correlations: &ParentCorrelations,
 	) -> Option<PendingEvent> {
 		// Quick inode-based matching for Unix systems
 		#[cfg(unix)]
 		if let Some(inode) = remove_event.inode {
 			if let Some(create_event) = storage.creates_by_inode.get(&inode) {
 				// Don't match events with the same path (not a move)
-				if create_event.event.path != remove_event.event.path {
-					let confidence = Self::calculate_confidence(remove_event, create_event, config);
-					if confidence >= config.confidence_threshold {
+				if create_event.event.path != remove_event.event.path
+					&& Self::in_scope(remove_event, create_event, config)
+				{
+					let confidence =
+						Self::confidence(remove_event, create_event, config, correlations);
+					let threshold =
+						config.pair_threshold(&remove_event.event.path, &create_event.event.path);
+					if confidence >= threshold {
 						return Some(create_event.clone());
 					}
 				}
@@ -33,9 +68,14 @@ impl MoveMatching {
 		if let Some(windows_id) = remove_event.windows_id {
 			if let Some(create_event) = storage.creates_by_windows_id.get(&windows_id) {
 				// Don't match events with the same path (not a move)
-				if create_event.event.path != remove_event.event.path {
-					let confidence = Self::calculate_confidence(remove_event, create_event, config);
-					if confidence >= config.confidence_threshold {
+				if create_event.event.path != remove_event.event.path
+					&& Self::in_scope(remove_event, create_event, config)
+				{
+					let confidence =
+						Self::confidence(remove_event, create_event, config, correlations);
+					let threshold =
+						config.pair_threshold(&remove_event.event.path, &create_event.event.path);
+					if confidence >= threshold {
 						return Some(create_event.clone());
 					}
 				}
@@ -45,27 +85,50 @@ impl MoveMatching {
 		if let Some(size) = remove_event.event.size {
 			// Remove event has size - look for creates with same size
 			if let Some(candidates) = storage.creates_by_size.get(&size) {
-				return Self::find_best_match_in_candidates(remove_event, candidates, config);
+				if let Some(match_result) = Self::find_best_match_in_candidates(
+					remove_event,
+					Self::same_size_candidates(remove_event, candidates, config),
+					config,
+					correlations,
+				) {
+					return Some(match_result);
+				}
+			}
+
+			// Then creates of nearly the same size, under `size_tolerance`
+			let nearby: Vec<PendingEvent> = nearby_sized(&storage.creates_by_size, size, config)
+				.into_iter()
+				.cloned()
+				.collect();
+			if let Some(match_result) =
+				Self::find_best_match_in_candidates(remove_event, &nearby, config, correlations)
+			{
+				return Some(match_result);
 			}
 		} else {
 			// Remove event has no size - this happens when file was removed and we couldn't get metadata
 			// We need to check ALL create events since we don't know what size to match
 
 			// First check creates without size (directories, etc.)
-			if let Some(match_result) =
-				Self::find_best_match_in_candidates(remove_event, &storage.creates_no_size, config)
-			{
+			if let Some(match_result) = Self::find_best_match_in_candidates(
+				remove_event,
+				&storage.creates_no_size,
+				config,
+				correlations,
+			) {
 				return Some(match_result);
 			}
 
-			// Then check ALL size-based creates (iterate through all size buckets)
-			for candidates in storage.creates_by_size.values() {
-				if let Some(match_result) =
-					Self::find_best_match_in_candidates(remove_event, candidates, config)
-				{
-					return Some(match_result);
-				}
-			}
+			// Then the best among ALL size-based creates; comparing across buckets keeps the
+			// result independent of the map's iteration order
+			let all_sized: Vec<PendingEvent> =
+				storage.creates_by_size.values().flatten().cloned().collect();
+			return Self::find_best_match_in_candidates(
+				remove_event,
+				&all_sized,
+				config,
+				correlations,
+			);
 		}
 
 		None
@@ -74,15 +137,21 @@ impl MoveMatching {
 	/// Find a matching remove event for a given create event
 	pub async fn find_matching_remove(
 		create_event: &PendingEvent, storage: &PendingEventsStorage, config: &MoveDetectorConfig,
+		correlations: &ParentCorrelations,
 	) -> Option<PendingEvent> {
 		// Quick inode-based matching for Unix systems
 		#[cfg(unix)]
 		if let Some(inode) = create_event.inode {
 			if let Some(remove_event) = storage.removes_by_inode.get(&inode) {
 				// Don't match events with the same path (not a move)
-				if remove_event.event.path != create_event.event.path {
-					let confidence = Self::calculate_confidence(remove_event, create_event, config);
-					if confidence >= config.confidence_threshold {
+				if remove_event.event.path != create_event.event.path
+					&& Self::in_scope(remove_event, create_event, config)
+				{
+					let confidence =
+						Self::confidence(remove_event, create_event, config, correlations);
+					let threshold =
+						config.pair_threshold(&remove_event.event.path, &create_event.event.path);
+					if confidence >= threshold {
 						return Some(remove_event.clone());
 					}
 				}
@@ -94,9 +163,14 @@ impl MoveMatching {
 		if let Some(windows_id) = create_event.windows_id {
 			if let Some(remove_event) = storage.removes_by_windows_id.get(&windows_id) {
 				// Don't match events with the same path (not a move)
-				if remove_event.event.path != create_event.event.path {
-					let confidence = Self::calculate_confidence(remove_event, create_event, config);
-					if confidence >= config.confidence_threshold {
+				if remove_event.event.path != create_event.event.path
+					&& Self::in_scope(remove_event, create_event, config)
+				{
+					let confidence =
+						Self::confidence(remove_event, create_event, config, correlations);
+					let threshold =
+						config.pair_threshold(&remove_event.event.path, &create_event.event.path);
+					if confidence >= threshold {
 						return Some(remove_event.clone());
 					}
 				}
@@ -106,18 +180,36 @@ impl MoveMatching {
 		if let Some(size) = create_event.event.size {
 			// First check removes with the same size
 			if let Some(candidates) = storage.removes_by_size.get(&size) {
-				if let Some(match_result) =
-					Self::find_best_match_in_candidates_for_create(create_event, candidates, config)
-				{
+				if let Some(match_result) = Self::find_best_match_in_candidates_for_create(
+					create_event,
+					Self::same_size_candidates(create_event, candidates, config),
+					config,
+					correlations,
+				) {
 					return Some(match_result);
 				}
 			}
 
+			// Then removes of nearly the same size, under `size_tolerance`
+			let nearby: Vec<PendingEvent> = nearby_sized(&storage.removes_by_size, size, config)
+				.into_iter()
+				.cloned()
+				.collect();
+			if let Some(match_result) = Self::find_best_match_in_candidates_for_create(
+				create_event,
+				&nearby,
+				config,
+				correlations,
+			) {
+				return Some(match_result);
+			}
+
 			// Also check removes without size (files removed before we could get metadata)
 			if let Some(match_result) = Self::find_best_match_in_candidates_for_create(
 				create_event,
 				&storage.removes_no_size,
 				config,
+				correlations,
 			) {
 				return Some(match_result);
 			}
@@ -127,6 +219,7 @@ impl MoveMatching {
 				create_event,
 				&storage.removes_no_size,
 				config,
+				correlations,
 			);
 		}
 
@@ -134,68 +227,154 @@ impl MoveMatching {
 	}
 
 	/// Calculate confidence score for a potential move match
+	///
+	/// The weighted sum behind [`BlendedConfidenceScorer`]; the detector scores pairs through
+	/// `MoveDetectorConfig::confidence_scorer` instead of calling this directly.
 	pub fn calculate_confidence(
 		remove_event: &PendingEvent, create_event: &PendingEvent, config: &MoveDetectorConfig,
 	) -> f32 {
-		let mut confidence = 0.0;
+		let weighted = Self::weighted_signals(remove_event, create_event, config);
+		let confidence = weighted.size
+			+ weighted.time
+			+ weighted.identity
+			+ weighted.content_hash
+			+ weighted.name_similarity;
+		confidence.clamp(0.0, 1.0)
+	}
+
+	/// How well each signal matches, from 0.0 to 1.0, before weighting
+	pub fn signals(
+		remove_event: &PendingEvent, create_event: &PendingEvent, config: &MoveDetectorConfig,
+	) -> DecisionSignals {
 		// Size matching
-		let size_match = match (remove_event.event.size, create_event.event.size) {
-			(Some(size1), Some(size2)) if size1 == size2 => 1.0,
+		let size = match (remove_event.event.size, create_event.event.size) {
+			(Some(size1), Some(size2)) if size1.abs_diff(size2) <= config.size_tolerance => {
+				1.0 - size1.abs_diff(size2) as f32 / (config.size_tolerance + 1) as f32
+			}
 			(None, None) => 0.8,    // Both are directories or unknown
 			(None, Some(_)) => 0.6, // Remove event has no size (common in real cut/paste), but create does
 			(Some(_), None) => 0.6, // Create event has no size, but remove does
 			_ => 0.0,               // Different sizes
 		};
-		confidence += size_match * config.weight_size_match;
 
 		// Time factor (closer in time = higher confidence)
-		let time_diff = if create_event.timestamp > remove_event.timestamp {
-			create_event.timestamp.duration_since(remove_event.timestamp)
-		} else {
-			remove_event.timestamp.duration_since(create_event.timestamp)
-		};
-
-		let time_factor = if time_diff <= config.timeout {
-			1.0 - (time_diff.as_millis() as f32 / config.timeout.as_millis() as f32)
+		let time_diff = Self::time_between(remove_event, create_event);
+		let timeout = config.pair_timeout(&remove_event.event.path, &create_event.event.path);
+		let time = if time_diff <= timeout {
+			1.0 - (time_diff.as_millis() as f32 / timeout.as_millis() as f32)
 		} else {
 			0.0
 		};
-		confidence += time_factor * config.weight_time_factor;
 
 		// Inode matching (Unix only)
 		#[cfg(unix)]
-		{
-			let inode_match = match (remove_event.inode, create_event.inode) {
-				(Some(inode1), Some(inode2)) if inode1 == inode2 => 1.0,
-				_ => 0.0,
-			};
-			confidence += inode_match * config.weight_inode_match;
-		}
+		let identity = match (remove_event.inode, create_event.inode) {
+			(Some(inode1), Some(inode2)) if inode1 == inode2 => 1.0,
+			_ => 0.0,
+		};
 
 		// Windows ID matching
 		#[cfg(windows)]
-		{
-			let windows_id_match = match (remove_event.windows_id, create_event.windows_id) {
-				(Some(id1), Some(id2)) if id1 == id2 => 1.0,
-				_ => 0.0,
-			};
-			confidence += windows_id_match * config.weight_inode_match; // Reuse inode weight
-		}
+		let identity = match (remove_event.windows_id, create_event.windows_id) {
+			(Some(id1), Some(id2)) if id1 == id2 => 1.0,
+			_ => 0.0,
+		};
+
+		#[cfg(not(any(unix, windows)))]
+		let identity = 0.0;
 
 		// Content hash matching
-		let content_hash_match = match (&remove_event.content_hash, &create_event.content_hash) {
+		let content_hash = match (&remove_event.content_hash, &create_event.content_hash) {
 			(Some(hash1), Some(hash2)) if hash1 == hash2 => 1.0,
 			(None, None) => 0.5, // Both are directories or unhashable
 			_ => 0.0,
 		};
-		confidence += content_hash_match * config.weight_content_hash;
 
 		// Name similarity
 		let name_similarity =
 			calculate_name_similarity(&remove_event.event.path, &create_event.event.path);
-		confidence += name_similarity * config.weight_name_similarity;
 
-		confidence.clamp(0.0, 1.0)
+		DecisionSignals { size, time, identity, content_hash, name_similarity }
+	}
+
+	/// [`Self::signals`] times their weights, as they add up to [`Self::calculate_confidence`]
+	pub fn weighted_signals(
+		remove_event: &PendingEvent, create_event: &PendingEvent, config: &MoveDetectorConfig,
+	) -> DecisionSignals {
+		let signals = Self::signals(remove_event, create_event, config);
+		let time_counts = !config.timing_as_tiebreaker_only
+			|| Self::has_strong_signal(remove_event, create_event);
+		DecisionSignals {
+			size: signals.size * config.weight_size_match,
+			time: if time_counts { signals.time * config.weight_time_factor } else { 0.0 },
+			// Windows IDs reuse the inode weight
+			identity: signals.identity * config.weight_inode_match,
+			content_hash: signals.content_hash * config.weight_content_hash,
+			name_similarity: signals.name_similarity * config.weight_name_similarity,
+		}
+	}
+
+	/// The configured scorer's confidence plus the parent-correlation bonus, if the pair's
+	/// directories match a recently confirmed move
+	pub fn confidence(
+		remove_event: &PendingEvent, create_event: &PendingEvent, config: &MoveDetectorConfig,
+		correlations: &ParentCorrelations,
+	) -> f32 {
+		let confidence = config.confidence_scorer.score(remove_event, create_event, config);
+		if correlations.contains(&remove_event.event.path, &create_event.event.path) {
+			(confidence + config.weight_parent_correlation).clamp(0.0, 1.0)
+		} else {
+			confidence
+		}
+	}
+
+	/// Absolute gap between the two events, whichever came first: backends may report a
+	/// create before the remove it belongs to
+	fn time_between(remove_event: &PendingEvent, create_event: &PendingEvent) -> Duration {
+		if create_event.timestamp > remove_event.timestamp {
+			create_event.timestamp.duration_since(remove_event.timestamp)
+		} else {
+			remove_event.timestamp.duration_since(create_event.timestamp)
+		}
+	}
+
+	/// Identity evidence that timing may reinforce under `timing_as_tiebreaker_only`
+	fn has_strong_signal(remove_event: &PendingEvent, create_event: &PendingEvent) -> bool {
+		let same_id = |a: Option<u64>, b: Option<u64>| a.is_some() && a == b;
+		same_id(remove_event.inode, create_event.inode)
+			|| same_id(remove_event.windows_id, create_event.windows_id)
+			|| (remove_event.content_hash.is_some()
+				&& remove_event.content_hash == create_event.content_hash)
+	}
+
+	/// The highest-ranked of the scored candidates for a pair with `other`
+	///
+	/// Ranked by confidence; under `timing_as_tiebreaker_only`, equal confidences go to the
+	/// candidate closer in time. Remaining ties are broken as described for
+	/// `MoveDetectorConfig::tie_break_by_path_proximity`, so the winner is deterministic.
+	fn best_candidate<'c>(
+		scored: impl Iterator<Item = (&'c PendingEvent, f32, Duration)>, other: &Path,
+		config: &MoveDetectorConfig,
+	) -> Option<&'c PendingEvent> {
+		use std::cmp::Ordering;
+		let distance = |candidate: &PendingEvent| path_distance(&candidate.event.path, other);
+		scored
+			.max_by(|(a, a_confidence, a_time), (b, b_confidence, b_time)| {
+				a_confidence
+					.partial_cmp(b_confidence)
+					.unwrap_or(Ordering::Equal)
+					.then_with(|| match config.timing_as_tiebreaker_only {
+						true => b_time.cmp(a_time),
+						false => Ordering::Equal,
+					})
+					.then_with(|| match config.tie_break_by_path_proximity {
+						true => distance(b).cmp(&distance(a)),
+						false => Ordering::Equal,
+					})
+					.then_with(|| b.timestamp.cmp(&a.timestamp))
+					.then_with(|| b.event.path.cmp(&a.event.path))
+			})
+			.map(|(candidate, _, _)| candidate)
 	}
 
 	/// Determine the detection method used for the match
@@ -232,42 +411,188 @@ impl MoveMatching {
 	/// Find the best match among candidates
 	fn find_best_match_in_candidates(
 		remove_event: &PendingEvent, candidates: &[PendingEvent], config: &MoveDetectorConfig,
+		correlations: &ParentCorrelations,
 	) -> Option<PendingEvent> {
-		candidates
+		let capped = Self::over_candidate_cap(candidates, config);
+		let scored = candidates
 			.iter()
 			// Filter out candidates with the same path (not a move, just recreate at same location)
 			.filter(|candidate| candidate.event.path != remove_event.event.path)
+			.filter(|candidate| !capped || Self::has_strong_signal(remove_event, candidate))
+			.filter(|candidate| Self::passes_zero_byte_filter(remove_event, candidate, config))
+			.filter(|candidate| Self::in_scope(remove_event, candidate, config))
 			.map(|candidate| {
-				let confidence = Self::calculate_confidence(remove_event, candidate, config);
-				(candidate, confidence)
+				let confidence = Self::confidence(remove_event, candidate, config, correlations);
+				(candidate, confidence, Self::time_between(remove_event, candidate))
 			})
-			.filter(|(_, confidence)| *confidence >= config.confidence_threshold)
-			.max_by(|(_, conf1), (_, conf2)| {
-				conf1
-					.partial_cmp(conf2)
-					.unwrap_or(std::cmp::Ordering::Equal)
-			})
-			.map(|(candidate, _)| candidate.clone())
+			.filter(|(candidate, confidence, _)| {
+				*confidence >= config.pair_threshold(&remove_event.event.path, &candidate.event.path)
+			});
+		Self::best_candidate(scored, &remove_event.event.path, config).cloned()
 	}
 	/// Find the best match among candidates for create events
 	fn find_best_match_in_candidates_for_create(
 		create_event: &PendingEvent, candidates: &[PendingEvent], config: &MoveDetectorConfig,
+		correlations: &ParentCorrelations,
 	) -> Option<PendingEvent> {
-		candidates
+		let capped = Self::over_candidate_cap(candidates, config);
+		let scored = candidates
 			.iter()
 			// Filter out candidates with the same path (not a move, just recreate at same location)
 			.filter(|candidate| candidate.event.path != create_event.event.path)
+			.filter(|candidate| !capped || Self::has_strong_signal(candidate, create_event))
+			.filter(|candidate| Self::passes_zero_byte_filter(candidate, create_event, config))
+			.filter(|candidate| Self::in_scope(candidate, create_event, config))
 			.map(|candidate| {
-				let confidence = Self::calculate_confidence(candidate, create_event, config);
-				(candidate, confidence)
-			})
-			.filter(|(_, confidence)| *confidence >= config.confidence_threshold)
-			.max_by(|(_, conf1), (_, conf2)| {
-				conf1
-					.partial_cmp(conf2)
-					.unwrap_or(std::cmp::Ordering::Equal)
+				let confidence = Self::confidence(candidate, create_event, config, correlations);
+				(candidate, confidence, Self::time_between(candidate, create_event))
 			})
-			.map(|(candidate, _)| candidate.clone())
+			.filter(|(candidate, confidence, _)| {
+				*confidence >= config.pair_threshold(&candidate.event.path, &create_event.event.path)
+			});
+		Self::best_candidate(scored, &create_event.event.path, config).cloned()
+	}
+
+	/// Whether `remove_event` and `create_event` pair under
+	/// `MoveDetectorConfig::cross_device_stem_matching`: same kind, same name stem, within
+	/// `timeout` of each other, and following a recently confirmed directory mapping
+	pub fn is_stem_match(
+		remove_event: &PendingEvent, create_event: &PendingEvent, config: &MoveDetectorConfig,
+		correlations: &ParentCorrelations,
+	) -> bool {
+		let (source, destination) = (&remove_event.event.path, &create_event.event.path);
+		remove_event.event.is_directory == create_event.event.is_directory
+			&& source != destination
+			&& Self::in_scope(remove_event, create_event, config)
+			&& Self::time_between(remove_event, create_event)
+				<= config.pair_timeout(source, destination)
+			&& name_stem(source).is_some_and(|stem| name_stem(destination) == Some(stem))
+			&& correlations.contains(source, destination)
+	}
+
+	/// Whether `candidates` are too many to score by name; see
+	/// `MoveDetectorConfig::name_similarity_candidate_cap`
+	fn over_candidate_cap(candidates: &[PendingEvent], config: &MoveDetectorConfig) -> bool {
+		config.name_similarity_candidate_cap.is_some_and(|cap| candidates.len() > cap)
+	}
+
+	/// Pairs outside `config.move_scope` are left unpaired so they surface as Remove + Create
+	pub(crate) fn in_scope(
+		remove_event: &PendingEvent, create_event: &PendingEvent, config: &MoveDetectorConfig,
+	) -> bool {
+		config
+			.move_scope
+			.allows_pair(&remove_event.event.path, &create_event.event.path)
+	}
+
+	/// The part of `pending`'s own size bucket worth scoring: for a zero-byte file, when
+	/// `zero_byte_min_name_similarity` asks for identical names, only the empty files of the
+	/// same name, looked up in the name-ordered size-0 bucket
+	fn same_size_candidates<'b>(
+		pending: &PendingEvent, bucket: &'b [PendingEvent], config: &MoveDetectorConfig,
+	) -> &'b [PendingEvent] {
+		if pending.event.size == Some(0) && config.zero_byte_min_name_similarity >= 1.0 {
+			zero_byte_named(bucket, pending.event.path.file_name())
+		} else {
+			bucket
+		}
+	}
+
+	/// Reject zero-byte pairs whose names are not similar enough.
+	///
+	/// All empty files share the size-0 bucket, so without this every empty file is a candidate
+	/// for every other one. With identical names required, `same_size_candidates` has already
+	/// narrowed that bucket to one name; a lower threshold still scores the whole bucket, one
+	/// name similarity per pair, before the full confidence calculation. Inode/Windows ID
+	/// matches are resolved earlier and never reach this filter.
+	fn passes_zero_byte_filter(
+		remove_event: &PendingEvent, create_event: &PendingEvent, config: &MoveDetectorConfig,
+	) -> bool {
+		let is_zero_byte =
+			|pending: &PendingEvent| !pending.event.is_directory && pending.event.size == Some(0);
+		if !is_zero_byte(remove_event) && !is_zero_byte(create_event) {
+			return true;
+		}
+
+		if config.zero_byte_min_name_similarity >= 1.0 {
+			return remove_event.event.path.file_name() == create_event.event.path.file_name();
+		}
+
+		calculate_name_similarity(&remove_event.event.path, &create_event.event.path)
+			>= config.zero_byte_min_name_similarity
+	}
+}
+
+/// File name up to its first `.`, ignoring a leading one: `report.tar.gz` -> `report`
+fn name_stem(path: &Path) -> Option<&str> {
+	let name = path.file_name()?.to_str()?;
+	let stem = match name.char_indices().skip(1).find(|(_, c)| *c == '.') {
+		Some((end, _)) => &name[..end],
+		None => name,
+	};
+	Some(stem)
+}
+
+/// Source-directory to destination-directory mappings of recently confirmed moves
+///
+/// Entries expire after `ttl` and at most [`PARENT_CORRELATION_CAPACITY`] are kept, the least
+/// recently confirmed going first.
+#[derive(Debug)]
+pub struct ParentCorrelations {
+	ttl: Duration,
+	mappings: HashMap<(PathBuf, PathBuf), Instant>,
+}
+
+/// Upper bound on remembered directory mappings
+pub const PARENT_CORRELATION_CAPACITY: usize = 64;
+
+impl ParentCorrelations {
+	pub fn new(ttl: Duration) -> Self {
+		Self { ttl, mappings: HashMap::new() }
+	}
+
+	/// Remember the directory mapping of a confirmed move; same-directory renames are ignored
+	pub fn record(&mut self, source: &Path, destination: &Path) {
+		if let Some(key) = Self::key(source, destination) {
+			self.insert(key);
+		}
+	}
+
+	/// Like [`Self::record`] for a moved directory, which also maps the directory itself, so
+	/// its former children moved after it correlate too
+	pub fn record_directory(&mut self, source: &Path, destination: &Path) {
+		self.record(source, destination);
+		if source != destination {
+			self.insert((source.to_path_buf(), destination.to_path_buf()));
+		}
+	}
+
+	fn insert(&mut self, key: (PathBuf, PathBuf)) {
+		let now = Instant::now();
+		self.mappings.insert(key, now);
+		self.mappings.retain(|_, confirmed| now.duration_since(*confirmed) <= self.ttl);
+		if self.mappings.len() > PARENT_CORRELATION_CAPACITY {
+			if let Some(oldest) = self
+				.mappings
+				.iter()
+				.min_by_key(|(_, confirmed)| **confirmed)
+				.map(|(k, _)| k.clone())
+			{
+				self.mappings.remove(&oldest);
+			}
+		}
+	}
+
+	/// Whether a move from `source` to `destination` follows a recently confirmed mapping
+	pub fn contains(&self, source: &Path, destination: &Path) -> bool {
+		Self::key(source, destination)
+			.and_then(|key| self.mappings.get(&key))
+			.is_some_and(|confirmed| confirmed.elapsed() <= self.ttl)
+	}
+
+	fn key(source: &Path, destination: &Path) -> Option<(PathBuf, PathBuf)> {
+		let (from, to) = (source.parent()?, destination.parent()?);
+		(from != to).then(|| (from.to_path_buf(), to.to_path_buf()))
 	}
 }
 
@@ -315,6 +640,8 @@ impl MetadataExtractor {
 	}
 
 	/// Get content hash for a file (if small enough)
+	///
+	/// Not bounded by `max_concurrent_hashes`; the detector hashes through [`BoundedHasher`].
 	pub async fn get_content_hash(path: &Path, max_size: u64) -> Option<String> {
 		if !path.is_file() {
 			return None;
@@ -325,16 +652,134 @@ impl MetadataExtractor {
 			return None; // File too large
 		}
 
-		let mut file = std::fs::File::open(path).ok()?;
-		let mut buffer = Vec::new();
+		XxHashContentHasher.hash_file(path)
+	}
+}
 
-		use std::io::Read;
-		if file.read_to_end(&mut buffer).is_err() {
-			return None;
+/// Computes the content hashes compared during move matching.
+///
+/// The detector only calls this for regular files within `content_hash_max_file_size`; see
+/// `MoveDetector::with_content_hasher`. Hashes are compared as opaque strings, so a custom
+/// hasher must be used for both halves of a move, i.e. for the detector's whole lifetime.
+pub trait ContentHasher: Send + Sync {
+	/// Hash the file's contents; `None` if it cannot be read
+	fn hash_file(&self, path: &Path) -> Option<String>;
+}
+
+/// A [`ContentHasher`] behind a semaphore, so at most a fixed number of files are read at once
+///
+/// Clones share the semaphore, and so do all hashers from [`BoundedHasher::shared`] with the
+/// same limit; see `MoveDetectorConfig::max_concurrent_hashes`. Reads run on the blocking
+/// pool, so waiting for a permit or for the disk never stalls the executor.
+#[derive(Clone)]
+pub struct BoundedHasher {
+	hasher: Arc<dyn ContentHasher>,
+	permits: Arc<tokio::sync::Semaphore>,
+}
+
+impl BoundedHasher {
+	/// `max_concurrent` of 0 is treated as 1
+	pub fn new(hasher: Arc<dyn ContentHasher>, max_concurrent: usize) -> Self {
+		Self { hasher, permits: Arc::new(tokio::sync::Semaphore::new(max_concurrent.max(1))) }
+	}
+
+	/// The default hasher under the process-wide limit of `max_concurrent` reads, shared by
+	/// every detector configured with the same `max_concurrent_hashes`
+	pub fn shared(max_concurrent: usize) -> Self {
+		static SHARED: OnceLock<Mutex<HashMap<usize, Arc<tokio::sync::Semaphore>>>> =
+			OnceLock::new();
+		let max_concurrent = max_concurrent.max(1);
+		let permits = SHARED
+			.get_or_init(Mutex::default)
+			.lock()
+			.unwrap_or_else(|poisoned| poisoned.into_inner())
+			.entry(max_concurrent)
+			.or_insert_with(|| Arc::new(tokio::sync::Semaphore::new(max_concurrent)))
+			.clone();
+		Self { hasher: Arc::new(XxHashContentHasher), permits }
+	}
+
+	/// Another hasher under the same limit
+	pub fn with_hasher(&self, hasher: Arc<dyn ContentHasher>) -> Self {
+		Self { hasher, permits: self.permits.clone() }
+	}
+
+	/// Hash all of `paths` at once, as many at a time as the limit allows; the results are in
+	/// the order of `paths`
+	pub async fn hash_files(&self, paths: &[PathBuf]) -> Vec<Option<String>> {
+		let tasks: Vec<_> = paths
+			.iter()
+			.map(|path| {
+				let hasher = self.clone();
+				let path = path.clone();
+				crate::runtime::spawn(async move { hasher.hash_file(&path).await })
+			})
+			.collect();
+		let mut hashes = Vec::with_capacity(tasks.len());
+		for task in tasks {
+			hashes.push(task.join().await.flatten());
 		}
+		hashes
+	}
+
+	/// Wait for a permit, then hash the file's contents; `None` if it cannot be read
+	pub async fn hash_file(&self, path: &Path) -> Option<String> {
+		let _permit = self.permits.acquire().await.ok()?;
+		let hasher = self.hasher.clone();
+		let path = path.to_path_buf();
+		crate::runtime::spawn_blocking(move || hasher.hash_file(&path))
+			.join()
+			.await
+			.flatten()
+	}
+}
+
+impl std::fmt::Debug for BoundedHasher {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("BoundedHasher")
+			.field("available_permits", &self.permits.available_permits())
+			.finish_non_exhaustive()
+	}
+}
+
+/// Default hasher: XxHash64 over the whole file, hex encoded
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XxHashContentHasher;
 
+impl ContentHasher for XxHashContentHasher {
+	fn hash_file(&self, path: &Path) -> Option<String> {
+		let buffer = std::fs::read(path).ok()?;
 		let mut hasher = XxHash64::default();
 		buffer.hash(&mut hasher);
 		Some(format!("{:x}", hasher.finish()))
 	}
 }
+
+/// Scores how likely a pending remove and create are one move, from 0.0 to 1.0
+///
+/// Set through `MoveDetectorConfig::confidence_scorer` to replace the default weighted sum,
+/// e.g. with a trained model or a strict rule set. A pair is reported as a move when its
+/// score, plus the `weight_parent_correlation` bonus, reaches the path's confidence
+/// threshold; ties and the candidates offered are decided as before. Inode, Windows ID and
+/// content hash are filled in on the events when the detector could read them. Called for
+/// every candidate pair, on the event loop, so it must be cheap.
+pub trait ConfidenceScorer: Send + Sync + std::fmt::Debug {
+	fn score(
+		&self, remove_event: &PendingEvent, create_event: &PendingEvent,
+		config: &MoveDetectorConfig,
+	) -> f32;
+}
+
+/// Default scorer: size, timing, inode or Windows ID, content hash and name similarity,
+/// weighted by the `weight_*` fields of the config
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BlendedConfidenceScorer;
+
+impl ConfidenceScorer for BlendedConfidenceScorer {
+	fn score(
+		&self, remove_event: &PendingEvent, create_event: &PendingEvent,
+		config: &MoveDetectorConfig,
+	) -> f32 {
+		MoveMatching::calculate_confidence(remove_event, create_event, config)
+	}
+}
@@ -1,25 +1,48 @@
+use crate::runtime::Instant;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use tokio::time::Instant;
+use std::time::{Duration, SystemTime};
+
+/// How long metadata recorded by `MoveDetector::prewarm` is kept, at least
+pub const PREWARM_TTL: Duration = Duration::from_secs(60);
 
 /// Cached metadata for a file that we've seen before
 #[derive(Debug, Clone)]
 pub struct FileMetadata {
 	pub size: Option<u64>,
 	pub windows_id: Option<u64>,
+	/// Only recorded while the file still exists, since a removed path has no inode to read
+	pub inode: Option<u64>,
+	/// Recorded ahead of time by `MoveDetector::prewarm`, and kept for [`PREWARM_TTL`]
+	pub prewarmed: bool,
 	pub last_seen: Instant,
 }
 
 impl FileMetadata {
 	pub fn new(size: Option<u64>, windows_id: Option<u64>) -> Self {
-		Self { size, windows_id, last_seen: Instant::now() }
+		Self { size, windows_id, inode: None, prewarmed: false, last_seen: Instant::now() }
+	}
+
+	pub fn with_inode(mut self, inode: Option<u64>) -> Self {
+		self.inode = inode;
+		self
 	}
 }
 
+/// A content hash together with the file state it was computed from
+#[derive(Debug, Clone)]
+pub struct CachedContentHash {
+	pub size: u64,
+	pub modified: SystemTime,
+	pub hash: String,
+	pub last_seen: Instant,
+}
+
 /// Cache for storing metadata of recently seen files
 #[derive(Debug, Default)]
 pub struct MetadataCache {
 	cache: HashMap<PathBuf, FileMetadata>,
+	content_hashes: HashMap<PathBuf, CachedContentHash>,
 }
 
 impl MetadataCache {
@@ -37,11 +60,30 @@ impl MetadataCache {
 		self.cache.get(path)
 	}
 
-	/// Remove and return metadata for a path
+	/// Remove and return metadata for a path; also drops its cached content hash
 	pub fn remove(&mut self, path: &Path) -> Option<FileMetadata> {
+		self.content_hashes.remove(path);
 		self.cache.remove(path)
 	}
 
+	/// Content hash of `path` if it was computed for exactly this size and mtime
+	pub fn content_hash(&self, path: &Path, size: u64, modified: SystemTime) -> Option<&str> {
+		self.content_hashes
+			.get(path)
+			.filter(|cached| cached.size == size && cached.modified == modified)
+			.map(|cached| cached.hash.as_str())
+	}
+
+	/// Remember the content hash computed for `path` at the given size and mtime
+	pub fn insert_content_hash(
+		&mut self, path: PathBuf, size: u64, modified: SystemTime, hash: String,
+	) {
+		self.content_hashes.insert(
+			path,
+			CachedContentHash { size, modified, hash, last_seen: Instant::now() },
+		);
+	}
+
 	/// Check if metadata exists for a path
 	pub fn contains(&self, path: &Path) -> bool {
 		self.cache.contains_key(path)
@@ -52,10 +94,15 @@ impl MetadataCache {
 		self.cache.keys()
 	}
 
-	/// Clear old entries based on age
+	/// Clear old entries based on age; prewarmed entries are kept for at least [`PREWARM_TTL`]
 	pub fn cleanup_old_entries(&mut self, max_age: std::time::Duration) {
-		let cutoff = Instant::now() - max_age;
-		self.cache.retain(|_, metadata| metadata.last_seen > cutoff);
+		let now = Instant::now();
+		self.cache.retain(|_, metadata| {
+			let max_age = if metadata.prewarmed { max_age.max(PREWARM_TTL) } else { max_age };
+			now.duration_since(metadata.last_seen) < max_age
+		});
+		self.content_hashes
+			.retain(|_, cached| now.duration_since(cached.last_seen) < max_age);
 	}
 
 	/// Get the number of cached entries
@@ -71,5 +118,6 @@ impl MetadataCache {
 	/// Clear all cached metadata
 	pub fn clear(&mut self) {
 		self.cache.clear();
+		self.content_hashes.clear();
 	}
 }
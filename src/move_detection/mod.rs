@@ -7,9 +7,12 @@
 //! # Module Organization
 //!
 //! - [`config`] - Configuration structures and validation
+//! - [`diff`] - Synthetic events from two snapshots of a tree
 //! - [`events`] - Event storage and management
 //! - [`metadata`] - File metadata caching
 //! - [`heuristics`] - Path type inference and similarity algorithms
+//! - [`flapping`] - Files moved back and forth in quick succession
+//! - [`lifecycle`] - Trace of each event through the detector, for debugging
 //! - [`matching`] - Move detection algorithms and confidence calculations
 //! - [`monitoring`] - Resource monitoring and statistics
 //! - [`detector`] - Main MoveDetector implementation
@@ -17,15 +20,24 @@
 
 pub mod config;
 pub mod detector;
+pub mod diff;
 pub mod error;
 pub mod events;
+pub mod flapping;
 pub mod heuristics;
+pub mod lifecycle;
 pub mod matching;
 pub mod metadata;
 pub mod monitoring;
 pub mod test_helpers;
 
 // Re-export main types for convenience
-pub use config::MoveDetectorConfig;
+pub use config::{
+	CacheMismatchPolicy, MoveDetectorConfig, MoveScope, ThresholdOverride, TimeoutOverride,
+};
 pub use detector::MoveDetector;
+pub use diff::{diff_trees, diff_trees_with_config};
 pub use error::MoveDetectionError;
+pub use matching::{
+	BlendedConfidenceScorer, BoundedHasher, ConfidenceScorer, ContentHasher, XxHashContentHasher,
+};
@@ -1,5 +1,9 @@
-use crate::move_detection::events::PendingEventsStorage;
+use crate::events::MoveDetectionMethod;
+use crate::move_detection::events::{PendingEvent, PendingEventsStorage};
 use crate::move_detection::metadata::MetadataCache;
+use crate::runtime::Instant;
+use std::collections::HashMap;
+use std::time::Duration;
 
 /// Statistics about resource usage and performance
 #[derive(Debug, Clone)]
@@ -12,6 +16,63 @@ pub struct ResourceStats {
 	pub moves_detected: u64,
 	pub confidence_sum: f64,
 	pub average_confidence: f32,
+	/// How long currently pending events have been waiting, as of the last `update`
+	pub pending_event_ages: PendingEventAges,
+	/// `moves_detected` split by the method that decided each move
+	pub moves_by_method: HashMap<MoveDetectionMethod, MethodStats>,
+}
+
+/// Moves one `MoveDetectionMethod` decided
+///
+/// A method that never shows up here (e.g. `ContentHash`) is not what pairs this workload's
+/// moves, so its cost can be saved by disabling it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodStats {
+	pub moves: u64,
+	pub confidence_sum: f64,
+}
+
+impl MethodStats {
+	/// Mean confidence of these moves, 0.0 when there are none
+	pub fn average_confidence(&self) -> f32 {
+		if self.moves == 0 {
+			0.0
+		} else {
+			(self.confidence_sum / self.moves as f64) as f32
+		}
+	}
+}
+
+/// Ages of the events currently waiting for a move partner, each list sorted ascending.
+///
+/// A snapshot for tuning `MoveDetectorConfig::timeout`: removes that keep reaching the timeout
+/// unmatched suggest it is too short, while ages that never get near it suggest it can be
+/// tightened. It says nothing about events that were already matched or expired; pair it with
+/// `moves_detected` for that side of the picture.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PendingEventAges {
+	pub removes: Vec<Duration>,
+	pub creates: Vec<Duration>,
+}
+
+impl PendingEventAges {
+	pub fn from_storage(storage: &PendingEventsStorage) -> Self {
+		let now = Instant::now();
+		Self {
+			removes: sorted_ages(now, storage.iter_removes()),
+			creates: sorted_ages(now, storage.iter_creates()),
+		}
+	}
+
+	/// Longest-waiting pending remove
+	pub fn oldest_remove(&self) -> Option<Duration> {
+		self.removes.last().copied()
+	}
+
+	/// Longest-waiting pending create
+	pub fn oldest_create(&self) -> Option<Duration> {
+		self.creates.last().copied()
+	}
 }
 
 impl ResourceStats {
@@ -25,6 +86,8 @@ impl ResourceStats {
 			moves_detected: 0,
 			confidence_sum: 0.0,
 			average_confidence: 0.0,
+			pending_event_ages: PendingEventAges::default(),
+			moves_by_method: HashMap::new(),
 		}
 	}
 
@@ -54,6 +117,7 @@ impl ResourceStats {
 		self.cached_metadata_entries = metadata_cache.len();
 		self.memory_usage_estimate_bytes =
 			Self::calculate_memory_estimate(pending_events, metadata_cache);
+		self.pending_event_ages = PendingEventAges::from_storage(pending_events);
 
 		if self.moves_detected > 0 {
 			self.average_confidence = (self.confidence_sum / self.moves_detected as f64) as f32;
@@ -65,11 +129,19 @@ impl ResourceStats {
 		self.total_events_processed += 1;
 	}
 
-	/// Record a detected move with its confidence
-	pub fn record_move_detected(&mut self, confidence: f32) {
+	/// Record a detected move with the method that decided it and its confidence
+	pub fn record_move_detected(&mut self, method: MoveDetectionMethod, confidence: f32) {
 		self.moves_detected += 1;
 		self.confidence_sum += confidence as f64;
 		self.average_confidence = (self.confidence_sum / self.moves_detected as f64) as f32;
+		let by_method = self.moves_by_method.entry(method).or_default();
+		by_method.moves += 1;
+		by_method.confidence_sum += confidence as f64;
+	}
+
+	/// Moves decided by `method` so far
+	pub fn moves_by(&self, method: MoveDetectionMethod) -> u64 {
+		self.moves_by_method.get(&method).map_or(0, |stats| stats.moves)
 	}
 
 	/// Check if resource usage is concerning
@@ -83,6 +155,13 @@ impl ResourceStats {
 	}
 }
 
+fn sorted_ages<'a>(now: Instant, events: impl Iterator<Item = &'a PendingEvent>) -> Vec<Duration> {
+	let mut ages: Vec<Duration> =
+		events.map(|pending| now.duration_since(pending.timestamp)).collect();
+	ages.sort_unstable();
+	ages
+}
+
 impl Default for ResourceStats {
 	fn default() -> Self {
 		Self::new()
@@ -1,9 +1,14 @@
 //! Test helpers and mocks for move_detection module
+//!
+//! Stand-ins for the persistent cache a [`crate::MoveDetector`] is built on, for tests here
+//! and for applications testing their own integration: [`DummyCache`] knows nothing, and
+//! [`MockCache`] keeps nodes in memory, can be seeded with nodes, and can be made to fail.
 
-use crate::database::error::DatabaseResult;
+use crate::database::error::{DatabaseError, DatabaseResult};
 use crate::database::storage::filesystem_cache::trait_def::{CacheStats, FilesystemCacheStorage};
-use crate::database::types::{FilesystemNode, SharedNodeInfo, WatchMetadata};
-use std::path::Path;
+use crate::database::types::{FilesystemNode, NodeType, SharedNodeInfo, WatchMetadata};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 /// DummyCache: a stub FilesystemCacheStorage for unit tests
@@ -93,4 +98,230 @@ impl FilesystemCacheStorage for DummyCache {
 	) -> DatabaseResult<()> {
 		Ok(())
 	}
+	async fn invalidate_unified_node(&mut self, _path: &Path) -> DatabaseResult<()> {
+		Ok(())
+	}
+}
+
+/// In-memory `FilesystemCacheStorage` for tests
+///
+/// Nodes are kept per watch and by exact path; unified queries look across all watches.
+/// Seed it with [`MockCache::with_node`], or use [`MockCache::failing`] to make every call
+/// return `DatabaseError::StorageError`. Watch metadata, shared nodes and the content-hash
+/// index are kept too; stale-entry cleanup does nothing.
+#[derive(Debug, Default)]
+pub struct MockCache {
+	nodes: HashMap<Uuid, HashMap<PathBuf, FilesystemNode>>,
+	watches: HashMap<Uuid, WatchMetadata>,
+	shared: HashMap<u64, SharedNodeInfo>,
+	content_hashes: HashMap<String, PathBuf>,
+	failure: Option<String>,
+}
+
+impl MockCache {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Hold `node` for `watch_id`, as if it had been stored
+	pub fn with_node(mut self, watch_id: Uuid, node: FilesystemNode) -> Self {
+		self.nodes.entry(watch_id).or_default().insert(node.path.clone(), node);
+		self
+	}
+
+	/// Fail every call with `DatabaseError::StorageError(message)`
+	pub fn failing(mut self, message: impl Into<String>) -> Self {
+		self.failure = Some(message.into());
+		self
+	}
+
+	/// Every node held, across watches
+	pub fn nodes(&self) -> impl Iterator<Item = &FilesystemNode> {
+		self.nodes.values().flat_map(|nodes| nodes.values())
+	}
+
+	fn check(&self) -> DatabaseResult<()> {
+		match &self.failure {
+			Some(message) => Err(DatabaseError::StorageError(message.clone())),
+			None => Ok(()),
+		}
+	}
+
+	fn collect(
+		&self, keep: impl Fn(&FilesystemNode) -> bool,
+	) -> DatabaseResult<Vec<FilesystemNode>> {
+		self.check()?;
+		let mut nodes: Vec<_> = self.nodes().filter(|node| keep(node)).cloned().collect();
+		nodes.sort_by(|a, b| a.path.cmp(&b.path));
+		Ok(nodes)
+	}
+
+	fn watch_nodes(&self, watch_id: &Uuid) -> impl Iterator<Item = &FilesystemNode> {
+		self.nodes.get(watch_id).into_iter().flat_map(|nodes| nodes.values())
+	}
+}
+
+#[async_trait::async_trait]
+impl FilesystemCacheStorage for MockCache {
+	async fn store_filesystem_node(
+		&mut self, watch_id: &Uuid, node: &FilesystemNode, _event_type: &str,
+	) -> DatabaseResult<()> {
+		self.check()?;
+		self.nodes.entry(*watch_id).or_default().insert(node.path.clone(), node.clone());
+		Ok(())
+	}
+	async fn get_filesystem_node(
+		&mut self, watch_id: &Uuid, path: &Path,
+	) -> DatabaseResult<Option<FilesystemNode>> {
+		self.check()?;
+		Ok(self.nodes.get(watch_id).and_then(|nodes| nodes.get(path)).cloned())
+	}
+	async fn list_directory_for_watch(
+		&mut self, watch_id: &Uuid, parent_path: &Path,
+	) -> DatabaseResult<Vec<FilesystemNode>> {
+		self.check()?;
+		let mut nodes: Vec<_> = self
+			.watch_nodes(watch_id)
+			.filter(|node| node.path.parent() == Some(parent_path))
+			.cloned()
+			.collect();
+		nodes.sort_by(|a, b| a.path.cmp(&b.path));
+		Ok(nodes)
+	}
+	async fn store_watch_metadata(&mut self, metadata: &WatchMetadata) -> DatabaseResult<()> {
+		self.check()?;
+		self.watches.insert(metadata.watch_id, metadata.clone());
+		Ok(())
+	}
+	async fn get_watch_metadata(
+		&mut self, watch_id: &Uuid,
+	) -> DatabaseResult<Option<WatchMetadata>> {
+		self.check()?;
+		Ok(self.watches.get(watch_id).cloned())
+	}
+	async fn remove_watch(&mut self, watch_id: &Uuid) -> DatabaseResult<()> {
+		self.check()?;
+		self.watches.remove(watch_id);
+		self.nodes.remove(watch_id);
+		Ok(())
+	}
+	async fn store_shared_node(&mut self, shared_info: &SharedNodeInfo) -> DatabaseResult<()> {
+		self.check()?;
+		self.shared.insert(shared_info.node.computed.path_hash, shared_info.clone());
+		Ok(())
+	}
+	async fn get_shared_node(&mut self, path_hash: u64) -> DatabaseResult<Option<SharedNodeInfo>> {
+		self.check()?;
+		Ok(self.shared.get(&path_hash).cloned())
+	}
+	async fn batch_store_filesystem_nodes(
+		&mut self, watch_id: &Uuid, nodes: &[FilesystemNode], event_type: &str,
+	) -> DatabaseResult<()> {
+		for node in nodes {
+			self.store_filesystem_node(watch_id, node, event_type).await?;
+		}
+		Ok(())
+	}
+	async fn find_nodes_by_prefix(
+		&mut self, watch_id: &Uuid, prefix: &Path,
+	) -> DatabaseResult<Vec<FilesystemNode>> {
+		self.check()?;
+		let mut nodes: Vec<_> = self
+			.watch_nodes(watch_id)
+			.filter(|node| node.path.starts_with(prefix))
+			.cloned()
+			.collect();
+		nodes.sort_by(|a, b| a.path.cmp(&b.path));
+		Ok(nodes)
+	}
+	async fn get_cache_stats(&mut self, watch_id: &Uuid) -> DatabaseResult<CacheStats> {
+		self.check()?;
+		let mut stats = CacheStats { shared_nodes: self.shared.len() as u64, ..Default::default() };
+		for node in self.watch_nodes(watch_id) {
+			stats.total_nodes += 1;
+			match node.node_type {
+				NodeType::Directory { .. } => stats.directories += 1,
+				NodeType::File { .. } => stats.files += 1,
+				NodeType::Symlink { .. } => stats.symlinks += 1,
+			}
+		}
+		Ok(stats)
+	}
+	async fn cleanup_stale_cache(
+		&mut self, _watch_id: &Uuid, _max_age_seconds: u64,
+	) -> DatabaseResult<usize> {
+		self.check()?;
+		Ok(0)
+	}
+	async fn list_directory_unified(
+		&mut self, parent_path: &Path,
+	) -> DatabaseResult<Vec<FilesystemNode>> {
+		self.collect(|node| node.path.parent() == Some(parent_path))
+	}
+	async fn get_unified_node(&mut self, path: &Path) -> DatabaseResult<Option<FilesystemNode>> {
+		self.check()?;
+		Ok(self.nodes().find(|node| node.path == path).cloned())
+	}
+	async fn list_ancestors(&mut self, path: &Path) -> DatabaseResult<Vec<FilesystemNode>> {
+		self.collect(|node| path.starts_with(&node.path) && node.path != path)
+	}
+	async fn list_descendants(&mut self, path: &Path) -> DatabaseResult<Vec<FilesystemNode>> {
+		self.collect(|node| node.path.starts_with(path) && node.path != path)
+	}
+	async fn search_nodes(&mut self, pattern: &str) -> DatabaseResult<Vec<FilesystemNode>> {
+		self.collect(|node| node.path.to_string_lossy().contains(pattern))
+	}
+	async fn get_node(
+		&mut self, watch_id: &Uuid, path: &Path,
+	) -> DatabaseResult<Option<FilesystemNode>> {
+		self.get_filesystem_node(watch_id, path).await
+	}
+	async fn remove_filesystem_node(
+		&mut self, watch_id: &Uuid, path: &Path, _event_type: &str,
+	) -> DatabaseResult<()> {
+		self.check()?;
+		if let Some(nodes) = self.nodes.get_mut(watch_id) {
+			nodes.remove(path);
+		}
+		Ok(())
+	}
+	async fn rename_filesystem_node(
+		&mut self, watch_id: &Uuid, old_path: &Path, new_path: &Path, _event_type: &str,
+	) -> DatabaseResult<()> {
+		self.check()?;
+		if let Some(nodes) = self.nodes.get_mut(watch_id) {
+			if let Some(mut node) = nodes.remove(old_path) {
+				node.path = new_path.to_path_buf();
+				nodes.insert(new_path.to_path_buf(), node);
+			}
+		}
+		Ok(())
+	}
+	async fn invalidate_unified_node(&mut self, path: &Path) -> DatabaseResult<()> {
+		self.check()?;
+		for nodes in self.nodes.values_mut() {
+			nodes.remove(path);
+		}
+		Ok(())
+	}
+	async fn record_content_hash(&mut self, hash: &str, path: &Path) -> DatabaseResult<()> {
+		self.check()?;
+		self.content_hashes.insert(hash.to_string(), path.to_path_buf());
+		Ok(())
+	}
+	async fn path_for_content_hash(&mut self, hash: &str) -> DatabaseResult<Option<PathBuf>> {
+		self.check()?;
+		Ok(self.content_hashes.get(hash).cloned())
+	}
+	async fn relocate_content_hashes(
+		&mut self, old_path: &Path, new_path: &Path,
+	) -> DatabaseResult<()> {
+		self.check()?;
+		for path in self.content_hashes.values_mut() {
+			if let Ok(rest) = path.strip_prefix(old_path) {
+				*path = new_path.join(rest);
+			}
+		}
+		Ok(())
+	}
 }
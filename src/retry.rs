@@ -89,7 +89,7 @@ impl RetryManager {
 						error
 					);
 
-					tokio::time::sleep(delay).await;
+					crate::runtime::sleep(delay).await;
 					attempt += 1;
 				}
 			}
@@ -152,7 +152,7 @@ impl RetryManager {
 						error
 					);
 
-					tokio::time::sleep(delay).await;
+					crate::runtime::sleep(delay).await;
 					attempt += 1;
 				}
 			}
@@ -0,0 +1,169 @@
+//! Minimal async runtime abstraction.
+//!
+//! The crate only needs four things from a runtime: spawning tasks, running blocking work,
+//! sleeping, and a monotonic `Instant`. Those are routed through here so the library can run on
+//! tokio (`runtime-tokio`, default) or async-std (`runtime-async-std`). If both features are
+//! enabled tokio is used; Cargo features are additive, so rejecting the combination would break
+//! any dependency graph where two crates pick different runtimes.
+//!
+//! Everything else stays on runtime-agnostic primitives: `tokio::sync` channels and locks work
+//! under any executor, and `tokio::select!` is a plain macro over futures. That means async-std
+//! users still compile the `tokio` crate, but not its scheduler, timer or IO driver.
+//!
+//! Limitations:
+//! - The `watcher` binary and the test suite use `#[tokio::main]`/`#[tokio::test]` and require
+//!   `runtime-tokio`.
+//! - Under async-std, `JoinHandle::abort` cancels at the next await point of the task (via
+//!   `futures_util::future::Abortable`), the same guarantee tokio gives.
+
+use std::future::Future;
+use std::time::Duration;
+
+#[cfg(not(any(feature = "runtime-tokio", feature = "runtime-async-std")))]
+compile_error!("enable one of the `runtime-tokio` or `runtime-async-std` features");
+
+/// Monotonic clock used for move-detection timing.
+///
+/// With tokio this is `tokio::time::Instant`, which honours `tokio::time::pause()` in tests.
+#[cfg(feature = "runtime-tokio")]
+pub type Instant = tokio::time::Instant;
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+pub type Instant = std::time::Instant;
+
+/// Handle to a spawned task that can be cancelled or awaited.
+pub struct JoinHandle<T> {
+	#[cfg(feature = "runtime-tokio")]
+	inner: tokio::task::JoinHandle<T>,
+	#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+	inner: async_std::task::JoinHandle<Result<T, futures_util::future::Aborted>>,
+	#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+	abort_handle: Option<futures_util::future::AbortHandle>,
+}
+
+impl<T> JoinHandle<T> {
+	/// Request cancellation; takes effect at the task's next await point.
+	pub fn abort(&self) {
+		#[cfg(feature = "runtime-tokio")]
+		self.inner.abort();
+		#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+		if let Some(handle) = &self.abort_handle {
+			handle.abort();
+		}
+	}
+
+	/// Wait for the task to finish; `None` if it was aborted or panicked.
+	pub async fn join(self) -> Option<T> {
+		#[cfg(feature = "runtime-tokio")]
+		{
+			self.inner.await.ok()
+		}
+		#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+		{
+			self.inner.await.ok()
+		}
+	}
+
+	/// Wait for the task for at most `timeout`; gives the handle back if it is still running.
+	pub async fn join_timeout(mut self, timeout: Duration) -> Result<Option<T>, Self> {
+		tokio::select! {
+			result = &mut self.inner => Ok(result.ok()),
+			_ = sleep(timeout) => Err(self),
+		}
+	}
+}
+
+/// Spawn a future onto the active runtime.
+///
+/// Must be called from within that runtime (tokio panics otherwise).
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+	F: Future + Send + 'static,
+	F::Output: Send + 'static,
+{
+	#[cfg(feature = "runtime-tokio")]
+	{
+		JoinHandle { inner: tokio::spawn(future) }
+	}
+	#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+	{
+		let (abortable, abort_handle) = futures_util::future::abortable(future);
+		JoinHandle { inner: async_std::task::spawn(abortable), abort_handle: Some(abort_handle) }
+	}
+}
+
+/// Whether `spawn` can be called from here, i.e. the caller runs inside a runtime.
+///
+/// Always true under async-std, whose global executor starts on demand.
+pub fn is_available() -> bool {
+	#[cfg(feature = "runtime-tokio")]
+	{
+		tokio::runtime::Handle::try_current().is_ok()
+	}
+	#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+	{
+		true
+	}
+}
+
+/// Run blocking work on the runtime's blocking thread pool.
+///
+/// Blocking closures cannot be aborted on either runtime; `abort` is a no-op for them.
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+	F: FnOnce() -> R + Send + 'static,
+	R: Send + 'static,
+{
+	#[cfg(feature = "runtime-tokio")]
+	{
+		JoinHandle { inner: tokio::task::spawn_blocking(f) }
+	}
+	#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+	{
+		JoinHandle { inner: async_std::task::spawn_blocking(move || Ok(f())), abort_handle: None }
+	}
+}
+
+/// Sleep without blocking the executor.
+pub async fn sleep(duration: Duration) {
+	#[cfg(feature = "runtime-tokio")]
+	tokio::time::sleep(duration).await;
+	#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+	async_std::task::sleep(duration).await;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn test_spawn_and_join() {
+		let handle = spawn(async { 21 * 2 });
+		assert_eq!(handle.join().await, Some(42));
+
+		let blocking = spawn_blocking(|| "done");
+		assert_eq!(blocking.join().await, Some("done"));
+	}
+
+	#[tokio::test]
+	async fn test_abort_pending_task() {
+		let handle = spawn(async {
+			sleep(Duration::from_secs(60)).await;
+			1
+		});
+		handle.abort();
+		assert_eq!(handle.join().await, None);
+	}
+
+	#[tokio::test]
+	async fn test_join_timeout() {
+		let quick = spawn(async { 7 });
+		assert_eq!(
+			quick.join_timeout(Duration::from_secs(5)).await.ok(),
+			Some(Some(7))
+		);
+
+		let slow = spawn(sleep(Duration::from_secs(60)));
+		let slow = slow.join_timeout(Duration::from_millis(10)).await.err().unwrap();
+		slow.abort();
+	}
+}
@@ -0,0 +1,32 @@
+//! Secondary destinations for delivered events
+//!
+//! The watcher persists to redb on its own; anything else (SQLite, Postgres, Kafka, ...) is
+//! plugged in by implementing [`EventSink`] and registering it with
+//! `WatcherConfig::with_event_sink`, so the crate takes on none of their dependencies.
+
+use crate::error::Result;
+use crate::events::FileSystemEvent;
+
+/// A destination every delivered event is mirrored to
+///
+/// `write` is called on the event loop once the event is in the receiver's channel, for each
+/// registered sink in turn and awaited before the next event is processed, so a slow sink slows
+/// the watcher down instead of buffering without bound. A failed write is retried with the
+/// policy of `WatcherConfig::error_recovery_config` when one is set (only errors that
+/// `WatcherError::is_retryable` accepts), then reported as
+/// `WatcherDiagnostic::SinkWriteFailed`; the event is not offered again.
+#[async_trait::async_trait]
+pub trait EventSink: Send + Sync {
+	async fn write(&self, event: &FileSystemEvent) -> Result<()>;
+
+	/// Name used in diagnostics and logs
+	fn name(&self) -> &str {
+		std::any::type_name::<Self>()
+	}
+}
+
+impl std::fmt::Debug for dyn EventSink {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_tuple("EventSink").field(&self.name()).finish()
+	}
+}
@@ -1,33 +1,549 @@
+use crate::database::path_utils::normalize_separators;
+use crate::database::storage::delivery_log::DeliveryLog;
 use crate::database::storage::filesystem_cache::synchronizer::{
 	DefaultFilesystemCacheSynchronizer, FilesystemCacheSynchronizer,
 };
 use crate::database::storage::filesystem_cache::RedbFilesystemCache;
-use crate::database::{DatabaseAdapter, DatabaseConfig};
+use crate::database::storage::FilesystemCacheStorage;
+use crate::database::{DatabaseAdapter, DatabaseConfig, DatabaseError, DatabaseResult};
+use crate::diagnostics::{DiagnosticsSender, WatcherDiagnostic};
 use crate::error::{ErrorRecoveryConfig, Result, WatcherError};
-use crate::events::{EventType, FileSystemEvent};
+use crate::events::{EventType, FileOwnership, FileSystemEvent, TimestampSource};
+use crate::metrics::{WatcherMetrics, WatcherStats};
+use crate::move_detection::lifecycle::{LifecycleEvent, LifecycleSender};
 use crate::move_detection::{MoveDetector, MoveDetectorConfig};
 use crate::retry::RetryManager;
+use chrono::{DateTime, Utc};
+use globset::{Glob, GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
 use notify::{Config, EventKind, RecommendedWatcher, RecursiveMode, Watcher as NotifyWatcher};
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, warn};
 
+/// What a watcher observes; see `WatcherConfig::targets`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum WatchTargets {
+	/// The tree at `WatcherConfig::path`
+	#[default]
+	Tree,
+	/// Exactly these files.
+	///
+	/// Each distinct parent directory gets one non-recursive watch, so the number of OS watches
+	/// is bounded by the number of parents rather than by the size of any tree. Events for
+	/// other entries in those directories are discarded in the backend callback, before any
+	/// metadata reads or move detection. Since it is the parent that is watched, a file that
+	/// does not exist yet, or is deleted and recreated (including an editor's save-by-rename),
+	/// keeps being reported. The parents themselves must exist at `start()`; a parent that is
+	/// removed and recreated later is not watched again.
+	Files(Vec<PathBuf>),
+}
+
+/// How a move from a watched path into an ignored one is reported; see
+/// `WatcherConfig::moved_to_ignored`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MovedToIgnoredPolicy {
+	/// A plain Remove of the source: from the watch's point of view the file is gone
+	#[default]
+	Remove,
+	/// An [`EventType::MovedToIgnored`] event whose `move_data` names the ignored destination
+	Marker,
+}
+
+/// Channel fill levels at which write coalescing switches itself on and off; see
+/// `WatcherConfig::adaptive_coalescing`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveCoalescing {
+	/// Fraction of the event channel in use (0.0 to 1.0) at which coalescing engages
+	pub engage_at: f32,
+	/// Fraction at or below which it is released again; must be below `engage_at`
+	pub release_at: f32,
+	/// How long a file must be quiet before its coalesced event is sent, as with
+	/// `WatcherConfig::stabilize_writes`
+	pub quiet_period: Duration,
+}
+
+impl Default for AdaptiveCoalescing {
+	fn default() -> Self {
+		Self { engage_at: 0.8, release_at: 0.4, quiet_period: Duration::from_millis(200) }
+	}
+}
+
+/// A change the application made itself; see [`WatcherHandle::apply_known_operations`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KnownOperation {
+	/// A file or directory was created at `path`; `size` is the file's length, `None` for a
+	/// directory
+	Create {
+		path: PathBuf,
+		is_directory: bool,
+		size: Option<u64>,
+	},
+	/// Whatever was at `path` was removed
+	Remove { path: PathBuf, is_directory: bool },
+	/// `from` was renamed to `to`
+	Move {
+		from: PathBuf,
+		to: PathBuf,
+		is_directory: bool,
+	},
+}
+
+impl KnownOperation {
+	/// The same operation with its paths in the canonical form event paths have
+	fn canonical(self) -> Self {
+		match self {
+			Self::Create { path, is_directory, size } => {
+				Self::Create { path: canonical_path(&path), is_directory, size }
+			}
+			Self::Remove { path, is_directory } => {
+				Self::Remove { path: canonical_path(&path), is_directory }
+			}
+			Self::Move { from, to, is_directory } => {
+				Self::Move { from: canonical_path(&from), to: canonical_path(&to), is_directory }
+			}
+		}
+	}
+
+	/// Backend events the operation causes, by path and type
+	fn expected_events(&self) -> Vec<(PathBuf, EventType)> {
+		match self {
+			Self::Create { path, .. } => vec![(path.clone(), EventType::Create)],
+			Self::Remove { path, .. } => vec![(path.clone(), EventType::Remove)],
+			Self::Move { from, to, .. } => {
+				let from_types = [EventType::Remove, EventType::RenameFrom, EventType::Rename];
+				let to_types = [EventType::Create, EventType::RenameTo, EventType::Rename];
+				let from = from_types.into_iter().map(|event_type| (from.clone(), event_type));
+				from.chain(to_types.into_iter().map(|event_type| (to.clone(), event_type)))
+					.collect()
+			}
+		}
+	}
+
+	/// The event the filesystem cache is updated with, built from the operation alone so it
+	/// can be applied before the change is made
+	fn to_event(&self) -> FileSystemEvent {
+		match self {
+			Self::Create { path, is_directory, size } => {
+				FileSystemEvent::new(EventType::Create, path.clone(), *is_directory, *size)
+			}
+			Self::Remove { path, is_directory } => {
+				FileSystemEvent::new(EventType::Remove, path.clone(), *is_directory, None)
+			}
+			Self::Move { from, to, is_directory } => {
+				FileSystemEvent::new(EventType::Move, to.clone(), *is_directory, None)
+					.with_move_data(crate::events::MoveEvent::new(
+						from.clone(),
+						to.clone(),
+						1.0,
+						crate::events::MoveDetectionMethod::FileSystemEvent,
+					))
+			}
+		}
+	}
+}
+
+/// How long backend events matching a [`KnownOperation`] are dropped after it was applied
+const KNOWN_OPERATION_WINDOW: Duration = Duration::from_secs(5);
+
+/// Backend events expected from recent [`KnownOperation`]s, shared by the handle and the
+/// event loop
+#[derive(Default)]
+struct KnownOperations {
+	/// (path, event type) -> when the operation was applied
+	expected: HashMap<(PathBuf, EventType), Instant>,
+}
+
+impl KnownOperations {
+	fn record(&mut self, operation: &KnownOperation) {
+		let now = Instant::now();
+		self.expected
+			.retain(|_, applied| now.duration_since(*applied) < KNOWN_OPERATION_WINDOW);
+		for key in operation.expected_events() {
+			self.expected.insert(key, now);
+		}
+	}
+
+	fn suppresses(&self, path: &Path, event_type: &EventType) -> bool {
+		self.expected
+			.get(&(path.to_path_buf(), event_type.clone()))
+			.is_some_and(|applied| applied.elapsed() < KNOWN_OPERATION_WINDOW)
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct WatcherConfig {
 	pub watch_id: uuid::Uuid,
+	/// Root to watch; may be relative or contain symlinks.
+	///
+	/// `start` canonicalizes it once and every event path is rooted at that canonical form,
+	/// whatever form was given here. Symlinks are resolved, including a symlinked root itself:
+	/// events report the target's path, not the link's, because that is the only form all
+	/// backends agree on (FSEvents, for one, always reports resolved paths).
 	pub path: PathBuf,
+	/// Descend into the directories below `path` in the initial and catch-up scans
+	///
+	/// Has no effect when `path` is a file: `start` turns it off with a warning, so the default
+	/// config can watch a single file. For watching a handful of files,
+	/// [`WatchTargets::Files`] is the better fit.
 	pub recursive: bool,
+	/// Watch the whole tree at `path` (default) or only an explicit set of files.
+	///
+	/// With [`WatchTargets::Files`], `path` and `recursive` are ignored and events are only
+	/// reported for the listed paths, in canonical form (symlinked parents resolved). Listing
+	/// an existing directory is rejected by `validate`, since its contents would not be watched.
+	pub targets: WatchTargets,
 	pub move_detector_config: Option<MoveDetectorConfig>,
 	pub error_recovery_config: Option<ErrorRecoveryConfig>,
 	pub database_config: Option<DatabaseConfig>,
+	/// Event types delivered to the consumer; `None` delivers everything.
+	///
+	/// notify 6.x exposes no per-kind event mask on any backend (inotify, FSEvents and
+	/// ReadDirectoryChangesW are all registered with a fixed mask), so the kernel still
+	/// delivers everything. Filtering happens in two places instead:
+	/// - in the notify callback thread, events that cannot contribute to an allowed type are
+	///   dropped before metadata reads, database writes and move detection;
+	/// - after move detection, only allowed types are sent to the receiver.
+	///
+	/// Allowing `Move` keeps Create/Remove/Rename* flowing into the detector, since moves are
+	/// derived from them. In recursive mode Create is always processed internally so new
+	/// subdirectories are still watched. Events processed internally but not allowed are
+	/// still persisted to the database and filesystem cache.
+	pub event_types: Option<HashSet<EventType>>,
+	/// Hold file Create/Write events until the file size has not changed for this long.
+	///
+	/// Meant for "wait until the copy/upload finishes": one event is emitted per burst, with the
+	/// type of the first held event (Create wins over Write) and the final size. Detected moves
+	/// bypass this (a renamed file is already complete) and cancel any hold on their source.
+	/// Size is polled, so a writer that pauses for longer than the quiet period, or rewrites the
+	/// file in place without changing its size and without further Write events, will be
+	/// reported early. Directories are never held. Events still held when the watcher stops
+	/// are delivered then, unless the event channel is full.
+	pub stabilize_writes: Option<Duration>,
+	/// Coalesce writes while the consumer falls behind; `None` (the default) never does
+	///
+	/// Once the event channel is `engage_at` full, file Create/Write events are held and
+	/// coalesced per path as with `stabilize_writes`, so a burst of writes to one file takes
+	/// one slot in the channel instead of many. Once the consumer has drained it to
+	/// `release_at`, the held events are sent and events pass straight through again. Each
+	/// switch is reported as `WatcherDiagnostic::Backpressure`, so the consumer can tell it is
+	/// the bottleneck. The fill level is checked as events are delivered and, while coalescing,
+	/// on the held-write poll, so release does not wait for the next event. Has no effect with
+	/// `stabilize_writes`, which coalesces all the time.
+	pub adaptive_coalescing: Option<AdaptiveCoalescing>,
+	/// Drop events for the watcher's own database file when it lies inside the watched tree.
+	///
+	/// Without this every database write is reported back as a filesystem event, which is
+	/// stored again, and so on. A warning is logged at start either way when the database is
+	/// inside the watched path; moving it out is the better fix.
+	pub ignore_own_database: bool,
+	/// Attach the file's Unix owner and group to each event (`FileSystemEvent::ownership`).
+	///
+	/// Costs one extra `lstat` per event for paths that still exist. Removed paths are looked
+	/// up in the filesystem cache instead, so a file the cache never saw is reported without an
+	/// owner. Off Unix this is accepted but never produces ownership.
+	pub capture_ownership: bool,
+	/// Attach the real path behind symlinks to events (`FileSystemEvent::resolved_path`)
+	///
+	/// Set for events whose path is a symlink or goes through a symlinked directory, such as
+	/// a write through a link to a directory the backend followed. Costs one `canonicalize`
+	/// per event; paths that are gone, including dangling links, get `None`.
+	pub resolve_targets: bool,
+	/// Where the timestamps of live events come from (`FileSystemEvent::timestamp_source`)
+	///
+	/// `ProcessingTime` (the default) stamps each event when the watcher converts it. notify
+	/// carries no operation time, so `OsEvent` takes the kernel's record of the last change
+	/// instead: the path's status-change time (ctime), which creates, writes, renames and
+	/// permission changes set. It is used for those events on Unix while the path still exists;
+	/// removes, snapshot events of `emit_existing_on_start` and everything off Unix keep the
+	/// processing time and say so. Costs one `lstat` per such event.
+	pub timestamp_source: TimestampSource,
+	/// Keep the last this many delivered events in memory for [`WatcherHandle::recent_events`]
+	///
+	/// 0 (the default) disables the buffer. Events are recorded once they are in the channel,
+	/// after `event_types` filtering and write stabilization, so the buffer mirrors what the
+	/// receiver sees; it does not replace reading the receiver.
+	pub recent_events_capacity: usize,
+	/// Report every file and directory already in the watched tree as a synthetic Create at start
+	///
+	/// The tree is walked once the native watch is registered, and the walk also fills the
+	/// filesystem cache, so moves of pre-existing files can be matched. Walked entries carry
+	/// `FileSystemEvent::snapshot` and are all sent before any live event, so a consumer can
+	/// build its baseline and then tail. Live events that arrive while the walk runs are queued
+	/// and reconciled afterwards: a live Create for a path the walk already reported is
+	/// dropped, so each file is reported exactly once, either as pre-existing or as a live
+	/// Create. A file created during the walk may therefore show up on either side. In the
+	/// filesystem cache the live event wins: the dropped Create still updates the node the
+	/// walk stored, after it. With
+	/// `stabilize_writes`, snapshot Creates are held like any other and can be released after
+	/// live events. With this off (the default) nothing is walked and only
+	/// changes after `start()` are reported. The walk runs on the event loop, so on large trees
+	/// live events are delayed until it finishes.
+	pub emit_existing_on_start: bool,
+	/// Glob patterns (`globset` syntax) for paths whose events are dropped
+	///
+	/// Paths inside the watched root are matched relative to it, so `target` or `*.tmp` work
+	/// as expected; a pattern that matches a directory also hides everything below it. Other
+	/// paths (e.g. `WatchTargets::Files` outside `path`) are matched in full. Matching happens in
+	/// the backend callback, before metadata reads and move detection, so an ignored file moved
+	/// into the watched area is reported as a Create. The set can be replaced while running
+	/// with [`WatcherHandle::update_ignore_patterns`]. The reverse, a watched file moved into
+	/// an ignored path (e.g. into `target/`), is covered by `moved_to_ignored`.
+	pub ignore_patterns: Vec<String>,
+	/// Glob patterns (`globset` syntax) for the paths in scope; empty (the default) watches
+	/// the whole tree
+	///
+	/// Shapes the watch rather than carving holes in it: with `*/src/**`, only what is below
+	/// a `src` one level down is reported, not the files and directories beside them.
+	/// Patterns are matched against paths relative to the root, with `*` staying within one
+	/// component; a path is in scope if it or a directory above it matches. The backend only
+	/// watches directories that can lead to a match, non-recursively on the way there, and
+	/// recursively from the first directory in scope down; new directories on the way are
+	/// watched as they appear, without being reported. Scans report only paths in scope. Only
+	/// applies to [`WatchTargets::Tree`]; `validate` rejects it with `Files`. A file moved into
+	/// scope from outside it is reported as a Create, and one moved out as a Remove.
+	pub watch_patterns: Vec<String>,
+	/// What a move from a watched path into an ignored one produces
+	///
+	/// The create half of such a move is dropped with the rest of the ignored path, so there
+	/// is nothing to pair the remove with. By default the source is reported as a Remove,
+	/// which is accurate for the watch but can surprise a consumer expecting a Move.
+	/// [`MovedToIgnoredPolicy::Marker`] reports an [`EventType::MovedToIgnored`] instead, so
+	/// the consumer knows the file still exists out of scope. This needs both paths in one
+	/// backend rename event, which inotify provides; backends that report the two halves
+	/// separately (Windows, FSEvents) give neither a marker nor a Remove, the source simply
+	/// stops being reported.
+	pub moved_to_ignored: MovedToIgnoredPolicy,
+	/// Glob patterns for editor and office sidecar files (swap, lock, backup) whose events are
+	/// dropped; defaults to [`DEFAULT_SIDECAR_PATTERNS`]
+	///
+	/// Matched against the file name alone, wherever the file is, and filtered at the same
+	/// points as `ignore_patterns`, so a swap file created next to a saved document cannot be
+	/// paired with it by move detection. Kept apart from `ignore_patterns` so user patterns
+	/// can be set without restating this list, and not touched by
+	/// [`WatcherHandle::update_ignore_patterns`]. Set to empty to report sidecars like any
+	/// other file. A rename into a sidecar name counts as a move into an ignored path for
+	/// `moved_to_ignored`.
+	pub sidecar_patterns: Vec<String>,
+	/// Log every delivered event in the database so it can be acknowledged and replayed
+	///
+	/// Each logged event gets a per-watch `FileSystemEvent::sequence`. The consumer passes it
+	/// to [`WatcherHandle::ack`] once the event is processed, and after a restart
+	/// [`WatcherHandle::replay_unacked`] returns everything logged but not acknowledged, for
+	/// at-least-once processing. Requires `database_config`, and a fixed `watch_id` so the
+	/// next run finds the same log. Costs one database write per delivered event, on the event
+	/// loop. An event that cannot be logged is still delivered, without a sequence.
+	pub delivery_log: bool,
+	/// Destinations every delivered event is also written to; see [`crate::sink::EventSink`]
+	///
+	/// Written in order after the receiver got the event, so they see exactly what it sees.
+	pub event_sinks: Vec<Arc<dyn crate::sink::EventSink>>,
+	/// How long the watched root may be missing before the watcher gives up on it
+	///
+	/// Deploys often remove and recreate the root. When the backend reports the root itself
+	/// removed or renamed away, the watcher waits this long for a directory to reappear at the
+	/// same path. If one does, it is watched again, its entries are reported as Creates and
+	/// [`WatcherDiagnostic::WatchRootReplaced`] is emitted. Otherwise
+	/// [`WatcherDiagnostic::WatchRootGone`] is emitted and the watcher stops, closing the
+	/// event channel. Only applies to [`WatchTargets::Tree`]. The root is resolved once at
+	/// start, so swapping a symlinked root to another target is not noticed unless the old
+	/// target goes away.
+	pub root_gone_grace: Duration,
+	/// Send a [`WatcherDiagnostic::Summary`] this often, counting the events delivered since
+	/// the previous one
+	///
+	/// For dashboards that would otherwise poll [`WatcherHandle::stats`] and diff the
+	/// counters. Summaries go on the diagnostics channel, so they need
+	/// [`start_with_diagnostics`], and are sent even when nothing happened. The first one also
+	/// counts the `emit_existing_on_start` scan. `None` (the default) sends none.
+	pub summary_interval: Option<Duration>,
+	/// Collapse events for one file reached through several paths (hard links, bind mounts,
+	/// overlay filesystems) that arrive within this window into one event
+	///
+	/// Files are told apart by `(dev, ino)`, read when the event is delivered. The first
+	/// Create, Write, Chmod or `Other` event of a file is held for the window; later ones for
+	/// the same file, under any path, are folded into it, and the single event that comes out
+	/// lists every path in `FileSystemEvent::identity_paths`. Its type is the strongest seen
+	/// (Create, then Write, then the rest). A Remove or move touching a held path releases
+	/// the held event first, so order is kept. Directories, and files gone before they could
+	/// be read, pass through unchanged. Events still held when the watcher stops are
+	/// delivered then. Unix only: elsewhere this is accepted and does nothing. `None` (the
+	/// default) delivers every event as it comes.
+	pub identity_dedup: Option<Duration>,
+	/// Flag events observed this soon after the native watch is registered as
+	/// `FileSystemEvent::startup`
+	///
+	/// A directory that is already busy when the watch starts (an active download folder)
+	/// produces a burst of events for operations the consumer did not cause; flagged events
+	/// can be skipped or handled apart. They are still delivered, and stored in the database
+	/// and filesystem cache, like any other. Events are judged by their timestamp, so one
+	/// observed inside the period and released later by `stabilize_writes` or
+	/// `identity_dedup` is flagged too. `emit_existing_on_start` entries are marked
+	/// `snapshot` instead. `None` (the default) flags nothing.
+	pub startup_quiet_period: Option<Duration>,
+	/// Do not descend into directories on another filesystem than the watched root, such as
+	/// a network share or bind mount below it
+	///
+	/// Such a directory is still reported as an entry of its parent, but nothing below it is
+	/// watched or scanned. The tree is then watched one directory at a time instead of with a
+	/// single recursive watch, which costs a backend watch per directory on inotify; new
+	/// subdirectories are added as they appear. A file moved across the boundary leaves or
+	/// enters the watch, so it is reported as a Remove or Create, not a Move. Only applies to
+	/// [`WatchTargets::Tree`] with a directory at `path`; `validate` rejects it otherwise. Only
+	/// on Unix, where devices are compared by `st_dev`: elsewhere this is accepted and does
+	/// nothing.
+	pub stay_on_device: bool,
+	/// Deliver modification events of directories
+	///
+	/// Adding or removing an entry also modifies its parent directory, and some backends
+	/// (Windows, FSEvents) report that as well as the entry's own event; attribute changes of
+	/// a directory show up the same way. These come out as Write events with
+	/// `is_directory: true`, useful to invalidate a cached directory listing but otherwise
+	/// noise. Off (the default), they are dropped after the path's kind is read, before
+	/// persistence and move detection.
+	pub emit_directory_modify: bool,
+	/// Keep watching what can be watched when the backend's watch limit runs out (e.g.
+	/// `fs.inotify.max_user_watches`) while registering the tree
+	///
+	/// Off (the default), hitting the limit at start stops the watcher. On, the tree is then
+	/// registered again one directory at a time, shallowest first, until the limit is hit; the
+	/// directories left over are reported in one [`WatcherDiagnostic::WatchesDegraded`]
+	/// listing the top of each unwatched subtree, and the watcher runs on without them.
+	/// Changes in those subtrees go unreported: they are not polled instead. New
+	/// subdirectories elsewhere are still added as they appear, while the limit allows.
+	pub degrade_on_watch_limit: bool,
+	/// Normalize the separators of paths from the backend before anything else sees them
+	///
+	/// Repeated and trailing separators are removed and, on Windows, `/` becomes `\`, so a
+	/// path reported as `C:/a/b/` is delivered, cached and paired for moves as `C:\a\b`; see
+	/// [`crate::database::path_utils::normalize_separators`]. On by default; off, paths are
+	/// passed on as the backend reported them. Cache lookups normalize either way.
+	pub normalize_paths: bool,
+}
+
+impl Default for WatcherConfig {
+	fn default() -> Self {
+		Self {
+			watch_id: uuid::Uuid::new_v4(),
+			path: PathBuf::from("."),
+			recursive: true,
+			targets: WatchTargets::Tree,
+			move_detector_config: None,
+			error_recovery_config: None,
+			database_config: None,
+			event_types: None,
+			stabilize_writes: None,
+			adaptive_coalescing: None,
+			ignore_own_database: true,
+			capture_ownership: false,
+			resolve_targets: false,
+			timestamp_source: TimestampSource::ProcessingTime,
+			recent_events_capacity: 0,
+			emit_existing_on_start: false,
+			ignore_patterns: Vec::new(),
+			watch_patterns: Vec::new(),
+			moved_to_ignored: MovedToIgnoredPolicy::Remove,
+			sidecar_patterns: DEFAULT_SIDECAR_PATTERNS.iter().map(|p| p.to_string()).collect(),
+			delivery_log: false,
+			event_sinks: Vec::new(),
+			root_gone_grace: Duration::from_secs(2),
+			summary_interval: None,
+			identity_dedup: None,
+			startup_quiet_period: None,
+			stay_on_device: false,
+			emit_directory_modify: false,
+			degrade_on_watch_limit: false,
+			normalize_paths: true,
+		}
+	}
 }
 
 impl WatcherConfig {
 	/// Validate the watcher configuration
 	pub fn validate(&self) -> Result<()> {
+		IgnoreFilter::compile(&self.ignore_patterns)?;
+		WatchScope::new(&self.path, &self.watch_patterns)?;
+		if !self.watch_patterns.is_empty() && matches!(self.targets, WatchTargets::Files(_)) {
+			return Err(WatcherError::ConfigurationError {
+				parameter: "watch_patterns".to_string(),
+				reason: "Watched files are in scope by definition".to_string(),
+				expected: "WatchTargets::Tree".to_string(),
+				actual: "WatchTargets::Files".to_string(),
+			});
+		}
+		compile_globs("sidecar_patterns", &self.sidecar_patterns)?;
+		if self.delivery_log && self.database_config.is_none() {
+			return Err(WatcherError::ConfigurationError {
+				parameter: "delivery_log".to_string(),
+				reason: "The delivery log is stored in the database".to_string(),
+				expected: "database_config to be set".to_string(),
+				actual: "no database configured".to_string(),
+			});
+		}
+		if self.summary_interval == Some(Duration::ZERO) {
+			return Err(WatcherError::ConfigurationError {
+				parameter: "summary_interval".to_string(),
+				reason: "Summaries would be sent continuously".to_string(),
+				expected: "a positive interval or None".to_string(),
+				actual: "0".to_string(),
+			});
+		}
+		if let Some(adaptive) = &self.adaptive_coalescing {
+			if !(0.0 <= adaptive.release_at
+				&& adaptive.release_at < adaptive.engage_at
+				&& adaptive.engage_at <= 1.0)
+			{
+				return Err(WatcherError::ConfigurationError {
+					parameter: "adaptive_coalescing".to_string(),
+					reason: "Coalescing must release below the level it engages at".to_string(),
+					expected: "0.0 <= release_at < engage_at <= 1.0".to_string(),
+					actual: format!(
+						"release_at {}, engage_at {}",
+						adaptive.release_at, adaptive.engage_at
+					),
+				});
+			}
+		}
+		if let WatchTargets::Files(files) = &self.targets {
+			if files.is_empty() {
+				return Err(WatcherError::ConfigurationError {
+					parameter: "targets".to_string(),
+					reason: "No files to watch".to_string(),
+					expected: "at least one file".to_string(),
+					actual: "empty list".to_string(),
+				});
+			}
+			for file in files {
+				if file.file_name().is_none() || !target_parent(file).is_dir() {
+					return Err(WatcherError::InvalidPath {
+						path: file.to_string_lossy().to_string(),
+					});
+				}
+				if file.is_dir() {
+					return Err(WatcherError::ConfigurationError {
+						parameter: "targets".to_string(),
+						reason: "Only the entry itself would be watched, not its contents"
+							.to_string(),
+						expected: "files, or WatchTargets::Tree to watch a directory".to_string(),
+						actual: format!("directory {}", file.display()),
+					});
+				}
+			}
+			if self.stay_on_device {
+				return Err(WatcherError::ConfigurationError {
+					parameter: "stay_on_device".to_string(),
+					reason: "Listed files are watched without recursion".to_string(),
+					expected: "stay_on_device: false with WatchTargets::Files".to_string(),
+					actual: "true".to_string(),
+				});
+			}
+			return self.validate_move_detector_config();
+		}
+
 		// Check if path exists
 		if !self.path.exists() {
 			return Err(WatcherError::InvalidPath {
@@ -46,6 +562,9 @@ impl WatcherConfig {
 						actual: format!("{actual:?}", actual = metadata.file_type()),
 					});
 				}
+				if metadata.is_file() {
+					self.validate_file_root()?;
+				}
 			}
 			Err(io_err) => match io_err.kind() {
 				std::io::ErrorKind::PermissionDenied => {
@@ -64,7 +583,23 @@ impl WatcherConfig {
 			},
 		}
 
-		// Validate move detector config if present
+		self.validate_move_detector_config()
+	}
+
+	/// Reject `stay_on_device` when `path` is a single file; `recursive` is coerced by `start`
+	fn validate_file_root(&self) -> Result<()> {
+		if !self.stay_on_device {
+			return Ok(());
+		}
+		Err(WatcherError::ConfigurationError {
+			parameter: "stay_on_device".to_string(),
+			reason: "A file has nothing to recurse into".to_string(),
+			expected: "stay_on_device: false, or WatchTargets::Files to watch files".to_string(),
+			actual: format!("path {} is a file", self.path.display()),
+		})
+	}
+
+	fn validate_move_detector_config(&self) -> Result<()> {
 		if let Some(ref move_config) = self.move_detector_config {
 			if let Err(reason) = move_config.validate() {
 				return Err(WatcherError::ConfigurationError {
@@ -89,42 +624,598 @@ impl WatcherConfig {
 		self.database_config = Some(config);
 		self
 	}
+
+	/// Also write every delivered event to `sink`
+	pub fn with_event_sink(mut self, sink: impl crate::sink::EventSink + 'static) -> Self {
+		self.event_sinks.push(Arc::new(sink));
+		self
+	}
+
+	/// Restrict delivered events to the given types
+	pub fn with_event_types(mut self, event_types: impl IntoIterator<Item = EventType>) -> Self {
+		self.event_types = Some(event_types.into_iter().collect());
+		self
+	}
+
+	/// Device of the watched root when recursion stops at it, see `stay_on_device`
+	fn root_device(&self) -> Option<u64> {
+		let applies = self.stay_on_device && self.targets == WatchTargets::Tree;
+		applies.then(|| device_of(&self.path)).flatten()
+	}
+
+	/// Paths handed to the backend, with how each is watched
+	fn watch_registrations(&self, scope: &WatchScope) -> Vec<(PathBuf, RecursiveMode)> {
+		match &self.targets {
+			WatchTargets::Tree => tree_registrations(&self.path, self.root_device(), scope),
+			WatchTargets::Files(files) => {
+				let parents: HashSet<&Path> =
+					files.iter().map(|file| target_parent(file)).collect();
+				parents
+					.into_iter()
+					.map(|parent| (parent.to_path_buf(), RecursiveMode::NonRecursive))
+					.collect()
+			}
+		}
+	}
+
+	/// Event types that must reach the processing pipeline to produce the allowed output.
+	///
+	/// Returns `None` when no allowlist is configured.
+	pub(crate) fn required_input_types(&self) -> Option<HashSet<EventType>> {
+		let allowed = self.event_types.as_ref()?;
+		let mut required = allowed.clone();
+		if allowed.contains(&EventType::Copy) {
+			required.insert(EventType::Create);
+		}
+		if allowed.contains(&EventType::Move) {
+			required.extend([
+				EventType::Create,
+				EventType::Remove,
+				EventType::RenameFrom,
+				EventType::RenameTo,
+				EventType::Rename,
+			]);
+		}
+		if self.recursive {
+			required.insert(EventType::Create);
+		}
+		Some(required)
+	}
 }
 
-#[derive(Debug)]
 pub struct WatcherHandle {
 	stop_sender: oneshot::Sender<()>,
+	task: crate::runtime::JoinHandle<()>,
+	/// Clone of the watcher's database adapter, published once it is initialized
+	database: Arc<std::sync::Mutex<Option<DatabaseAdapter>>>,
+	recent: Arc<RecentEvents>,
+	/// Flips to true once the backend watch is registered and the initial scan is done
+	ready: tokio::sync::watch::Receiver<bool>,
+	ignore: IgnoreFilter,
+	metrics: Arc<WatcherMetrics>,
+	watch_id: uuid::Uuid,
+	/// Present when `WatcherConfig::delivery_log` is enabled
+	acks: Option<AckTracker>,
+	commands: mpsc::Sender<LoopCommand>,
+	/// Present until taken when `MoveDetectorConfig::lifecycle_trace` is enabled
+	lifecycle: std::sync::Mutex<Option<mpsc::Receiver<LifecycleEvent>>>,
+	/// Shared with the event loop
+	known: Arc<std::sync::Mutex<KnownOperations>>,
 }
 
 impl WatcherHandle {
+	/// Wait until the watcher captures filesystem changes.
+	///
+	/// Resolves once the notify backend is watching the root and the `emit_existing_on_start`
+	/// scan, if enabled, has been reported; anything changed after that is guaranteed to produce
+	/// events. Returns [`WatcherError::NotInitialized`] if the watcher gave up before getting
+	/// there (the backend could not be installed, or it was stopped during the scan); the reason
+	/// is logged. Cheap to call again once ready.
+	pub async fn ready(&self) -> Result<()> {
+		let mut ready = self.ready.clone();
+		ready
+			.wait_for(|ready| *ready)
+			.await
+			.map(|_| ())
+			.map_err(|_| WatcherError::NotInitialized)
+	}
+
+	/// Whether the watcher is currently capturing changes: [`WatcherHandle::ready`] has
+	/// resolved and the watcher task has not ended since (e.g. because the receiver was
+	/// dropped). Does not probe the backend; a watch silently lost by the OS is not detected.
+	pub fn healthy(&self) -> bool {
+		crate::metrics::is_healthy(&self.ready)
+	}
+
+	/// Snapshot of the watcher's counters; see [`crate::metrics`]
+	pub fn stats(&self) -> WatcherStats {
+		self.metrics.snapshot(self.healthy())
+	}
+
+	/// Serve `/healthz`, `/metrics` and `/stats` for this watcher over HTTP on `addr`.
+	///
+	/// See [`crate::metrics::http`] for the routes. Binding port 0 picks a free port, available
+	/// from [`MetricsServer::local_addr`](crate::metrics::http::MetricsServer::local_addr).
+	/// The server keeps answering after the watcher stops (reporting it unhealthy) until the
+	/// returned [`MetricsServer`](crate::metrics::http::MetricsServer) is dropped.
+	#[cfg(feature = "http-metrics")]
+	pub async fn serve_metrics(
+		&self, addr: std::net::SocketAddr,
+	) -> Result<crate::metrics::http::MetricsServer> {
+		crate::metrics::http::serve(addr, self.ready.clone(), self.metrics.clone()).await
+	}
+
+	/// Register this watcher's counters in `registry`, to expose them through a server the
+	/// application already runs.
+	///
+	/// See [`crate::metrics::prometheus`] for the metric names; each carries a `watch_id` label.
+	/// Values are read when the registry is gathered, also after the watcher stopped (reporting
+	/// it down). Fails with `AlreadyReg` if a watcher with the same `watch_id` is registered.
+	#[cfg(feature = "prometheus")]
+	pub fn register_prometheus(
+		&self, registry: &::prometheus::Registry,
+	) -> ::prometheus::Result<()> {
+		crate::metrics::prometheus::register(
+			registry,
+			self.watch_id,
+			self.ready.clone(),
+			self.metrics.clone(),
+		)
+	}
+
+	/// The `WatcherConfig::watch_id` this watcher was started with
+	pub fn watch_id(&self) -> uuid::Uuid {
+		self.watch_id
+	}
+
+	/// Replace `WatcherConfig::ignore_patterns` of the running watcher.
+	///
+	/// The new set applies to every event the backend reports from now on, and to events
+	/// already queued but not yet processed. On an invalid pattern the current set is kept
+	/// and [`WatcherError::ConfigurationError`] is returned. Paths that stop being ignored are
+	/// not rescanned: whatever changed under them while they were ignored goes unreported, and
+	/// later events for them (e.g. a Remove of a file never reported as created) refer to
+	/// state the consumer has not seen.
+	pub fn update_ignore_patterns(&self, patterns: Vec<String>) -> Result<()> {
+		self.ignore.replace(IgnoreFilter::compile(&patterns)?);
+		info!("Ignore patterns updated: {:?}", patterns);
+		Ok(())
+	}
+
+	/// The move detector's trace of every event it handles; see
+	/// `MoveDetectorConfig::lifecycle_trace`.
+	///
+	/// `None` when tracing is off, and after the first call: there is one stream per watcher.
+	pub fn lifecycle_events(&self) -> Option<mpsc::Receiver<LifecycleEvent>> {
+		self.lifecycle.lock().unwrap_or_else(|e| e.into_inner()).take()
+	}
+
+	/// The most recently delivered events, oldest first; see
+	/// `WatcherConfig::recent_events_capacity`. Always empty when the buffer is disabled.
+	pub fn recent_events(&self) -> Vec<FileSystemEvent> {
+		self.recent.snapshot()
+	}
+
+	/// Clear the move detector's pending state, as [`MoveDetector::reset`] does.
+	///
+	/// Useful before a bulk operation, or when the consumer knows the event stream lost
+	/// continuity (e.g. after a backend overflow), so stale halves are not paired with new
+	/// events. Events the reset releases, including with `emit_removes` the removes held by
+	/// `defer_removes`, are delivered before this returns. The request is handled between
+	/// events, so it waits for the event being processed. Returns
+	/// [`WatcherError::ChannelSend`] once the watcher has stopped.
+	pub async fn reset_detection(&self, emit_removes: bool) -> Result<()> {
+		let (done, finished) = oneshot::channel();
+		self.commands
+			.send(LoopCommand::ResetDetection { emit_removes, done })
+			.await
+			.map_err(|_| WatcherError::ChannelSend)?;
+		finished.await.map_err(|_| WatcherError::ChannelSend)
+	}
+
+	/// Record the size, inode and Windows ID of `paths` in the move detector now, as
+	/// [`MoveDetector::prewarm`] does.
+	///
+	/// Call it right before moving files that existed before the watcher started: when the
+	/// backend reports such a move as a remove and a create, the remove then carries the
+	/// file's identity and pairs by inode or Windows ID instead of by size and timing alone.
+	/// Returns how many of `paths` could be read; the rest are skipped. Like
+	/// [`Self::reset_detection`], it is handled between events and returns
+	/// [`WatcherError::ChannelSend`] once the watcher has stopped.
+	pub async fn prewarm(&self, paths: &[PathBuf]) -> Result<usize> {
+		let (done, finished) = oneshot::channel();
+		self.commands
+			.send(LoopCommand::Prewarm { paths: paths.to_vec(), done })
+			.await
+			.map_err(|_| WatcherError::ChannelSend)?;
+		finished.await.map_err(|_| WatcherError::ChannelSend)
+	}
+
+	/// Record changes the application made itself in the filesystem cache, and keep the
+	/// backend events they cause from being delivered.
+	///
+	/// Creates are stored with the metadata they carry, removes and moves are applied to the
+	/// cached nodes; nothing is read from disk. For a few seconds afterwards, backend events
+	/// matching an operation by path and type (a Create at a created path; a Remove, rename or
+	/// Create at either end of a move) still update the cache but are neither run through move
+	/// detection nor delivered. Other events for the same paths, such as the Write of the data
+	/// a new file was created with, are delivered as usual. Events the watcher handled before
+	/// this call were delivered already, so calling it right before making the changes is the
+	/// reliable order. Like [`Self::reset_detection`], the cache update is handled between
+	/// events and returns [`WatcherError::ChannelSend`] once the watcher has stopped.
+	pub async fn apply_known_operations(&self, operations: Vec<KnownOperation>) -> Result<()> {
+		let operations: Vec<KnownOperation> =
+			operations.into_iter().map(KnownOperation::canonical).collect();
+		{
+			let mut known = self.known.lock().unwrap_or_else(|e| e.into_inner());
+			for operation in &operations {
+				known.record(operation);
+			}
+		}
+		let events = operations.iter().map(KnownOperation::to_event).collect();
+		let (done, finished) = oneshot::channel();
+		self.commands
+			.send(LoopCommand::ApplyKnown { events, done })
+			.await
+			.map_err(|_| WatcherError::ChannelSend)?;
+		finished.await.map_err(|_| WatcherError::ChannelSend)
+	}
+
+	/// [`Self::apply_known_operations`] for a single operation
+	pub async fn apply_known_operation(&self, operation: KnownOperation) -> Result<()> {
+		self.apply_known_operations(vec![operation]).await
+	}
+
+	/// Acknowledge the event delivered with `sequence` as processed; see
+	/// `WatcherConfig::delivery_log`.
+	///
+	/// Acknowledgements may arrive in any order. The mark persisted in the database only
+	/// advances over a contiguous run of acknowledged sequences, so an event that is never
+	/// acknowledged holds back everything after it; those acknowledged above the gap are kept
+	/// in memory and are replayed again after a restart. Acknowledging a sequence at or below
+	/// the mark does nothing. Returns [`WatcherError::NotInitialized`] before the database is
+	/// open.
+	pub async fn ack(&self, sequence: u64) -> Result<()> {
+		let (acks, database) = self.delivery_database().await?;
+		let mut state = acks.state.lock().await;
+		let state = match &mut *state {
+			Some(state) => state,
+			None => state.insert(AckState {
+				acknowledged: DeliveryLog::acknowledged(&database, &acks.watch_id)?,
+				ahead: BTreeSet::new(),
+			}),
+		};
+		if state.acknowledged.is_some_and(|acknowledged| sequence <= acknowledged) {
+			return Ok(());
+		}
+		state.ahead.insert(sequence);
+		let mut through = state.acknowledged;
+		loop {
+			let next = through.map_or(0, |through| through.saturating_add(1));
+			if !state.ahead.remove(&next) {
+				break;
+			}
+			through = Some(next);
+		}
+		if let Some(through) = through.filter(|_| through != state.acknowledged) {
+			DeliveryLog::acknowledge(&database, &acks.watch_id, through)?;
+			state.acknowledged = Some(through);
+		}
+		Ok(())
+	}
+
+	/// Events logged for this watch but not acknowledged, oldest first, each with its
+	/// `sequence`; see `WatcherConfig::delivery_log`.
+	///
+	/// Meant to be called once after a restart, before reading the receiver, to process again
+	/// what the previous run delivered but never saw acknowledged. Events delivered by this run
+	/// and not acknowledged yet are included too, as are ones acknowledged above a gap.
+	pub async fn replay_unacked(&self) -> Result<Vec<FileSystemEvent>> {
+		let (acks, database) = self.delivery_database().await?;
+		Ok(DeliveryLog::unacknowledged(&database, &acks.watch_id)?)
+	}
+
+	async fn delivery_database(&self) -> Result<(&AckTracker, Arc<redb::Database>)> {
+		let acks = self.acks.as_ref().ok_or_else(|| WatcherError::ConfigurationError {
+			parameter: "delivery_log".to_string(),
+			reason: "Events are only acknowledged with the delivery log enabled".to_string(),
+			expected: "true".to_string(),
+			actual: "false".to_string(),
+		})?;
+		let adapter = self.database.lock().unwrap_or_else(|e| e.into_inner()).clone();
+		let Some(adapter) = adapter else {
+			return Err(WatcherError::NotInitialized);
+		};
+		let database = adapter.get_raw_database().await.ok_or(WatcherError::NotInitialized)?;
+		Ok((acks, database))
+	}
+
+	/// Signal the watcher to stop and wait for its task to finish, then close the database.
+	///
+	/// The stop signal is only seen between events, so this waits for the event currently being
+	/// processed. That can be unbounded (e.g. a content-hash read on a hung network mount); use
+	/// [`WatcherHandle::stop_with_timeout`] when shutdown has a deadline.
 	pub async fn stop(self) -> Result<()> {
-		self.stop_sender.send(()).map_err(|_| WatcherError::StopSignal)
+		let signalled = self.stop_sender.send(()).is_ok();
+		let _ = self.task.join().await;
+		close_shared_database(&self.database, None).await;
+		if signalled {
+			Ok(())
+		} else {
+			Err(WatcherError::StopSignal)
+		}
+	}
+
+	/// Like [`WatcherHandle::stop`], but abort the watcher task if it has not finished within
+	/// `timeout` and return [`WatcherError::ShutdownTimeout`].
+	///
+	/// The database is closed either way, within what is left of `timeout`; a close that does
+	/// not finish in time (e.g. behind a database write that is stuck) is abandoned and also
+	/// reported as [`WatcherError::ShutdownTimeout`]. What a forced abort leaves behind:
+	/// - Events already acknowledged by the database are durable (each write commits on its
+	///   own); a write that was in flight is rolled back by redb, never half-applied.
+	/// - Pending move-detection state and writes held by `stabilize_writes` are dropped, and
+	///   the filesystem cache may not reflect the last events delivered to the receiver.
+	/// - The receiver sees the channel close without a final event.
+	/// - Abort takes effect at the task's next await point. A task stuck inside a blocking call
+	///   keeps running (and keeps its database handle and the redb file lock) until that call
+	///   returns, so reopening the same database in-process can still fail for a while.
+	pub async fn stop_with_timeout(self, timeout: Duration) -> Result<()> {
+		let deadline = Instant::now() + timeout;
+		let signalled = self.stop_sender.send(()).is_ok();
+		let result = match self.task.join_timeout(timeout).await {
+			Ok(_) if signalled => Ok(()),
+			Ok(_) => Err(WatcherError::StopSignal),
+			Err(task) => {
+				warn!(
+					"Watcher did not stop within {:?}, aborting its task",
+					timeout
+				);
+				// Not joined: a task wedged in blocking code would hang the join just the same
+				task.abort();
+				Err(WatcherError::ShutdownTimeout { timeout })
+			}
+		};
+		if !close_shared_database(&self.database, Some(deadline)).await && result.is_ok() {
+			return Err(WatcherError::ShutdownTimeout { timeout });
+		}
+		result
+	}
+}
+
+impl std::fmt::Debug for WatcherHandle {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("WatcherHandle").finish_non_exhaustive()
+	}
+}
+
+/// Close the database shared with the watcher task; false if `deadline` passed first, in which
+/// case the file stays open until the last handle to it is dropped
+async fn close_shared_database(
+	database: &std::sync::Mutex<Option<DatabaseAdapter>>, deadline: Option<Instant>,
+) -> bool {
+	let adapter = database.lock().unwrap_or_else(|e| e.into_inner()).take();
+	let Some(adapter) = adapter else {
+		return true;
+	};
+	let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+	tokio::select! {
+		closed = adapter.close() => {
+			if let Err(e) = closed {
+				warn!("Failed to close watcher database: {}", e);
+			}
+			true
+		}
+		_ = crate::runtime::sleep(remaining.unwrap_or_default()), if remaining.is_some() => {
+			warn!("Watcher database did not close before the stop deadline, abandoning the close");
+			false
+		}
 	}
 }
 
 pub fn start(config: WatcherConfig) -> Result<(WatcherHandle, mpsc::Receiver<FileSystemEvent>)> {
+	start_inner(config, None)
+}
+
+/// Like [`start`], and also return a channel of [`WatcherDiagnostic`]s.
+///
+/// Diagnostics report problems that are otherwise only logged: events left out of move
+/// detection, backend overflows, unreadable entries during scans, database failures and OS
+/// limits. See `diagnostics` for the delivery guarantees; dropping the receiver is fine.
+pub fn start_with_diagnostics(
+	config: WatcherConfig,
+) -> Result<(
+	WatcherHandle,
+	mpsc::Receiver<FileSystemEvent>,
+	mpsc::Receiver<WatcherDiagnostic>,
+)> {
+	let (diagnostics, diagnostics_rx) = DiagnosticsSender::channel();
+	let (handle, event_rx) = start_inner(config, Some(diagnostics))?;
+	Ok((handle, event_rx, diagnostics_rx))
+}
+
+fn start_inner(
+	mut config: WatcherConfig, diagnostics: Option<DiagnosticsSender>,
+) -> Result<(WatcherHandle, mpsc::Receiver<FileSystemEvent>)> {
 	// Validate configuration first
 	config.validate()?;
+	if config.recursive && config.targets == WatchTargets::Tree && config.path.is_file() {
+		warn!(
+			"{} is a file, so it is watched without recursion",
+			config.path.display()
+		);
+		config.recursive = false;
+	}
+	config.path = canonical_root(&config.path)?;
+	if let WatchTargets::Files(files) = &mut config.targets {
+		for file in files.iter_mut() {
+			// The file itself may not exist yet; its parent does (checked by validate)
+			let name = file.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+			*file = canonical_root(target_parent(file))?.join(name);
+		}
+	}
 
 	let (event_tx, event_rx) = mpsc::channel(100);
 	let (stop_tx, stop_rx) = oneshot::channel();
+	let (commands, commands_rx) = mpsc::channel(8);
 
-	let handle = WatcherHandle { stop_sender: stop_tx };
-
-	tokio::spawn(run_watcher(config, event_tx, stop_rx));
+	let shared_database = Arc::new(std::sync::Mutex::new(None));
+	let recent = Arc::new(RecentEvents::new(config.recent_events_capacity));
+	let (ready_tx, ready) = tokio::sync::watch::channel(false);
+	let ignore = IgnoreFilter::new(
+		&config.path,
+		IgnoreFilter::compile(&config.ignore_patterns)?,
+		compile_globs("sidecar_patterns", &config.sidecar_patterns)?,
+	);
+	let metrics = Arc::new(WatcherMetrics::default());
+	let watch_id = config.watch_id;
+	let acks = config
+		.delivery_log
+		.then(|| AckTracker { watch_id: config.watch_id, state: tokio::sync::Mutex::new(None) });
+	let tracing_lifecycle = config
+		.move_detector_config
+		.as_ref()
+		.is_some_and(|config| config.lifecycle_trace);
+	let (lifecycle_tx, lifecycle_rx) = tracing_lifecycle.then(LifecycleSender::channel).unzip();
+	let known = Arc::new(std::sync::Mutex::new(KnownOperations::default()));
+	let link = HandleLink {
+		database: shared_database.clone(),
+		recent: recent.clone(),
+		ready: ready_tx,
+		ignore: ignore.clone(),
+		metrics: metrics.clone(),
+		commands: commands_rx,
+		lifecycle: lifecycle_tx,
+		known: known.clone(),
+	};
+	let task = crate::runtime::spawn(run_watcher(config, event_tx, stop_rx, link, diagnostics));
+	let handle = WatcherHandle {
+		stop_sender: stop_tx,
+		task,
+		database: shared_database,
+		recent,
+		ready,
+		ignore,
+		metrics,
+		watch_id,
+		acks,
+		commands,
+		lifecycle: std::sync::Mutex::new(lifecycle_rx),
+		known,
+	};
 
 	Ok((handle, event_rx))
 }
 
+/// Directory holding a watched file; a bare file name lives in the current directory
+fn target_parent(file: &Path) -> &Path {
+	match file.parent() {
+		Some(parent) if !parent.as_os_str().is_empty() => parent,
+		_ => Path::new("."),
+	}
+}
+
+/// `path` with its parent canonicalized, as event paths are; as given if the parent cannot be
+/// resolved
+fn canonical_path(path: &Path) -> PathBuf {
+	match (canonical_root(target_parent(path)), path.file_name()) {
+		(Ok(parent), Some(name)) => parent.join(name),
+		_ => path.to_path_buf(),
+	}
+}
+
+/// Resolve the watch root once so every event path shares a single absolute form.
+///
+/// Backends join their relative names onto the path they were registered with, so
+/// canonicalizing here is enough to keep `./watched/x` and `/abs/watched/x` from both showing
+/// up. On Windows the `\\?\` prefix `canonicalize` adds to drive paths is dropped again, as
+/// few consumers expect verbatim paths; UNC roots keep theirs.
+fn canonical_root(path: &Path) -> Result<PathBuf> {
+	let canonical = path.canonicalize().map_err(|e| {
+		WatcherError::filesystem_error_with_path(
+			"canonicalize watch root",
+			&path.to_string_lossy(),
+			&e.to_string(),
+			e.raw_os_error(),
+		)
+	})?;
+	#[cfg(windows)]
+	{
+		// Non-Unicode roots keep the verbatim form rather than risk a lossy conversion
+		if let Some(stripped) = canonical.to_str().and_then(|text| text.strip_prefix(r"\\?\")) {
+			if !stripped.starts_with(r"UNC\") {
+				return Ok(PathBuf::from(stripped));
+			}
+		}
+	}
+	Ok(canonical)
+}
+
+/// State the watcher task shares with its [`WatcherHandle`]
+struct HandleLink {
+	/// Filled in once the database adapter is initialized
+	database: Arc<std::sync::Mutex<Option<DatabaseAdapter>>>,
+	recent: Arc<RecentEvents>,
+	ready: tokio::sync::watch::Sender<bool>,
+	ignore: IgnoreFilter,
+	metrics: Arc<WatcherMetrics>,
+	commands: mpsc::Receiver<LoopCommand>,
+	lifecycle: Option<LifecycleSender>,
+	known: Arc<std::sync::Mutex<KnownOperations>>,
+}
+
+/// Requests from the handle, handled by the event loop between events
+enum LoopCommand {
+	ResetDetection {
+		emit_removes: bool,
+		done: oneshot::Sender<()>,
+	},
+	Prewarm {
+		paths: Vec<PathBuf>,
+		done: oneshot::Sender<usize>,
+	},
+	/// Cache updates for `WatcherHandle::apply_known_operations`
+	ApplyKnown {
+		events: Vec<FileSystemEvent>,
+		done: oneshot::Sender<()>,
+	},
+}
+
+/// The handle's side of `WatcherConfig::delivery_log`
+struct AckTracker {
+	watch_id: uuid::Uuid,
+	/// Loaded from the database on the first acknowledgement
+	state: tokio::sync::Mutex<Option<AckState>>,
+}
+
+struct AckState {
+	/// Persisted high-water mark
+	acknowledged: Option<u64>,
+	/// Acknowledged sequences above a gap, folded into the mark once the gap is filled
+	ahead: BTreeSet<u64>,
+}
+
 async fn run_watcher(
 	config: WatcherConfig, event_tx: mpsc::Sender<FileSystemEvent>,
-	mut stop_rx: oneshot::Receiver<()>,
+	mut stop_rx: oneshot::Receiver<()>, link: HandleLink, diagnostics: Option<DiagnosticsSender>,
 ) {
+	let HandleLink {
+		database: shared_database,
+		recent,
+		ready,
+		ignore,
+		metrics,
+		mut commands,
+		lifecycle,
+		known,
+	} = link;
 	// Initialize database adapter if configured
 	let database = if let Some(db_config) = config.database_config.clone() {
 		match DatabaseAdapter::new(db_config).await {
 			Ok(adapter) => {
 				info!("Database adapter initialized successfully");
+				// Lets the handle close the database even if this task has to be aborted
+				*shared_database.lock().unwrap_or_else(|e| e.into_inner()) = Some(adapter.clone());
 				adapter
 			}
 			Err(e) => {
@@ -132,6 +1223,13 @@ async fn run_watcher(
 					"Failed to initialize database, continuing without persistence: {}",
 					e
 				);
+				metrics.record_persistence_error();
+				crate::diagnostics::emit(
+					diagnostics.as_ref(),
+					WatcherDiagnostic::PersistenceDegraded {
+						reason: format!("database could not be opened: {e}"),
+					},
+				);
 				DatabaseAdapter::disabled()
 			}
 		}
@@ -139,6 +1237,12 @@ async fn run_watcher(
 		DatabaseAdapter::disabled()
 	};
 
+	let delivery = if config.delivery_log {
+		DeliverySequencer::open(&database, config.watch_id).await
+	} else {
+		None
+	};
+
 	// Initialize persistent filesystem cache
 	let mut _dummy_tempdir = None;
 	let fs_cache = if let Some(cache) = database.get_filesystem_cache().await {
@@ -154,16 +1258,61 @@ async fn run_watcher(
 		cache
 	};
 
-	// Use a concrete type for the cache, not a trait object
-	let fs_cache = Arc::new(tokio::sync::Mutex::new(fs_cache));
+	// The synchronizer gets its own handle on the same database. The move detector borrows its
+	// cache mutably for the whole loop, so sharing one mutex-guarded instance deadlocked the
+	// loop on the first cache update. Clones keep the configured batching and durability.
+	let sync_cache = fs_cache.clone();
+	let ownership = config
+		.capture_ownership
+		.then(|| OwnershipCapture { cache: tokio::sync::Mutex::new(fs_cache.clone()) });
+	let mut detector_cache = fs_cache;
+	let input_filter = config.required_input_types();
+	let scope = match WatchScope::new(&config.path, &config.watch_patterns) {
+		Ok(scope) => scope,
+		Err(e) => {
+			error!("Invalid watch patterns: {}", e);
+			return;
+		}
+	};
+	let registrations = config.watch_registrations(&scope);
+	let root_device = config.root_device();
+	let own_database = config
+		.database_config
+		.as_ref()
+		.and_then(|db_config| OwnDatabaseFilter::new(&config.path, &db_config.database_path));
+	if let Some(own_database) = &own_database {
+		warn!(
+			"Database {:?} is inside the watched path {:?}; {}",
+			own_database.file_name,
+			config.path,
+			if config.ignore_own_database {
+				"its events are ignored"
+			} else {
+				"its writes will be reported as events"
+			}
+		);
+	}
+	let own_database = own_database.filter(|_| config.ignore_own_database);
 	let move_detector_config = config.move_detector_config.unwrap_or_default();
-	// Avoid temporary value drop by creating a binding for the lock guard
-	let mut fs_cache_guard = fs_cache.lock().await;
-	let mut move_detector = MoveDetector::new(move_detector_config, &mut *fs_cache_guard);
+	let mut move_detector = MoveDetector::new(move_detector_config, &mut detector_cache);
+	if let Some(diagnostics) = &diagnostics {
+		move_detector = move_detector.with_diagnostics(diagnostics.clone());
+	}
+	if let Some(lifecycle) = lifecycle {
+		move_detector = move_detector.with_lifecycle(lifecycle);
+	}
 	let cache_sync = Arc::new(tokio::sync::Mutex::new(
-		DefaultFilesystemCacheSynchronizer { cache: fs_cache.clone() },
+		DefaultFilesystemCacheSynchronizer {
+			cache: Arc::new(tokio::sync::Mutex::new(sync_cache)),
+			write_retry: config
+				.database_config
+				.as_ref()
+				.map(|db_config| db_config.write_retry.clone()),
+			diagnostics: diagnostics.clone(),
+		},
 	));
 
+	let mirror_retry = config.error_recovery_config.clone().map(RetryManager::new);
 	// Initialize retry manager
 	let retry_config = config.error_recovery_config.unwrap_or_default();
 	let retry_manager = RetryManager::new(retry_config); // Initialize watcher with retry logic
@@ -186,13 +1335,60 @@ async fn run_watcher(
 
 	let (raw_event_tx, mut raw_event_rx) = mpsc::channel(100);
 	let (notify_tx, notify_rx) = std::sync::mpsc::channel(); // Set up the watcher callback with direct error handling for now
-	if let Err(e) = setup_watcher_callback(&mut watcher, &config.path, notify_tx.clone()).await {
+	let only_paths = match &config.targets {
+		WatchTargets::Tree => None,
+		WatchTargets::Files(files) if config.normalize_paths => {
+			Some(files.iter().map(|file| normalize_separators(file)).collect())
+		}
+		WatchTargets::Files(files) => Some(files.iter().cloned().collect()),
+	};
+	let filters = CallbackFilters {
+		input_types: input_filter,
+		own_database: own_database.clone(),
+		only_paths,
+		ignore: ignore.clone(),
+		moved_to_ignored: config.moved_to_ignored,
+		normalize_paths: config.normalize_paths,
+		scope: scope.clone(),
+	};
+	if let Err(shortfall) = crate::fd_budget::ensure(
+		&registrations,
+		<RecommendedWatcher as notify::Watcher>::kind(),
+	) {
+		error!(
+			"Not enough file descriptors to watch {:?}: {}",
+			config.path, shortfall.error
+		);
+		crate::diagnostics::emit(
+			diagnostics.as_ref(),
+			WatcherDiagnostic::DescriptorBudgetExceeded {
+				path: config.path.clone(),
+				needed: shortfall.needed,
+				limit: shortfall.limit,
+			},
+		);
+		return;
+	}
+	let root_removed = Arc::new(tokio::sync::Notify::new());
+	let root_signal = (config.targets == WatchTargets::Tree)
+		.then(|| RootSignal { root: config.path.clone(), removed: root_removed.clone() });
+	if let Err(e) = setup_watcher_callback(
+		&mut watcher,
+		&registrations,
+		notify_tx.clone(),
+		filters,
+		root_signal,
+		config.degrade_on_watch_limit,
+		diagnostics.clone(),
+	)
+	.await
+	{
 		error!("Failed to setup watcher callback: {}", e);
 		return;
 	}
 
 	// Spawn blocking task to bridge sync notify channel to async
-	let _blocking_task = tokio::task::spawn_blocking(move || {
+	let _blocking_task = crate::runtime::spawn_blocking(move || {
 		for event in notify_rx {
 			if raw_event_tx.blocking_send(event).is_err() {
 				debug!("Event receiver dropped, stopping notify thread.");
@@ -201,6 +1397,107 @@ async fn run_watcher(
 		}
 	});
 
+	let mut catch_up = SubdirectoryCatchUp::new();
+	catch_up.root_device = root_device;
+	let mut sink = LoopSink {
+		tx: event_tx,
+		allowed: config.event_types.clone(),
+		stabilizer: config.stabilize_writes.map(WriteStabilizer::new),
+		adaptive_coalescing: config
+			.adaptive_coalescing
+			.filter(|_| config.stabilize_writes.is_none()),
+		coalescing: false,
+		identity_dedup: config.identity_dedup.map(IdentityDedup::new),
+		recent,
+		diagnostics,
+		ignore,
+		scope,
+		metrics,
+		delivery,
+		mirrors: config.event_sinks,
+		mirror_retry,
+		quiet_until: config
+			.startup_quiet_period
+			.and_then(|period| chrono::Duration::from_std(period).ok())
+			.map(|period| Utc::now() + period),
+		persistence_suspended: false,
+		emit_directory_modify: config.emit_directory_modify,
+		resolve_targets: config.resolve_targets,
+		timestamp_source: config.timestamp_source,
+	};
+	let mut summary = config
+		.summary_interval
+		.filter(|_| sink.diagnostics.is_some())
+		.map(SummaryTimer::new);
+	let identity_poll = config
+		.identity_dedup
+		.map(|window| (window / 4).max(Duration::from_millis(10)))
+		.unwrap_or(Duration::from_secs(3600));
+
+	if config.emit_existing_on_start {
+		// The watch is already registered, so anything created from here on is queued in
+		// `raw_event_rx` and reconciled against the walk through `catch_up`
+		let walk: Vec<_> = match &config.targets {
+			WatchTargets::Tree => {
+				let mut walk = walkdir::WalkDir::new(&config.path).min_depth(1).follow_links(false);
+				if !config.recursive {
+					walk = walk.max_depth(1);
+				}
+				walk_on_device(walk, root_device, device_of)
+			}
+			// Missing files are simply not there yet, not something the scan skipped
+			WatchTargets::Files(files) => files
+				.iter()
+				.filter(|file| file.symlink_metadata().is_ok())
+				.flat_map(|file| walkdir::WalkDir::new(file).max_depth(0).follow_links(false))
+				.collect(),
+		};
+		let scanned = async {
+			let processed = report_existing(
+				walk,
+				ScanKind::Initial { own_database: own_database.as_ref() },
+				&mut move_detector,
+				&database,
+				&mut sink,
+				&mut catch_up,
+				ownership.as_ref(),
+			)
+			.await?;
+			let mut cache_sync_guard = cache_sync.lock().await;
+			for fs_event in &processed {
+				cache_sync_guard.handle_event(&config.watch_id, fs_event).await;
+			}
+			Ok::<_, WatcherError>(processed.len())
+		};
+		tokio::select! {
+			_ = &mut stop_rx => {
+				info!("Watcher shutdown requested during initial scan, stopping.");
+				return;
+			}
+			result = scanned => match result {
+				Ok(count) => {
+					// Queued live events may be processed well after their path was walked
+					catch_up.refresh_scanned();
+					info!("Initial scan reported {} existing entries", count);
+				}
+				Err(e) => {
+					warn!("Initial scan failed: {}", e);
+					if matches!(e, WatcherError::ChannelSend) {
+						return;
+					}
+				}
+			}
+		}
+	}
+
+	// Dropping `ready` on any early return above tells `WatcherHandle::ready` to give up
+	ready.send_replace(true);
+	// Set while the root is gone and `root_gone_grace` is running
+	let mut root_missing: Option<Instant> = None;
+	let mut stabilizer_poll = PollDeadline::new();
+	let mut identity_poll_due = PollDeadline::new();
+	let mut root_poll = PollDeadline::new();
+
 	// Main event processing loop with error recovery
 	loop {
 		tokio::select! {
@@ -209,22 +1506,172 @@ async fn run_watcher(
 				break;
 			}
 			Some(event) = raw_event_rx.recv() => {
-				let processed = match process_single_event(
-					&event,
-					&mut move_detector,
-					&database,
-					&event_tx,
-				).await {
-					Ok(events) => events,
-					Err(e) => {
-						warn!("Failed to process filesystem event: {} - Event: {:?}", e, event);
-						Vec::new()
+				let handled = async {
+					let mut processed = match process_single_event(
+						&event,
+						&mut move_detector,
+						&database,
+						&mut sink,
+						&mut catch_up,
+						ownership.as_ref(),
+						&known,
+					).await {
+						Ok(events) => events,
+						Err(e) => {
+							warn!("Failed to process filesystem event: {} - Event: {:?}", e, event);
+							Vec::new()
+						}
+					};
+					if config.recursive && config.targets == WatchTargets::Tree {
+						// A directory moved within the tree was reported already, and so was
+						// everything in it; it only needs watching at its new place
+						let moved_dirs: Vec<PathBuf> = processed
+							.iter()
+							.filter(|e| e.is_directory && e.event_type == EventType::Move)
+							.map(|e| e.path.clone())
+							.collect();
+						for dir in moved_dirs {
+							watch_subdirectory(&mut watcher, &dir, &mut sink, &mut catch_up);
+						}
+						let new_dirs: Vec<PathBuf> = processed
+							.iter()
+							.filter(|e| e.is_directory && e.event_type == EventType::Create)
+							.map(|e| e.path.clone())
+							.chain(catch_up.take_route_dirs())
+							.collect();
+						for dir in new_dirs {
+							match watch_new_subdirectory(
+								&mut watcher,
+								&dir,
+								&mut move_detector,
+								&database,
+								&mut sink,
+								&mut catch_up,
+								ownership.as_ref(),
+							).await {
+								Ok(events) => processed.extend(events),
+								Err(e) => warn!("Catch-up scan of new directory {:?} failed: {}", dir, e),
+							}
+						}
+					}
+					// Synchronize cache for each processed event, then let live creates the
+					// scans already reported win over the scanned nodes
+					let mut cache_sync_guard = cache_sync.lock().await;
+					for fs_event in processed.iter().chain(&catch_up.take_superseded()) {
+						cache_sync_guard.handle_event(&config.watch_id, fs_event).await;
 					}
 				};
-				// Synchronize cache for each processed event
-				let mut cache_sync_guard = cache_sync.lock().await;
-				for fs_event in &processed {
-					cache_sync_guard.handle_event(&config.watch_id, fs_event).await;
+				// Stop must get through even while processing is parked, e.g. on a full event
+				// channel whose consumer stopped reading to call `stop`
+				tokio::select! {
+					_ = &mut stop_rx => {
+						info!("Watcher shutdown requested during event processing, stopping.");
+						break;
+					}
+					_ = handled => {}
+				}
+			}
+			_ = crate::runtime::sleep(crate::move_detection::detector::EXCHANGE_WINDOW),
+				if move_detector.has_held_events() || move_detector.has_deferred_removes() => {
+				let mut released = move_detector.take_expired_held();
+				released.extend(move_detector.take_expired_removes().await);
+				if !deliver_released(released, &mut sink, &cache_sync, &config.watch_id).await {
+					break;
+				}
+			}
+			Some(command) = commands.recv() => match command {
+				LoopCommand::ResetDetection { emit_removes, done } => {
+					info!("Resetting move detection state");
+					let released = move_detector.reset(emit_removes);
+					let delivered =
+						deliver_released(released, &mut sink, &cache_sync, &config.watch_id).await;
+					let _ = done.send(());
+					if !delivered {
+						break;
+					}
+				}
+				LoopCommand::Prewarm { paths, done } => {
+					let _ = done.send(move_detector.prewarm(&paths).await);
+				}
+				LoopCommand::ApplyKnown { events, done } => {
+					let mut cache_sync_guard = cache_sync.lock().await;
+					for fs_event in &events {
+						cache_sync_guard.handle_event(&config.watch_id, fs_event).await;
+					}
+					let _ = done.send(());
+				}
+			},
+			_ = crate::runtime::sleep(stabilizer_poll.until_due()),
+				if sink.has_held_events() || sink.coalescing => {
+				stabilizer_poll.restart(sink.stabilizer_poll());
+				// A consumer catching up while no new events arrive still releases coalescing
+				if sink.adapt_to_backpressure().await.is_err() || sink.flush_stable().await.is_err() {
+					break;
+				}
+			}
+			_ = crate::runtime::sleep(identity_poll_due.until_due()), if sink.has_identity_groups() => {
+				identity_poll_due.restart(identity_poll);
+				if sink.flush_identities().await.is_err() {
+					break;
+				}
+			}
+			_ = crate::runtime::sleep(summary.as_ref().map(SummaryTimer::until_due).unwrap_or_default()),
+				if summary.is_some() => {
+				if let Some(summary) = &mut summary {
+					sink.diagnose(WatcherDiagnostic::Summary(summary.take(&sink.metrics)));
+				}
+			}
+			// Whatever is at the root path now, if anything, is not what the backend watched
+			_ = root_removed.notified(), if root_missing.is_none() => {
+				warn!(
+					"Watch root {:?} was removed, waiting up to {:?} for it to reappear",
+					config.path, config.root_gone_grace
+				);
+				root_missing = Some(Instant::now());
+			}
+			_ = crate::runtime::sleep(root_poll.until_due()), if root_missing.is_some() => {
+				root_poll.restart(ROOT_POLL_INTERVAL);
+				if config.path.is_dir() {
+					root_missing = None;
+					let walk = rewatch_root(
+						&mut watcher,
+						&config.path,
+						config.recursive,
+						config.stay_on_device,
+						&sink.scope,
+						&mut catch_up,
+					);
+					let scanned = match walk {
+						Ok(walk) => report_existing(
+							walk,
+							ScanKind::NewDirectory,
+							&mut move_detector,
+							&database,
+							&mut sink,
+							&mut catch_up,
+							ownership.as_ref(),
+						).await,
+						Err(e) => Err(e),
+					};
+					let processed = match scanned {
+						Ok(processed) => processed,
+						Err(WatcherError::ChannelSend) => break,
+						Err(e) => {
+							warn!("Could not watch the replaced root {:?}: {}", config.path, e);
+							root_missing = Some(Instant::now());
+							continue;
+						}
+					};
+					let mut cache_sync_guard = cache_sync.lock().await;
+					for fs_event in &processed {
+						cache_sync_guard.handle_event(&config.watch_id, fs_event).await;
+					}
+					info!("Watch root {:?} reappeared, watching it again", config.path);
+					sink.diagnose(WatcherDiagnostic::WatchRootReplaced { path: config.path.clone() });
+				} else if root_missing.is_some_and(|since| since.elapsed() >= config.root_gone_grace) {
+					error!("Watch root {:?} is gone, stopping", config.path);
+					sink.diagnose(WatcherDiagnostic::WatchRootGone { path: config.path.clone() });
+					break;
 				}
 			}
 			else => {
@@ -233,6 +1680,19 @@ async fn run_watcher(
 			}
 		}
 	}
+	// Removes held for pairing are delivered as plain Removes rather than lost. Like
+	// `flush_held_on_stop`, this does not wait on a full channel.
+	let mut released = move_detector.reset(true);
+	let fits = sink.tx.capacity();
+	if released.len() > fits {
+		warn!(
+			"Event channel full at shutdown; dropping {} held removes",
+			released.len() - fits
+		);
+		released.truncate(fits);
+	}
+	deliver_released(released, &mut sink, &cache_sync, &config.watch_id).await;
+	sink.flush_held_on_stop().await;
 	info!("Watcher event loop finished. Channel will be closed.");
 }
 
@@ -269,90 +1729,1584 @@ async fn create_filesystem_watcher(path: &std::path::Path) -> Result<Recommended
 	Ok(watcher)
 }
 
-/// Setup watcher callback and start watching
-async fn setup_watcher_callback(
-	watcher: &mut RecommendedWatcher, path: &std::path::Path,
-	notify_tx: std::sync::mpsc::Sender<notify::Event>,
-) -> Result<()> {
-	// Replace the watcher callback
-	*watcher = RecommendedWatcher::new(
-		move |res| {
-			if let Ok(event) = res {
-				if let Err(e) = notify_tx.send(event) {
-					error!("Error sending notify event: {}", e);
-				}
+/// Matches the watcher's own database file and any sidecar named after it.
+///
+/// redb 2.x keeps everything in the one file and locks it in place, so today that is the whole
+/// set; names starting with the database file name (`watcher.redb-journal`, `watcher.redb.lock`)
+/// are matched too so a storage change that adds sidecars does not reintroduce the loop.
+#[derive(Clone)]
+struct OwnDatabaseFilter {
+	dir: PathBuf,
+	file_name: std::ffi::OsString,
+}
+
+impl OwnDatabaseFilter {
+	/// `None` unless the database lies under `watch_root`, which must already be canonical.
+	fn new(watch_root: &Path, database_path: &Path) -> Option<Self> {
+		let file_name = database_path.file_name()?.to_os_string();
+		let parent = match database_path.parent() {
+			Some(parent) if !parent.as_os_str().is_empty() => parent,
+			_ => Path::new("."),
+		};
+		let dir = canonical_root(parent).ok()?;
+		dir.starts_with(watch_root).then_some(Self { dir, file_name })
+	}
+
+	fn matches(&self, path: &Path) -> bool {
+		let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+			return false;
+		};
+		parent == self.dir && name.as_encoded_bytes().starts_with(self.file_name.as_encoded_bytes())
+	}
+}
+
+/// File names of editor and office sidecars dropped by default; see
+/// `WatcherConfig::sidecar_patterns`
+pub const DEFAULT_SIDECAR_PATTERNS: &[&str] = &[
+	// Microsoft Office owner files (`~$report.docx`)
+	"~$*",
+	// LibreOffice lock files (`.~lock.report.odt#`)
+	".~lock.*#",
+	// Vim swap files (`.notes.txt.swp`, `.swo`, ...) and its write-permission probe
+	".*.sw[a-p]",
+	"4913",
+	// Emacs lock links (`.#notes.txt`) and auto-save files (`#notes.txt#`)
+	".#*",
+	"#*#",
+	// Backups left by Vim, Emacs, nano and others (`notes.txt~`)
+	"*~",
+];
+
+/// Compile the patterns of the `parameter` setting
+fn compile_globs(parameter: &str, patterns: &[String]) -> Result<GlobSet> {
+	let mut builder = GlobSetBuilder::new();
+	for pattern in patterns {
+		let glob = Glob::new(pattern).map_err(|e| WatcherError::ConfigurationError {
+			parameter: parameter.to_string(),
+			reason: e.to_string(),
+			expected: "valid glob pattern".to_string(),
+			actual: pattern.clone(),
+		})?;
+		builder.add(glob);
+	}
+	builder.build().map_err(|e| WatcherError::ConfigurationError {
+		parameter: parameter.to_string(),
+		reason: e.to_string(),
+		expected: "valid glob patterns".to_string(),
+		actual: format!("{patterns:?}"),
+	})
+}
+
+/// `WatcherConfig::ignore_patterns` and `sidecar_patterns`, shared between the backend
+/// callback, the event loop and the handle so [`WatcherHandle::update_ignore_patterns`] takes
+/// effect everywhere at once
+#[derive(Clone)]
+struct IgnoreFilter {
+	/// Canonical watch root; paths below it are matched relative to it
+	root: PathBuf,
+	globs: Arc<std::sync::RwLock<Arc<GlobSet>>>,
+	/// Matched against file names only, and fixed for the watcher's lifetime
+	sidecars: Arc<GlobSet>,
+}
+
+impl IgnoreFilter {
+	fn new(root: &Path, globs: GlobSet, sidecars: GlobSet) -> Self {
+		Self {
+			root: root.to_path_buf(),
+			globs: Arc::new(std::sync::RwLock::new(Arc::new(globs))),
+			sidecars: Arc::new(sidecars),
+		}
+	}
+
+	fn compile(patterns: &[String]) -> Result<GlobSet> {
+		compile_globs("ignore_patterns", patterns)
+	}
+
+	fn replace(&self, globs: GlobSet) {
+		*self.globs.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(globs);
+	}
+
+	/// Whether `path` is a sidecar, or it or a directory above it within the root matches an
+	/// ignore pattern
+	fn matches(&self, path: &Path) -> bool {
+		if path.file_name().is_some_and(|name| self.sidecars.is_match(name)) {
+			return true;
+		}
+		// Cloning the Arc keeps the lock out of the glob matching
+		let globs = self.globs.read().unwrap_or_else(|e| e.into_inner()).clone();
+		if globs.is_empty() {
+			return false;
+		}
+		let relative = path.strip_prefix(&self.root).unwrap_or(path);
+		relative
+			.ancestors()
+			.take_while(|ancestor| !ancestor.as_os_str().is_empty())
+			.any(|ancestor| globs.is_match(ancestor))
+	}
+}
+
+/// `WatcherConfig::watch_patterns`, matched relative to the watch root
+#[derive(Clone)]
+struct WatchScope {
+	root: PathBuf,
+	globs: Arc<GlobSet>,
+	/// Each pattern split into its components, `None` standing for `**`
+	components: Arc<Vec<Vec<Option<GlobMatcher>>>>,
+}
+
+impl WatchScope {
+	fn new(root: &Path, patterns: &[String]) -> Result<Self> {
+		let invalid = |pattern: &str, e: globset::Error| WatcherError::ConfigurationError {
+			parameter: "watch_patterns".to_string(),
+			reason: e.to_string(),
+			expected: "valid glob pattern".to_string(),
+			actual: pattern.to_string(),
+		};
+		let glob = |pattern: &str| {
+			GlobBuilder::new(pattern)
+				.literal_separator(true)
+				.build()
+				.map_err(|e| invalid(pattern, e))
+		};
+		let mut builder = GlobSetBuilder::new();
+		let mut components = Vec::new();
+		for pattern in patterns {
+			builder.add(glob(pattern)?);
+			components.push(
+				pattern
+					.split('/')
+					.filter(|component| !component.is_empty())
+					.map(|component| match component {
+						"**" => Ok(None),
+						_ => glob(component).map(|glob| Some(glob.compile_matcher())),
+					})
+					.collect::<Result<Vec<_>>>()?,
+			);
+		}
+		let globs = builder.build().map_err(|e| invalid(&format!("{patterns:?}"), e))?;
+		Ok(Self {
+			root: root.to_path_buf(),
+			globs: Arc::new(globs),
+			components: Arc::new(components),
+		})
+	}
+
+	/// No patterns: the whole tree is in scope
+	fn is_everything(&self) -> bool {
+		self.components.is_empty()
+	}
+
+	/// Whether `path` or a directory above it within the root matches; paths outside the
+	/// root are not the scope's business
+	fn contains(&self, path: &Path) -> bool {
+		if self.is_everything() {
+			return true;
+		}
+		let Ok(relative) = path.strip_prefix(&self.root) else {
+			return true;
+		};
+		relative
+			.ancestors()
+			.take_while(|ancestor| !ancestor.as_os_str().is_empty())
+			.any(|ancestor| self.globs.is_match(ancestor))
+	}
+
+	/// Whether `path` is in scope or a directory on the way to something that may be
+	fn leads_to(&self, path: &Path) -> bool {
+		if self.is_everything() {
+			return true;
+		}
+		let Ok(relative) = path.strip_prefix(&self.root) else {
+			return true;
+		};
+		let relative: Vec<&OsStr> = relative.iter().collect();
+		self.components.iter().any(|pattern| prefix_matches(pattern, &relative))
+	}
+
+	/// Registrations for the tree at `dir`: recursive from each directory in scope down,
+	/// non-recursive for the directories on the way to them
+	fn registrations(&self, dir: &Path) -> Vec<(PathBuf, RecursiveMode)> {
+		let mut registrations = Vec::new();
+		let mut walk = walkdir::WalkDir::new(dir).follow_links(false).into_iter();
+		while let Some(entry) = walk.next() {
+			let Ok(entry) = entry else {
+				continue;
+			};
+			if !entry.file_type().is_dir() {
+				continue;
+			}
+			if self.contains(entry.path()) {
+				registrations.push((entry.into_path(), RecursiveMode::Recursive));
+				walk.skip_current_dir();
+			} else if self.leads_to(entry.path()) {
+				registrations.push((entry.into_path(), RecursiveMode::NonRecursive));
+			} else {
+				walk.skip_current_dir();
+			}
+		}
+		registrations
+	}
+}
+
+/// Whether `path` could be the start of something `pattern` matches; a path that runs past
+/// the end of the pattern is below a match
+fn prefix_matches(pattern: &[Option<GlobMatcher>], path: &[&OsStr]) -> bool {
+	match (pattern.split_first(), path.split_first()) {
+		(_, None) | (None, Some(_)) | (Some((None, _)), Some(_)) => true,
+		(Some((Some(component), pattern)), Some((name, path))) => {
+			component.is_match(name) && prefix_matches(pattern, path)
+		}
+	}
+}
+
+/// Filters applied in the backend callback, the earliest point we control
+struct CallbackFilters {
+	/// See `WatcherConfig::required_input_types`
+	input_types: Option<HashSet<EventType>>,
+	own_database: Option<OwnDatabaseFilter>,
+	/// `WatchTargets::Files`: the parent watches also see every sibling
+	only_paths: Option<HashSet<PathBuf>>,
+	ignore: IgnoreFilter,
+	moved_to_ignored: MovedToIgnoredPolicy,
+	/// `WatcherConfig::normalize_paths`
+	normalize_paths: bool,
+	/// Directories on the way into scope pass too, so the event loop can watch new ones
+	scope: WatchScope,
+}
+
+/// `notify::Event` info tag of a rename kept whole for [`MovedToIgnoredPolicy::Marker`]
+const MOVED_TO_IGNORED_INFO: &str = "rust_watcher:moved_to_ignored";
+
+impl CallbackFilters {
+	/// Drop filtered paths from `event`; false if nothing is left to process
+	fn retain(&self, event: &mut notify::Event) -> bool {
+		if self.normalize_paths {
+			for path in &mut event.paths {
+				*path = normalize_separators(path);
+			}
+		}
+		let marker = self.rewrite_moved_to_ignored(event);
+		if let Some(types) = &self.input_types {
+			let event_type =
+				if marker { EventType::MovedToIgnored } else { EventType::from(event.kind) };
+			if !types.contains(&event_type) {
+				return false;
+			}
+		}
+		if marker {
+			// The destination is ignored on purpose; the source passed `keeps` already
+			return true;
+		}
+		event.paths.retain(|path| self.keeps(path));
+		!event.paths.is_empty()
+	}
+
+	fn keeps(&self, path: &Path) -> bool {
+		!self.own_database.as_ref().is_some_and(|own| own.matches(path))
+			&& self.only_paths.as_ref().is_none_or(|only| only.contains(path))
+			&& !self.ignore.matches(path)
+			&& self.scope.leads_to(path)
+	}
+
+	/// Apply `WatcherConfig::moved_to_ignored` to a rename from a kept path into an ignored
+	/// one; true if `event` was tagged as a marker
+	fn rewrite_moved_to_ignored(&self, event: &mut notify::Event) -> bool {
+		use notify::event::{ModifyKind, RemoveKind, RenameMode};
+		if event.kind != EventKind::Modify(ModifyKind::Name(RenameMode::Both)) {
+			return false;
+		}
+		let [source, destination] = event.paths.as_slice() else {
+			return false;
+		};
+		if !self.ignore.matches(destination) || !self.keeps(source) {
+			return false;
+		}
+		match self.moved_to_ignored {
+			MovedToIgnoredPolicy::Remove => {
+				event.kind = EventKind::Remove(RemoveKind::Any);
+				event.paths.truncate(1);
+				false
+			}
+			MovedToIgnoredPolicy::Marker => {
+				event.attrs.set_info(MOVED_TO_IGNORED_INFO);
+				true
+			}
+		}
+	}
+}
+
+/// Wakes the event loop when the backend reports the watch root itself removed or renamed
+struct RootSignal {
+	root: PathBuf,
+	removed: Arc<tokio::sync::Notify>,
+}
+
+impl RootSignal {
+	/// Checked before filtering, since `event_types` or ignore patterns may drop the event
+	fn check(&self, event: &notify::Event) {
+		if matches!(
+			event.kind,
+			EventKind::Remove(_) | EventKind::Modify(notify::event::ModifyKind::Name(_))
+		) && event.paths.contains(&self.root)
+		{
+			self.removed.notify_one();
+		}
+	}
+}
+
+/// Setup watcher callback and start watching
+async fn setup_watcher_callback(
+	watcher: &mut RecommendedWatcher, registrations: &[(PathBuf, RecursiveMode)],
+	notify_tx: std::sync::mpsc::Sender<notify::Event>, filters: CallbackFilters,
+	root_signal: Option<RootSignal>, degrade_on_watch_limit: bool,
+	diagnostics: Option<DiagnosticsSender>,
+) -> Result<()> {
+	let degraded = diagnostics.clone();
+	// Replace the watcher callback
+	*watcher = RecommendedWatcher::new(
+		move |res: notify::Result<notify::Event>| {
+			if let Ok(mut event) = res {
+				if let Some(root_signal) = &root_signal {
+					root_signal.check(&event);
+				}
+				if event.need_rescan() {
+					warn!("Backend dropped events, rescan needed: {:?}", event.paths);
+					crate::diagnostics::emit(
+						diagnostics.as_ref(),
+						WatcherDiagnostic::BackendOverflow { path: event.paths.first().cloned() },
+					);
+				}
+				if !filters.retain(&mut event) {
+					return;
+				}
+				if let Err(e) = notify_tx.send(event) {
+					error!("Error sending notify event: {}", e);
+				}
 			} else if let Err(e) = res {
 				error!("Notify error: {}", e);
+				if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) {
+					crate::diagnostics::emit(
+						diagnostics.as_ref(),
+						WatcherDiagnostic::ResourceLimit {
+							resource: "file watches".to_string(),
+							path: e.paths.first().cloned(),
+						},
+					);
+				}
+			}
+		},
+		Config::default().with_poll_interval(Duration::from_millis(50)),
+	)
+	.map_err(WatcherError::Notify)?;
+
+	register_watches(
+		watcher,
+		registrations,
+		degrade_on_watch_limit,
+		degraded.as_ref(),
+	)?;
+	Ok(())
+}
+
+/// Register every path with the backend and return the roots of the subtrees left unwatched
+///
+/// Those are only left over with `degrade`, once the watch limit is hit; see
+/// `WatcherConfig::degrade_on_watch_limit`. They are also reported as
+/// `WatcherDiagnostic::WatchesDegraded`. Any other failure is an error.
+fn register_watches<W: NotifyWatcher>(
+	watcher: &mut W, registrations: &[(PathBuf, RecursiveMode)], degrade: bool,
+	diagnostics: Option<&DiagnosticsSender>,
+) -> Result<Vec<PathBuf>> {
+	let mut queue: VecDeque<(PathBuf, RecursiveMode)> = registrations.iter().cloned().collect();
+	while let Some((path, mode)) = queue.pop_front() {
+		let Err(e) = watcher.watch(&path, mode) else {
+			info!("Successfully started watching path: {:?}", path);
+			continue;
+		};
+		if !degrade || !matches!(e.kind, notify::ErrorKind::MaxFilesWatch) {
+			return Err(watch_error(&path, e));
+		}
+		if mode == RecursiveMode::Recursive {
+			// The backend may have watched part of the tree before giving up. Start over one
+			// directory at a time, shallowest first, so what is left unwatched are whole
+			// subtrees rather than scattered directories.
+			let _ = watcher.unwatch(&path);
+			let mut dirs: Vec<PathBuf> = walkdir::WalkDir::new(&path)
+				.follow_links(false)
+				.into_iter()
+				.filter_map(|entry| entry.ok())
+				.filter(|entry| entry.file_type().is_dir())
+				.map(walkdir::DirEntry::into_path)
+				.collect();
+			dirs.sort_by(|a, b| (a.components().count(), a).cmp(&(b.components().count(), b)));
+			for dir in dirs.into_iter().rev() {
+				queue.push_front((dir, RecursiveMode::NonRecursive));
+			}
+			continue;
+		}
+		let left: HashSet<PathBuf> =
+			std::iter::once(path).chain(queue.into_iter().map(|(path, _)| path)).collect();
+		let mut roots: Vec<PathBuf> = left
+			.iter()
+			.filter(|dir| !dir.parent().is_some_and(|parent| left.contains(parent)))
+			.cloned()
+			.collect();
+		roots.sort();
+		warn!(
+			"Watch limit reached, {} subtree(s) are not watched: {:?}",
+			roots.len(),
+			roots
+		);
+		crate::diagnostics::emit(
+			diagnostics,
+			WatcherDiagnostic::WatchesDegraded { unwatched: roots.clone() },
+		);
+		return Ok(roots);
+	}
+	Ok(Vec::new())
+}
+
+/// Register one path with the backend, mapping permission problems to typed errors
+fn watch_path(watcher: &mut RecommendedWatcher, path: &Path, mode: RecursiveMode) -> Result<()> {
+	watcher.watch(path, mode).map_err(|e| watch_error(path, e))?;
+	info!("Successfully started watching path: {:?}", path);
+	Ok(())
+}
+
+fn watch_error(path: &Path, e: notify::Error) -> WatcherError {
+	error!("Failed to watch path {:?}: {}", path, e);
+	match &e.kind {
+		notify::ErrorKind::Generic(msg) if msg.contains("permission") => {
+			WatcherError::from_permission_denied(
+				"watch path",
+				&path.to_string_lossy(),
+				std::io::Error::new(std::io::ErrorKind::PermissionDenied, msg.clone()),
+			)
+		}
+		notify::ErrorKind::Io(io_err) => match io_err.kind() {
+			std::io::ErrorKind::PermissionDenied => WatcherError::from_permission_denied(
+				"watch path",
+				&path.to_string_lossy(),
+				std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Permission denied"),
+			),
+			_ => WatcherError::Notify(e),
+		},
+		_ => WatcherError::Notify(e),
+	}
+}
+
+/// Process a single filesystem event with proper error handling
+async fn process_single_event<'a>(
+	event: &notify::Event, move_detector: &mut MoveDetector<'a, RedbFilesystemCache>,
+	database: &DatabaseAdapter, sink: &mut LoopSink, catch_up: &mut SubdirectoryCatchUp,
+	ownership: Option<&OwnershipCapture>, known: &std::sync::Mutex<KnownOperations>,
+) -> Result<Vec<FileSystemEvent>> {
+	let is_known = |path: &Path, event_type: EventType| {
+		known.lock().unwrap_or_else(|e| e.into_inner()).suppresses(path, &event_type)
+	};
+	if event.info() == Some(MOVED_TO_IGNORED_INFO) {
+		if let [source, destination] = event.paths.as_slice() {
+			return process_moved_to_ignored(
+				event,
+				(source, destination),
+				move_detector,
+				database,
+				sink,
+				ownership,
+			)
+			.await;
+		}
+	}
+	use notify::event::{ModifyKind, RenameMode};
+	if event.kind == EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+		&& move_detector.config().rename_pairs_as_moves
+	{
+		// With either path ignored, the other is reported on its own below
+		if let [source, destination] = event.paths.as_slice() {
+			let kept = |path: &Path| !sink.ignore.matches(path) && sink.scope.contains(path);
+			if kept(source) && kept(destination) && !is_known(destination, EventType::Rename) {
+				return process_rename_pair(
+					event,
+					(source, destination),
+					move_detector,
+					database,
+					sink,
+					ownership,
+				)
+				.await;
+			}
+		}
+	}
+	let mut all_processed = Vec::new();
+	for path in &event.paths {
+		// Queued before `update_ignore_patterns` reached the callback
+		if sink.ignore.matches(path) {
+			continue;
+		}
+		sink.metrics.record_received();
+		let fs_event = convert_notify_event(&event.kind, path.clone(), move_detector);
+		if !sink.scope.contains(path) {
+			// Only here to be on the way into scope; a new one needs watching all the same
+			if fs_event.is_directory
+				&& matches!(fs_event.event_type, EventType::Create | EventType::Move)
+			{
+				catch_up.route_dirs.push(path.clone());
+			}
+			continue;
+		}
+		if fs_event.event_type == EventType::Write
+			&& fs_event.is_directory
+			&& !sink.emit_directory_modify
+		{
+			continue;
+		}
+		if is_known(path, fs_event.event_type.clone()) {
+			debug!("Dropping event of a known operation: {:?}", fs_event);
+			catch_up.supersede(fs_event);
+			continue;
+		}
+		if fs_event.event_type == EventType::Create && !catch_up.record_native_create(path) {
+			debug!(
+				"Dropping create already reported by catch-up scan: {:?}",
+				path
+			);
+			catch_up.supersede(fs_event);
+			continue;
+		}
+		if fs_event.event_type == EventType::Remove {
+			// A later create of the same path is a new file, not the one the scan saw
+			catch_up.forget(path);
+		}
+		all_processed
+			.extend(process_fs_event(fs_event, move_detector, database, sink, ownership).await?);
+	}
+	Ok(all_processed)
+}
+
+/// Report a rename into an ignored path as [`EventType::MovedToIgnored`]
+async fn process_moved_to_ignored<'a>(
+	event: &notify::Event, (source, destination): (&PathBuf, &PathBuf),
+	move_detector: &mut MoveDetector<'a, RedbFilesystemCache>, database: &DatabaseAdapter,
+	sink: &mut LoopSink, ownership: Option<&OwnershipCapture>,
+) -> Result<Vec<FileSystemEvent>> {
+	if sink.ignore.matches(source) || !sink.scope.contains(source) {
+		return Ok(Vec::new());
+	}
+	sink.metrics.record_received();
+	// The destination still exists, so its kind and size are read from there
+	let mut fs_event = convert_notify_event(&event.kind, destination.clone(), move_detector);
+	fs_event.event_type = EventType::MovedToIgnored;
+	fs_event.path = source.clone();
+	fs_event.move_data = Some(crate::events::MoveEvent::new(
+		source.clone(),
+		destination.clone(),
+		1.0,
+		crate::events::MoveDetectionMethod::Rename,
+	));
+	process_fs_event(fs_event, move_detector, database, sink, ownership).await
+}
+
+/// Hand a rename event carrying both paths to the detector as one generic rename of the
+/// destination with the source in `move_data`; see `MoveDetectorConfig::rename_pairs_as_moves`
+async fn process_rename_pair<'a>(
+	event: &notify::Event, (source, destination): (&PathBuf, &PathBuf),
+	move_detector: &mut MoveDetector<'a, RedbFilesystemCache>, database: &DatabaseAdapter,
+	sink: &mut LoopSink, ownership: Option<&OwnershipCapture>,
+) -> Result<Vec<FileSystemEvent>> {
+	sink.metrics.record_received();
+	let mut fs_event = convert_notify_event(&event.kind, destination.clone(), move_detector);
+	fs_event.move_data = Some(crate::events::MoveEvent::new(
+		source.clone(),
+		destination.clone(),
+		1.0,
+		crate::events::MoveDetectionMethod::Rename,
+	));
+	process_fs_event(fs_event, move_detector, database, sink, ownership).await
+}
+
+/// Persist, run move detection on, and forward one converted event
+async fn process_fs_event<'a>(
+	mut fs_event: FileSystemEvent, move_detector: &mut MoveDetector<'a, RedbFilesystemCache>,
+	database: &DatabaseAdapter, sink: &mut LoopSink, ownership: Option<&OwnershipCapture>,
+) -> Result<Vec<FileSystemEvent>> {
+	if let Some(ownership) = ownership {
+		fs_event.ownership = ownership.lookup(&fs_event).await;
+	}
+	if sink.resolve_targets {
+		fs_event.resolved_path = resolved_target(&fs_event.path);
+	}
+	if sink.timestamp_source == TimestampSource::OsEvent {
+		if let Some(timestamp) = os_timestamp(&fs_event) {
+			fs_event = fs_event.with_os_timestamp(timestamp);
+		}
+	}
+	let mut all_processed = Vec::new();
+	// Store event in database (needs reference)
+	let stored = database.store_event(&fs_event).await;
+	sink.record_persistence(database, "store event", stored);
+	// Store metadata if this is a create/write event; a length failure is reported by the
+	// detector, which reads the same path
+	if matches!(fs_event.event_type, EventType::Create | EventType::Write) {
+		let long_path_prefix = move_detector.config().long_path_prefix;
+		let fs_path = crate::long_paths::for_fs(&fs_event.path, long_path_prefix);
+		if let Ok(metadata) = std::fs::metadata(&fs_path) {
+			let stored = database.store_metadata(&fs_event.path, &metadata).await;
+			sink.record_persistence(database, "store metadata", stored);
+		}
+	}
+	// Move detector needs ownership
+	let processed_events = move_detector.process_event(fs_event).await;
+	for processed in processed_events {
+		log_processed_event(&processed);
+		sink.deliver(&processed).await?;
+		all_processed.push(processed);
+	}
+	Ok(all_processed)
+}
+
+/// When the kernel recorded the operation behind `event`, for `TimestampSource::OsEvent`: the
+/// path's ctime, for the event kinds that set it and not later than the processing time
+fn os_timestamp(event: &FileSystemEvent) -> Option<DateTime<Utc>> {
+	let sets_ctime = matches!(
+		event.event_type,
+		EventType::Create
+			| EventType::Write
+			| EventType::RenameTo
+			| EventType::Rename
+			| EventType::Chmod
+	);
+	if event.snapshot || !sets_ctime {
+		return None;
+	}
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		let metadata = std::fs::symlink_metadata(&event.path).ok()?;
+		let changed = DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32)?;
+		(changed <= event.timestamp).then_some(changed)
+	}
+	#[cfg(not(unix))]
+	{
+		None
+	}
+}
+
+/// Real path of `path` if it differs, i.e. `path` is or goes through a symlink; `None` as well
+/// for a path that cannot be resolved, such as a dangling link
+fn resolved_target(path: &Path) -> Option<PathBuf> {
+	let resolved = canonical_root(path).ok()?;
+	(resolved != path).then_some(resolved)
+}
+
+/// Source of `FileSystemEvent::ownership`; see `WatcherConfig::capture_ownership`
+struct OwnershipCapture {
+	/// Own handle on the filesystem cache, for paths that no longer exist
+	cache: tokio::sync::Mutex<RedbFilesystemCache>,
+}
+
+impl OwnershipCapture {
+	async fn lookup(&self, event: &FileSystemEvent) -> Option<FileOwnership> {
+		// lstat: the owner of a symlink itself, consistent with how the cache records links
+		if let Ok(metadata) = std::fs::symlink_metadata(&event.path) {
+			return FileOwnership::from_metadata(&metadata);
+		}
+		let node = self.cache.lock().await.get_unified_node(&event.path).await.ok()??;
+		Some(FileOwnership { uid: node.metadata.uid?, gid: node.metadata.gid? })
+	}
+}
+
+/// Consumer channel plus the output-side filters (allowlist, write stabilization)
+struct LoopSink {
+	tx: mpsc::Sender<FileSystemEvent>,
+	allowed: Option<HashSet<EventType>>,
+	stabilizer: Option<WriteStabilizer>,
+	identity_dedup: Option<IdentityDedup>,
+	recent: Arc<RecentEvents>,
+	diagnostics: Option<DiagnosticsSender>,
+	/// Shared with the backend callback and the handle
+	ignore: IgnoreFilter,
+	/// Shared with the backend callback
+	scope: WatchScope,
+	metrics: Arc<WatcherMetrics>,
+	delivery: Option<DeliverySequencer>,
+	/// `WatcherConfig::event_sinks`, retried with `mirror_retry` when set
+	mirrors: Vec<Arc<dyn crate::sink::EventSink>>,
+	mirror_retry: Option<RetryManager>,
+	/// End of `WatcherConfig::startup_quiet_period`
+	quiet_until: Option<DateTime<Utc>>,
+	/// The database's circuit breaker was open after the last write; see
+	/// `DatabaseConfig::circuit_breaker`
+	persistence_suspended: bool,
+	/// `WatcherConfig::emit_directory_modify`
+	emit_directory_modify: bool,
+	/// `WatcherConfig::resolve_targets`
+	resolve_targets: bool,
+	/// `WatcherConfig::timestamp_source`
+	timestamp_source: TimestampSource,
+	/// `WatcherConfig::adaptive_coalescing`, unless `stabilize_writes` holds writes anyway
+	adaptive_coalescing: Option<AdaptiveCoalescing>,
+	/// `stabilizer` was installed by `adaptive_coalescing` and is removed once it releases
+	coalescing: bool,
+}
+
+/// Numbers delivered events and logs them; see `WatcherConfig::delivery_log`
+struct DeliverySequencer {
+	database: Arc<redb::Database>,
+	watch_id: uuid::Uuid,
+	next: u64,
+}
+
+impl DeliverySequencer {
+	/// Continue the watch's numbering; `None` (logged) when the database is unavailable
+	async fn open(database: &DatabaseAdapter, watch_id: uuid::Uuid) -> Option<Self> {
+		let Some(database) = database.get_raw_database().await else {
+			warn!("Delivery log disabled: no database available");
+			return None;
+		};
+		match DeliveryLog::next_sequence(&database, &watch_id) {
+			Ok(next) => Some(Self { database, watch_id, next }),
+			Err(e) => {
+				warn!("Delivery log disabled: {}", e);
+				None
+			}
+		}
+	}
+
+	/// Assign the next sequence to `event` and log it
+	fn log(&mut self, event: &mut FileSystemEvent) -> crate::database::DatabaseResult<()> {
+		event.sequence = Some(self.next);
+		if let Err(e) = DeliveryLog::append(&self.database, &self.watch_id, self.next, event) {
+			event.sequence = None;
+			return Err(e);
+		}
+		self.next += 1;
+		Ok(())
+	}
+}
+
+impl LoopSink {
+	fn diagnose(&self, diagnostic: WatcherDiagnostic) {
+		crate::diagnostics::emit(self.diagnostics.as_ref(), diagnostic);
+	}
+
+	/// Count and report the outcome of a database write made for an event, including the
+	/// circuit breaker opening or closing because of it
+	fn record_persistence(
+		&mut self, database: &DatabaseAdapter, operation: &str, result: DatabaseResult<()>,
+	) {
+		let failure = match result {
+			// Skipped by the open breaker, which was reported when it opened
+			Ok(()) | Err(DatabaseError::CircuitOpen) => None,
+			Err(e) => {
+				warn!("Failed to {} in database: {}", operation, e);
+				self.metrics.record_persistence_error();
+				Some(format!("failed to {operation}: {e}"))
+			}
+		};
+		let suspended = database.persistence_suspended();
+		let opened = suspended && !self.persistence_suspended;
+		if self.persistence_suspended && !suspended {
+			self.diagnose(WatcherDiagnostic::PersistenceRestored);
+		}
+		self.persistence_suspended = suspended;
+		let reason = match (failure, opened) {
+			(Some(failure), false) => failure,
+			(Some(failure), true) => format!("{failure}; persistence suspended"),
+			(None, true) => format!("{operation} was too slow; persistence suspended"),
+			(None, false) => return,
+		};
+		self.diagnose(WatcherDiagnostic::PersistenceDegraded { reason });
+	}
+
+	async fn deliver(&mut self, event: &FileSystemEvent) -> Result<()> {
+		self.adapt_to_backpressure().await?;
+		if let Some(dedup) = &mut self.identity_dedup {
+			if dedup.hold(event) {
+				return Ok(());
+			}
+			for released in dedup.take_touching(event) {
+				self.stabilize(&released).await?;
+			}
+		}
+		self.stabilize(event).await
+	}
+
+	async fn stabilize(&mut self, event: &FileSystemEvent) -> Result<()> {
+		if let Some(stabilizer) = &mut self.stabilizer {
+			if stabilizer.hold(event) {
+				return Ok(());
+			}
+		}
+		self.send(event).await
+	}
+
+	fn has_held_events(&self) -> bool {
+		self.stabilizer.as_ref().is_some_and(|s| s.has_pending())
+	}
+
+	/// How often held writes are checked for having settled
+	fn stabilizer_poll(&self) -> Duration {
+		self.stabilizer
+			.as_ref()
+			.map(|stabilizer| (stabilizer.quiet_period / 4).max(Duration::from_millis(10)))
+			.unwrap_or(Duration::from_secs(3600))
+	}
+
+	/// Switch `WatcherConfig::adaptive_coalescing` on or off for the channel's fill level
+	async fn adapt_to_backpressure(&mut self) -> Result<()> {
+		let Some(adaptive) = self.adaptive_coalescing else {
+			return Ok(());
+		};
+		let fill = 1.0 - self.tx.capacity() as f32 / self.tx.max_capacity() as f32;
+		if !self.coalescing && fill >= adaptive.engage_at {
+			warn!(
+				"Event channel {:.0}% full, coalescing writes until the consumer catches up",
+				fill * 100.0
+			);
+			self.stabilizer = Some(WriteStabilizer::new(adaptive.quiet_period));
+			self.coalescing = true;
+			self.diagnose(WatcherDiagnostic::Backpressure { coalescing: true, fill });
+		} else if self.coalescing && fill <= adaptive.release_at {
+			info!(
+				"Event channel down to {:.0}% full, no longer coalescing writes",
+				fill * 100.0
+			);
+			self.coalescing = false;
+			self.diagnose(WatcherDiagnostic::Backpressure { coalescing: false, fill });
+			let held = self.stabilizer.take().map(|mut stabilizer| stabilizer.take_all());
+			for event in held.unwrap_or_default() {
+				self.send(&event).await?;
+			}
+		}
+		Ok(())
+	}
+
+	fn has_identity_groups(&self) -> bool {
+		self.identity_dedup.as_ref().is_some_and(|d| d.has_pending())
+	}
+
+	/// Pass on every collapsed event whose window has run out
+	async fn flush_identities(&mut self) -> Result<()> {
+		let ready = match &mut self.identity_dedup {
+			Some(dedup) => dedup.take_expired(),
+			None => return Ok(()),
+		};
+		for event in &ready {
+			self.stabilize(event).await?;
+		}
+		Ok(())
+	}
+
+	/// Deliver what `stabilize_writes` and `identity_dedup` still hold once the loop has
+	/// stopped, writes held the longest first.
+	///
+	/// Does not wait on a full channel, whose consumer may be the one waiting for `stop`; what
+	/// does not fit is dropped and logged.
+	async fn flush_held_on_stop(&mut self) {
+		let mut held = self.stabilizer.as_mut().map(WriteStabilizer::take_all).unwrap_or_default();
+		held.extend(self.identity_dedup.as_mut().map(IdentityDedup::take_all).unwrap_or_default());
+		for (delivered, event) in held.iter().enumerate() {
+			if self.tx.capacity() == 0 {
+				warn!(
+					"Event channel full at shutdown; dropping {} held events",
+					held.len() - delivered
+				);
+				return;
+			}
+			if self.send(event).await.is_err() {
+				return;
+			}
+		}
+	}
+
+	/// Emit every held event whose file has been quiet for the configured period
+	async fn flush_stable(&mut self) -> Result<()> {
+		let ready = match &mut self.stabilizer {
+			Some(stabilizer) => stabilizer.take_stable(),
+			None => return Ok(()),
+		};
+		for event in &ready {
+			debug!("STABLE: {:?} (size: {:?})", event.path, event.size);
+			self.send(event).await?;
+		}
+		Ok(())
+	}
+
+	async fn send(&mut self, event: &FileSystemEvent) -> Result<()> {
+		if let Some(allowed) = &self.allowed {
+			if !allowed.contains(&event.event_type) {
+				return Ok(());
+			}
+		}
+		let mut event = event.clone();
+		event.startup =
+			!event.snapshot && self.quiet_until.is_some_and(|until| event.timestamp < until);
+		let logged = self.delivery.as_mut().map(|delivery| delivery.log(&mut event));
+		if let Some(Err(e)) = logged {
+			warn!("Failed to log event for delivery {:?}: {}", event.path, e);
+			self.metrics.record_persistence_error();
+			self.diagnose(WatcherDiagnostic::PersistenceDegraded {
+				reason: format!("event could not be added to the delivery log: {e}"),
+			});
+		}
+		self.tx.send(event.clone()).await.map_err(|_| {
+			warn!("Event receiver dropped, ending processing loop.");
+			WatcherError::ChannelSend
+		})?;
+		self.recent.record(&event);
+		self.metrics.record_delivered(&event);
+		self.mirror(&event).await;
+		Ok(())
+	}
+
+	/// Write `event` to every `WatcherConfig::event_sinks` entry
+	async fn mirror(&self, event: &FileSystemEvent) {
+		for mirror in &self.mirrors {
+			let written = match &self.mirror_retry {
+				Some(retry) => retry.execute_simple(mirror.name(), || mirror.write(event)).await,
+				None => mirror.write(event).await,
+			};
+			if let Err(e) = written {
+				warn!(
+					"Event sink {} failed to write {:?}: {}",
+					mirror.name(),
+					event.path,
+					e
+				);
+				self.diagnose(WatcherDiagnostic::SinkWriteFailed {
+					sink: mirror.name().to_string(),
+					path: event.path.clone(),
+					reason: e.to_string(),
+				});
+			}
+		}
+	}
+}
+
+/// Schedule and baseline of `WatcherConfig::summary_interval`
+struct SummaryTimer {
+	interval: Duration,
+	/// Counters as of the previous summary
+	last: WatcherStats,
+	since: Instant,
+}
+
+impl SummaryTimer {
+	fn new(interval: Duration) -> Self {
+		Self { interval, last: WatcherStats::default(), since: Instant::now() }
+	}
+
+	fn until_due(&self) -> Duration {
+		(self.since + self.interval).saturating_duration_since(Instant::now())
+	}
+
+	/// Counts since the previous summary, starting the next period
+	fn take(&mut self, metrics: &WatcherMetrics) -> crate::metrics::SummaryEvent {
+		let now = Instant::now();
+		let stats = metrics.snapshot(true);
+		let summary = stats.summary_since(&self.last, now - self.since);
+		self.last = stats;
+		self.since = now;
+		summary
+	}
+}
+
+/// Deadline of a periodic `select!` arm in the event loop.
+///
+/// The arm's sleep is created anew on every iteration of the loop, so it sleeps until a stored
+/// deadline rather than for the whole period; otherwise a steady stream of events on the other
+/// arms would keep postponing it forever.
+struct PollDeadline(Instant);
+
+impl PollDeadline {
+	/// Due immediately
+	fn new() -> Self {
+		Self(Instant::now())
+	}
+
+	fn until_due(&self) -> Duration {
+		self.0.saturating_duration_since(Instant::now())
+	}
+
+	/// Start the next period, when the arm fires
+	fn restart(&mut self, period: Duration) {
+		self.0 = Instant::now() + period;
+	}
+}
+
+/// Bounded buffer of the last delivered events, shared with the handle.
+///
+/// A plain mutex is enough: both sides only hold it for a push or a clone of at most
+/// `capacity` events, never across an await, so the loop is not held up by readers.
+struct RecentEvents {
+	capacity: usize,
+	events: std::sync::Mutex<VecDeque<FileSystemEvent>>,
+}
+
+impl RecentEvents {
+	fn new(capacity: usize) -> Self {
+		Self { capacity, events: std::sync::Mutex::new(VecDeque::with_capacity(capacity)) }
+	}
+
+	fn record(&self, event: &FileSystemEvent) {
+		if self.capacity == 0 {
+			return;
+		}
+		let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+		if events.len() == self.capacity {
+			events.pop_front();
+		}
+		events.push_back(event.clone());
+	}
+
+	fn snapshot(&self) -> Vec<FileSystemEvent> {
+		self.events.lock().unwrap_or_else(|e| e.into_inner()).iter().cloned().collect()
+	}
+}
+
+/// A file event being held until the file stops growing
+struct HeldWrite {
+	event: FileSystemEvent,
+	last_size: Option<u64>,
+	last_change: Instant,
+}
+
+/// Coalesces Create/Write bursts per path into one event once the size is stable.
+///
+/// Polling happens on the watcher loop (see `stabilize_writes`); each poll costs one `stat`
+/// per held path, which is fine for the handful of in-flight copies this is meant for but not
+/// for thousands of files being written at once.
+struct WriteStabilizer {
+	quiet_period: Duration,
+	pending: HashMap<PathBuf, HeldWrite>,
+}
+
+impl WriteStabilizer {
+	fn new(quiet_period: Duration) -> Self {
+		Self { quiet_period, pending: HashMap::new() }
+	}
+
+	fn has_pending(&self) -> bool {
+		!self.pending.is_empty()
+	}
+
+	/// Returns true if the event was taken over (held) and must not be delivered now.
+	fn hold(&mut self, event: &FileSystemEvent) -> bool {
+		match event.event_type {
+			EventType::Create | EventType::Write if !event.is_directory => {
+				match self.pending.get_mut(&event.path) {
+					Some(held) => {
+						held.last_size = event.size.or(held.last_size);
+						held.last_change = Instant::now();
+					}
+					None => {
+						self.pending.insert(
+							event.path.clone(),
+							HeldWrite {
+								event: event.clone(),
+								last_size: event.size,
+								last_change: Instant::now(),
+							},
+						);
+					}
+				}
+				true
+			}
+			EventType::Remove => {
+				// Deleted before it settled: the held event is stale, the Remove still goes out
+				self.pending.remove(&event.path);
+				false
+			}
+			// The source stays where it is
+			EventType::Copy => false,
+			_ => {
+				if let Some(move_data) = &event.move_data {
+					self.pending.remove(&move_data.source_path);
+				}
+				false
+			}
+		}
+	}
+
+	/// Remove and return every held event with the size last seen, oldest first
+	fn take_all(&mut self) -> Vec<FileSystemEvent> {
+		let mut held: Vec<FileSystemEvent> = self
+			.pending
+			.drain()
+			.map(|(_, held)| FileSystemEvent { size: held.last_size, ..held.event })
+			.collect();
+		held.sort_by_key(|event| event.timestamp);
+		held
+	}
+
+	/// Remove and return held events whose file size has not changed for the quiet period
+	fn take_stable(&mut self) -> Vec<FileSystemEvent> {
+		let now = Instant::now();
+		let mut ready = Vec::new();
+		self.pending.retain(|path, held| {
+			let current_size = match std::fs::metadata(path) {
+				Ok(metadata) => Some(metadata.len()),
+				// Gone without a Remove yet; the Remove (or move) will follow on its own
+				Err(_) => return false,
+			};
+			if current_size != held.last_size {
+				held.last_size = current_size;
+				held.last_change = now;
+				return true;
 			}
-		},
-		Config::default().with_poll_interval(Duration::from_millis(50)),
+			if now.duration_since(held.last_change) < self.quiet_period {
+				return true;
+			}
+			let mut event = held.event.clone();
+			event.size = current_size;
+			ready.push(event);
+			false
+		});
+		ready.sort_by_key(|event| event.timestamp);
+		ready
+	}
+}
+
+/// Folds events for one `(dev, ino)` seen under several paths into one; see
+/// `WatcherConfig::identity_dedup`
+struct IdentityDedup {
+	window: Duration,
+	pending: HashMap<(u64, u64), HeldIdentity>,
+}
+
+struct HeldIdentity {
+	/// Carries the paths collected so far in `identity_paths`
+	event: FileSystemEvent,
+	since: Instant,
+}
+
+impl IdentityDedup {
+	fn new(window: Duration) -> Self {
+		Self { window, pending: HashMap::new() }
+	}
+
+	fn has_pending(&self) -> bool {
+		!self.pending.is_empty()
+	}
+
+	/// Returns true if the event was folded into a held one and must not be delivered now.
+	fn hold(&mut self, event: &FileSystemEvent) -> bool {
+		let collapsible = matches!(
+			event.event_type,
+			EventType::Create | EventType::Write | EventType::Chmod | EventType::Other(_)
+		);
+		if !collapsible || event.is_directory || event.move_data.is_some() {
+			return false;
+		}
+		let Some(identity) = file_identity(&event.path) else {
+			return false;
+		};
+		let strength = |event_type: &EventType| match event_type {
+			EventType::Create => 2,
+			EventType::Write => 1,
+			_ => 0,
+		};
+		match self.pending.get_mut(&identity) {
+			Some(held) => {
+				if strength(&event.event_type) > strength(&held.event.event_type) {
+					held.event.event_type = event.event_type.clone();
+				}
+				held.event.size = event.size.or(held.event.size);
+				if !held.event.identity_paths.contains(&event.path) {
+					held.event.identity_paths.push(event.path.clone());
+				}
+			}
+			None => {
+				let mut event = event.clone();
+				event.identity_paths = vec![event.path.clone()];
+				self.pending.insert(identity, HeldIdentity { event, since: Instant::now() });
+			}
+		}
+		true
+	}
+
+	/// Remove and return held events for the paths an event that was not held touches (e.g.
+	/// removes or moves away), which must go out before it
+	fn take_touching(&mut self, event: &FileSystemEvent) -> Vec<FileSystemEvent> {
+		let source = event.move_data.as_ref().map(|move_data| &move_data.source_path);
+		self.take_where(|held| {
+			held.event
+				.identity_paths
+				.iter()
+				.any(|path| *path == event.path || Some(path) == source)
+		})
+	}
+
+	/// Remove and return held events whose window has run out
+	fn take_expired(&mut self) -> Vec<FileSystemEvent> {
+		let window = self.window;
+		self.take_where(|held| held.since.elapsed() >= window)
+	}
+
+	/// Remove and return every held event, oldest first
+	fn take_all(&mut self) -> Vec<FileSystemEvent> {
+		self.take_where(|_| true)
+	}
+
+	fn take_where(&mut self, take: impl Fn(&HeldIdentity) -> bool) -> Vec<FileSystemEvent> {
+		let mut ready = Vec::new();
+		self.pending.retain(|_, held| {
+			if !take(held) {
+				return true;
+			}
+			ready.push(held.event.clone());
+			false
+		});
+		ready.sort_by_key(|event| event.timestamp);
+		ready
+	}
+}
+
+/// `(dev, ino)` of the file at `path`, not following a final symlink; always `None` off Unix
+fn file_identity(path: &Path) -> Option<(u64, u64)> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		let metadata = std::fs::symlink_metadata(path).ok()?;
+		Some((metadata.dev(), metadata.ino()))
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = path;
+		None
+	}
+}
+
+/// How long a create path is remembered for catch-up deduplication.
+///
+/// Only has to outlive the gap between the directory create being processed and the native
+/// events for its contents draining from the queue; a burst larger than the channel can make
+/// that gap longer, in which case a duplicate create is reported rather than a missing one.
+const CATCH_UP_DEDUP_WINDOW: Duration = Duration::from_secs(5);
+
+/// Bookkeeping for the catch-up scan of directories created after `start()`, and for the
+/// initial scan (`emit_existing_on_start`).
+///
+/// Files written into a new directory before its watch is live never produce native events,
+/// so the directory is scanned once the watch is registered. On backends that do extend
+/// recursion on their own (inotify) the scan and the native stream overlap; this remembers
+/// recently reported creates so each path is reported once, whichever source sees it first.
+struct SubdirectoryCatchUp {
+	/// Path -> (when it was reported, whether the report came from a catch-up scan)
+	recent_creates: HashMap<PathBuf, (Instant, bool)>,
+	/// Native creates dropped because a scan reported their path first; see `supersede`
+	superseded: Vec<FileSystemEvent>,
+	/// Device new directories are watched and scanned on, see `WatcherConfig::stay_on_device`
+	root_device: Option<u64>,
+	/// New directories on the way into `WatcherConfig::watch_patterns`, not reported but
+	/// still to be watched
+	route_dirs: Vec<PathBuf>,
+}
+
+impl SubdirectoryCatchUp {
+	fn new() -> Self {
+		Self {
+			recent_creates: HashMap::new(),
+			superseded: Vec::new(),
+			root_device: None,
+			route_dirs: Vec::new(),
+		}
+	}
+
+	fn prune(&mut self) {
+		let now = Instant::now();
+		self.recent_creates
+			.retain(|_, (seen, _)| now.duration_since(*seen) < CATCH_UP_DEDUP_WINDOW);
+	}
+
+	/// Record a native create; returns false if a catch-up scan already reported the path.
+	fn record_native_create(&mut self, path: &Path) -> bool {
+		if self.recent_creates.len() > 1024 {
+			self.prune();
+		}
+		match self.recent_creates.get(path) {
+			Some((seen, true)) if seen.elapsed() < CATCH_UP_DEDUP_WINDOW => false,
+			_ => {
+				self.recent_creates.insert(path.to_path_buf(), (Instant::now(), false));
+				true
+			}
+		}
+	}
+
+	/// Record a path found by a catch-up scan; returns false if it was already reported.
+	fn record_scanned(&mut self, path: &Path) -> bool {
+		match self.recent_creates.get(path) {
+			Some((seen, _)) if seen.elapsed() < CATCH_UP_DEDUP_WINDOW => false,
+			_ => {
+				self.recent_creates.insert(path.to_path_buf(), (Instant::now(), true));
+				true
+			}
+		}
+	}
+
+	/// Restart the dedup window of every scanned path, so a scan that took longer than the
+	/// window still suppresses the live creates queued behind it.
+	fn refresh_scanned(&mut self) {
+		let now = Instant::now();
+		for (seen, _) in self.recent_creates.values_mut().filter(|(_, scanned)| *scanned) {
+			*seen = now;
+		}
+	}
+
+	/// Forget a path after a native remove; whatever is created there next must be reported.
+	fn forget(&mut self, path: &Path) {
+		self.recent_creates.remove(path);
+	}
+
+	/// Keep a native create that is not delivered because a scan reported its path first, or
+	/// an event of a known operation.
+	///
+	/// The live event is newer than the scan's snapshot of the path, so it still goes to the
+	/// filesystem cache after the scan's node: the cache ends with one node per path, as the
+	/// live event left it.
+	fn supersede(&mut self, event: FileSystemEvent) {
+		self.superseded.push(event);
+	}
+
+	fn take_superseded(&mut self) -> Vec<FileSystemEvent> {
+		std::mem::take(&mut self.superseded)
+	}
+
+	fn take_route_dirs(&mut self) -> Vec<PathBuf> {
+		std::mem::take(&mut self.route_dirs)
+	}
+}
+
+/// Register watches for the directory tree at `dir`, without reporting anything in it
+fn watch_subdirectory(
+	watcher: &mut RecommendedWatcher, dir: &Path, sink: &mut LoopSink,
+	catch_up: &mut SubdirectoryCatchUp,
+) {
+	for (path, mode) in tree_registrations(dir, catch_up.root_device, &sink.scope) {
+		let Err(e) = watcher.watch(&path, mode) else {
+			continue;
+		};
+		if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) {
+			warn!("Watch limit reached, {:?} is not watched: {}", path, e);
+			sink.diagnose(WatcherDiagnostic::ResourceLimit {
+				resource: "file watches".to_string(),
+				path: Some(path),
+			});
+			break;
+		}
+		// The directory may already be gone again; a scan of it will find nothing then.
+		debug!(
+			"Could not register watch on new directory {:?}: {}",
+			path, e
+		);
+	}
+	catch_up.prune();
+}
+
+/// Ensure a directory created after `start()` is watched, then report anything that was
+/// created inside it before the watch became effective.
+///
+/// Re-registering a path the backend already watches is harmless for notify's backends, so
+/// this does not try to detect whether recursion was extended automatically.
+async fn watch_new_subdirectory<'a>(
+	watcher: &mut RecommendedWatcher, dir: &Path,
+	move_detector: &mut MoveDetector<'a, RedbFilesystemCache>, database: &DatabaseAdapter,
+	sink: &mut LoopSink, catch_up: &mut SubdirectoryCatchUp, ownership: Option<&OwnershipCapture>,
+) -> Result<Vec<FileSystemEvent>> {
+	watch_subdirectory(watcher, dir, sink, catch_up);
+
+	report_existing(
+		walk_on_device(
+			walkdir::WalkDir::new(dir).min_depth(1).follow_links(false),
+			catch_up.root_device,
+			device_of,
+		),
+		ScanKind::NewDirectory,
+		move_detector,
+		database,
+		sink,
+		catch_up,
+		ownership,
 	)
-	.map_err(WatcherError::Notify)?;
+	.await
+}
 
-	// Start watching the path
-	let mode = RecursiveMode::Recursive; // You could make this configurable
-	watcher.watch(path, mode).map_err(|e| {
-		error!("Failed to watch path {:?}: {}", path, e);
-		match &e.kind {
-			notify::ErrorKind::Generic(msg) if msg.contains("permission") => {
-				WatcherError::from_permission_denied(
-					"watch path",
-					&path.to_string_lossy(),
-					std::io::Error::new(std::io::ErrorKind::PermissionDenied, msg.clone()),
-				)
+/// How often a missing watch root is checked for during `WatcherConfig::root_gone_grace`
+const ROOT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Watch the directory that replaced the removed root again; its entries are then reported
+/// like those of a new subdirectory
+fn rewatch_root(
+	watcher: &mut RecommendedWatcher, root: &Path, recursive: bool, stay_on_device: bool,
+	scope: &WatchScope, catch_up: &mut SubdirectoryCatchUp,
+) -> Result<Vec<walkdir::Result<walkdir::DirEntry>>> {
+	// The backend usually dropped the old watch along with the directory already
+	let _ = watcher.unwatch(root);
+	// The new root may well be on another device than the old one
+	let root_device = stay_on_device.then(|| device_of(root)).flatten();
+	catch_up.root_device = root_device;
+	for (path, mode) in tree_registrations(root, root_device, scope) {
+		watch_path(watcher, &path, mode)?;
+	}
+	catch_up.prune();
+
+	let walk = walkdir::WalkDir::new(root).min_depth(1).follow_links(false);
+	let walk = if recursive { walk } else { walk.max_depth(1) };
+	Ok(walk_on_device(walk, root_device, device_of))
+}
+
+/// Device `path` is on, for `WatcherConfig::stay_on_device`; always `None` off Unix
+fn device_of(path: &Path) -> Option<u64> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		std::fs::symlink_metadata(path).ok().map(|metadata| metadata.dev())
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = path;
+		None
+	}
+}
+
+/// Entries of `walk`, without descending into directories on another device than `device`;
+/// those directories are still listed themselves. `None` walks everything.
+fn walk_on_device(
+	walk: walkdir::WalkDir, device: Option<u64>, device_of: impl Fn(&Path) -> Option<u64>,
+) -> Vec<walkdir::Result<walkdir::DirEntry>> {
+	let mut entries = Vec::new();
+	let mut walk = walk.into_iter();
+	while let Some(entry) = walk.next() {
+		if let (Some(device), Ok(entry)) = (device, &entry) {
+			if entry.file_type().is_dir() && device_of(entry.path()) != Some(device) {
+				walk.skip_current_dir();
 			}
-			notify::ErrorKind::Io(io_err) => match io_err.kind() {
-				std::io::ErrorKind::PermissionDenied => WatcherError::from_permission_denied(
-					"watch path",
-					&path.to_string_lossy(),
-					std::io::Error::new(std::io::ErrorKind::PermissionDenied, "Permission denied"),
-				),
-				_ => WatcherError::Notify(e),
-			},
-			_ => WatcherError::Notify(e),
 		}
-	})?;
+		entries.push(entry);
+	}
+	entries
+}
 
-	info!("Successfully started watching path: {:?}", path);
-	Ok(())
+/// Registrations for the tree at `dir`: on the root's device only, if there is one, and only
+/// toward what `scope` covers
+fn tree_registrations(
+	dir: &Path, root_device: Option<u64>, scope: &WatchScope,
+) -> Vec<(PathBuf, RecursiveMode)> {
+	match root_device {
+		// Every directory on the root's device, each on its own
+		Some(device) => directories_on_device(dir, device, device_of)
+			.into_iter()
+			.filter(|dir| scope.leads_to(dir))
+			.map(|dir| (dir, RecursiveMode::NonRecursive))
+			.collect(),
+		None if !scope.is_everything() => scope.registrations(dir),
+		// Always recursive: `recursive: false` only limits the catch-up and initial scans
+		None => vec![(dir.to_path_buf(), RecursiveMode::Recursive)],
+	}
 }
 
-/// Process a single filesystem event with proper error handling
-async fn process_single_event<'a>(
-	event: &notify::Event, move_detector: &mut MoveDetector<'a>, database: &DatabaseAdapter,
-	event_tx: &mpsc::Sender<FileSystemEvent>,
+/// `dir` and the directories below it that are on `device`, stopping at other devices
+fn directories_on_device(
+	dir: &Path, device: u64, device_of: impl Fn(&Path) -> Option<u64>,
+) -> Vec<PathBuf> {
+	let walk = walkdir::WalkDir::new(dir).follow_links(false);
+	walk_on_device(walk, Some(device), &device_of)
+		.into_iter()
+		.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.file_type().is_dir() && device_of(entry.path()) == Some(device))
+		.map(walkdir::DirEntry::into_path)
+		.collect()
+}
+
+/// Scanned entries whose files `report_existing` hashes together, see
+/// `MoveDetectorConfig::max_concurrent_hashes`
+const SCAN_PREHASH_BATCH: usize = 64;
+
+/// What a `report_existing` walk is for
+#[derive(Clone, Copy)]
+enum ScanKind<'a> {
+	/// `emit_existing_on_start`; entries are flagged `FileSystemEvent::snapshot`
+	Initial {
+		own_database: Option<&'a OwnDatabaseFilter>,
+	},
+	/// Catch-up of a directory that appeared while running; entries are live creates
+	NewDirectory,
+}
+
+/// Report every entry of `walk` not yet reported as a Create, recording it in `catch_up`
+async fn report_existing<'a>(
+	walk: impl IntoIterator<Item = walkdir::Result<walkdir::DirEntry>>, kind: ScanKind<'_>,
+	move_detector: &mut MoveDetector<'a, RedbFilesystemCache>, database: &DatabaseAdapter,
+	sink: &mut LoopSink, catch_up: &mut SubdirectoryCatchUp, ownership: Option<&OwnershipCapture>,
 ) -> Result<Vec<FileSystemEvent>> {
 	let mut all_processed = Vec::new();
-	for path in &event.paths {
-		let fs_event = convert_notify_event(&event.kind, path.clone(), move_detector);
-		// Store event in database (needs reference)
-		if let Err(e) = database.store_event(&fs_event).await {
-			warn!("Failed to store event in database: {}", e);
-		}
-		// Store metadata if this is a create/write event
-		if matches!(fs_event.event_type, EventType::Create | EventType::Write) {
-			if let Ok(metadata) = std::fs::metadata(&fs_event.path) {
-				if let Err(e) = database.store_metadata(&fs_event.path, &metadata).await {
-					warn!("Failed to store metadata in database: {}", e);
-				}
+	let mut walk = walk.into_iter().peekable();
+	while walk.peek().is_some() {
+		let mut batch = Vec::new();
+		for entry in walk.by_ref().take(SCAN_PREHASH_BATCH) {
+			if let Some(fs_event) = scanned_event(entry, kind, sink, catch_up) {
+				batch.push(fs_event);
 			}
 		}
-		// Move detector needs ownership
-		let processed_events = move_detector.process_event(fs_event.clone()).await;
-		for processed in processed_events {
-			log_processed_event(&processed);
-			event_tx.send(processed.clone()).await.map_err(|_| {
-				warn!("Event receiver dropped, ending processing loop.");
-				WatcherError::ChannelSend
-			})?;
-			all_processed.push(processed);
+		// Read the batch's files concurrently, rather than one per event below
+		let files: Vec<PathBuf> =
+			batch.iter().filter(|e| !e.is_directory).map(|e| e.path.clone()).collect();
+		move_detector.prehash(&files).await;
+		for fs_event in batch {
+			all_processed.extend(
+				process_fs_event(fs_event, move_detector, database, sink, ownership).await?,
+			);
 		}
 	}
 	Ok(all_processed)
 }
 
+/// The Create a scan reports for `entry`; `None` for unreadable, ignored, out-of-scope and
+/// already reported entries
+fn scanned_event(
+	entry: walkdir::Result<walkdir::DirEntry>, kind: ScanKind<'_>, sink: &mut LoopSink,
+	catch_up: &mut SubdirectoryCatchUp,
+) -> Option<FileSystemEvent> {
+	let entry = match entry {
+		Ok(entry) => entry,
+		Err(e) => {
+			debug!("Skipping unreadable entry during scan: {}", e);
+			sink.diagnose(WatcherDiagnostic::ScanSkip {
+				path: e.path().map(Path::to_path_buf),
+				reason: e.to_string(),
+			});
+			return None;
+		}
+	};
+	let own_database = match kind {
+		ScanKind::Initial { own_database } => own_database,
+		ScanKind::NewDirectory => None,
+	};
+	if own_database.is_some_and(|own| own.matches(entry.path()))
+		|| sink.ignore.matches(entry.path())
+		|| !sink.scope.contains(entry.path())
+	{
+		return None;
+	}
+	if !catch_up.record_scanned(entry.path()) {
+		return None;
+	}
+	let metadata = entry.metadata().ok();
+	let is_directory = entry.file_type().is_dir();
+	let size = metadata.filter(|m| m.is_file()).map(|m| m.len());
+	let mut fs_event = FileSystemEvent::new(
+		EventType::Create,
+		entry.path().to_path_buf(),
+		is_directory,
+		size,
+	);
+	fs_event.snapshot = matches!(kind, ScanKind::Initial { .. });
+	debug!("Scan found {:?}", fs_event.path);
+	Some(fs_event)
+}
+
 fn convert_notify_event(
-	kind: &EventKind, path: PathBuf, move_detector: &MoveDetector<'_>,
+	kind: &EventKind, path: PathBuf, move_detector: &MoveDetector<'_, RedbFilesystemCache>,
 ) -> FileSystemEvent {
 	let event_type = EventType::from(*kind);
 	debug!(
@@ -368,12 +3322,20 @@ fn convert_notify_event(
 		debug!("Metadata read: is_dir={}, size={:?}", is_dir, file_size);
 		(is_dir, file_size)
 	} else {
+		// The kind the backend reported, when it says (inotify does for creates and removes)
+		let reported = match kind {
+			EventKind::Create(notify::event::CreateKind::Folder)
+			| EventKind::Remove(notify::event::RemoveKind::Folder) => Some(true),
+			EventKind::Create(notify::event::CreateKind::File)
+			| EventKind::Remove(notify::event::RemoveKind::File) => Some(false),
+			_ => None,
+		};
 		debug!("Path does not exist, using heuristics: {:?}", path);
 		// File/directory no longer exists - use improved heuristics
-		let is_dir = match move_detector.infer_path_type(&path) {
+		let is_dir = match reported.or_else(|| move_detector.infer_path_type(&path)) {
 			Some(is_directory) => {
 				debug!(
-					"Move detector inferred path type: is_directory={}",
+					"Path type from backend or move detector: is_directory={}",
 					is_directory
 				);
 				is_directory
@@ -397,6 +3359,23 @@ fn convert_notify_event(
 	FileSystemEvent::new(event_type, path, is_directory, size)
 }
 
+/// Deliver events the detector released outside of event processing and sync them into the
+/// filesystem cache; false once the receiver is gone
+async fn deliver_released(
+	released: Vec<FileSystemEvent>, sink: &mut LoopSink,
+	cache_sync: &tokio::sync::Mutex<DefaultFilesystemCacheSynchronizer<RedbFilesystemCache>>,
+	watch_id: &uuid::Uuid,
+) -> bool {
+	let mut cache_sync_guard = cache_sync.lock().await;
+	let mut receiver_dropped = false;
+	for fs_event in released {
+		log_processed_event(&fs_event);
+		receiver_dropped |= sink.deliver(&fs_event).await.is_err();
+		cache_sync_guard.handle_event(watch_id, &fs_event).await;
+	}
+	!receiver_dropped
+}
+
 /// Log processed events with appropriate level:
 /// - INFO level for confirmed moves/renames
 /// - DEBUG level for all other events
@@ -405,16 +3384,25 @@ fn log_processed_event(event: &FileSystemEvent) {
 		EventType::Move => {
 			if let Some(move_data) = &event.move_data {
 				info!(
-					"MOVE DETECTED: {:?} -> {:?} (confidence: {:.2}, method: {:?})",
+					"MOVE DETECTED: {:?} -> {:?} (confidence: {:.2}, method: {:?}{})",
 					move_data.source_path,
 					move_data.destination_path,
 					move_data.confidence,
-					move_data.detection_method
+					move_data.detection_method,
+					if move_data.late { ", late" } else { "" }
 				);
 			} else {
 				info!("MOVE: {:?} (generic move)", event.path);
 			}
 		}
+		EventType::Copy => {
+			if let Some(move_data) = &event.move_data {
+				info!(
+					"COPY DETECTED: {:?} -> {:?}",
+					move_data.source_path, move_data.destination_path
+				);
+			}
+		}
 		EventType::RenameFrom | EventType::RenameTo | EventType::Rename => {
 			info!("RENAME: {:?} (type: {:?})", event.path, event.event_type);
 		}
@@ -426,6 +3414,7 @@ fn log_processed_event(event: &FileSystemEvent) {
 					EventType::Write => "WRITE",
 					EventType::Remove => "REMOVE",
 					EventType::Chmod => "CHMOD",
+					EventType::MovedToIgnored => "MOVED_TO_IGNORED",
 					EventType::Other(ref s) => s,
 					_ => "OTHER",
 				},
@@ -452,6 +3441,7 @@ mod tests {
 			move_detector_config: None,
 			error_recovery_config: None,
 			database_config: None,
+			..Default::default()
 		};
 
 		assert_eq!(config.path, temp_dir.path());
@@ -470,6 +3460,7 @@ mod tests {
 			move_detector_config: Some(move_config),
 			error_recovery_config: None,
 			database_config: None,
+			..Default::default()
 		};
 
 		assert!(!config.recursive);
@@ -484,6 +3475,7 @@ mod tests {
 			move_detector_config: None,
 			error_recovery_config: None,
 			database_config: None,
+			..Default::default()
 		};
 
 		let result = start(config);
@@ -498,13 +3490,486 @@ mod tests {
 	}
 
 	#[test]
-	fn test_watcher_handle_creation() {
+	fn test_watch_targets_files_validation() {
+		let temp_dir = TempDir::new().unwrap();
+		let files = |files: Vec<PathBuf>| WatcherConfig {
+			targets: WatchTargets::Files(files),
+			..Default::default()
+		};
+
+		// Missing files are fine as long as their directory exists
+		let config = files(vec![temp_dir.path().join("not_yet.txt")]);
+		assert!(config.validate().is_ok());
+		assert_eq!(
+			config.watch_registrations(&WatchScope::new(temp_dir.path(), &[]).unwrap()),
+			vec![(temp_dir.path().to_path_buf(), RecursiveMode::NonRecursive)]
+		);
+
+		assert!(files(Vec::new()).validate().is_err());
+		assert!(matches!(
+			files(vec![temp_dir.path().join("missing_dir/file.txt")]).validate(),
+			Err(WatcherError::InvalidPath { .. })
+		));
+	}
+
+	#[test]
+	fn test_tree_options_are_rejected_where_there_is_no_tree() {
+		let temp_dir = TempDir::new().unwrap();
+		let file = temp_dir.path().join("single.txt");
+		std::fs::write(&file, "x").unwrap();
+		let rejected = |config: WatcherConfig| match config.validate() {
+			Err(WatcherError::ConfigurationError { parameter, .. }) => parameter,
+			other => panic!("Expected ConfigurationError, got: {other:?}"),
+		};
+
+		// The default `recursive` is accepted for a file and turned off by `start`
+		let file_root = WatcherConfig { path: file.clone(), ..Default::default() };
+		assert!(file_root.validate().is_ok());
+		let on_device = WatcherConfig { stay_on_device: true, ..file_root };
+		assert_eq!(rejected(on_device), "stay_on_device");
+
+		let files = |files: Vec<PathBuf>| WatcherConfig {
+			targets: WatchTargets::Files(files),
+			..Default::default()
+		};
+		assert_eq!(
+			rejected(files(vec![file.clone(), temp_dir.path().to_path_buf()])),
+			"targets"
+		);
+		let on_device = WatcherConfig { stay_on_device: true, ..files(vec![file]) };
+		assert_eq!(rejected(on_device), "stay_on_device");
+
+		// The same options are fine for a directory tree
+		let tree = WatcherConfig {
+			path: temp_dir.path().to_path_buf(),
+			stay_on_device: true,
+			..Default::default()
+		};
+		assert!(tree.validate().is_ok());
+	}
+
+	/// A handle around `task` with the event loop's other channels left unconnected
+	fn test_handle(
+		stop_sender: oneshot::Sender<()>, task: crate::runtime::JoinHandle<()>,
+		database: Arc<std::sync::Mutex<Option<DatabaseAdapter>>>,
+	) -> WatcherHandle {
+		WatcherHandle {
+			stop_sender,
+			task,
+			database,
+			recent: Arc::new(RecentEvents::new(0)),
+			ready: tokio::sync::watch::channel(false).1,
+			ignore: IgnoreFilter::new(Path::new("/"), GlobSet::empty(), GlobSet::empty()),
+			metrics: Arc::default(),
+			watch_id: uuid::Uuid::new_v4(),
+			acks: None,
+			commands: mpsc::channel(1).0,
+			lifecycle: Default::default(),
+			known: Default::default(),
+		}
+	}
+
+	#[tokio::test]
+	async fn test_watcher_handle_creation() {
 		// Test that WatcherHandle can be created (unit test for the struct)
 		let (tx, _rx) = oneshot::channel();
-		let handle = WatcherHandle { stop_sender: tx };
+		let task = crate::runtime::spawn(async {});
+		let handle = test_handle(tx, task, Arc::new(std::sync::Mutex::new(None)));
 
 		// Test that handle exists and has expected structure
 		// We can't easily test the stop functionality without async runtime
 		assert!(std::mem::size_of_val(&handle) > 0);
 	}
+
+	#[test]
+	fn test_recursion_stops_at_a_device_boundary() {
+		let temp_dir = tempfile::tempdir().unwrap();
+		let root = temp_dir.path();
+		std::fs::create_dir_all(root.join("sub/deeper")).unwrap();
+		std::fs::create_dir_all(root.join("mnt/share/inner")).unwrap();
+		std::fs::write(root.join("mnt/share/remote.txt"), "remote").unwrap();
+		std::fs::write(root.join("sub/local.txt"), "local").unwrap();
+		// `mnt` stands in for a mount point: it and everything below it are on device 2
+		let mount = root.join("mnt");
+		let device_of = |path: &Path| Some(if path.starts_with(&mount) { 2 } else { 1 });
+
+		let mut watched = directories_on_device(root, 1, device_of);
+		watched.sort();
+		assert_eq!(
+			watched,
+			[root.to_path_buf(), root.join("sub"), root.join("sub/deeper")]
+		);
+
+		let walk = walkdir::WalkDir::new(root).min_depth(1);
+		let mut scanned: Vec<_> = walk_on_device(walk, Some(1), device_of)
+			.into_iter()
+			.map(|entry| entry.unwrap().into_path())
+			.collect();
+		scanned.sort();
+		// The mount point is an entry of its parent; nothing below it is
+		assert_eq!(
+			scanned,
+			[
+				mount.clone(),
+				root.join("sub"),
+				root.join("sub/deeper"),
+				root.join("sub/local.txt")
+			]
+		);
+		let walk = walkdir::WalkDir::new(root).min_depth(1);
+		assert_eq!(walk_on_device(walk, None, device_of).len(), 7);
+	}
+
+	#[test]
+	fn test_ignore_filter_matches_relative_paths_and_their_descendants() {
+		let filter = IgnoreFilter::new(
+			Path::new("/watched"),
+			IgnoreFilter::compile(&["target".to_string(), "*.tmp".to_string()]).unwrap(),
+			GlobSet::empty(),
+		);
+		assert!(filter.matches(Path::new("/watched/target")));
+		assert!(filter.matches(Path::new("/watched/target/debug/app")));
+		assert!(filter.matches(Path::new("/watched/src/edit.tmp")));
+		assert!(!filter.matches(Path::new("/watched/src/main.rs")));
+		// Only the part below the root is matched, so the root's own name never counts
+		assert!(!filter.matches(Path::new("/target/file.rs")));
+
+		// A bad pattern is rejected before anything is replaced
+		assert!(matches!(
+			IgnoreFilter::compile(&["src/[".to_string()]),
+			Err(WatcherError::ConfigurationError { .. })
+		));
+		filter.replace(IgnoreFilter::compile(&[]).unwrap());
+		assert!(!filter.matches(Path::new("/watched/target/debug/app")));
+	}
+
+	#[test]
+	fn test_default_sidecar_patterns_match_file_names_only() {
+		let sidecars = WatcherConfig::default().sidecar_patterns;
+		let filter = IgnoreFilter::new(
+			Path::new("/watched"),
+			GlobSet::empty(),
+			compile_globs("sidecar_patterns", &sidecars).unwrap(),
+		);
+		for sidecar in [
+			"~$report.docx",
+			".~lock.report.odt#",
+			".notes.txt.swp",
+			".notes.txt.swo",
+			"4913",
+			".#notes.txt",
+			"#notes.txt#",
+			"notes.txt~",
+		] {
+			assert!(
+				filter.matches(&Path::new("/watched/docs").join(sidecar)),
+				"{sidecar}"
+			);
+		}
+		for kept in ["report.docx", "notes.txt", ".gitignore", "swap.swp", "~/dir/file.txt"] {
+			assert!(
+				!filter.matches(&Path::new("/watched/docs").join(kept)),
+				"{kept}"
+			);
+		}
+		// Updating the ignore patterns leaves the sidecars filtered
+		filter.replace(IgnoreFilter::compile(&[]).unwrap());
+		assert!(filter.matches(Path::new("/watched/.notes.txt.swp")));
+	}
+
+	#[test]
+	fn test_catch_up_reports_each_path_once() {
+		let mut catch_up = SubdirectoryCatchUp::new();
+		let native = PathBuf::from("/watched/new/native.txt");
+		let scanned = PathBuf::from("/watched/new/scanned.txt");
+
+		// Native first: the scan must skip it
+		assert!(catch_up.record_native_create(&native));
+		assert!(!catch_up.record_scanned(&native));
+
+		// Scan first: the late native event must be dropped
+		assert!(catch_up.record_scanned(&scanned));
+		assert!(!catch_up.record_native_create(&scanned));
+
+		// Repeated native creates (delete + recreate) are not suppressed
+		assert!(catch_up.record_native_create(&native));
+	}
+
+	#[test]
+	fn test_catch_up_forgets_removed_paths() {
+		let mut catch_up = SubdirectoryCatchUp::new();
+		let path = PathBuf::from("/watched/existing.txt");
+
+		// Found by the scan, then deleted and recreated: the new file must be reported
+		assert!(catch_up.record_scanned(&path));
+		catch_up.forget(&path);
+		assert!(catch_up.record_native_create(&path));
+	}
+
+	#[test]
+	fn test_required_input_types_for_move_allowlist() {
+		let config = WatcherConfig { recursive: false, ..Default::default() }
+			.with_event_types([EventType::Move]);
+		let required = config.required_input_types().unwrap();
+		assert!(required.contains(&EventType::Create));
+		assert!(required.contains(&EventType::Remove));
+		assert!(required.contains(&EventType::RenameFrom));
+		assert!(!required.contains(&EventType::Write));
+
+		assert!(WatcherConfig::default().required_input_types().is_none());
+	}
+
+	#[test]
+	fn test_stabilizer_releases_after_quiet_period() {
+		let temp_dir = TempDir::new().unwrap();
+		let path = temp_dir.path().join("upload.bin");
+		std::fs::write(&path, vec![0u8; 16]).unwrap();
+
+		let mut stabilizer = WriteStabilizer::new(Duration::from_millis(20));
+		let create = FileSystemEvent::new(EventType::Create, path.clone(), false, Some(8));
+		let write = FileSystemEvent::new(EventType::Write, path.clone(), false, Some(16));
+		assert!(stabilizer.hold(&create));
+		assert!(stabilizer.hold(&write));
+
+		// Not quiet long enough yet
+		assert!(stabilizer.take_stable().is_empty());
+		std::thread::sleep(Duration::from_millis(30));
+		let ready = stabilizer.take_stable();
+		assert_eq!(ready.len(), 1);
+		assert_eq!(ready[0].event_type, EventType::Create);
+		assert_eq!(ready[0].id, create.id);
+		assert_eq!(ready[0].size, Some(16));
+		assert!(!stabilizer.has_pending());
+	}
+
+	#[test]
+	fn test_stabilizer_move_and_remove_cancel_hold() {
+		let mut stabilizer = WriteStabilizer::new(Duration::from_millis(20));
+		let source = PathBuf::from("/watched/partial.tmp");
+		let deleted = PathBuf::from("/watched/deleted.tmp");
+		assert!(stabilizer.hold(&FileSystemEvent::new(
+			EventType::Create,
+			source.clone(),
+			false,
+			None
+		)));
+		assert!(stabilizer.hold(&FileSystemEvent::new(
+			EventType::Create,
+			deleted.clone(),
+			false,
+			None
+		)));
+
+		let mut moved = FileSystemEvent::new(
+			EventType::Move,
+			PathBuf::from("/watched/final.bin"),
+			false,
+			None,
+		);
+		moved.move_data = Some(crate::events::MoveEvent::new(
+			source,
+			moved.path.clone(),
+			1.0,
+			crate::events::MoveDetectionMethod::FileSystemEvent,
+		));
+		assert!(!stabilizer.hold(&moved), "moves are delivered immediately");
+		assert!(!stabilizer.hold(&FileSystemEvent::new(
+			EventType::Remove,
+			deleted,
+			false,
+			None
+		)));
+		assert!(!stabilizer.has_pending());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_resolved_target_skips_plain_paths_and_dangling_links() {
+		let temp_dir = TempDir::new().unwrap();
+		let root = temp_dir.path().canonicalize().unwrap();
+		let (target, link, dangling) = (
+			root.join("target.txt"),
+			root.join("link.txt"),
+			root.join("dangling.txt"),
+		);
+		std::fs::write(&target, "data").unwrap();
+		std::os::unix::fs::symlink(&target, &link).unwrap();
+		std::os::unix::fs::symlink(root.join("missing.txt"), &dangling).unwrap();
+
+		assert_eq!(resolved_target(&link), Some(target.clone()));
+		assert_eq!(resolved_target(&target), None);
+		assert_eq!(resolved_target(&dangling), None);
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn test_identity_dedup_releases_a_held_link_before_its_remove() {
+		let temp_dir = TempDir::new().unwrap();
+		let (original, link) = (temp_dir.path().join("a.txt"), temp_dir.path().join("b.txt"));
+		std::fs::write(&original, "data").unwrap();
+		std::fs::hard_link(&original, &link).unwrap();
+		let mut dedup = IdentityDedup::new(Duration::from_secs(60));
+
+		let write = FileSystemEvent::new(EventType::Write, original.clone(), false, Some(4));
+		let create = FileSystemEvent::new(EventType::Create, link.clone(), false, Some(4));
+		assert!(dedup.hold(&write));
+		assert!(dedup.hold(&create));
+		let remove = FileSystemEvent::new(EventType::Remove, original.clone(), false, None);
+		assert!(!dedup.hold(&remove));
+
+		let released = dedup.take_touching(&remove);
+		assert_eq!(released.len(), 1);
+		assert_eq!(released[0].event_type, EventType::Create);
+		assert_eq!(released[0].path, original);
+		assert_eq!(released[0].identity_paths, vec![original, link]);
+		assert!(!dedup.has_pending());
+	}
+
+	#[tokio::test]
+	async fn test_stop_with_timeout_aborts_wedged_task() {
+		let temp_dir = TempDir::new().unwrap();
+		let adapter = DatabaseAdapter::new(DatabaseConfig {
+			database_path: temp_dir.path().join("wedged.redb"),
+			..Default::default()
+		})
+		.await
+		.unwrap();
+		let database = Arc::new(std::sync::Mutex::new(Some(adapter)));
+
+		// Stands in for a watcher stuck on a hung read: holds the stop receiver, never polls it
+		let (stop_sender, stop_rx) = oneshot::channel();
+		let task = crate::runtime::spawn(async move {
+			let _stop_rx = stop_rx;
+			crate::runtime::sleep(Duration::from_secs(3600)).await;
+		});
+		let handle = test_handle(stop_sender, task, database.clone());
+
+		let started = Instant::now();
+		let result = handle.stop_with_timeout(Duration::from_millis(50)).await;
+		assert!(matches!(result, Err(WatcherError::ShutdownTimeout { .. })));
+		assert!(started.elapsed() < Duration::from_secs(2));
+		assert!(
+			database.lock().unwrap().is_none(),
+			"database should be closed"
+		);
+	}
+
+	#[tokio::test]
+	async fn test_stop_with_timeout_does_not_wait_on_a_blocked_close() {
+		let temp_dir = TempDir::new().unwrap();
+		let adapter = DatabaseAdapter::new(DatabaseConfig {
+			database_path: temp_dir.path().join("blocked.redb"),
+			..Default::default()
+		})
+		.await
+		.unwrap();
+		// Stands in for a store call stuck inside the database
+		let _blocked_writer = adapter.lock_storage().await;
+		let database = Arc::new(std::sync::Mutex::new(Some(adapter)));
+
+		let (stop_sender, stop_rx) = oneshot::channel();
+		let task = crate::runtime::spawn(async move {
+			let _ = stop_rx.await;
+		});
+		let handle = test_handle(stop_sender, task, database.clone());
+
+		let started = Instant::now();
+		let result = handle.stop_with_timeout(Duration::from_millis(100)).await;
+		assert!(matches!(result, Err(WatcherError::ShutdownTimeout { .. })));
+		assert!(started.elapsed() < Duration::from_secs(2));
+	}
+
+	#[test]
+	fn test_own_database_filter() {
+		let temp_dir = TempDir::new().unwrap();
+		let root = temp_dir.path().canonicalize().unwrap();
+		let filter = OwnDatabaseFilter::new(&root, &root.join("watcher.redb")).unwrap();
+
+		assert!(filter.matches(&root.join("watcher.redb")));
+		assert!(filter.matches(&root.join("watcher.redb-journal")));
+		assert!(!filter.matches(&root.join("other.redb")));
+		assert!(!filter.matches(&root.join("nested").join("watcher.redb")));
+
+		let outside = TempDir::new().unwrap();
+		assert!(OwnDatabaseFilter::new(&root, &outside.path().join("watcher.redb")).is_none());
+	}
+
+	/// Takes a watch per directory like inotify, and fails past `limit` of them
+	struct LimitedWatcher {
+		limit: usize,
+		watched: BTreeSet<PathBuf>,
+	}
+
+	impl NotifyWatcher for LimitedWatcher {
+		fn new<F: notify::EventHandler>(_handler: F, _config: Config) -> notify::Result<Self> {
+			Ok(Self { limit: usize::MAX, watched: BTreeSet::new() })
+		}
+
+		fn watch(&mut self, path: &Path, mode: RecursiveMode) -> notify::Result<()> {
+			let walk = walkdir::WalkDir::new(path).follow_links(false);
+			let walk = match mode {
+				RecursiveMode::Recursive => walk,
+				RecursiveMode::NonRecursive => walk.max_depth(0),
+			};
+			for entry in walk.into_iter().filter_map(|entry| entry.ok()) {
+				if !entry.file_type().is_dir() {
+					continue;
+				}
+				if self.watched.len() == self.limit {
+					return Err(notify::Error::new(notify::ErrorKind::MaxFilesWatch));
+				}
+				self.watched.insert(entry.into_path());
+			}
+			Ok(())
+		}
+
+		fn unwatch(&mut self, path: &Path) -> notify::Result<()> {
+			self.watched.retain(|dir| !dir.starts_with(path));
+			Ok(())
+		}
+
+		fn kind() -> notify::WatcherKind {
+			notify::WatcherKind::Inotify
+		}
+	}
+
+	#[test]
+	fn test_watch_limit_degrades_to_the_subtrees_that_fit() {
+		let temp_dir = TempDir::new().unwrap();
+		let root = temp_dir.path().to_path_buf();
+		for dir in ["a/a1", "a/a2", "b/b1", "c"] {
+			std::fs::create_dir_all(root.join(dir)).unwrap();
+		}
+		let registrations = vec![(root.clone(), RecursiveMode::Recursive)];
+		let limited = |limit| LimitedWatcher { limit, watched: BTreeSet::new() };
+
+		let (diagnostics, mut diagnostics_rx) = DiagnosticsSender::channel();
+
+		// Without the option the limit is an error, as before
+		let mut watcher = limited(5);
+		assert!(register_watches(&mut watcher, &registrations, false, Some(&diagnostics)).is_err());
+		assert!(diagnostics_rx.try_recv().is_err());
+
+		// With it, the shallowest five directories are watched and the rest reported
+		let mut watcher = limited(5);
+		let unwatched =
+			register_watches(&mut watcher, &registrations, true, Some(&diagnostics)).unwrap();
+		assert_eq!(unwatched, vec![root.join("a/a2"), root.join("b/b1")]);
+		let expected: BTreeSet<PathBuf> =
+			["", "a", "b", "c", "a/a1"].iter().map(|dir| root.join(dir)).collect();
+		assert_eq!(watcher.watched, expected);
+		match diagnostics_rx.try_recv() {
+			Ok(WatcherDiagnostic::WatchesDegraded { unwatched: reported }) => {
+				assert_eq!(reported, unwatched)
+			}
+			other => panic!("expected WatchesDegraded, got {other:?}"),
+		}
+
+		// A tree within the limit is watched whole, with nothing to report
+		let mut watcher = limited(7);
+		let unwatched =
+			register_watches(&mut watcher, &registrations, true, Some(&diagnostics)).unwrap();
+		assert!(unwatched.is_empty());
+		assert!(diagnostics_rx.try_recv().is_err());
+	}
 }
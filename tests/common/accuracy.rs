@@ -0,0 +1,209 @@
+//! Precision/recall of move detection against scripted ground truth
+//!
+//! A [`Script`] performs real filesystem operations in a temp dir and feeds the events a
+//! backend without rename pairing would report (a Remove of the source, then a Create of the
+//! destination) to a [`MoveDetector`]. Every `Move` step is a ground-truth move; the
+//! [`MoveAccuracyEvaluator`] compares those with the moves the detector reported.
+//!
+//! Meant for justifying weight and threshold changes: run the baseline scripts with a
+//! candidate `MoveDetectorConfig` and compare the F1 scores.
+
+use rust_watcher::move_detection::test_helpers::DummyCache;
+use rust_watcher::{EventType, FileSystemEvent, MoveDetector, MoveDetectorConfig};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// One scripted operation; paths are relative to the script's temp dir
+#[derive(Debug, Clone)]
+pub enum Step {
+	Create(&'static str, &'static str),
+	/// A ground-truth move
+	Move(&'static str, &'static str),
+	Delete(&'static str),
+	Wait(Duration),
+}
+
+/// Named sequence of steps
+#[derive(Debug, Clone)]
+pub struct Script {
+	pub name: &'static str,
+	pub steps: Vec<Step>,
+}
+
+impl Script {
+	/// Run against a fresh detector with `config`, scoring its moves against the script's
+	pub async fn evaluate(&self, config: MoveDetectorConfig) -> AccuracyReport {
+		let dir = TempDir::new().expect("Failed to create temp directory");
+		let mut cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut cache);
+		let mut evaluator = MoveAccuracyEvaluator::default();
+		let event = |event_type, path: &Path| {
+			let size = std::fs::metadata(path).ok().map(|m| m.len());
+			FileSystemEvent::new(event_type, path.to_path_buf(), false, size)
+		};
+		for step in &self.steps {
+			match step {
+				Step::Create(path, content) => {
+					let path = dir.path().join(path);
+					std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+					std::fs::write(&path, content).unwrap();
+					evaluator
+						.record(&detector.process_event(event(EventType::Create, &path)).await);
+				}
+				Step::Move(from, to) => {
+					let (from, to) = (dir.path().join(from), dir.path().join(to));
+					std::fs::create_dir_all(to.parent().unwrap()).unwrap();
+					std::fs::rename(&from, &to).unwrap();
+					evaluator.expect_move(&from, &to);
+					evaluator
+						.record(&detector.process_event(event(EventType::Remove, &from)).await);
+					evaluator.record(&detector.process_event(event(EventType::Create, &to)).await);
+				}
+				Step::Delete(path) => {
+					let path = dir.path().join(path);
+					std::fs::remove_file(&path).unwrap();
+					evaluator
+						.record(&detector.process_event(event(EventType::Remove, &path)).await);
+				}
+				Step::Wait(duration) => tokio::time::sleep(*duration).await,
+			}
+		}
+		evaluator.record(&detector.take_expired_removes().await);
+		evaluator.report()
+	}
+}
+
+/// Collects ground-truth moves and detected moves
+#[derive(Debug, Default)]
+pub struct MoveAccuracyEvaluator {
+	expected: Vec<(PathBuf, PathBuf)>,
+	detected: Vec<(PathBuf, PathBuf)>,
+}
+
+impl MoveAccuracyEvaluator {
+	/// A move that really happened
+	pub fn expect_move(&mut self, from: &Path, to: &Path) {
+		self.expected.push((from.to_path_buf(), to.to_path_buf()));
+	}
+
+	/// Detector output; every event with move data counts as a detected move
+	pub fn record(&mut self, events: &[FileSystemEvent]) {
+		self.detected.extend(events.iter().filter_map(|event| {
+			let move_data = event.move_data.as_ref()?;
+			Some((
+				move_data.source_path.clone(),
+				move_data.destination_path.clone(),
+			))
+		}));
+	}
+
+	/// Match detected moves to expected ones; a repeated pair must be detected as often as it
+	/// happened
+	pub fn report(&self) -> AccuracyReport {
+		let mut unmatched: HashMap<&(PathBuf, PathBuf), usize> = HashMap::new();
+		for pair in &self.expected {
+			*unmatched.entry(pair).or_default() += 1;
+		}
+		let mut true_positives = 0;
+		for pair in &self.detected {
+			if let Some(count) = unmatched.get_mut(pair).filter(|count| **count > 0) {
+				*count -= 1;
+				true_positives += 1;
+			}
+		}
+		AccuracyReport {
+			true_positives,
+			false_positives: self.detected.len() - true_positives,
+			false_negatives: self.expected.len() - true_positives,
+		}
+	}
+}
+
+/// Confusion counts of detected moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccuracyReport {
+	pub true_positives: usize,
+	pub false_positives: usize,
+	pub false_negatives: usize,
+}
+
+impl AccuracyReport {
+	/// Share of detected moves that were real; 1.0 when nothing was detected
+	pub fn precision(&self) -> f64 {
+		ratio(
+			self.true_positives,
+			self.true_positives + self.false_positives,
+		)
+	}
+
+	/// Share of real moves that were detected; 1.0 when there were none
+	pub fn recall(&self) -> f64 {
+		ratio(
+			self.true_positives,
+			self.true_positives + self.false_negatives,
+		)
+	}
+
+	pub fn f1(&self) -> f64 {
+		let (precision, recall) = (self.precision(), self.recall());
+		if precision + recall == 0.0 {
+			0.0
+		} else {
+			2.0 * precision * recall / (precision + recall)
+		}
+	}
+}
+
+fn ratio(part: usize, whole: usize) -> f64 {
+	if whole == 0 {
+		1.0
+	} else {
+		part as f64 / whole as f64
+	}
+}
+
+/// Baseline scripts: chain moves, rapid back-and-forth renames, and a cross-directory
+/// reorganization with a deletion and an unrelated create mixed in
+pub fn baseline_scripts() -> Vec<Script> {
+	use Step::*;
+	vec![
+		Script {
+			name: "chain-move",
+			steps: vec![
+				Create("a.txt", "chained content"),
+				Move("a.txt", "b.txt"),
+				Move("b.txt", "c.txt"),
+				Move("c.txt", "d.txt"),
+			],
+		},
+		Script {
+			name: "rapid-cycle",
+			steps: vec![
+				Create("doc.md", "cycled document"),
+				Move("doc.md", "doc.md.bak"),
+				Move("doc.md.bak", "doc.md"),
+				Move("doc.md", "doc.md.bak"),
+				Move("doc.md.bak", "doc.md"),
+				Move("doc.md", "doc.md.bak"),
+				Move("doc.md.bak", "doc.md"),
+			],
+		},
+		Script {
+			name: "cross-dir",
+			steps: vec![
+				Create("src/one.rs", "fn one() {}"),
+				Create("src/two.rs", "fn two() { two() }"),
+				Create("src/three.rs", "fn three() { 3 + 3 }"),
+				Create("src/gone.rs", "deleted for good"),
+				Wait(Duration::from_millis(20)),
+				Move("src/one.rs", "lib/one.rs"),
+				Delete("src/gone.rs"),
+				Move("src/two.rs", "lib/two.rs"),
+				Create("lib/new.rs", "brand new file!!"),
+				Move("src/three.rs", "lib/three.rs"),
+			],
+		},
+	]
+}
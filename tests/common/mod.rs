@@ -8,6 +8,7 @@ use rust_watcher::database::{
 use std::path::PathBuf;
 use tempfile::TempDir;
 
+pub mod accuracy;
 mod db_integration;
 
 /// Create a temporary directory for testing
@@ -67,6 +68,13 @@ pub fn create_test_events(count: usize) -> Vec<rust_watcher::FileSystemEvent> {
 			size: Some((i % 100) as u64 * 1024),
 			timestamp: chrono::Utc::now(),
 			move_data: None,
+			timestamp_source: rust_watcher::TimestampSource::ProcessingTime,
+			ownership: None,
+			snapshot: false,
+			sequence: None,
+			identity_paths: Vec::new(),
+			startup: false,
+			resolved_path: None,
 		};
 		events.push(event);
 	}
@@ -1,7 +1,7 @@
 // Integration test for basic watcher functionality
 // Tests the public API of rust-watcher using only public interfaces
 
-use rust_watcher::{start, WatcherConfig};
+use rust_watcher::{start, EventType, WatcherConfig};
 use std::time::Duration;
 
 mod common;
@@ -16,6 +16,7 @@ async fn test_watcher_creation() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	// Test that watcher can be created without panicking
@@ -39,48 +40,32 @@ async fn test_watcher_basic_file_detection() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let (handle, mut receiver) = start(config).unwrap();
 
-	// Give watcher time to initialize
-	common::wait_for_events().await;
-
-	// Create a test file
+	// No sleep: once ready, a file created right away must be captured
+	handle.ready().await.unwrap();
 	let test_file = temp_dir.path().join("test.txt");
 	common::create_test_file(&test_file, "test content").unwrap();
 
-	// Wait for events
-	common::wait_for_events().await;
-
-	// Try to receive events (don't assert on count as it varies by platform)
-	let mut events_received = 0;
-	let timeout = Duration::from_millis(1000);
-	let start_time = std::time::Instant::now();
-
-	while start_time.elapsed() < timeout {
-		match tokio::time::timeout(Duration::from_millis(100), receiver.recv()).await {
-			Ok(Some(event)) => {
-				events_received += 1;
-				println!("Received event: {:?}", event.event_type); // Verify event has basic required fields
-				assert!(!event.id.to_string().is_empty());
-				assert!(!event.path.as_os_str().is_empty());
-
-				// If we got at least one event, test passes
-				if events_received >= 1 {
-					break;
-				}
-			}
-			Ok(None) => break,  // Channel closed
-			Err(_) => continue, // Timeout, try again
+	let mut create_seen = false;
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+	{
+		assert!(!event.id.to_string().is_empty());
+		if event.event_type == EventType::Create && event.path.ends_with("test.txt") {
+			create_seen = true;
+			break;
 		}
 	}
-
-	// Stop the watcher
 	handle.stop().await.unwrap();
 
-	println!("Integration test completed: received {events_received} events");
-	// Don't assert on specific count as file events vary by platform
+	assert!(
+		create_seen,
+		"file created right after ready() was not reported"
+	);
 }
 
 #[tokio::test]
@@ -94,6 +79,7 @@ async fn test_watcher_config_validation() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let result = start(valid_config);
@@ -102,3 +88,536 @@ async fn test_watcher_config_validation() {
 	let (handle, _receiver) = result.unwrap();
 	handle.stop().await.unwrap();
 }
+
+#[cfg(feature = "http-metrics")]
+#[tokio::test]
+async fn test_metrics_endpoint_serves_health_and_counters() {
+	use std::io::{Read, Write};
+
+	fn get(addr: std::net::SocketAddr, path: &str) -> String {
+		let mut stream = std::net::TcpStream::connect(addr).unwrap();
+		write!(
+			stream,
+			"GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+		)
+		.unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		response
+	}
+
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	let server = handle.serve_metrics(([127, 0, 0, 1], 0).into()).await.unwrap();
+	let addr = server.local_addr();
+
+	common::create_test_file(&temp_dir.path().join("counted.txt"), "x").unwrap();
+	tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+		.await
+		.unwrap()
+		.unwrap();
+
+	let (health, metrics) =
+		tokio::task::spawn_blocking(move || (get(addr, "/healthz"), get(addr, "/metrics")))
+			.await
+			.unwrap();
+	assert!(health.starts_with("HTTP/1.1 200"), "{health}");
+	assert!(metrics.starts_with("HTTP/1.1 200"), "{metrics}");
+	for name in [
+		"rust_watcher_up 1",
+		"rust_watcher_events_received_total",
+		"rust_watcher_events_delivered_total",
+		"rust_watcher_moves_detected_total",
+		"rust_watcher_persistence_errors_total",
+	] {
+		assert!(metrics.contains(name), "missing {name}: {metrics}");
+	}
+	assert!(handle.stats().events_delivered >= 1);
+
+	handle.stop().await.unwrap();
+	let health = tokio::task::spawn_blocking(move || get(addr, "/healthz")).await.unwrap();
+	assert!(health.starts_with("HTTP/1.1 503"), "{health}");
+}
+
+#[cfg(feature = "prometheus")]
+#[tokio::test]
+async fn test_registered_prometheus_metrics_follow_the_watcher() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	let registry = prometheus::Registry::new();
+	handle.register_prometheus(&registry).unwrap();
+	let watch_id = handle.watch_id().to_string();
+	// One registration per watch_id
+	assert!(handle.register_prometheus(&registry).is_err());
+
+	let value = |name: &str| {
+		let family = registry
+			.gather()
+			.into_iter()
+			.find(|family| family.get_name() == name)
+			.unwrap_or_else(|| panic!("{name} is not registered"));
+		let metric = &family.get_metric()[0];
+		let label = &metric.get_label()[0];
+		assert_eq!(label.get_name(), "watch_id");
+		assert_eq!(label.get_value(), watch_id);
+		match family.get_field_type() {
+			prometheus::proto::MetricType::GAUGE => metric.get_gauge().get_value(),
+			_ => metric.get_counter().get_value(),
+		}
+	};
+	assert_eq!(value("rust_watcher_up"), 1.0);
+	assert_eq!(value("rust_watcher_create_events_delivered_total"), 0.0);
+
+	common::create_test_file(&temp_dir.path().join("counted.txt"), "x").unwrap();
+	let event = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+		.await
+		.unwrap()
+		.unwrap();
+	assert_eq!(event.event_type, rust_watcher::EventType::Create);
+	let stats = handle.stats();
+	assert_eq!(
+		value("rust_watcher_events_delivered_total"),
+		stats.events_delivered as f64
+	);
+	assert_eq!(value("rust_watcher_create_events_delivered_total"), 1.0);
+	assert!(value("rust_watcher_events_received_total") >= 1.0);
+
+	handle.stop().await.unwrap();
+	assert_eq!(value("rust_watcher_up"), 0.0);
+}
+
+#[tokio::test]
+async fn test_event_sink_receives_every_delivered_event() {
+	use rust_watcher::{EventSink, FileSystemEvent};
+	use std::sync::{Arc, Mutex};
+
+	struct MemorySink(Arc<Mutex<Vec<FileSystemEvent>>>);
+
+	#[async_trait::async_trait]
+	impl EventSink for MemorySink {
+		async fn write(&self, event: &FileSystemEvent) -> rust_watcher::Result<()> {
+			self.0.lock().unwrap().push(event.clone());
+			Ok(())
+		}
+	}
+
+	let temp_dir = common::setup_temp_dir();
+	let mirrored = Arc::new(Mutex::new(Vec::new()));
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() }
+		.with_event_sink(MemorySink(mirrored.clone()));
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	for name in ["a.txt", "b.txt", "c.txt"] {
+		common::create_test_file(&temp_dir.path().join(name), "content").unwrap();
+	}
+	std::fs::rename(temp_dir.path().join("a.txt"), temp_dir.path().join("d.txt")).unwrap();
+	std::fs::remove_file(temp_dir.path().join("b.txt")).unwrap();
+
+	let mut received = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+	{
+		received.push(event.id);
+	}
+	handle.stop().await.unwrap();
+
+	assert!(!received.is_empty());
+	let mirrored: Vec<_> = mirrored.lock().unwrap().iter().map(|event| event.id).collect();
+	assert_eq!(mirrored, received);
+}
+
+/// inotify reports a rename as RenameFrom, RenameTo and a both-paths event; one Move comes out
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_rename_is_reported_once() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	let root = temp_dir.path().canonicalize().unwrap();
+	common::create_test_file(&root.join("old.txt"), "content").unwrap();
+	tokio::time::sleep(Duration::from_millis(200)).await;
+	std::fs::rename(root.join("old.txt"), root.join("new.txt")).unwrap();
+
+	let mut renames = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+	{
+		if event.path == root.join("new.txt") {
+			renames.push(event);
+		}
+	}
+	handle.stop().await.unwrap();
+
+	assert_eq!(renames.len(), 1, "{renames:?}");
+	assert_eq!(renames[0].event_type, EventType::Move);
+	let move_data = renames[0].move_data.as_ref().unwrap();
+	assert_eq!(move_data.source_path, root.join("old.txt"));
+	assert_eq!(move_data.confidence, 1.0);
+}
+
+#[tokio::test]
+async fn test_summaries_add_up_to_the_delivered_events() {
+	use rust_watcher::{start_with_diagnostics, SummaryEvent, WatcherDiagnostic};
+
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		summary_interval: Some(Duration::from_millis(100)),
+		..Default::default()
+	};
+	let (handle, mut receiver, mut diagnostics) = start_with_diagnostics(config).unwrap();
+	handle.ready().await.unwrap();
+
+	common::create_test_file(&temp_dir.path().join("a.txt"), "four").unwrap();
+	common::create_test_file(&temp_dir.path().join("b.txt"), "eight by").unwrap();
+	std::fs::remove_file(temp_dir.path().join("b.txt")).unwrap();
+
+	let mut expected = SummaryEvent::default();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+	{
+		match event.event_type {
+			EventType::Create => expected.creates += 1,
+			EventType::Write => expected.writes += 1,
+			EventType::Remove => expected.removes += 1,
+			_ => {}
+		}
+		if matches!(event.event_type, EventType::Create | EventType::Write) {
+			expected.bytes_changed += event.size.unwrap_or(0);
+		}
+	}
+	// Let one more period pass so the last delivered event is in some summary
+	tokio::time::sleep(Duration::from_millis(300)).await;
+	handle.stop().await.unwrap();
+
+	let mut total = SummaryEvent::default();
+	let mut summaries = 0;
+	while let Ok(diagnostic) = diagnostics.try_recv() {
+		if let WatcherDiagnostic::Summary(summary) = diagnostic {
+			summaries += 1;
+			total.creates += summary.creates;
+			total.writes += summary.writes;
+			total.removes += summary.removes;
+			total.moves += summary.moves;
+			total.bytes_changed += summary.bytes_changed;
+		}
+	}
+	assert!(summaries >= 2, "only {summaries} summaries");
+	assert_eq!(total, expected);
+	assert_eq!((total.creates, total.removes), (2, 1));
+}
+
+/// A file and its hard link are one file; with `identity_dedup` they are reported once
+#[cfg(unix)]
+#[tokio::test]
+async fn test_identity_dedup_reports_hard_links_once() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		identity_dedup: Some(Duration::from_millis(500)),
+		..Default::default()
+	};
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	let root = temp_dir.path().canonicalize().unwrap();
+	let (original, link) = (root.join("a.txt"), root.join("b.txt"));
+	common::create_test_file(&original, "one").unwrap();
+	std::fs::hard_link(&original, &link).unwrap();
+	{
+		use std::io::Write;
+		let mut file = std::fs::OpenOptions::new().append(true).open(&link).unwrap();
+		file.write_all(b" two").unwrap();
+	}
+
+	let mut events = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1500), receiver.recv()).await
+	{
+		events.push(event);
+	}
+	handle.stop().await.unwrap();
+
+	assert_eq!(events.len(), 1, "{events:?}");
+	assert_eq!(events[0].event_type, EventType::Create);
+	assert_eq!(events[0].path, original);
+	assert_eq!(events[0].identity_paths, vec![original, link]);
+	assert_eq!(events[0].size, Some(7));
+}
+
+/// `identity_dedup` windows run out under steady input, and held events survive `stop`
+#[cfg(unix)]
+#[tokio::test]
+async fn test_identity_dedup_releases_under_steady_input_and_on_stop() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		identity_dedup: Some(Duration::from_millis(200)),
+		..Default::default()
+	};
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	let root = temp_dir.path().canonicalize().unwrap();
+	let (settled, busy) = (root.join("settled.txt"), root.join("busy.txt"));
+	common::create_test_file(&settled, "done").unwrap();
+	let mut settled_seen = false;
+	for i in 0..200 {
+		std::fs::write(&busy, format!("chunk {i}")).unwrap();
+		tokio::time::sleep(Duration::from_millis(5)).await;
+		while let Ok(event) = receiver.try_recv() {
+			settled_seen |= event.path == settled;
+		}
+	}
+	assert!(
+		settled_seen,
+		"a window never ran out while events kept arriving"
+	);
+
+	let late = root.join("late.txt");
+	common::create_test_file(&late, "late").unwrap();
+	tokio::time::sleep(Duration::from_millis(50)).await;
+	handle.stop().await.unwrap();
+	let mut late_seen = false;
+	while let Some(event) = receiver.recv().await {
+		late_seen |= event.path == late;
+	}
+	assert!(late_seen, "a held event was dropped on stop");
+}
+
+/// Events observed during `startup_quiet_period` are delivered flagged, later ones are not
+#[tokio::test]
+async fn test_startup_quiet_period_flags_initial_churn() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		startup_quiet_period: Some(Duration::from_millis(800)),
+		..Default::default()
+	};
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	let root = temp_dir.path().canonicalize().unwrap();
+	common::create_test_file(&root.join("early.txt"), "early").unwrap();
+	tokio::time::sleep(Duration::from_millis(1200)).await;
+	common::create_test_file(&root.join("late.txt"), "late").unwrap();
+
+	let mut events = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+	{
+		events.push(event);
+	}
+	handle.stop().await.unwrap();
+
+	let for_file = |name: &str| -> Vec<_> {
+		let path = root.join(name);
+		events.iter().filter(|event| event.path == path).collect()
+	};
+	let (early, late) = (for_file("early.txt"), for_file("late.txt"));
+	assert!(!early.is_empty() && !late.is_empty(), "{events:?}");
+	assert!(early.iter().all(|event| event.startup), "{early:?}");
+	assert!(late.iter().all(|event| !event.startup), "{late:?}");
+}
+
+/// A file added to a directory, then the directory's own modification: the latter is only
+/// delivered with `emit_directory_modify`. The modification is made explicit (a new mtime),
+/// since not every backend reports the one implied by adding the file.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_directory_modify_events_are_opt_in() {
+	async fn run(
+		emit_directory_modify: bool,
+	) -> (std::path::PathBuf, Vec<rust_watcher::FileSystemEvent>) {
+		let temp_dir = common::setup_temp_dir();
+		let root = temp_dir.path().canonicalize().unwrap();
+		let dir = root.join("listing");
+		std::fs::create_dir(&dir).unwrap();
+		let config = WatcherConfig { path: root, emit_directory_modify, ..Default::default() };
+		let (handle, mut receiver) = start(config).unwrap();
+		handle.ready().await.unwrap();
+
+		common::create_test_file(&dir.join("entry.txt"), "entry").unwrap();
+		let modified = std::time::SystemTime::now() + Duration::from_secs(60);
+		std::fs::File::open(&dir).unwrap().set_modified(modified).unwrap();
+		let mut events = Vec::new();
+		while let Ok(Some(event)) =
+			tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+		{
+			events.push(event);
+		}
+		handle.stop().await.unwrap();
+		(dir, events)
+	}
+	let directory_writes = |dir: &std::path::Path, events: &[rust_watcher::FileSystemEvent]| {
+		events
+			.iter()
+			.filter(|event| {
+				event.path == dir && event.is_directory && event.event_type == EventType::Write
+			})
+			.count()
+	};
+
+	let (dir, events) = run(false).await;
+	let entry = dir.join("entry.txt");
+	assert!(events.iter().any(|event| event.path == entry), "{events:?}");
+	assert_eq!(directory_writes(&dir, &events), 0, "{events:?}");
+
+	let (dir, events) = run(true).await;
+	assert!(directory_writes(&dir, &events) > 0, "{events:?}");
+}
+
+/// With `stay_on_device` the tree is watched one directory at a time; changes in existing and
+/// new subdirectories are still reported
+#[cfg(unix)]
+#[tokio::test]
+async fn test_stay_on_device_still_follows_subdirectories() {
+	let temp_dir = common::setup_temp_dir();
+	let root = temp_dir.path().canonicalize().unwrap();
+	std::fs::create_dir_all(root.join("existing/nested")).unwrap();
+	let config = WatcherConfig { path: root.clone(), stay_on_device: true, ..Default::default() };
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	common::create_test_file(&root.join("existing/nested/a.txt"), "a").unwrap();
+	std::fs::create_dir(root.join("fresh")).unwrap();
+	tokio::time::sleep(Duration::from_millis(200)).await;
+	common::create_test_file(&root.join("fresh/b.txt"), "b").unwrap();
+
+	let mut paths = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+	{
+		paths.push(event.path);
+	}
+	handle.stop().await.unwrap();
+	for expected in [root.join("existing/nested/a.txt"), root.join("fresh/b.txt")] {
+		assert!(paths.contains(&expected), "{expected:?} not in {paths:?}");
+	}
+}
+
+#[tokio::test]
+async fn test_coalescing_engages_while_the_consumer_lags() {
+	use rust_watcher::{start_with_diagnostics, AdaptiveCoalescing, WatcherDiagnostic};
+	use std::collections::HashSet;
+
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		adaptive_coalescing: Some(AdaptiveCoalescing {
+			quiet_period: Duration::from_millis(50),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let (handle, mut receiver, mut diagnostics) = start_with_diagnostics(config).unwrap();
+	handle.ready().await.unwrap();
+
+	// Nothing reads the events yet, so the channel fills up
+	let files = common::create_test_files(temp_dir.path(), 150).unwrap();
+	let engaged = tokio::time::timeout(Duration::from_secs(5), async {
+		loop {
+			match diagnostics.recv().await {
+				Some(WatcherDiagnostic::Backpressure { coalescing: true, fill }) => break fill,
+				Some(_) => continue,
+				None => panic!("diagnostics closed before coalescing engaged"),
+			}
+		}
+	})
+	.await
+	.expect("coalescing did not engage");
+	assert!(engaged >= 0.8, "engaged at {engaged}");
+
+	let mut reported = HashSet::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1000), receiver.recv()).await
+	{
+		reported.insert(event.path.file_name().unwrap().to_owned());
+	}
+	// Released once the channel has drained, without waiting for another event
+	let released = tokio::time::timeout(Duration::from_secs(2), async {
+		loop {
+			match diagnostics.recv().await {
+				Some(WatcherDiagnostic::Backpressure { coalescing: false, .. }) => break,
+				Some(_) => continue,
+				None => panic!("diagnostics closed before coalescing was released"),
+			}
+		}
+	})
+	.await;
+	assert!(
+		released.is_ok(),
+		"coalescing was not released once the channel drained"
+	);
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(500), receiver.recv()).await
+	{
+		reported.insert(event.path.file_name().unwrap().to_owned());
+	}
+	handle.stop().await.unwrap();
+
+	for file in &files {
+		assert!(
+			reported.contains(file.file_name().unwrap()),
+			"{file:?} was never reported"
+		);
+	}
+}
+
+/// Coalescing is released once the consumer drains the channel, even if no event follows
+#[tokio::test]
+async fn test_coalescing_is_released_without_another_event() {
+	use rust_watcher::{start_with_diagnostics, AdaptiveCoalescing, WatcherDiagnostic};
+
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		adaptive_coalescing: Some(AdaptiveCoalescing {
+			engage_at: 0.2,
+			release_at: 0.05,
+			quiet_period: Duration::from_millis(50),
+		}),
+		..Default::default()
+	};
+	let (handle, mut receiver, mut diagnostics) = start_with_diagnostics(config).unwrap();
+	handle.ready().await.unwrap();
+
+	// Few enough events that the watcher never waits on the channel and is idle once they
+	// are in; the last delivery it made saw the channel above `release_at`
+	common::create_test_files(temp_dir.path(), 20).unwrap();
+	let backpressure = |diagnostic: &WatcherDiagnostic| match diagnostic {
+		WatcherDiagnostic::Backpressure { coalescing, .. } => Some(*coalescing),
+		_ => None,
+	};
+	let engaged = tokio::time::timeout(Duration::from_secs(5), async {
+		while let Some(diagnostic) = diagnostics.recv().await {
+			if backpressure(&diagnostic) == Some(true) {
+				return;
+			}
+		}
+	})
+	.await;
+	assert!(engaged.is_ok(), "coalescing did not engage");
+	tokio::time::sleep(Duration::from_millis(500)).await;
+
+	while tokio::time::timeout(Duration::from_millis(200), receiver.recv())
+		.await
+		.is_ok_and(|event| event.is_some())
+	{}
+	let released = tokio::time::timeout(Duration::from_secs(2), async {
+		while let Some(diagnostic) = diagnostics.recv().await {
+			if backpressure(&diagnostic) == Some(false) {
+				return;
+			}
+		}
+	})
+	.await;
+	handle.stop().await.unwrap();
+	assert!(
+		released.is_ok(),
+		"coalescing stayed on after the channel drained"
+	);
+}
@@ -21,6 +21,7 @@ fn make_watch(root: &str) -> WatchMetadata {
 		node_count: 0,
 		is_active: true,
 		config_hash: 0,
+		event_retention: None,
 		permissions: Some(WatchPermissions {
 			can_read: true,
 			can_write: true,
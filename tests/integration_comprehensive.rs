@@ -1,7 +1,10 @@
 // Integration tests for comprehensive watcher functionality
 // Tests the public API with various scenarios using only public interfaces
 
-use rust_watcher::{start, MoveDetectorConfig, WatcherConfig};
+use rust_watcher::{
+	start, EventType, KnownOperation, MoveDetectorConfig, MovedToIgnoredPolicy, TimestampSource,
+	WatchTargets, WatcherConfig,
+};
 
 mod common;
 
@@ -15,6 +18,7 @@ async fn test_basic_file_creation_detection() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let (handle, mut event_receiver) = start(config).unwrap();
@@ -66,6 +70,7 @@ async fn test_file_move_detection_with_config() {
 		move_detector_config: Some(move_config),
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let (handle, mut event_receiver) = start(config).unwrap();
@@ -119,6 +124,7 @@ async fn test_recursive_directory_watching() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let (handle, mut event_receiver) = start(config).unwrap();
@@ -162,6 +168,264 @@ async fn test_recursive_directory_watching() {
 	// Main goal is ensuring no crashes with recursive watching
 }
 
+#[tokio::test]
+async fn test_file_in_new_subdirectory_is_reported() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		watch_id: uuid::Uuid::new_v4(),
+		path: temp_dir.path().to_path_buf(),
+		recursive: true,
+		move_detector_config: None,
+		error_recovery_config: None,
+		database_config: None,
+		..Default::default()
+	};
+
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	// Write the file immediately after the directory so it races the new watch
+	let sub_dir = temp_dir.path().join("late_subdir");
+	std::fs::create_dir(&sub_dir).unwrap();
+	let inner_file = sub_dir.join("inner.txt");
+	common::create_test_file(&inner_file, "inner content").unwrap();
+
+	let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+	let mut inner_creates = 0;
+	while std::time::Instant::now() < deadline {
+		match tokio::time::timeout(std::time::Duration::from_millis(300), event_receiver.recv())
+			.await
+		{
+			Ok(Some(event)) => {
+				if event.path == inner_file && event.event_type == EventType::Create {
+					inner_creates += 1;
+				}
+			}
+			Ok(None) => break,
+			Err(_) if inner_creates > 0 => break,
+			Err(_) => continue,
+		}
+	}
+
+	handle.stop().await.unwrap();
+	assert_eq!(
+		inner_creates, 1,
+		"File created inside a new subdirectory should be reported exactly once"
+	);
+}
+
+#[tokio::test]
+async fn test_event_type_allowlist_excludes_writes() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() }
+		.with_event_types([EventType::Create, EventType::Remove]);
+
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	let test_file = temp_dir.path().join("allowlisted.txt");
+	common::create_test_file(&test_file, "first").unwrap();
+	common::wait_for_events().await;
+	for i in 0..5 {
+		common::create_test_file(&test_file, &format!("rewrite {i}")).unwrap();
+	}
+	common::wait_for_events().await;
+	std::fs::remove_file(&test_file).unwrap();
+
+	let mut seen = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(std::time::Duration::from_millis(500), event_receiver.recv()).await
+	{
+		seen.push(event.event_type);
+	}
+	handle.stop().await.unwrap();
+
+	assert!(
+		!seen.contains(&EventType::Write),
+		"Write events must be filtered out: {seen:?}"
+	);
+	assert!(
+		seen.iter().all(|t| matches!(t, EventType::Create | EventType::Remove)),
+		"Only allowlisted types may be delivered: {seen:?}"
+	);
+	assert!(
+		seen.contains(&EventType::Create),
+		"Create should be delivered: {seen:?}"
+	);
+}
+
+#[tokio::test]
+async fn test_stabilize_writes_emits_single_event_after_copy() {
+	use std::io::Write;
+
+	let temp_dir = common::setup_temp_dir();
+	let quiet_period = std::time::Duration::from_millis(300);
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		stabilize_writes: Some(quiet_period),
+		..Default::default()
+	};
+
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	// Simulate a slow copy: ten chunks with pauses shorter than the quiet period
+	let target = temp_dir.path().join("large_upload.bin");
+	let mut file = std::fs::File::create(&target).unwrap();
+	for _ in 0..10 {
+		file.write_all(&[7u8; 4096]).unwrap();
+		file.flush().unwrap();
+		tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+	}
+	drop(file);
+	let writes_done = std::time::Instant::now();
+
+	let mut target_events = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(std::time::Duration::from_secs(2), event_receiver.recv()).await
+	{
+		// Access/close notifications are not held; only Create/Write are coalesced
+		if event.path == target && matches!(event.event_type, EventType::Create | EventType::Write)
+		{
+			target_events.push((event, writes_done.elapsed()));
+		}
+	}
+	handle.stop().await.unwrap();
+
+	assert_eq!(
+		target_events.len(),
+		1,
+		"Expected one stable event, got {:?}",
+		target_events.iter().map(|(e, _)| &e.event_type).collect::<Vec<_>>()
+	);
+	let (event, emitted_after) = &target_events[0];
+	assert_eq!(event.event_type, EventType::Create);
+	assert_eq!(
+		event.size,
+		Some(10 * 4096),
+		"Stable event should carry the final size"
+	);
+	assert!(
+		*emitted_after >= quiet_period / 2,
+		"Event emitted before writes settled: {emitted_after:?}"
+	);
+}
+
+#[tokio::test]
+async fn test_stabilized_writes_settle_under_steady_input_and_survive_stop() {
+	let temp_dir = common::setup_temp_dir();
+	let quiet_period = std::time::Duration::from_millis(200);
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		stabilize_writes: Some(quiet_period),
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	// `settled.txt` goes quiet while `busy.txt` keeps the loop busy well past the quiet period
+	let settled = temp_dir.path().join("settled.txt");
+	let busy = temp_dir.path().join("busy.txt");
+	common::create_test_file(&settled, "done").unwrap();
+	// Access/close notifications are not held; only Create/Write are
+	let held = |event: &rust_watcher::FileSystemEvent, path: &std::path::Path| {
+		event.path == path && matches!(event.event_type, EventType::Create | EventType::Write)
+	};
+	let mut settled_seen = false;
+	for i in 0..200 {
+		std::fs::write(&busy, format!("chunk {i}")).unwrap();
+		tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+		while let Ok(event) = event_receiver.try_recv() {
+			settled_seen |= held(&event, &settled);
+		}
+	}
+	assert!(
+		settled_seen,
+		"a quiet file was held for as long as other events kept arriving"
+	);
+
+	// Still held when the watcher stops, and delivered rather than dropped
+	let late = temp_dir.path().join("late.txt");
+	common::create_test_file(&late, "late").unwrap();
+	common::wait_for_events().await;
+	handle.stop().await.unwrap();
+	let mut late_seen = false;
+	while let Some(event) = event_receiver.recv().await {
+		late_seen |= held(&event, &late);
+	}
+	assert!(late_seen, "a held write was dropped on stop");
+}
+
+#[tokio::test]
+async fn test_directory_moved_within_the_tree_is_not_rescanned() {
+	let temp_dir = common::setup_temp_dir();
+	let before = temp_dir.path().join("before");
+	std::fs::create_dir(&before).unwrap();
+	for name in ["a.txt", "b.txt"] {
+		common::create_test_file(&before.join(name), "existing").unwrap();
+	}
+	let config = WatcherConfig {
+		watch_id: uuid::Uuid::new_v4(),
+		path: temp_dir.path().to_path_buf(),
+		recursive: true,
+		move_detector_config: Some(MoveDetectorConfig::default()),
+		..Default::default()
+	};
+
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+	let after = temp_dir.path().join("after");
+	std::fs::rename(&before, &after).unwrap();
+	common::wait_for_events().await;
+
+	// It is still watched at its new place
+	let fresh = after.join("fresh.txt");
+	common::create_test_file(&fresh, "new").unwrap();
+	common::wait_for_events().await;
+	handle.stop().await.unwrap();
+	let mut events = Vec::new();
+	while let Some(event) = event_receiver.recv().await {
+		events.push(event);
+	}
+
+	assert!(
+		events.iter().any(|e| e.path == after && e.event_type == EventType::Move),
+		"Expected the directory move, got {events:?}"
+	);
+	// The other children were already there; the move must not report them as new
+	let created: Vec<_> = events
+		.iter()
+		.filter(|e| e.path.starts_with(&after) && e.event_type == EventType::Create)
+		.map(|e| e.path.clone())
+		.collect();
+	assert_eq!(
+		created,
+		vec![fresh],
+		"Moved directory's entries were reported as created"
+	);
+}
+
+#[tokio::test]
+async fn test_default_config_watches_a_single_file() {
+	let temp_dir = common::setup_temp_dir();
+	let file = temp_dir.path().join("single.txt");
+	common::create_test_file(&file, "before").unwrap();
+	// `recursive` keeps its default of true; a file root must still start
+	let config = WatcherConfig { path: file.clone(), ..Default::default() };
+
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+	common::create_test_file(&file, "after").unwrap();
+	common::wait_for_events().await;
+	handle.stop().await.unwrap();
+
+	let mut seen = false;
+	while let Some(event) = event_receiver.recv().await {
+		seen |= event.path.file_name() == file.file_name();
+	}
+	assert!(seen, "no event for the watched file");
+}
+
 #[tokio::test]
 async fn test_non_recursive_directory_watching() {
 	let temp_dir = common::setup_temp_dir();
@@ -172,6 +436,7 @@ async fn test_non_recursive_directory_watching() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let (handle, _event_receiver) = start(config).unwrap();
@@ -200,3 +465,790 @@ async fn test_non_recursive_directory_watching() {
 
 	// Main goal is ensuring non-recursive mode works without crashes
 }
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[tokio::test]
+async fn test_rename_exchange_reported_as_exchange_pair() {
+	use nix::fcntl::{renameat2, RenameFlags};
+	use rust_watcher::MoveDetectionMethod;
+
+	let temp_dir = common::setup_temp_dir();
+	let first = temp_dir.path().join("first.txt");
+	let second = temp_dir.path().join("second.txt");
+	common::create_test_file(&first, "first content").unwrap();
+	common::create_test_file(&second, "second content, longer").unwrap();
+
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	renameat2(None, &first, None, &second, RenameFlags::RENAME_EXCHANGE).unwrap();
+	assert_eq!(
+		std::fs::read_to_string(&first).unwrap(),
+		"second content, longer"
+	);
+
+	let mut moves = Vec::new();
+	let mut creates_and_removes = Vec::new();
+	let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+	while std::time::Instant::now() < deadline {
+		match tokio::time::timeout(std::time::Duration::from_millis(500), event_receiver.recv())
+			.await
+		{
+			Ok(Some(event)) => match event.event_type {
+				EventType::Move => moves.push(event.move_data.unwrap()),
+				EventType::Create | EventType::Remove => creates_and_removes.push(event),
+				_ => {}
+			},
+			Ok(None) => break,
+			Err(_) if !moves.is_empty() => break,
+			Err(_) => continue,
+		}
+	}
+	handle.stop().await.unwrap();
+
+	assert!(
+		creates_and_removes.is_empty(),
+		"phantom events: {creates_and_removes:?}"
+	);
+	assert_eq!(
+		moves.len(),
+		2,
+		"expected both halves of the exchange: {moves:?}"
+	);
+	assert_eq!(moves[0].source_path, first);
+	assert_eq!(moves[0].destination_path, second);
+	assert_eq!(moves[1].source_path, second);
+	assert_eq!(moves[1].destination_path, first);
+	assert!(moves.iter().all(|m| m.detection_method == MoveDetectionMethod::Exchange));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_relative_watch_path_yields_canonical_event_paths() {
+	let temp_dir = common::setup_temp_dir();
+	let canonical_root = temp_dir.path().canonicalize().unwrap();
+
+	// Reach the temp dir from the working directory via `..` so the configured path is relative
+	let cwd = std::env::current_dir().unwrap();
+	let mut relative = std::path::PathBuf::new();
+	for _ in cwd.components().skip(1) {
+		relative.push("..");
+	}
+	relative.push(canonical_root.strip_prefix("/").unwrap());
+	relative.push(".");
+
+	let config = WatcherConfig { path: relative, ..Default::default() };
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	let sub_dir = canonical_root.join("nested");
+	std::fs::create_dir(&sub_dir).unwrap();
+	common::create_test_file(&sub_dir.join("inner.txt"), "inner").unwrap();
+	common::create_test_file(&canonical_root.join("top.txt"), "top").unwrap();
+	std::fs::rename(canonical_root.join("top.txt"), sub_dir.join("moved.txt")).unwrap();
+
+	let mut paths = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1000),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		paths.push(event.path.clone());
+		if let Some(move_data) = event.move_data {
+			paths.push(move_data.source_path);
+			paths.push(move_data.destination_path);
+		}
+	}
+	handle.stop().await.unwrap();
+
+	assert!(!paths.is_empty(), "expected events for the changes");
+	for path in &paths {
+		assert!(
+			path.starts_with(&canonical_root)
+				&& !path.components().any(|c| {
+					matches!(
+						c,
+						std::path::Component::CurDir | std::path::Component::ParentDir
+					)
+				}),
+			"event path {path:?} is not under the canonical root {canonical_root:?}"
+		);
+	}
+}
+
+#[tokio::test]
+async fn test_database_inside_watched_dir_is_not_reported() {
+	let temp_dir = common::setup_temp_dir();
+	let database_path = temp_dir.path().join("watcher.redb");
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() }
+		.with_database(rust_watcher::DatabaseConfig {
+			database_path: database_path.clone(),
+			..Default::default()
+		});
+
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	// Every stored event is a database write, so any leak would show up repeatedly
+	let marker = temp_dir.path().join("marker.txt");
+	for i in 0..3 {
+		common::create_test_file(&marker, &format!("write {i}")).unwrap();
+	}
+
+	let mut paths = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1000),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		paths.push(event.path);
+	}
+	handle.stop().await.unwrap();
+
+	assert!(
+		paths.iter().any(|p| p.ends_with("marker.txt")),
+		"marker events missing: {paths:?}"
+	);
+	assert!(
+		!paths.iter().any(|p| p
+			.file_name()
+			.is_some_and(|n| n.to_string_lossy().starts_with("watcher.redb"))),
+		"events reported for the database file: {paths:?}"
+	);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_capture_ownership_attaches_current_uid() {
+	use std::os::unix::fs::MetadataExt;
+
+	let temp_dir = common::setup_temp_dir();
+	// Whatever created the temp dir is this process, so its owner is our effective uid
+	let expected_uid = std::fs::metadata(temp_dir.path()).unwrap().uid();
+
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		capture_ownership: true,
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	let test_file = temp_dir.path().join("owned.txt");
+	common::create_test_file(&test_file, "owned").unwrap();
+
+	let mut ownership = None;
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1000),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		if event.path.ends_with("owned.txt") {
+			ownership = event.ownership;
+			break;
+		}
+	}
+	handle.stop().await.unwrap();
+
+	let ownership = ownership.expect("expected an event carrying ownership for the new file");
+	assert_eq!(ownership.uid, expected_uid);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_resolve_targets_reports_the_path_behind_a_symlink() {
+	let temp_dir = common::setup_temp_dir();
+	let outside = common::setup_temp_dir();
+	let real_file = outside.path().join("real.txt");
+	common::create_test_file(&real_file, "before").unwrap();
+	std::os::unix::fs::symlink(outside.path(), temp_dir.path().join("linked")).unwrap();
+
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		resolve_targets: true,
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	// Written through the link; the backend follows it into the directory it points to
+	std::fs::write(temp_dir.path().join("linked/real.txt"), "after").unwrap();
+	let mut resolved = None;
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		if event.path.ends_with("linked/real.txt") {
+			resolved = Some(event.resolved_path);
+			break;
+		}
+	}
+	handle.stop().await.unwrap();
+
+	let resolved = resolved.expect("no event for the file written through the link");
+	assert_eq!(resolved, Some(real_file.canonicalize().unwrap()));
+}
+
+#[tokio::test]
+async fn test_recent_events_keeps_only_newest_in_order() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		recent_events_capacity: 5,
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	for i in 0..12 {
+		common::create_test_file(&temp_dir.path().join(format!("recent_{i}.txt")), "x").unwrap();
+		tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+	}
+
+	let mut received = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1000),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		received.push(event.id);
+	}
+	assert!(
+		received.len() > 5,
+		"expected more events than the buffer holds"
+	);
+
+	let recent: Vec<_> = handle.recent_events().into_iter().map(|event| event.id).collect();
+	handle.stop().await.unwrap();
+
+	assert_eq!(recent, received[received.len() - 5..]);
+}
+
+#[tokio::test]
+async fn test_emit_existing_on_start_reports_each_file_once() {
+	let temp_dir = common::setup_temp_dir();
+	for i in 0..300 {
+		common::create_test_file(&temp_dir.path().join(format!("existing_{i}.txt")), "x").unwrap();
+	}
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		emit_existing_on_start: true,
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+
+	// Spread across startup so some land before the watch, some mid-scan and some after
+	let root = temp_dir.path().to_path_buf();
+	let writer = tokio::spawn(async move {
+		for i in 0..20 {
+			common::create_test_file(&root.join(format!("live_{i}.txt")), "y").unwrap();
+			tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+		}
+	});
+
+	let mut creates: std::collections::HashMap<std::path::PathBuf, usize> =
+		std::collections::HashMap::new();
+	let mut snapshot_paths = std::collections::HashSet::new();
+	let mut live_seen = false;
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		if event.event_type == EventType::Create {
+			*creates.entry(event.path.clone()).or_default() += 1;
+		}
+		if event.snapshot {
+			assert!(
+				!live_seen,
+				"snapshot event {:?} after a live one",
+				event.path
+			);
+			snapshot_paths.insert(event.path.clone());
+		} else {
+			live_seen = true;
+		}
+	}
+	writer.await.unwrap();
+	handle.stop().await.unwrap();
+
+	let canonical_root = temp_dir.path().canonicalize().unwrap();
+	for name in (0..300)
+		.map(|i| format!("existing_{i}.txt"))
+		.chain((0..20).map(|i| format!("live_{i}.txt")))
+	{
+		let count = creates.get(&canonical_root.join(&name)).copied().unwrap_or(0);
+		assert_eq!(count, 1, "{name} reported {count} times");
+	}
+	for i in 0..300 {
+		let path = canonical_root.join(format!("existing_{i}.txt"));
+		assert!(
+			snapshot_paths.contains(&path),
+			"{path:?} not flagged as snapshot"
+		);
+	}
+}
+
+#[tokio::test]
+async fn test_watch_targets_files_reports_only_listed_files() {
+	let temp_dir = common::setup_temp_dir();
+	for i in 0..50 {
+		common::create_test_file(&temp_dir.path().join(format!("other_{i}.txt")), "x").unwrap();
+	}
+	let existing = temp_dir.path().join("watched.txt");
+	common::create_test_file(&existing, "watched").unwrap();
+	// Not there yet: watching the parent lets its creation be reported too
+	let future = temp_dir.path().join("later.txt");
+
+	let config = WatcherConfig {
+		targets: WatchTargets::Files(vec![existing.clone(), future.clone()]),
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	for i in 0..50 {
+		common::create_test_file(&temp_dir.path().join(format!("other_{i}.txt")), "changed")
+			.unwrap();
+	}
+	std::fs::remove_file(&existing).unwrap();
+	tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+	common::create_test_file(&existing, "recreated").unwrap();
+	common::create_test_file(&future, "new").unwrap();
+
+	let mut events = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		events.push(event);
+	}
+	handle.stop().await.unwrap();
+
+	let root = temp_dir.path().canonicalize().unwrap();
+	let (existing, future) = (root.join("watched.txt"), root.join("later.txt"));
+	for event in &events {
+		assert!(
+			event.path == existing || event.path == future,
+			"unexpected event for {:?}",
+			event.path
+		);
+	}
+	let seen = |path: &std::path::Path, event_type: EventType| {
+		events.iter().any(|event| event.path == path && event.event_type == event_type)
+	};
+	assert!(
+		seen(&existing, EventType::Remove),
+		"deletion not reported: {events:?}"
+	);
+	assert!(
+		seen(&existing, EventType::Create),
+		"recreation not reported: {events:?}"
+	);
+	assert!(
+		seen(&future, EventType::Create),
+		"new target not reported: {events:?}"
+	);
+}
+
+#[tokio::test]
+async fn test_update_ignore_patterns_stops_matching_events() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	let drain = |receiver: &mut tokio::sync::mpsc::Receiver<rust_watcher::FileSystemEvent>| {
+		let mut paths = Vec::new();
+		while let Ok(event) = receiver.try_recv() {
+			paths.push(event.path);
+		}
+		paths
+	};
+
+	common::create_test_file(&temp_dir.path().join("before.log"), "x").unwrap();
+	common::wait_for_events().await;
+	assert!(drain(&mut event_receiver).iter().any(|path| path.ends_with("before.log")));
+
+	// A bad glob is rejected and leaves the current (empty) set in place
+	assert!(handle.update_ignore_patterns(vec!["[".to_string()]).is_err());
+	handle
+		.update_ignore_patterns(vec!["*.log".to_string(), "build".to_string()])
+		.unwrap();
+
+	common::create_test_file(&temp_dir.path().join("after.log"), "x").unwrap();
+	std::fs::create_dir(temp_dir.path().join("build")).unwrap();
+	common::create_test_file(&temp_dir.path().join("build/output.bin"), "x").unwrap();
+	common::create_test_file(&temp_dir.path().join("kept.txt"), "x").unwrap();
+	common::wait_for_events().await;
+	let paths = drain(&mut event_receiver);
+	handle.stop().await.unwrap();
+
+	assert!(
+		paths.iter().any(|path| path.ends_with("kept.txt")),
+		"{paths:?}"
+	);
+	assert!(
+		!paths.iter().any(|path| path.ends_with("after.log")
+			|| path.components().any(|c| c.as_os_str() == "build")),
+		"ignored paths were reported: {paths:?}"
+	);
+}
+
+#[tokio::test]
+async fn test_editor_swap_files_are_filtered() {
+	let temp_dir = common::setup_temp_dir();
+	let document = temp_dir.path().join("notes.txt");
+	common::create_test_file(&document, "first draft").unwrap();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		move_detector_config: Some(MoveDetectorConfig::default()),
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	// What vim does around a save: swap file up, document rewritten, swap file gone
+	let swap = temp_dir.path().join(".notes.txt.swp");
+	common::create_test_file(&swap, "swap contents").unwrap();
+	std::fs::write(&document, "second draft").unwrap();
+	std::fs::remove_file(&swap).unwrap();
+	common::wait_for_events().await;
+	handle.stop().await.unwrap();
+
+	let mut events = Vec::new();
+	while let Ok(event) = event_receiver.try_recv() {
+		events.push(event);
+	}
+	assert!(
+		events.iter().any(|event| event.path == document),
+		"the edit was not reported: {events:?}"
+	);
+	assert!(
+		!events.iter().any(|event| event.path == swap
+			|| event.move_data.as_ref().is_some_and(|m| m.source_path == swap)),
+		"swap file events were reported: {events:?}"
+	);
+}
+
+// Needs the source and destination in one backend rename event, which only inotify provides
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_move_into_ignored_directory_reports_marker() {
+	let temp_dir = common::setup_temp_dir();
+	std::fs::create_dir(temp_dir.path().join("target")).unwrap();
+	let source = temp_dir.path().join("report.txt");
+	common::create_test_file(&source, "contents").unwrap();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		ignore_patterns: vec!["target".to_string()],
+		moved_to_ignored: MovedToIgnoredPolicy::Marker,
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	std::fs::rename(&source, temp_dir.path().join("target/report.txt")).unwrap();
+	let mut events = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		events.push(event);
+	}
+	handle.stop().await.unwrap();
+
+	let root = temp_dir.path().canonicalize().unwrap();
+	let marker = events
+		.iter()
+		.find(|event| event.event_type == EventType::MovedToIgnored)
+		.unwrap_or_else(|| panic!("no MovedToIgnored event: {events:?}"));
+	assert_eq!(marker.path, root.join("report.txt"));
+	let move_data = marker.move_data.as_ref().unwrap();
+	assert_eq!(move_data.destination_path, root.join("target/report.txt"));
+	assert!(!marker.is_directory);
+	assert!(
+		!events.iter().any(|event| event.event_type == EventType::Remove
+			|| event.path.starts_with(root.join("target"))),
+		"source also reported as removed, or ignored path leaked: {events:?}"
+	);
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_move_into_ignored_directory_reports_remove_by_default() {
+	let temp_dir = common::setup_temp_dir();
+	std::fs::create_dir(temp_dir.path().join("target")).unwrap();
+	let source = temp_dir.path().join("report.txt");
+	common::create_test_file(&source, "contents").unwrap();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		ignore_patterns: vec!["target".to_string()],
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	std::fs::rename(&source, temp_dir.path().join("target/report.txt")).unwrap();
+	let mut events = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		events.push(event);
+	}
+	handle.stop().await.unwrap();
+
+	let root = temp_dir.path().canonicalize().unwrap();
+	assert!(
+		events
+			.iter()
+			.any(|event| event.event_type == EventType::Remove
+				&& event.path == root.join("report.txt")),
+		"source removal not reported: {events:?}"
+	);
+	assert!(!events.iter().any(|event| event.event_type == EventType::MovedToIgnored));
+}
+
+#[tokio::test]
+async fn test_reset_detection_releases_deferred_removes() {
+	let temp_dir = common::setup_temp_dir();
+	let doomed = temp_dir.path().join("doomed.txt");
+	common::create_test_file(&doomed, "contents").unwrap();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		move_detector_config: Some(MoveDetectorConfig {
+			timeout: std::time::Duration::from_secs(60),
+			defer_removes: true,
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	std::fs::remove_file(&doomed).unwrap();
+	let held =
+		tokio::time::timeout(std::time::Duration::from_millis(500), event_receiver.recv()).await;
+	assert!(held.is_err(), "remove was not deferred: {held:?}");
+
+	handle.reset_detection(true).await.unwrap();
+	let released =
+		tokio::time::timeout(std::time::Duration::from_millis(500), event_receiver.recv())
+			.await
+			.expect("reset did not release the deferred remove")
+			.unwrap();
+	assert_eq!(released.event_type, EventType::Remove);
+	assert_eq!(
+		released.path,
+		temp_dir.path().canonicalize().unwrap().join("doomed.txt")
+	);
+	handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_stop_delivers_deferred_removes() {
+	let temp_dir = common::setup_temp_dir();
+	let doomed = temp_dir.path().join("doomed.txt");
+	common::create_test_file(&doomed, "contents").unwrap();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		move_detector_config: Some(MoveDetectorConfig {
+			timeout: std::time::Duration::from_secs(60),
+			defer_removes: true,
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	std::fs::remove_file(&doomed).unwrap();
+	let held =
+		tokio::time::timeout(std::time::Duration::from_millis(500), event_receiver.recv()).await;
+	assert!(held.is_err(), "remove was not deferred: {held:?}");
+
+	handle.stop().await.unwrap();
+	let mut events = Vec::new();
+	while let Some(event) = event_receiver.recv().await {
+		events.push(event);
+	}
+	let expected = temp_dir.path().canonicalize().unwrap().join("doomed.txt");
+	assert!(
+		events.iter().any(|e| e.event_type == EventType::Remove && e.path == expected),
+		"held remove lost on stop: {events:?}"
+	);
+}
+
+/// First Create event for `name` from a watcher on `dir` stamping with `source`
+async fn stamped_create(
+	dir: &std::path::Path, name: &str, source: TimestampSource,
+) -> rust_watcher::FileSystemEvent {
+	let config = WatcherConfig {
+		path: dir.to_path_buf(),
+		move_detector_config: None,
+		timestamp_source: source,
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	common::create_test_file(&dir.join(name), "contents").unwrap();
+	let event = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+		loop {
+			let event = event_receiver.recv().await.expect("watcher stopped");
+			if event.event_type == EventType::Create && event.path.ends_with(name) {
+				break event;
+			}
+		}
+	})
+	.await
+	.expect("no create event");
+	handle.stop().await.unwrap();
+	event
+}
+
+#[tokio::test]
+async fn test_timestamp_source_follows_the_config() {
+	let temp_dir = common::setup_temp_dir();
+
+	let processed = stamped_create(
+		temp_dir.path(),
+		"processed.txt",
+		TimestampSource::ProcessingTime,
+	)
+	.await;
+	assert_eq!(processed.timestamp_source, TimestampSource::ProcessingTime);
+
+	let stamped = stamped_create(temp_dir.path(), "os.txt", TimestampSource::OsEvent).await;
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+		let metadata = std::fs::metadata(temp_dir.path().join("os.txt")).unwrap();
+		assert_eq!(stamped.timestamp_source, TimestampSource::OsEvent);
+		assert_eq!(stamped.timestamp.timestamp(), metadata.ctime());
+		assert_eq!(
+			stamped.timestamp.timestamp_subsec_nanos() as i64,
+			metadata.ctime_nsec()
+		);
+	}
+	#[cfg(not(unix))]
+	assert_eq!(stamped.timestamp_source, TimestampSource::ProcessingTime);
+}
+
+#[tokio::test]
+async fn test_prewarm_reads_the_paths_that_exist() {
+	let temp_dir = common::setup_temp_dir();
+	let existing = temp_dir.path().join("existing.txt");
+	common::create_test_file(&existing, "contents").unwrap();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, _event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	let missing = temp_dir.path().join("missing.txt");
+	assert_eq!(handle.prewarm(&[existing, missing]).await.unwrap(), 1);
+	handle.stop().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_known_create_is_not_reported_again_by_the_backend() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	let known = temp_dir.path().join("known.txt");
+	handle
+		.apply_known_operation(KnownOperation::Create {
+			path: known.clone(),
+			is_directory: false,
+			size: Some(4),
+		})
+		.await
+		.unwrap();
+	common::create_test_file(&known, "ours").unwrap();
+	common::create_test_file(&temp_dir.path().join("theirs.txt"), "theirs").unwrap();
+	let mut events = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		events.push(event);
+	}
+	handle.stop().await.unwrap();
+
+	let creates: Vec<_> = events
+		.iter()
+		.filter(|event| event.event_type == EventType::Create)
+		.map(|event| event.path.file_name().unwrap())
+		.collect();
+	assert_eq!(creates, vec!["theirs.txt"], "{events:?}");
+}
+
+#[tokio::test]
+async fn test_watch_patterns_limit_events_to_the_paths_in_scope() {
+	let temp_dir = common::setup_temp_dir();
+	for dir in ["a/src", "a/docs", "b/src"] {
+		std::fs::create_dir_all(temp_dir.path().join(dir)).unwrap();
+	}
+	common::create_test_file(&temp_dir.path().join("a/src/existing.rs"), "fn a() {}").unwrap();
+	common::create_test_file(&temp_dir.path().join("a/docs/existing.md"), "# a").unwrap();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		watch_patterns: vec!["*/src/**".to_string()],
+		emit_existing_on_start: true,
+		..Default::default()
+	};
+	let (handle, mut event_receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+
+	common::create_test_file(&temp_dir.path().join("root.txt"), "churn").unwrap();
+	common::create_test_file(&temp_dir.path().join("a/docs/guide.md"), "# guide").unwrap();
+	common::create_test_file(&temp_dir.path().join("a/src/lib.rs"), "fn lib() {}").unwrap();
+	common::create_test_file(&temp_dir.path().join("b/src/main.rs"), "fn main() {}").unwrap();
+	std::fs::create_dir_all(temp_dir.path().join("c/src")).unwrap();
+	tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+	common::create_test_file(&temp_dir.path().join("c/src/new.rs"), "fn new() {}").unwrap();
+	let mut events = Vec::new();
+	while let Ok(Some(event)) = tokio::time::timeout(
+		std::time::Duration::from_millis(1500),
+		event_receiver.recv(),
+	)
+	.await
+	{
+		events.push(event);
+	}
+	handle.stop().await.unwrap();
+
+	let root = temp_dir.path().canonicalize().unwrap();
+	let in_scope = |path: &std::path::Path| {
+		let relative = path.strip_prefix(&root).unwrap();
+		let components: Vec<_> = relative.components().collect();
+		components.len() > 2 && components[1].as_os_str() == "src"
+	};
+	assert!(
+		events.iter().all(|event| in_scope(&event.path)),
+		"event outside the watch patterns: {events:?}"
+	);
+	for expected in ["a/src/existing.rs", "a/src/lib.rs", "b/src/main.rs", "c/src/new.rs"] {
+		assert!(
+			events.iter().any(|event| event.path == root.join(expected)),
+			"{expected} not reported: {events:?}"
+		);
+	}
+}
@@ -24,6 +24,13 @@ fn create_test_event(event_type: EventType, path: PathBuf, size: Option<u64>) ->
 		is_directory: false,
 		size,
 		move_data: None,
+		timestamp_source: rust_watcher::TimestampSource::ProcessingTime,
+		ownership: None,
+		snapshot: false,
+		sequence: None,
+		identity_paths: Vec::new(),
+		startup: false,
+		resolved_path: None,
 	}
 }
 
@@ -398,6 +405,7 @@ async fn test_watcher_with_database_integration() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: Some(db_config),
+		..Default::default()
 	};
 
 	// Start the watcher with database
@@ -470,6 +478,245 @@ async fn test_database_persistence_and_retrieval() {
 	// assert!(stats.total_metadata > 0);
 }
 
+/// Test that events written before the adapter is dropped are visible after reopening
+#[test]
+async fn test_events_survive_adapter_drop_and_reopen() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let db_path = temp_dir.path().join(format!("reopen_test-{}.redb", Uuid::new_v4()));
+	let config = DatabaseConfig { database_path: db_path, ..Default::default() };
+	let paths: Vec<PathBuf> =
+		(0..10).map(|i| temp_dir.path().join(format!("dropped_{i}.txt"))).collect();
+
+	{
+		let adapter = DatabaseAdapter::new(config.clone()).await.expect("Failed to create adapter");
+		for path in &paths {
+			let event = create_test_event(EventType::Create, path.clone(), Some(64));
+			adapter.store_event(&event).await.expect("Failed to store event");
+		}
+		// No close(): dropping must not lose acknowledged writes or keep the file locked
+	}
+
+	let adapter = DatabaseAdapter::new(config.clone()).await.expect("Failed to reopen database");
+	for path in &paths {
+		let events = adapter.get_events_for_path(path).await.expect("Failed to read events");
+		assert_eq!(events.len(), 1, "Event for {path:?} lost across reopen");
+	}
+
+	// Explicit close releases the file as well
+	adapter.close().await.expect("Failed to close adapter");
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to reopen after close");
+	let stats = adapter.get_stats().await.expect("Failed to get stats");
+	assert_eq!(stats.total_events, paths.len() as u64);
+}
+
+/// Paging through a path's history returns every event once, in append order
+#[test]
+async fn test_events_for_path_are_paged_in_sequence_order() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let config =
+		DatabaseConfig { database_path: temp_dir.path().join("paged.redb"), ..Default::default() };
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to create adapter");
+	let path = temp_dir.path().join("busy.txt");
+	let other = temp_dir.path().join("other.txt");
+	for i in 0..100u64 {
+		let event = create_test_event(EventType::Write, path.clone(), Some(i));
+		adapter.store_event(&event).await.expect("Failed to store event");
+		if i % 10 == 0 {
+			let event = create_test_event(EventType::Write, other.clone(), Some(i));
+			adapter.store_event(&event).await.expect("Failed to store event");
+		}
+	}
+
+	let mut pages = Vec::new();
+	let mut cursor = None;
+	loop {
+		let page = adapter
+			.get_events_for_path_paged(&path, cursor, 30)
+			.await
+			.expect("Failed to read page");
+		pages.push(page.events);
+		match page.next_cursor {
+			Some(next) => cursor = Some(next),
+			None => break,
+		}
+	}
+
+	let sizes: Vec<usize> = pages.iter().map(Vec::len).collect();
+	assert_eq!(sizes, vec![30, 30, 30, 10]);
+	let events: Vec<EventRecord> = pages.into_iter().flatten().collect();
+	assert!(events.iter().all(|event| event.path == path));
+	assert!(events.windows(2).all(|pair| pair[0].sequence_number < pair[1].sequence_number));
+	let all = adapter.get_events_for_path(&path).await.expect("Failed to read events");
+	let paged_ids: Vec<_> = events.iter().map(|event| event.event_id).collect();
+	let all_ids: Vec<_> = all.iter().map(|event| event.event_id).collect();
+	assert_eq!(all_ids.len(), 100);
+	assert_eq!(paged_ids, all_ids);
+}
+
+/// Events removed by retention cleanup no longer show up in a path's pages
+#[test]
+async fn test_paging_skips_events_removed_by_cleanup() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let config = DatabaseConfig {
+		database_path: temp_dir.path().join("paged_cleanup.redb"),
+		event_retention: std::time::Duration::from_millis(300),
+		..DatabaseConfig::for_small_directories()
+	};
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to create adapter");
+	let path = temp_dir.path().join("busy.txt");
+	for i in 0..10u64 {
+		let event = create_test_event(EventType::Write, path.clone(), Some(i));
+		adapter.store_event(&event).await.expect("Failed to store event");
+	}
+	// Records expire a retention period after they were stored, and are removed a period later
+	sleep(TokioDuration::from_millis(700)).await;
+	for i in 10..15u64 {
+		let event = create_test_event(EventType::Write, path.clone(), Some(i));
+		adapter.store_event(&event).await.expect("Failed to store event");
+	}
+	assert_eq!(adapter.cleanup_old_events().await.unwrap(), 10);
+
+	let mut paged = Vec::new();
+	let mut cursor = None;
+	loop {
+		let page = adapter
+			.get_events_for_path_paged(&path, cursor, 3)
+			.await
+			.expect("Failed to read page");
+		paged.extend(page.events.iter().map(|event| event.event_id));
+		match page.next_cursor {
+			Some(next) => cursor = Some(next),
+			None => break,
+		}
+	}
+	let kept = adapter.get_events_for_path(&path).await.expect("Failed to read events");
+	assert_eq!(kept.len(), 5);
+	assert_eq!(
+		paged,
+		kept.iter().map(|event| event.event_id).collect::<Vec<_>>()
+	);
+}
+
+/// Test merging one database file into another
+#[test]
+async fn test_import_from_merges_two_databases() {
+	use rust_watcher::database::storage::FilesystemCacheStorage;
+	use rust_watcher::database::types::{FilesystemNode, WatchMetadata};
+
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let target_config = DatabaseConfig {
+		database_path: temp_dir.path().join("merge_target.redb"),
+		..Default::default()
+	};
+	let source_config = DatabaseConfig {
+		database_path: temp_dir.path().join("merge_source.redb"),
+		..Default::default()
+	};
+	let shared_path = temp_dir.path().join("shared.txt");
+	let source_only_path = temp_dir.path().join("source_only.txt");
+	std::fs::write(&shared_path, b"shared").unwrap();
+	std::fs::write(&source_only_path, b"source").unwrap();
+	let shared_watch = Uuid::new_v4();
+	let source_watch = Uuid::new_v4();
+	let watch_metadata = |watch_id| WatchMetadata {
+		watch_id,
+		root_path: temp_dir.path().to_path_buf(),
+		created_at: Utc::now(),
+		last_scan: None,
+		node_count: 0,
+		is_active: true,
+		config_hash: 0,
+		event_retention: None,
+		permissions: None,
+	};
+	let node =
+		|path: &PathBuf| FilesystemNode::new(path.clone(), &std::fs::metadata(path).unwrap());
+
+	let target = DatabaseAdapter::new(target_config).await.expect("Failed to create target");
+	for _ in 0..3 {
+		let event = create_test_event(EventType::Create, shared_path.clone(), Some(6));
+		target.store_event(&event).await.unwrap();
+	}
+	let mut target_cache = target.get_filesystem_cache().await.unwrap();
+	target_cache.store_watch_metadata(&watch_metadata(shared_watch)).await.unwrap();
+	target_cache
+		.store_filesystem_node(&shared_watch, &node(&shared_path), "create")
+		.await
+		.unwrap();
+	drop(target_cache);
+
+	{
+		let source = DatabaseAdapter::new(source_config.clone())
+			.await
+			.expect("Failed to create source");
+		let event = create_test_event(EventType::Write, shared_path.clone(), Some(6));
+		source.store_event(&event).await.unwrap();
+		for _ in 0..2 {
+			let event = create_test_event(EventType::Create, source_only_path.clone(), Some(6));
+			source.store_event(&event).await.unwrap();
+		}
+		let mut source_cache = source.get_filesystem_cache().await.unwrap();
+		source_cache.store_watch_metadata(&watch_metadata(shared_watch)).await.unwrap();
+		source_cache.store_watch_metadata(&watch_metadata(source_watch)).await.unwrap();
+		source_cache
+			.store_filesystem_node(&source_watch, &node(&source_only_path), "create")
+			.await
+			.unwrap();
+		drop(source_cache);
+		source.close().await.unwrap();
+	}
+
+	let report = target.import_from(&source_config.database_path).await.expect("import failed");
+	assert_eq!(report.events_imported, 3);
+	assert_eq!(
+		report.events_resequenced, 3,
+		"source sequences 0..3 collide with the target's"
+	);
+	assert_eq!(report.shared_watch_ids, vec![shared_watch]);
+	assert!(report.cache_entries_imported > 0);
+
+	let stats = target.get_stats().await.unwrap();
+	assert_eq!(stats.total_events, 6);
+	let shared_events = target.get_events_for_path(&shared_path).await.unwrap();
+	assert_eq!(shared_events.len(), 4);
+	assert_eq!(shared_events.last().unwrap().event_type, "Write");
+	let sequences: Vec<u64> = shared_events.iter().map(|e| e.sequence_number).collect();
+	assert!(
+		sequences.windows(2).all(|w| w[0] < w[1]),
+		"sequences not unique: {sequences:?}"
+	);
+	assert_eq!(
+		target.get_events_for_path(&source_only_path).await.unwrap().len(),
+		2
+	);
+
+	// The prefix index holds each merged event once, under its target sequence number
+	let query = rust_watcher::database::EventQuery::new().under_prefix(&shared_path);
+	let by_prefix: Vec<u64> =
+		target.query(query).await.unwrap().iter().map(|e| e.sequence_number).collect();
+	assert_eq!(by_prefix, sequences);
+
+	let mut merged_cache = target.get_filesystem_cache().await.unwrap();
+	let imported_node = merged_cache.get_node(&source_watch, &source_only_path).await.unwrap();
+	assert_eq!(
+		imported_node.map(|n| n.path),
+		Some(source_only_path.clone())
+	);
+	assert!(merged_cache.get_node(&shared_watch, &shared_path).await.unwrap().is_some());
+	assert!(merged_cache.get_watch_metadata(&source_watch).await.unwrap().is_some());
+	drop(merged_cache);
+
+	// A second import of the same file must not duplicate events
+	let again = target.import_from(&source_config.database_path).await.unwrap();
+	assert_eq!(again.events_imported, 0);
+	assert_eq!(again.events_skipped, 3);
+	assert_eq!(target.get_stats().await.unwrap().total_events, 6);
+	let query = rust_watcher::database::EventQuery::new().under_prefix(&shared_path);
+	assert_eq!(target.query(query).await.unwrap().len(), 4);
+
+	assert!(target.import_from(target.database_path().unwrap()).await.is_err());
+}
+
 /// Test database cleanup and maintenance
 #[test]
 #[ignore]
@@ -691,3 +938,554 @@ async fn test_persistent_per_event_type_stats() {
 	// Explicitly drop storage before temp_dir goes out of scope to avoid file lock issues
 	drop(storage);
 }
+
+/// Deleting events leaves free pages in the file; compaction hands them back
+#[test]
+async fn test_fragmentation_rises_after_deletes_and_compaction_lowers_it() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let db_path = temp_dir.path().join("fragmentation.redb");
+	let config = DatabaseConfig {
+		database_path: db_path,
+		event_retention: std::time::Duration::from_millis(500),
+		..DatabaseConfig::for_small_directories()
+	};
+	let adapter = DatabaseAdapter::new(config).await.unwrap();
+
+	for i in 0..3000 {
+		let path = PathBuf::from(format!("/fragmentation/some/longer/directory/file_{i}.txt"));
+		adapter
+			.store_event(&create_test_event(EventType::Create, path, Some(i)))
+			.await
+			.unwrap();
+	}
+	let populated = adapter.fragmentation_ratio().await.unwrap();
+
+	sleep(TokioDuration::from_millis(600)).await;
+	assert!(adapter.cleanup_old_events().await.unwrap() > 0);
+	let after_delete = adapter.fragmentation_ratio().await.unwrap();
+	assert!(
+		after_delete > populated,
+		"fragmentation did not rise after deletes: {populated} -> {after_delete}"
+	);
+
+	adapter.compact().await.unwrap();
+	let after_compact = adapter.fragmentation_ratio().await.unwrap();
+	assert!(
+		after_compact < after_delete,
+		"compaction did not lower fragmentation: {after_delete} -> {after_compact}"
+	);
+	for ratio in [populated, after_delete, after_compact] {
+		assert!(
+			(0.0..=1.0).contains(&ratio),
+			"fragmentation ratio out of range: {ratio}"
+		);
+	}
+	adapter.close().await.unwrap();
+}
+
+/// The background compaction task leaves small files alone and skips held databases quietly
+#[test]
+async fn test_compaction_task_skips_small_and_held_databases() {
+	use rust_watcher::database::background_tasks::{BackgroundTask, CompactionTask, SharedStorage};
+	use rust_watcher::database::storage::{DatabaseStorage, RedbStorage};
+	use std::sync::Arc;
+
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let config = DatabaseConfig {
+		database_path: temp_dir.path().join("compaction_task.redb"),
+		..DatabaseConfig::for_small_directories()
+	};
+	let redb = RedbStorage::new(config.clone()).await.unwrap();
+	let database = redb.get_database();
+	let boxed: Box<dyn DatabaseStorage> = Box::new(redb);
+	let storage: Arc<SharedStorage> = Arc::new(tokio::sync::RwLock::new(boxed));
+	let task = |min_reclaimable_bytes| CompactionTask {
+		storage: Arc::downgrade(&storage),
+		fragmentation_threshold: config.compaction_fragmentation_threshold,
+		min_reclaimable_bytes,
+	};
+
+	// A fresh file reads as fragmented, but has too little to reclaim
+	assert!(storage.read().await.fragmentation_ratio().await.unwrap() > 0.5);
+	let file_size = || std::fs::metadata(&config.database_path).unwrap().len();
+	let before = file_size();
+	task(config.compaction_min_reclaimable_bytes).run().await.unwrap();
+	assert_eq!(file_size(), before);
+
+	// Held elsewhere: skipped without an error
+	task(0).run().await.unwrap();
+	drop(database);
+	task(0).run().await.unwrap();
+}
+
+/// A CBOR database keeps reading as CBOR even when reopened with the default (bincode) config
+#[test]
+async fn test_serialization_format_is_kept_by_the_database_file() {
+	use rust_watcher::database::{SerializationFormat, StorageKey};
+
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let db_path = temp_dir.path().join("cbor.redb");
+	let record = EventRecord::new(
+		"Create".to_string(),
+		PathBuf::from("/cbor/file.txt"),
+		false,
+		Duration::minutes(5),
+		0,
+	);
+	let mut metadata = MetadataRecord::new(PathBuf::from("/cbor/file.txt"), false);
+	metadata.size = Some(12);
+
+	let mut storage = RedbStorage::new(DatabaseConfig {
+		database_path: db_path.clone(),
+		serialization_format: SerializationFormat::Cbor,
+		..Default::default()
+	})
+	.await
+	.unwrap();
+	storage.store_event(&record).await.unwrap();
+	storage.store_metadata(&metadata).await.unwrap();
+	drop(storage);
+
+	let mut storage =
+		RedbStorage::new(DatabaseConfig { database_path: db_path, ..Default::default() })
+			.await
+			.unwrap();
+	let events = storage.get_events(&StorageKey::path_hash(&record.path)).await.unwrap();
+	assert_eq!(events.len(), 1);
+	assert_eq!(events[0].event_id, record.event_id);
+	let stored = storage.get_metadata(&metadata.path).await.unwrap().unwrap();
+	assert_eq!(stored.size, Some(12));
+}
+
+/// Batched event writes stay in the adapter until flushed
+#[test]
+async fn test_batched_event_writes_are_persisted_by_flush() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let db_path = temp_dir.path().join(format!("batch_test-{}.redb", Uuid::new_v4()));
+	let config = DatabaseConfig {
+		database_path: db_path.clone(),
+		batch_event_writes: true,
+		write_batch_size: 100,
+		flush_interval: std::time::Duration::from_secs(3600),
+		..Default::default()
+	};
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to create adapter");
+
+	let paths: Vec<_> = (0..3).map(|i| temp_dir.path().join(format!("file{i}.txt"))).collect();
+	for path in &paths {
+		let event = create_test_event(EventType::Create, path.clone(), Some(1));
+		adapter.store_event(&event).await.expect("Failed to store event");
+	}
+	assert_eq!(adapter.pending_write_count().await, 3);
+	// Queries read the database, not the buffer
+	assert!(adapter.get_events_for_path(&paths[0]).await.unwrap().is_empty());
+
+	assert_eq!(adapter.flush().await.unwrap(), 3);
+	assert_eq!(adapter.pending_write_count().await, 0);
+	for path in &paths {
+		assert_eq!(adapter.get_events_for_path(path).await.unwrap().len(), 1);
+	}
+	assert_eq!(adapter.flush().await.unwrap(), 0);
+
+	// Whatever is still buffered is committed by close
+	let late = temp_dir.path().join("late.txt");
+	let event = create_test_event(EventType::Create, late.clone(), Some(1));
+	adapter.store_event(&event).await.expect("Failed to store event");
+	adapter.close().await.unwrap();
+	let config = DatabaseConfig { database_path: db_path, ..Default::default() };
+	let reopened = DatabaseAdapter::new(config).await.expect("Failed to reopen");
+	assert_eq!(reopened.get_events_for_path(&late).await.unwrap().len(), 1);
+}
+
+/// A full batch is committed without an explicit flush
+#[test]
+async fn test_batched_event_writes_commit_full_batches() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let db_path = temp_dir.path().join(format!("batch_size_test-{}.redb", Uuid::new_v4()));
+	let config = DatabaseConfig {
+		database_path: db_path,
+		batch_event_writes: true,
+		write_batch_size: 2,
+		..Default::default()
+	};
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to create adapter");
+	let path = temp_dir.path().join("file.txt");
+	for expected_pending in [1, 0, 1] {
+		let event = create_test_event(EventType::Write, path.clone(), Some(1));
+		adapter.store_event(&event).await.expect("Failed to store event");
+		assert_eq!(adapter.pending_write_count().await, expected_pending);
+	}
+	assert_eq!(adapter.get_events_for_path(&path).await.unwrap().len(), 2);
+}
+
+/// Per-watch retention overrides the global one for events under the watch root
+#[test]
+async fn test_cleanup_honors_per_watch_retention() {
+	use rust_watcher::database::types::WatchMetadata;
+
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let db_path = temp_dir.path().join(format!("retention_test-{}.redb", Uuid::new_v4()));
+	let config = DatabaseConfig {
+		database_path: db_path.clone(),
+		event_retention: std::time::Duration::from_secs(600),
+		..Default::default()
+	};
+	let (audit, scratch) = (
+		temp_dir.path().join("audit"),
+		temp_dir.path().join("scratch"),
+	);
+	let unwatched = temp_dir.path().join("elsewhere");
+	let watch = |root: &PathBuf, retention| WatchMetadata {
+		watch_id: Uuid::new_v4(),
+		root_path: root.clone(),
+		created_at: Utc::now(),
+		last_scan: None,
+		node_count: 0,
+		is_active: true,
+		config_hash: 0,
+		permissions: None,
+		event_retention: Some(retention),
+	};
+	let aged = |path: PathBuf, age: Duration| {
+		let mut record =
+			EventRecord::new("Create".to_string(), path, false, Duration::seconds(600), 0);
+		record.timestamp = Utc::now() - age;
+		record.expires_at = record.timestamp + Duration::seconds(600);
+		record
+	};
+
+	{
+		let mut storage = RedbStorage::new(config.clone()).await.unwrap();
+		let day = std::time::Duration::from_secs(86_400);
+		storage.store_watch_metadata(&watch(&audit, day * 30)).await.unwrap();
+		let hour = std::time::Duration::from_secs(3600);
+		storage.store_watch_metadata(&watch(&scratch, hour)).await.unwrap();
+		for record in [
+			aged(audit.join("old.log"), Duration::days(2)),
+			aged(scratch.join("old.tmp"), Duration::hours(2)),
+			aged(scratch.join("recent.tmp"), Duration::minutes(10)),
+			aged(unwatched.join("old.txt"), Duration::days(2)),
+		] {
+			storage.store_event(&record).await.unwrap();
+		}
+	}
+
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to create adapter");
+	assert_eq!(adapter.cleanup_old_events().await.unwrap(), 2);
+	let count = |path: PathBuf| {
+		let adapter = &adapter;
+		async move { adapter.get_events_for_path(&path).await.unwrap().len() }
+	};
+	// Kept for 30 days, although past the global retention
+	assert_eq!(count(audit.join("old.log")).await, 1);
+	// Past the scratch watch's hour
+	assert_eq!(count(scratch.join("old.tmp")).await, 0);
+	assert_eq!(count(scratch.join("recent.tmp")).await, 1);
+	// No watch: the global retention applies
+	assert_eq!(count(unwatched.join("old.txt")).await, 0);
+}
+
+/// `current_state` returns the last event of a path, also once the log no longer has it
+#[test]
+async fn test_current_state_is_the_latest_event_of_a_path() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let config = DatabaseConfig {
+		database_path: temp_dir.path().join(format!("current_state-{}.redb", Uuid::new_v4())),
+		track_current_state: true,
+		..Default::default()
+	};
+	let path = temp_dir.path().join("report.txt");
+	let other = temp_dir.path().join("other.txt");
+
+	let adapter = DatabaseAdapter::new(config.clone()).await.expect("Failed to create adapter");
+	for event_type in [EventType::Create, EventType::Write, EventType::Chmod, EventType::Write] {
+		let event = create_test_event(event_type, path.clone(), Some(10));
+		adapter.store_event(&event).await.unwrap();
+	}
+	adapter
+		.store_event(&create_test_event(EventType::Create, other.clone(), None))
+		.await
+		.unwrap();
+	adapter
+		.store_event(&create_test_event(EventType::Remove, path.clone(), None))
+		.await
+		.unwrap();
+
+	let state = adapter.current_state(&path).await.unwrap().expect("a current state");
+	assert_eq!(state.event_type, "Remove");
+	assert_eq!(state.path, path);
+	assert_eq!(state.sequence_number, 5);
+	let state = adapter.current_state(&other).await.unwrap().expect("a current state");
+	assert_eq!(
+		(state.event_type.as_str(), state.sequence_number),
+		("Create", 4)
+	);
+	assert!(adapter
+		.current_state(&temp_dir.path().join("never.txt"))
+		.await
+		.unwrap()
+		.is_none());
+
+	// A move leaves its source removed
+	let moved = temp_dir.path().join("moved.txt");
+	let move_event = FileSystemEvent {
+		move_data: Some(rust_watcher::MoveEvent::new(
+			other.clone(),
+			moved.clone(),
+			0.9,
+			rust_watcher::MoveDetectionMethod::InodeMatching,
+		)),
+		..create_test_event(EventType::Move, moved.clone(), None)
+	};
+	adapter.store_event(&move_event).await.unwrap();
+	let state = adapter.current_state(&other).await.unwrap().expect("a current state");
+	assert_eq!(
+		(state.event_type.as_str(), state.sequence_number),
+		("Remove", 6)
+	);
+	assert_eq!(state.path, other);
+	assert_eq!(state.confidence, Some(0.9));
+	let state = adapter.current_state(&moved).await.unwrap().expect("a current state");
+	assert_eq!(
+		(state.event_type.as_str(), state.sequence_number),
+		("Move", 6)
+	);
+	adapter.close().await.unwrap();
+
+	// Pruning the whole log leaves the compacted table alone
+	{
+		let mut storage = RedbStorage::new(config.clone()).await.unwrap();
+		assert_eq!(storage.delete_oldest_events(7).await.unwrap(), 7);
+	}
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to reopen adapter");
+	assert!(adapter.get_events_for_path(&path).await.unwrap().is_empty());
+	let state = adapter.current_state(&path).await.unwrap().expect("a current state");
+	assert_eq!(state.event_type, "Remove");
+	adapter.close().await.unwrap();
+
+	// Off by default: nothing is tracked
+	let untracked = DatabaseConfig {
+		database_path: temp_dir.path().join("untracked.redb"),
+		..Default::default()
+	};
+	let adapter = DatabaseAdapter::new(untracked).await.expect("Failed to create adapter");
+	adapter
+		.store_event(&create_test_event(EventType::Create, path.clone(), None))
+		.await
+		.unwrap();
+	assert!(adapter.current_state(&path).await.unwrap().is_none());
+}
+
+/// Acknowledged events are not replayed after a restart; out-of-order acks only count once
+/// the prefix before them is acknowledged
+#[test]
+async fn test_replay_unacked_after_restart_skips_acknowledged_prefix() {
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let watch_dir = temp_dir.path().join("watch");
+	std::fs::create_dir_all(&watch_dir).expect("Failed to create watch directory");
+	let config = WatcherConfig {
+		watch_id: Uuid::new_v4(),
+		path: watch_dir.clone(),
+		database_config: Some(DatabaseConfig {
+			database_path: temp_dir.path().join(format!("delivery-{}.redb", Uuid::new_v4())),
+			..Default::default()
+		}),
+		delivery_log: true,
+		..Default::default()
+	};
+
+	let (handle, mut event_rx) = start(config.clone()).expect("Failed to start watcher");
+	handle.ready().await.expect("Watcher did not start");
+	for i in 0..5 {
+		std::fs::write(watch_dir.join(format!("file{i}.txt")), "content").unwrap();
+	}
+	let mut sequences = Vec::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(TokioDuration::from_millis(750), event_rx.recv()).await
+	{
+		sequences.push(event.sequence.expect("Logged events carry a sequence"));
+	}
+	assert!(
+		sequences.len() >= 5,
+		"Expected an event per file, got {sequences:?}"
+	);
+	assert!(sequences.windows(2).all(|pair| pair[0] < pair[1]));
+
+	// Acknowledge the first three, last one first
+	let acked = sequences[2];
+	handle.ack(acked).await.unwrap();
+	assert_eq!(
+		handle.replay_unacked().await.unwrap().len(),
+		sequences.len()
+	);
+	handle.ack(sequences[0]).await.unwrap();
+	handle.ack(sequences[1]).await.unwrap();
+	assert_eq!(
+		handle.replay_unacked().await.unwrap().len(),
+		sequences.len() - 3
+	);
+	handle.stop().await.expect("Failed to stop watcher");
+	drop(event_rx);
+
+	let (handle, _event_rx) = start(config).expect("Failed to restart watcher");
+	handle.ready().await.expect("Watcher did not restart");
+	let replayed: Vec<_> = handle
+		.replay_unacked()
+		.await
+		.unwrap()
+		.into_iter()
+		.map(|event| event.sequence.unwrap())
+		.collect();
+	let expected: Vec<_> = sequences.iter().copied().filter(|&s| s > acked).collect();
+	assert_eq!(replayed, expected);
+	handle.stop().await.expect("Failed to stop watcher");
+}
+
+/// Files created while the initial scan runs end up in the cache once, as they are on disk,
+/// whichever of the scan and the live event reported them
+#[test]
+async fn test_files_created_during_the_initial_scan_are_cached_in_their_live_state() {
+	use rust_watcher::database::types::NodeType;
+
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let watch_dir = temp_dir.path().join("watch");
+	std::fs::create_dir_all(&watch_dir).unwrap();
+	for i in 0..300 {
+		common::create_test_file(&watch_dir.join(format!("existing_{i}.txt")), "x").unwrap();
+	}
+	let db_config = DatabaseConfig {
+		database_path: temp_dir.path().join(format!("scan-{}.redb", Uuid::new_v4())),
+		..Default::default()
+	};
+	let config = WatcherConfig {
+		watch_id: Uuid::new_v4(),
+		path: watch_dir.clone(),
+		emit_existing_on_start: true,
+		database_config: Some(db_config.clone()),
+		..Default::default()
+	};
+	let (handle, mut event_rx) = start(config.clone()).expect("Failed to start watcher");
+
+	// Spread across startup: each file is created empty and then grows, so a node taken
+	// from the scan's view of it would be stale
+	let root = watch_dir.clone();
+	let writer = tokio::spawn(async move {
+		for i in 0..20 {
+			let path = root.join(format!("live_{i}.txt"));
+			std::fs::write(&path, "").unwrap();
+			std::fs::write(&path, "y".repeat(i + 1)).unwrap();
+			sleep(TokioDuration::from_millis(5)).await;
+		}
+	});
+	while let Ok(Some(_)) =
+		tokio::time::timeout(TokioDuration::from_millis(1500), event_rx.recv()).await
+	{}
+	writer.await.unwrap();
+	handle.stop().await.expect("Failed to stop watcher");
+
+	let mut storage = RedbStorage::new(db_config).await.expect("Failed to reopen database");
+	let root = watch_dir.canonicalize().unwrap();
+	let listed = storage.list_directory_for_watch(&config.watch_id, &root).await.unwrap();
+	for i in 0..20 {
+		let path = root.join(format!("live_{i}.txt"));
+		let copies = listed.iter().filter(|node| node.path == path).count();
+		assert_eq!(copies, 1, "{path:?} cached {copies} times");
+		let node = storage
+			.get_filesystem_node(&config.watch_id, &path)
+			.await
+			.unwrap()
+			.expect("live file not cached");
+		match node.node_type {
+			NodeType::File { size, .. } => assert_eq!(size, i as u64 + 1, "{path:?}"),
+			other => panic!("{path:?} cached as {other:?}"),
+		}
+		assert!(node.last_event_type.is_some(), "{path:?}");
+	}
+}
+
+/// A known create applied before the file exists is cached with the metadata it carries
+#[test]
+async fn test_known_create_is_cached_before_the_file_exists() {
+	use rust_watcher::database::types::NodeType;
+	use rust_watcher::KnownOperation;
+
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let watch_dir = temp_dir.path().join("watch");
+	std::fs::create_dir_all(&watch_dir).unwrap();
+	let db_config = DatabaseConfig {
+		database_path: temp_dir.path().join(format!("known-{}.redb", Uuid::new_v4())),
+		..Default::default()
+	};
+	let config = WatcherConfig {
+		path: watch_dir.clone(),
+		database_config: Some(db_config.clone()),
+		..Default::default()
+	};
+	let (handle, _event_rx) = start(config.clone()).expect("Failed to start watcher");
+	handle.ready().await.unwrap();
+
+	let path = watch_dir.canonicalize().unwrap().join("upcoming.bin");
+	let operation =
+		KnownOperation::Create { path: path.clone(), is_directory: false, size: Some(42) };
+	handle.apply_known_operation(operation).await.unwrap();
+	handle.stop().await.expect("Failed to stop watcher");
+
+	let mut storage = RedbStorage::new(db_config).await.expect("Failed to reopen database");
+	let node = storage
+		.get_filesystem_node(&config.watch_id, &path)
+		.await
+		.unwrap()
+		.expect("known create not cached");
+	match node.node_type {
+		NodeType::File { size, .. } => assert_eq!(size, 42),
+		other => panic!("cached as {other:?}"),
+	}
+}
+
+/// A Write that changes a cached file is reported with what changed
+#[test]
+async fn test_write_to_a_cached_file_reports_the_node_diff() {
+	use rust_watcher::database::types::NodeChange;
+	use rust_watcher::{start_with_diagnostics, WatcherDiagnostic};
+
+	let temp_dir = TempDir::new().expect("Failed to create temp directory");
+	let watch_dir = temp_dir.path().join("watch");
+	std::fs::create_dir_all(&watch_dir).unwrap();
+	let config = WatcherConfig {
+		path: watch_dir.clone(),
+		database_config: Some(DatabaseConfig {
+			database_path: temp_dir.path().join(format!("diff-{}.redb", Uuid::new_v4())),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let (handle, mut event_rx, mut diagnostics) =
+		start_with_diagnostics(config).expect("Failed to start watcher");
+	handle.ready().await.unwrap();
+
+	let path = watch_dir.canonicalize().unwrap().join("growing.txt");
+	std::fs::write(&path, "four").unwrap();
+	while let Ok(Some(_)) =
+		tokio::time::timeout(TokioDuration::from_millis(500), event_rx.recv()).await
+	{}
+	std::fs::write(&path, "now sixteen long").unwrap();
+	// Writing the first contents may be reported too; wait for the second
+	let grown = NodeChange::Size { old: 4, new: 16 };
+	let reported = tokio::time::timeout(TokioDuration::from_secs(5), async {
+		loop {
+			if let WatcherDiagnostic::NodeChanged { path: changed, diff } =
+				diagnostics.recv().await.expect("diagnostics closed")
+			{
+				if changed == path && diff.changes.contains(&grown) {
+					break;
+				}
+			}
+		}
+	})
+	.await;
+	handle.stop().await.expect("Failed to stop watcher");
+	assert!(
+		reported.is_ok(),
+		"no NodeChanged diagnostic for the size change"
+	);
+}
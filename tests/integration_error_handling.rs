@@ -1,7 +1,10 @@
 // Integration tests for error handling scenarios
 // Tests the public API error handling using only public interfaces
 
-use rust_watcher::{start, WatcherConfig, WatcherError};
+use rust_watcher::{
+	start, start_with_diagnostics, MoveDetectorConfig, WatcherConfig, WatcherDiagnostic,
+	WatcherError,
+};
 use std::path::PathBuf;
 
 mod common;
@@ -15,6 +18,7 @@ async fn test_watcher_invalid_path() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let result = start(config);
@@ -38,6 +42,7 @@ async fn test_watcher_stop_after_start() {
 		move_detector_config: None,
 		error_recovery_config: None,
 		database_config: None,
+		..Default::default()
 	};
 
 	let (handle, _receiver) = start(config).unwrap();
@@ -58,6 +63,7 @@ async fn test_watcher_multiple_start_stop() {
 			move_detector_config: None,
 			error_recovery_config: None,
 			database_config: None,
+			..Default::default()
 		};
 
 		let (handle, _receiver) = start(config)
@@ -76,22 +82,201 @@ async fn test_watcher_multiple_start_stop() {
 #[tokio::test]
 async fn test_watcher_permission_denied() {
 	// On Unix systems, try to watch /root (usually requires root)
-	let config =
-		WatcherConfig { path: PathBuf::from("/root"), recursive: true, move_detector_config: None };
-	let result = start(config);
+	let config = WatcherConfig {
+		path: PathBuf::from("/root"),
+		recursive: true,
+		move_detector_config: None,
+		..Default::default()
+	};
 
 	// Should either succeed (if running as root) or fail with permission error
-	if result.is_err() {
-		match result.unwrap_err() {
-			WatcherError::Notify(_) => {
-				// Expected for permission denied
-			}
-			other => panic!("Unexpected error type: {:?}", other),
+	match start(config) {
+		// If it succeeds, we're running as root - clean up
+		Ok((handle, _)) => {
+			let _ = handle.stop().await;
+		}
+		Err(WatcherError::Notify(_)) => {
+			// Expected for permission denied
+		}
+		Err(other) => panic!("Unexpected error type: {:?}", other),
+	}
+}
+
+#[tokio::test]
+async fn test_stop_while_event_channel_is_full() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	// Kept alive but never read, so the watcher ends up parked on a full channel
+	let (handle, _receiver) = start(config).unwrap();
+	common::wait_for_events().await;
+
+	for i in 0..300 {
+		common::create_test_file(&temp_dir.path().join(format!("file_{i}.txt")), "x").unwrap();
+	}
+	common::wait_for_events().await;
+
+	let stopped = tokio::time::timeout(std::time::Duration::from_secs(5), handle.stop()).await;
+	assert!(stopped.is_ok(), "stop() hung on a full event channel");
+}
+
+#[tokio::test]
+async fn test_pending_limit_is_reported_as_dropped_event_diagnostic() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		move_detector_config: Some(MoveDetectorConfig {
+			max_pending_events: 1,
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let (handle, mut receiver, mut diagnostics) = start_with_diagnostics(config).unwrap();
+	handle.ready().await.unwrap();
+
+	// The first create fills the single pending slot, the others overflow it
+	for i in 0..3 {
+		common::create_test_file(&temp_dir.path().join(format!("file_{i}.txt")), "x").unwrap();
+	}
+
+	let diagnostic =
+		tokio::time::timeout(std::time::Duration::from_secs(2), diagnostics.recv()).await;
+	// Events are still delivered; drain them so the loop is never parked on a full channel
+	while let Ok(Some(_)) =
+		tokio::time::timeout(std::time::Duration::from_millis(200), receiver.recv()).await
+	{}
+	handle.stop().await.unwrap();
+
+	match diagnostic {
+		Ok(Some(WatcherDiagnostic::DroppedEvent { path, reason, .. })) => {
+			assert!(path.starts_with(temp_dir.path().canonicalize().unwrap()));
+			assert!(reason.contains("max_pending_events"), "{reason}");
+		}
+		other => panic!("expected a DroppedEvent diagnostic, got {other:?}"),
+	}
+}
+
+/// Next diagnostic other than `DroppedEvent`, or `None` after `secs`
+async fn next_root_diagnostic(
+	diagnostics: &mut tokio::sync::mpsc::Receiver<WatcherDiagnostic>, secs: u64,
+) -> Option<WatcherDiagnostic> {
+	let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(secs);
+	loop {
+		match tokio::time::timeout_at(deadline, diagnostics.recv()).await {
+			Ok(Some(WatcherDiagnostic::DroppedEvent { .. })) => continue,
+			Ok(diagnostic) => return diagnostic,
+			Err(_) => return None,
 		}
 	}
-	// If it succeeds, we're running as root - clean up
-	else {
-		let (handle, _) = result.unwrap();
-		let _ = handle.stop().await;
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_root_recreated_within_grace_is_watched_again() {
+	let temp_dir = common::setup_temp_dir();
+	let root = temp_dir.path().join("release");
+	std::fs::create_dir(&root).unwrap();
+	let config = WatcherConfig {
+		path: root.clone(),
+		root_gone_grace: std::time::Duration::from_secs(5),
+		..Default::default()
+	};
+	let (handle, mut receiver, mut diagnostics) = start_with_diagnostics(config).unwrap();
+	handle.ready().await.unwrap();
+	let root = root.canonicalize().unwrap();
+
+	std::fs::remove_dir_all(&root).unwrap();
+	tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+	std::fs::create_dir(&root).unwrap();
+	common::create_test_file(&root.join("shipped.txt"), "v2").unwrap();
+
+	match next_root_diagnostic(&mut diagnostics, 3).await {
+		Some(WatcherDiagnostic::WatchRootReplaced { path }) => assert_eq!(path, root),
+		other => panic!("expected WatchRootReplaced, got {other:?}"),
+	}
+
+	// Changes in the new root keep flowing on the same channel
+	common::create_test_file(&root.join("after.txt"), "x").unwrap();
+	let mut seen = std::collections::HashSet::new();
+	while let Ok(Some(event)) =
+		tokio::time::timeout(std::time::Duration::from_secs(1), receiver.recv()).await
+	{
+		if event.event_type == rust_watcher::EventType::Create {
+			seen.insert(event.path);
+		}
+	}
+	assert!(seen.contains(&root.join("shipped.txt")), "{seen:?}");
+	assert!(seen.contains(&root.join("after.txt")), "{seen:?}");
+	assert!(handle.healthy());
+	handle.stop().await.unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_root_missing_past_grace_stops_the_watcher() {
+	let temp_dir = common::setup_temp_dir();
+	let root = temp_dir.path().join("release");
+	std::fs::create_dir(&root).unwrap();
+	let config = WatcherConfig {
+		path: root.clone(),
+		root_gone_grace: std::time::Duration::from_millis(300),
+		..Default::default()
+	};
+	let (handle, mut receiver, mut diagnostics) = start_with_diagnostics(config).unwrap();
+	handle.ready().await.unwrap();
+	let root = root.canonicalize().unwrap();
+
+	std::fs::remove_dir(&root).unwrap();
+	match next_root_diagnostic(&mut diagnostics, 3).await {
+		Some(WatcherDiagnostic::WatchRootGone { path }) => assert_eq!(path, root),
+		other => panic!("expected WatchRootGone, got {other:?}"),
+	}
+	// The event channel closes once the watcher has stopped
+	let closed = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+		while receiver.recv().await.is_some() {}
+	})
+	.await;
+	assert!(closed.is_ok(), "event channel still open");
+	assert!(!handle.healthy());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_file_renamed_back_and_forth_is_reported_as_flapping() {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig {
+		path: temp_dir.path().to_path_buf(),
+		move_detector_config: Some(MoveDetectorConfig {
+			flapping_threshold: Some(3),
+			..Default::default()
+		}),
+		..Default::default()
+	};
+	let root = temp_dir.path().canonicalize().unwrap();
+	let (a, b) = (root.join("a.txt"), root.join("b.txt"));
+	common::create_test_file(&a, "flap").unwrap();
+	let (handle, mut receiver, mut diagnostics) = start_with_diagnostics(config).unwrap();
+	handle.ready().await.unwrap();
+
+	// Spaced past the exchange window so each rename is a plain move
+	for i in 0..6 {
+		let (from, to) = if i % 2 == 0 { (&a, &b) } else { (&b, &a) };
+		std::fs::rename(from, to).unwrap();
+		tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+	}
+
+	let flapping = next_root_diagnostic(&mut diagnostics, 3).await;
+	while let Ok(Some(_)) =
+		tokio::time::timeout(std::time::Duration::from_millis(200), receiver.recv()).await
+	{}
+	handle.stop().await.unwrap();
+
+	match flapping {
+		Some(WatcherDiagnostic::FlappingDetected { paths, moves, .. }) => {
+			assert_eq!(paths, vec![a, b]);
+			assert_eq!(moves, 4);
+		}
+		other => panic!("expected FlappingDetected, got {other:?}"),
 	}
+	// Reported once per episode
+	assert!(next_root_diagnostic(&mut diagnostics, 0).await.is_none());
 }
@@ -7,6 +7,8 @@
 use rust_watcher::database::storage::core::{DatabaseStorage, RedbStorage};
 use rust_watcher::database::storage::event_retention::{cleanup_old_events, EventRetentionConfig};
 use rust_watcher::database::types::EventRecord;
+use rust_watcher::EventType;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use tempfile::tempdir;
 
@@ -37,6 +39,7 @@ async fn test_event_retention_cleanup_storage() {
 		content_hash: None,
 		confidence: None,
 		detection_method: None,
+		source_path: None,
 		expires_at: now - chrono::Duration::seconds(60), // already expired
 	};
 	let recent_event = EventRecord {
@@ -52,6 +55,7 @@ async fn test_event_retention_cleanup_storage() {
 		content_hash: None,
 		confidence: None,
 		detection_method: None,
+		source_path: None,
 		expires_at: now + chrono::Duration::seconds(120), // not expired
 	};
 	storage.store_event(&old_event).await.expect("Failed to store old event");
@@ -60,6 +64,7 @@ async fn test_event_retention_cleanup_storage() {
 	// Run cleanup with time-based retention
 	let retention_cfg = EventRetentionConfig {
 		max_event_age: std::time::Duration::from_secs(61), // 61 seconds
+		type_retention: HashMap::new(),
 		max_events: None,
 		background: false,
 		background_interval: None,
@@ -83,6 +88,7 @@ async fn test_event_retention_cleanup_storage() {
 			content_hash: None,
 			confidence: None,
 			detection_method: None,
+			source_path: None,
 			expires_at: now + chrono::Duration::seconds(120),
 		};
 		storage.store_event(&event).await.expect("Failed to store event");
@@ -91,6 +97,7 @@ async fn test_event_retention_cleanup_storage() {
 	// Run cleanup with count-based retention
 	let retention_cfg = EventRetentionConfig {
 		max_event_age: std::time::Duration::from_secs(3600), // Keep all by age
+		type_retention: HashMap::new(),
 		max_events: Some(5),
 		background: false,
 		background_interval: None,
@@ -130,6 +137,7 @@ async fn test_event_retention_duplicates_and_out_of_order() {
 		content_hash: None,
 		confidence: None,
 		detection_method: None,
+		source_path: None,
 		expires_at: now - chrono::Duration::seconds(1800), // expired
 	};
 	let event2 = event1.clone();
@@ -150,6 +158,7 @@ async fn test_event_retention_duplicates_and_out_of_order() {
 		content_hash: None,
 		confidence: None,
 		detection_method: None,
+		source_path: None,
 		expires_at: now + chrono::Duration::seconds(7200), // not expired
 	};
 	let past_event = EventRecord {
@@ -165,6 +174,7 @@ async fn test_event_retention_duplicates_and_out_of_order() {
 		content_hash: None,
 		confidence: None,
 		detection_method: None,
+		source_path: None,
 		expires_at: now - chrono::Duration::seconds(3600), // expired
 	};
 	storage.store_event(&future_event).await.expect("Failed to store future_event");
@@ -173,6 +183,7 @@ async fn test_event_retention_duplicates_and_out_of_order() {
 	// Run cleanup with a retention window that should only keep the future event
 	let retention_cfg = EventRetentionConfig {
 		max_event_age: std::time::Duration::from_secs(1800), // 30 min
+		type_retention: HashMap::new(),
 		max_events: None,
 		background: false,
 		background_interval: None,
@@ -186,3 +197,87 @@ async fn test_event_retention_duplicates_and_out_of_order() {
 		"Expected only the future event to remain, found {remaining}"
 	);
 }
+
+#[tokio::test]
+async fn test_event_retention_per_event_type() {
+	let temp_dir = tempdir().expect("Failed to create temp directory");
+	let config = rust_watcher::database::DatabaseConfig {
+		database_path: temp_dir.path().join("retention_by_type.redb"),
+		..rust_watcher::database::DatabaseConfig::for_small_directories()
+	};
+	let mut storage = RedbStorage::new(config).await.expect("Failed to create storage");
+
+	// Both an hour old: past the Write retention, within the Move retention
+	let an_hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+	let record = |event_type: &str, path: &str| EventRecord {
+		timestamp: an_hour_ago,
+		expires_at: an_hour_ago + chrono::Duration::days(1),
+		..EventRecord::new(
+			event_type.to_string(),
+			PathBuf::from(path),
+			false,
+			chrono::Duration::days(1),
+			0,
+		)
+	};
+	storage
+		.store_event(&record("Write", "/w/noise.txt"))
+		.await
+		.expect("Failed to store write");
+	storage
+		.store_event(&record("Move", "/w/moved.txt"))
+		.await
+		.expect("Failed to store move");
+
+	let retention_cfg = EventRetentionConfig {
+		type_retention: HashMap::from([
+			(EventType::Write, std::time::Duration::from_secs(60)),
+			(
+				EventType::Move,
+				std::time::Duration::from_secs(7 * 24 * 3600),
+			),
+		]),
+		..EventRetentionConfig::default()
+	};
+	let removed = cleanup_old_events(&mut storage, &retention_cfg).await.expect("Cleanup failed");
+	assert_eq!(removed, 1);
+
+	let end = chrono::Utc::now();
+	let remaining = storage
+		.find_events_by_time_range(an_hour_ago - chrono::Duration::minutes(1), end)
+		.await
+		.expect("Query failed");
+	let types: Vec<&str> = remaining.iter().map(|event| event.event_type.as_str()).collect();
+	assert_eq!(types, vec!["Move"]);
+}
+
+#[tokio::test]
+async fn test_event_type_retention_sets_expiry_for_default_cleanup() {
+	use rust_watcher::{DatabaseAdapter, FileSystemEvent};
+	use std::time::Duration;
+
+	let temp_dir = tempdir().expect("Failed to create temp directory");
+	let config = rust_watcher::database::DatabaseConfig {
+		database_path: temp_dir.path().join("expiry_by_type.redb"),
+		event_retention: Duration::from_millis(500),
+		event_type_retention: HashMap::from([(EventType::Move, Duration::from_secs(3600))]),
+		..rust_watcher::database::DatabaseConfig::for_small_directories()
+	};
+	let adapter = DatabaseAdapter::new(config).await.expect("Failed to create adapter");
+	let (written, moved) = (
+		PathBuf::from("/w/written.txt"),
+		PathBuf::from("/w/moved.txt"),
+	);
+	let write = FileSystemEvent::new(EventType::Write, written.clone(), false, Some(1));
+	let mv = FileSystemEvent::new(EventType::Move, moved.clone(), false, Some(1));
+	adapter.store_event(&write).await.unwrap();
+	adapter.store_event(&mv).await.unwrap();
+
+	// Past the Write's expiry, as `cleanup_old_events` measures it, but not the Move's
+	tokio::time::sleep(Duration::from_millis(1200)).await;
+	let removed = adapter.cleanup_old_events().await.unwrap();
+	assert_eq!(removed, 1);
+	assert!(adapter.get_events_for_path(&written).await.unwrap().is_empty());
+	assert_eq!(adapter.get_events_for_path(&moved).await.unwrap().len(), 1);
+	adapter.close().await.unwrap();
+}
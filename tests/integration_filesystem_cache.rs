@@ -67,6 +67,37 @@ async fn test_get_node_api() {
 	assert!(missing.is_none());
 }
 
+#[cfg(windows)]
+#[tokio::test]
+async fn test_mixed_separators_resolve_to_the_same_node() {
+	let (temp_dir, _db_path, mut storage, watch_id) = setup_test_storage("mixed_separators").await;
+	let base = temp_dir.path().to_string_lossy().to_string();
+	let forward = std::path::PathBuf::from(format!("{}/dir/file.txt", base.replace('\\', "/")));
+	let node = create_and_store_node(&mut storage, &watch_id, &forward, "test").await;
+	// Once the file is gone, lookups cannot canonicalize and go by the path as written
+	std::fs::remove_file(&forward).unwrap();
+	storage
+		.store_filesystem_node(&watch_id, &node, "test")
+		.await
+		.expect("store node");
+
+	for variant in [
+		format!("{base}\\dir\\file.txt"),
+		format!("{base}\\dir/file.txt\\"),
+		format!("{}//dir//file.txt/", base.replace('\\', "/")),
+	] {
+		let retrieved = storage
+			.get_filesystem_node(&watch_id, std::path::Path::new(&variant))
+			.await
+			.expect("get");
+		assert_eq!(
+			retrieved.map(|found| found.path),
+			Some(node.path.clone()),
+			"{variant}"
+		);
+	}
+}
+
 #[tokio::test]
 async fn test_search_nodes_glob_patterns() {
 	let (temp_dir, _db_path, mut storage, watch_id) =
@@ -188,3 +219,150 @@ async fn test_hierarchy_ancestor_descendant_traversal() {
 	// Should not include root itself
 	assert!(!descendant_paths.contains(&root));
 }
+
+#[tokio::test]
+async fn test_sorted_directory_listings() {
+	use rust_watcher::database::storage::filesystem_cache::RedbFilesystemCache;
+	use rust_watcher::database::storage::FilesystemCacheStorage;
+	use rust_watcher::database::types::{FilesystemNode, WatchMetadata};
+	use rust_watcher::database::{ListingOrder, ListingSort};
+	use std::time::{Duration, SystemTime};
+
+	let (temp_dir, _db_path, storage, watch_id) = setup_test_storage("sorted_listings").await;
+	let mut cache = RedbFilesystemCache::new(storage.get_database());
+	let parent = temp_dir.path().join("listing");
+	std::fs::create_dir(&parent).unwrap();
+	// name, size, age in hours; `None` is a directory
+	let entries = [
+		("beta.txt", Some(30), 1),
+		("Alpha.txt", Some(10), 3),
+		("zeta", None, 2),
+		("gamma.txt", Some(20), 4),
+		("delta", None, 5),
+	];
+	for (name, size, age) in entries {
+		let path = parent.join(name);
+		match size {
+			Some(size) => std::fs::write(&path, vec![b'x'; size]).unwrap(),
+			None => std::fs::create_dir(&path).unwrap(),
+		}
+		let modified = SystemTime::now() - Duration::from_secs(age * 3600);
+		std::fs::File::open(&path).unwrap().set_modified(modified).unwrap();
+		let node = FilesystemNode::new(path.clone(), &std::fs::metadata(&path).unwrap());
+		cache.store_filesystem_node(&watch_id, &node, "Create").await.unwrap();
+	}
+
+	let names = |nodes: Vec<FilesystemNode>| -> Vec<String> {
+		let name = |node: &FilesystemNode| node.path.file_name().unwrap().to_string_lossy().into();
+		nodes.iter().map(name).collect()
+	};
+	macro_rules! listed {
+		($order:expr) => {
+			names(cache.list_directory_for_watch_sorted(&watch_id, &parent, $order).await.unwrap())
+		};
+	}
+	assert_eq!(
+		listed!(ListingOrder::default()),
+		["Alpha.txt", "beta.txt", "delta", "gamma.txt", "zeta"]
+	);
+	assert_eq!(
+		listed!(ListingOrder::by(ListingSort::Name).directories_first()),
+		["delta", "zeta", "Alpha.txt", "beta.txt", "gamma.txt"]
+	);
+	// Directories count as 0 bytes and tie, so they fall back to name order
+	assert_eq!(
+		listed!(ListingOrder::by(ListingSort::Size)),
+		["delta", "zeta", "Alpha.txt", "gamma.txt", "beta.txt"]
+	);
+	assert_eq!(
+		listed!(ListingOrder::by(ListingSort::Size).descending().directories_first()),
+		["delta", "zeta", "beta.txt", "gamma.txt", "Alpha.txt"]
+	);
+	assert_eq!(
+		listed!(ListingOrder::by(ListingSort::Modified).descending()),
+		["beta.txt", "zeta", "Alpha.txt", "gamma.txt", "delta"]
+	);
+
+	// The unified view only covers registered watches
+	let metadata = WatchMetadata {
+		watch_id,
+		root_path: parent.clone(),
+		created_at: chrono::Utc::now(),
+		last_scan: None,
+		node_count: 0,
+		is_active: true,
+		config_hash: 0,
+		event_retention: None,
+		permissions: None,
+	};
+	cache.store_watch_metadata(&metadata).await.unwrap();
+	let unified = cache
+		.list_directory_unified_sorted(&parent, ListingOrder::by(ListingSort::Modified))
+		.await
+		.unwrap();
+	assert_eq!(
+		names(unified),
+		["delta", "gamma.txt", "Alpha.txt", "zeta", "beta.txt"]
+	);
+}
+
+#[tokio::test]
+async fn test_paged_unified_listing_visits_each_child_once() {
+	use rust_watcher::database::storage::filesystem_cache::RedbFilesystemCache;
+	use rust_watcher::database::storage::FilesystemCacheStorage;
+	use rust_watcher::database::types::{FilesystemNode, WatchMetadata};
+	use std::collections::BTreeSet;
+
+	let (temp_dir, _db_path, storage, _) = setup_test_storage("paged_listing").await;
+	let mut cache = RedbFilesystemCache::new(storage.get_database());
+	std::fs::create_dir(temp_dir.path().join("wide")).unwrap();
+	let parent = temp_dir.path().join("wide").canonicalize().unwrap();
+	let files: Vec<_> = (0..7).map(|i| parent.join(format!("file_{i}.txt"))).collect();
+	for file in &files {
+		std::fs::write(file, "x").unwrap();
+	}
+
+	// Two watches of `parent` with overlapping children, and one elsewhere
+	let mut watches = Vec::new();
+	for root in [parent.clone(), parent.clone(), temp_dir.path().to_path_buf()] {
+		let metadata = WatchMetadata {
+			watch_id: uuid::Uuid::new_v4(),
+			root_path: root,
+			created_at: chrono::Utc::now(),
+			last_scan: None,
+			node_count: 0,
+			is_active: true,
+			config_hash: 0,
+			event_retention: None,
+			permissions: None,
+		};
+		cache.store_watch_metadata(&metadata).await.unwrap();
+		watches.push(metadata.watch_id);
+	}
+	for (watch_id, range) in [(watches[0], 0..5), (watches[1], 3..7)] {
+		for file in &files[range] {
+			let node = FilesystemNode::new(file.clone(), &std::fs::metadata(file).unwrap());
+			cache.store_filesystem_node(&watch_id, &node, "Create").await.unwrap();
+		}
+	}
+
+	let mut listed = Vec::new();
+	let mut cursor = None;
+	loop {
+		let page = cache.list_directory_unified_page(&parent, cursor.as_ref(), 3).await.unwrap();
+		assert!(page.nodes.len() <= 3);
+		listed.extend(page.nodes.into_iter().map(|node| node.path));
+		match page.next_cursor {
+			Some(next) => cursor = Some(next),
+			None => break,
+		}
+	}
+	assert_eq!(listed.len(), files.len(), "{listed:?}");
+	assert_eq!(
+		listed.into_iter().collect::<BTreeSet<_>>(),
+		files.iter().cloned().collect::<BTreeSet<_>>()
+	);
+
+	let unified = cache.list_directory_unified(&parent).await.unwrap();
+	assert_eq!(unified.len(), files.len());
+}
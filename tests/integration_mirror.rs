@@ -0,0 +1,73 @@
+// End-to-end check that the event stream is enough to follow a tree (see src/mirror.rs)
+//
+// Only built with the `mirror` feature: cargo test --features mirror --test integration_mirror
+#![cfg(feature = "mirror")]
+
+use rust_watcher::mirror::DirectoryMirror;
+use rust_watcher::{start, MoveDetectorConfig, WatcherConfig};
+use std::fs;
+use std::time::Duration;
+
+mod common;
+
+#[tokio::test]
+async fn test_mirror_follows_a_mix_of_operations() {
+	let temp_dir = common::setup_temp_dir();
+	let root = temp_dir.path().canonicalize().unwrap();
+	fs::create_dir_all(root.join("old/sub")).unwrap();
+	common::create_test_file(&root.join("keep.txt"), "keep").unwrap();
+	common::create_test_file(&root.join("old/a.txt"), "a").unwrap();
+	common::create_test_file(&root.join("old/sub/b.txt"), "bb").unwrap();
+	common::create_test_file(&root.join("doomed.txt"), "doomed").unwrap();
+
+	let config = WatcherConfig {
+		path: root.clone(),
+		recursive: true,
+		move_detector_config: Some(MoveDetectorConfig {
+			// Report renames the detector cannot pair as removes instead of dropping them
+			round_trip_window: Some(Duration::from_secs(5)),
+			..MoveDetectorConfig::with_timeout(300)
+		}),
+		..Default::default()
+	};
+	let (handle, mut receiver) = start(config).unwrap();
+	handle.ready().await.unwrap();
+	let mut mirror = DirectoryMirror::scan(&root).unwrap();
+
+	common::create_test_file(&root.join("added.txt"), "added").unwrap();
+	fs::write(root.join("keep.txt"), "keep, now longer").unwrap();
+	fs::create_dir(root.join("fresh")).unwrap();
+	common::create_test_file(&root.join("fresh/c.txt"), "c").unwrap();
+	// Often before `fresh` is watched: seen as a rename out of the tree plus a catch-up create
+	fs::rename(root.join("old/a.txt"), root.join("fresh/a.txt")).unwrap();
+	fs::remove_file(root.join("old/sub/b.txt")).unwrap();
+	// Last for its subtree: changes below a renamed directory can be reported under its old path
+	fs::rename(root.join("old"), root.join("moved")).unwrap();
+	fs::remove_file(root.join("doomed.txt")).unwrap();
+
+	// Held removes and creates are released once the move timeout passes
+	while let Ok(Some(event)) =
+		tokio::time::timeout(Duration::from_millis(1500), receiver.recv()).await
+	{
+		mirror.apply(&event);
+	}
+	handle.stop().await.unwrap();
+
+	let on_disk = DirectoryMirror::scan(&root).unwrap();
+	assert_eq!(mirror.relative_paths(), on_disk.relative_paths());
+	for (path, entry) in on_disk.entries() {
+		assert_eq!(
+			mirror.entries()[path].is_directory,
+			entry.is_directory,
+			"{}",
+			path.display()
+		);
+	}
+
+	let target = tempfile::tempdir().unwrap();
+	mirror.materialize(target.path()).unwrap();
+	assert_eq!(
+		DirectoryMirror::scan(target.path()).unwrap().relative_paths(),
+		on_disk.relative_paths()
+	);
+}
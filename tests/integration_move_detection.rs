@@ -2,7 +2,7 @@
 // Tests move detector using public API only
 
 use chrono::Utc;
-use rust_watcher::{EventType, FileSystemEvent, MoveDetector, MoveDetectorConfig};
+use rust_watcher::{EventType, FileSystemEvent, MoveDetector, MoveDetectorConfig, MoveScope};
 use uuid::Uuid;
 
 use rust_watcher::move_detection::test_helpers::DummyCache;
@@ -51,6 +51,13 @@ async fn test_move_detector_event_processing() {
 		is_directory: false,
 		size: Some(12),
 		move_data: None,
+		timestamp_source: rust_watcher::TimestampSource::ProcessingTime,
+		ownership: None,
+		snapshot: false,
+		sequence: None,
+		identity_paths: Vec::new(),
+		startup: false,
+		resolved_path: None,
 	};
 
 	let create_event = FileSystemEvent {
@@ -61,6 +68,13 @@ async fn test_move_detector_event_processing() {
 		is_directory: false,
 		size: Some(12),
 		move_data: None,
+		timestamp_source: rust_watcher::TimestampSource::ProcessingTime,
+		ownership: None,
+		snapshot: false,
+		sequence: None,
+		identity_paths: Vec::new(),
+		startup: false,
+		resolved_path: None,
 	};
 	// Process events
 	let result1 = detector.process_event(remove_event).await;
@@ -92,3 +106,646 @@ fn test_move_detector_config_validation() {
 		"Invalid config should fail validation"
 	);
 }
+
+#[tokio::test]
+async fn test_zero_byte_moves_pair_by_name() {
+	// Low threshold so that size + timing alone would be enough to pair unrelated empty files
+	let config = MoveDetectorConfig { confidence_threshold: 0.4, ..Default::default() };
+	let mut dummy_cache = DummyCache;
+	let mut detector = MoveDetector::new(config, &mut dummy_cache);
+
+	let temp_dir = common::setup_temp_dir();
+	let src_dir = temp_dir.path().join("src");
+	let dst_dir = temp_dir.path().join("dst");
+
+	let empty_event = |event_type, path: std::path::PathBuf| {
+		FileSystemEvent::new(event_type, path, false, Some(0))
+	};
+
+	let start = std::time::Instant::now();
+
+	for i in 0..50 {
+		let path = src_dir.join(format!("marker_{i}.lock"));
+		detector.process_event(empty_event(EventType::Remove, path)).await;
+	}
+	for i in 0..10 {
+		let path = src_dir.join(format!("deleted_{i}.flag"));
+		detector.process_event(empty_event(EventType::Remove, path)).await;
+	}
+
+	let mut moves = Vec::new();
+	for i in 0..50 {
+		let path = dst_dir.join(format!("marker_{i}.lock"));
+		moves.extend(detector.process_event(empty_event(EventType::Create, path)).await);
+	}
+	let mut unrelated = Vec::new();
+	for i in 0..10 {
+		let path = dst_dir.join(format!("fresh_{i}.flag"));
+		unrelated.extend(detector.process_event(empty_event(EventType::Create, path)).await);
+	}
+
+	let elapsed = start.elapsed();
+	assert!(
+		elapsed < std::time::Duration::from_secs(5),
+		"Zero-byte matching took too long: {elapsed:?}"
+	);
+
+	assert_eq!(moves.len(), 50);
+	for event in &moves {
+		let move_data = event.move_data.as_ref().expect("empty file move should be detected");
+		assert_eq!(
+			move_data.source_path.file_name(),
+			move_data.destination_path.file_name(),
+			"Zero-byte move paired with the wrong source"
+		);
+		assert_eq!(move_data.source_path.parent(), Some(src_dir.as_path()));
+	}
+
+	assert!(
+		unrelated.iter().all(|e| !e.is_move()),
+		"Unrelated empty files must not be paired"
+	);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_non_utf8_file_move_detected_and_serialized() {
+	use std::ffi::OsStr;
+	use std::os::unix::ffi::OsStrExt;
+
+	let temp_dir = common::setup_temp_dir();
+	let src_dir = temp_dir.path().join("src");
+	let dst_dir = temp_dir.path().join("dst");
+	std::fs::create_dir_all(&dst_dir).unwrap();
+
+	// Latin-1 names: lossy UTF-8 turns both into the same "r\u{fffd}sum\u{fffd}.txt"
+	let source = src_dir.join(OsStr::from_bytes(b"r\xe9sum\xe9.txt"));
+	let decoy = src_dir.join(OsStr::from_bytes(b"r\xe8sum\xe8.txt"));
+	let destination = dst_dir.join(OsStr::from_bytes(b"r\xe9sum\xe9.txt"));
+	std::fs::write(&destination, "latin-1 named file").unwrap();
+	let size = std::fs::metadata(&destination).unwrap().len();
+
+	// No inode or content hash is available for already-removed paths, so size, timing and
+	// name similarity decide; the decoy only loses on name similarity
+	let config = MoveDetectorConfig { confidence_threshold: 0.4, ..Default::default() };
+	let mut dummy_cache = DummyCache;
+	let mut detector = MoveDetector::new(config, &mut dummy_cache);
+
+	detector
+		.process_event(FileSystemEvent::new(
+			EventType::Remove,
+			decoy.clone(),
+			false,
+			Some(size),
+		))
+		.await;
+	detector
+		.process_event(FileSystemEvent::new(
+			EventType::Remove,
+			source.clone(),
+			false,
+			Some(size),
+		))
+		.await;
+	let results = detector
+		.process_event(FileSystemEvent::new(
+			EventType::Create,
+			destination.clone(),
+			false,
+			Some(size),
+		))
+		.await;
+
+	let move_event = results.iter().find(|e| e.is_move()).expect("move should be detected");
+	let move_data = move_event.move_data.as_ref().unwrap();
+	assert_eq!(
+		move_data.source_path, source,
+		"paired with the wrong non-UTF-8 source"
+	);
+	assert_eq!(move_data.destination_path, destination);
+
+	let json = move_event.to_json().expect("non-UTF-8 paths must serialize");
+	let restored: FileSystemEvent = serde_json::from_str(&json).unwrap();
+	assert_eq!(restored.path, destination);
+	let restored_move = restored.move_data.unwrap();
+	assert_eq!(restored_move.source_path, source);
+	assert_eq!(restored_move.destination_path, destination);
+}
+
+#[tokio::test]
+async fn test_create_just_after_timeout_pairs_as_late_move() {
+	let temp_dir = common::setup_temp_dir();
+	let source = temp_dir.path().join("src").join("report.pdf");
+	let destination = temp_dir.path().join("dst").join("report.pdf");
+	let event = |event_type: EventType, path: std::path::PathBuf| {
+		FileSystemEvent::new(event_type, path, false, Some(1234))
+	};
+
+	for late_pairing_window in [Some(std::time::Duration::from_secs(1)), None] {
+		let config = MoveDetectorConfig {
+			timeout: std::time::Duration::from_millis(50),
+			late_pairing_window,
+			..Default::default()
+		};
+		let mut dummy_cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut dummy_cache);
+
+		detector.process_event(event(EventType::Remove, source.clone())).await;
+		tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+		let results = detector.process_event(event(EventType::Create, destination.clone())).await;
+
+		assert_eq!(results.len(), 1);
+		if late_pairing_window.is_some() {
+			assert_eq!(results[0].event_type, EventType::Move);
+			let move_data =
+				results[0].move_data.as_ref().expect("late move should carry move data");
+			assert!(move_data.late);
+			assert_eq!(move_data.source_path, source);
+			assert_eq!(move_data.destination_path, destination);
+		} else {
+			assert_eq!(results[0].event_type, EventType::Create);
+		}
+	}
+}
+
+#[tokio::test]
+async fn test_file_moved_out_and_back_is_a_round_trip() {
+	let temp_dir = common::setup_temp_dir();
+	let watched = temp_dir.path().join("watched");
+	let staging = temp_dir.path().join("staging");
+	std::fs::create_dir_all(&watched).unwrap();
+	std::fs::create_dir_all(&staging).unwrap();
+	let path = watched.join("report.pdf");
+	let outside = staging.join("report.pdf");
+
+	for round_trip_window in [Some(std::time::Duration::from_secs(1)), None] {
+		let config = MoveDetectorConfig {
+			timeout: std::time::Duration::from_millis(50),
+			round_trip_window,
+			..Default::default()
+		};
+		let mut dummy_cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut dummy_cache);
+
+		common::create_test_file(&path, "quarterly figures").unwrap();
+		let size = Some(std::fs::metadata(&path).unwrap().len());
+		detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				path.clone(),
+				false,
+				size,
+			))
+			.await;
+
+		// Out to the staging area: only the old name is seen
+		std::fs::rename(&path, &outside).unwrap();
+		let results = detector
+			.process_event(FileSystemEvent::new(
+				EventType::RenameFrom,
+				path.clone(),
+				false,
+				None,
+			))
+			.await;
+		assert!(results.is_empty());
+		assert_eq!(detector.has_deferred_removes(), round_trip_window.is_some());
+		tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+		let departed = detector.take_expired_removes().await;
+
+		// And back: only the new name is seen
+		std::fs::rename(&outside, &path).unwrap();
+		let results = detector
+			.process_event(FileSystemEvent::new(
+				EventType::RenameTo,
+				path.clone(),
+				false,
+				size,
+			))
+			.await;
+		assert_eq!(results.len(), 1);
+		if round_trip_window.is_some() {
+			assert_eq!(departed.len(), 1);
+			assert_eq!(departed[0].event_type, EventType::Remove);
+			assert_eq!(departed[0].path, path);
+			assert_eq!(results[0].event_type, EventType::Move);
+			let move_data = results[0].move_data.as_ref().expect("return should carry move data");
+			assert_eq!(
+				move_data.detection_method,
+				rust_watcher::MoveDetectionMethod::RoundTrip
+			);
+			assert!(move_data.late);
+			assert_eq!(move_data.source_path, path);
+			assert_eq!(move_data.destination_path, path);
+		} else {
+			assert!(departed.is_empty());
+			assert_ne!(results[0].event_type, EventType::Move);
+		}
+		std::fs::remove_file(&path).unwrap();
+	}
+}
+
+#[tokio::test]
+async fn test_os_timestamp_drives_move_latency() {
+	let source = std::path::PathBuf::from("/nonexistent/src/latency.bin");
+	let destination = std::path::PathBuf::from("/nonexistent/dst/latency.bin");
+	let config = MoveDetectorConfig {
+		timeout: std::time::Duration::from_millis(200),
+		confidence_threshold: 0.4,
+		..Default::default()
+	};
+
+	// Processed back to back, but the OS saw the remove well before the timeout
+	for (os_stamped, expect_move) in [(false, true), (true, false)] {
+		let mut dummy_cache = DummyCache;
+		let mut detector = MoveDetector::new(config.clone(), &mut dummy_cache);
+		let mut remove = FileSystemEvent::new(EventType::Remove, source.clone(), false, Some(77));
+		if os_stamped {
+			remove = remove.with_os_timestamp(Utc::now() - chrono::Duration::seconds(2));
+		}
+		detector.process_event(remove).await;
+		let results = detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				destination.clone(),
+				false,
+				Some(77),
+			))
+			.await;
+		assert_eq!(
+			results.iter().any(|e| e.event_type == EventType::Move),
+			expect_move,
+			"os_stamped={os_stamped}: {results:?}"
+		);
+	}
+}
+
+#[tokio::test]
+async fn test_pending_event_ages_within_bounds() {
+	let mut dummy_cache = DummyCache;
+	let mut detector = MoveDetector::new(MoveDetectorConfig::default(), &mut dummy_cache);
+	let root = std::path::PathBuf::from("/nonexistent/ages");
+
+	let start = std::time::Instant::now();
+	for (name, size) in [("a.log", 10), ("b.log", 20)] {
+		let event = FileSystemEvent::new(EventType::Remove, root.join(name), false, Some(size));
+		detector.process_event(event).await;
+	}
+	let create = FileSystemEvent::new(EventType::Create, root.join("c.bin"), false, Some(30));
+	detector.process_event(create).await;
+	tokio::time::sleep(std::time::Duration::from_millis(60)).await;
+
+	let ages = detector.pending_event_ages();
+	let upper = start.elapsed();
+	assert_eq!(ages.removes.len(), 2);
+	assert_eq!(ages.creates.len(), 1);
+	for age in ages.removes.iter().chain(&ages.creates) {
+		assert!(
+			*age >= std::time::Duration::from_millis(60) && *age <= upper,
+			"age {age:?}"
+		);
+	}
+	assert!(
+		ages.removes.windows(2).all(|w| w[0] <= w[1]),
+		"ages should be sorted"
+	);
+
+	let stats = detector.get_resource_stats();
+	assert_eq!(stats.pending_event_ages.removes.len(), 2);
+	assert_eq!(
+		stats.pending_event_ages.oldest_create(),
+		stats.pending_event_ages.creates.last().copied()
+	);
+}
+
+#[tokio::test]
+async fn test_move_scope_filters_same_and_cross_directory_pairs() {
+	let temp_dir = common::setup_temp_dir();
+	let dir_a = temp_dir.path().join("a");
+	let dir_b = temp_dir.path().join("b");
+	// Paths are never created on disk, so renames are not held as possible exchange halves
+	let cases = [
+		(
+			"same-dir",
+			dir_a.join("old.txt"),
+			dir_a.join("new.txt"),
+			true,
+		),
+		(
+			"cross-dir",
+			dir_a.join("old.txt"),
+			dir_b.join("new.txt"),
+			false,
+		),
+	];
+
+	for scope in [MoveScope::AllMoves, MoveScope::CrossDirectoryOnly, MoveScope::SameDirectoryOnly]
+	{
+		for (label, source, destination, same_directory) in &cases {
+			let expect_move = match scope {
+				MoveScope::AllMoves => true,
+				MoveScope::CrossDirectoryOnly => !same_directory,
+				MoveScope::SameDirectoryOnly => *same_directory,
+			};
+			let config = MoveDetectorConfig {
+				move_scope: scope,
+				confidence_threshold: 0.4,
+				..Default::default()
+			};
+			let event = |event_type, path: &std::path::PathBuf| {
+				FileSystemEvent::new(event_type, path.clone(), false, Some(42))
+			};
+
+			// Native rename pair
+			let mut dummy_cache = DummyCache;
+			let mut detector = MoveDetector::new(config.clone(), &mut dummy_cache);
+			assert!(detector.process_event(event(EventType::RenameFrom, source)).await.is_empty());
+			let output = detector.process_event(event(EventType::RenameTo, destination)).await;
+			if expect_move {
+				assert_eq!(output.len(), 1, "{scope:?}/{label}: {output:?}");
+				let move_data = output[0].move_data.as_ref().expect("rename should be a move");
+				assert_eq!(
+					move_data.same_directory, *same_directory,
+					"{scope:?}/{label}"
+				);
+			} else {
+				let kinds: Vec<_> =
+					output.iter().map(|e| (e.event_type.clone(), e.path.clone())).collect();
+				assert_eq!(
+					kinds,
+					vec![
+						(EventType::Remove, source.clone()),
+						(EventType::Create, destination.clone())
+					],
+					"{scope:?}/{label}"
+				);
+			}
+
+			// Heuristic remove/create pair
+			let mut dummy_cache = DummyCache;
+			let mut detector = MoveDetector::new(config, &mut dummy_cache);
+			detector.process_event(event(EventType::Remove, source)).await;
+			let output = detector.process_event(event(EventType::Create, destination)).await;
+			assert_eq!(output.len(), 1, "{scope:?}/{label}: {output:?}");
+			match &output[0].move_data {
+				Some(move_data) => {
+					assert!(expect_move, "{scope:?}/{label}: paired outside scope");
+					assert_eq!(move_data.same_directory, *same_directory);
+				}
+				None => {
+					assert!(!expect_move, "{scope:?}/{label}: expected a heuristic move");
+					assert_eq!(output[0].event_type, EventType::Create);
+				}
+			}
+		}
+	}
+}
+
+#[tokio::test]
+async fn test_timing_as_tiebreaker_only_rejects_unrelated_same_size_pair() {
+	let temp_dir = common::setup_temp_dir();
+	// Neither path exists, so there is no inode or content hash to go on: size and timing only
+	let removed = temp_dir.path().join("report.pdf");
+	let created = temp_dir.path().join("holiday.jpg");
+
+	let mut outcomes = Vec::new();
+	for timing_as_tiebreaker_only in [false, true] {
+		let config = MoveDetectorConfig {
+			confidence_threshold: 0.4,
+			timing_as_tiebreaker_only,
+			..Default::default()
+		};
+		let mut dummy_cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut dummy_cache);
+		detector
+			.process_event(FileSystemEvent::new(
+				EventType::Remove,
+				removed.clone(),
+				false,
+				Some(4096),
+			))
+			.await;
+		tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+		let output = detector
+			.process_event(FileSystemEvent::new(
+				EventType::Create,
+				created.clone(),
+				false,
+				Some(4096),
+			))
+			.await;
+		outcomes.push(output.iter().any(|event| event.is_move()));
+	}
+
+	assert_eq!(
+		outcomes,
+		vec![true, false],
+		"timing should only push the pair over the threshold when it is additive"
+	);
+}
+
+#[tokio::test]
+async fn test_defer_removes_reports_one_event_per_operation() {
+	let temp_dir = common::setup_temp_dir();
+	let moved_from = temp_dir.path().join("src").join("a.bin");
+	let moved_to = temp_dir.path().join("dst").join("a.bin");
+	let deleted = temp_dir.path().join("src").join("gone.txt");
+
+	let mut per_mode = Vec::new();
+	for defer_removes in [false, true] {
+		let config = MoveDetectorConfig {
+			timeout: std::time::Duration::from_millis(100),
+			confidence_threshold: 0.4,
+			defer_removes,
+			..Default::default()
+		};
+		let mut dummy_cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut dummy_cache);
+		let mut output = Vec::new();
+		for (event_type, path, size) in [
+			(EventType::Remove, &moved_from, 42),
+			(EventType::Remove, &deleted, 7),
+			(EventType::Create, &moved_to, 42),
+		] {
+			let event = FileSystemEvent::new(event_type, path.clone(), false, Some(size));
+			output.extend(detector.process_event(event).await);
+		}
+		tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+		output.extend(detector.take_expired_removes().await);
+		assert!(!detector.has_deferred_removes());
+		per_mode.push(output);
+	}
+
+	let touching = |events: &[FileSystemEvent], path: &std::path::Path| {
+		events
+			.iter()
+			.filter(|e| {
+				e.path == path || e.move_data.as_ref().is_some_and(|m| m.source_path == path)
+			})
+			.map(|e| e.event_type.clone())
+			.collect::<Vec<_>>()
+	};
+
+	// Immediate mode reports the move's Remove before the Move that supersedes it
+	assert_eq!(
+		touching(&per_mode[0], &moved_from),
+		vec![EventType::Remove, EventType::Move]
+	);
+
+	let deferred = &per_mode[1];
+	assert_eq!(touching(deferred, &moved_from), vec![EventType::Move]);
+	assert_eq!(touching(deferred, &deleted), vec![EventType::Remove]);
+	assert_eq!(deferred.len(), 2, "unexpected extra events: {deferred:?}");
+}
+
+/// Files deeper than MAX_PATH are still read for move matching
+#[cfg(windows)]
+#[tokio::test]
+async fn test_moves_past_max_path_are_detected() {
+	use rust_watcher::diagnostics::DiagnosticsSender;
+	use rust_watcher::WatcherDiagnostic;
+
+	let temp_dir = common::setup_temp_dir();
+	let mut deep = temp_dir.path().to_path_buf();
+	while deep.as_os_str().len() < 300 {
+		deep.push("a_rather_long_directory_name");
+	}
+	std::fs::create_dir_all(&deep).unwrap();
+	let (source, destination) = (deep.join("source.txt"), deep.join("destination.txt"));
+	std::fs::write(&source, "deep content").unwrap();
+	assert!(source.as_os_str().len() > 260);
+
+	let (diagnostics, mut diagnostics_rx) = DiagnosticsSender::channel();
+	let mut dummy_cache = DummyCache;
+	let mut detector = MoveDetector::new(MoveDetectorConfig::default(), &mut dummy_cache)
+		.with_diagnostics(diagnostics);
+	let event = |event_type, path: &std::path::Path| {
+		FileSystemEvent::new(event_type, path.to_path_buf(), false, None)
+	};
+
+	detector.process_event(event(EventType::Create, &source)).await;
+	std::fs::rename(&source, &destination).unwrap();
+	detector.process_event(event(EventType::Remove, &source)).await;
+	let results = detector.process_event(event(EventType::Create, &destination)).await;
+
+	assert!(
+		results.iter().any(|e| e.event_type == EventType::Move),
+		"Expected a move, got {results:?}"
+	);
+	while let Ok(diagnostic) = diagnostics_rx.try_recv() {
+		assert!(!matches!(diagnostic, WatcherDiagnostic::PathTooLong { .. }));
+	}
+}
+
+#[tokio::test]
+async fn test_confidence_ties_are_broken_by_path_proximity_then_age() {
+	use rust_watcher::move_detection::events::{PendingEvent, PendingEventsStorage};
+	use rust_watcher::move_detection::matching::{MoveMatching, ParentCorrelations};
+	use std::path::PathBuf;
+
+	let pending = |event_type, path: &str| {
+		PendingEvent::new(FileSystemEvent::new(
+			event_type,
+			PathBuf::from(path),
+			false,
+			Some(10),
+		))
+	};
+	// Same size and file name, no identity signals and no timing: an exact tie
+	let older = pending(EventType::Remove, "/w/archive/2023/report.txt");
+	std::thread::sleep(std::time::Duration::from_millis(2));
+	let closer = pending(EventType::Remove, "/w/docs-old/report.txt");
+	let create = pending(EventType::Create, "/w/docs/report.txt");
+	let correlations = ParentCorrelations::new(std::time::Duration::from_secs(1));
+
+	for by_proximity in [true, false] {
+		let config = MoveDetectorConfig {
+			confidence_threshold: 0.3,
+			weight_time_factor: 0.0,
+			tie_break_by_path_proximity: by_proximity,
+			..Default::default()
+		};
+		let expected = if by_proximity { &closer } else { &older };
+		// Insertion order must not matter
+		for removes in [[&older, &closer], [&closer, &older]] {
+			let mut storage = PendingEventsStorage::new();
+			for remove in removes {
+				storage.add_remove(remove.clone());
+			}
+			let matched =
+				MoveMatching::find_matching_remove(&create, &storage, &config, &correlations)
+					.await
+					.expect("both removes are above the threshold");
+			assert_eq!(matched.event.path, expected.event.path);
+		}
+	}
+}
+
+#[tokio::test]
+async fn test_cross_device_move_follows_a_confirmed_directory_mapping() {
+	use rust_watcher::MoveDetectionMethod;
+
+	let temp_dir = common::setup_temp_dir();
+	let (src, dst) = (temp_dir.path().join("src"), temp_dir.path().join("dst"));
+	let event = |event_type, path: std::path::PathBuf, size| {
+		FileSystemEvent::new(event_type, path, false, Some(size))
+	};
+
+	for stem_matching in [true, false] {
+		let config = MoveDetectorConfig {
+			confidence_threshold: 0.5,
+			cross_device_stem_matching: stem_matching,
+			..Default::default()
+		};
+		let mut dummy_cache = DummyCache;
+		let mut detector = MoveDetector::new(config, &mut dummy_cache);
+
+		// Copy + delete of a.txt, paired by size and name: establishes src -> dst
+		detector.process_event(event(EventType::Create, dst.join("a.txt"), 10)).await;
+		let first = detector.process_event(event(EventType::Remove, src.join("a.txt"), 10)).await;
+		assert_eq!(first.len(), 1);
+		assert_eq!(first[0].event_type, EventType::Move);
+
+		// b.txt is compressed on the way: different size and content, no shared inode
+		detector.process_event(event(EventType::Create, dst.join("b.txt.gz"), 4)).await;
+		let second = detector.process_event(event(EventType::Remove, src.join("b.txt"), 10)).await;
+		assert_eq!(second.len(), 1);
+		if stem_matching {
+			let move_data = second[0].move_data.as_ref().expect("expected a move");
+			assert_eq!(move_data.detection_method, MoveDetectionMethod::CrossDevice);
+			assert_eq!(move_data.source_path, src.join("b.txt"));
+			assert_eq!(move_data.destination_path, dst.join("b.txt.gz"));
+		} else {
+			assert_eq!(second[0].event_type, EventType::Remove);
+		}
+	}
+}
+
+#[test]
+fn test_remove_create_by_id_clears_every_index() {
+	use rust_watcher::move_detection::events::{PendingEvent, PendingEventsStorage};
+	use std::path::PathBuf;
+
+	let create = |size| {
+		PendingEvent::new(FileSystemEvent::new(
+			EventType::Create,
+			PathBuf::from("/w/a.txt"),
+			false,
+			size,
+		))
+	};
+	let mut storage = PendingEventsStorage::new();
+	let sized = create(Some(10)).with_inode(Some(7)).with_windows_id(Some(9));
+	storage.add_create(sized.clone());
+	assert!(storage.remove_create_by_id(sized.event.id));
+	assert_eq!(storage.count_creates(), 0);
+	assert!(storage.creates_by_inode.is_empty());
+	assert!(storage.creates_by_windows_id.is_empty());
+	assert!(!storage.remove_create_by_id(sized.event.id));
+
+	// An entry left only in the inode index still counts as removed
+	let inode_only = create(None).with_inode(Some(8));
+	storage.creates_by_inode.insert(8, inode_only.clone());
+	assert!(storage.remove_create_by_id(inode_only.event.id));
+	assert!(storage.creates_by_inode.is_empty());
+}
@@ -27,6 +27,7 @@ async fn test_register_and_list_watches() {
 		node_count: 0,
 		is_active: true,
 		config_hash: 123,
+		event_retention: None,
 		permissions: None,
 	};
 	let watch2 = WatchMetadata {
@@ -37,6 +38,7 @@ async fn test_register_and_list_watches() {
 		node_count: 0,
 		is_active: true,
 		config_hash: 456,
+		event_retention: None,
 		permissions: None,
 	};
 	multi_watch.register_watch(&watch1).await.expect("register_watch 1");
@@ -81,6 +83,7 @@ async fn test_remove_watch() {
 		node_count: 0,
 		is_active: true,
 		config_hash: 789,
+		event_retention: None,
 		permissions: None,
 	};
 	multi_watch.register_watch(&watch).await.expect("register_watch");
@@ -101,6 +104,8 @@ async fn test_remove_watch() {
 				permissions: 0,
 				inode: None,
 				windows_id: None,
+				uid: None,
+				gid: None,
 			},
 			cache_info: rust_watcher::database::types::CacheInfo {
 				cached_at: Utc::now(),
@@ -159,6 +164,7 @@ async fn test_shared_node_management() {
 		node_count: 0,
 		is_active: true,
 		config_hash: 111,
+		event_retention: None,
 		permissions: None,
 	};
 	let watch2 = WatchMetadata {
@@ -169,6 +175,7 @@ async fn test_shared_node_management() {
 		node_count: 0,
 		is_active: true,
 		config_hash: 222,
+		event_retention: None,
 		permissions: None,
 	};
 	multi_watch.register_watch(&watch1).await.expect("register_watch1");
@@ -190,6 +197,8 @@ async fn test_shared_node_management() {
 				permissions: 0,
 				inode: None,
 				windows_id: None,
+				uid: None,
+				gid: None,
 			},
 			cache_info: rust_watcher::database::types::CacheInfo {
 				cached_at: Utc::now(),
@@ -231,3 +240,45 @@ async fn test_shared_node_management() {
 	drop(multi_watch);
 	drop(db);
 }
+
+#[tokio::test]
+async fn test_watches_covering_a_shared_path_lists_every_watch() {
+	use rust_watcher::database::storage::FilesystemCacheStorage;
+	use rust_watcher::database::types::FilesystemNode;
+	use rust_watcher::database::{DatabaseAdapter, DatabaseConfig};
+
+	let temp_dir = tempdir().expect("Failed to create temp dir");
+	let config = DatabaseConfig {
+		database_path: temp_dir.path().join("covering.redb"),
+		..DatabaseConfig::for_small_directories()
+	};
+	let adapter = DatabaseAdapter::new(config).await.expect("adapter");
+	let mut cache = adapter.get_filesystem_cache().await.expect("filesystem cache");
+
+	// One watch on the project, an overlapping one on its docs directory
+	let project = temp_dir.path().join("project");
+	let docs = project.join("docs");
+	std::fs::create_dir_all(&docs).unwrap();
+	let shared = docs.join("guide.md");
+	let own = project.join("Cargo.toml");
+	std::fs::write(&shared, "guide").unwrap();
+	std::fs::write(&own, "[package]").unwrap();
+	let project_watch = Uuid::new_v4();
+	let docs_watch = Uuid::new_v4();
+	for (watch_id, path) in [(project_watch, &shared), (project_watch, &own), (docs_watch, &shared)]
+	{
+		let node = FilesystemNode::new(path.clone(), &std::fs::metadata(path).unwrap());
+		cache.store_filesystem_node(&watch_id, &node, "Create").await.unwrap();
+	}
+
+	let mut covering = adapter.watches_covering(&shared).await.unwrap();
+	covering.sort();
+	let mut expected = vec![project_watch, docs_watch];
+	expected.sort();
+	assert_eq!(covering, expected);
+	assert_eq!(
+		adapter.watches_covering(&own).await.unwrap(),
+		vec![project_watch]
+	);
+	assert!(adapter.watches_covering(&project.join("unknown.txt")).await.unwrap().is_empty());
+}
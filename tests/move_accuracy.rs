@@ -0,0 +1,68 @@
+// Move detection accuracy on the baseline scripts (see common/accuracy.rs)
+//
+// The minimum F1 asserted here is the bar for changes to the weights and matching: a change
+// that lowers a score below it needs a reason, and one that raises a score should raise the
+// bar with it. Scores are measured with the Unix weights.
+//
+// The scripts report moves as remove + create, where the removed file can no longer be
+// stat'ed, so the remove side has neither inode nor content hash. The default threshold (0.7)
+// pairs none of those; the baseline uses a heuristic threshold of 0.4 instead. Chain moves and
+// rapid cycles score low because a stale pending create of an earlier path is paired with a
+// later remove.
+
+use rust_watcher::MoveDetectorConfig;
+
+mod common;
+
+use common::accuracy::{baseline_scripts, MoveAccuracyEvaluator};
+
+const MIN_F1: &[(&str, f64)] = &[("chain-move", 0.33), ("rapid-cycle", 0.5), ("cross-dir", 0.85)];
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_heuristic_pairing_meets_the_baseline_f1() {
+	let config = MoveDetectorConfig { confidence_threshold: 0.4, ..Default::default() };
+	for script in baseline_scripts() {
+		let report = script.evaluate(config.clone()).await;
+		let (_, min_f1) = MIN_F1.iter().find(|(name, _)| *name == script.name).unwrap();
+		assert!(
+			report.f1() >= *min_f1,
+			"{} scored F1 {:.2}, below {min_f1}: {report:?}",
+			script.name,
+			report.f1()
+		);
+	}
+}
+
+#[test]
+fn test_evaluator_counts_false_positives_and_misses() {
+	use rust_watcher::{EventType, FileSystemEvent, MoveDetectionMethod, MoveEvent};
+	use std::path::Path;
+
+	let moved = |from: &str, to: &str| {
+		let event = FileSystemEvent::new(EventType::Move, to.into(), false, None);
+		event.with_move_data(MoveEvent::new(
+			from.into(),
+			to.into(),
+			1.0,
+			MoveDetectionMethod::Inode,
+		))
+	};
+	let mut evaluator = MoveAccuracyEvaluator::default();
+	evaluator.expect_move(Path::new("/a"), Path::new("/b"));
+	evaluator.expect_move(Path::new("/b"), Path::new("/c"));
+	evaluator.record(&[moved("/a", "/b"), moved("/a", "/c")]);
+
+	let report = evaluator.report();
+	assert_eq!(
+		(
+			report.true_positives,
+			report.false_positives,
+			report.false_negatives
+		),
+		(1, 1, 1)
+	);
+	assert_eq!(report.precision(), 0.5);
+	assert_eq!(report.recall(), 0.5);
+	assert_eq!(report.f1(), 0.5);
+}
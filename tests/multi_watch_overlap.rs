@@ -18,6 +18,7 @@ fn make_watch(root: &str) -> WatchMetadata {
 		node_count: 0,
 		is_active: true,
 		config_hash: 0,
+		event_retention: None,
 		permissions: Some(WatchPermissions {
 			can_read: true,
 			can_write: true,
@@ -97,3 +98,88 @@ async fn test_compute_overlap_statistics() {
 	assert!(overlaps.iter().any(|o| matches!(o, WatchOverlap::Ancestor { .. })));
 	assert!(overlaps.iter().any(|o| matches!(o, WatchOverlap::Partial { .. })));
 }
+
+#[tokio::test]
+async fn test_shared_nodes_between_intersects_cached_paths() {
+	use rust_watcher::database::storage::filesystem_cache::RedbFilesystemCache;
+	use rust_watcher::database::storage::FilesystemCacheStorage;
+	use rust_watcher::database::types::{FilesystemNode, NodeType, SharedNodeInfo};
+
+	let temp_dir = tempdir().expect("Failed to create temp dir");
+	let root = temp_dir.path().canonicalize().unwrap().join("tree");
+	std::fs::create_dir_all(root.join("sub")).unwrap();
+	for (name, contents) in [("a.txt", "a"), ("sub/b.txt", "bb"), ("sub/c.txt", "ccc")] {
+		std::fs::write(root.join(name), contents).unwrap();
+	}
+	let db = Arc::new(redb::Database::create(temp_dir.path().join("shared.redb")).unwrap());
+	rust_watcher::database::storage::tables::initialize_tables(&db).await.unwrap();
+	let multi_watch = MultiWatchDatabase::new(db.clone());
+	let mut cache = RedbFilesystemCache::new(db.clone());
+
+	// Watch `outer` covers the whole tree, `inner` only `sub`; each has cached part of it
+	let outer = make_watch(root.to_str().unwrap());
+	let inner = make_watch(root.join("sub").to_str().unwrap());
+	multi_watch.register_watch(&outer).await.unwrap();
+	multi_watch.register_watch(&inner).await.unwrap();
+	let node = |name: &str| {
+		let path = root.join(name);
+		FilesystemNode::new(path.clone(), &std::fs::metadata(&path).unwrap())
+	};
+	for name in ["a.txt", "sub", "sub/b.txt"] {
+		cache
+			.store_filesystem_node(&outer.watch_id, &node(name), "create")
+			.await
+			.unwrap();
+	}
+	for name in ["sub", "sub/b.txt", "sub/c.txt"] {
+		cache
+			.store_filesystem_node(&inner.watch_id, &node(name), "create")
+			.await
+			.unwrap();
+	}
+
+	let expected = vec![root.join("sub"), root.join("sub/b.txt")];
+	let shared = multi_watch
+		.shared_nodes_between(&outer.watch_id, &inner.watch_id)
+		.await
+		.unwrap();
+	assert_eq!(shared, expected);
+	let reversed = multi_watch
+		.shared_nodes_between(&inner.watch_id, &outer.watch_id)
+		.await
+		.unwrap();
+	assert_eq!(reversed, expected);
+	let unrelated = make_watch("/x/y");
+	assert!(multi_watch
+		.shared_nodes_between(&outer.watch_id, &unrelated.watch_id)
+		.await
+		.unwrap()
+		.is_empty());
+
+	// A third watch over the whole tree that also caches sub/b.txt
+	let whole = make_watch(temp_dir.path().canonicalize().unwrap().to_str().unwrap());
+	multi_watch.register_watch(&whole).await.unwrap();
+	cache
+		.store_filesystem_node(&whole.watch_id, &node("sub/b.txt"), "create")
+		.await
+		.unwrap();
+
+	// Optimization shares the real nodes, not placeholders
+	multi_watch.optimize_shared_cache().await;
+	let b_hash = node("sub/b.txt").computed.path_hash;
+	let shared_b: Option<SharedNodeInfo> = multi_watch.get_shared_node(b_hash).await.unwrap();
+	let shared_b = shared_b.expect("sub/b.txt is shared");
+	assert!(matches!(
+		shared_b.node.node_type,
+		NodeType::File { size: 2, .. }
+	));
+	assert!(shared_b.watching_scopes.contains(&outer.watch_id));
+	assert!(shared_b.watching_scopes.contains(&inner.watch_id));
+	// Each overlapping pair adds its scopes rather than replacing the last pair's
+	assert!(shared_b.watching_scopes.contains(&whole.watch_id));
+	assert_eq!(shared_b.watching_scopes.len(), 3);
+	assert_eq!(shared_b.reference_count, 3);
+	// `sub` is also the inner watch's root; its real node wins over the root merge
+	let sub_hash = node("sub").computed.path_hash;
+	assert!(multi_watch.get_shared_node(sub_hash).await.unwrap().is_some());
+}
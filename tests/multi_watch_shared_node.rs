@@ -31,6 +31,8 @@ async fn test_store_and_get_shared_node() {
 			permissions: 0o644,
 			inode: None,
 			windows_id: None,
+			uid: None,
+			gid: None,
 		},
 		cache_info: CacheInfo {
 			cached_at: Utc::now(),
@@ -82,6 +84,8 @@ async fn test_remove_watch_cleans_up_shared_node() {
 			permissions: 0o644,
 			inode: None,
 			windows_id: None,
+			uid: None,
+			gid: None,
 		},
 		cache_info: CacheInfo {
 			cached_at: Utc::now(),
@@ -0,0 +1,51 @@
+// Smoke tests for the supported async runtimes
+//
+// Run the async-std variant with:
+//   cargo test --no-default-features --features runtime-async-std --test runtime_smoke
+// With default features only the tokio variant is compiled.
+
+use rust_watcher::{start, EventType, WatcherConfig};
+use std::time::Duration;
+
+mod common;
+
+/// Start a watcher, create a file, and wait for its Create event using only the public API.
+/// The timeout uses a plain polling loop so the helper does not depend on either runtime's timer.
+async fn observe_create(
+	sleep: impl Fn(Duration) -> std::pin::Pin<Box<dyn std::future::Future<Output = ()>>>,
+) {
+	let temp_dir = common::setup_temp_dir();
+	let config = WatcherConfig { path: temp_dir.path().to_path_buf(), ..Default::default() };
+	let (handle, mut receiver) = start(config).expect("watcher should start");
+
+	sleep(Duration::from_millis(100)).await;
+	let test_file = temp_dir.path().join("smoke.txt");
+	common::create_test_file(&test_file, "smoke").unwrap();
+
+	let mut observed = false;
+	for _ in 0..50 {
+		match receiver.try_recv() {
+			Ok(event) if event.path == test_file && event.event_type == EventType::Create => {
+				observed = true;
+				break;
+			}
+			Ok(_) => continue,
+			Err(_) => sleep(Duration::from_millis(50)).await,
+		}
+	}
+
+	handle.stop().await.expect("watcher should stop");
+	assert!(observed, "Create event should be delivered on this runtime");
+}
+
+#[cfg(feature = "runtime-tokio")]
+#[tokio::test]
+async fn test_smoke_tokio_runtime() {
+	observe_create(|d| Box::pin(tokio::time::sleep(d))).await;
+}
+
+#[cfg(all(feature = "runtime-async-std", not(feature = "runtime-tokio")))]
+#[test]
+fn test_smoke_async_std_runtime() {
+	async_std::task::block_on(observe_create(|d| Box::pin(async_std::task::sleep(d))));
+}
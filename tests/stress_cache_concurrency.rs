@@ -25,6 +25,8 @@ fn make_test_node(path: &str) -> FilesystemNode {
 			permissions: 0o644,
 			inode: None,
 			windows_id: None,
+			uid: None,
+			gid: None,
 		},
 		cache_info: CacheInfo {
 			cached_at: Utc::now(),